@@ -0,0 +1,370 @@
+-- Schema as created by the ad-hoc DDL in `db::init_db` before migrations.
+-- Every statement is idempotent so databases created by older versions
+-- are adopted as they are. `embeddings` is created before migrations run
+-- (its vector dimension comes from EMBEDDING_DIMENSION).
+
+CREATE INDEX IF NOT EXISTS idx_embeddings_fakeid ON embeddings(fakeid);
+CREATE INDEX IF NOT EXISTS idx_embeddings_source ON embeddings(source);
+
+-- Embedding dimension registry (provider/dimension per row and per table)
+ALTER TABLE embeddings ADD COLUMN IF NOT EXISTS provider TEXT;
+ALTER TABLE embeddings ADD COLUMN IF NOT EXISTS dimension INTEGER;
+-- Window index of full-text chunk embeddings (source = 'content')
+ALTER TABLE embeddings ADD COLUMN IF NOT EXISTS chunk_index INTEGER;
+UPDATE embeddings SET dimension = vector_dims(vector) WHERE dimension IS NULL;
+
+CREATE TABLE IF NOT EXISTS embedding_registry (
+    name TEXT PRIMARY KEY,
+    provider TEXT,
+    model TEXT,
+    dimension INTEGER NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS accounts (
+    fakeid TEXT PRIMARY KEY,
+    nickname TEXT,
+    round_head_img TEXT,
+    signature TEXT,
+    service_type INTEGER,
+    count INTEGER NOT NULL DEFAULT 0,
+    articles INTEGER NOT NULL DEFAULT 0,
+    total_count INTEGER NOT NULL DEFAULT 0,
+    create_time BIGINT,
+    update_time BIGINT,
+    last_update_time BIGINT,
+    sync_all BOOLEAN NOT NULL DEFAULT FALSE,
+    raw_json JSONB
+);
+
+-- Account history sync progress
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_status TEXT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_full BOOLEAN;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_begin INTEGER;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_synced INTEGER;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_error TEXT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_started_at BIGINT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sync_finished_at BIGINT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS monitored BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- id = fakeid:aid
+CREATE TABLE IF NOT EXISTS articles (
+    id TEXT PRIMARY KEY,
+    fakeid TEXT NOT NULL,
+    aid TEXT NOT NULL,
+    title TEXT NOT NULL,
+    link TEXT NOT NULL,
+    create_time BIGINT NOT NULL,
+    update_time BIGINT,
+    digest TEXT,
+    cover TEXT,
+    is_deleted BOOLEAN NOT NULL DEFAULT FALSE,
+    itemidx INTEGER NOT NULL DEFAULT 1,
+    content_json JSONB,
+    raw_json JSONB
+);
+
+CREATE INDEX IF NOT EXISTS idx_articles_fakeid_time ON articles(fakeid, create_time DESC);
+CREATE INDEX IF NOT EXISTS idx_articles_link ON articles(link);
+
+-- Article HTML, keyed like articles
+CREATE TABLE IF NOT EXISTS article_content (
+    id TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    original_url TEXT,
+    create_time BIGINT DEFAULT (extract(epoch from now())::bigint)
+);
+
+CREATE INDEX IF NOT EXISTS idx_article_content_url ON article_content(original_url);
+-- Per-entry cache TTL (NULL = never expires)
+ALTER TABLE article_content ADD COLUMN IF NOT EXISTS expires_at BIGINT;
+
+-- Images/media
+CREATE TABLE IF NOT EXISTS assets (
+    url TEXT PRIMARY KEY,
+    data BYTEA NOT NULL,
+    mime_type TEXT,
+    size INTEGER,
+    create_time BIGINT DEFAULT (extract(epoch from now())::bigint)
+);
+
+-- Blobs may live outside the database (see `storage`); `storage` names the backend
+ALTER TABLE assets ADD COLUMN IF NOT EXISTS storage TEXT NOT NULL DEFAULT 'postgres';
+ALTER TABLE assets ALTER COLUMN data DROP NOT NULL;
+
+-- Cached thumbnails / transcodes of assets
+CREATE TABLE IF NOT EXISTS asset_variants (
+    url TEXT NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    format TEXT NOT NULL,
+    data BYTEA NOT NULL,
+    mime_type TEXT NOT NULL,
+    size INTEGER,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (url, width, height, format)
+);
+
+CREATE TABLE IF NOT EXISTS comments (
+    id TEXT PRIMARY KEY,
+    article_id TEXT NOT NULL,
+    content_json JSONB NOT NULL,
+    create_time BIGINT DEFAULT (extract(epoch from now())::bigint)
+);
+
+CREATE INDEX IF NOT EXISTS idx_comments_article_id ON comments(article_id);
+
+CREATE TABLE IF NOT EXISTS cookies (
+    auth_key TEXT PRIMARY KEY,
+    token TEXT NOT NULL,
+    cookies_json TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+-- Session keepalive state (see `keepalive`)
+ALTER TABLE cookies ADD COLUMN IF NOT EXISTS last_checked_at BIGINT;
+ALTER TABLE cookies ADD COLUMN IF NOT EXISTS last_ok_at BIGINT;
+ALTER TABLE cookies ADD COLUMN IF NOT EXISTS last_error TEXT;
+ALTER TABLE cookies ADD COLUMN IF NOT EXISTS check_failures INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS insight_tasks (
+    id UUID PRIMARY KEY,
+    prompt TEXT NOT NULL,
+    status VARCHAR(50) NOT NULL,
+    keywords TEXT[] NOT NULL DEFAULT '{}',
+    target_count INTEGER NOT NULL DEFAULT 30,
+    processed_count INTEGER NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Executive summary generated by /api/insight/summarize
+ALTER TABLE insight_tasks ADD COLUMN IF NOT EXISTS summary JSONB;
+ALTER TABLE insight_tasks ADD COLUMN IF NOT EXISTS completion_reason TEXT;
+ALTER TABLE insight_tasks ADD COLUMN IF NOT EXISTS schedule_id UUID;
+
+CREATE TABLE IF NOT EXISTS insight_articles (
+    id UUID PRIMARY KEY,
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    title TEXT NOT NULL,
+    url TEXT NOT NULL,
+    account_name TEXT,
+    account_fakeid TEXT,
+    publish_time BIGINT,
+    similarity FLOAT,
+    insight TEXT,
+    relevance_score FLOAT,
+    created_at BIGINT NOT NULL
+);
+
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS account_fakeid TEXT;
+-- Best chunk similarity from deep scan (NULL when not deep scanned)
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS chunk_similarity FLOAT;
+-- Key of the article's stored comments (comment collection only)
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS article_id TEXT;
+-- Duplicate detection: the original a linked duplicate repeats, and the
+-- simhash/embedding kept articles are compared by (see `dedup`)
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS duplicates_of UUID;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS simhash BIGINT;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS dedup_embedding REAL[];
+-- User feedback on matched articles (see `api::feedback`)
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS feedback TEXT;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS feedback_note TEXT;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS feedback_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_insight_articles_task_id ON insight_articles(task_id);
+
+CREATE TABLE IF NOT EXISTS cached_articles (
+    url_hash TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+
+ALTER TABLE cached_articles ADD COLUMN IF NOT EXISTS expires_at BIGINT;
+
+-- Online availability history
+CREATE TABLE IF NOT EXISTS article_link_checks (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    article_id TEXT,
+    http_status INTEGER,
+    status TEXT NOT NULL,
+    detail TEXT,
+    checked_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_checks_url_time ON article_link_checks(url, checked_at DESC);
+
+-- Takedowns / edits detected against snapshots
+CREATE TABLE IF NOT EXISTS archive_alerts (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    article_id TEXT,
+    kind TEXT NOT NULL,
+    status TEXT NOT NULL,
+    detail TEXT,
+    similarity REAL,
+    snapshot_url TEXT,
+    diff TEXT,
+    notified BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_archive_alerts_url ON archive_alerts(url, created_at DESC);
+
+-- Read-only public links
+CREATE TABLE IF NOT EXISTS task_shares (
+    token TEXT PRIMARY KEY,
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    revoked_at BIGINT,
+    view_count INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_task_shares_task_id ON task_shares(task_id);
+
+-- One digest per task and language
+CREATE TABLE IF NOT EXISTS insight_digests (
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    language TEXT NOT NULL,
+    content TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    article_count INTEGER NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (task_id, language)
+);
+
+-- Worker config + checkpoint for resume
+CREATE TABLE IF NOT EXISTS insight_task_state (
+    task_id UUID PRIMARY KEY REFERENCES insight_tasks(id),
+    config JSONB NOT NULL,
+    checkpoint JSONB NOT NULL DEFAULT '{}',
+    updated_at BIGINT NOT NULL
+);
+
+-- Token usage and estimated cost per task, stage and model (see `llm::usage`)
+CREATE TABLE IF NOT EXISTS task_llm_usage (
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    stage TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    calls BIGINT NOT NULL DEFAULT 0,
+    prompt_tokens BIGINT NOT NULL DEFAULT 0,
+    completion_tokens BIGINT NOT NULL DEFAULT 0,
+    cost_usd DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (task_id, stage, provider, model)
+);
+
+-- Recurring tasks
+CREATE TABLE IF NOT EXISTS insight_schedules (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    prompt TEXT NOT NULL,
+    mode TEXT NOT NULL DEFAULT 'append',
+    interval_seconds BIGINT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    config JSONB NOT NULL,
+    deepseek_api_key TEXT,
+    gemini_api_key TEXT,
+    task_id UUID,
+    next_run_at BIGINT NOT NULL,
+    last_run_at BIGINT,
+    run_count INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Discovery filters (see `api::account_list`)
+CREATE TABLE IF NOT EXISTS account_blocklist (
+    fakeid TEXT PRIMARY KEY,
+    nickname TEXT,
+    reason TEXT,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS account_allowlist (
+    fakeid TEXT PRIMARY KEY,
+    nickname TEXT,
+    reason TEXT,
+    created_at BIGINT NOT NULL
+);
+
+-- Tasks waiting for a worker slot (see `task_queue`)
+CREATE TABLE IF NOT EXISTS task_queue (
+    task_id UUID PRIMARY KEY,
+    priority INTEGER NOT NULL DEFAULT 0,
+    seq BIGSERIAL,
+    enqueued_at BIGINT NOT NULL
+);
+
+-- Saved task configurations
+CREATE TABLE IF NOT EXISTS insight_templates (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT,
+    settings JSONB NOT NULL,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    last_used_at BIGINT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Background exports with progress counters
+CREATE TABLE IF NOT EXISTS export_jobs (
+    id UUID PRIMARY KEY,
+    task_id UUID NOT NULL,
+    format TEXT NOT NULL,
+    target_dir TEXT NOT NULL,
+    export_dir TEXT,
+    status TEXT NOT NULL DEFAULT 'running',
+    total INTEGER NOT NULL DEFAULT 0,
+    succeeded INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    images_downloaded INTEGER NOT NULL DEFAULT 0,
+    message TEXT,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    finished_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS idx_export_jobs_task_id ON export_jobs(task_id);
+
+-- Encrypted LLM provider keys (see `credentials`)
+CREATE TABLE IF NOT EXISTS llm_credentials (
+    provider TEXT PRIMARY KEY,
+    nonce BYTEA NOT NULL,
+    ciphertext BYTEA NOT NULL,
+    hint TEXT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+-- Full-text search: jieba-segmented tsvectors, filled by the background indexer
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS search_tsv tsvector;
+ALTER TABLE article_content ADD COLUMN IF NOT EXISTS search_text TEXT;
+ALTER TABLE article_content ADD COLUMN IF NOT EXISTS search_tsv tsvector;
+
+CREATE INDEX IF NOT EXISTS idx_articles_search ON articles USING GIN(search_tsv);
+CREATE INDEX IF NOT EXISTS idx_article_content_search ON article_content USING GIN(search_tsv);
+
+-- Changed text must be re-indexed: reset the vector so the indexer picks the row up
+CREATE OR REPLACE FUNCTION reset_search_tsv() RETURNS trigger AS $$
+BEGIN
+    NEW.search_tsv := NULL;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS trg_articles_search_reset ON articles;
+CREATE TRIGGER trg_articles_search_reset BEFORE UPDATE OF title, digest ON articles FOR EACH ROW
+    WHEN (OLD.title IS DISTINCT FROM NEW.title OR OLD.digest IS DISTINCT FROM NEW.digest)
+    EXECUTE FUNCTION reset_search_tsv();
+
+DROP TRIGGER IF EXISTS trg_article_content_search_reset ON article_content;
+CREATE TRIGGER trg_article_content_search_reset BEFORE UPDATE OF content ON article_content FOR EACH ROW
+    WHEN (OLD.content IS DISTINCT FROM NEW.content)
+    EXECUTE FUNCTION reset_search_tsv();
@@ -0,0 +1,21 @@
+-- Per-user API tokens and ownership of tasks, MP sessions and accounts.
+-- Rows created before this migration (or by the shared env tokens) have no
+-- owner and are visible to admins and to the env tokens only.
+
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- SHA-256 of the API token; the token itself is shown once on creation
+    token_hash TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL DEFAULT 'write',
+    created_at BIGINT NOT NULL,
+    last_seen_at BIGINT
+);
+
+ALTER TABLE insight_tasks ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE cookies ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_insight_tasks_owner ON insight_tasks(owner_id);
+CREATE INDEX IF NOT EXISTS idx_cookies_owner ON cookies(owner_id);
+CREATE INDEX IF NOT EXISTS idx_accounts_owner ON accounts(owner_id);
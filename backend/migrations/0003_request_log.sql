@@ -0,0 +1,21 @@
+-- Audit log of outbound MP backend (`cgi-bin`) calls, see `request_log`
+
+CREATE TABLE IF NOT EXISTS request_log (
+    id BIGSERIAL PRIMARY KEY,
+    -- Unix milliseconds when the request was sent
+    requested_at BIGINT NOT NULL,
+    endpoint TEXT NOT NULL,
+    -- Caller, e.g. "wechat.searchbiz" or "public.get_articles"
+    source TEXT NOT NULL,
+    -- Crawl lane of the session (hashed auth key, see `crawl::session_lane`)
+    session TEXT NOT NULL,
+    fakeid TEXT,
+    keyword TEXT,
+    http_status INTEGER,
+    ret BIGINT,
+    latency_ms INTEGER NOT NULL,
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_log_requested_at ON request_log(requested_at);
+CREATE INDEX IF NOT EXISTS idx_request_log_session ON request_log(session, requested_at);
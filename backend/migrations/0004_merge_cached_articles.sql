@@ -0,0 +1,15 @@
+-- Fold the legacy `cached_articles` (md5(url) keyed) into `article_content`,
+-- the single article HTML store (see `repository::articles`). Rows are keyed
+-- by the `articles` id when the link is known, otherwise by the md5 of the
+-- URL; URLs that already have a copy in `article_content` keep that copy.
+
+INSERT INTO article_content (id, content, original_url, create_time, expires_at)
+SELECT DISTINCT ON (COALESCE(a.id, ca.url_hash))
+       COALESCE(a.id, ca.url_hash), ca.content, ca.url, ca.created_at, ca.expires_at
+FROM cached_articles ca
+LEFT JOIN LATERAL (SELECT id FROM articles WHERE link = ca.url LIMIT 1) a ON TRUE
+WHERE NOT EXISTS (SELECT 1 FROM article_content c WHERE c.original_url = ca.url)
+ORDER BY COALESCE(a.id, ca.url_hash), ca.created_at DESC
+ON CONFLICT (id) DO NOTHING;
+
+DROP TABLE IF EXISTS cached_articles;
@@ -0,0 +1,18 @@
+-- Timestamped versions of article content, see `repository::articles`.
+-- A version is stored whenever the readable text of a fetched copy differs
+-- from the previous one.
+
+CREATE TABLE IF NOT EXISTS article_versions (
+    id BIGSERIAL PRIMARY KEY,
+    -- `article_content` id of the article
+    article_id TEXT NOT NULL,
+    url TEXT,
+    -- md5 of the readable text, so markup-only changes are not new versions
+    content_hash TEXT NOT NULL,
+    content TEXT NOT NULL,
+    captured_at BIGINT NOT NULL,
+    -- What captured it: "stored" (copy held before versioning), "fetch" or "verify"
+    source TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_article_versions_article ON article_versions(article_id, captured_at DESC);
@@ -0,0 +1,8 @@
+-- Online availability of insight articles, see `link_status`
+
+-- Latest link check outcome ("ok", "deleted", "violation", "invalid",
+-- "not_found" or "error"); NULL until first checked
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS status TEXT;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS status_checked_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_insight_articles_status_checked ON insight_articles(status_checked_at NULLS FIRST);
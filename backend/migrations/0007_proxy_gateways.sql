@@ -0,0 +1,17 @@
+-- Managed download gateways, see `gateway`
+
+CREATE TABLE IF NOT EXISTS proxy_gateways (
+    id UUID PRIMARY KEY,
+    -- Relay endpoint, called as `<url>?url=<target>&authorization=<auth_key>`
+    url TEXT NOT NULL UNIQUE,
+    auth_key TEXT,
+    -- Relative share of traffic when healthy
+    weight INTEGER NOT NULL DEFAULT 1,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL,
+    -- Outcome of the latest health check
+    last_checked_at BIGINT,
+    last_latency_ms INTEGER,
+    last_error TEXT
+);
@@ -0,0 +1,4 @@
+-- Proxy pool entries can be standard HTTP / SOCKS5 proxies, see `gateway::ProxyKind`
+
+-- "gateway" (URL-rewriting relay), "http" or "socks5"
+ALTER TABLE proxy_gateways ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'gateway';
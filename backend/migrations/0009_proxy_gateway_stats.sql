@@ -0,0 +1,12 @@
+-- Request outcomes per download gateway or proxy, see `gateway::flush_stats`
+
+CREATE TABLE IF NOT EXISTS proxy_gateway_stats (
+    -- Gateway or proxy URL without its password; includes proxies listed in
+    -- requests, not only pool entries
+    url TEXT PRIMARY KEY,
+    requests BIGINT NOT NULL DEFAULT 0,
+    failures BIGINT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    last_failure_at BIGINT,
+    updated_at BIGINT NOT NULL
+);
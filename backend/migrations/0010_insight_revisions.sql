@@ -0,0 +1,31 @@
+-- Re-analysis runs over a task's collected articles, see `api::reanalyze`
+
+CREATE TABLE IF NOT EXISTS insight_revisions (
+    id UUID PRIMARY KEY,
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    -- 1, 2, ... per task; the worker's own results count as revision 0
+    revision INTEGER NOT NULL,
+    prompt TEXT NOT NULL,
+    reasoning_provider TEXT NOT NULL,
+    embedding_provider TEXT NOT NULL,
+    -- "running", "completed" or "failed"
+    status TEXT NOT NULL,
+    total INTEGER NOT NULL,
+    processed INTEGER NOT NULL DEFAULT 0,
+    relevant INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at BIGINT NOT NULL,
+    completed_at BIGINT,
+    -- Last time the results were copied onto insight_articles
+    applied_at BIGINT,
+    UNIQUE (task_id, revision)
+);
+
+CREATE TABLE IF NOT EXISTS insight_revision_articles (
+    revision_id UUID NOT NULL REFERENCES insight_revisions(id),
+    article_id UUID NOT NULL REFERENCES insight_articles(id),
+    similarity FLOAT,
+    is_relevant BOOLEAN NOT NULL,
+    insight TEXT,
+    PRIMARY KEY (revision_id, article_id)
+);
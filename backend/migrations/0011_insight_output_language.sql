@@ -0,0 +1,4 @@
+-- Language of a task's insights (BCP 47 tag, see `llm::language_name`);
+-- NULL for tasks created before it was configurable, which used zh-CN
+
+ALTER TABLE insight_tasks ADD COLUMN IF NOT EXISTS output_language TEXT;
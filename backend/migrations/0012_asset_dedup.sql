@@ -0,0 +1,14 @@
+-- Image deduplication by content, see `storage::save`
+
+-- SHA-256 of the blob; each distinct content is stored once. NULL for assets
+-- saved before hashing (`--dedup-assets` fills it in).
+ALTER TABLE assets ADD COLUMN IF NOT EXISTS sha256 TEXT;
+CREATE UNIQUE INDEX IF NOT EXISTS assets_sha256_key ON assets (sha256);
+
+-- URLs whose bytes are stored under another asset (the one with this sha256)
+CREATE TABLE IF NOT EXISTS asset_aliases (
+    url TEXT PRIMARY KEY,
+    sha256 TEXT NOT NULL,
+    created_at BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS asset_aliases_sha256_idx ON asset_aliases (sha256);
@@ -0,0 +1,14 @@
+-- Built-in task completion notifiers (see `notify`). The secret (SMTP
+-- password or Telegram bot token) is encrypted like `llm_credentials`.
+CREATE TABLE IF NOT EXISTS notifiers (
+    kind TEXT PRIMARY KEY,                  -- 'email' or 'telegram'
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    scheduled_only BOOLEAN NOT NULL DEFAULT FALSE,
+    config JSONB NOT NULL,
+    nonce BYTEA,
+    ciphertext BYTEA,
+    hint TEXT,
+    last_sent_at BIGINT,
+    last_error TEXT,
+    updated_at BIGINT NOT NULL
+);
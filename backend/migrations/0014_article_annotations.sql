@@ -0,0 +1,17 @@
+-- Tags and notes on insight articles, see `api::annotations`
+
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_insight_articles_tags ON insight_articles USING GIN (tags);
+
+CREATE TABLE IF NOT EXISTS article_annotations (
+    id UUID PRIMARY KEY,
+    article_id UUID NOT NULL REFERENCES insight_articles(id),
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    note TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_article_annotations_article ON article_annotations(article_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_article_annotations_task ON article_annotations(task_id);
@@ -0,0 +1,14 @@
+-- What the last export of a task to a directory wrote, for incremental
+-- exports (see `api::export_manifest`)
+
+CREATE TABLE IF NOT EXISTS export_manifests (
+    task_id UUID NOT NULL,
+    target_dir TEXT NOT NULL,
+    format TEXT NOT NULL,
+    -- Folder the export was written to, reused by incremental exports
+    export_dir TEXT NOT NULL,
+    -- insight_articles.id -> {"hash", "file", "exported_at"}
+    entries JSONB NOT NULL DEFAULT '{}',
+    updated_at BIGINT NOT NULL,
+    PRIMARY KEY (task_id, target_dir, format)
+);
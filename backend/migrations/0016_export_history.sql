@@ -0,0 +1,21 @@
+-- Export history: request parameters, per-article outcomes and the archive
+-- of downloaded exports kept for re-download (see `api::export`)
+
+ALTER TABLE export_jobs ADD COLUMN IF NOT EXISTS params JSONB NOT NULL DEFAULT '{}';
+-- ZIP of a downloaded export, kept until archive_expires_at
+ALTER TABLE export_jobs ADD COLUMN IF NOT EXISTS archive_path TEXT;
+ALTER TABLE export_jobs ADD COLUMN IF NOT EXISTS archive_expires_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_export_jobs_created_at ON export_jobs(created_at DESC);
+
+CREATE TABLE IF NOT EXISTS export_job_articles (
+    job_id UUID NOT NULL REFERENCES export_jobs(id),
+    idx INTEGER NOT NULL,
+    -- insight_articles.id
+    article_id UUID,
+    title TEXT NOT NULL,
+    success BOOLEAN NOT NULL,
+    message TEXT NOT NULL,
+    images INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (job_id, idx)
+);
@@ -0,0 +1,12 @@
+-- Byline and provenance parsed from article pages when they are fetched
+-- (see `content::extract::metadata`). NULL until a copy has been fetched.
+
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS author TEXT;
+-- Declared 原创 by the account
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS is_original BOOLEAN;
+-- `__biz` of the publishing account
+ALTER TABLE articles ADD COLUMN IF NOT EXISTS biz TEXT;
+
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS author TEXT;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS is_original BOOLEAN;
+ALTER TABLE insight_articles ADD COLUMN IF NOT EXISTS biz TEXT;
@@ -0,0 +1,13 @@
+-- Vectors of texts already embedded, reused across tasks and retries
+-- (see `llm::cache`). `dimension` is 0 when the provider's default is used.
+
+CREATE TABLE IF NOT EXISTS embedding_cache (
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    dimension INTEGER NOT NULL DEFAULT 0,
+    -- SHA-256 of the embedded text, hex
+    text_hash TEXT NOT NULL,
+    embedding REAL[] NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (provider, model, dimension, text_hash)
+);
@@ -0,0 +1,22 @@
+-- Articles a task scanned but did not keep, recorded only for tasks created
+-- with `debug_candidates` (see `api::insight::list_candidates`)
+
+CREATE TABLE IF NOT EXISTS insight_candidates (
+    id BIGSERIAL PRIMARY KEY,
+    task_id UUID NOT NULL REFERENCES insight_tasks(id),
+    title TEXT NOT NULL,
+    url TEXT NOT NULL,
+    account_name TEXT,
+    -- NULL when the article was dropped before it was embedded
+    similarity DOUBLE PRECISION,
+    -- "prefilter", "embedding_failed", "below_threshold", "duplicate",
+    -- "llm_failed" or "irrelevant"
+    reason TEXT NOT NULL,
+    -- Pre-filter rule, duplicated article, error or the model's verdict
+    detail TEXT,
+    created_at BIGINT NOT NULL,
+    -- An article is recorded once per task, even when a resumed run rescans it
+    UNIQUE (task_id, url)
+);
+
+CREATE INDEX IF NOT EXISTS idx_insight_candidates_task ON insight_candidates(task_id, id);
@@ -0,0 +1,7 @@
+-- Schedule API keys are encrypted like `notifiers` secrets. Plaintext keys
+-- left from earlier versions are sealed (or dropped, without
+-- SETTINGS_MASTER_KEY) at startup by `api::schedule::seal_legacy_keys`.
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS deepseek_key_nonce BYTEA;
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS deepseek_key_ciphertext BYTEA;
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS gemini_key_nonce BYTEA;
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS gemini_key_ciphertext BYTEA;
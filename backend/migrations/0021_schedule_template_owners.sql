@@ -0,0 +1,8 @@
+-- Schedules and templates belong to the user who created them, like tasks.
+-- Rows from earlier versions have no owner and are visible to admins and to
+-- the env tokens only.
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE insight_templates ADD COLUMN IF NOT EXISTS owner_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_insight_schedules_owner ON insight_schedules(owner_id);
+CREATE INDEX IF NOT EXISTS idx_insight_templates_owner ON insight_templates(owner_id);
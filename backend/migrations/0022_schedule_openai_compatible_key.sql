@@ -0,0 +1,4 @@
+-- The openai_compatible API key of a schedule, sealed like the other keys in
+-- 0020_schedule_sealed_keys.sql
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS openai_compatible_key_nonce BYTEA;
+ALTER TABLE insight_schedules ADD COLUMN IF NOT EXISTS openai_compatible_key_ciphertext BYTEA;
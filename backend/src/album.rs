@@ -0,0 +1,215 @@
+//! Article albums (合集)
+//!
+//! Albums group an account's articles by topic. The albums an article belongs
+//! to are read from its page; `mp/appmsgalbum?action=getalbum` lists an
+//! album's articles newest first, paged by the `msgid` / `itemidx` of the last
+//! article of the previous page. Like comments, this needs no MP session.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+
+use crate::comments::{js_var, url_param};
+use crate::crawl::{self, Priority};
+use crate::wechat::client::with_mp_headers;
+use crate::wechat::model::lenient_i64;
+
+/// Articles requested per `getalbum` page (the endpoint's own default)
+const PAGE_SIZE: &str = "10";
+
+lazy_static! {
+    // `album_id=...` in album links, `"album_id": "..."` in page data and
+    // `data-album_id="..."` on the album tags under the title
+    static ref ALBUM_ID: Regex =
+        Regex::new(r#"album_?id["']?\s*(?:=|:)\s*["']?(\d{6,})"#).unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbumArticle {
+    pub title: String,
+    pub url: String,
+    pub create_time: i64,
+    pub msgid: String,
+    pub itemidx: String,
+}
+
+/// One `getalbum` page
+#[derive(Debug, Default)]
+pub struct AlbumPage {
+    pub title: Option<String>,
+    pub articles: Vec<AlbumArticle>,
+    /// `continue_flag`: older articles follow
+    pub has_more: bool,
+}
+
+/// Ids of the albums an article page links to, in page order
+pub fn album_ids(html: &str) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for caps in ALBUM_ID.captures_iter(html) {
+        let id = caps[1].to_string();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// The account (`__biz`) of an article page
+pub fn page_biz(html: &str, url: &str) -> Option<String> {
+    js_var(html, "biz").or_else(|| url_param(url, "__biz"))
+}
+
+/// Parse a `getalbum` response. A single article comes as an object rather
+/// than a one-element list.
+pub fn parse_album(json: &Value) -> AlbumPage {
+    let Some(resp) = json.get("getalbum_resp") else {
+        return AlbumPage::default();
+    };
+    let items = match resp.get("article_list") {
+        Some(Value::Array(list)) => list.iter().collect(),
+        Some(item @ Value::Object(_)) => vec![item],
+        _ => Vec::new(),
+    };
+    let text = |v: &Value, key: &str| -> String {
+        match v.get(key) {
+            Some(Value::String(s)) => html_escape::decode_html_entities(s).trim().to_string(),
+            Some(Value::Number(n)) => n.to_string(),
+            _ => String::new(),
+        }
+    };
+
+    AlbumPage {
+        title: resp
+            .get("base_info")
+            .map(|b| text(b, "title"))
+            .filter(|t| !t.is_empty()),
+        articles: items
+            .into_iter()
+            .filter_map(|item| {
+                let url = text(item, "url");
+                if url.is_empty() {
+                    return None;
+                }
+                Some(AlbumArticle {
+                    title: text(item, "title"),
+                    url: url.replacen("http://", "https://", 1),
+                    create_time: item.get("create_time").and_then(lenient_i64).unwrap_or(0),
+                    msgid: text(item, "msgid"),
+                    itemidx: text(item, "itemidx"),
+                })
+            })
+            .collect(),
+        has_more: resp
+            .get("continue_flag")
+            .and_then(lenient_i64)
+            .is_some_and(|f| f != 0),
+    }
+}
+
+/// Fetch one page of an album, after `from` (the last article of the previous page)
+pub async fn fetch_page(
+    client: &reqwest::Client,
+    biz: &str,
+    album_id: &str,
+    from: Option<&AlbumArticle>,
+    priority: Priority,
+) -> anyhow::Result<AlbumPage> {
+    let mut query = vec![
+        ("action", "getalbum"),
+        ("__biz", biz),
+        ("album_id", album_id),
+        ("count", PAGE_SIZE),
+        ("f", "json"),
+    ];
+    if let Some(last) = from {
+        query.push(("begin_msgid", last.msgid.as_str()));
+        query.push(("begin_itemidx", last.itemidx.as_str()));
+    }
+
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, priority, "album.fetch").await;
+    let json: Value = with_mp_headers(client.get("https://mp.weixin.qq.com/mp/appmsgalbum"))
+        .query(&query)
+        .send()
+        .await?
+        .json()
+        .await?;
+    crate::wechat::client::check_base_resp(&json)?;
+    Ok(parse_album(&json))
+}
+
+/// Up to `max` articles of an album, newest first, with the album title
+pub async fn articles(
+    client: &reqwest::Client,
+    biz: &str,
+    album_id: &str,
+    max: usize,
+    priority: Priority,
+) -> anyhow::Result<(Option<String>, Vec<AlbumArticle>)> {
+    let mut title = None;
+    let mut articles: Vec<AlbumArticle> = Vec::new();
+    while articles.len() < max {
+        let page = fetch_page(client, biz, album_id, articles.last(), priority).await?;
+        title = title.or(page.title);
+        let before = articles.len();
+        for article in page.articles {
+            if !articles.iter().any(|a| a.url == article.url) {
+                articles.push(article);
+            }
+        }
+        // Stop as well when a page brings nothing new, so paging cannot loop
+        if !page.has_more || articles.len() == before {
+            break;
+        }
+    }
+    articles.truncate(max);
+    Ok((title, articles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_album_ids_and_parse() {
+        let html = r#"
+            <a href="https://mp.weixin.qq.com/mp/appmsgalbum?__biz=MzA5&action=getalbum&album_id=2345678901#wechat_redirect">#行业观察</a>
+            <span class="album-tag" data-album_id="2345678901"></span>
+            window.appmsg_album_info = {"album_id": "3456789012", "title": "x"};
+        "#;
+        assert_eq!(album_ids(html), vec!["2345678901", "3456789012"]);
+        assert_eq!(
+            page_biz("", "https://mp.weixin.qq.com/s?__biz=MzA5&mid=1").as_deref(),
+            Some("MzA5")
+        );
+
+        let json = serde_json::json!({
+            "base_resp": {"ret": 0},
+            "getalbum_resp": {
+                "base_info": {"title": "行业观察"},
+                "article_list": [
+                    {"title": "A &amp; B", "url": "http://mp.weixin.qq.com/s?__biz=MzA5&mid=1#rd",
+                     "create_time": "1700000000", "msgid": "2247483650", "itemidx": "1"},
+                    {"title": "C", "url": "", "msgid": "2247483649", "itemidx": "1"}
+                ],
+                "continue_flag": "1"
+            }
+        });
+        let page = parse_album(&json);
+        assert_eq!(page.title.as_deref(), Some("行业观察"));
+        assert!(page.has_more);
+        assert_eq!(page.articles.len(), 1);
+        assert_eq!(page.articles[0].title, "A & B");
+        assert_eq!(page.articles[0].create_time, 1700000000);
+        assert!(page.articles[0].url.starts_with("https://"));
+
+        let single = serde_json::json!({
+            "getalbum_resp": {
+                "article_list": {"title": "D", "url": "https://mp.weixin.qq.com/s/d", "create_time": 1},
+                "continue_flag": "0"
+            }
+        });
+        let page = parse_album(&single);
+        assert_eq!(page.articles.len(), 1);
+        assert!(!page.has_more);
+    }
+}
@@ -0,0 +1,162 @@
+//! Account sync API
+//!
+//! Start, inspect and cancel full-history syncs of official accounts, and
+//! refresh monitored accounts for newly published articles.
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::sync;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SyncRequest {
+    pub fakeid: String,
+    pub nickname: Option<String>,
+    /// Crawl the whole history (default) or stop at the first page without new articles
+    pub full: Option<bool>,
+    /// Start from the newest page instead of resuming a previous sync
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SyncStatus {
+    pub fakeid: String,
+    pub nickname: Option<String>,
+    pub sync_status: Option<String>,
+    pub sync_full: Option<bool>,
+    pub sync_all: bool,
+    /// Next `begin` offset (publish entries already crawled)
+    pub sync_begin: Option<i32>,
+    pub sync_synced: Option<i32>,
+    pub total_count: i32,
+    pub articles: i32,
+    pub sync_error: Option<String>,
+    pub sync_started_at: Option<i64>,
+    pub sync_finished_at: Option<i64>,
+    #[sqlx(skip)]
+    pub running: bool,
+}
+
+async fn load_status(state: &AppState, fakeid: &str) -> Result<SyncStatus, AppError> {
+    let mut status = sqlx::query_as::<_, SyncStatus>(
+        r#"
+        SELECT fakeid, nickname, sync_status, sync_full, sync_all, sync_begin, sync_synced,
+               total_count, articles, sync_error, sync_started_at, sync_finished_at
+        FROM accounts WHERE fakeid = $1
+        "#,
+    )
+    .bind(fakeid)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(AppError::NotFound("Account not found".to_string()))?;
+    status.running = sync::is_running(fakeid);
+    Ok(status)
+}
+
+/// Start or resume a history sync
+pub async fn start_sync(
+    State(state): State<AppState>,
+    Json(req): Json<SyncRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if req.fakeid.trim().is_empty() {
+        return Err(AppError::BadRequest("fakeid不能为空".to_string()));
+    }
+    if !sync::start(&state, &req.fakeid, req.full.unwrap_or(true), req.restart).await? {
+        return Err(AppError::BadRequest("该公众号正在同步中".to_string()));
+    }
+    if let Some(nickname) = req.nickname.as_deref().filter(|n| !n.is_empty()) {
+        sqlx::query("UPDATE accounts SET nickname = $2 WHERE fakeid = $1")
+            .bind(&req.fakeid)
+            .bind(nickname)
+            .execute(&state.db_pool)
+            .await?;
+    }
+
+    let status = load_status(&state, &req.fakeid).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "同步已开始",
+        "data": status
+    })))
+}
+
+/// Sync progress of an account
+pub async fn get_sync_status(
+    State(state): State<AppState>,
+    Path(fakeid): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let status = load_status(&state, &fakeid).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": status
+    })))
+}
+
+/// Stop a running sync after the current page; it can be resumed later
+pub async fn cancel_sync(
+    State(state): State<AppState>,
+    Path(fakeid): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !sync::cancel(&fakeid) {
+        load_status(&state, &fakeid).await?;
+        return Err(AppError::BadRequest(
+            "该公众号没有正在进行的同步".to_string(),
+        ));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RefreshRequest {
+    /// Accounts to refresh; defaults to every monitored account
+    pub fakeids: Option<Vec<String>>,
+}
+
+/// Fetch new articles of monitored accounts and report what changed
+pub async fn refresh_accounts(
+    State(state): State<AppState>,
+    body: Option<Json<RefreshRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let summaries = sync::refresh_accounts(&state, req.fakeids.as_deref())
+        .await
+        .map_err(|e| AppError::BadGateway(e.to_string()))?;
+
+    let new_total: usize = summaries.iter().map(|s| s.new_articles.len()).sum();
+    let deleted_total: usize = summaries.iter().map(|s| s.deleted.len()).sum();
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "new": new_total,
+        "deleted": deleted_total,
+        "data": summaries
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MonitorRequest {
+    pub fakeid: String,
+    pub monitored: bool,
+}
+
+/// Include or exclude an account from refreshes
+pub async fn set_monitored(
+    State(state): State<AppState>,
+    Json(req): Json<MonitorRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let updated = sqlx::query("UPDATE accounts SET monitored = $2 WHERE fakeid = $1")
+        .bind(&req.fakeid)
+        .bind(req.monitored)
+        .execute(&state.db_pool)
+        .await?
+        .rows_affected();
+    if updated == 0 {
+        return Err(AppError::NotFound("Account not found".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,620 @@
+//! Bulk account import
+//!
+//! `POST /api/account/import` takes a watchlist as OPML, JSON or CSV in the
+//! request body and adds every account to `accounts`. Entries may give a
+//! fakeid (also read from `__biz=` links and our own feed URLs) or just a
+//! name; names are matched against known accounts first, then looked up with
+//! `searchbiz` and only taken on an exact nickname or WeChat ID match.
+//! `searchbiz` allows a few calls per minute, so lookups per request are
+//! capped. The response reports how each row was resolved.
+
+use std::collections::{BTreeMap, HashSet};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    Extension, Json,
+};
+use base64::Engine;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Principal;
+use crate::crawl::Priority;
+use crate::error::AppError;
+use crate::wechat::client::MpClient;
+use crate::wechat::model::BizAccount;
+use crate::AppState;
+
+/// Entries accepted per import
+const MAX_ENTRIES: usize = 1000;
+/// `searchbiz` lookups per import, about five minutes of budget
+const MAX_LOOKUPS: usize = 30;
+/// Accounts requested per lookup, returned as candidates when none matches
+const LOOKUP_COUNT: u32 = 5;
+
+lazy_static! {
+    static ref OUTLINE: Regex = Regex::new(r"(?is)<outline\b([^>]*?)(/?)>").unwrap();
+    static ref ATTRIBUTE: Regex =
+        Regex::new(r#"([A-Za-z_:][-\w:.]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ImportQuery {
+    /// "opml", "json" or "csv"; detected from the body when omitted
+    pub format: Option<String>,
+    /// Resolve and report without adding anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ImportFormat {
+    Opml,
+    Json,
+    Csv,
+}
+
+impl ImportFormat {
+    fn parse(format: &str) -> Option<Self> {
+        match format {
+            "opml" | "xml" => Some(Self::Opml),
+            "json" => Some(Self::Json),
+            "csv" | "txt" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+
+    fn detect(body: &str) -> Self {
+        let head = body.trim_start_matches('\u{feff}').trim_start();
+        if head.starts_with('<') {
+            Self::Opml
+        } else if head.starts_with('[') || head.starts_with('{') {
+            Self::Json
+        } else {
+            Self::Csv
+        }
+    }
+}
+
+/// One account of the input, as given
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ImportEntry {
+    fakeid: Option<String>,
+    nickname: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Added,
+    /// Would be added (dry run)
+    New,
+    /// Already in `accounts`
+    Exists,
+    /// Already in `accounts`, added by another user
+    Taken,
+    /// Same account as an earlier row
+    Duplicate,
+    NotFound,
+    /// No exact match; see the candidates
+    Ambiguous,
+    /// Neither a name nor a fakeid
+    Invalid,
+    /// Over the lookup limit of one import
+    Skipped,
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportRow {
+    /// 1-based position in the input
+    pub row: usize,
+    pub input: String,
+    pub status: Option<ImportStatus>,
+    pub fakeid: Option<String>,
+    pub nickname: Option<String>,
+    /// Where the fakeid came from: "input", "local" or "search"
+    pub resolved_by: Option<&'static str>,
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<BizAccount>,
+}
+
+// ============ Parsing ============
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+/// A fakeid is the base64 of the account's numeric id, e.g. `MzA5MDAwNTA2Ng==`
+fn looks_like_fakeid(value: &str) -> bool {
+    base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .is_ok_and(|id| id.len() >= 5 && id.iter().all(u8::is_ascii_digit))
+}
+
+/// Fakeid in an article link (`__biz=`) or one of our account feed URLs
+fn fakeid_from_url(value: &str) -> Option<String> {
+    let url = url::Url::parse(value.trim()).ok()?;
+    if let Some((_, biz)) = url
+        .query_pairs()
+        .find(|(name, _)| name == "__biz" || name == "fakeid")
+    {
+        return non_empty(Some(&biz));
+    }
+    let file = url.path().strip_prefix("/api/feeds/account/")?;
+    let fakeid = urlencoding::decode(file.strip_suffix(".xml")?).ok()?;
+    non_empty(Some(&fakeid))
+}
+
+/// A bare value: a fakeid, a link carrying one, or a name
+fn entry_from_text(value: &str) -> ImportEntry {
+    let value = value.trim();
+    if looks_like_fakeid(value) {
+        ImportEntry {
+            fakeid: Some(value.to_string()),
+            nickname: None,
+        }
+    } else if let Some(fakeid) = fakeid_from_url(value) {
+        ImportEntry {
+            fakeid: Some(fakeid),
+            nickname: None,
+        }
+    } else {
+        ImportEntry {
+            fakeid: None,
+            nickname: non_empty(Some(value)),
+        }
+    }
+}
+
+/// Leaf `<outline>` elements; folders (outlines with children) are skipped
+fn parse_opml(body: &str) -> Vec<ImportEntry> {
+    OUTLINE
+        .captures_iter(body)
+        .filter_map(|outline| {
+            let attributes: Vec<(String, String)> = ATTRIBUTE
+                .captures_iter(&outline[1])
+                .map(|a| {
+                    let value = a.get(2).or_else(|| a.get(3)).map_or("", |v| v.as_str());
+                    (
+                        a[1].to_lowercase(),
+                        html_escape::decode_html_entities(value).to_string(),
+                    )
+                })
+                .collect();
+            let attr = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .and_then(|(_, v)| non_empty(Some(v)))
+            };
+            let fakeid = attr("fakeid").or_else(|| {
+                ["xmlurl", "htmlurl", "url"]
+                    .iter()
+                    .find_map(|name| attr(name).and_then(|url| fakeid_from_url(&url)))
+            });
+            let self_closing = !outline[2].is_empty();
+            if fakeid.is_none() && attr("xmlurl").is_none() && !self_closing {
+                return None;
+            }
+            Some(ImportEntry {
+                fakeid,
+                nickname: attr("text").or_else(|| attr("title")),
+            })
+        })
+        .collect()
+}
+
+/// An array of names / fakeids / `{"fakeid", "nickname"}` objects, bare or
+/// under `"accounts"`
+fn parse_json(body: &str) -> Result<Vec<ImportEntry>, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(body.trim_start_matches('\u{feff}')).map_err(|e| e.to_string())?;
+    let list = match &value {
+        serde_json::Value::Array(list) => list,
+        serde_json::Value::Object(object) => object
+            .get("accounts")
+            .and_then(|a| a.as_array())
+            .ok_or("JSON 需要是数组或包含 accounts 数组")?,
+        _ => return Err("JSON 需要是数组或包含 accounts 数组".to_string()),
+    };
+    Ok(list
+        .iter()
+        .map(|item| match item {
+            serde_json::Value::String(text) => entry_from_text(text),
+            serde_json::Value::Object(object) => {
+                let field = |name: &str| non_empty(object.get(name).and_then(|v| v.as_str()));
+                ImportEntry {
+                    fakeid: field("fakeid").or_else(|| field("biz")),
+                    nickname: field("nickname").or_else(|| field("name")),
+                }
+            }
+            _ => ImportEntry::default(),
+        })
+        .collect())
+}
+
+/// RFC 4180 rows, quotes and all
+fn csv_rows(body: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = body.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => quoted = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => quoted = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|f| !f.trim().is_empty()));
+    rows
+}
+
+/// CSV with a `fakeid` and/or `nickname` header, or one account per line
+fn parse_csv(body: &str) -> Vec<ImportEntry> {
+    let mut rows = csv_rows(body);
+    let column = |header: &[String], names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.contains(&h.trim().to_lowercase().as_str()))
+    };
+    let (fakeid_col, name_col) = match rows.first() {
+        Some(header) => (
+            column(header, &["fakeid", "biz", "__biz"]),
+            column(header, &["nickname", "name", "公众号", "名称"]),
+        ),
+        None => return Vec::new(),
+    };
+    if fakeid_col.is_none() && name_col.is_none() {
+        return rows
+            .iter()
+            .filter_map(|row| row.iter().find(|f| !f.trim().is_empty()))
+            .map(|value| entry_from_text(value))
+            .collect();
+    }
+    rows.remove(0);
+    rows.iter()
+        .map(|row| {
+            let cell =
+                |col: Option<usize>| non_empty(col.and_then(|c| row.get(c)).map(|s| s.as_str()));
+            ImportEntry {
+                fakeid: cell(fakeid_col),
+                nickname: cell(name_col),
+            }
+        })
+        .collect()
+}
+
+fn parse(format: ImportFormat, body: &str) -> Result<Vec<ImportEntry>, String> {
+    match format {
+        ImportFormat::Opml => Ok(parse_opml(body)),
+        ImportFormat::Json => parse_json(body),
+        ImportFormat::Csv => Ok(parse_csv(body)),
+    }
+}
+
+// ============ Resolution ============
+
+/// A known account with this nickname, from the caller's monitored accounts
+/// or past tasks
+async fn local_match(
+    state: &AppState,
+    principal: &Principal,
+    nickname: &str,
+) -> Result<Option<(String, String)>, AppError> {
+    Ok(sqlx::query_as(
+        r#"
+        SELECT fakeid, nickname FROM accounts
+        WHERE lower(nickname) = lower($1) AND ($2 OR owner_id IS NOT DISTINCT FROM $3)
+        UNION ALL
+        SELECT a.account_fakeid, a.account_name FROM insight_articles a
+        JOIN insight_tasks t ON t.id = a.task_id
+        WHERE lower(a.account_name) = lower($1) AND a.account_fakeid IS NOT NULL
+          AND ($2 OR t.owner_id IS NOT DISTINCT FROM $3)
+        LIMIT 1
+        "#,
+    )
+    .bind(nickname)
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .fetch_optional(&state.db_pool)
+    .await?)
+}
+
+/// The search result that is exactly `name`, by nickname or WeChat ID
+fn exact_match<'a>(name: &str, accounts: &'a [BizAccount]) -> Option<&'a BizAccount> {
+    let name = name.trim().to_lowercase();
+    accounts
+        .iter()
+        .find(|a| a.nickname.to_lowercase() == name || a.alias.to_lowercase() == name)
+}
+
+fn input_text(entry: &ImportEntry) -> String {
+    match (&entry.nickname, &entry.fakeid) {
+        (Some(name), Some(fakeid)) => format!("{} ({})", name, fakeid),
+        (Some(name), None) => name.clone(),
+        (None, Some(fakeid)) => fakeid.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+// ============ Handler ============
+
+/// Add a watchlist of accounts in bulk
+pub async fn import_accounts(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    Query(query): Query<ImportQuery>,
+    body: String,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let format = match query.format.as_deref() {
+        Some(format) => ImportFormat::parse(format).ok_or_else(|| {
+            AppError::BadRequest(format!("未知的导入格式: {} (可选 opml, json, csv)", format))
+        })?,
+        None => ImportFormat::detect(&body),
+    };
+    let entries = parse(format, &body)
+        .map_err(|e| AppError::BadRequest(format!("无法解析导入内容: {}", e)))?;
+    if entries.is_empty() {
+        return Err(AppError::BadRequest("没有可导入的公众号".to_string()));
+    }
+    if entries.len() > MAX_ENTRIES {
+        return Err(AppError::BadRequest(format!(
+            "单次最多导入 {} 个公众号",
+            MAX_ENTRIES
+        )));
+    }
+
+    let mut rows: Vec<ImportRow> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| ImportRow {
+            row: i + 1,
+            input: input_text(entry),
+            status: None,
+            fakeid: entry.fakeid.clone(),
+            nickname: entry.nickname.clone(),
+            resolved_by: entry.fakeid.as_ref().map(|_| "input"),
+            message: None,
+            candidates: Vec::new(),
+        })
+        .collect();
+
+    // Resolve names to fakeids
+    let mut client: Option<Result<MpClient, String>> = None;
+    let mut lookups = 0;
+    let mut inputs = HashSet::new();
+    for row in rows.iter_mut() {
+        let key = match (&row.fakeid, &row.nickname) {
+            (Some(fakeid), _) => fakeid.clone(),
+            (None, Some(name)) => name.to_lowercase(),
+            (None, None) => {
+                row.status = Some(ImportStatus::Invalid);
+                continue;
+            }
+        };
+        if !inputs.insert(key) {
+            row.status = Some(ImportStatus::Duplicate);
+            continue;
+        }
+        let Some(name) = row.nickname.clone().filter(|_| row.fakeid.is_none()) else {
+            continue;
+        };
+
+        if let Some((fakeid, nickname)) = local_match(&state, &principal, &name).await? {
+            row.fakeid = Some(fakeid);
+            row.nickname = Some(nickname);
+            row.resolved_by = Some("local");
+            continue;
+        }
+        if lookups >= MAX_LOOKUPS {
+            row.status = Some(ImportStatus::Skipped);
+            row.message = Some(format!("单次最多搜索 {} 个名称，请分批导入", MAX_LOOKUPS));
+            continue;
+        }
+        lookups += 1;
+        let session = match client.take() {
+            Some(session) => session,
+            None => MpClient::from_headers(&state, &headers)
+                .await
+                .map_err(|e| e.to_string()),
+        };
+        let found = match &session {
+            Ok(session) => session
+                .search_accounts(&name, LOOKUP_COUNT, Priority::Task)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.clone()),
+        };
+        client = Some(session);
+        match found {
+            Ok(accounts) => match exact_match(&name, &accounts) {
+                Some(account) => {
+                    row.fakeid = Some(account.fakeid.clone());
+                    row.nickname = Some(account.nickname.clone());
+                    row.resolved_by = Some("search");
+                }
+                None if accounts.is_empty() => row.status = Some(ImportStatus::NotFound),
+                None => {
+                    row.status = Some(ImportStatus::Ambiguous);
+                    row.candidates = accounts;
+                }
+            },
+            Err(e) => {
+                row.status = Some(ImportStatus::Error);
+                row.message = Some(e);
+            }
+        }
+    }
+
+    // Names and fakeids of one account given in different rows
+    let mut fakeids = HashSet::new();
+    for row in rows.iter_mut().filter(|r| r.status.is_none()) {
+        if let Some(fakeid) = &row.fakeid {
+            if !fakeids.insert(fakeid.clone()) {
+                row.status = Some(ImportStatus::Duplicate);
+            }
+        }
+    }
+
+    let pending: Vec<&ImportRow> = rows.iter().filter(|r| r.status.is_none()).collect();
+    let fakeids: Vec<String> = pending.iter().filter_map(|r| r.fakeid.clone()).collect();
+    let nicknames: Vec<Option<String>> = pending.iter().map(|r| r.nickname.clone()).collect();
+    let new: HashSet<String> = if query.dry_run {
+        let existing: Vec<String> =
+            sqlx::query_scalar("SELECT fakeid FROM accounts WHERE fakeid = ANY($1)")
+                .bind(&fakeids)
+                .fetch_all(&state.db_pool)
+                .await?;
+        let existing: HashSet<String> = existing.into_iter().collect();
+        fakeids
+            .iter()
+            .filter(|f| !existing.contains(*f))
+            .cloned()
+            .collect()
+    } else {
+        let added: Vec<String> = sqlx::query_scalar(
+            r#"
+            INSERT INTO accounts (fakeid, nickname, create_time, update_time, owner_id)
+            SELECT fakeid, nickname, $3, $3, $4 FROM UNNEST($1::text[], $2::text[]) AS t(fakeid, nickname)
+            ON CONFLICT (fakeid) DO NOTHING
+            RETURNING fakeid
+            "#,
+        )
+        .bind(&fakeids)
+        .bind(&nicknames)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(principal.user_id)
+        .fetch_all(&state.db_pool)
+        .await?;
+        added.into_iter().collect()
+    };
+    // Accounts are unique by fakeid, so one another user added is not ours
+    let taken: Vec<String> = sqlx::query_scalar(
+        "SELECT fakeid FROM accounts WHERE fakeid = ANY($1) AND NOT ($2 OR owner_id IS NOT DISTINCT FROM $3)",
+    )
+    .bind(&fakeids)
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+    let taken: HashSet<String> = taken.into_iter().collect();
+    for row in rows.iter_mut().filter(|r| r.status.is_none()) {
+        let is_new = row.fakeid.as_ref().is_some_and(|f| new.contains(f));
+        let is_taken = row.fakeid.as_ref().is_some_and(|f| taken.contains(f));
+        row.status = Some(match (is_new, query.dry_run) {
+            (true, true) => ImportStatus::New,
+            (true, false) => ImportStatus::Added,
+            (false, _) if is_taken => {
+                row.message = Some("该公众号已被其他用户添加".to_string());
+                ImportStatus::Taken
+            }
+            (false, _) => ImportStatus::Exists,
+        });
+    }
+
+    let mut summary: BTreeMap<ImportStatus, usize> = BTreeMap::new();
+    for status in rows.iter().filter_map(|r| r.status) {
+        *summary.entry(status).or_default() += 1;
+    }
+    if !query.dry_run {
+        tracing::info!(
+            "[Accounts] Imported {} account(s) from {} row(s)",
+            new.len(),
+            rows.len()
+        );
+    }
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "dry_run": query.dry_run,
+        "summary": summary,
+        "data": rows
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_import() {
+        assert!(looks_like_fakeid("MzA5MDAwNTA2Ng=="));
+        assert!(!looks_like_fakeid("科技观察"));
+        assert!(!looks_like_fakeid("abcd"));
+
+        let opml = r#"<?xml version="1.0"?><opml version="2.0"><head><title>订阅</title></head><body>
+            <outline text="科技">
+                <outline text="A &amp; B" type="rss" xmlUrl="https://rss.example.com/feed?__biz=MzA5MDAwNTA2Ng%3D%3D"/>
+                <outline text="科技观察" xmlUrl="http://localhost:3000/api/feeds/account/MzI0MDAwMDAwMQ%3D%3D.xml"></outline>
+                <outline title="只有名字"/>
+            </outline>
+        </body></opml>"#;
+        assert_eq!(ImportFormat::detect(opml), ImportFormat::Opml);
+        let entries = parse_opml(opml);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].fakeid.as_deref(), Some("MzA5MDAwNTA2Ng=="));
+        assert_eq!(entries[0].nickname.as_deref(), Some("A & B"));
+        assert_eq!(entries[1].fakeid.as_deref(), Some("MzI0MDAwMDAwMQ=="));
+        assert_eq!(entries[2].fakeid, None);
+        assert_eq!(entries[2].nickname.as_deref(), Some("只有名字"));
+
+        let json = r#"{"accounts": ["科技观察", "MzA5MDAwNTA2Ng==", {"name": "晚点", "fakeid": "MzI0MDAwMDAwMQ=="}, 3]}"#;
+        let entries = parse_json(json).unwrap();
+        assert_eq!(entries[0].nickname.as_deref(), Some("科技观察"));
+        assert_eq!(entries[1].fakeid.as_deref(), Some("MzA5MDAwNTA2Ng=="));
+        assert_eq!(entries[2].nickname.as_deref(), Some("晚点"));
+        assert_eq!(entries[3], ImportEntry::default());
+        assert!(parse_json("{\"a\": 1}").is_err());
+
+        let csv = "\u{feff}公众号,fakeid\r\n\"科技, 观察\",\r\n晚点,MzI0MDAwMDAwMQ==\r\n\r\n";
+        let entries = parse_csv(csv);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].nickname.as_deref(), Some("科技, 观察"));
+        assert_eq!(entries[0].fakeid, None);
+        assert_eq!(entries[1].fakeid.as_deref(), Some("MzI0MDAwMDAwMQ=="));
+
+        let lines =
+            parse_csv("科技观察\nhttps://mp.weixin.qq.com/s?__biz=MzA5MDAwNTA2Ng==&mid=1\n");
+        assert_eq!(lines[0].nickname.as_deref(), Some("科技观察"));
+        assert_eq!(lines[1].fakeid.as_deref(), Some("MzA5MDAwNTA2Ng=="));
+
+        let found = vec![BizAccount {
+            fakeid: "x".to_string(),
+            nickname: "AI Daily".to_string(),
+            alias: "aidaily".to_string(),
+            round_head_img: String::new(),
+        }];
+        assert!(exact_match("ai daily", &found).is_some());
+        assert!(exact_match("aidaily", &found).is_some());
+        assert!(exact_match("AI", &found).is_none());
+    }
+}
@@ -0,0 +1,237 @@
+//! Account blocklist and allowlist
+//!
+//! Keyword discovery surfaces plenty of marketing accounts. Discovered accounts
+//! on `account_blocklist` are never scanned, and a task can restrict discovery
+//! to `account_allowlist`. Tasks targeting a specific account bypass both lists.
+
+use std::collections::HashSet;
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::AppError;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct ListEntryRequest {
+    /// "block" or "allow"
+    pub list: String,
+    pub fakeid: String,
+    pub nickname: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoveEntryRequest {
+    pub list: String,
+    pub fakeid: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ListEntry {
+    pub fakeid: String,
+    pub nickname: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: i64,
+}
+
+fn table_for(list: &str) -> Result<&'static str, AppError> {
+    match list {
+        "block" => Ok("account_blocklist"),
+        "allow" => Ok("account_allowlist"),
+        other => Err(AppError::BadRequest(format!(
+            "不支持的list: {} (block/allow)",
+            other
+        ))),
+    }
+}
+
+/// Which discovered accounts a task may scan
+#[derive(Debug, Default)]
+pub struct AccountFilter {
+    blocked: HashSet<String>,
+    /// `Some` restricts scanning to these accounts
+    allowed: Option<HashSet<String>>,
+}
+
+impl AccountFilter {
+    pub fn allows(&self, fakeid: &str) -> bool {
+        !self.blocked.contains(fakeid) && self.allowed.as_ref().is_none_or(|a| a.contains(fakeid))
+    }
+
+    /// Filter for a task: the stored lists combined with its overrides
+    pub async fn for_task(
+        pool: &PgPool,
+        ignore_blocklist: bool,
+        blocked_fakeids: &[String],
+        allowlist_only: bool,
+        allowed_fakeids: Option<&[String]>,
+    ) -> Result<Self, sqlx::Error> {
+        let mut blocked: HashSet<String> = blocked_fakeids.iter().cloned().collect();
+        if !ignore_blocklist {
+            let stored: Vec<String> = sqlx::query_scalar("SELECT fakeid FROM account_blocklist")
+                .fetch_all(pool)
+                .await?;
+            blocked.extend(stored);
+        }
+
+        let allowed = match allowed_fakeids {
+            Some(ids) => Some(ids.iter().cloned().collect()),
+            None if allowlist_only => Some(
+                sqlx::query_scalar::<_, String>("SELECT fakeid FROM account_allowlist")
+                    .fetch_all(pool)
+                    .await?
+                    .into_iter()
+                    .collect(),
+            ),
+            None => None,
+        };
+
+        Ok(AccountFilter { blocked, allowed })
+    }
+}
+
+/// Accounts to search by keyword: `extra` fakeids, the allowlist, then monitored
+/// accounts (`accounts`, most recently updated first), as far as `filter` allows
+pub async fn search_targets(
+    pool: &PgPool,
+    filter: &AccountFilter,
+    extra: &[String],
+    limit: usize,
+) -> Result<Vec<(String, String)>, sqlx::Error> {
+    let mut candidates: Vec<(String, Option<String>)> =
+        extra.iter().map(|fakeid| (fakeid.clone(), None)).collect();
+    candidates.extend(
+        sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT fakeid, nickname FROM account_allowlist ORDER BY created_at",
+        )
+        .fetch_all(pool)
+        .await?,
+    );
+    candidates.extend(
+        sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT fakeid, nickname FROM accounts ORDER BY last_update_time DESC NULLS LAST",
+        )
+        .fetch_all(pool)
+        .await?,
+    );
+
+    let mut targets: Vec<(String, String)> = Vec::new();
+    for (fakeid, nickname) in candidates {
+        if !filter.allows(&fakeid) {
+            continue;
+        }
+        if let Some(target) = targets.iter_mut().find(|(f, _)| *f == fakeid) {
+            // `extra` fakeids take their nickname from the lists
+            if let Some(nickname) = nickname.filter(|_| target.1 == fakeid) {
+                target.1 = nickname;
+            }
+        } else if targets.len() < limit {
+            let nickname = nickname.unwrap_or_else(|| fakeid.clone());
+            targets.push((fakeid, nickname));
+        }
+    }
+    Ok(targets)
+}
+
+/// Both lists
+pub async fn get_lists(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let blocklist = sqlx::query_as::<_, ListEntry>(
+        "SELECT fakeid, nickname, reason, created_at FROM account_blocklist ORDER BY created_at DESC",
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+    let allowlist = sqlx::query_as::<_, ListEntry>(
+        "SELECT fakeid, nickname, reason, created_at FROM account_allowlist ORDER BY created_at DESC",
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "blocklist": blocklist,
+        "allowlist": allowlist
+    })))
+}
+
+/// Add an account to a list (or update its nickname and reason)
+pub async fn add_entry(
+    State(state): State<AppState>,
+    Json(req): Json<ListEntryRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let table = table_for(&req.list)?;
+    let fakeid = req.fakeid.trim();
+    if fakeid.is_empty() {
+        return Err(AppError::BadRequest("fakeid不能为空".to_string()));
+    }
+
+    let nickname = match &req.nickname {
+        Some(n) => Some(n.clone()),
+        None => sqlx::query_scalar::<_, Option<String>>(
+            "SELECT nickname FROM accounts WHERE fakeid = $1",
+        )
+        .bind(fakeid)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .flatten(),
+    };
+
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO {} (fakeid, nickname, reason, created_at) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (fakeid) DO UPDATE SET
+            nickname = COALESCE(EXCLUDED.nickname, {0}.nickname),
+            reason = COALESCE(EXCLUDED.reason, {0}.reason)
+        "#,
+        table
+    ))
+    .bind(fakeid)
+    .bind(&nickname)
+    .bind(&req.reason)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Remove an account from a list
+pub async fn remove_entry(
+    State(state): State<AppState>,
+    Json(req): Json<RemoveEntryRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let table = table_for(&req.list)?;
+    let deleted = sqlx::query(&format!("DELETE FROM {} WHERE fakeid = $1", table))
+        .bind(&req.fakeid)
+        .execute(&state.db_pool)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(AppError::NotFound("Account not in list".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_filter() {
+        let filter = AccountFilter {
+            blocked: ["spam".to_string()].into(),
+            allowed: None,
+        };
+        assert!(filter.allows("news"));
+        assert!(!filter.allows("spam"));
+
+        let filter = AccountFilter {
+            blocked: ["spam".to_string()].into(),
+            allowed: Some(["news".to_string(), "spam".to_string()].into()),
+        };
+        assert!(filter.allows("news"));
+        assert!(!filter.allows("spam"));
+        assert!(!filter.allows("other"));
+    }
+}
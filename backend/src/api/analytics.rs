@@ -0,0 +1,230 @@
+//! Task analytics
+//!
+//! Chart-ready aggregates of a task's `insight_articles`: publications over
+//! time, top accounts, similarity bands and title keywords. Aggregation runs in
+//! SQL; only keyword counting happens here, since titles need jieba
+//! segmentation that Postgres lacks.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::authorize_task;
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+/// Timezone for day/week buckets; WeChat publishing follows China time
+const DEFAULT_TIMEZONE: &str = "Asia/Shanghai";
+/// Similarity bands per unit (0.1 wide)
+const BANDS_PER_UNIT: i32 = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    /// "day" (default) or "week"
+    pub bucket: Option<String>,
+    pub tz: Option<String>,
+    /// Accounts and keywords returned (default 20)
+    pub top: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Overview {
+    pub total: i64,
+    pub accounts: i64,
+    pub first_publish_time: Option<i64>,
+    pub last_publish_time: Option<i64>,
+    pub avg_similarity: Option<f64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TimelinePoint {
+    /// Bucket start date, `YYYY-MM-DD` (Monday for weeks)
+    pub bucket: String,
+    pub count: i64,
+    pub avg_similarity: Option<f64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AccountStat {
+    pub account_name: String,
+    pub account_fakeid: Option<String>,
+    pub count: i64,
+    pub avg_similarity: Option<f64>,
+    pub avg_relevance: Option<f64>,
+    pub last_publish_time: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SimilarityBand {
+    pub min: f64,
+    pub max: f64,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeywordCount {
+    pub keyword: String,
+    /// Titles containing the keyword
+    pub count: usize,
+}
+
+/// Terms (2+ characters) by the number of titles containing them, most frequent first
+fn title_keywords<'a>(titles: impl IntoIterator<Item = &'a str>, top: usize) -> Vec<KeywordCount> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for title in titles {
+        let terms: HashSet<String> = crate::fulltext::segment(title)
+            .split(' ')
+            .filter(|t| t.chars().count() >= 2 && !t.chars().all(|c| c.is_ascii_digit()))
+            .map(String::from)
+            .collect();
+        for term in terms {
+            *counts.entry(term).or_default() += 1;
+        }
+    }
+    let mut keywords: Vec<KeywordCount> = counts
+        .into_iter()
+        .map(|(keyword, count)| KeywordCount { keyword, count })
+        .collect();
+    keywords.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.keyword.cmp(&b.keyword))
+    });
+    keywords.truncate(top);
+    keywords
+}
+
+/// Aggregates of a task's articles for charts
+pub async fn task_analytics(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<AnalyticsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let bucket = query.bucket.unwrap_or_else(|| "day".to_string());
+    if bucket != "day" && bucket != "week" {
+        return Err(AppError::BadRequest("bucket只支持day或week".to_string()));
+    }
+    let tz = query.tz.unwrap_or_else(|| DEFAULT_TIMEZONE.to_string());
+    let tz_valid: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)")
+            .bind(&tz)
+            .fetch_one(&state.db_pool)
+            .await?;
+    if !tz_valid {
+        return Err(AppError::BadRequest(format!("无效的时区: {}", tz)));
+    }
+    let top = query.top.unwrap_or(20).clamp(1, 100);
+
+    authorize_task(&state, &principal, id).await?;
+
+    let overview = sqlx::query_as::<_, Overview>(
+        r#"
+        SELECT COUNT(*) AS total,
+               COUNT(DISTINCT COALESCE(account_fakeid, account_name)) AS accounts,
+               MIN(NULLIF(publish_time, 0)) AS first_publish_time,
+               MAX(publish_time) AS last_publish_time,
+               AVG(similarity) AS avg_similarity
+        FROM insight_articles WHERE task_id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    let timeline = sqlx::query_as::<_, TimelinePoint>(
+        r#"
+        SELECT to_char(date_trunc($2, to_timestamp(publish_time) AT TIME ZONE $3), 'YYYY-MM-DD') AS bucket,
+               COUNT(*) AS count,
+               AVG(similarity) AS avg_similarity
+        FROM insight_articles
+        WHERE task_id = $1 AND publish_time > 0
+        GROUP BY 1
+        ORDER BY 1
+        "#,
+    )
+    .bind(id)
+    .bind(&bucket)
+    .bind(&tz)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let accounts = sqlx::query_as::<_, AccountStat>(
+        r#"
+        SELECT COALESCE(account_name, 'unknown') AS account_name,
+               MAX(account_fakeid) AS account_fakeid,
+               COUNT(*) AS count,
+               AVG(similarity) AS avg_similarity,
+               AVG(relevance_score) AS avg_relevance,
+               MAX(publish_time) AS last_publish_time
+        FROM insight_articles
+        WHERE task_id = $1
+        GROUP BY COALESCE(account_name, 'unknown')
+        ORDER BY count DESC, avg_similarity DESC NULLS LAST
+        LIMIT $2
+        "#,
+    )
+    .bind(id)
+    .bind(top)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let similarity_bands = sqlx::query_as::<_, SimilarityBand>(
+        r#"
+        SELECT band / $2::float8 AS min, (band + 1) / $2::float8 AS max, COUNT(*) AS count
+        FROM (
+            SELECT floor(similarity * $2)::int AS band
+            FROM insight_articles
+            WHERE task_id = $1 AND similarity IS NOT NULL
+        ) b
+        GROUP BY band
+        ORDER BY band
+        "#,
+    )
+    .bind(id)
+    .bind(BANDS_PER_UNIT)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let titles: Vec<String> =
+        sqlx::query_scalar("SELECT title FROM insight_articles WHERE task_id = $1")
+            .bind(id)
+            .fetch_all(&state.db_pool)
+            .await?;
+    let keywords = title_keywords(titles.iter().map(String::as_str), top as usize);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "bucket": bucket,
+            "timezone": tz,
+            "overview": overview,
+            "timeline": timeline,
+            "accounts": accounts,
+            "similarity_bands": similarity_bands,
+            "keywords": keywords
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_keywords() {
+        let titles = ["Rust rust tokio", "rust 2024", "tokio axum"];
+        let keywords = title_keywords(titles, 2);
+        assert_eq!(keywords.len(), 2);
+        // Counted once per title; digits are skipped
+        assert_eq!(keywords[0].keyword, "rust");
+        assert_eq!(keywords[0].count, 2);
+        assert_eq!(keywords[1].keyword, "tokio");
+    }
+}
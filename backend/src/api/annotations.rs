@@ -0,0 +1,310 @@
+//! Article tags and notes
+//!
+//! Matched articles can carry free-form tags (`insight_articles.tags`) and any
+//! number of notes (`article_annotations`), for triaging a task's results
+//! before exporting a subset. `get_task` filters by tag with `?tag=`, and
+//! returns the notes along with the articles.
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::authorize_task;
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+/// Longest tag, in characters
+const MAX_TAG_CHARS: usize = 32;
+const MAX_TAGS: usize = 20;
+const MAX_NOTE_CHARS: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct TagsRequest {
+    /// `insight_articles.id`
+    pub id: Uuid,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddNoteRequest {
+    /// `insight_articles.id`
+    pub id: Uuid,
+    pub note: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNoteRequest {
+    /// `article_annotations.id`
+    pub id: Uuid,
+    pub note: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteNoteRequest {
+    /// `article_annotations.id`
+    pub id: Uuid,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Annotation {
+    pub id: Uuid,
+    pub article_id: Uuid,
+    pub note: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Trimmed tags without blanks or repeats; an error for overlong ones
+pub fn clean_tags(tags: &[String]) -> Result<Vec<String>, AppError> {
+    let mut cleaned: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim();
+        if tag.chars().count() > MAX_TAG_CHARS {
+            return Err(AppError::BadRequest(format!(
+                "标签不能超过 {} 个字符: {}",
+                MAX_TAG_CHARS, tag
+            )));
+        }
+        if !tag.is_empty() && !cleaned.iter().any(|t| t == tag) {
+            cleaned.push(tag.to_string());
+        }
+    }
+    Ok(cleaned)
+}
+
+/// Tags of a `?tag=a,b` filter; articles must carry all of them
+pub fn tag_filter(tag: Option<&str>) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in tag.unwrap_or("").split(',') {
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+fn clean_note(note: &str) -> Result<&str, AppError> {
+    let note = note.trim();
+    if note.is_empty() {
+        return Err(AppError::BadRequest("笔记不能为空".to_string()));
+    }
+    if note.chars().count() > MAX_NOTE_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "笔记不能超过 {} 个字符",
+            MAX_NOTE_CHARS
+        )));
+    }
+    Ok(note)
+}
+
+/// Task of an insight article the principal may see
+async fn article_task(
+    state: &AppState,
+    principal: &Principal,
+    article_id: Uuid,
+) -> Result<Uuid, AppError> {
+    let task_id: Uuid = sqlx::query_scalar("SELECT task_id FROM insight_articles WHERE id = $1")
+        .bind(article_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Article not found".to_string()))?;
+    authorize_task(state, principal, task_id)
+        .await
+        .map_err(|_| AppError::NotFound("Article not found".to_string()))?;
+    Ok(task_id)
+}
+
+/// Task of a note the principal may see
+async fn note_task(
+    state: &AppState,
+    principal: &Principal,
+    note_id: Uuid,
+) -> Result<Uuid, AppError> {
+    let task_id: Uuid = sqlx::query_scalar("SELECT task_id FROM article_annotations WHERE id = $1")
+        .bind(note_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Note not found".to_string()))?;
+    authorize_task(state, principal, task_id)
+        .await
+        .map_err(|_| AppError::NotFound("Note not found".to_string()))?;
+    Ok(task_id)
+}
+
+/// Notes on a task's articles, oldest first
+pub async fn task_annotations(
+    pool: &sqlx::PgPool,
+    task_id: Uuid,
+) -> Result<Vec<Annotation>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, article_id, note, created_at, updated_at FROM article_annotations WHERE task_id = $1 ORDER BY created_at",
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await
+}
+
+// ============ Handlers ============
+
+/// Add and remove tags of an article; returns its tags
+pub async fn update_tags(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<TagsRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    article_task(&state, &principal, req.id).await?;
+    let add = clean_tags(&req.add)?;
+    let remove = clean_tags(&req.remove)?;
+
+    // Existing tags keep their order, new ones are appended; nothing is
+    // updated when the result would exceed the limit
+    let tags: Option<Vec<String>> = sqlx::query_scalar(
+        r#"
+        WITH next AS (
+            SELECT ARRAY(
+                SELECT t FROM unnest(a.tags || $2::TEXT[]) WITH ORDINALITY AS x(t, n)
+                WHERE t <> ALL($3::TEXT[])
+                GROUP BY t
+                ORDER BY MIN(n)
+            ) AS tags
+            FROM insight_articles a
+            WHERE a.id = $1
+        )
+        UPDATE insight_articles SET tags = next.tags
+        FROM next
+        WHERE id = $1 AND cardinality(next.tags) <= $4
+        RETURNING insight_articles.tags
+        "#,
+    )
+    .bind(req.id)
+    .bind(&add)
+    .bind(&remove)
+    .bind(MAX_TAGS as i32)
+    .fetch_optional(&state.db_pool)
+    .await?;
+    let tags = tags.ok_or(AppError::BadRequest(format!(
+        "每篇文章最多 {} 个标签",
+        MAX_TAGS
+    )))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "tags": tags
+    })))
+}
+
+/// Tags used in a task with their article counts, most used first
+pub async fn task_tags(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT t, COUNT(*) FROM insight_articles, unnest(tags) AS t WHERE task_id = $1 GROUP BY t ORDER BY COUNT(*) DESC, t",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await?;
+    let tags: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(tag, count)| serde_json::json!({ "tag": tag, "count": count }))
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": tags
+    })))
+}
+
+/// Attach a note to an article
+pub async fn add_note(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<AddNoteRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let task_id = article_task(&state, &principal, req.id).await?;
+    let note = clean_note(&req.note)?;
+    let now = chrono::Utc::now().timestamp();
+    let annotation: Annotation = sqlx::query_as(
+        "INSERT INTO article_annotations (id, article_id, task_id, note, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $5) RETURNING id, article_id, note, created_at, updated_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(req.id)
+    .bind(task_id)
+    .bind(note)
+    .bind(now)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": annotation
+    })))
+}
+
+/// Replace the text of a note
+pub async fn update_note(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<UpdateNoteRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    note_task(&state, &principal, req.id).await?;
+    let note = clean_note(&req.note)?;
+    let annotation: Annotation = sqlx::query_as(
+        "UPDATE article_annotations SET note = $1, updated_at = $2 WHERE id = $3 RETURNING id, article_id, note, created_at, updated_at",
+    )
+    .bind(note)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": annotation
+    })))
+}
+
+/// Delete a note
+pub async fn delete_note(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<DeleteNoteRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    note_task(&state, &principal, req.id).await?;
+    sqlx::query("DELETE FROM article_annotations WHERE id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_tags() {
+        let tags = vec![
+            " 待读 ".to_string(),
+            "".to_string(),
+            "重要".to_string(),
+            "待读".to_string(),
+        ];
+        assert_eq!(clean_tags(&tags).unwrap(), vec!["待读", "重要"]);
+        assert!(clean_tags(&["长".repeat(MAX_TAG_CHARS + 1)]).is_err());
+        assert_eq!(tag_filter(Some(" 重要, ,待读,重要")), vec!["重要", "待读"]);
+        assert!(tag_filter(None).is_empty());
+        assert!(clean_note("  ").is_err());
+        assert_eq!(clean_note(" 笔记 ").unwrap(), "笔记");
+    }
+}
@@ -0,0 +1,244 @@
+//! Archive availability API
+//!
+//! Lists articles whose online copies vanished and exposes the check history.
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::archive::{run_verification_round, VANISHED_STATUSES};
+use crate::error::AppError;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct VanishedQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// List articles whose latest check says the online copy is gone,
+/// with a flag telling whether a local snapshot exists
+pub async fn list_vanished(
+    State(state): State<AppState>,
+    Query(query): Query<VanishedQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let total: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM (
+            SELECT DISTINCT ON (url) status
+            FROM article_link_checks
+            ORDER BY url, checked_at DESC
+        ) v
+        WHERE v.status = ANY($1)
+        "#,
+    )
+    .bind(&VANISHED_STATUSES[..])
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    #[allow(clippy::type_complexity)]
+    let rows: Vec<(
+        String,
+        Option<String>,
+        Option<String>,
+        String,
+        Option<i32>,
+        Option<String>,
+        i64,
+        Option<i64>,
+        bool,
+    )> = sqlx::query_as(
+        r#"
+        SELECT v.url, v.article_id,
+               COALESCE(a.title, (SELECT ia.title FROM insight_articles ia WHERE ia.url = v.url LIMIT 1)) AS title,
+               v.status, v.http_status, v.detail, v.checked_at,
+               (SELECT MAX(k.checked_at) FROM article_link_checks k WHERE k.url = v.url AND k.status = 'ok') AS last_ok_at,
+               EXISTS(SELECT 1 FROM article_content c WHERE c.id = v.article_id OR c.original_url = v.url) AS has_snapshot
+        FROM (
+            SELECT DISTINCT ON (url) url, article_id, status, http_status, detail, checked_at
+            FROM article_link_checks
+            ORDER BY url, checked_at DESC
+        ) v
+        LEFT JOIN articles a ON a.id = v.article_id
+        WHERE v.status = ANY($1)
+        ORDER BY v.checked_at DESC
+        OFFSET $2 LIMIT $3
+        "#,
+    )
+    .bind(&VANISHED_STATUSES[..])
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let data: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(
+            |(url, article_id, title, status, http_status, detail, checked_at, last_ok_at, has_snapshot)| {
+                serde_json::json!({
+                    "url": url,
+                    "article_id": article_id,
+                    "title": title,
+                    "status": status,
+                    "http_status": http_status,
+                    "detail": detail,
+                    "checked_at": checked_at,
+                    "last_ok_at": last_ok_at,
+                    "has_snapshot": has_snapshot
+                })
+            },
+        )
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data,
+        "total": total,
+        "offset": offset,
+        "limit": limit
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub url: String,
+}
+
+/// Status history for a single article URL
+pub async fn link_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if query.url.is_empty() {
+        return Err(AppError::BadRequest("url不能为空".to_string()));
+    }
+
+    let rows: Vec<(String, Option<i32>, Option<String>, i64)> = sqlx::query_as(
+        "SELECT status, http_status, detail, checked_at FROM article_link_checks WHERE url = $1 ORDER BY checked_at DESC LIMIT 200",
+    )
+    .bind(&query.url)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let history: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|(status, http_status, detail, checked_at)| {
+            serde_json::json!({
+                "status": status,
+                "http_status": http_status,
+                "detail": detail,
+                "checked_at": checked_at
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "url": query.url,
+        "data": history
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyNowRequest {
+    pub limit: Option<i64>,
+}
+
+/// Trigger a verification round immediately
+pub async fn verify_now(
+    State(state): State<AppState>,
+    Json(req): Json<VerifyNowRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = req.limit.unwrap_or(20).clamp(1, 500);
+    let summary = run_verification_round(&state.db_pool, limit).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "summary": summary
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertsQuery {
+    pub kind: Option<String>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Takedown / change alerts, newest first
+pub async fn list_alerts(
+    State(state): State<AppState>,
+    Query(query): Query<AlertsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM archive_alerts WHERE ($1::TEXT IS NULL OR kind = $1)",
+    )
+    .bind(&query.kind)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    #[allow(clippy::type_complexity)]
+    let rows: Vec<(
+        i64,
+        String,
+        Option<String>,
+        String,
+        String,
+        Option<String>,
+        Option<f32>,
+        Option<String>,
+        Option<String>,
+        bool,
+        i64,
+    )> = sqlx::query_as(
+        r#"
+        SELECT id, url, article_id, kind, status, detail, similarity, snapshot_url, diff, notified, created_at
+        FROM archive_alerts
+        WHERE ($1::TEXT IS NULL OR kind = $1)
+        ORDER BY created_at DESC
+        OFFSET $2 LIMIT $3
+        "#,
+    )
+    .bind(&query.kind)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let data: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(
+            |(id, url, article_id, kind, status, detail, similarity, snapshot_url, diff, notified, created_at)| {
+                serde_json::json!({
+                    "id": id,
+                    "url": url,
+                    "article_id": article_id,
+                    "kind": kind,
+                    "status": status,
+                    "detail": detail,
+                    "similarity": similarity,
+                    "snapshot_url": snapshot_url,
+                    "diff": diff,
+                    "notified": notified,
+                    "created_at": created_at
+                })
+            },
+        )
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data,
+        "total": total,
+        "offset": offset,
+        "limit": limit
+    })))
+}
@@ -0,0 +1,87 @@
+//! Browser login for the API token
+//!
+//! The frontend posts a token once and gets it back as an HttpOnly cookie, so
+//! the token never has to live in page script. Scripts and CLI clients send
+//! it as a header instead and never need these endpoints.
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth::{self, Principal, COOKIE_NAME};
+use crate::error::AppError;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub token: String,
+}
+
+/// Check a token and store it in the session cookie
+pub async fn login(
+    State(state): State<AppState>,
+    Json(req): Json<LoginRequest>,
+) -> Result<Response, AppError> {
+    if !auth::enabled() {
+        return Ok(Json(json!({
+            "success": true,
+            "data": { "role": auth::Role::Admin, "auth_enabled": false }
+        }))
+        .into_response());
+    }
+    let token = req.token.trim();
+    let principal = auth::resolve(&state.db_pool, token)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("API Token 无效".to_string()))?;
+
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Strict; Max-Age={}",
+        COOKIE_NAME,
+        token,
+        30 * 24 * 3600
+    );
+    Ok((
+        StatusCode::OK,
+        [(header::SET_COOKIE, cookie)],
+        Json(json!({
+            "success": true,
+            "data": {
+                "role": principal.role,
+                "name": principal.name,
+                "user_id": principal.user_id,
+                "auth_enabled": true
+            }
+        })),
+    )
+        .into_response())
+}
+
+/// Clear the session cookie
+pub async fn logout() -> impl IntoResponse {
+    let cookie = format!(
+        "{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0",
+        COOKIE_NAME
+    );
+    (
+        [(header::SET_COOKIE, cookie)],
+        Json(json!({ "success": true })),
+    )
+}
+
+/// User and role of the current token
+pub async fn me(Extension(principal): Extension<Principal>) -> Json<serde_json::Value> {
+    Json(json!({
+        "success": true,
+        "data": {
+            "role": principal.role,
+            "name": principal.name,
+            "user_id": principal.user_id,
+            "auth_enabled": auth::enabled()
+        }
+    }))
+}
@@ -0,0 +1,374 @@
+//! Cache management API
+//!
+//! Invalidation and TTL control for stored article HTML (`article_content`,
+//! see `crate::repository::articles`), size statistics, bulk clearing and age/TTL eviction, and garbage collection of
+//! assets no cached HTML references (see `crate::gc`). The embedding cache
+//! (`crate::llm::cache`) is reported and cleared here as well.
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::repository::articles::{self, now_ts};
+use crate::AppState;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CacheEntryRequest {
+    pub id: Option<String>, // fakeid:aid (article_content id)
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTtlRequest {
+    pub id: Option<String>,
+    pub url: Option<String>,
+    /// Seconds from now until the entry expires. `None` removes the TTL.
+    pub ttl_seconds: Option<i64>,
+}
+
+/// Longest TTL accepted for a cached article, longer ones are capped
+const MAX_TTL_SECS: i64 = 10 * 365 * 86400;
+
+/// Tables `clear` can empty
+const CLEARABLE: &[&str] = &["article_content", "assets", "embedding_cache"];
+
+#[derive(Debug, Deserialize)]
+pub struct ClearCacheRequest {
+    /// Defaults to `article_content`; `assets` also deletes the stored blobs
+    pub tables: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvictQuery {
+    /// Age such as `3600`, `90m`, `12h` or `30d`; without it only entries
+    /// past their TTL are evicted
+    pub older_than: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TableStats {
+    pub rows: i64,
+    /// On-disk size of the table, including TOAST and indexes
+    pub bytes: i64,
+    /// Entries past their TTL, for tables that have one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired: Option<i64>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StorageStats {
+    pub storage: String,
+    pub rows: i64,
+    /// Blob bytes, wherever they are stored
+    pub bytes: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DedupStats {
+    /// URLs whose bytes are stored under another asset
+    pub aliases: i64,
+    /// Blob bytes not stored thanks to those aliases
+    pub bytes_saved: i64,
+    /// Assets saved before hashing (`--dedup-assets` merges them)
+    pub unhashed: i64,
+}
+
+// ============ Helpers ============
+
+/// Expiry timestamp for a requested TTL; the TTL must be positive
+pub fn expires_at(ttl_seconds: Option<i64>) -> Result<Option<i64>, AppError> {
+    match ttl_seconds {
+        Some(ttl) if ttl <= 0 => Err(AppError::BadRequest("ttl_seconds必须大于0".to_string())),
+        Some(ttl) => Ok(Some(now_ts() + ttl.min(MAX_TTL_SECS))),
+        None => Ok(None),
+    }
+}
+
+/// Parse an age like `3600`, `90m`, `12h` or `30d` into seconds
+pub fn parse_age(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last()? {
+        (i, c) if c.is_ascii_alphabetic() => (&value[..i], c.to_ascii_lowercase()),
+        _ => (value, 's'),
+    };
+    let multiplier = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    number
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .and_then(|n| n.checked_mul(multiplier))
+}
+
+async fn table_stats(
+    pool: &sqlx::PgPool,
+    table: &str,
+    expiry: bool,
+) -> Result<TableStats, sqlx::Error> {
+    let expired = if expiry {
+        "COUNT(*) FILTER (WHERE expires_at IS NOT NULL AND expires_at <= $1)"
+    } else {
+        "NULL::bigint"
+    };
+    sqlx::query_as::<_, TableStats>(&format!(
+        "SELECT COUNT(*) AS rows, pg_total_relation_size('{table}')::bigint AS bytes, {expired} AS expired FROM {table}",
+        table = table,
+        expired = expired
+    ))
+    .bind(now_ts())
+    .fetch_one(pool)
+    .await
+}
+
+/// Delete every asset with its blob; returns (removed, bytes freed)
+async fn clear_assets(pool: &sqlx::PgPool) -> anyhow::Result<(u64, i64)> {
+    // Aliases first, so removing an asset does not hand its blob over to one
+    let (mut removed, mut freed) = (0, 0);
+    let mut after = String::new();
+    loop {
+        let urls: Vec<String> = sqlx::query_scalar(
+            "SELECT url FROM asset_aliases WHERE url > $1 ORDER BY url LIMIT 200",
+        )
+        .bind(&after)
+        .fetch_all(pool)
+        .await?;
+        let Some(last) = urls.last() else {
+            break;
+        };
+        after = last.clone();
+        for url in urls {
+            if let Some(bytes) = crate::storage::remove(pool, &url).await? {
+                removed += 1;
+                freed += bytes;
+            }
+        }
+    }
+    let mut after = String::new();
+    loop {
+        let urls: Vec<String> =
+            sqlx::query_scalar("SELECT url FROM assets WHERE url > $1 ORDER BY url LIMIT 200")
+                .bind(&after)
+                .fetch_all(pool)
+                .await?;
+        let Some(last) = urls.last() else {
+            break;
+        };
+        after = last.clone();
+        for url in urls {
+            if let Some(bytes) = crate::storage::remove(pool, &url).await? {
+                removed += 1;
+                freed += bytes;
+            }
+        }
+    }
+    Ok((removed, freed))
+}
+
+// ============ Handlers ============
+
+/// Invalidate cached HTML for an article so the next read re-fetches it
+pub async fn invalidate(
+    State(state): State<AppState>,
+    Json(req): Json<CacheEntryRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if req.id.is_none() && req.url.is_none() {
+        return Err(AppError::BadRequest("id或url不能为空".to_string()));
+    }
+
+    let removed = articles::remove(&state.db_pool, req.id.as_deref(), req.url.as_deref()).await?;
+    tracing::info!(
+        "[Cache] Invalidated {} entries (id={:?}, url={:?})",
+        removed,
+        req.id,
+        req.url
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "removed": removed
+    })))
+}
+
+/// Set (or clear) the TTL of a cached article
+pub async fn set_ttl(
+    State(state): State<AppState>,
+    Json(req): Json<SetTtlRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if req.id.is_none() && req.url.is_none() {
+        return Err(AppError::BadRequest("id或url不能为空".to_string()));
+    }
+    let expires_at = expires_at(req.ttl_seconds)?;
+    let updated = articles::set_expiry(
+        &state.db_pool,
+        req.id.as_deref(),
+        req.url.as_deref(),
+        expires_at,
+    )
+    .await?;
+
+    if updated == 0 {
+        return Err(AppError::NotFound("Cache entry not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "updated": updated,
+        "expires_at": expires_at
+    })))
+}
+
+/// Rows and sizes of the cache tables
+pub async fn stats(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = &state.db_pool;
+    let article_content = table_stats(pool, "article_content", true).await?;
+    let assets = table_stats(pool, "assets", false).await?;
+    let asset_variants = table_stats(pool, "asset_variants", false).await?;
+    let embedding_cache = table_stats(pool, "embedding_cache", false).await?;
+    let by_storage = sqlx::query_as::<_, StorageStats>(
+        r#"
+        SELECT storage, COUNT(*) AS rows,
+               COALESCE(SUM(COALESCE(octet_length(data)::bigint, size::bigint, 0)), 0)::bigint AS bytes
+        FROM assets GROUP BY storage ORDER BY storage
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    let dedup = sqlx::query_as::<_, DedupStats>(
+        r#"
+        SELECT (SELECT COUNT(*) FROM asset_aliases) AS aliases,
+               (SELECT COALESCE(SUM(COALESCE(octet_length(a.data)::bigint, a.size::bigint, 0)), 0)::bigint
+                FROM asset_aliases x JOIN assets a ON a.sha256 = x.sha256) AS bytes_saved,
+               (SELECT COUNT(*) FROM assets WHERE sha256 IS NULL) AS unhashed
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "article_content": article_content,
+            "assets": assets,
+            "asset_storage": by_storage,
+            "asset_dedup": dedup,
+            "asset_variants": asset_variants,
+            "embedding_cache": embedding_cache,
+            "embedding_cache_lookups": crate::llm::cache::counters()
+        }
+    })))
+}
+
+/// Empty cache tables
+pub async fn clear(
+    State(state): State<AppState>,
+    Json(req): Json<ClearCacheRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let tables = req
+        .tables
+        .unwrap_or_else(|| vec!["article_content".to_string()]);
+    if let Some(unknown) = tables.iter().find(|t| !CLEARABLE.contains(&t.as_str())) {
+        return Err(AppError::BadRequest(format!(
+            "未知的缓存表: {} (可选 {})",
+            unknown,
+            CLEARABLE.join(", ")
+        )));
+    }
+
+    let mut removed = serde_json::Map::new();
+    let mut freed = 0;
+    for table in &tables {
+        let count = if table == "assets" {
+            let (count, bytes) = clear_assets(&state.db_pool).await?;
+            freed += bytes;
+            count
+        } else if table == "embedding_cache" {
+            crate::llm::cache::clear(&state.db_pool).await?
+        } else {
+            articles::clear(&state.db_pool).await?
+        };
+        removed.insert(table.clone(), count.into());
+    }
+    tracing::info!("[Cache] Cleared {:?}: {:?}", tables, removed);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "removed": removed,
+        "asset_bytes_freed": freed
+    })))
+}
+
+/// Evict cached HTML past its TTL and, with `older_than`, everything cached
+/// before that age. Images left unreferenced are reclaimed by the asset GC.
+pub async fn evict(
+    State(state): State<AppState>,
+    Query(query): Query<EvictQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let now = now_ts();
+    let cutoff = match query.older_than.as_deref() {
+        Some(age) => Some(
+            now - parse_age(age).ok_or_else(|| {
+                AppError::BadRequest(format!("无效的 older_than: {} (如 3600、12h、30d)", age))
+            })?,
+        ),
+        None => None,
+    };
+
+    let removed = articles::evict(&state.db_pool, cutoff).await?;
+    tracing::info!(
+        "[Cache] Evicted {} article_content (cutoff {:?})",
+        removed,
+        cutoff
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "removed": { "article_content": removed },
+        "cutoff": cutoff
+    })))
+}
+
+/// Dry run of the asset GC: what a run would remove and how much space it frees
+pub async fn gc_report(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let report = crate::gc::collect(&state.db_pool, true).await?;
+    Ok(Json(serde_json::json!({ "success": true, "data": report })))
+}
+
+/// Remove unreferenced assets now
+pub async fn gc_run(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let report = crate::gc::collect(&state.db_pool, false).await?;
+    tracing::info!(
+        "[Cache] GC removed {} asset(s), {} bytes",
+        report.assets,
+        report.bytes
+    );
+    Ok(Json(serde_json::json!({ "success": true, "data": report })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("3600"), Some(3600));
+        assert_eq!(parse_age("90m"), Some(5400));
+        assert_eq!(parse_age("12H"), Some(43200));
+        assert_eq!(parse_age(" 30d "), Some(30 * 86400));
+        assert_eq!(parse_age("0"), None);
+        assert_eq!(parse_age("-5d"), None);
+        assert_eq!(parse_age("2w"), None);
+        assert_eq!(parse_age("d"), None);
+        assert_eq!(parse_age(""), None);
+    }
+}
@@ -0,0 +1,388 @@
+//! Cross-account topic overlap
+//!
+//! `POST /api/insight/compare_accounts` compares what two or more accounts
+//! write about, from the stored vectors of their articles (one mean vector per
+//! article, as for the topic map). It reports how close the accounts' centroids
+//! are and clusters all their articles together: a topic is shared when
+//! several accounts each give it a fair part of their output, and distinctive
+//! when nearly all of it comes from one account. Topics are weighed by each
+//! account's share of its own articles, so a prolific account does not drown
+//! out the others.
+
+use axum::{extract::State, Extension, Json};
+use pgvector::Vector;
+use serde::{Deserialize, Serialize};
+
+use crate::auth::Principal;
+use crate::cluster::Clustering;
+use crate::error::AppError;
+use crate::AppState;
+
+const MAX_ACCOUNTS: usize = 10;
+const DEFAULT_PER_ACCOUNT: i64 = 300;
+const MAX_PER_ACCOUNT: i64 = 1000;
+/// Titles per topic shown to the labelling model, and per account in a topic
+const REPRESENTATIVE_TITLES: usize = 5;
+const ACCOUNT_TITLES: usize = 3;
+/// Weight of one account in a topic that makes the topic its own
+const DISTINCTIVE_MIN_WEIGHT: f64 = 0.8;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareRequest {
+    pub fakeids: Vec<String>,
+    /// Publish time range, unix seconds (end exclusive)
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    /// Topic count, at most `cluster::MAX_K`; about √(n/2) when unset
+    pub k: Option<usize>,
+    /// Most recent articles per account (default 300)
+    pub limit_per_account: Option<i64>,
+    /// Name topics with the LLM (default true); otherwise by their most central title
+    pub label: Option<bool>,
+    pub provider: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub deepseek_api_key: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ArticleRow {
+    title: String,
+    vector: Vector,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum TopicKind {
+    /// At least two accounts give it a fair part of their output
+    Shared,
+    /// Almost all of it comes from one account
+    Distinctive,
+    Mixed,
+}
+
+/// Topic composition of the compared accounts
+struct Overlap {
+    /// Per topic and account: the account's articles in the topic
+    counts: Vec<Vec<usize>>,
+    /// Per topic and account: fraction of the account's articles in the topic
+    shares: Vec<Vec<f64>>,
+    /// Per topic: its kind and, for distinctive topics, the owning account
+    kinds: Vec<(TopicKind, Option<usize>)>,
+    /// Per account pair: Σ over topics of the smaller share, 1 for the same mix
+    topic_overlap: Vec<Vec<f64>>,
+}
+
+/// Kind of a topic from the accounts' shares of it. An account covers the
+/// topic when its weight is at least half of an even split.
+fn classify(shares: &[f64]) -> (TopicKind, Option<usize>) {
+    let total: f64 = shares.iter().sum();
+    if total <= 0.0 {
+        return (TopicKind::Mixed, None);
+    }
+    let weights: Vec<f64> = shares.iter().map(|s| s / total).collect();
+    if let Some(owner) = weights.iter().position(|w| *w >= DISTINCTIVE_MIN_WEIGHT) {
+        return (TopicKind::Distinctive, Some(owner));
+    }
+    let fair = 0.5 / shares.len() as f64;
+    if weights.iter().filter(|w| **w >= fair).count() >= 2 {
+        (TopicKind::Shared, None)
+    } else {
+        (TopicKind::Mixed, None)
+    }
+}
+
+/// `owners[i]` is the account (0..accounts) of the i-th clustered vector
+fn overlap(owners: &[usize], accounts: usize, clustering: &Clustering) -> Overlap {
+    let topics = clustering.centroids.len();
+    let mut counts = vec![vec![0usize; accounts]; topics];
+    let mut totals = vec![0usize; accounts];
+    for (&owner, &topic) in owners.iter().zip(&clustering.assignments) {
+        counts[topic][owner] += 1;
+        totals[owner] += 1;
+    }
+    let shares: Vec<Vec<f64>> = counts
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(&totals)
+                .map(|(&n, &total)| {
+                    if total > 0 {
+                        n as f64 / total as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let kinds = shares.iter().map(|s| classify(s)).collect();
+    let topic_overlap = (0..accounts)
+        .map(|a| {
+            (0..accounts)
+                .map(|b| shares.iter().map(|s| s[a].min(s[b])).sum())
+                .collect()
+        })
+        .collect();
+    Overlap {
+        counts,
+        shares,
+        kinds,
+        topic_overlap,
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10_000.0).round() / 10_000.0
+}
+
+/// Topical overlap between accounts from their stored article vectors
+pub async fn compare_accounts(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<CompareRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let mut fakeids: Vec<String> = Vec::new();
+    for fakeid in &req.fakeids {
+        let fakeid = fakeid.trim();
+        if !fakeid.is_empty() && !fakeids.iter().any(|f| f == fakeid) {
+            fakeids.push(fakeid.to_string());
+        }
+    }
+    if fakeids.len() < 2 || fakeids.len() > MAX_ACCOUNTS {
+        return Err(AppError::BadRequest(format!(
+            "请选择 2 到 {} 个公众号进行对比",
+            MAX_ACCOUNTS
+        )));
+    }
+    let limit = req
+        .limit_per_account
+        .unwrap_or(DEFAULT_PER_ACCOUNT)
+        .clamp(1, MAX_PER_ACCOUNT);
+
+    let mut nicknames = Vec::with_capacity(fakeids.len());
+    let mut rows: Vec<ArticleRow> = Vec::new();
+    let mut owners: Vec<usize> = Vec::new();
+    for (index, fakeid) in fakeids.iter().enumerate() {
+        let account: Option<(Option<String>, Option<uuid::Uuid>)> =
+            sqlx::query_as("SELECT nickname, owner_id FROM accounts WHERE fakeid = $1")
+                .bind(fakeid)
+                .fetch_optional(&state.db_pool)
+                .await?;
+        let (nickname, _) = account
+            .filter(|(_, owner)| principal.can_access(*owner))
+            .ok_or(AppError::NotFound(format!("Account not found: {}", fakeid)))?;
+        let nickname = nickname.unwrap_or_else(|| fakeid.clone());
+
+        // One vector per article: the mean of its title, digest and chunk embeddings
+        let account_rows: Vec<ArticleRow> = sqlx::query_as(
+            r#"
+            SELECT a.title, AVG(e.vector) AS vector
+            FROM embeddings e
+            JOIN articles a ON a.fakeid = e.fakeid AND a.aid = e.aid
+            WHERE a.fakeid = $1
+              AND ($2::BIGINT IS NULL OR a.create_time >= $2)
+              AND ($3::BIGINT IS NULL OR a.create_time < $3)
+            GROUP BY a.id, a.title, a.create_time
+            ORDER BY a.create_time DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(fakeid)
+        .bind(req.start_time)
+        .bind(req.end_time)
+        .bind(limit)
+        .fetch_all(&state.db_pool)
+        .await?;
+        if account_rows.len() < 2 {
+            return Err(AppError::BadRequest(format!(
+                "公众号「{}」已向量化的文章不足，无法对比",
+                nickname
+            )));
+        }
+        owners.resize(owners.len() + account_rows.len(), index);
+        rows.extend(account_rows);
+        nicknames.push(nickname);
+    }
+
+    let accounts = fakeids.len();
+    let k = req
+        .k
+        .unwrap_or_else(|| crate::cluster::default_k(rows.len()))
+        .clamp(1, crate::cluster::MAX_K);
+    let mut vectors: Vec<Vec<f32>> = rows.iter().map(|r| r.vector.to_vec()).collect();
+    let cluster_owners = owners.clone();
+    let (clustering, vectors, centroid_similarity, overlap) =
+        tokio::task::spawn_blocking(move || {
+            let clustering = crate::cluster::kmeans(&mut vectors, k);
+            let centroids: Vec<Vec<f32>> = (0..accounts)
+                .map(|a| {
+                    crate::cluster::centroid(
+                        vectors
+                            .iter()
+                            .zip(&cluster_owners)
+                            .filter(|(_, o)| **o == a)
+                            .map(|(v, _)| v),
+                    )
+                })
+                .collect();
+            let similarity: Vec<Vec<f64>> = centroids
+                .iter()
+                .map(|a| {
+                    centroids
+                        .iter()
+                        .map(|b| crate::cluster::dot(a, b) as f64)
+                        .collect()
+                })
+                .collect();
+            let overlap = overlap(&cluster_owners, accounts, &clustering);
+            (clustering, vectors, similarity, overlap)
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let members: Vec<Vec<usize>> = (0..clustering.centroids.len())
+        .map(|c| clustering.members(&vectors, c))
+        .collect();
+    let titles: Vec<Vec<String>> = members
+        .iter()
+        .map(|m| {
+            m.iter()
+                .take(REPRESENTATIVE_TITLES)
+                .map(|&i| rows[i].title.clone())
+                .collect()
+        })
+        .collect();
+
+    let mut labels: Vec<String> = titles.iter().map(|t| t[0].clone()).collect();
+    if req.label.unwrap_or(true) {
+        let labelled = crate::api::embedding::label_clusters(
+            &state,
+            req.provider.as_deref(),
+            req.gemini_api_key.as_deref(),
+            req.deepseek_api_key.as_deref(),
+            &titles,
+        )
+        .await;
+        match labelled {
+            Ok(named) => {
+                for (label, name) in labels.iter_mut().zip(named) {
+                    if !name.trim().is_empty() {
+                        *label = name.trim().to_string();
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("[Compare] Labelling failed, using titles: {}", e),
+        }
+    }
+
+    let topics: Vec<serde_json::Value> = members
+        .iter()
+        .enumerate()
+        .map(|(c, m)| {
+            let (kind, owner) = overlap.kinds[c];
+            let per_account: Vec<serde_json::Value> = (0..accounts)
+                .map(|a| {
+                    let account_titles: Vec<&str> = m
+                        .iter()
+                        .filter(|&&i| owners[i] == a)
+                        .take(ACCOUNT_TITLES)
+                        .map(|&i| rows[i].title.as_str())
+                        .collect();
+                    serde_json::json!({
+                        "fakeid": fakeids[a],
+                        "articles": overlap.counts[c][a],
+                        "share": round(overlap.shares[c][a]),
+                        "titles": account_titles
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "id": c,
+                "label": labels[c],
+                "kind": kind,
+                "owner": owner.map(|a| &fakeids[a]),
+                "size": m.len(),
+                "representative_titles": titles[c],
+                "accounts": per_account
+            })
+        })
+        .collect();
+
+    let account_list: Vec<serde_json::Value> = (0..accounts)
+        .map(|a| {
+            // Its own topics, largest part of its output first
+            let mut distinctive: Vec<usize> = (0..members.len())
+                .filter(|&c| overlap.kinds[c].1 == Some(a))
+                .collect();
+            distinctive.sort_by(|x, y| overlap.shares[*y][a].total_cmp(&overlap.shares[*x][a]));
+            serde_json::json!({
+                "fakeid": fakeids[a],
+                "nickname": nicknames[a],
+                "articles": owners.iter().filter(|o| **o == a).count(),
+                "distinctive_topics": distinctive
+            })
+        })
+        .collect();
+    let mut pairs = Vec::new();
+    for a in 0..accounts {
+        for b in a + 1..accounts {
+            pairs.push(serde_json::json!({
+                "a": fakeids[a],
+                "b": fakeids[b],
+                "centroid_similarity": round(centroid_similarity[a][b]),
+                "topic_overlap": round(overlap.topic_overlap[a][b])
+            }));
+        }
+    }
+    let shared: Vec<usize> = (0..members.len())
+        .filter(|&c| overlap.kinds[c].0 == TopicKind::Shared)
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "k": topics.len(),
+            "accounts": account_list,
+            "pairs": pairs,
+            "shared_topics": shared,
+            "topics": topics
+        }
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlap() {
+        assert_eq!(classify(&[0.5, 0.1]), (TopicKind::Distinctive, Some(0)));
+        assert_eq!(classify(&[0.3, 0.2]), (TopicKind::Shared, None));
+        // 0.22 of the weight is below half an even split of two
+        assert_eq!(classify(&[0.39, 0.11]), (TopicKind::Mixed, None));
+        assert_eq!(classify(&[0.0, 0.0]), (TopicKind::Mixed, None));
+        // Ten accounts covering a topic evenly still share it
+        assert_eq!(classify(&[0.1; 10]), (TopicKind::Shared, None));
+
+        // Account 0: four articles on topic 0; account 1: one on topic 0, three on topic 1
+        let clustering = Clustering {
+            assignments: vec![0, 0, 0, 0, 0, 1, 1, 1],
+            centroids: vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+        };
+        let owners = [0, 0, 0, 0, 1, 1, 1, 1];
+        let result = overlap(&owners, 2, &clustering);
+        assert_eq!(result.counts, vec![vec![4, 1], vec![0, 3]]);
+        assert_eq!(result.shares[0], vec![1.0, 0.25]);
+        assert_eq!(result.kinds[0], (TopicKind::Distinctive, Some(0)));
+        assert_eq!(result.kinds[1], (TopicKind::Distinctive, Some(1)));
+        assert_eq!(result.topic_overlap[0][1], 0.25);
+        assert_eq!(result.topic_overlap[1][1], 1.0);
+
+        let a = vec![1.0, 0.0];
+        let b = vec![0.0, 1.0];
+        let centroid = crate::cluster::centroid([&a, &b]);
+        assert!(
+            (crate::cluster::dot(&centroid, &a) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6
+        );
+    }
+}
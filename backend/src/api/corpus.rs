@@ -0,0 +1,112 @@
+//! Corpus chat API
+//!
+//! Questions answered from the article library with citations (see `rag`).
+
+use axum::{extract::State, Json};
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::llm::{ChatRequest, ProviderConfig};
+use crate::rag::{self, HistoryMessage};
+use crate::AppState;
+
+/// Passages retrieved by default, and at most
+const DEFAULT_TOP_K: usize = 8;
+const MAX_TOP_K: usize = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct CorpusChatRequest {
+    pub question: String,
+    #[serde(default)]
+    pub history: Vec<HistoryMessage>,
+    pub top_k: Option<usize>,
+    /// Lowest passage similarity (default 0.3)
+    pub min_score: Option<f32>,
+    /// Chat provider for the answer (default "gemini")
+    pub provider: Option<String>,
+    /// Embeds the question; defaults to the provider the embeddings table was built with
+    pub embedding_provider: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub deepseek_api_key: Option<String>,
+}
+
+/// Answer a question from the article library
+pub async fn chat(
+    State(state): State<AppState>,
+    Json(req): Json<CorpusChatRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let question = req.question.trim();
+    if question.is_empty() {
+        return Err(AppError::BadRequest("问题不能为空".to_string()));
+    }
+    let top_k = req.top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K);
+
+    // Embed the question into the same space as the stored vectors
+    let registry = crate::embedding_registry::current();
+    let embedding_provider = req
+        .embedding_provider
+        .clone()
+        .or_else(|| registry.as_ref().and_then(|r| r.provider.clone()))
+        .unwrap_or_else(|| "ollama".to_string());
+    let mut embedding_config = crate::api::llm::keyed_config(
+        &embedding_provider,
+        req.deepseek_api_key.as_deref(),
+        req.gemini_api_key.as_deref(),
+    );
+    if let Some(registry) = &registry {
+        embedding_config.model = registry.model.clone();
+        embedding_config.dimension = Some(registry.dimension);
+    }
+    let embedder =
+        crate::api::llm::embedding_provider(&state, &embedding_provider, embedding_config).await?;
+    let vector = embedder
+        .embed_one(question)
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Embedding failed: {}", e)))?;
+    crate::embedding_registry::check_dimension(vector.len())?;
+
+    let passages =
+        rag::retrieve(&state.db_pool, vector, top_k, req.min_score.unwrap_or(0.3)).await?;
+    let mut citations = rag::citations(&passages);
+
+    if passages.is_empty() {
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "data": {
+                "answer": "文章库中没有找到与这个问题相关的内容。",
+                "citations": citations
+            }
+        })));
+    }
+
+    let provider = req.provider.clone().unwrap_or_else(|| "gemini".to_string());
+    let config: ProviderConfig = crate::api::llm::keyed_config(
+        &provider,
+        req.deepseek_api_key.as_deref(),
+        req.gemini_api_key.as_deref(),
+    );
+    let llm = crate::api::llm::chat_provider(&state, &provider, config).await?;
+
+    let system = rag::system_prompt(&passages, &citations);
+    let prompt = rag::user_prompt(&req.history, question);
+    let answer = llm
+        .chat(&ChatRequest {
+            system: Some(&system),
+            prompt: &prompt,
+            temperature: Some(0.3),
+            max_tokens: Some(2048),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Answer generation failed: {}", e)))?
+        .text;
+    rag::mark_cited(&answer, &mut citations);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "answer": answer,
+            "citations": citations
+        }
+    })))
+}
@@ -0,0 +1,26 @@
+//! Crawl coordinator API
+//!
+//! Queue depth and estimated wait for every WeChat traffic lane, plus
+//! downloads in flight under the shared download limits.
+
+use axum::{extract::State, Json};
+
+use crate::crawl::coordinator;
+use crate::AppState;
+
+/// Per-lane queue status
+pub async fn status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let lanes = coordinator().status();
+    let waiting: usize = lanes.iter().map(|l| l.waiting).sum();
+    let in_flight: usize = lanes.iter().map(|l| l.in_flight).sum();
+    let max_wait = lanes.iter().map(|l| l.estimated_wait_ms).max().unwrap_or(0);
+
+    Json(serde_json::json!({
+        "success": true,
+        "waiting": waiting,
+        "in_flight": in_flight,
+        "estimated_wait_ms": max_wait,
+        "lanes": lanes,
+        "downloads": state.downloads.status()
+    }))
+}
@@ -0,0 +1,281 @@
+//! Task digest generation
+//!
+//! Summarizes a task's relevant articles into a Markdown digest. Several
+//! languages can be requested at once; they are produced in a single LLM pass
+//! and stored per task and language in `insight_digests`.
+
+use axum::{
+    extract::{Query, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::api::insight::{authorize_task, InsightArticle, InsightTask};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::llm::ChatRequest;
+use crate::AppState;
+
+/// Maximum number of languages per request
+const MAX_LANGUAGES: usize = 5;
+/// Default number of top articles fed into the digest
+const DEFAULT_MAX_ARTICLES: i64 = 30;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct GenerateDigestRequest {
+    pub task_id: Uuid,
+    /// Language tags, e.g. ["zh-CN", "en"]. Defaults to the task's output language.
+    pub languages: Option<Vec<String>>,
+    pub provider: Option<String>, // "gemini" or "deepseek"
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub max_articles: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListDigestsQuery {
+    pub task_id: Uuid,
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct InsightDigest {
+    pub task_id: Uuid,
+    pub language: String,
+    pub content: String,
+    pub provider: String,
+    pub article_count: i32,
+    pub created_at: i64,
+}
+
+// ============ Helpers ============
+
+/// Loose BCP 47 check: "en", "zh-CN", "zh-Hant-TW"
+pub(crate) fn is_valid_language_tag(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let primary = parts.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|p| (2..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+fn build_digest_prompt(task: &InsightTask, articles: &[InsightArticle], languages: &[String]) -> String {
+    let mut list = String::new();
+    for (i, a) in articles.iter().enumerate() {
+        list.push_str(&format!(
+            "{}. {} ({})\n   Insight: {}\n",
+            i + 1,
+            a.title,
+            a.account_name.as_deref().unwrap_or("unknown"),
+            a.insight.as_deref().unwrap_or("-")
+        ));
+    }
+
+    let keys = languages
+        .iter()
+        .map(|l| format!("\"{}\": \"...\"", l))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "You are preparing a research digest for the topic: {}\n\n\
+        Relevant WeChat articles and their insights:\n{}\n\
+        Write a digest in Markdown with: a one-paragraph overview, 3-6 key findings \
+        (cite article numbers like [3]), and notable sources.\n\
+        Produce the SAME digest in each of these languages: {}. \
+        Translations must be faithful to each other; keep article titles in their original language.\n\
+        Return JSON ONLY, one key per language: {{ {} }}",
+        task.prompt,
+        list,
+        languages.join(", "),
+        keys
+    )
+}
+
+/// Extract per-language digests from an LLM reply
+fn parse_digest_response(text: &str, languages: &[String]) -> HashMap<String, String> {
+    let clean = text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+
+    let mut digests = HashMap::new();
+    if let Ok(map) = serde_json::from_str::<HashMap<String, String>>(clean) {
+        for lang in languages {
+            // Models occasionally change the key's case ("zh-cn")
+            if let Some((_, content)) = map.iter().find(|(k, _)| k.eq_ignore_ascii_case(lang)) {
+                if !content.trim().is_empty() {
+                    digests.insert(lang.clone(), content.trim().to_string());
+                }
+            }
+        }
+    } else if languages.len() == 1 && !clean.is_empty() {
+        // Single language: accept a plain Markdown answer
+        digests.insert(languages[0].clone(), clean.to_string());
+    }
+    digests
+}
+
+// ============ Handlers ============
+
+/// Generate digests for a task in one or more languages
+pub async fn generate_digest(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<GenerateDigestRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, req.task_id).await?;
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(req.task_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+
+    let mut languages = req
+        .languages
+        .unwrap_or_else(|| vec![task.output_language().to_string()]);
+    let mut seen = std::collections::HashSet::new();
+    languages.retain(|l| seen.insert(l.to_ascii_lowercase()));
+    if languages.is_empty() || languages.len() > MAX_LANGUAGES {
+        return Err(AppError::BadRequest(format!(
+            "languages数量必须在1到{}之间",
+            MAX_LANGUAGES
+        )));
+    }
+    if let Some(bad) = languages.iter().find(|l| !is_valid_language_tag(l)) {
+        return Err(AppError::BadRequest(format!("无效的语言代码: {}", bad)));
+    }
+
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY relevance_score DESC NULLS LAST, similarity DESC NULLS LAST LIMIT $2",
+    )
+    .bind(req.task_id)
+    .bind(req.max_articles.unwrap_or(DEFAULT_MAX_ARTICLES).clamp(1, 200))
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    if articles.is_empty() {
+        return Err(AppError::BadRequest("任务没有可用于摘要的文章".to_string()));
+    }
+
+    let provider = req.provider.unwrap_or_else(|| "gemini".to_string());
+    let prompt = build_digest_prompt(&task, &articles, &languages);
+    // Budget output by language count; each digest is roughly 1k tokens
+    let max_tokens = 1500 * languages.len() as u32;
+
+    let config = crate::api::llm::keyed_config(
+        &provider,
+        req.deepseek_api_key.as_deref(),
+        req.gemini_api_key.as_deref(),
+    );
+    let llm = crate::api::llm::chat_provider(&state, &provider, config).await?;
+    let reply = llm
+        .chat(&ChatRequest {
+            prompt: &prompt,
+            temperature: Some(0.3),
+            max_tokens: Some(max_tokens),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Digest generation failed: {}", e)))?
+        .text;
+
+    let digests = parse_digest_response(&reply, &languages);
+    if digests.is_empty() {
+        return Err(AppError::BadGateway(
+            "Failed to parse digest from LLM response".to_string(),
+        ));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    for (language, content) in &digests {
+        sqlx::query(
+            r#"
+            INSERT INTO insight_digests (task_id, language, content, provider, article_count, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (task_id, language) DO UPDATE SET
+                content = EXCLUDED.content,
+                provider = EXCLUDED.provider,
+                article_count = EXCLUDED.article_count,
+                created_at = EXCLUDED.created_at
+            "#,
+        )
+        .bind(req.task_id)
+        .bind(language)
+        .bind(content)
+        .bind(&provider)
+        .bind(articles.len() as i32)
+        .bind(now)
+        .execute(&state.db_pool)
+        .await?;
+    }
+
+    let missing: Vec<&String> = languages.iter().filter(|l| !digests.contains_key(*l)).collect();
+    tracing::info!(
+        "[Digest] Task {}: generated {:?}, missing {:?}",
+        req.task_id,
+        digests.keys().collect::<Vec<_>>(),
+        missing
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": digests,
+        "missing": missing
+    })))
+}
+
+/// Stored digests of a task
+pub async fn list_digests(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<ListDigestsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, query.task_id).await?;
+
+    let digests = sqlx::query_as::<_, InsightDigest>(
+        "SELECT task_id, language, content, provider, article_count, created_at FROM insight_digests WHERE task_id = $1 AND ($2::TEXT IS NULL OR language = $2) ORDER BY language",
+    )
+    .bind(query.task_id)
+    .bind(&query.language)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": digests
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_tags() {
+        assert!(is_valid_language_tag("en"));
+        assert!(is_valid_language_tag("zh-CN"));
+        assert!(is_valid_language_tag("zh-Hant-TW"));
+        assert!(!is_valid_language_tag("english!"));
+        assert!(!is_valid_language_tag(""));
+    }
+
+    #[test]
+    fn test_parse_digest_response() {
+        let langs = vec!["zh-CN".to_string(), "en".to_string()];
+        let reply = "```json\n{\"zh-cn\": \"摘要\", \"en\": \"Digest\"}\n```";
+        let digests = parse_digest_response(reply, &langs);
+        assert_eq!(digests.get("zh-CN").map(String::as_str), Some("摘要"));
+        assert_eq!(digests.get("en").map(String::as_str), Some("Digest"));
+
+        let single = parse_digest_response("# Plain markdown", &langs[..1]);
+        assert_eq!(single.get("zh-CN").map(String::as_str), Some("# Plain markdown"));
+    }
+}
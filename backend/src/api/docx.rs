@@ -0,0 +1,371 @@
+//! DOCX generation API
+//!
+//! Writes cleaned articles (see `content::extract`) as Word documents: a
+//! minimal WordprocessingML package with built-in heading styles and the
+//! article's images embedded. Images come from the export's local copies or,
+//! failing that, from the asset store; WebP and other formats Word can't
+//! show are converted to PNG.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::path::Path;
+
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::Response,
+    Json,
+};
+use html_escape::encode_text;
+use serde::Deserialize;
+use sqlx::PgPool;
+use zip::write::SimpleFileOptions;
+
+use crate::api::insight;
+use crate::content::extract::{Block, ExtractedArticle};
+use crate::error::AppError;
+use crate::AppState;
+
+pub const DOCX_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+
+/// Widest image on an A4 page with default margins (6 inches), in EMU
+const MAX_IMAGE_EMU: u64 = 5_486_400;
+/// EMU per pixel at 96 dpi
+const EMU_PER_PX: u64 = 9_525;
+
+#[derive(Debug, Deserialize)]
+pub struct DocxRequest {
+    pub html: String,
+    pub filename: Option<String>,
+}
+
+/// Image bytes ready to embed
+struct Media {
+    ext: &'static str,
+    data: Vec<u8>,
+    width_px: u32,
+    height_px: u32,
+}
+
+/// Decode enough of an image to size it; converts formats Word lacks to PNG
+fn prepare_image(data: Vec<u8>) -> Option<Media> {
+    let format = image::guess_format(&data).ok()?;
+    let ext = match format {
+        image::ImageFormat::Png => "png",
+        image::ImageFormat::Jpeg => "jpeg",
+        image::ImageFormat::Gif => "gif",
+        _ => {
+            let img = image::load_from_memory(&data).ok()?;
+            let mut png = Vec::new();
+            img.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+                .ok()?;
+            return Some(Media {
+                ext: "png",
+                width_px: img.width(),
+                height_px: img.height(),
+                data: png,
+            });
+        }
+    };
+    let (width_px, height_px) = image::io::Reader::with_format(Cursor::new(&data), format)
+        .into_dimensions()
+        .ok()?;
+    Some(Media {
+        ext,
+        data,
+        width_px,
+        height_px,
+    })
+}
+
+/// Bytes of every image of `article`: local files under `base_dir` first, then the asset store
+pub async fn load_images(
+    pool: &PgPool,
+    base_dir: Option<&Path>,
+    article: &ExtractedArticle,
+) -> HashMap<String, Vec<u8>> {
+    let mut images = HashMap::new();
+    for block in &article.blocks {
+        let Block::Image { src, .. } = block else {
+            continue;
+        };
+        if images.contains_key(src) {
+            continue;
+        }
+        let data = if src.starts_with("http") || src.starts_with("//") {
+            let url = if src.starts_with("//") {
+                format!("https:{}", src)
+            } else {
+                src.clone()
+            };
+            crate::storage::load(pool, &url)
+                .await
+                .unwrap_or(None)
+                .map(|(data, _)| data)
+        } else {
+            match base_dir {
+                Some(dir) => tokio::fs::read(dir.join(src)).await.ok(),
+                None => None,
+            }
+        };
+        if let Some(data) = data {
+            images.insert(src.clone(), data);
+        }
+    }
+    images
+}
+
+fn paragraph(style: Option<&str>, text: &str) -> String {
+    let ppr = style
+        .map(|s| format!("<w:pPr><w:pStyle w:val=\"{}\"/></w:pPr>", s))
+        .unwrap_or_default();
+    let runs = text
+        .split('\n')
+        .map(|line| format!("<w:t xml:space=\"preserve\">{}</w:t>", encode_text(line)))
+        .collect::<Vec<_>>()
+        .join("<w:br/>");
+    format!("<w:p>{}<w:r>{}</w:r></w:p>", ppr, runs)
+}
+
+fn drawing(rel_id: usize, media: &Media, name: &str) -> String {
+    let mut cx = media.width_px as u64 * EMU_PER_PX;
+    let mut cy = media.height_px as u64 * EMU_PER_PX;
+    if cx > MAX_IMAGE_EMU {
+        cy = cy * MAX_IMAGE_EMU / cx;
+        cx = MAX_IMAGE_EMU;
+    }
+    format!(
+        r#"<w:p><w:pPr><w:jc w:val="center"/></w:pPr><w:r><w:drawing><wp:inline distT="0" distB="0" distL="0" distR="0"><wp:extent cx="{cx}" cy="{cy}"/><wp:docPr id="{id}" name="{name}"/><a:graphic xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main"><a:graphicData uri="http://schemas.openxmlformats.org/drawingml/2006/picture"><pic:pic xmlns:pic="http://schemas.openxmlformats.org/drawingml/2006/picture"><pic:nvPicPr><pic:cNvPr id="{id}" name="{name}"/><pic:cNvPicPr/></pic:nvPicPr><pic:blipFill><a:blip r:embed="rId{id}"/><a:stretch><a:fillRect/></a:stretch></pic:blipFill><pic:spPr><a:xfrm><a:off x="0" y="0"/><a:ext cx="{cx}" cy="{cy}"/></a:xfrm><a:prstGeom prst="rect"><a:avLst/></a:prstGeom></pic:spPr></pic:pic></a:graphicData></a:graphic></wp:inline></w:drawing></w:r></w:p>"#,
+        cx = cx,
+        cy = cy,
+        id = rel_id,
+        name = name,
+    )
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Default Extension="png" ContentType="image/png"/><Default Extension="jpeg" ContentType="image/jpeg"/><Default Extension="gif" ContentType="image/gif"/><Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/><Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/><Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:docDefaults><w:rPrDefault><w:rPr><w:rFonts w:ascii="Calibri" w:hAnsi="Calibri" w:eastAsia="Microsoft YaHei"/><w:sz w:val="22"/></w:rPr></w:rPrDefault><w:pPrDefault><w:pPr><w:spacing w:after="160" w:line="300" w:lineRule="auto"/></w:pPr></w:pPrDefault></w:docDefaults><w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/></w:style><w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/><w:basedOn w:val="Normal"/><w:rPr><w:b/><w:sz w:val="40"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="240"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:sz w:val="32"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="200"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:sz w:val="28"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Heading3"><w:name w:val="heading 3"/><w:basedOn w:val="Normal"/><w:pPr><w:keepNext/><w:outlineLvl w:val="2"/></w:pPr><w:rPr><w:b/><w:sz w:val="24"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Subtitle"><w:name w:val="Subtitle"/><w:basedOn w:val="Normal"/><w:rPr><w:color w:val="808080"/><w:sz w:val="18"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Quote"><w:name w:val="Quote"/><w:basedOn w:val="Normal"/><w:pPr><w:ind w:left="567"/><w:pBdr><w:left w:val="single" w:sz="12" w:space="8" w:color="CCCCCC"/></w:pBdr></w:pPr><w:rPr><w:color w:val="555555"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="Code"><w:name w:val="Code"/><w:basedOn w:val="Normal"/><w:pPr><w:shd w:val="clear" w:color="auto" w:fill="F4F4F4"/></w:pPr><w:rPr><w:rFonts w:ascii="Consolas" w:hAnsi="Consolas"/><w:sz w:val="18"/></w:rPr></w:style><w:style w:type="paragraph" w:styleId="ListParagraph"><w:name w:val="List Paragraph"/><w:basedOn w:val="Normal"/><w:pPr><w:ind w:left="567" w:hanging="283"/></w:pPr></w:style></w:styles>"#;
+
+/// A whole `.docx` file: title, optional meta line and insight, then the article body
+pub fn build_docx(
+    title: &str,
+    meta: &[String],
+    insight: Option<&str>,
+    article: &ExtractedArticle,
+    images: &HashMap<String, Vec<u8>>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut body = paragraph(Some("Title"), title);
+    if !meta.is_empty() {
+        body.push_str(&paragraph(Some("Subtitle"), &meta.join(" · ")));
+    }
+    if let Some(insight) = insight.filter(|i| !i.is_empty()) {
+        body.push_str(&paragraph(Some("Quote"), insight));
+    }
+
+    let mut media: Vec<Media> = Vec::new();
+    let mut list_index = 0;
+    for block in &article.blocks {
+        if !matches!(block, Block::ListItem { .. }) {
+            list_index = 0;
+        }
+        match block {
+            Block::Heading { level, text } => {
+                let style = format!("Heading{}", (*level).clamp(1, 3));
+                body.push_str(&paragraph(Some(&style), text));
+            }
+            Block::Paragraph { text } => body.push_str(&paragraph(None, text)),
+            Block::Quote { text } => body.push_str(&paragraph(Some("Quote"), text)),
+            Block::Code { text } => body.push_str(&paragraph(Some("Code"), text)),
+            Block::ListItem { ordered, text } => {
+                list_index += 1;
+                let item = if *ordered {
+                    format!("{}. {}", list_index, text)
+                } else {
+                    format!("• {}", text)
+                };
+                body.push_str(&paragraph(Some("ListParagraph"), &item));
+            }
+            Block::Image { src, .. } => {
+                if let Some(m) = images.get(src).and_then(|d| prepare_image(d.clone())) {
+                    // rId1 is the styles part
+                    let rel_id = media.len() + 2;
+                    body.push_str(&drawing(rel_id, &m, &format!("image{}", rel_id)));
+                    media.push(m);
+                }
+            }
+        }
+    }
+
+    let document = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing"><w:body>{}<w:sectPr><w:pgSz w:w="11906" w:h="16838"/><w:pgMar w:top="1440" w:right="1440" w:bottom="1440" w:left="1440" w:header="720" w:footer="720" w:gutter="0"/></w:sectPr></w:body></w:document>"#,
+        body
+    );
+
+    let mut rels = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>"#,
+    );
+    for (i, m) in media.iter().enumerate() {
+        rels.push_str(&format!(
+            r#"<Relationship Id="rId{0}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/image" Target="media/image{0}.{1}"/>"#,
+            i + 2,
+            m.ext
+        ));
+    }
+    rels.push_str("</Relationships>");
+
+    let core = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><dc:title>{}</dc:title><dcterms:created xsi:type="dcterms:W3CDTF">{}</dcterms:created></cp:coreProperties>"#,
+        encode_text(title),
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    );
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let parts: [(&str, &[u8]); 6] = [
+        ("[Content_Types].xml", CONTENT_TYPES.as_bytes()),
+        ("_rels/.rels", ROOT_RELS.as_bytes()),
+        ("docProps/core.xml", core.as_bytes()),
+        ("word/document.xml", document.as_bytes()),
+        ("word/styles.xml", STYLES.as_bytes()),
+        ("word/_rels/document.xml.rels", rels.as_bytes()),
+    ];
+    for (name, data) in parts {
+        zip.start_file(name, options)?;
+        zip.write_all(data)?;
+    }
+    for (i, m) in media.iter().enumerate() {
+        // Already compressed
+        zip.start_file(
+            format!("word/media/image{}.{}", i + 2, m.ext),
+            options.compression_method(zip::CompressionMethod::Stored),
+        )?;
+        zip.write_all(&m.data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Generate a DOCX from article HTML
+pub async fn generate_docx(
+    State(state): State<AppState>,
+    Json(req): Json<DocxRequest>,
+) -> Result<Response<axum::body::Body>, AppError> {
+    if req.html.is_empty() {
+        return Err(AppError::BadRequest("Missing html content".to_string()));
+    }
+    let filename = req.filename.as_deref().unwrap_or("article");
+
+    let temp_id = uuid::Uuid::new_v4().to_string();
+    let temp_dir = std::env::temp_dir()
+        .join("wechat-insights-docx")
+        .join(&temp_id);
+    let images_dir = temp_dir.join("images");
+    tokio::fs::create_dir_all(&images_dir)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to create temp dir: {}", e)))?;
+
+    let client = reqwest::Client::builder()
+        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
+
+    // Local copies of the images (cached in `assets`), referenced as images/<file>
+    let (processed_html, _) = insight::process_html_images(
+        &client,
+        &state.downloads,
+        &req.html,
+        &images_dir,
+        &temp_id,
+        None,
+        &state.db_pool,
+        false,
+    )
+    .await;
+    let extracted = crate::content::extract::extract(&processed_html);
+    let images = load_images(&state.db_pool, Some(&temp_dir), &extracted).await;
+    let title = if extracted.title.is_empty() {
+        filename.to_string()
+    } else {
+        extracted.title.clone()
+    };
+    let meta: Vec<String> = extracted.account_name.iter().cloned().collect();
+
+    let result = build_docx(&title, &meta, None, &extracted, &images);
+    let _ = tokio::fs::remove_dir_all(&temp_dir).await;
+    let bytes = result.map_err(|e| AppError::Internal(format!("DOCX gen failed: {}", e)))?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, DOCX_CONTENT_TYPE)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.docx\"",
+                urlencoding::encode(filename)
+            ),
+        )
+        .header(header::CONTENT_LENGTH, bytes.len())
+        .body(axum::body::Body::from(bytes))
+        .unwrap();
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_docx() {
+        let mut png = Vec::new();
+        image::RgbImage::new(1200, 600)
+            .write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+            .unwrap();
+        let article = ExtractedArticle {
+            blocks: vec![
+                Block::Heading {
+                    level: 2,
+                    text: "小节 <1>".to_string(),
+                },
+                Block::Paragraph {
+                    text: "正文 & more".to_string(),
+                },
+                Block::Image {
+                    src: "images/a.png".to_string(),
+                    alt: None,
+                },
+                Block::Image {
+                    src: "images/missing.png".to_string(),
+                    alt: None,
+                },
+            ],
+            ..Default::default()
+        };
+        let images = HashMap::from([("images/a.png".to_string(), png)]);
+        let bytes = build_docx("标题", &[], Some("洞察"), &article, &images).unwrap();
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut document = String::new();
+        std::io::Read::read_to_string(
+            &mut zip.by_name("word/document.xml").unwrap(),
+            &mut document,
+        )
+        .unwrap();
+        assert!(document.contains("小节 &lt;1&gt;"));
+        assert!(document.contains("正文 &amp; more"));
+        // Scaled down to the page width, aspect ratio kept
+        assert!(document.contains(r#"cx="5486400" cy="2743200""#));
+        assert_eq!(document.matches("<w:drawing>").count(), 1);
+        assert!(zip.by_name("word/media/image2.png").is_ok());
+    }
+}
@@ -1,6 +1,9 @@
 //! Embedding API handlers with PostgreSQL + pgvector
 
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
 use pgvector::Vector;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
@@ -67,6 +70,9 @@ pub struct EmbeddingData {
     pub vector: Vec<f32>,
     #[serde(rename = "indexedAt")]
     pub indexed_at: i64,
+    /// Embedding provider that produced the vector ("gemini", "ollama")
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -91,6 +97,24 @@ pub struct SearchRequest {
     #[serde(rename = "minScore")]
     pub min_score: Option<f32>,
     pub offset: Option<usize>,
+    /// Keyword query for hybrid search; also embedded when `vector` is empty
+    #[serde(rename = "queryText")]
+    pub query_text: Option<String>,
+    /// Score fusion settings for hybrid search
+    pub fusion: Option<FusionOptions>,
+    /// Collapse chunk/title/digest hits into one result per article (max score)
+    #[serde(rename = "groupByArticle")]
+    pub group_by_article: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FusionOptions {
+    /// "rrf" (reciprocal rank fusion, default) or "linear"
+    pub method: Option<String>,
+    /// Weight of the vector side, 0..1 (default 0.5)
+    pub weight: Option<f32>,
+    /// RRF rank constant (default 60)
+    pub k: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
@@ -101,6 +125,24 @@ pub struct SearchResultItem {
     pub source: String,
     pub link: Option<String>, // Added link
     pub score: f32,
+    /// Window index for `source = "content"` hits
+    #[serde(rename = "chunkIndex", skip_serializing_if = "Option::is_none")]
+    pub chunk_index: Option<i32>,
+    /// Matching embeddings of the article when grouped
+    #[serde(rename = "chunkHits", skip_serializing_if = "Option::is_none")]
+    pub chunk_hits: Option<i64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SearchRow {
+    id: String,
+    fakeid: String,
+    title: String,
+    source: String,
+    link: Option<String>,
+    score: f64,
+    chunk_index: Option<i32>,
+    chunk_hits: Option<i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -117,6 +159,52 @@ pub struct SearchResponse {
     pub error: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct HybridResultItem {
+    /// Article id (fakeid:aid)
+    pub id: String,
+    pub title: String,
+    pub fakeid: String,
+    pub link: Option<String>,
+    /// Fused score
+    pub score: f32,
+    #[serde(rename = "vectorScore")]
+    pub vector_score: Option<f32>,
+    #[serde(rename = "vectorRank")]
+    pub vector_rank: Option<i64>,
+    #[serde(rename = "textScore")]
+    pub text_score: Option<f32>,
+    #[serde(rename = "textRank")]
+    pub text_rank: Option<i64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct HybridRow {
+    id: String,
+    fakeid: String,
+    title: String,
+    link: Option<String>,
+    score: f64,
+    vector_score: Option<f64>,
+    vector_rank: Option<i64>,
+    text_score: Option<f64>,
+    text_rank: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HybridSearchResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<HybridResultItem>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "searchTime")]
+    pub search_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct StatsResponse {
     pub success: bool,
@@ -144,67 +232,15 @@ pub struct ClearResponse {
 
 // ============ Ollama Client ============
 
-const DEFAULT_OLLAMA_BASE_URL: &str = "http://127.0.0.1:11434";
-const DEFAULT_EMBEDDING_MODEL: &str = "qwen3-embedding:8b-q8_0";
-
-#[derive(Debug, Deserialize)]
-struct OllamaEmbedResponse {
-    embeddings: Vec<Vec<f32>>,
-}
-
 async fn call_ollama_embed(texts: Vec<String>) -> Result<Vec<Vec<f32>>, AppError> {
-    let base_url =
-        std::env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| DEFAULT_OLLAMA_BASE_URL.to_string());
-    let model = std::env::var("OLLAMA_EMBEDDING_MODEL")
-        .unwrap_or_else(|_| DEFAULT_EMBEDDING_MODEL.to_string());
-
-    let client = reqwest::Client::builder()
-        .no_proxy()
-        .timeout(std::time::Duration::from_secs(600)) // 10 minutes timeout for large batches
-        .build()
-        .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
-
-    let url = format!("{}/api/embed", base_url);
-
-    let payload = serde_json::json!({
-        "model": model,
-        "input": texts,
-    });
-
-    tracing::info!("[Ollama] Sending request to {} with model '{}'", url, model);
-    tracing::debug!("[Ollama] Payload: {}", payload);
-
-    let response = client.post(&url).json(&payload).send().await.map_err(|e| {
-        tracing::error!("[Ollama] Failed to connect to {}: {}", url, e);
-        e
-    })?;
-
-    let status = response.status();
-    tracing::info!("[Ollama] Response Status: {}", status);
-
-    if !status.is_success() {
-        let headers = response.headers().clone();
-        tracing::warn!("[Ollama] Response Headers: {:?}", headers);
-        let error_text = response.text().await.unwrap_or_default();
-        tracing::error!("[Ollama] Error Body: '{}'", error_text);
-
-        return Err(AppError::BadRequest(format!(
-            "Ollama error (Status: {}): {}",
-            status,
-            if error_text.is_empty() {
-                "(Empty response body)"
-            } else {
-                &error_text
-            }
-        )));
-    }
-
-    let result: OllamaEmbedResponse = response.json().await?;
-    Ok(result.embeddings)
+    let provider = crate::llm::embedding_provider("ollama", &Default::default())?;
+    provider
+        .embed(&texts)
+        .await
+        .map_err(|e| AppError::BadRequest(e.to_string()))
 }
 
 /// Helper for internal use (e.g. from other modules)
-#[allow(dead_code)]
 pub async fn generate_embedding_ollama(text: &str) -> Result<Vec<f32>, AppError> {
     let embeddings = call_ollama_embed(vec![text.to_string()]).await?;
     embeddings
@@ -327,6 +363,20 @@ pub async fn store(
     let mut stored = 0;
     let mut failed = 0;
 
+    // One mismatched vector means the client uses another model: reject the batch
+    if let Some(emb) = req.embeddings.iter().find(|e| !e.vector.is_empty()) {
+        if let Err(AppError::BadRequest(msg)) =
+            crate::embedding_registry::check_dimension(emb.vector.len())
+        {
+            return Ok(Json(StoreResponse {
+                success: false,
+                stored: 0,
+                failed: req.embeddings.len(),
+                error: Some(msg),
+            }));
+        }
+    }
+
     for emb in req.embeddings {
         if emb.id.is_empty() || emb.vector.is_empty() {
             failed += 1;
@@ -338,8 +388,8 @@ pub async fn store(
 
         let result = sqlx::query(
             r#"
-            INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at, provider, dimension)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             ON CONFLICT (id) DO UPDATE SET
                 fakeid = EXCLUDED.fakeid,
                 aid = EXCLUDED.aid,
@@ -347,7 +397,9 @@ pub async fn store(
                 source = EXCLUDED.source,
                 text_hash = EXCLUDED.text_hash,
                 vector = EXCLUDED.vector,
-                indexed_at = EXCLUDED.indexed_at
+                indexed_at = EXCLUDED.indexed_at,
+                provider = EXCLUDED.provider,
+                dimension = EXCLUDED.dimension
             "#,
         )
         .bind(&emb.id)
@@ -358,6 +410,8 @@ pub async fn store(
         .bind(&emb.text_hash)
         .bind(&vector)
         .bind(emb.indexed_at)
+        .bind(&emb.provider)
+        .bind(emb.vector.len() as i32)
         .execute(&pool)
         .await;
 
@@ -397,6 +451,17 @@ pub async fn search(
             error: Some("请提供查询向量".to_string()),
         }));
     }
+    if let Err(AppError::BadRequest(msg)) =
+        crate::embedding_registry::check_dimension(req.vector.len())
+    {
+        return Ok(Json(SearchResponse {
+            success: false,
+            results: None,
+            total: None,
+            search_time: None,
+            error: Some(msg),
+        }));
+    }
 
     let top_k = req.top_k.unwrap_or(50) as i32;
     let min_score = req.min_score.unwrap_or(0.3);
@@ -407,48 +472,241 @@ pub async fn search(
 
     // Native pgvector similarity search - uses index for O(log N) performance!
     // 1 - (vector <=> query) converts cosine distance to cosine similarity
-    let rows: Vec<(String, String, String, String, Option<String>, f64)> = sqlx::query_as(
+    let group = req.group_by_article.unwrap_or(false);
+    let query = if group {
+        // Take the nearest embeddings first (index scan), then max-pool per article
+        sqlx::query_as::<_, SearchRow>(
+            r#"
+            WITH candidates AS (
+                SELECT e.id, e.fakeid, e.aid, e.title, e.source, e.chunk_index,
+                       1 - (e.vector <=> $1::vector) AS score
+                FROM embeddings e
+                ORDER BY e.vector <=> $1::vector
+                LIMIT $5
+            ),
+            best AS (
+                SELECT DISTINCT ON (fakeid, aid) id, fakeid, aid, title, source, chunk_index, score,
+                       COUNT(*) OVER (PARTITION BY fakeid, aid) AS chunk_hits
+                FROM candidates
+                WHERE score >= $2
+                ORDER BY fakeid, aid, score DESC
+            )
+            SELECT b.id, b.fakeid, b.title, b.source, a.link, b.score, b.chunk_index, b.chunk_hits
+            FROM best b
+            LEFT JOIN articles a ON b.fakeid = a.fakeid AND b.aid = a.aid
+            ORDER BY b.score DESC
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+    } else {
+        sqlx::query_as::<_, SearchRow>(
+            r#"
+            SELECT e.id, e.fakeid, e.title, e.source, a.link,
+                   1 - (e.vector <=> $1::vector) as score,
+                   e.chunk_index, NULL::BIGINT AS chunk_hits
+            FROM embeddings e
+            LEFT JOIN articles a ON e.fakeid = a.fakeid AND e.aid = a.aid
+            WHERE 1 - (e.vector <=> $1::vector) >= $2
+            ORDER BY e.vector <=> $1::vector
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+    };
+    let mut query = query
+        .bind(&query_vector)
+        .bind(min_score as f64)
+        .bind(top_k)
+        .bind(offset);
+    if group {
+        // Several embeddings per article: fetch enough candidates to fill the page after grouping
+        query = query.bind(((top_k as i64 + offset) * 8).max(200));
+    }
+    let rows = query.fetch_all(&pool).await?;
+
+    let results: Vec<SearchResultItem> = rows
+        .into_iter()
+        .map(|row| SearchResultItem {
+            id: row.id,
+            fakeid: row.fakeid,
+            title: row.title,
+            source: row.source,
+            link: row.link,
+            score: row.score as f32,
+            chunk_index: row.chunk_index,
+            chunk_hits: row.chunk_hits,
+        })
+        .collect();
+
+    let total = results.len();
+    let search_time = start_time.elapsed().as_millis() as u64;
+
+    tracing::info!(
+        "[Search] Found {} matches in {}ms (pgvector native search)",
+        total,
+        search_time
+    );
+
+    Ok(Json(SearchResponse {
+        success: true,
+        results: Some(results),
+        total: Some(total),
+        search_time: Some(search_time),
+        error: None,
+    }))
+}
+
+/// Hybrid search: pgvector similarity and full-text rank fused in one query.
+/// Candidates from each side are ranked per article, then combined with
+/// weighted reciprocal rank fusion or a weighted linear blend of scores.
+pub async fn hybrid_search(
+    State(pool): State<PgPool>,
+    Json(req): Json<SearchRequest>,
+) -> Result<Json<HybridSearchResponse>, AppError> {
+    let start_time = std::time::Instant::now();
+
+    let query_text = req.query_text.as_deref().unwrap_or("").trim().to_string();
+    let terms = crate::fulltext::query_terms(&query_text);
+    if terms.is_empty() {
+        return Ok(Json(HybridSearchResponse {
+            success: false,
+            results: None,
+            total: None,
+            search_time: None,
+            error: Some("请提供搜索关键词".to_string()),
+        }));
+    }
+
+    let fusion = req.fusion.unwrap_or_default();
+    let method = fusion.method.unwrap_or_else(|| "rrf".to_string());
+    if !matches!(method.as_str(), "rrf" | "linear") {
+        return Err(AppError::BadRequest(format!("不支持的融合方式: {}", method)));
+    }
+    let weight = fusion.weight.unwrap_or(0.5).clamp(0.0, 1.0) as f64;
+    let rrf_k = fusion.k.unwrap_or(60).max(1) as f64;
+
+    let vector = if req.vector.is_empty() {
+        generate_embedding_ollama(&query_text).await?
+    } else {
+        req.vector
+    };
+    if let Err(AppError::BadRequest(msg)) = crate::embedding_registry::check_dimension(vector.len())
+    {
+        return Ok(Json(HybridSearchResponse {
+            success: false,
+            results: None,
+            total: None,
+            search_time: None,
+            error: Some(msg),
+        }));
+    }
+    let query_vector = Vector::from(vector);
+
+    let top_k = req.top_k.unwrap_or(50) as i64;
+    let min_score = req.min_score.unwrap_or(0.3);
+    let offset = req.offset.unwrap_or(0) as i64;
+    // Each side contributes enough candidates to fill the requested page
+    let candidates = ((top_k + offset) * 4).max(100);
+
+    // Embeddings are per (article, source); the best source counts for the article.
+    // Keyword rank sums title/digest (weighted x2) and body matches as in /api/search/fulltext.
+    let rows = sqlx::query_as::<_, HybridRow>(
         r#"
-        SELECT e.id, e.fakeid, e.title, e.source, a.link,
-               1 - (e.vector <=> $1::vector) as score
-        FROM embeddings e
-        LEFT JOIN articles a ON e.fakeid = a.fakeid AND e.aid = a.aid
-        WHERE 1 - (e.vector <=> $1::vector) >= $2
-        ORDER BY e.vector <=> $1::vector
-        LIMIT $3 OFFSET $4
+        WITH q AS (SELECT plainto_tsquery('simple', $2) AS q),
+        vec_raw AS (
+            SELECT e.fakeid, e.aid, e.title, 1 - (e.vector <=> $1::vector) AS score
+            FROM embeddings e
+            ORDER BY e.vector <=> $1::vector
+            LIMIT $4
+        ),
+        vec_best AS (
+            SELECT DISTINCT ON (fakeid, aid)
+                   fakeid || ':' || COALESCE(aid, '') AS key, fakeid, title, score
+            FROM vec_raw
+            WHERE score >= $3
+            ORDER BY fakeid, aid, score DESC
+        ),
+        vec AS (
+            SELECT key, fakeid, title, score, ROW_NUMBER() OVER (ORDER BY score DESC) AS rnk
+            FROM vec_best
+        ),
+        kw_raw AS (
+            SELECT a.id, ts_rank_cd(a.search_tsv, q.q) * 2 AS rank
+            FROM articles a, q
+            WHERE a.search_tsv @@ q.q
+            UNION ALL
+            SELECT c.id, ts_rank_cd(c.search_tsv, q.q) AS rank
+            FROM article_content c, q
+            WHERE c.search_tsv @@ q.q
+        ),
+        kw AS (
+            SELECT id AS key, SUM(rank)::FLOAT8 AS score,
+                   ROW_NUMBER() OVER (ORDER BY SUM(rank) DESC) AS rnk
+            FROM kw_raw
+            GROUP BY id
+            ORDER BY score DESC
+            LIMIT $4
+        ),
+        fused AS (
+            SELECT COALESCE(v.key, k.key) AS key, v.fakeid, v.title,
+                   v.score AS vector_score, v.rnk AS vector_rank,
+                   k.score AS text_score, k.rnk AS text_rank,
+                   CASE WHEN $5 = 'linear' THEN
+                       $6 * COALESCE(v.score, 0)
+                       + (1 - $6) * COALESCE(k.score / NULLIF(MAX(k.score) OVER (), 0), 0)
+                   ELSE
+                       $6 * COALESCE(1.0 / ($7 + v.rnk), 0)
+                       + (1 - $6) * COALESCE(1.0 / ($7 + k.rnk), 0)
+                   END AS score
+            FROM vec v
+            FULL OUTER JOIN kw k ON k.key = v.key
+        )
+        SELECT f.key AS id, COALESCE(a.fakeid, f.fakeid, split_part(f.key, ':', 1)) AS fakeid,
+               COALESCE(a.title, f.title, '') AS title, a.link, f.score::FLOAT8 AS score,
+               f.vector_score::FLOAT8 AS vector_score, f.vector_rank, f.text_score, f.text_rank
+        FROM fused f
+        LEFT JOIN articles a ON a.id = f.key
+        ORDER BY f.score DESC
+        LIMIT $8 OFFSET $9
         "#,
     )
     .bind(&query_vector)
+    .bind(terms.join(" "))
     .bind(min_score as f64)
+    .bind(candidates)
+    .bind(&method)
+    .bind(weight)
+    .bind(rrf_k)
     .bind(top_k)
     .bind(offset)
     .fetch_all(&pool)
     .await?;
 
-    let results: Vec<SearchResultItem> = rows
+    let results: Vec<HybridResultItem> = rows
         .into_iter()
-        .map(
-            |(id, fakeid, title, source, link, score)| SearchResultItem {
-                id,
-                fakeid,
-                title,
-                source,
-                link,
-                score: score as f32,
-            },
-        )
+        .map(|row| HybridResultItem {
+            id: row.id,
+            title: row.title,
+            fakeid: row.fakeid,
+            link: row.link,
+            score: row.score as f32,
+            vector_score: row.vector_score.map(|s| s as f32),
+            vector_rank: row.vector_rank,
+            text_score: row.text_score.map(|s| s as f32),
+            text_rank: row.text_rank,
+        })
         .collect();
 
     let total = results.len();
     let search_time = start_time.elapsed().as_millis() as u64;
 
     tracing::info!(
-        "[Search] Found {} matches in {}ms (pgvector native search)",
+        "[Search] Hybrid ({}) found {} matches in {}ms",
+        method,
         total,
         search_time
     );
 
-    Ok(Json(SearchResponse {
+    Ok(Json(HybridSearchResponse {
         success: true,
         results: Some(results),
         total: Some(total),
@@ -545,7 +803,17 @@ pub struct UnindexedCountResponse {
 pub async fn unindexed_count(
     State(pool): State<PgPool>,
 ) -> Result<Json<UnindexedCountResponse>, AppError> {
-    // Check if title embedding exists for the article
+    let count = count_unindexed(&pool).await?;
+
+    Ok(Json(UnindexedCountResponse {
+        success: true,
+        count: count as usize,
+        error: None,
+    }))
+}
+
+/// Articles without a title embedding
+pub async fn count_unindexed(pool: &PgPool) -> Result<i64, AppError> {
     let count: (i64,) = sqlx::query_as(
         r#"
         SELECT COUNT(*) 
@@ -556,19 +824,16 @@ pub async fn unindexed_count(
         )
         "#,
     )
-    .fetch_one(&pool)
+    .fetch_one(pool)
     .await?;
-
-    Ok(Json(UnindexedCountResponse {
-        success: true,
-        count: count.0 as usize,
-        error: None,
-    }))
+    Ok(count.0)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AutoIndexRequest {
     pub limit: Option<i32>,
+    /// "title" (title + digest, default) or "content" (full-text chunks)
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -585,8 +850,17 @@ pub async fn auto_index(
     State(pool): State<PgPool>,
     Json(req): Json<AutoIndexRequest>,
 ) -> Result<Json<AutoIndexResponse>, AppError> {
-    let limit = req.limit.unwrap_or(20);
+    match req.source.as_deref().unwrap_or("title") {
+        "title" => Ok(Json(index_batch(&pool, req.limit.unwrap_or(20)).await?)),
+        "content" => Ok(Json(
+            index_content_batch(&pool, req.limit.unwrap_or(5)).await?,
+        )),
+        other => Err(AppError::BadRequest(format!("不支持的source: {}", other))),
+    }
+}
 
+/// Embed up to `limit` unindexed articles (title + digest) with Ollama
+pub async fn index_batch(pool: &PgPool, limit: i32) -> Result<AutoIndexResponse, AppError> {
     // 1. Fetch unindexed articles
     let rows: Vec<(String, String, String, String, Option<String>)> = sqlx::query_as(
         r#"
@@ -600,17 +874,17 @@ pub async fn auto_index(
         "#,
     )
     .bind(limit)
-    .fetch_all(&pool)
+    .fetch_all(pool)
     .await?;
 
     if rows.is_empty() {
-        return Ok(Json(AutoIndexResponse {
+        return Ok(AutoIndexResponse {
             success: true,
             indexed: 0,
             failed: 0,
             remaining: 0,
             error: None,
-        }));
+        });
     }
 
     let mut indexed = 0;
@@ -658,6 +932,20 @@ pub async fn auto_index(
     if !texts_to_embed.is_empty() {
         match call_ollama_embed(texts_to_embed).await {
             Ok(embeddings) => {
+                if let Some(first) = embeddings.first() {
+                    if let Err(AppError::BadRequest(msg)) =
+                        crate::embedding_registry::check_dimension(first.len())
+                    {
+                        return Ok(AutoIndexResponse {
+                            success: false,
+                            indexed: 0,
+                            failed: rows.len(),
+                            remaining: 0,
+                            error: Some(msg),
+                        });
+                    }
+                }
+
                 // Store embeddings
                 for (i, embedding) in embeddings.into_iter().enumerate() {
                     if i >= metadata.len() {
@@ -669,6 +957,7 @@ pub async fn auto_index(
                     // fakeid:aid:source
                     let embedding_id = format!("{}:{}:{}", fakeid, aid, source);
 
+                    let dimension = embedding.len() as i32;
                     let vector = Vector::from(embedding);
                     let now = std::time::SystemTime::now()
                         .duration_since(std::time::UNIX_EPOCH)
@@ -680,11 +969,13 @@ pub async fn auto_index(
 
                     let result = sqlx::query(
                         r#"
-                        INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at)
-                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                        INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at, provider, dimension)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'ollama', $9)
                         ON CONFLICT (id) DO UPDATE SET
                             vector = EXCLUDED.vector,
-                            indexed_at = EXCLUDED.indexed_at
+                            indexed_at = EXCLUDED.indexed_at,
+                            provider = EXCLUDED.provider,
+                            dimension = EXCLUDED.dimension
                         "#
                     )
                     .bind(&embedding_id)
@@ -695,7 +986,8 @@ pub async fn auto_index(
                     .bind(&text_hash)
                     .bind(&vector)
                     .bind(now)
-                    .execute(&pool)
+                    .bind(dimension)
+                    .execute(pool)
                     .await;
 
                     if let Err(e) = result {
@@ -711,38 +1003,174 @@ pub async fn auto_index(
             Err(e) => {
                 tracing::error!("Ollama batch failed: {}", e);
                 failed = rows.len();
-                return Ok(Json(AutoIndexResponse {
+                return Ok(AutoIndexResponse {
                     success: false,
                     indexed: 0,
                     failed,
                     remaining: 0,
                     error: Some(format!("Ollama failed: {}", e)),
-                }));
+                });
             }
         }
     }
 
     // Check remaining
-    let remaining: (i64,) = sqlx::query_as(
+    let remaining = count_unindexed(pool).await?;
+
+    Ok(AutoIndexResponse {
+        success: true,
+        indexed,
+        failed,
+        remaining: remaining as usize,
+        error: None,
+    })
+}
+
+/// Cached article bodies whose chunk embeddings are missing or outdated.
+/// Chunks carry the md5 of the whole body as `text_hash`, so edits re-queue the article.
+pub async fn count_unindexed_content(pool: &PgPool) -> Result<i64, AppError> {
+    let count: i64 = sqlx::query_scalar(
         r#"
-        SELECT COUNT(*) 
-        FROM articles a 
+        SELECT COUNT(*)
+        FROM article_content c
+        JOIN articles a ON a.id = c.id
         WHERE NOT EXISTS (
-            SELECT 1 FROM embeddings e 
-            WHERE e.fakeid = a.fakeid AND e.aid = a.aid AND e.source = 'title'
+            SELECT 1 FROM embeddings e
+            WHERE e.id = a.fakeid || ':' || a.aid || ':content:0' AND e.text_hash = md5(c.content)
         )
         "#,
     )
-    .fetch_one(&pool)
+    .fetch_one(pool)
     .await?;
+    Ok(count)
+}
 
-    Ok(Json(AutoIndexResponse {
+/// Embed full article bodies as overlapping token windows (`source = 'content'`)
+pub async fn index_content_batch(
+    pool: &PgPool,
+    limit: i32,
+) -> Result<AutoIndexResponse, AppError> {
+    let rows: Vec<(String, String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT a.fakeid, a.aid, a.title, c.content
+        FROM article_content c
+        JOIN articles a ON a.id = c.id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM embeddings e
+            WHERE e.id = a.fakeid || ':' || a.aid || ':content:0' AND e.text_hash = md5(c.content)
+        )
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let mut indexed = 0;
+    let mut failed = 0;
+
+    for (fakeid, aid, title, content) in rows {
+        let text_hash = format!("{:x}", md5::compute(content.as_bytes()));
+        let mut chunks = tokio::task::spawn_blocking(move || {
+            let text = crate::content::extract::extract(&content).text();
+            crate::content::chunk::article_chunks(&text)
+        })
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+        // Bodies without text (image-only posts) fall back to the title so they are not retried forever
+        if chunks.is_empty() {
+            chunks.push(title.clone());
+        }
+
+        let embeddings = match call_ollama_embed(chunks).await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!("Ollama chunk batch failed: {}", e);
+                return Ok(AutoIndexResponse {
+                    success: false,
+                    indexed,
+                    failed: failed + 1,
+                    remaining: 0,
+                    error: Some(format!("Ollama failed: {}", e)),
+                });
+            }
+        };
+        if let Some(first) = embeddings.first() {
+            if let Err(AppError::BadRequest(msg)) =
+                crate::embedding_registry::check_dimension(first.len())
+            {
+                return Ok(AutoIndexResponse {
+                    success: false,
+                    indexed,
+                    failed: failed + 1,
+                    remaining: 0,
+                    error: Some(msg),
+                });
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let count = embeddings.len();
+        let mut article_ok = true;
+        for (i, embedding) in embeddings.into_iter().enumerate() {
+            let dimension = embedding.len() as i32;
+            let result = sqlx::query(
+                r#"
+                INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at, provider, dimension, chunk_index)
+                VALUES ($1, $2, $3, $4, 'content', $5, $6, $7, 'ollama', $8, $9)
+                ON CONFLICT (id) DO UPDATE SET
+                    title = EXCLUDED.title,
+                    text_hash = EXCLUDED.text_hash,
+                    vector = EXCLUDED.vector,
+                    indexed_at = EXCLUDED.indexed_at,
+                    provider = EXCLUDED.provider,
+                    dimension = EXCLUDED.dimension,
+                    chunk_index = EXCLUDED.chunk_index
+                "#,
+            )
+            .bind(format!("{}:{}:content:{}", fakeid, aid, i))
+            .bind(&fakeid)
+            .bind(&aid)
+            .bind(&title)
+            .bind(&text_hash)
+            .bind(Vector::from(embedding))
+            .bind(now)
+            .bind(dimension)
+            .bind(i as i32)
+            .execute(pool)
+            .await;
+            if let Err(e) = result {
+                tracing::error!("Failed to save chunk {} of {}:{}: {}", i, fakeid, aid, e);
+                article_ok = false;
+            }
+        }
+
+        // Drop chunks left over from a longer previous version
+        sqlx::query(
+            "DELETE FROM embeddings WHERE fakeid = $1 AND aid = $2 AND source = 'content' AND chunk_index >= $3",
+        )
+        .bind(&fakeid)
+        .bind(&aid)
+        .bind(count as i32)
+        .execute(pool)
+        .await?;
+
+        if article_ok {
+            indexed += 1;
+        } else {
+            failed += 1;
+        }
+    }
+
+    let remaining = count_unindexed_content(pool).await?;
+
+    Ok(AutoIndexResponse {
         success: true,
         indexed,
         failed,
-        remaining: remaining.0 as usize,
+        remaining: remaining as usize,
         error: None,
-    }))
+    })
 }
 
 // ============ AppState Wrapper Handlers ============
@@ -763,6 +1191,139 @@ pub async fn search_handler(
     search(State(state.db_pool), body).await
 }
 
+/// Hybrid search (AppState wrapper)
+pub async fn hybrid_search_handler(
+    State(state): State<AppState>,
+    body: Json<SearchRequest>,
+) -> Result<Json<HybridSearchResponse>, AppError> {
+    hybrid_search(State(state.db_pool), body).await
+}
+
+/// Re-embed all stored vectors with another provider/dimension in the background
+pub async fn migrate_handler(
+    State(state): State<AppState>,
+    Json(req): Json<crate::embedding_registry::MigrateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let migration = crate::embedding_registry::start_migration(&state.db_pool, req).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "migration": migration
+    })))
+}
+
+/// Current embedding registry and migration progress
+pub async fn migrate_status_handler() -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "registry": crate::embedding_registry::current(),
+        "migration": crate::embedding_registry::migration_status()
+    })))
+}
+
+/// Auto-index daemon status
+pub async fn auto_index_daemon_status() -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": crate::autoindex::status()
+    })))
+}
+
+/// Start/stop the auto-index daemon or change its interval and batch size
+pub async fn auto_index_daemon_control(
+    Json(req): Json<crate::autoindex::DaemonControl>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let status = crate::autoindex::control(req)?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": status
+    })))
+}
+
+/// Vector index status
+pub async fn index_status_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": crate::vector_index::status(&state.db_pool).await?
+    })))
+}
+
+/// Create or rebuild the vector index in the background
+pub async fn index_build_handler(
+    State(state): State<AppState>,
+    Json(spec): Json<crate::vector_index::IndexSpec>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    crate::vector_index::start_build(&state.db_pool, spec)?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": crate::vector_index::status(&state.db_pool).await?
+    })))
+}
+
+/// Drop the vector index
+pub async fn index_drop_handler(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    crate::vector_index::drop_index(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": crate::vector_index::status(&state.db_pool).await?
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    /// Accept a dump from another provider/model of the same dimension
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Stream the embeddings table as a binary dump (see `embedding_dump`)
+pub async fn export_handler(
+    State(state): State<AppState>,
+    Query(filter): Query<crate::embedding_dump::ExportFilter>,
+) -> Result<axum::response::Response, AppError> {
+    use axum::http::{header, StatusCode};
+
+    let stream = crate::embedding_dump::export_stream(&state.db_pool, filter).await?;
+    let filename = format!(
+        "embeddings-{}.wemb",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    );
+    Ok(axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .body(axum::body::Body::from_stream(stream))
+        .unwrap())
+}
+
+/// Upsert the embeddings of a binary dump streamed as the request body
+pub async fn import_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    body: axum::body::Body,
+) -> Result<Json<serde_json::Value>, AppError> {
+    use futures::TryStreamExt;
+
+    if crate::embedding_registry::migration_status().is_some_and(|m| m.status == "running") {
+        return Err(AppError::BadRequest(
+            "向量迁移进行中, 请稍后导入".to_string(),
+        ));
+    }
+    let stream = body.into_data_stream().map_err(std::io::Error::other);
+    let mut reader = tokio_util::io::StreamReader::new(stream);
+    let summary = crate::embedding_dump::import(&state.db_pool, &mut reader, query.force).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": summary
+    })))
+}
+
 /// Get stats (AppState wrapper)
 pub async fn stats_handler(State(state): State<AppState>) -> Result<Json<StatsResponse>, AppError> {
     stats(State(state.db_pool)).await
@@ -792,3 +1353,292 @@ pub async fn auto_index_handler(
 ) -> Result<Json<AutoIndexResponse>, AppError> {
     auto_index(State(state.db_pool), body).await
 }
+
+// ============ Similar Articles ============
+
+#[derive(Debug, Deserialize)]
+pub struct SimilarQuery {
+    /// `fakeid:aid`, as in `articles.id`
+    pub article_id: String,
+    /// Neighbours returned (default 10, at most 50)
+    pub limit: Option<i64>,
+    #[serde(rename = "minScore")]
+    pub min_score: Option<f32>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SimilarArticle {
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    pub fakeid: String,
+    pub account_name: Option<String>,
+    pub create_time: i64,
+    pub cover: Option<String>,
+    pub score: f64,
+}
+
+/// Nearest stored articles to one article ("related reading"). The article's
+/// vector is the mean of its embeddings; the article itself and the other
+/// articles of the same push are left out.
+pub async fn similar_handler(
+    State(state): State<AppState>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (fakeid, aid) = query
+        .article_id
+        .split_once(':')
+        .ok_or_else(|| AppError::BadRequest("article_id 格式应为 fakeid:aid".to_string()))?;
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+    let min_score = query.min_score.unwrap_or(0.3);
+
+    let vector: Option<Vector> =
+        sqlx::query_scalar("SELECT AVG(vector) FROM embeddings WHERE fakeid = $1 AND aid = $2")
+            .bind(fakeid)
+            .bind(aid)
+            .fetch_one(&state.db_pool)
+            .await?;
+    let vector = vector.ok_or_else(|| AppError::NotFound("该文章尚未向量化".to_string()))?;
+
+    // aid is "{appmsgid}_{itemidx}": one push shares the appmsgid
+    let push_id = aid.split('_').next().unwrap_or(aid);
+    let articles: Vec<SimilarArticle> = sqlx::query_as(
+        r#"
+        WITH candidates AS (
+            SELECT e.fakeid, e.aid, 1 - (e.vector <=> $1::vector) AS score
+            FROM embeddings e
+            WHERE NOT (e.fakeid = $2 AND split_part(e.aid, '_', 1) = $3)
+            ORDER BY e.vector <=> $1::vector
+            LIMIT $4
+        )
+        SELECT a.id, a.title, a.link, a.fakeid, acc.nickname AS account_name,
+               a.create_time, a.cover, MAX(c.score) AS score
+        FROM candidates c
+        JOIN articles a ON a.fakeid = c.fakeid AND a.aid = c.aid
+        LEFT JOIN accounts acc ON acc.fakeid = a.fakeid
+        WHERE c.score >= $5 AND NOT a.is_deleted
+        GROUP BY a.id, a.title, a.link, a.fakeid, acc.nickname, a.create_time, a.cover
+        ORDER BY score DESC
+        LIMIT $6
+        "#,
+    )
+    .bind(&vector)
+    .bind(fakeid)
+    .bind(push_id)
+    // Several embeddings per article: fetch enough to fill the list after grouping
+    .bind((limit * 8).max(100))
+    .bind(min_score as f64)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": articles
+    })))
+}
+
+// ============ Clustering ============
+
+/// Articles clustered at most per request
+const MAX_CLUSTER_ARTICLES: i64 = 3000;
+/// Titles per cluster shown to the labelling model and returned
+const REPRESENTATIVE_TITLES: usize = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct ClusterRequest {
+    pub fakeid: Option<String>,
+    /// Only the articles an insight task matched
+    pub task_id: Option<uuid::Uuid>,
+    /// Publish time range, unix seconds (end exclusive)
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    /// Cluster count, at most `cluster::MAX_K`; about √(n/2) when unset
+    pub k: Option<usize>,
+    /// Most recent articles considered (default 1000)
+    pub limit: Option<i64>,
+    /// Name clusters with the LLM (default true); otherwise by their most central title
+    pub label: Option<bool>,
+    pub provider: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub deepseek_api_key: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ClusterArticleRow {
+    id: String,
+    title: String,
+    link: String,
+    fakeid: String,
+    create_time: i64,
+    vector: Vector,
+}
+
+/// Short names for clusters from their representative titles, in order
+pub(crate) async fn label_clusters(
+    state: &AppState,
+    provider: Option<&str>,
+    gemini_api_key: Option<&str>,
+    deepseek_api_key: Option<&str>,
+    titles: &[Vec<String>],
+) -> anyhow::Result<Vec<String>> {
+    let provider = provider.unwrap_or("gemini").to_string();
+    let config = crate::api::llm::keyed_config(&provider, deepseek_api_key, gemini_api_key);
+    let llm = crate::api::llm::chat_provider(state, &provider, config)
+        .await
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    let groups: Vec<String> = titles
+        .iter()
+        .enumerate()
+        .map(|(i, t)| format!("{}. {}", i + 1, t.join(" / ")))
+        .collect();
+    let prompt = format!(
+        "下面每一行是一组主题相近的公众号文章标题。为每组起一个不超过 8 个字的中文主题名，按顺序输出。\n\
+         只返回 JSON: {{\"labels\": [\"主题1\", \"主题2\"]}}\n\n{}",
+        groups.join("\n")
+    );
+    let reply = llm
+        .chat(&crate::llm::ChatRequest {
+            prompt: &prompt,
+            temperature: Some(0.2),
+            json: true,
+            ..Default::default()
+        })
+        .await?;
+
+    #[derive(Deserialize)]
+    struct Labels {
+        labels: Vec<String>,
+    }
+    let parsed: Labels = serde_json::from_str(crate::llm::extract_json(&reply.text))?;
+    Ok(parsed.labels)
+}
+
+/// Cluster stored article vectors into topics with 2-D map coordinates
+pub async fn cluster_handler(
+    State(state): State<AppState>,
+    Json(req): Json<ClusterRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = req.limit.unwrap_or(1000).clamp(1, MAX_CLUSTER_ARTICLES);
+
+    // One vector per article: the mean of its title, digest and chunk embeddings
+    let rows: Vec<ClusterArticleRow> = sqlx::query_as(
+        r#"
+        SELECT a.id, a.title, a.link, a.fakeid, a.create_time, AVG(e.vector) AS vector
+        FROM embeddings e
+        JOIN articles a ON a.fakeid = e.fakeid AND a.aid = e.aid
+        WHERE ($1::TEXT IS NULL OR a.fakeid = $1)
+          AND ($2::BIGINT IS NULL OR a.create_time >= $2)
+          AND ($3::BIGINT IS NULL OR a.create_time < $3)
+          AND ($4::UUID IS NULL OR EXISTS (
+              SELECT 1 FROM insight_articles ia
+              WHERE ia.task_id = $4 AND ia.account_fakeid = a.fakeid
+                AND (ia.url = a.link OR ia.title = a.title)
+          ))
+        GROUP BY a.id, a.title, a.link, a.fakeid, a.create_time
+        ORDER BY a.create_time DESC
+        LIMIT $5
+        "#,
+    )
+    .bind(&req.fakeid)
+    .bind(req.start_time)
+    .bind(req.end_time)
+    .bind(req.task_id)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    if rows.len() < 2 {
+        return Err(AppError::BadRequest(
+            "已向量化的文章不足，无法聚类".to_string(),
+        ));
+    }
+
+    let k = req
+        .k
+        .unwrap_or_else(|| crate::cluster::default_k(rows.len()))
+        .clamp(1, crate::cluster::MAX_K);
+    let mut vectors: Vec<Vec<f32>> = rows.iter().map(|r| r.vector.to_vec()).collect();
+    let (clustering, vectors, points) = tokio::task::spawn_blocking(move || {
+        let clustering = crate::cluster::kmeans(&mut vectors, k);
+        let points = crate::cluster::project_2d(&vectors);
+        (clustering, vectors, points)
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let members: Vec<Vec<usize>> = (0..clustering.centroids.len())
+        .map(|c| clustering.members(&vectors, c))
+        .collect();
+    let titles: Vec<Vec<String>> = members
+        .iter()
+        .map(|m| {
+            m.iter()
+                .take(REPRESENTATIVE_TITLES)
+                .map(|&i| rows[i].title.clone())
+                .collect()
+        })
+        .collect();
+
+    let mut labels: Vec<String> = titles.iter().map(|t| t[0].clone()).collect();
+    if req.label.unwrap_or(true) {
+        let labelled = label_clusters(
+            &state,
+            req.provider.as_deref(),
+            req.gemini_api_key.as_deref(),
+            req.deepseek_api_key.as_deref(),
+            &titles,
+        )
+        .await;
+        match labelled {
+            Ok(named) => {
+                for (label, name) in labels.iter_mut().zip(named) {
+                    if !name.trim().is_empty() {
+                        *label = name.trim().to_string();
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("[Cluster] Labelling failed, using titles: {}", e),
+        }
+    }
+
+    let clusters: Vec<serde_json::Value> = members
+        .iter()
+        .enumerate()
+        .map(|(c, m)| {
+            serde_json::json!({
+                "id": c,
+                "label": labels[c],
+                "size": m.len(),
+                "representative_titles": titles[c],
+                "article_ids": m.iter().map(|&i| &rows[i].id).collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let articles: Vec<serde_json::Value> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            serde_json::json!({
+                "id": r.id,
+                "title": r.title,
+                "link": r.link,
+                "fakeid": r.fakeid,
+                "create_time": r.create_time,
+                "cluster": clustering.assignments[i],
+                "x": points[i].0,
+                "y": points[i].1
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "k": clusters.len(),
+            "clusters": clusters,
+            "articles": articles
+        }
+    })))
+}
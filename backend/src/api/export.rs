@@ -0,0 +1,787 @@
+//! Background export jobs
+//!
+//! `/api/insight/export` runs as a job recorded in `export_jobs`. Progress is
+//! broadcast to Server-Sent Event subscribers (per-article results, images
+//! downloaded, ETA) and a running job can be cancelled between articles.
+//!
+//! `/api/insight/export/download` runs the same job in a temporary workspace
+//! and streams the result as a ZIP archive (see `zip_directory`). The archive
+//! is kept for `EXPORT_ARCHIVE_TTL_HOURS` (default 24, 0 disables) so it can be
+//! downloaded again from `/api/insight/export/:job_id/download`.
+//!
+//! Jobs stay in `export_jobs` with their request parameters and, in
+//! `export_job_articles`, the outcome of every article; `/api/insight/exports`
+//! lists them.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::io::Write;
+use std::path::{Path as StdPath, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    Extension, Json,
+};
+use futures::stream::Stream;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+const DEFAULT_ARCHIVE_TTL_HOURS: u64 = 24;
+
+lazy_static! {
+    /// Jobs running in this process
+    static ref JOBS: Mutex<HashMap<Uuid, Arc<ExportJobHandle>>> = Mutex::new(HashMap::new());
+}
+
+// ============ Types ============
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub format: String,
+    pub target_dir: String,
+    pub export_dir: Option<String>,
+    pub status: String, // running, completed, failed, cancelled
+    pub total: i32,
+    pub succeeded: i32,
+    pub failed: i32,
+    pub images_downloaded: i32,
+    pub message: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub finished_at: Option<i64>,
+    /// Request parameters, without credentials
+    pub params: serde_json::Value,
+    #[serde(skip)]
+    pub archive_path: Option<String>,
+    pub archive_expires_at: Option<i64>,
+}
+
+impl ExportJob {
+    fn duration_secs(&self) -> Option<i64> {
+        self.finished_at.map(|finished| finished - self.created_at)
+    }
+
+    /// Re-download link while the ZIP of a downloaded export is kept
+    fn download_url(&self) -> Option<String> {
+        let expires_at = self.archive_expires_at?;
+        let path = self.archive_path.as_deref()?;
+        (expires_at > chrono::Utc::now().timestamp() && StdPath::new(path).is_file())
+            .then(|| format!("/api/insight/export/{}/download", self.id))
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        value["duration_secs"] = serde_json::json!(self.duration_secs());
+        value["download_url"] = serde_json::json!(self.download_url());
+        value
+    }
+}
+
+const EXPORT_JOB_COLUMNS: &str = "id, task_id, format, target_dir, export_dir, status, total, succeeded, failed, images_downloaded, message, created_at, updated_at, finished_at, params, archive_path, archive_expires_at";
+
+/// Outcome of one article of a job
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportJobArticle {
+    #[sqlx(rename = "idx")]
+    pub index: i32,
+    pub article_id: Option<Uuid>,
+    pub title: String,
+    pub success: bool,
+    pub message: String,
+    pub images: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListExportsQuery {
+    pub task_id: Option<Uuid>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportProgress {
+    pub total: usize,
+    pub done: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub images_downloaded: usize,
+    /// Estimated seconds remaining, once at least one article has finished
+    pub eta_seconds: Option<u64>,
+}
+
+/// SSE payload; the event name is the `type` field
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportEvent {
+    Progress(ExportProgress),
+    Article {
+        index: usize,
+        title: String,
+        success: bool,
+        message: String,
+        images: usize,
+    },
+    Done {
+        status: String,
+        message: String,
+        export_dir: Option<String>,
+    },
+}
+
+impl ExportEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            ExportEvent::Progress(_) => "progress",
+            ExportEvent::Article { .. } => "article",
+            ExportEvent::Done { .. } => "done",
+        }
+    }
+
+    fn to_sse(&self) -> Event {
+        Event::default()
+            .event(self.name())
+            .data(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+/// Live state of a running job, shared between the worker and SSE subscribers
+pub struct ExportJobHandle {
+    pub id: Uuid,
+    db_pool: sqlx::PgPool,
+    events: broadcast::Sender<ExportEvent>,
+    cancelled: AtomicBool,
+    started: Instant,
+    progress: Mutex<ExportProgress>,
+}
+
+impl ExportJobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Stop after the articles in progress
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Record one finished article and notify subscribers
+    pub async fn record_article(
+        &self,
+        index: usize,
+        article_id: Option<Uuid>,
+        title: &str,
+        success: bool,
+        message: &str,
+        images: usize,
+    ) {
+        let progress = {
+            let mut p = self.progress.lock().unwrap();
+            p.done += 1;
+            if success {
+                p.succeeded += 1;
+            } else {
+                p.failed += 1;
+            }
+            p.images_downloaded += images;
+            let per_article = self.started.elapsed().as_secs_f64() / p.done as f64;
+            p.eta_seconds = Some((per_article * p.total.saturating_sub(p.done) as f64) as u64);
+            p.clone()
+        };
+
+        let _ = self.events.send(ExportEvent::Article {
+            index,
+            title: title.to_string(),
+            success,
+            message: message.to_string(),
+            images,
+        });
+        let _ = self.events.send(ExportEvent::Progress(progress.clone()));
+
+        let _ = sqlx::query(
+            "UPDATE export_jobs SET succeeded = $1, failed = $2, images_downloaded = $3, updated_at = $4 WHERE id = $5",
+        )
+        .bind(progress.succeeded as i32)
+        .bind(progress.failed as i32)
+        .bind(progress.images_downloaded as i32)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(self.id)
+        .execute(&self.db_pool)
+        .await;
+
+        if let Err(e) = sqlx::query(
+            r#"
+            INSERT INTO export_job_articles (job_id, idx, article_id, title, success, message, images)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (job_id, idx) DO UPDATE
+            SET success = EXCLUDED.success, message = EXCLUDED.message, images = EXCLUDED.images
+            "#,
+        )
+        .bind(self.id)
+        .bind(index as i32)
+        .bind(article_id)
+        .bind(title)
+        .bind(success)
+        .bind(message)
+        .bind(images as i32)
+        .execute(&self.db_pool)
+        .await
+        {
+            tracing::warn!("[Export] Failed to record article of job {}: {}", self.id, e);
+        }
+    }
+
+    /// Move the ZIP of a downloaded export out of its workspace so it can be
+    /// downloaded again; returns where it now is, or `None` when archives are
+    /// not kept (or moving failed) and it stays where it was
+    pub async fn keep_archive(&self, zip_path: &StdPath) -> Option<PathBuf> {
+        let hours = archive_ttl_hours();
+        if hours == 0 {
+            return None;
+        }
+        let archive = archives_dir().join(format!("{}.zip", self.id));
+        let moved = std::fs::create_dir_all(archives_dir())
+            .and_then(|_| std::fs::rename(zip_path, &archive));
+        if let Err(e) = moved {
+            tracing::warn!("[Export] Failed to keep archive of job {}: {}", self.id, e);
+            return None;
+        }
+        let expires_at = chrono::Utc::now().timestamp() + (hours * 3600) as i64;
+        if let Err(e) = sqlx::query(
+            "UPDATE export_jobs SET archive_path = $1, archive_expires_at = $2 WHERE id = $3",
+        )
+        .bind(archive.to_string_lossy().to_string())
+        .bind(expires_at)
+        .bind(self.id)
+        .execute(&self.db_pool)
+        .await
+        {
+            tracing::warn!("[Export] Failed to save archive of job {}: {}", self.id, e);
+        }
+        Some(archive)
+    }
+
+    /// Persist the final state, unregister the job and notify subscribers
+    pub async fn finish(&self, status: &str, message: &str, export_dir: Option<&str>) {
+        let now = chrono::Utc::now().timestamp();
+        if let Err(e) = sqlx::query(
+            "UPDATE export_jobs SET status = $1, message = $2, export_dir = COALESCE($3, export_dir), updated_at = $4, finished_at = $4 WHERE id = $5",
+        )
+        .bind(status)
+        .bind(message)
+        .bind(export_dir)
+        .bind(now)
+        .bind(self.id)
+        .execute(&self.db_pool)
+        .await
+        {
+            tracing::error!("[Export] Failed to save job {}: {}", self.id, e);
+        }
+
+        // Unregister before the final event: later subscribers read the row instead
+        JOBS.lock().unwrap().remove(&self.id);
+        let _ = self.events.send(ExportEvent::Done {
+            status: status.to_string(),
+            message: message.to_string(),
+            export_dir: export_dir.map(String::from),
+        });
+        tracing::info!("[Export] Job {} {}: {}", self.id, status, message);
+    }
+}
+
+/// Create the `export_jobs` row and register the job as running
+pub async fn start_job(
+    db_pool: &sqlx::PgPool,
+    task_id: Uuid,
+    format: &str,
+    target_dir: &str,
+    export_dir: &str,
+    total: usize,
+    params: &serde_json::Value,
+) -> Result<Arc<ExportJobHandle>, AppError> {
+    let id = Uuid::new_v4();
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        "INSERT INTO export_jobs (id, task_id, format, target_dir, export_dir, status, total, created_at, updated_at, params) VALUES ($1, $2, $3, $4, $5, 'running', $6, $7, $7, $8)",
+    )
+    .bind(id)
+    .bind(task_id)
+    .bind(format)
+    .bind(target_dir)
+    .bind(export_dir)
+    .bind(total as i32)
+    .bind(now)
+    .bind(params)
+    .execute(db_pool)
+    .await?;
+
+    let (events, _) = broadcast::channel(256);
+    let handle = Arc::new(ExportJobHandle {
+        id,
+        db_pool: db_pool.clone(),
+        events,
+        cancelled: AtomicBool::new(false),
+        started: Instant::now(),
+        progress: Mutex::new(ExportProgress {
+            total,
+            ..Default::default()
+        }),
+    });
+    JOBS.lock().unwrap().insert(id, handle.clone());
+    Ok(handle)
+}
+
+/// Jobs running in this process
+pub fn running_count() -> usize {
+    JOBS.lock().unwrap().len()
+}
+
+/// Stop every running job after its current articles (server shutdown)
+pub fn cancel_all() {
+    for handle in JOBS.lock().unwrap().values() {
+        handle.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn archive_ttl_hours() -> u64 {
+    std::env::var("EXPORT_ARCHIVE_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_ARCHIVE_TTL_HOURS)
+}
+
+/// Where the ZIPs of downloaded exports are kept
+fn archives_dir() -> PathBuf {
+    std::env::temp_dir()
+        .join("wechat-insights-export")
+        .join("archives")
+}
+
+/// Remove kept archives that have expired
+pub async fn purge_archives(pool: &sqlx::PgPool) -> Result<usize, sqlx::Error> {
+    let expired: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, archive_path FROM export_jobs WHERE archive_path IS NOT NULL AND archive_expires_at <= $1",
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_all(pool)
+    .await?;
+    for (id, path) in &expired {
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("[Export] Failed to remove archive {}: {}", path, e);
+                continue;
+            }
+        }
+        sqlx::query("UPDATE export_jobs SET archive_path = NULL WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(expired.len())
+}
+
+/// Purge expired archives every hour
+pub fn spawn_archive_purge(pool: sqlx::PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match purge_archives(&pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("[Export] Removed {} expired archive(s)", n),
+                Err(e) => tracing::warn!("[Export] Archive purge failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Delete the jobs of a task with their article outcomes and kept archives
+pub async fn delete_task_jobs(pool: &sqlx::PgPool, task_id: Uuid) -> Result<(), sqlx::Error> {
+    let archives: Vec<String> = sqlx::query_scalar(
+        "SELECT archive_path FROM export_jobs WHERE task_id = $1 AND archive_path IS NOT NULL",
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    for path in archives {
+        let _ = std::fs::remove_file(path);
+    }
+    sqlx::query(
+        "DELETE FROM export_job_articles WHERE job_id IN (SELECT id FROM export_jobs WHERE task_id = $1)",
+    )
+    .bind(task_id)
+    .execute(pool)
+    .await?;
+    sqlx::query("DELETE FROM export_jobs WHERE task_id = $1")
+        .bind(task_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Scratch directory of a downloaded export, removed when dropped
+pub struct TempWorkspace(PathBuf);
+
+impl TempWorkspace {
+    pub fn create() -> std::io::Result<Self> {
+        let path = std::env::temp_dir()
+            .join("wechat-insights-export")
+            .join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&path)?;
+        Ok(Self(path))
+    }
+
+    pub fn path(&self) -> &StdPath {
+        &self.0
+    }
+}
+
+impl Drop for TempWorkspace {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            tracing::warn!("[Export] Failed to remove {:?}: {}", self.0, e);
+        }
+    }
+}
+
+/// Archive `dir` (as its top-level folder) into `zip_path`. Images and
+/// PDF / Office files are already compressed and are stored as is.
+pub fn zip_directory(dir: &StdPath, zip_path: &StdPath) -> anyhow::Result<()> {
+    use zip::write::SimpleFileOptions;
+    use zip::CompressionMethod;
+
+    let root = dir.parent().unwrap_or(dir);
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(zip_path)?);
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries: Vec<_> = std::fs::read_dir(&current)?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let path = entry.path();
+            let name = path
+                .strip_prefix(root)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if entry.file_type()?.is_dir() {
+                zip.add_directory(name, SimpleFileOptions::default())?;
+                pending.push(path);
+                continue;
+            }
+            let compressed = matches!(
+                path.extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .as_deref(),
+                Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "pdf" | "docx" | "xlsx" | "zip")
+            );
+            let options = SimpleFileOptions::default()
+                .compression_method(if compressed {
+                    CompressionMethod::Stored
+                } else {
+                    CompressionMethod::Deflated
+                })
+                .large_file(entry.metadata()?.len() >= u32::MAX as u64);
+            zip.start_file(name, options)?;
+            std::io::copy(&mut std::fs::File::open(&path)?, &mut zip)?;
+        }
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+async fn load_job(state: &AppState, job_id: Uuid) -> Result<ExportJob, AppError> {
+    sqlx::query_as::<_, ExportJob>(&format!(
+        "SELECT {} FROM export_jobs WHERE id = $1",
+        EXPORT_JOB_COLUMNS
+    ))
+    .bind(job_id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(AppError::NotFound("Export job not found".to_string()))
+}
+
+/// A job of a task the principal may see
+async fn authorize_job(
+    state: &AppState,
+    principal: &Principal,
+    job_id: Uuid,
+) -> Result<ExportJob, AppError> {
+    let job = load_job(state, job_id).await?;
+    crate::api::insight::authorize_task(state, principal, job.task_id)
+        .await
+        .map_err(|_| AppError::NotFound("Export job not found".to_string()))?;
+    Ok(job)
+}
+
+// ============ Handlers ============
+
+/// Current state of an export job with the outcome of each finished article
+pub async fn get_export_job(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let job = authorize_job(&state, &principal, job_id).await?;
+    let articles: Vec<ExportJobArticle> = sqlx::query_as(
+        "SELECT idx, article_id, title, success, message, images FROM export_job_articles WHERE job_id = $1 ORDER BY idx",
+    )
+    .bind(job_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+    let mut data = job.to_json();
+    data["articles"] = serde_json::json!(articles);
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data
+    })))
+}
+
+/// Past exports of the caller's tasks (all tasks for admins), newest first
+pub async fn list_exports(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<ListExportsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let columns = EXPORT_JOB_COLUMNS
+        .split(", ")
+        .map(|c| format!("j.{}", c))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let jobs = sqlx::query_as::<_, ExportJob>(&format!(
+        r#"
+        SELECT {}
+        FROM export_jobs j
+        JOIN insight_tasks t ON t.id = j.task_id
+        WHERE ($1 OR t.owner_id IS NOT DISTINCT FROM $2)
+          AND ($3::UUID IS NULL OR j.task_id = $3)
+        ORDER BY j.created_at DESC
+        LIMIT $4 OFFSET $5
+        "#,
+        columns
+    ))
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .bind(query.task_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": jobs.iter().map(ExportJob::to_json).collect::<Vec<_>>()
+    })))
+}
+
+/// Download the kept ZIP of an earlier downloaded export
+pub async fn download_export(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<Uuid>,
+) -> Result<axum::response::Response, AppError> {
+    use axum::http::{header, StatusCode};
+
+    let job = authorize_job(&state, &principal, job_id).await?;
+    let path = job
+        .download_url()
+        .and(job.archive_path.as_deref())
+        .ok_or(AppError::NotFound("导出文件不存在或已过期".to_string()))?;
+    let file = tokio::fs::File::open(path).await?;
+    let filename = format!(
+        "{}.zip",
+        job.export_dir
+            .as_deref()
+            .and_then(|dir| StdPath::new(dir).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "export".to_string())
+    );
+    Ok(axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"export.zip\"; filename*=UTF-8''{}",
+                urlencoding::encode(&filename)
+            ),
+        )
+        .body(axum::body::Body::from_stream(
+            tokio_util::io::ReaderStream::new(file),
+        ))
+        .unwrap())
+}
+
+/// SSE stream of export progress. Finished jobs get one progress and one done event.
+pub async fn export_events(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let job = authorize_job(&state, &principal, job_id).await?;
+    let live = JOBS.lock().unwrap().get(&job_id).map(|handle| {
+        let rx = handle.events.subscribe();
+        let snapshot = handle.progress.lock().unwrap().clone();
+        (rx, snapshot)
+    });
+
+    let mut initial = VecDeque::new();
+    let rx = match live {
+        Some((rx, snapshot)) => {
+            initial.push_back(ExportEvent::Progress(snapshot));
+            Some(rx)
+        }
+        None => {
+            let done = (job.succeeded + job.failed) as usize;
+            initial.push_back(ExportEvent::Progress(ExportProgress {
+                total: job.total as usize,
+                done,
+                succeeded: job.succeeded as usize,
+                failed: job.failed as usize,
+                images_downloaded: job.images_downloaded as usize,
+                eta_seconds: None,
+            }));
+            initial.push_back(ExportEvent::Done {
+                // A job missing from memory but still "running" died with the server
+                status: if job.status == "running" {
+                    "failed".to_string()
+                } else {
+                    job.status
+                },
+                message: job.message.unwrap_or_default(),
+                export_dir: job.export_dir,
+            });
+            None
+        }
+    };
+
+    let stream = futures::stream::unfold(
+        (initial, rx, false),
+        |(mut queue, mut rx, finished)| async move {
+            if finished {
+                return None;
+            }
+            let event = match queue.pop_front() {
+                Some(event) => event,
+                None => loop {
+                    match rx.as_mut()?.recv().await {
+                        Ok(event) => break event,
+                        // Slow subscriber: skip ahead, the next progress event catches up
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                },
+            };
+            let finished = matches!(event, ExportEvent::Done { .. });
+            Some((Ok(event.to_sse()), (queue, rx, finished)))
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Cancel a running export; articles already in progress are finished first
+pub async fn cancel_export(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_job(&state, &principal, job_id).await?;
+    let handle = JOBS.lock().unwrap().get(&job_id).cloned();
+    match handle {
+        Some(handle) => {
+            handle.cancel();
+            tracing::info!("[Export] Cancel requested for job {}", job_id);
+            Ok(Json(serde_json::json!({ "success": true })))
+        }
+        None => Err(AppError::BadRequest("导出任务已结束".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_directory() {
+        let workspace = TempWorkspace::create().unwrap();
+        let dir = workspace.path().join("任务_export");
+        std::fs::create_dir_all(dir.join("images")).unwrap();
+        std::fs::write(dir.join("1_标题.md"), "# 标题\n\n![](images/a.png)").unwrap();
+        std::fs::write(dir.join("images").join("a.png"), [0x89, b'P', b'N', b'G']).unwrap();
+
+        let zip_path = workspace.path().join("export.zip");
+        zip_directory(&dir, &zip_path).unwrap();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&zip_path).unwrap()).unwrap();
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "任务_export/1_标题.md",
+                "任务_export/images/",
+                "任务_export/images/a.png"
+            ]
+        );
+        let png = archive.by_name("任务_export/images/a.png").unwrap();
+        assert_eq!(png.compression(), zip::CompressionMethod::Stored);
+        drop(png);
+
+        let path = workspace.path().to_path_buf();
+        drop(workspace);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_job_summary() {
+        let workspace = TempWorkspace::create().unwrap();
+        let archive = workspace.path().join("export.zip");
+        std::fs::write(&archive, "zip").unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let mut job = ExportJob {
+            id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            format: "markdown".to_string(),
+            target_dir: String::new(),
+            export_dir: None,
+            status: "completed".to_string(),
+            total: 2,
+            succeeded: 2,
+            failed: 0,
+            images_downloaded: 3,
+            message: None,
+            created_at: now - 90,
+            updated_at: now,
+            finished_at: Some(now),
+            params: serde_json::json!({ "format": "markdown" }),
+            archive_path: Some(archive.to_string_lossy().to_string()),
+            archive_expires_at: Some(now + 3600),
+        };
+        let summary = job.to_json();
+        assert_eq!(summary["duration_secs"], 90);
+        assert_eq!(
+            summary["download_url"],
+            format!("/api/insight/export/{}/download", Uuid::nil())
+        );
+        assert!(summary.get("archive_path").is_none());
+
+        job.archive_expires_at = Some(now - 1);
+        assert!(job.download_url().is_none());
+        job.archive_expires_at = Some(now + 3600);
+        std::fs::remove_file(&archive).unwrap();
+        assert!(job.download_url().is_none());
+    }
+}
@@ -0,0 +1,170 @@
+//! Export manifests
+//!
+//! Every export to a server directory in a per-article format records, per
+//! task, target directory and format, the folder it wrote and for each article
+//! its file and a hash of what went into it: title, link, account, date,
+//! insight and the readable text of the page. An export with `incremental:
+//! true` writes into the same folder and only processes articles that are new,
+//! changed or whose file has gone missing; the rest are left in place.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api::insight::InsightArticle;
+
+/// Formats that write one file per article, the only ones exported incrementally
+pub const FORMATS: [&str; 4] = ["markdown", "pdf", "docx", "obsidian"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub hash: String,
+    /// Path relative to the export folder
+    pub file: String,
+    pub exported_at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub export_dir: String,
+    pub entries: HashMap<Uuid, ManifestEntry>,
+}
+
+impl Manifest {
+    /// Entry of an article whose file is still there and whose hash matches
+    pub fn unchanged(&self, article_id: Uuid, hash: &str) -> Option<&ManifestEntry> {
+        self.entries.get(&article_id).filter(|entry| {
+            entry.hash == hash
+                && std::path::Path::new(&self.export_dir)
+                    .join(&entry.file)
+                    .is_file()
+        })
+    }
+}
+
+/// Hash of an article's exported content; `html` is the page it was built from
+pub fn article_hash(article: &InsightArticle, html: &str) -> String {
+    let input = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        article.title,
+        article.url,
+        article.account_name.as_deref().unwrap_or(""),
+        article.publish_time.unwrap_or(0),
+        article.insight.as_deref().unwrap_or(""),
+        crate::repository::articles::content_hash(html)
+    );
+    format!("{:x}", md5::compute(input.as_bytes()))
+}
+
+/// Manifest of the last export of `task_id` to `target_dir` in `format`
+pub async fn load(
+    pool: &PgPool,
+    task_id: Uuid,
+    target_dir: &str,
+    format: &str,
+) -> Result<Option<Manifest>, sqlx::Error> {
+    let row: Option<(String, serde_json::Value)> = sqlx::query_as(
+        "SELECT export_dir, entries FROM export_manifests WHERE task_id = $1 AND target_dir = $2 AND format = $3",
+    )
+    .bind(task_id)
+    .bind(target_dir)
+    .bind(format)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(export_dir, entries)| Manifest {
+        export_dir,
+        entries: serde_json::from_value(entries).unwrap_or_default(),
+    }))
+}
+
+/// Replace the manifest of `task_id` / `target_dir` / `format`
+pub async fn save(
+    pool: &PgPool,
+    task_id: Uuid,
+    target_dir: &str,
+    format: &str,
+    manifest: &Manifest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO export_manifests (task_id, target_dir, format, export_dir, entries, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (task_id, target_dir, format) DO UPDATE
+        SET export_dir = EXCLUDED.export_dir, entries = EXCLUDED.entries, updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(task_id)
+    .bind(target_dir)
+    .bind(format)
+    .bind(&manifest.export_dir)
+    .bind(serde_json::to_value(&manifest.entries).unwrap_or_default())
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let mut article = InsightArticle {
+            id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            title: "标题".to_string(),
+            url: "https://mp.weixin.qq.com/s/abc".to_string(),
+            account_name: Some("科技观察".to_string()),
+            account_fakeid: None,
+            publish_time: Some(1_700_000_000),
+            similarity: Some(0.8),
+            insight: Some("洞察".to_string()),
+            relevance_score: None,
+            created_at: 0,
+            chunk_similarity: None,
+            article_id: None,
+            duplicates_of: None,
+            feedback: None,
+            status: None,
+            status_checked_at: None,
+            author: None,
+            is_original: None,
+            biz: None,
+            tags: Vec::new(),
+        };
+        let html = r#"<div id="js_content"><p>正文</p></div>"#;
+        let hash = article_hash(&article, html);
+        // Markup churn alone is not a change
+        assert_eq!(
+            hash,
+            article_hash(
+                &article,
+                r#"<div id="js_content"><p>正文</p></div><script>x()</script>"#
+            )
+        );
+        article.insight = Some("新的洞察".to_string());
+        assert_ne!(hash, article_hash(&article, html));
+
+        let dir = std::env::temp_dir().join(format!("manifest-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1_标题.md"), "x").unwrap();
+        let entry = |file: &str| ManifestEntry {
+            hash: hash.clone(),
+            file: file.to_string(),
+            exported_at: 0,
+        };
+        let (kept, gone) = (Uuid::new_v4(), Uuid::new_v4());
+        let manifest = Manifest {
+            export_dir: dir.to_string_lossy().to_string(),
+            entries: HashMap::from([(kept, entry("1_标题.md")), (gone, entry("2_删除.md"))]),
+        };
+        assert!(manifest.unchanged(kept, &hash).is_some());
+        assert!(manifest.unchanged(kept, "other").is_none());
+        // The file was removed since
+        assert!(manifest.unchanged(gone, &hash).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+//! Article feedback
+//!
+//! Users accept or reject matched articles. Rejected articles keep their row,
+//! so their URLs stay excluded from later runs of the task, but no longer count
+//! toward its target. With `feedback_tuning` on, later runs also move the
+//! prompt embedding toward accepted and away from rejected articles (Rocchio)
+//! before scoring candidates.
+
+use axum::{extract::State, Extension, Json};
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+/// Rocchio weights of the original query, accepted and rejected centroids
+const ROCCHIO_ALPHA: f32 = 1.0;
+const ROCCHIO_BETA: f32 = 0.75;
+const ROCCHIO_GAMMA: f32 = 0.15;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackRequest {
+    /// `insight_articles.id`
+    pub id: Uuid,
+    pub note: Option<String>,
+}
+
+fn centroid<'a>(vectors: impl Iterator<Item = &'a Vec<f32>>, dim: usize) -> Option<Vec<f32>> {
+    let mut sum = vec![0f32; dim];
+    let mut n = 0;
+    for v in vectors.filter(|v| v.len() == dim) {
+        sum.iter_mut().zip(v).for_each(|(s, x)| *s += x);
+        n += 1;
+    }
+    if n == 0 {
+        return None;
+    }
+    sum.iter_mut().for_each(|s| *s /= n as f32);
+    Some(sum)
+}
+
+/// Rocchio update of `query`, L2-normalized. Vectors of another dimension are ignored.
+pub fn rocchio(query: &[f32], accepted: &[Vec<f32>], rejected: &[Vec<f32>]) -> Vec<f32> {
+    let dim = query.len();
+    let mut tuned: Vec<f32> = query.iter().map(|x| x * ROCCHIO_ALPHA).collect();
+    if let Some(c) = centroid(accepted.iter(), dim) {
+        tuned
+            .iter_mut()
+            .zip(&c)
+            .for_each(|(t, x)| *t += ROCCHIO_BETA * x);
+    }
+    if let Some(c) = centroid(rejected.iter(), dim) {
+        tuned
+            .iter_mut()
+            .zip(&c)
+            .for_each(|(t, x)| *t -= ROCCHIO_GAMMA * x);
+    }
+    let norm = tuned.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        tuned.iter_mut().for_each(|x| *x /= norm);
+    }
+    tuned
+}
+
+/// Prompt embedding tuned by the task's feedback; unchanged without any
+pub async fn tuned_embedding(
+    pool: &PgPool,
+    task_id: Uuid,
+    prompt_embedding: &[f32],
+) -> Result<Vec<f32>, sqlx::Error> {
+    let rows: Vec<(String, Vec<f32>)> = sqlx::query_as(
+        "SELECT feedback, dedup_embedding FROM insight_articles WHERE task_id = $1 AND feedback IS NOT NULL AND dedup_embedding IS NOT NULL",
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Ok(prompt_embedding.to_vec());
+    }
+
+    let (accepted, rejected): (Vec<_>, Vec<_>) = rows
+        .into_iter()
+        .partition(|(feedback, _)| feedback == "accepted");
+    let accepted: Vec<Vec<f32>> = accepted.into_iter().map(|(_, v)| v).collect();
+    let rejected: Vec<Vec<f32>> = rejected.into_iter().map(|(_, v)| v).collect();
+    Ok(rocchio(prompt_embedding, &accepted, &rejected))
+}
+
+async fn record(
+    state: &AppState,
+    principal: &Principal,
+    req: &FeedbackRequest,
+    feedback: &str,
+) -> Result<(), AppError> {
+    let task_id: Option<Uuid> =
+        sqlx::query_scalar("SELECT task_id FROM insight_articles WHERE id = $1")
+            .bind(req.id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    let task_id = task_id.ok_or(AppError::NotFound("Article not found".to_string()))?;
+    crate::api::insight::authorize_task(state, principal, task_id)
+        .await
+        .map_err(|_| AppError::NotFound("Article not found".to_string()))?;
+
+    sqlx::query(
+        "UPDATE insight_articles SET feedback = $1, feedback_note = $2, feedback_at = $3 WHERE id = $4",
+    )
+    .bind(feedback)
+    .bind(&req.note)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    // Rejected articles don't count toward the target
+    sqlx::query(
+        "UPDATE insight_tasks SET processed_count = (SELECT COUNT(*) FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected') WHERE id = $1",
+    )
+    .bind(task_id)
+    .execute(&state.db_pool)
+    .await?;
+    Ok(())
+}
+
+/// Mark a matched article as relevant
+pub async fn accept_article(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<FeedbackRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    record(&state, &principal, &req, "accepted").await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Mark a matched article as irrelevant
+pub async fn reject_article(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<FeedbackRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    record(&state, &principal, &req, "rejected").await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rocchio() {
+        let query = vec![1.0, 0.0, 0.0];
+        assert_eq!(rocchio(&query, &[], &[]), query);
+
+        let tuned = rocchio(
+            &query,
+            &[vec![0.0, 1.0, 0.0]],
+            &[vec![0.0, 0.0, 1.0], vec![1.0, 1.0]],
+        );
+        assert!(tuned[1] > 0.0);
+        assert!(tuned[2] < 0.0);
+        let norm: f32 = tuned.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,272 @@
+//! RSS feeds
+//!
+//! `GET /api/feeds/task/:id.xml` lists a task's matched articles with their
+//! insights as descriptions, `GET /api/feeds/account/:fakeid.xml` the synced
+//! articles of a monitored account with their digests. Newest first, up to
+//! `?limit=` items. RSS readers rarely let users set headers, so these paths
+//! also take the API token as `?token=` (see `auth`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::FixedOffset;
+use html_escape::encode_text;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::insight::{authorize_task, InsightArticle, InsightTask};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedQuery {
+    /// Items in the feed, default 50, at most 200
+    pub limit: Option<i64>,
+}
+
+struct Channel {
+    title: String,
+    description: String,
+    /// Absolute URL of the feed itself
+    url: String,
+    updated: i64,
+}
+
+struct Item {
+    title: String,
+    link: String,
+    /// HTML, escaped once more when written into the XML
+    description: Option<String>,
+    author: Option<String>,
+    published: i64,
+}
+
+/// `<id>.xml` → `<id>`
+fn feed_key(file: &str) -> Option<&str> {
+    file.strip_suffix(".xml").filter(|key| !key.is_empty())
+}
+
+/// Origin the feed is served from: `PUBLIC_BASE_URL`, else the request's host
+fn base_url(headers: &HeaderMap) -> String {
+    if let Ok(base) = std::env::var("PUBLIC_BASE_URL") {
+        if !base.is_empty() {
+            return base.trim_end_matches('/').to_string();
+        }
+    }
+    let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let scheme = value("x-forwarded-proto").unwrap_or("http");
+    let host = value("x-forwarded-host")
+        .or_else(|| value(header::HOST.as_str()))
+        .unwrap_or("localhost");
+    format!("{}://{}", scheme, host)
+}
+
+/// RFC 822 date in China time, as WeChat publishes
+fn rfc822(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+        .to_rfc2822()
+}
+
+/// Plain text as an HTML description, keeping its line breaks
+fn text_html(text: &str) -> String {
+    encode_text(text.trim()).replace('\n', "<br>")
+}
+
+fn render(channel: &Channel, items: &[Item]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><channel>",
+    );
+    xml.push_str(&format!(
+        "<title>{}</title><link>{}</link><description>{}</description><language>zh-CN</language><lastBuildDate>{}</lastBuildDate><atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+        encode_text(&channel.title),
+        encode_text(&channel.url),
+        encode_text(&channel.description),
+        rfc822(channel.updated),
+        html_escape::encode_double_quoted_attribute(&channel.url)
+    ));
+    for item in items {
+        xml.push_str(&format!(
+            "<item><title>{}</title><link>{}</link><guid isPermaLink=\"true\">{}</guid><pubDate>{}</pubDate>",
+            encode_text(&item.title),
+            encode_text(&item.link),
+            encode_text(&item.link),
+            rfc822(item.published)
+        ));
+        if let Some(author) = &item.author {
+            xml.push_str(&format!("<dc:creator>{}</dc:creator>", encode_text(author)));
+        }
+        if let Some(description) = &item.description {
+            xml.push_str(&format!(
+                "<description>{}</description>",
+                encode_text(description)
+            ));
+        }
+        xml.push_str("</item>");
+    }
+    xml.push_str("</channel></rss>");
+    xml
+}
+
+fn rss_response(xml: String) -> Response {
+    (
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        xml,
+    )
+        .into_response()
+}
+
+fn limit(query: &FeedQuery) -> i64 {
+    query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+// ============ Handlers ============
+
+/// A task's matched articles, insights as descriptions
+pub async fn task_feed(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, AppError> {
+    let id = feed_key(&file)
+        .and_then(|key| Uuid::parse_str(key).ok())
+        .ok_or(AppError::NotFound("Feed not found".to_string()))?;
+    authorize_task(&state, &principal, id).await?;
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected' ORDER BY COALESCE(NULLIF(publish_time, 0), created_at) DESC LIMIT $2",
+    )
+    .bind(id)
+    .bind(limit(&query))
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let channel = Channel {
+        title: format!("洞察：{}", task.prompt),
+        description: task.prompt.clone(),
+        url: format!("{}/api/feeds/task/{}.xml", base_url(&headers), id),
+        updated: task.updated_at,
+    };
+    let items: Vec<Item> = articles
+        .into_iter()
+        .map(|a| Item {
+            description: a
+                .insight
+                .as_deref()
+                .filter(|i| !i.trim().is_empty())
+                .map(text_html),
+            published: a.publish_time.filter(|ts| *ts > 0).unwrap_or(a.created_at),
+            title: a.title,
+            link: a.url,
+            author: a.account_name,
+        })
+        .collect();
+    Ok(rss_response(render(&channel, &items)))
+}
+
+/// An account's synced articles, digests as descriptions
+pub async fn account_feed(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+    Path(file): Path<String>,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, AppError> {
+    let fakeid = feed_key(&file).ok_or(AppError::NotFound("Feed not found".to_string()))?;
+    let account: Option<(Option<String>, Option<String>, Option<Uuid>)> =
+        sqlx::query_as("SELECT nickname, signature, owner_id FROM accounts WHERE fakeid = $1")
+            .bind(fakeid)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    let (nickname, signature, _) = account
+        .filter(|(_, _, owner)| principal.can_access(*owner))
+        .ok_or(AppError::NotFound("Account not found".to_string()))?;
+
+    let rows: Vec<(String, String, i64, Option<String>)> = sqlx::query_as(
+        "SELECT title, link, create_time, digest FROM articles WHERE fakeid = $1 AND is_deleted = false ORDER BY create_time DESC, itemidx LIMIT $2",
+    )
+    .bind(fakeid)
+    .bind(limit(&query))
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let nickname = nickname.unwrap_or_else(|| fakeid.to_string());
+    let channel = Channel {
+        title: nickname.clone(),
+        description: signature
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| nickname.clone()),
+        url: format!(
+            "{}/api/feeds/account/{}.xml",
+            base_url(&headers),
+            urlencoding::encode(fakeid)
+        ),
+        updated: rows
+            .first()
+            .map(|(_, _, ts, _)| *ts)
+            .unwrap_or_else(|| chrono::Utc::now().timestamp()),
+    };
+    let items: Vec<Item> = rows
+        .into_iter()
+        .map(|(title, link, create_time, digest)| Item {
+            title,
+            link,
+            description: digest
+                .filter(|d| !d.trim().is_empty())
+                .map(|d| text_html(&d)),
+            author: Some(nickname.clone()),
+            published: create_time,
+        })
+        .collect();
+    Ok(rss_response(render(&channel, &items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(feed_key("abc.xml"), Some("abc"));
+        assert_eq!(feed_key(".xml"), None);
+        assert_eq!(feed_key("abc"), None);
+
+        let channel = Channel {
+            title: "洞察：AI & 芯片".to_string(),
+            description: "AI & 芯片".to_string(),
+            url: "https://insight.example.com/api/feeds/task/1.xml".to_string(),
+            updated: 1_700_000_000,
+        };
+        let items = vec![Item {
+            title: "<标题>".to_string(),
+            link: "https://mp.weixin.qq.com/s?__biz=x&mid=1".to_string(),
+            description: Some(text_html("要点一\n要点 <二>")),
+            author: Some("科技观察".to_string()),
+            published: 1_700_000_000,
+        }];
+        let xml = render(&channel, &items);
+        assert!(xml.contains("<title>洞察：AI &amp; 芯片</title>"));
+        assert!(xml.contains("<title>&lt;标题&gt;</title>"));
+        assert!(xml.contains("<link>https://mp.weixin.qq.com/s?__biz=x&amp;mid=1</link>"));
+        assert!(xml.contains("<pubDate>Wed, 15 Nov 2023 06:13:20 +0800</pubDate>"));
+        assert!(xml.contains("<dc:creator>科技观察</dc:creator>"));
+        // HTML escaped once for the description, once more for the XML
+        assert!(xml.contains("<description>要点一&lt;br&gt;要点 &amp;lt;二&amp;gt;</description>"));
+        assert!(xml.ends_with("</item></channel></rss>"));
+    }
+}
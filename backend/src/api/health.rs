@@ -0,0 +1,178 @@
+//! Readiness check
+//!
+//! `/health` only says the process is up. `/health/ready` checks what the
+//! service depends on: Postgres and the pgvector extension are required and
+//! make the endpoint answer 503 when they fail; Ollama (only when
+//! `OLLAMA_BASE_URL` is set) and the PDF engine are optional and only mark the
+//! result `degraded`.
+
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::Serialize;
+
+use crate::AppState;
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    /// "ok", "error" or "skipped"
+    pub status: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub latency_ms: u64,
+}
+
+impl Check {
+    fn new(required: bool, started: Instant, result: Result<Option<String>, String>) -> Self {
+        let (status, detail) = match result {
+            Ok(detail) => ("ok", detail),
+            Err(e) => ("error", Some(e)),
+        };
+        Check {
+            status,
+            required,
+            detail,
+            latency_ms: started.elapsed().as_millis() as u64,
+        }
+    }
+
+    fn skipped(detail: &str) -> Self {
+        Check {
+            status: "skipped",
+            required: false,
+            detail: Some(detail.to_string()),
+            latency_ms: 0,
+        }
+    }
+
+    fn failed(&self) -> bool {
+        self.status == "error"
+    }
+}
+
+async fn timed<F, T>(future: F) -> Result<T, String>
+where
+    F: std::future::Future<Output = Result<T, String>>,
+{
+    tokio::time::timeout(CHECK_TIMEOUT, future)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())))
+}
+
+async fn check_postgres(state: &AppState) -> Check {
+    let started = Instant::now();
+    let result = timed(async {
+        sqlx::query_scalar::<_, i32>("SELECT 1")
+            .fetch_one(&state.db_pool)
+            .await
+            .map(|_| None)
+            .map_err(|e| e.to_string())
+    })
+    .await;
+    Check::new(true, started, result)
+}
+
+async fn check_pgvector(state: &AppState) -> Check {
+    let started = Instant::now();
+    let result = timed(async {
+        let version: Option<String> =
+            sqlx::query_scalar("SELECT extversion FROM pg_extension WHERE extname = 'vector'")
+                .fetch_optional(&state.db_pool)
+                .await
+                .map_err(|e| e.to_string())?;
+        version
+            .map(|v| Some(format!("version {}", v)))
+            .ok_or_else(|| "extension not installed".to_string())
+    })
+    .await;
+    Check::new(true, started, result)
+}
+
+async fn check_ollama() -> Check {
+    let started = Instant::now();
+    match crate::llm::ollama::ping().await {
+        None => Check::skipped("OLLAMA_BASE_URL not set"),
+        Some(result) => Check::new(
+            false,
+            started,
+            result.map(|_| None).map_err(|e| e.to_string()),
+        ),
+    }
+}
+
+fn check_pdf() -> Check {
+    let started = Instant::now();
+    let result = crate::api::pdf_engine::engine()
+        .map(|engine| {
+            let prince = if crate::api::pdf_engine::find_prince().is_some() {
+                "found"
+            } else {
+                "not found"
+            };
+            Some(format!("{} engine (Prince {})", engine.name(), prince))
+        })
+        .map_err(|e| e.to_string());
+    Check::new(false, started, result)
+}
+
+/// Overall status: unavailable (503) if a required check failed, degraded if
+/// an optional one did
+fn overall<'a>(checks: impl Iterator<Item = &'a Check> + Clone) -> (&'static str, StatusCode) {
+    if checks.clone().any(|c| c.required && c.failed()) {
+        ("unavailable", StatusCode::SERVICE_UNAVAILABLE)
+    } else if checks.clone().any(|c| c.failed()) {
+        ("degraded", StatusCode::OK)
+    } else {
+        ("ready", StatusCode::OK)
+    }
+}
+
+/// Per-dependency status; 503 when a required dependency fails
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let (postgres, pgvector, ollama) = tokio::join!(
+        check_postgres(&state),
+        check_pgvector(&state),
+        check_ollama()
+    );
+    let checks = [
+        ("postgres", postgres),
+        ("pgvector", pgvector),
+        ("ollama", ollama),
+        ("pdf", check_pdf()),
+    ];
+
+    let (status, code) = overall(checks.iter().map(|(_, c)| c));
+    let checks: serde_json::Map<String, serde_json::Value> = checks
+        .into_iter()
+        .map(|(name, check)| (name.to_string(), serde_json::json!(check)))
+        .collect();
+
+    (
+        code,
+        Json(serde_json::json!({ "status": status, "checks": checks })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overall_status() {
+        let ok = |required| Check::new(required, Instant::now(), Ok(None));
+        let failed = |required| Check::new(required, Instant::now(), Err("down".to_string()));
+
+        let checks = [ok(true), Check::skipped("not configured")];
+        assert_eq!(overall(checks.iter()), ("ready", StatusCode::OK));
+        let checks = [ok(true), failed(false)];
+        assert_eq!(overall(checks.iter()), ("degraded", StatusCode::OK));
+        let checks = [failed(true), ok(false)];
+        assert_eq!(
+            overall(checks.iter()),
+            ("unavailable", StatusCode::SERVICE_UNAVAILABLE)
+        );
+    }
+}
@@ -1,18 +1,34 @@
 use axum::{
-    extract::{Path, State},
-    Json,
+    extract::{Path, Query, State},
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
 
 use uuid::Uuid;
 
+use crate::auth::Principal;
+use crate::content::extract::PageMetadata;
+use crate::crawl::{self, Priority};
+use crate::download::DownloadScheduler;
 use crate::error::AppError;
+use crate::gateway::{Pick, ProxySpec};
+use crate::llm::cache::CachedEmbedding;
+use crate::llm::usage::{MeteredChat, MeteredEmbedding, UsageMeter};
+use crate::llm::{ChatProvider, ChatRequest, EmbeddingProvider, ProviderConfig};
+use crate::repository::{self, articles::Freshness};
+use crate::wechat::client::{MpClient, MpError, WECHAT_USER_AGENT};
 use crate::AppState;
 
+use lazy_static::lazy_static;
 use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
 
-const WECHAT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+lazy_static! {
+    /// Configs of tasks paused for keyword review, so API keys given at
+    /// creation survive the pause (lost on restart, see `update_keywords`)
+    static ref AWAITING_REVIEW: Mutex<HashMap<Uuid, TaskConfig>> = Mutex::new(HashMap::new());
+}
 
 // ============ Types ============
 
@@ -27,6 +43,22 @@ pub struct InsightTask {
     pub created_at: i64,
     pub updated_at: i64,
     pub completion_reason: Option<String>,
+    pub schedule_id: Option<Uuid>,
+    /// Executive summary (see `api::summary`), once generated
+    pub summary: Option<serde_json::Value>,
+    /// 1-based place in the task queue while waiting to start (`list_tasks` only)
+    #[sqlx(default)]
+    pub queue_position: Option<i64>,
+    /// Language tag of the insights; `None` for older tasks (zh-CN)
+    pub output_language: Option<String>,
+}
+
+impl InsightTask {
+    pub fn output_language(&self) -> &str {
+        self.output_language
+            .as_deref()
+            .unwrap_or(crate::llm::DEFAULT_OUTPUT_LANGUAGE)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
@@ -42,6 +74,38 @@ pub struct InsightArticle {
     pub insight: Option<String>,
     pub relevance_score: Option<f64>,
     pub created_at: i64,
+    /// Best chunk-level similarity of the full text (deep scan only)
+    pub chunk_similarity: Option<f64>,
+    /// `fakeid:aid` key of the stored comments (comment collection only)
+    pub article_id: Option<String>,
+    /// Earlier article of the task this one repeats (dedup "link" mode)
+    pub duplicates_of: Option<Uuid>,
+    /// User feedback: "accepted" or "rejected"
+    pub feedback: Option<String>,
+    /// Latest online availability check (see `link_status`), e.g. "ok" or "deleted"
+    pub status: Option<String>,
+    pub status_checked_at: Option<i64>,
+    /// Byline parsed from the page (see `content::extract::metadata`)
+    pub author: Option<String>,
+    /// Declared 原创 by the account
+    pub is_original: Option<bool>,
+    /// `__biz` of the publishing account
+    pub biz: Option<String>,
+    /// User tags (see `annotations`)
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl InsightArticle {
+    /// Fill in what the row does not record yet from a freshly fetched page
+    pub fn apply_page_metadata(&mut self, meta: PageMetadata) {
+        self.author = self.author.take().or(meta.author);
+        self.is_original = self.is_original.or(meta.original);
+        self.biz = self.biz.take().or(meta.biz);
+        if self.publish_time.is_none_or(|ts| ts <= 0) {
+            self.publish_time = meta.publish_time.or(self.publish_time);
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,18 +117,188 @@ pub struct CreateTaskRequest {
     pub specific_account_fakeid: Option<String>,
     pub specific_account_name: Option<String>,
     // LLM Provider Configuration
-    pub keyword_provider: Option<String>, // "gemini" or "deepseek"
-    pub reasoning_provider: Option<String>, // "gemini" or "deepseek"
-    pub embedding_provider: Option<String>, // "gemini" or "ollama"
+    pub keyword_provider: Option<String>, // "gemini", "deepseek", "ollama" or "openai_compatible"
+    pub reasoning_provider: Option<String>, // "gemini", "deepseek", "ollama" or "openai_compatible"
+    pub embedding_provider: Option<String>, // "gemini", "ollama" or "openai_compatible"
     pub ollama_base_url: Option<String>,
     pub ollama_embedding_model: Option<String>,
+    pub ollama_chat_model: Option<String>,
+    // OpenAI-compatible endpoint; unset fields fall back to OPENAI_COMPATIBLE_* env vars
+    pub openai_compatible_base_url: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+    pub openai_compatible_model: Option<String>,
+    pub openai_compatible_embedding_model: Option<String>,
     // Search Speed: "high" (0.5s), "medium" (1-2s), "low" (2-3s)
     pub search_speed: Option<String>,
+    // Deep scan: score the full article text instead of just title + digest
+    pub deep_scan: Option<bool>,
+    // Collect comments of matched articles into `comments`
+    pub fetch_comments: Option<bool>,
+    // Include top comments in the insight prompt (default true when collecting)
+    pub comments_in_prompt: Option<bool>,
+    // Duplicate handling: "skip" (default), "link" or "off"
+    pub dedup: Option<String>,
+    // Embedding cosine above which an article duplicates a kept one (default DEDUP_SIMILARITY_THRESHOLD)
+    pub dedup_threshold: Option<f64>,
+    // Queue priority, higher starts first (default 0)
+    pub priority: Option<i32>,
+    // Tune the prompt embedding with accepted/rejected articles on later runs
+    pub feedback_tuning: Option<bool>,
+    // Account lists (discovery only): skip the stored blocklist, block extra fakeids,
+    // restrict to the stored allowlist, or to an explicit set of fakeids
+    pub ignore_blocklist: Option<bool>,
+    pub blocked_fakeids: Option<Vec<String>>,
+    pub allowlist_only: Option<bool>,
+    pub allowed_fakeids: Option<Vec<String>>,
+    // Pause in `awaiting_review` after keyword generation until the keywords are approved
+    pub review_keywords: Option<bool>,
+    // Publish time window, unix seconds (before exclusive); account history is paged
+    // back to `published_after` and articles outside the window are skipped
+    pub published_after: Option<i64>,
+    pub published_before: Option<i64>,
+    // History pages read per account (default 1, or MAX_PAGES_PER_ACCOUNT with a
+    // publish window); paging stops early once a page has no similar article
+    pub max_pages_per_account: Option<u32>,
+    // Queue the other articles of the albums (合集) that highly similar kept articles belong to
+    pub expand_albums: Option<bool>,
+    // Candidate sources: "accounts" (default, discover accounts and read their history),
+    // "articles" (keyword search inside monitored accounts) or "both"
+    pub search_mode: Option<String>,
+    // Language tag of insights and summaries, e.g. "zh-CN" (default) or "en"
+    pub output_language: Option<String>,
+    // Accounts scanned at once (default 3, at most 8)
+    pub account_parallelism: Option<u32>,
+    // Lexical checks on title and digest before an article is embedded or judged:
+    // {"must_include": [...], "must_exclude": [...], "title_regex": "..."}
+    pub prefilter: Option<crate::prefilter::Rules>,
+    // Embed each page up front and judge its most similar articles first
+    pub rank_candidates: Option<bool>,
+    // Record scanned articles that were not kept in `insight_candidates`
+    pub debug_candidates: Option<bool>,
+}
+
+/// Most prompts accepted by one `create_batch` call
+const MAX_BATCH_PROMPTS: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBatchRequest {
+    pub prompts: Vec<String>,
+    /// `CreateTaskRequest` fields shared by every task (providers, keys, priority, ...)
+    #[serde(flatten)]
+    pub settings: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct CreateTaskResponse {
     pub id: Uuid,
+    /// Set when the WeChat session may expire before the task finishes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResumeTaskRequest {
+    pub id: Uuid,
+    // API keys are not persisted with the task, so they must be supplied again
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+}
+
+/// Worker configuration of a task, persisted in `insight_task_state` so it can be resumed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskConfig {
+    pub prompt: String,
+    pub target_count: i32,
+    #[serde(skip)]
+    pub deepseek_key: Option<String>,
+    #[serde(skip)]
+    pub gemini_key: Option<String>,
+    pub specific_fakeid: Option<String>,
+    pub specific_name: Option<String>,
+    pub keyword_provider: String,
+    pub reasoning_provider: String,
+    pub embedding_provider: String,
+    pub ollama_base_url: Option<String>,
+    pub ollama_embedding_model: Option<String>,
+    #[serde(default)]
+    pub ollama_chat_model: Option<String>,
+    #[serde(skip)]
+    pub openai_compatible_key: Option<String>,
+    #[serde(default)]
+    pub openai_compatible_base_url: Option<String>,
+    #[serde(default)]
+    pub openai_compatible_model: Option<String>,
+    #[serde(default)]
+    pub openai_compatible_embedding_model: Option<String>,
+    pub search_speed: String,
+    #[serde(default)]
+    pub deep_scan: bool,
+    #[serde(default)]
+    pub fetch_comments: bool,
+    #[serde(default)]
+    pub comments_in_prompt: bool,
+    /// `None` for tasks created before dedup existed, which behave as "skip"
+    #[serde(default)]
+    pub dedup: Option<String>,
+    #[serde(default)]
+    pub dedup_threshold: Option<f64>,
+    #[serde(default)]
+    pub feedback_tuning: bool,
+    #[serde(default)]
+    pub ignore_blocklist: bool,
+    #[serde(default)]
+    pub blocked_fakeids: Vec<String>,
+    #[serde(default)]
+    pub allowlist_only: bool,
+    #[serde(default)]
+    pub allowed_fakeids: Option<Vec<String>>,
+    /// User who created the task; its worker uses that user's MP session
+    #[serde(default)]
+    pub owner_id: Option<Uuid>,
+    /// Stop in `awaiting_review` once keywords are generated (discovery only)
+    #[serde(default)]
+    pub review_keywords: bool,
+    #[serde(default)]
+    pub published_after: Option<i64>,
+    #[serde(default)]
+    pub published_before: Option<i64>,
+    #[serde(default)]
+    pub max_pages_per_account: Option<u32>,
+    #[serde(default)]
+    pub expand_albums: bool,
+    /// `None` behaves as "accounts"
+    #[serde(default)]
+    pub search_mode: Option<String>,
+    #[serde(default)]
+    pub output_language: Option<String>,
+    #[serde(default)]
+    pub account_parallelism: Option<u32>,
+    #[serde(default)]
+    pub prefilter: Option<crate::prefilter::Rules>,
+    #[serde(default)]
+    pub rank_candidates: bool,
+    #[serde(default)]
+    pub debug_candidates: bool,
+}
+
+/// Worker position, saved after each keyword search and each scanned account
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TaskCheckpoint {
+    /// Keywords whose account search has finished
+    keywords_done: Vec<String>,
+    /// Accounts discovered so far (deduplicated)
+    accounts: Vec<AccountInfo>,
+    /// True once every keyword has been searched
+    discovery_done: bool,
+    /// fakeids whose articles have been fully scored
+    accounts_scanned: Vec<String>,
+    /// Article URLs already considered
+    seen_urls: Vec<String>,
+    scanned_count: i32,
+    /// Albums whose articles have been queued (album expansion only)
+    #[serde(default)]
+    albums_expanded: Vec<String>,
 }
 
 // ============ Handlers ============
@@ -75,23 +309,104 @@ use std::path::{Path as StdPath, PathBuf};
 #[derive(Debug, Deserialize)]
 pub struct ExportTaskRequest {
     pub task_id: Uuid,
+    /// Server-side directory to write into; not used by `/api/insight/export/download`
+    #[serde(default)]
     pub target_dir: String,
-    pub format: String, // "markdown" or "pdf"
-    pub proxies: Option<Vec<String>>,
+    pub format: String, // "markdown", "pdf" (one file per article), "report" (single merged PDF), "docx", "obsidian" (vault), or "csv" / "xlsx" (article table only)
+    pub proxies: Option<Vec<ProxySpec>>,
     pub authorization: Option<String>,
+    /// File name template for markdown / PDF / DOCX, see `naming` (default `{index}_{title}`)
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// Put markdown / PDF / DOCX files in one folder per account
+    #[serde(default)]
+    pub group_by_account: bool,
+    /// Export only these articles (`insight_articles.id`)
+    pub article_ids: Option<Vec<Uuid>>,
+    /// Export only articles carrying all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Export only articles at least this similar to the prompt
+    pub min_similarity: Option<f64>,
+    /// Continue the last export to `target_dir` in this format, processing
+    /// only new or changed articles (see `export_manifest`)
+    #[serde(default)]
+    pub incremental: bool,
+    /// Page style of PDF and report exports
+    #[serde(default)]
+    pub pdf: crate::api::pdf::PdfOptions,
+    /// How markdown exports reference images
+    #[serde(default)]
+    pub image_mode: ImageMode,
+}
+
+/// Image references of markdown exports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageMode {
+    /// `file://` path of the downloaded copy
+    #[default]
+    Absolute,
+    /// `images/<file>` next to the markdown file
+    Relative,
+    /// Inlined as a `data:` URI, nothing written to `images/`
+    Base64,
+    /// The original WeChat URL, nothing downloaded
+    Remote,
+}
+
+/// `data:` URI of an image file
+fn data_uri(path: &StdPath) -> Option<String> {
+    use base64::Engine;
+
+    let data = std::fs::read(path).ok()?;
+    // Same sniffing as `process_html_images`; the extension is only a guess from the URL
+    let mime = if data.starts_with(&[0x89, 0x50, 0x4e, 0x47]) {
+        "image/png"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.len() > 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    };
+    Some(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+/// `images/<file>` for the `file://` path of a downloaded image
+fn relative_image_src(src: &str) -> Option<String> {
+    let file = src.strip_prefix("file://")?.rsplit('/').next()?;
+    (!file.is_empty()).then(|| format!("images/{}", file))
 }
 
 #[derive(Debug, Serialize)]
 pub struct ExportTaskResponse {
     pub success: bool,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<Uuid>,
 }
 
-pub async fn export_task(
-    State(state): State<AppState>,
-    Json(req): Json<ExportTaskRequest>,
-) -> Result<Json<ExportTaskResponse>, AppError> {
-    // 1. Fetch Task and Articles
+/// Authorize an export request and load its task and the articles it selects
+async fn load_export(
+    state: &AppState,
+    principal: &Principal,
+    req: &ExportTaskRequest,
+) -> Result<(InsightTask, Vec<InsightArticle>), AppError> {
+    authorize_task(state, principal, req.task_id).await?;
+    if let Some(template) = &req.name_template {
+        crate::api::naming::validate_template(template).map_err(AppError::BadRequest)?;
+    }
+    if matches!(&req.article_ids, Some(ids) if ids.is_empty()) {
+        return Err(AppError::BadRequest("article_ids不能为空".to_string()));
+    }
+    let tags = crate::api::annotations::clean_tags(&req.tags)?;
+    req.pdf.validate()?;
+
     let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
         .bind(req.task_id)
         .fetch_optional(&state.db_pool)
@@ -99,56 +414,369 @@ pub async fn export_task(
         .ok_or(AppError::NotFound("Task not found".to_string()))?;
 
     let articles = sqlx::query_as::<_, InsightArticle>(
-        "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY similarity DESC NULLS LAST",
+        "SELECT * FROM insight_articles WHERE task_id = $1 AND ($2::UUID[] IS NULL OR id = ANY($2)) AND tags @> $3 AND ($4::FLOAT8 IS NULL OR similarity >= $4) ORDER BY similarity DESC NULLS LAST",
     )
     .bind(req.task_id)
+    .bind(&req.article_ids)
+    .bind(&tags)
+    .bind(req.min_similarity)
     .fetch_all(&state.db_pool)
     .await?;
+    Ok((task, articles))
+}
 
-    if articles.is_empty() {
-        return Ok(Json(ExportTaskResponse {
-            success: false,
-            message: "No articles to export".to_string(),
-        }));
-    }
+/// What `export_jobs.params` records of a request: everything but the
+/// authorization and proxy credentials
+fn export_params(req: &ExportTaskRequest) -> serde_json::Value {
+    serde_json::json!({
+        "format": req.format,
+        "target_dir": req.target_dir,
+        "name_template": req.name_template,
+        "group_by_account": req.group_by_account,
+        "article_ids": req.article_ids,
+        "tags": req.tags,
+        "min_similarity": req.min_similarity,
+        "incremental": req.incremental,
+        "proxies": req.proxies.as_ref().map_or(0, Vec::len),
+        "pdf": req.pdf,
+        "image_mode": req.image_mode,
+    })
+}
 
-    // 2. Prepare Directory
+/// `<prompt>_export_<time>`, the folder an export is written to
+fn export_folder_name(task: &InsightTask) -> String {
     let safe_prompt = task
         .prompt
         .replace(|c: char| !c.is_alphanumeric() && c != ' ', "_");
-    let export_dir = StdPath::new(&req.target_dir).join(format!(
+    format!(
         "{}_export_{}",
         safe_prompt,
         chrono::Utc::now().format("%Y%m%d%H%M")
-    ));
+    )
+}
 
-    // Create export dir
+/// Create an export directory and its images folder (none for article
+/// tables); returns the latter
+fn create_export_dirs(export_dir: &StdPath, format: &str) -> Result<PathBuf, AppError> {
     if !export_dir.exists() {
-        std::fs::create_dir_all(&export_dir)
+        std::fs::create_dir_all(export_dir)
             .map_err(|e| AppError::Internal(format!("Failed to create directory: {}", e)))?;
     }
+    if crate::api::spreadsheet::TableFormat::parse(format).is_some() {
+        return Ok(export_dir.join("images"));
+    }
 
-    // Create images dir
-    let images_dir = export_dir.join("images");
+    // An Obsidian vault keeps images under attachments/
+    let images_dir = if format == "obsidian" {
+        export_dir.join(crate::api::obsidian::ATTACHMENTS_DIR)
+    } else {
+        export_dir.join("images")
+    };
     std::fs::create_dir_all(&images_dir)
         .map_err(|e| AppError::Internal(format!("Failed to create images directory: {}", e)))?;
+    Ok(images_dir)
+}
 
-    tracing::info!("Exporting task {} to {:?}", task.id, export_dir);
+pub async fn export_task(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<ExportTaskRequest>,
+) -> Result<Json<ExportTaskResponse>, AppError> {
+    if req.target_dir.trim().is_empty() {
+        return Err(AppError::BadRequest("target_dir不能为空".to_string()));
+    }
+    if req.incremental && !crate::api::export_manifest::FORMATS.contains(&req.format.as_str()) {
+        return Err(AppError::BadRequest(format!(
+            "增量导出仅支持 {}",
+            crate::api::export_manifest::FORMATS.join(", ")
+        )));
+    }
 
-    // Sanitize proxies: remove trailing slashes
-    let sanitized_proxies = if let Some(proxies) = &req.proxies {
-        Some(
-            proxies
-                .iter()
-                .map(|p| p.trim_end_matches('/').to_string())
-                .collect::<Vec<_>>(),
-        )
+    // 1. Fetch Task and Articles
+    let (task, articles) = load_export(&state, &principal, &req).await?;
+
+    if articles.is_empty() {
+        return Ok(Json(ExportTaskResponse {
+            success: false,
+            message: "No articles to export".to_string(),
+            job_id: None,
+        }));
+    }
+
+    // 2. Prepare Directory; an incremental export continues in the last one's folder
+    let previous = if req.incremental {
+        crate::api::export_manifest::load(&state.db_pool, task.id, &req.target_dir, &req.format)
+            .await?
+            .filter(|manifest| StdPath::new(&manifest.export_dir).is_dir())
     } else {
         None
     };
+    let export_dir = match &previous {
+        Some(manifest) => PathBuf::from(&manifest.export_dir),
+        None => StdPath::new(&req.target_dir).join(export_folder_name(&task)),
+    };
+    let images_dir = create_export_dirs(&export_dir, &req.format)?;
+
+    tracing::info!("Exporting task {} to {:?}", task.id, export_dir);
+
+    // 3. Run as a background job; progress is streamed via /api/insight/export/:job_id/events
+    let export_dir_str = export_dir.to_string_lossy().to_string();
+    let job = crate::api::export::start_job(
+        &state.db_pool,
+        task.id,
+        &req.format,
+        &req.target_dir,
+        &export_dir_str,
+        articles.len(),
+        &export_params(&req),
+    )
+    .await?;
+    let job_id = job.id;
+
+    tokio::spawn(async move {
+        let result = run_export(
+            &state, &job, req, task, articles, export_dir, images_dir, previous,
+        )
+        .await;
+        match result {
+            Ok(_) if crate::shutdown::requested() => {
+                job.finish(
+                    "failed",
+                    "Interrupted by server shutdown",
+                    Some(&export_dir_str),
+                )
+                .await
+            }
+            Ok(message) => {
+                let status = if job.is_cancelled() { "cancelled" } else { "completed" };
+                job.finish(status, &message, Some(&export_dir_str)).await;
+            }
+            Err(e) => job.finish("failed", &e.to_string(), Some(&export_dir_str)).await,
+        }
+    });
+
+    Ok(Json(ExportTaskResponse {
+        success: true,
+        message: "Export started".to_string(),
+        job_id: Some(job_id),
+    }))
+}
+
+/// Response header carrying the job of a downloaded export, for progress via
+/// `/api/insight/export/:job_id/events`
+pub const EXPORT_JOB_HEADER: &str = "x-export-job";
+
+/// Build an export in a temporary workspace and stream it back as a ZIP.
+/// Headers are sent as soon as the job starts; the archive follows once every
+/// article is written. Dropping the response cancels the job.
+pub async fn export_download(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<ExportTaskRequest>,
+) -> Result<axum::response::Response, AppError> {
+    use axum::http::{header, StatusCode};
+    use futures::{StreamExt, TryStreamExt};
+
+    if req.incremental {
+        return Err(AppError::BadRequest(
+            "增量导出需要写入服务器目录，请使用 /api/insight/export".to_string(),
+        ));
+    }
+    let (task, articles) = load_export(&state, &principal, &req).await?;
+    if articles.is_empty() {
+        return Err(AppError::BadRequest("没有可导出的文章".to_string()));
+    }
+
+    let workspace = crate::api::export::TempWorkspace::create()
+        .map_err(|e| AppError::Internal(format!("Failed to create workspace: {}", e)))?;
+    let folder = export_folder_name(&task);
+    let filename = format!("{}.zip", folder);
+    let export_dir = workspace.path().join(&folder);
+    let images_dir = create_export_dirs(&export_dir, &req.format)?;
+    let export_dir_str = export_dir.to_string_lossy().to_string();
+    let job = crate::api::export::start_job(
+        &state.db_pool,
+        task.id,
+        &req.format,
+        "",
+        &export_dir_str,
+        articles.len(),
+        &export_params(&req),
+    )
+    .await?;
+    let job_id = job.id;
+    tracing::info!("Exporting task {} for download (job {})", task.id, job_id);
+
+    // The job runs detached so it finishes (and cleans up) even if the client leaves
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let worker = job.clone();
+    tokio::spawn(async move {
+        let job = worker;
+        let result = match run_export(
+            &state, &job, req, task, articles, export_dir, images_dir, None,
+        )
+        .await
+        {
+            Ok(_) if crate::shutdown::requested() => {
+                Err("Interrupted by server shutdown".to_string())
+            }
+            Ok(_) if job.is_cancelled() => Err("Export cancelled".to_string()),
+            Ok(message) => Ok(message),
+            Err(e) => Err(e.to_string()),
+        };
+        let zip_path = workspace.path().join("export.zip");
+        let result = match result {
+            Ok(message) => {
+                let (dir, zip) = (workspace.path().join(folder), zip_path.clone());
+                match tokio::task::spawn_blocking(move || {
+                    crate::api::export::zip_directory(&dir, &zip)
+                })
+                .await
+                {
+                    Ok(Ok(())) => {
+                        let kept = job.keep_archive(&zip_path).await;
+                        job.finish("completed", &message, None).await;
+                        Ok(kept.unwrap_or(zip_path))
+                    }
+                    Ok(Err(e)) => Err(format!("Failed to build ZIP: {}", e)),
+                    Err(e) => Err(format!("Failed to build ZIP: {}", e)),
+                }
+            }
+            Err(message) => Err(message),
+        };
+        if let Err(message) = &result {
+            let status = if job.is_cancelled() {
+                "cancelled"
+            } else {
+                "failed"
+            };
+            job.finish(status, message, None).await;
+        }
+        // The workspace goes with the result: removed once the archive is sent,
+        // or right away when nobody is waiting for it. A kept archive outlives it.
+        let _ = tx.send(result.map(|zip_path| (workspace, zip_path)));
+    });
 
-    // 3. Process Articles
-    // Build a single client for all requests (proxies are handled via URL rewriting now)
+    let cancel_on_drop = CancelOnDrop(job);
+    let body = futures::stream::once(async move {
+        let _guard = cancel_on_drop;
+        let (workspace, zip_path) = rx
+            .await
+            .map_err(|_| std::io::Error::other("export job ended unexpectedly"))?
+            .map_err(std::io::Error::other)?;
+        let file = tokio::fs::File::open(&zip_path).await?;
+        Ok::<_, std::io::Error>(tokio_util::io::ReaderStream::new(file).map(move |chunk| {
+            let _ = &workspace;
+            chunk
+        }))
+    })
+    .try_flatten();
+
+    Ok(axum::response::Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"export.zip\"; filename*=UTF-8''{}",
+                urlencoding::encode(&filename)
+            ),
+        )
+        .header(EXPORT_JOB_HEADER, job_id.to_string())
+        .body(axum::body::Body::from_stream(body))
+        .unwrap())
+}
+
+/// Cancels a downloaded export whose response is dropped before the archive is ready
+struct CancelOnDrop(std::sync::Arc<crate::api::export::ExportJobHandle>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// One article's outcome within an export job
+struct ExportItem {
+    index: usize,
+    article_id: Uuid,
+    title: String,
+    log: String,
+    success: bool,
+    images: usize,
+    /// Not attempted because the job was cancelled
+    skipped: bool,
+    /// Gateways that failed to deliver the article
+    gateway_failures: Vec<GatewayFailure>,
+    section: Option<crate::api::pdf::ReportSection>,
+    note: Option<crate::api::obsidian::NoteRef>,
+    /// Hash of the exported content and the file written, relative to the
+    /// export folder (see `export_manifest`)
+    hash: Option<String>,
+    file: Option<String>,
+    /// Left as the previous incremental export wrote it
+    unchanged: bool,
+}
+
+/// Write `summary.txt`, or append to it when continuing an earlier export
+fn write_summary(export_dir: &StdPath, content: &str, append: bool) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(export_dir.join("summary.txt"))?;
+    file.write_all(content.as_bytes())
+}
+
+/// Body of an export job: download, convert and write every article. With
+/// `previous`, articles it already holds unchanged are skipped.
+#[allow(clippy::too_many_arguments)]
+async fn run_export(
+    state: &AppState,
+    job: &std::sync::Arc<crate::api::export::ExportJobHandle>,
+    req: ExportTaskRequest,
+    task: InsightTask,
+    articles: Vec<InsightArticle>,
+    export_dir: PathBuf,
+    images_dir: PathBuf,
+    previous: Option<crate::api::export_manifest::Manifest>,
+) -> Result<String, AppError> {
+    let safe_prompt = task
+        .prompt
+        .replace(|c: char| !c.is_alphanumeric() && c != ' ', "_");
+
+    // Article tables need no content: one file straight from the rows
+    if let Some(table) = crate::api::spreadsheet::TableFormat::parse(&req.format) {
+        let path = export_dir.join(format!("{}.{}", safe_prompt, table.extension()));
+        let written = table
+            .build(&articles)
+            .and_then(|bytes| Ok(std::fs::write(&path, bytes)?));
+        if let Err(e) = written {
+            return Err(AppError::Internal(format!(
+                "{} export failed: {}",
+                table.extension().to_uppercase(),
+                e
+            )));
+        }
+        for (i, article) in articles.iter().enumerate() {
+            job.record_article(
+                i,
+                Some(article.id),
+                &article.title,
+                true,
+                "Added to table",
+                0,
+            )
+            .await;
+        }
+        return Ok(format!("Export completed to {:?}", path));
+    }
+
+    // Build a single client for all requests (gateways are handled via URL rewriting;
+    // HTTP / SOCKS5 proxies get their own client, see `gateway::get`)
     let client = reqwest::Client::builder()
         .user_agent(WECHAT_USER_AGENT)
         .danger_accept_invalid_certs(true)
@@ -156,11 +784,19 @@ pub async fn export_task(
         .build()
         .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
 
+    let appending = previous.is_some();
     let mut summary_content = String::new();
-    summary_content.push_str(&format!("Task Prompt: {}\n", task.prompt));
-    summary_content.push_str(&format!("Target: {}\n", task.target_count));
-    summary_content.push_str(&format!("Processed: {}\n", task.processed_count));
-    summary_content.push_str(&format!("Keywords: {:?}\n\n", task.keywords));
+    if appending {
+        summary_content.push_str(&format!(
+            "\n===== Incremental export {} =====\n",
+            chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+    } else {
+        summary_content.push_str(&format!("Task Prompt: {}\n", task.prompt));
+        summary_content.push_str(&format!("Target: {}\n", task.target_count));
+        summary_content.push_str(&format!("Processed: {}\n", task.processed_count));
+        summary_content.push_str(&format!("Keywords: {:?}\n\n", task.keywords));
+    }
 
     let total_articles = articles.len();
 
@@ -168,12 +804,42 @@ pub async fn export_task(
     use futures::stream::{self, StreamExt};
     use std::sync::Arc;
 
-    let shared_proxies = Arc::new(sanitized_proxies);
+    let shared_proxies = Arc::new(req.proxies.clone());
     let shared_auth = Arc::new(req.authorization.clone());
     let shared_export_dir = Arc::new(export_dir.clone());
     let shared_images_dir = Arc::new(images_dir.clone());
     let shared_format = Arc::new(req.format.clone());
+    let shared_pdf = Arc::new(req.pdf.clone());
     let shared_db_pool = state.db_pool.clone();
+    let shared_previous = Arc::new(previous);
+    let downloads = state.downloads.as_ref();
+    // Files kept from the last export stay reserved for their articles
+    let kept: HashMap<Uuid, String> = shared_previous
+        .as_ref()
+        .as_ref()
+        .map(|manifest| {
+            manifest
+                .entries
+                .iter()
+                .map(|(id, entry)| (*id, entry.file.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+    // Obsidian note names are assigned up front so repeated titles stay unique
+    let note_names = Arc::new(crate::api::obsidian::note_names(
+        articles.iter().map(|a| (a.id, a.title.as_str())),
+        &kept,
+    ));
+    let note_tags = Arc::new(crate::api::obsidian::task_tags(&task));
+    // So are file names, with per-account folders only for one-file-per-article formats
+    let by_account =
+        req.group_by_account && matches!(req.format.as_str(), "markdown" | "pdf" | "docx");
+    let file_names = Arc::new(crate::api::naming::export_names(
+        req.name_template.as_deref(),
+        by_account,
+        &articles,
+        &kept,
+    ));
 
     let concurrency = if req.format == "pdf" {
         // PDF generation is heavy, but user has high-performance CPU
@@ -184,18 +850,14 @@ pub async fn export_task(
         } else {
             (p.len() / 2).clamp(3, 20)
         }
+    } else if crate::gateway::pool_size() > 0 {
+        (crate::gateway::pool_size() / 2).clamp(3, 20)
     } else {
         1
     };
     tracing::info!("Concurrency: {}", concurrency);
 
-    let script_regex = Arc::new(Regex::new(r"(?s)<script[^>]*>.*?</script>").unwrap());
-    let style_regex = Arc::new(Regex::new(r"(?s)<style[^>]*>.*?</style>").unwrap());
-    let js_link_regex = Arc::new(
-        Regex::new(r#"(?i)<a[^>]+href\s*=\s*["']javascript:[^"']*["'][^>]*>.*?</a>"#).unwrap(),
-    );
-
-    let tasks = stream::iter(articles.into_iter().enumerate()).map(|(i, article)| {
+    let tasks = stream::iter(articles.into_iter().enumerate()).map(|(i, mut article)| {
         let db_pool = shared_db_pool.clone();
         let client = client.clone();
         let proxies = shared_proxies.clone();
@@ -203,11 +865,36 @@ pub async fn export_task(
         let export_dir = shared_export_dir.clone();
         let images_dir = shared_images_dir.clone();
         let fmt = shared_format.clone();
-        let script_re = script_regex.clone();
-        let style_re = style_regex.clone();
-        let js_link_re = js_link_regex.clone();
+        let pdf_options = shared_pdf.clone();
+        let image_mode = req.image_mode;
+        let job = job.clone();
+        let note_names = note_names.clone();
+        let note_tags = note_tags.clone();
+        let file_names = file_names.clone();
+        let previous = shared_previous.clone();
 
         async move {
+            let mut item = ExportItem {
+                index: i,
+                article_id: article.id,
+                title: article.title.clone(),
+                log: String::new(),
+                success: false,
+                images: 0,
+                skipped: false,
+                gateway_failures: Vec::new(),
+                section: None,
+                note: None,
+                hash: None,
+                file: None,
+                unchanged: false,
+            };
+            if job.is_cancelled() {
+                item.skipped = true;
+                item.log = format!("{}. {} ({})\n   [Skipped] Export cancelled\n", i + 1, article.title, article.url);
+                return item;
+            }
+
             tracing::info!(
                 "Processing article {}/{}: {}",
                 i + 1,
@@ -222,141 +909,421 @@ pub async fn export_task(
 
             let mut log_entry = String::new();
 
-            let gateway = if let Some(ps) = proxies.as_ref() {
-                if !ps.is_empty() {
-                    use rand::seq::SliceRandom;
-                    let mut rng = rand::thread_rng();
-                    ps.choose(&mut rng).map(|s| s.as_str())
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            let gateway_auth = auth.as_deref();
-
             log_entry.push_str(&format!("{}. {} ({})\n", i + 1, article.title, article.url));
             if let Some(insight) = &article.insight {
                 log_entry.push_str(&format!("   Insight: {}\n", insight));
             }
 
-            let url_hash = format!("{:x}", md5::compute(article.url.as_bytes()));
-            let cached_content: Option<String> = sqlx::query_scalar("SELECT content FROM cached_articles WHERE url_hash = $1")
-                .bind(&url_hash)
-                .fetch_optional(&db_pool)
+            // Account folders keep their own images/, so relative image paths still resolve
+            let name = &file_names[i];
+            let (article_dir, images_dir) = match &name.folder {
+                Some(folder) => {
+                    let dir = export_dir.join(folder);
+                    (dir.clone(), dir.join("images"))
+                }
+                None => (export_dir.as_ref().clone(), images_dir.as_ref().clone()),
+            };
+            if let Err(e) = std::fs::create_dir_all(&images_dir) {
+                log_entry.push_str(&format!("   [Error] Create folder failed: {}\n", e));
+                item.log = log_entry;
+                return item;
+            }
+
+            let cached_content = repository::articles::content(&db_pool, None, Some(&article.url))
                 .await
                 .unwrap_or(None);
 
-            let html_content = if let Some(content) = cached_content {
+            let (html_content, picked) = if let Some(content) = cached_content {
                 log_entry.push_str("   [Cache] Hit\n");
-                content
+                (
+                    content,
+                    crate::gateway::pick(proxies.as_deref(), auth.as_deref()),
+                )
             } else {
-                match fetch_html_content(&client, &article.url, gateway, gateway_auth).await {
-                    Ok(c) => {
-                        if c.trim().len() < 500 {
-                            tracing::warn!(
-                                "Content too short for {}: {} items",
-                                article.title,
-                                c.len()
-                            );
-                            log_entry.push_str("   [Error] Download failed: Content too short\n");
-                            return (i, log_entry);
-                        }
-
+                let fetched = fetch_html_rotating(
+                    &client,
+                    downloads,
+                    &article.url,
+                    proxies.as_deref(),
+                    auth.as_deref(),
+                    500,
+                    &mut item.gateway_failures,
+                )
+                .await;
+                for failure in &item.gateway_failures {
+                    log_entry.push_str(&format!(
+                        "   [Gateway] {} failed: {}\n",
+                        failure.gateway, failure.error
+                    ));
+                }
+                match fetched {
+                    Ok((c, route)) => {
                         // Save to cache
-                        let _ = sqlx::query("INSERT INTO cached_articles (url_hash, url, content, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (url_hash) DO NOTHING")
-                            .bind(&url_hash)
-                            .bind(&article.url)
-                            .bind(&c)
-                            .bind(chrono::Utc::now().timestamp())
-                            .execute(&db_pool)
-                            .await;
+                        let _ =
+                            repository::articles::save(&db_pool, None, &article.url, &c, None)
+                                .await;
 
-                        c
+                        (c, route)
                     }
                     Err(e) => {
                         tracing::error!("Failed to fetch article {}: {}", article.url, e);
                         log_entry.push_str(&format!("   [Error] Download failed: {}\n", e));
-                        return (i, log_entry);
+                        item.log = log_entry;
+                        return item;
                     }
                 }
             };
 
-            // Process Images & Content (Pass gateway info for image downloads)
-            let (processed_html, _) = process_html_images(
-                &client,
+            let hash = crate::api::export_manifest::article_hash(&article, &html_content);
+            let unchanged = previous
+                .as_ref()
+                .as_ref()
+                .and_then(|manifest| manifest.unchanged(article.id, &hash));
+            if let Some(entry) = unchanged {
+                log_entry.push_str("   [Unchanged] Kept from the last export.\n");
+                if *fmt == "obsidian" {
+                    item.note = Some(crate::api::obsidian::NoteRef {
+                        name: entry.file.trim_end_matches(".md").to_string(),
+                        title: article.title.clone(),
+                        account_name: article.account_name.clone(),
+                        publish_time: article.publish_time,
+                        insight: article.insight.clone(),
+                    });
+                }
+                item.file = Some(entry.file.clone());
+                item.hash = Some(hash);
+                item.unchanged = true;
+                item.success = true;
+                item.log = log_entry;
+                return item;
+            }
+            item.hash = Some(hash);
+            article.apply_page_metadata(crate::content::extract::metadata(
                 &html_content,
-                &images_dir,
-                &article.id.to_string(),
-                gateway,
-                gateway_auth,
-                &db_pool,
-                false, // Revert to relative paths as requested
-            )
-            .await;
+                &article.url,
+            ));
 
-            let filename = format!(
-                "{}_{}",
-                i + 1,
-                article
-                    .title
-                    .replace(|c: char| !c.is_alphanumeric() && c != ' ', "_")
-            );
+            // Process Images & Content (Pass gateway info for image downloads);
+            // markdown may keep the remote images or inline them instead
+            let image_mode = if *fmt == "markdown" {
+                image_mode
+            } else {
+                ImageMode::Absolute
+            };
+            let mut downloaded = Vec::new();
+            let processed_html = if image_mode == ImageMode::Remote {
+                html_content.clone()
+            } else {
+                let (processed_html, downloaded_images) = process_html_images(
+                    &client,
+                    downloads,
+                    &html_content,
+                    &images_dir,
+                    &article.id.to_string(),
+                    picked.as_ref(),
+                    &db_pool,
+                    false,
+                )
+                .await;
+                item.images = downloaded_images.len();
+                downloaded = downloaded_images;
+                processed_html
+            };
 
-            if *fmt == "markdown" {
-                let s1 = script_re.replace_all(&processed_html, "");
-                let s2 = style_re.replace_all(&s1, "");
-                let clean_html = js_link_re.replace_all(&s2, "");
+            // Structured content; images already point at the local copies
+            let extracted = crate::content::extract::extract(&processed_html);
 
-                // Convert to Markdown
-                let markdown_body = html2md::parse_html(&clean_html);
-                let full_md = format!(
-                    "---\ntitle: {}\nurl: {}\ndate: {}\n---\n\n# {}\n\n> Insight: {}\n\n{}",
+            let filename = &name.stem;
+            let relative = |extension: &str| match &name.folder {
+                Some(folder) => format!("{}/{}.{}", folder, filename, extension),
+                None => format!("{}.{}", filename, extension),
+            };
+
+            if *fmt == "report" {
+                // Chapters are assembled into one PDF once every article is in
+                log_entry.push_str("   [Success] Added to report.\n");
+                item.section = Some(crate::api::pdf::ReportSection {
+                    title: article.title.clone(),
+                    url: article.url.clone(),
+                    account_name: article.account_name.clone(),
+                    publish_time: article.publish_time,
+                    similarity: article.similarity,
+                    insight: article.insight.clone(),
+                    body: extracted,
+                });
+                item.success = true;
+                item.log = log_entry;
+                return item;
+            }
+
+            if *fmt == "obsidian" {
+                let name = &note_names[i];
+                let note =
+                    crate::api::obsidian::article_note(&article, &processed_html, &note_tags);
+                let file_path = export_dir.join(format!("{}.md", name));
+                if let Err(e) = std::fs::write(&file_path, note) {
+                    log_entry.push_str(&format!("   [Error] Write note failed: {}\n", e));
+                } else {
+                    log_entry.push_str("   [Success] Note saved.\n");
+                    item.file = Some(format!("{}.md", name));
+                    item.note = Some(crate::api::obsidian::NoteRef {
+                        name: name.clone(),
+                        title: article.title.clone(),
+                        account_name: article.account_name.clone(),
+                        publish_time: article.publish_time,
+                        insight: article.insight.clone(),
+                    });
+                    item.success = true;
+                }
+            } else if *fmt == "docx" {
+                let mut meta = Vec::new();
+                meta.extend(article.account_name.clone());
+                meta.extend(article.author.clone());
+                if let Some(ts) = article.publish_time.filter(|ts| *ts > 0) {
+                    if let Some(dt) = chrono::DateTime::from_timestamp(ts, 0) {
+                        meta.push(dt.format("%Y-%m-%d").to_string());
+                    }
+                }
+                meta.push(article.url.clone());
+                let images =
+                    crate::api::docx::load_images(&db_pool, Some(&article_dir), &extracted).await;
+                let file_path = article_dir.join(format!("{}.docx", filename));
+                let written = crate::api::docx::build_docx(
+                    &article.title,
+                    &meta,
+                    article.insight.as_deref(),
+                    &extracted,
+                    &images,
+                )
+                .and_then(|bytes| Ok(std::fs::write(&file_path, bytes)?));
+                if let Err(e) = written {
+                    log_entry.push_str(&format!("   [Error] DOCX gen failed: {}\n", e));
+                } else {
+                    log_entry.push_str("   [Success] DOCX generated.\n");
+                    item.file = Some(relative("docx"));
+                    item.success = true;
+                }
+            } else if *fmt == "markdown" {
+                let markdown_body =
+                    crate::content::markdown::to_markdown_with(&processed_html, &|src| {
+                        let relative = relative_image_src(src);
+                        match (image_mode, relative) {
+                            (ImageMode::Relative, Some(relative)) => relative,
+                            (ImageMode::Base64, Some(relative)) => {
+                                data_uri(&article_dir.join(relative))
+                                    .unwrap_or_else(|| src.to_string())
+                            }
+                            _ => src.to_string(),
+                        }
+                    });
+                // Inlined copies are not kept in images/
+                if image_mode == ImageMode::Base64 {
+                    for file in &downloaded {
+                        let _ = std::fs::remove_file(file);
+                    }
+                }
+                let mut front_matter = format!(
+                    "title: {}\nurl: {}\ndate: {}\n",
                     article.title,
                     article.url,
-                    article.publish_time.unwrap_or(0),
+                    article.publish_time.unwrap_or(0)
+                );
+                if let Some(author) = &article.author {
+                    front_matter.push_str(&format!("author: {}\n", author));
+                }
+                if let Some(original) = article.is_original {
+                    front_matter.push_str(&format!("original: {}\n", original));
+                }
+                let full_md = format!(
+                    "---\n{}---\n\n# {}\n\n> Insight: {}\n\n{}",
+                    front_matter,
                     article.title,
                     article.insight.as_deref().unwrap_or(""),
                     markdown_body
                 );
 
-                let file_path = export_dir.join(format!("{}.md", filename));
+                let file_path = article_dir.join(format!("{}.md", filename));
                 if let Err(e) = std::fs::write(&file_path, full_md) {
                     log_entry.push_str(&format!("   [Error] Write MD failed: {}\n", e));
                 } else {
                     log_entry.push_str("   [Success] Markdown saved.\n");
+                    item.file = Some(relative("md"));
+                    item.success = true;
                 }
             } else {
-                let pdf_html = processed_html;
-
-                let file_path = export_dir.join(format!("{}.pdf", filename));
-                if let Err(e) =
-                    crate::api::pdf::convert_html_to_pdf(&pdf_html, &file_path, &article.title, Some(&export_dir))
-                        .await
+                let mut pdf_html = format!("<h1>{}</h1>", html_escape::encode_text(&article.title));
+                if let Some(insight) = article
+                    .insight
+                    .as_deref()
+                    .filter(|s| pdf_options.include_insight && !s.is_empty())
+                {
+                    pdf_html.push_str(&format!(
+                        "<div class=\"insight\">{}</div>",
+                        html_escape::encode_text(insight)
+                    ));
+                }
+                pdf_html.push_str(&extracted.to_html());
+
+                let file_path = article_dir.join(format!("{}.pdf", filename));
+                let meta = crate::api::pdf::PdfMetadata {
+                    title: &article.title,
+                    author: article
+                        .author
+                        .as_deref()
+                        .or(article.account_name.as_deref()),
+                    created: article.publish_time,
+                    keywords: &article.tags,
+                };
+                if let Err(e) = crate::api::pdf::convert_html_to_pdf(
+                    &pdf_html,
+                    &file_path,
+                    &meta,
+                    Some(&article_dir),
+                    &pdf_options,
+                )
+                .await
                 {
                     log_entry.push_str(&format!("   [Error] PDF gen failed: {}\n", e));
                 } else {
                     log_entry.push_str("   [Success] PDF generated.\n");
+                    item.file = Some(relative("pdf"));
+                    item.success = true;
                 }
             }
 
-            (i, log_entry)
+            item.log = log_entry;
+            item
         }
     });
 
-    let mut results: Vec<(usize, String)> = tasks.buffer_unordered(concurrency).collect().await;
-    results.sort_by_key(|k| k.0);
-    for (_, log) in results {
-        summary_content.push_str(&log);
+    let mut results: Vec<ExportItem> = Vec::with_capacity(total_articles);
+    let mut pending = tasks.buffer_unordered(concurrency);
+    while let Some(item) = pending.next().await {
+        if !item.skipped {
+            let message = item.log.lines().last().unwrap_or_default().trim();
+            job.record_article(
+                item.index,
+                Some(item.article_id),
+                &item.title,
+                item.success,
+                message,
+                item.images,
+            )
+            .await;
+        }
+        results.push(item);
+    }
+    results.sort_by_key(|item| item.index);
+    let mut sections = Vec::new();
+    let mut notes = Vec::new();
+    let mut gateway_failures: std::collections::BTreeMap<String, usize> = Default::default();
+    let mut manifest = match shared_previous.as_ref() {
+        Some(previous) => previous.clone(),
+        None => crate::api::export_manifest::Manifest {
+            export_dir: export_dir.to_string_lossy().to_string(),
+            entries: HashMap::new(),
+        },
+    };
+    let mut unchanged = 0;
+    let now = chrono::Utc::now().timestamp();
+    for item in results {
+        if item.unchanged {
+            unchanged += 1;
+        } else {
+            summary_content.push_str(&item.log);
+        }
+        sections.extend(item.section);
+        notes.extend(item.note);
+        for failure in item.gateway_failures {
+            *gateway_failures.entry(failure.gateway).or_default() += 1;
+        }
+        if item.unchanged || !item.success {
+            continue;
+        }
+        if let (Some(hash), Some(file)) = (item.hash, item.file) {
+            let entry = crate::api::export_manifest::ManifestEntry {
+                hash,
+                file,
+                exported_at: now,
+            };
+            // A changed article may be named differently now
+            if let Some(old) = manifest.entries.insert(item.article_id, entry.clone()) {
+                if old.file != entry.file {
+                    let _ = std::fs::remove_file(export_dir.join(&old.file));
+                }
+            }
+        }
+    }
+    if unchanged > 0 {
+        summary_content.push_str(&format!("Unchanged since the last export: {}\n", unchanged));
+    }
+    if !req.target_dir.trim().is_empty()
+        && crate::api::export_manifest::FORMATS.contains(&req.format.as_str())
+    {
+        let saved = crate::api::export_manifest::save(
+            &state.db_pool,
+            task.id,
+            &req.target_dir,
+            &req.format,
+            &manifest,
+        )
+        .await;
+        if let Err(e) = saved {
+            tracing::warn!("Failed to save export manifest: {}", e);
+        }
+    }
+    if !gateway_failures.is_empty() {
+        summary_content.push_str("\nGateway failures:\n");
+        for (gateway, count) in &gateway_failures {
+            summary_content.push_str(&format!("   {}: {}\n", gateway, count));
+        }
+    }
+    if let Err(e) = crate::gateway::flush_stats(&state.db_pool).await {
+        tracing::warn!("Failed to save gateway stats: {}", e);
     }
 
-    let _ = std::fs::write(export_dir.join("summary.txt"), summary_content);
+    if job.is_cancelled() {
+        summary_content.push_str("\n[Cancelled] Export cancelled by user\n");
+        let _ = write_summary(&export_dir, &summary_content, appending);
+        return Ok("Export cancelled".to_string());
+    }
 
-    Ok(Json(ExportTaskResponse {
-        success: true,
-        message: format!("Export completed to {:?}", export_dir),
-    }))
+    if req.format == "report" {
+        if sections.is_empty() {
+            let _ = write_summary(&export_dir, &summary_content, appending);
+            return Err(AppError::Internal(
+                "No articles could be downloaded for the report".to_string(),
+            ));
+        }
+
+        let report_html = crate::api::pdf::build_report_html(&task, &sections, &req.pdf);
+        let report_path = export_dir.join(format!("{}_report.pdf", safe_prompt));
+        match crate::api::pdf::render_report_pdf(&report_html, &report_path, &export_dir).await {
+            Ok(()) => summary_content.push_str(&format!(
+                "\nReport: {} ({} of {} articles)\n",
+                report_path.display(),
+                sections.len(),
+                total_articles
+            )),
+            Err(e) => {
+                summary_content.push_str(&format!("\n[Error] Report PDF gen failed: {}\n", e));
+                let _ = write_summary(&export_dir, &summary_content, appending);
+                return Err(e);
+            }
+        }
+    }
+
+    if req.format == "obsidian" {
+        let index = crate::api::obsidian::index_note(&task, &notes, &note_tags);
+        let index_path = export_dir.join(format!("{}.md", crate::api::obsidian::INDEX_NOTE));
+        if let Err(e) = std::fs::write(&index_path, index) {
+            summary_content.push_str(&format!("\n[Error] Write index note failed: {}\n", e));
+        }
+    }
+
+    let _ = write_summary(&export_dir, &summary_content, appending);
+
+    Ok(format!("Export completed to {:?}", export_dir))
 }
 
 // Helper code to be inserted or appended later (fetch_html_content, process_html_images) or inlined.
@@ -375,7 +1342,7 @@ pub async fn export_task(
 #[derive(Debug, Deserialize)]
 pub struct PrefetchTaskRequest {
     pub task_id: Uuid,
-    pub proxies: Option<Vec<String>>,
+    pub proxies: Option<Vec<ProxySpec>>,
     pub authorization: Option<String>,
 }
 
@@ -385,6 +1352,8 @@ pub struct PrefetchStats {
     pub article_failed: usize,
     pub image_success: usize,
     pub image_failed: usize,
+    /// Failed article download attempts per gateway
+    pub gateway_failures: std::collections::BTreeMap<String, usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -394,10 +1363,14 @@ pub struct PrefetchTaskResponse {
     pub stats: PrefetchStats,
 }
 
+#[allow(clippy::single_match, clippy::redundant_pattern_matching, clippy::useless_format)]
 pub async fn prefetch_task(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<PrefetchTaskRequest>,
 ) -> Result<Json<PrefetchTaskResponse>, AppError> {
+    authorize_task(&state, &principal, req.task_id).await?;
+
     // 1. Fetch Task and Articles
     let _task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
         .bind(req.task_id)
@@ -405,31 +1378,35 @@ pub async fn prefetch_task(
         .await?
         .ok_or(AppError::NotFound("Task not found".to_string()))?;
 
-    let articles = sqlx::query_as::<_, InsightArticle>(
+    let mut articles = sqlx::query_as::<_, InsightArticle>(
         "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY similarity DESC NULLS LAST",
     )
     .bind(req.task_id)
     .fetch_all(&state.db_pool)
     .await?;
 
-    let sanitized_proxies = if let Some(proxies) = req.proxies {
-        Some(
-            proxies
-                .into_iter()
-                .map(|p| p.trim_end_matches('/').to_string())
-                .collect::<Vec<String>>(),
-        )
-    } else {
-        None
-    };
+    // Articles likely to be deleted soon first (stable, so similarity order holds within a rank)
+    let fakeids: Vec<String> = articles
+        .iter()
+        .filter_map(|a| a.account_fakeid.clone())
+        .collect();
+    let at_risk = crate::link_status::accounts_with_vanished(&state.db_pool, &fakeids).await?;
+    articles.sort_by_key(|a| {
+        let account_has_vanished = a
+            .account_fakeid
+            .as_ref()
+            .is_some_and(|f| at_risk.contains(f));
+        crate::link_status::prefetch_rank(a.status.as_deref(), account_has_vanished)
+    });
 
     // 2. Setup Concurrency
     use futures::stream::{self, StreamExt};
     use std::sync::Arc;
 
-    let shared_proxies = Arc::new(sanitized_proxies);
+    let shared_proxies = Arc::new(req.proxies);
     let shared_auth = Arc::new(req.authorization.clone());
     let shared_db_pool = state.db_pool.clone();
+    let downloads = state.downloads.as_ref();
 
     // Compile regex once (Allow http, https, and protocol-relative)
     let img_regex = Arc::new(Regex::new(r#"(?i)(?:data-src|src)\s*=\s*["']((?:https?:)?//[^"']+)["']"#).unwrap());
@@ -448,6 +1425,8 @@ pub async fn prefetch_task(
         } else {
             (p.len() / 2).clamp(3, 20)
         }
+    } else if crate::gateway::pool_size() > 0 {
+        (crate::gateway::pool_size() / 2).clamp(3, 20)
     } else {
         1
     };
@@ -467,11 +1446,13 @@ pub async fn prefetch_task(
             log_entry.push_str(&format!("{}. {} ({})\n", i + 1, article.title, article.url));
 
             // --- A. Content Fetching ---
-            let cached_content: Option<String> = sqlx::query_scalar("SELECT content FROM article_content WHERE id = $1")
-                .bind(article.id)
-                .fetch_optional(&db_pool)
-                .await
-                .unwrap_or(None);
+            let cached_content = repository::articles::content(
+                &db_pool,
+                article.article_id.as_deref(),
+                Some(&article.url),
+            )
+            .await
+            .unwrap_or(None);
 
             let html_content = if let Some(content) = cached_content {
                 // Check word count threshold (> 500 chars)
@@ -490,29 +1471,40 @@ pub async fn prefetch_task(
                 stats.article_success += 1;
                 c
             } else {
-                // Fetch
-                let gateway = if let Some(ps) = proxies.as_ref() {
-                    if !ps.is_empty() {
-                        use rand::seq::SliceRandom;
-                        let mut rng = rand::thread_rng();
-                        ps.choose(&mut rng).map(|s| s.as_str())
-                    } else { None }
-                } else { None };
-                let gateway_auth = auth.as_deref();
-
-                match fetch_html_content(&client, &article.url, gateway, gateway_auth).await {
-                    Ok(c) => {
+                // Fetch, moving to another gateway after each failure
+                let mut failures = Vec::new();
+                let fetched = fetch_html_rotating(
+                    &client,
+                    downloads,
+                    &article.url,
+                    proxies.as_deref(),
+                    auth.as_deref(),
+                    0,
+                    &mut failures,
+                )
+                .await;
+                for failure in failures {
+                    log_entry.push_str(&format!(
+                        "   [Gateway] {} failed: {}\n",
+                        failure.gateway, failure.error
+                    ));
+                    *stats.gateway_failures.entry(failure.gateway).or_default() += 1;
+                }
+
+                match fetched {
+                    Ok((c, _)) => {
                         if c.trim().len() < 500 {
                             log_entry.push_str("   [Warning] Fetched content short < 500\n");
                         }
                         // Save to cache (article_content)
-                        let _ = sqlx::query("INSERT INTO article_content (id, content, original_url, create_time) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO UPDATE SET content = EXCLUDED.content, create_time = EXCLUDED.create_time")
-                            .bind(article.id)
-                            .bind(&c)
-                            .bind(&article.url)
-                            .bind(chrono::Utc::now().timestamp())
-                            .execute(&db_pool)
-                            .await;
+                        let _ = repository::articles::save(
+                            &db_pool,
+                            article.article_id.as_deref(),
+                            &article.url,
+                            &c,
+                            None,
+                        )
+                        .await;
                         log_entry.push_str("   [Success] Fetched & Saved\n");
                         stats.article_success += 1;
                         c
@@ -540,39 +1532,27 @@ pub async fn prefetch_task(
                     let img_url = img_url_string.as_str();
                     img_total += 1;
 
-                    // Check assets table
-                    let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM assets WHERE url = $1)")
-                        .bind(img_url)
-                        .fetch_one(&db_pool)
-                        .await
-                        .unwrap_or(false);
-
-                    if exists {
+                    // Check asset storage
+                    if crate::storage::exists(&db_pool, img_url).await {
                         img_ok += 1;
                         continue;
                     }
 
                     // Download
-                    let gateway = if let Some(ps) = proxies.as_ref() {
-                        if !ps.is_empty() {
-                            use rand::seq::SliceRandom;
-                            let mut rng = rand::thread_rng();
-                            ps.choose(&mut rng).map(|s| s.as_str())
-                        } else { None }
-                    } else { None };
-                    let gateway_auth = auth.as_deref();
-
-                    let final_url = if let Some(gw) = gateway {
-                         let mut u = reqwest::Url::parse(gw).unwrap();
-                         {
-                            let mut p = u.query_pairs_mut();
-                            p.append_pair("url", img_url);
-                            if let Some(a) = gateway_auth { p.append_pair("authorization", a); }
-                         }
-                         u.to_string()
-                    } else { img_url.to_string() };
-
-                    match client.get(&final_url).send().await {
+                    let picked = crate::gateway::pick(proxies.as_deref(), auth.as_deref());
+                    let gateway = picked.as_ref().map(|p| p.url.as_str());
+                    let Ok(request) = crate::gateway::get(&client, picked.as_ref(), img_url) else {
+                        continue;
+                    };
+
+                    let _slot = downloads.acquire(gateway, img_url).await;
+                    let started = std::time::Instant::now();
+                    let resp = request.send().await;
+                    if let Some(gw) = gateway {
+                        let error = crate::gateway::response_error(&resp);
+                        crate::gateway::record(gw, error.as_deref(), started.elapsed());
+                    }
+                    match resp {
                         Ok(resp) => {
                             if resp.status().is_success() {
                                 if let Ok(bytes) = resp.bytes().await {
@@ -596,14 +1576,10 @@ pub async fn prefetch_task(
                                     };
 
                                     // Store
-                                    let _ = sqlx::query("INSERT INTO assets (url, data, mime_type, size, create_time) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (url) DO NOTHING")
-                                        .bind(img_url)
-                                        .bind(&compressed_data)
-                                        .bind("image/jpeg")
-                                        .bind(compressed_data.len() as i32)
-                                        .bind(chrono::Utc::now().timestamp())
-                                        .execute(&db_pool)
-                                        .await;
+                                    if let Err(e) = crate::storage::save(&db_pool, img_url, &compressed_data, "image/jpeg").await {
+                                        tracing::warn!("Failed to store image {}: {}", img_url, e);
+                                        continue;
+                                    }
                                     img_ok += 1;
                                 }
                             }
@@ -631,6 +1607,12 @@ pub async fn prefetch_task(
         total_stats.article_failed += s.article_failed;
         total_stats.image_success += s.image_success;
         total_stats.image_failed += s.image_failed;
+        for (gateway, count) in s.gateway_failures {
+            *total_stats.gateway_failures.entry(gateway).or_default() += count;
+        }
+    }
+    if let Err(e) = crate::gateway::flush_stats(&state.db_pool).await {
+        tracing::warn!("Failed to save gateway stats: {}", e);
     }
 
     Ok(Json(PrefetchTaskResponse {
@@ -643,6 +1625,9 @@ pub async fn prefetch_task(
 #[derive(Debug, Deserialize)]
 pub struct DeleteTaskRequest {
     pub id: Uuid,
+    /// Also remove cached HTML and assets only this task uses
+    #[serde(default)]
+    pub purge: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -653,9 +1638,72 @@ pub struct CancelTaskRequest {
 /// Delete a task and its articles
 pub async fn delete_task(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<DeleteTaskRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    // Delete articles first due to FK
+    authorize_task(&state, &principal, req.id).await?;
+
+    // Needs the task's insight_articles, so before they go
+    let purged = if req.purge {
+        Some(crate::gc::purge_task(&state.db_pool, req.id).await?)
+    } else {
+        None
+    };
+
+    // Delete articles, digests and share links first due to FK
+    sqlx::query("DELETE FROM task_shares WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query("DELETE FROM insight_digests WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    state.task_queue.remove(&state.db_pool, req.id).await?;
+    // A paused task's config holds its API keys
+    AWAITING_REVIEW.lock().unwrap().remove(&req.id);
+
+    sqlx::query("DELETE FROM insight_task_state WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query("DELETE FROM task_llm_usage WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query(
+        "DELETE FROM insight_revision_articles WHERE revision_id IN (SELECT id FROM insight_revisions WHERE task_id = $1)",
+    )
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    sqlx::query("DELETE FROM insight_revisions WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    crate::api::export::delete_task_jobs(&state.db_pool, req.id).await?;
+
+    sqlx::query("DELETE FROM export_manifests WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query("DELETE FROM article_annotations WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query("DELETE FROM insight_candidates WHERE task_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
     sqlx::query("DELETE FROM insight_articles WHERE task_id = $1")
         .bind(req.id)
         .execute(&state.db_pool)
@@ -667,14 +1715,45 @@ pub async fn delete_task(
         .execute(&state.db_pool)
         .await?;
 
-    Ok(Json(serde_json::json!({ "success": true })))
+    Ok(Json(
+        serde_json::json!({ "success": true, "purged": purged }),
+    ))
 }
 
 /// Cancel a running task
 pub async fn cancel_task(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<CancelTaskRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, req.id).await?;
+
+    // A task that hasn't started yet is cancelled right away
+    if state.task_queue.remove(&state.db_pool, req.id).await? {
+        sqlx::query(
+            "UPDATE insight_tasks SET status = 'cancelled', completion_reason = 'Cancelled by user', updated_at = $1 WHERE id = $2",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+        return Ok(Json(serde_json::json!({ "success": true })));
+    }
+
+    // No worker runs while keywords await review
+    let paused = sqlx::query(
+        "UPDATE insight_tasks SET status = 'cancelled', completion_reason = 'Cancelled by user', updated_at = $1 WHERE id = $2 AND status = 'awaiting_review'",
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+    if paused > 0 {
+        AWAITING_REVIEW.lock().unwrap().remove(&req.id);
+        return Ok(Json(serde_json::json!({ "success": true })));
+    }
+
     sqlx::query("UPDATE insight_tasks SET status = 'cancelling', updated_at = $1 WHERE id = $2")
         .bind(chrono::Utc::now().timestamp())
         .bind(req.id)
@@ -687,10 +1766,11 @@ pub async fn cancel_task(
 /// Create a new insight task
 pub async fn create_task(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<CreateTaskRequest>,
 ) -> Result<Json<CreateTaskResponse>, AppError> {
     // Pre-validation: Check if WeChat session is valid before creating task
-    let auth_key = get_valid_auth_key(&state)
+    let auth_key = get_valid_auth_key(&state, principal.user_id)
         .await
         .ok_or_else(|| AppError::BadRequest("请先登录微信公众平台".to_string()))?;
 
@@ -703,125 +1783,1022 @@ pub async fn create_task(
     }
 
     let task_id = Uuid::new_v4();
+    let mut config = TaskConfig::from_request(&req);
+    config.validate()?;
+    config.owner_id = principal.user_id;
+    let warning = crate::keepalive::expiry_warning(&state, &auth_key, config.target_count).await;
+    insert_task_record(&state, task_id, &config, None, None).await?;
+
+    state
+        .task_queue
+        .enqueue(
+            &state.db_pool,
+            task_id,
+            config,
+            req.priority.unwrap_or(crate::task_queue::DEFAULT_PRIORITY),
+        )
+        .await?;
+
+    Ok(Json(CreateTaskResponse {
+        id: task_id,
+        warning,
+    }))
+}
+
+/// Create one task per prompt with shared settings; they run through the task queue
+pub async fn create_batch(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<CreateBatchRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let prompts: Vec<String> = req
+        .prompts
+        .iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if prompts.is_empty() {
+        return Err(AppError::BadRequest("prompts不能为空".to_string()));
+    }
+    if prompts.len() > MAX_BATCH_PROMPTS {
+        return Err(AppError::BadRequest(format!(
+            "一次最多创建{}个任务",
+            MAX_BATCH_PROMPTS
+        )));
+    }
+
+    let auth_key = get_valid_auth_key(&state, principal.user_id)
+        .await
+        .ok_or_else(|| AppError::BadRequest("请先登录微信公众平台".to_string()))?;
+    if let Err(e) = validate_wechat_session(&state, &auth_key).await {
+        return Err(AppError::BadRequest(format!(
+            "微信登录已过期，请重新登录: {}",
+            e
+        )));
+    }
+
+    let mut ids = Vec::with_capacity(prompts.len());
+    let mut total_target = 0;
+    for prompt in prompts {
+        let mut fields = req.settings.clone();
+        fields.insert("prompt".to_string(), serde_json::Value::String(prompt));
+        let task: CreateTaskRequest = serde_json::from_value(serde_json::Value::Object(fields))
+            .map_err(|e| AppError::BadRequest(format!("无效的任务配置: {}", e)))?;
+
+        let task_id = Uuid::new_v4();
+        let mut config = TaskConfig::from_request(&task);
+        config.validate()?;
+        config.owner_id = principal.user_id;
+        total_target += config.target_count;
+        insert_task_record(&state, task_id, &config, None, None).await?;
+        state
+            .task_queue
+            .enqueue(
+                &state.db_pool,
+                task_id,
+                config,
+                task.priority.unwrap_or(crate::task_queue::DEFAULT_PRIORITY),
+            )
+            .await?;
+        ids.push(task_id);
+    }
+
+    tracing::info!("Queued batch of {} tasks", ids.len());
+
+    // The batch shares one session, so weigh it as a whole
+    let warning = crate::keepalive::expiry_warning(&state, &auth_key, total_target).await;
+    Ok(Json(serde_json::json!({ "success": true, "ids": ids, "warning": warning })))
+}
+
+/// Running and queued tasks
+pub async fn queue_status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "success": true,
+        "data": state.task_queue.snapshot()
+    }))
+}
+
+impl TaskConfig {
+    /// What the task carries for `provider`; keys left unset are resolved from
+    /// the stored ones when the provider is built
+    pub(crate) fn provider_config(&self, provider: &str, embedding: bool) -> ProviderConfig {
+        match provider.trim().to_lowercase().as_str() {
+            "gemini" => ProviderConfig {
+                api_key: self.gemini_key.clone(),
+                ..Default::default()
+            },
+            "deepseek" => ProviderConfig {
+                api_key: self.deepseek_key.clone(),
+                ..Default::default()
+            },
+            "ollama" => ProviderConfig {
+                base_url: self.ollama_base_url.clone(),
+                model: if embedding {
+                    self.ollama_embedding_model.clone()
+                } else {
+                    self.ollama_chat_model.clone()
+                },
+                ..Default::default()
+            },
+            "openai_compatible" => ProviderConfig {
+                api_key: self.openai_compatible_key.clone(),
+                base_url: self.openai_compatible_base_url.clone(),
+                model: if embedding {
+                    self.openai_compatible_embedding_model.clone()
+                } else {
+                    self.openai_compatible_model.clone()
+                },
+                ..Default::default()
+            },
+            _ => ProviderConfig::default(),
+        }
+    }
+
+    pub fn from_request(req: &CreateTaskRequest) -> Self {
+        TaskConfig {
+            prompt: req.prompt.clone(),
+            target_count: req.target_count.unwrap_or(30),
+            deepseek_key: req.deepseek_api_key.clone(),
+            gemini_key: req.gemini_api_key.clone(),
+            specific_fakeid: req.specific_account_fakeid.clone(),
+            specific_name: req.specific_account_name.clone(),
+            // LLM Provider Config
+            keyword_provider: req
+                .keyword_provider
+                .clone()
+                .unwrap_or_else(|| "gemini".to_string()),
+            reasoning_provider: req
+                .reasoning_provider
+                .clone()
+                .unwrap_or_else(|| "gemini".to_string()),
+            embedding_provider: req
+                .embedding_provider
+                .clone()
+                .unwrap_or_else(|| "gemini".to_string()),
+            ollama_base_url: req.ollama_base_url.clone(),
+            ollama_embedding_model: req.ollama_embedding_model.clone(),
+            ollama_chat_model: req.ollama_chat_model.clone(),
+            openai_compatible_key: req.openai_compatible_api_key.clone(),
+            openai_compatible_base_url: req.openai_compatible_base_url.clone(),
+            openai_compatible_model: req.openai_compatible_model.clone(),
+            openai_compatible_embedding_model: req.openai_compatible_embedding_model.clone(),
+            search_speed: req.search_speed.clone().unwrap_or_else(|| "medium".to_string()),
+            deep_scan: req.deep_scan.unwrap_or(false),
+            fetch_comments: req.fetch_comments.unwrap_or(false),
+            comments_in_prompt: req.comments_in_prompt.unwrap_or(true),
+            dedup: req.dedup.clone(),
+            dedup_threshold: req.dedup_threshold,
+            feedback_tuning: req.feedback_tuning.unwrap_or(false),
+            ignore_blocklist: req.ignore_blocklist.unwrap_or(false),
+            blocked_fakeids: req.blocked_fakeids.clone().unwrap_or_default(),
+            allowlist_only: req.allowlist_only.unwrap_or(false),
+            allowed_fakeids: req.allowed_fakeids.clone(),
+            owner_id: None,
+            review_keywords: req.review_keywords.unwrap_or(false),
+            published_after: req.published_after,
+            published_before: req.published_before,
+            max_pages_per_account: req.max_pages_per_account,
+            expand_albums: req.expand_albums.unwrap_or(false),
+            search_mode: req.search_mode.clone(),
+            output_language: req
+                .output_language
+                .as_deref()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_string),
+            account_parallelism: req.account_parallelism,
+            prefilter: req.prefilter.clone(),
+            rank_candidates: req.rank_candidates.unwrap_or(false),
+            debug_candidates: req.debug_candidates.unwrap_or(false),
+        }
+    }
+
+    pub(crate) fn output_language(&self) -> &str {
+        self.output_language
+            .as_deref()
+            .unwrap_or(crate::llm::DEFAULT_OUTPUT_LANGUAGE)
+    }
+
+    /// Reject option values a worker could not run with
+    pub(crate) fn validate(&self) -> Result<(), AppError> {
+        self.publish_window().validate()?;
+        if !crate::api::digest::is_valid_language_tag(self.output_language()) {
+            return Err(AppError::BadRequest(format!(
+                "无效的语言代码: {}",
+                self.output_language()
+            )));
+        }
+        if let Some(rules) = &self.prefilter {
+            crate::prefilter::Prefilter::new(rules)
+                .map_err(|e| AppError::BadRequest(format!("无效的title_regex: {}", e)))?;
+        }
+        match self.search_mode.as_deref() {
+            Some(mode) if SearchMode::parse(mode).is_none() => Err(AppError::BadRequest(format!(
+                "不支持的search_mode: {} (accounts/articles/both)",
+                mode
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn publish_window(&self) -> PublishWindow {
+        PublishWindow {
+            after: self.published_after,
+            before: self.published_before,
+        }
+    }
+}
+
+/// Insert a pending task and its resumable state.
+/// `seen_urls` pre-seeds the checkpoint so already-known articles are skipped.
+pub(crate) async fn insert_task_record(
+    state: &AppState,
+    task_id: Uuid,
+    config: &TaskConfig,
+    schedule_id: Option<Uuid>,
+    seen_urls: Option<Vec<String>>,
+) -> Result<(), AppError> {
     let now = chrono::Utc::now().timestamp();
-    let target = req.target_count.unwrap_or(30);
 
     // Insert task into DB
     sqlx::query(
-        "INSERT INTO insight_tasks (id, prompt, status, keywords, target_count, processed_count, created_at, updated_at, completion_reason) VALUES ($1, $2, $3, $4::text[], $5, $6, $7, $8, $9)"
+        "INSERT INTO insight_tasks (id, prompt, status, keywords, target_count, processed_count, created_at, updated_at, completion_reason, schedule_id, owner_id, output_language) VALUES ($1, $2, $3, $4::text[], $5, $6, $7, $8, $9, $10, $11, $12)"
     )
     .bind(task_id)
-    .bind(&req.prompt)
+    .bind(&config.prompt)
     .bind("pending") // Initial status
-    .bind(&Vec::<String>::new())
-    .bind(target)
+    .bind(Vec::<String>::new())
+    .bind(config.target_count)
     .bind(0)
     .bind(now)
     .bind(now)
     .bind(Option::<String>::None) // completion_reason starts as None
+    .bind(schedule_id)
+    .bind(config.owner_id)
+    .bind(config.output_language())
     .execute(&state.db_pool)
     .await?;
 
-    // Spawn background worker
-    let state_clone = state.clone();
-    let prompt_clone = req.prompt.clone();
-    let deepseek_key = req.deepseek_api_key.clone();
-    let gemini_key = req.gemini_api_key.clone();
-    let target_count = target;
-    let specific_fakeid = req.specific_account_fakeid.clone();
-    let specific_name = req.specific_account_name.clone();
-    // LLM Provider Config
-    let keyword_provider = req
-        .keyword_provider
-        .clone()
-        .unwrap_or_else(|| "gemini".to_string());
-    let reasoning_provider = req
-        .reasoning_provider
-        .clone()
-        .unwrap_or_else(|| "gemini".to_string());
-    let embedding_provider = req
-        .embedding_provider
-        .clone()
-        .unwrap_or_else(|| "gemini".to_string());
-    let ollama_base_url = req.ollama_base_url.clone();
-    let ollama_embedding_model = req.ollama_embedding_model.clone();
-    let search_speed = req.search_speed.clone().unwrap_or_else(|| "medium".to_string());
+    let checkpoint = TaskCheckpoint {
+        seen_urls: seen_urls.unwrap_or_default(),
+        ..Default::default()
+    };
 
-    tokio::spawn(async move {
-        if let Err(e) = process_task(
-            state_clone,
-            task_id,
-            prompt_clone,
-            target_count,
-            deepseek_key,
-            gemini_key,
-            specific_fakeid,
-            specific_name,
-            keyword_provider,
-            reasoning_provider,
-            embedding_provider,
-            ollama_base_url,
-            ollama_embedding_model,
-            search_speed,
-        )
+    sqlx::query(
+        "INSERT INTO insight_task_state (task_id, config, checkpoint, updated_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(task_id)
+    .bind(serde_json::to_value(config).map_err(|e| AppError::Internal(e.to_string()))?)
+    .bind(serde_json::to_value(&checkpoint).map_err(|e| AppError::Internal(e.to_string()))?)
+    .bind(now)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Start another run of an existing task: fresh checkpoint, new config, articles kept.
+/// `target_count` is the number of additional articles wanted.
+pub(crate) async fn restart_task_run(
+    state: &AppState,
+    task_id: Uuid,
+    config: &mut TaskConfig,
+) -> Result<(), AppError> {
+    // Same count the worker resumes from
+    let existing: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected'",
+    )
+    .bind(task_id)
+    .fetch_one(&state.db_pool)
+    .await?;
+    config.target_count += existing as i32;
+
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        "UPDATE insight_tasks SET status = 'pending', completion_reason = NULL, target_count = $1, updated_at = $2 WHERE id = $3",
+    )
+    .bind(config.target_count)
+    .bind(now)
+    .bind(task_id)
+    .execute(&state.db_pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO insight_task_state (task_id, config, checkpoint, updated_at) VALUES ($1, $2, '{}'::jsonb, $3)
+        ON CONFLICT (task_id) DO UPDATE SET config = EXCLUDED.config, checkpoint = '{}'::jsonb, updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(task_id)
+    .bind(serde_json::to_value(&*config).map_err(|e| AppError::Internal(e.to_string()))?)
+    .bind(now)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Resume a failed or cancelled task from its last checkpoint
+pub async fn resume_task(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<ResumeTaskRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, req.id).await?;
+
+    let status: String = sqlx::query_scalar("SELECT status FROM insight_tasks WHERE id = $1")
+        .bind(req.id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+
+    if status != "failed" && status != "cancelled" {
+        return Err(AppError::BadRequest(format!(
+            "只有失败或已取消的任务可以恢复 (当前状态: {})",
+            status
+        )));
+    }
+
+    let mut config = stored_config(&state, req.id).await?;
+    config.deepseek_key = req.deepseek_api_key;
+    config.gemini_key = req.gemini_api_key;
+    config.openai_compatible_key = req.openai_compatible_api_key;
+
+    let auth_key = get_valid_auth_key(&state, config.owner_id)
         .await
-        {
-            tracing::error!("Task {} failed: {}", task_id, e);
-            // Update status to failed
-            let log_path = std::env::current_dir()
-                .unwrap_or_default()
-                .join("logs")
-                .join("wechat_insights.log");
-            let reason = format!("Unexpected Error: {}. Log: {:?}", e, log_path);
-            let _ = update_task_status(&state.clone(), task_id, "failed", Some(reason)).await;
-        }
-    });
+        .ok_or_else(|| AppError::BadRequest("请先登录微信公众平台".to_string()))?;
+    if let Err(e) = validate_wechat_session(&state, &auth_key).await {
+        return Err(AppError::BadRequest(format!(
+            "微信登录已过期，请重新登录: {}",
+            e
+        )));
+    }
+
+    // Guard against double resume: only one request wins the status flip
+    let updated = sqlx::query(
+        "UPDATE insight_tasks SET status = 'pending', completion_reason = NULL, updated_at = $1 WHERE id = $2 AND status IN ('failed', 'cancelled')",
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(AppError::BadRequest("任务已在运行".to_string()));
+    }
 
-    Ok(Json(CreateTaskResponse { id: task_id }))
+    tracing::info!("Resuming task {}", req.id);
+    spawn_worker(&state, req.id, config).await?;
+
+    Ok(Json(serde_json::json!({ "success": true, "id": req.id })))
 }
 
-/// List all tasks
-pub async fn list_tasks(State(state): State<AppState>) -> Result<Json<Vec<InsightTask>>, AppError> {
-    let tasks =
-        sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks ORDER BY created_at DESC")
-            .fetch_all(&state.db_pool)
+/// Persisted worker configuration of a task (API keys are not stored)
+pub(crate) async fn stored_config(state: &AppState, task_id: Uuid) -> Result<TaskConfig, AppError> {
+    let config: Option<(serde_json::Value,)> =
+        sqlx::query_as("SELECT config FROM insight_task_state WHERE task_id = $1")
+            .bind(task_id)
+            .fetch_optional(&state.db_pool)
             .await?;
+    let (config,) = config.ok_or(AppError::BadRequest("该任务没有可恢复的检查点".to_string()))?;
+    serde_json::from_value(config)
+        .map_err(|e| AppError::Internal(format!("Invalid task config: {}", e)))
+}
+
+/// Queue a pending task at the default priority
+pub(crate) async fn spawn_worker(
+    state: &AppState,
+    task_id: Uuid,
+    config: TaskConfig,
+) -> Result<(), AppError> {
+    state
+        .task_queue
+        .enqueue(
+            &state.db_pool,
+            task_id,
+            config,
+            crate::task_queue::DEFAULT_PRIORITY,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Run `process_task`, marking the task failed on error.
+/// A task cancelled while still queued is marked cancelled without running.
+pub(crate) async fn run_worker(state: AppState, task_id: Uuid, config: TaskConfig) {
+    if let Ok(true) = is_task_cancelled(&state, task_id).await {
+        tracing::info!("Task {} cancelled while queued", task_id);
+        let _ = update_task_status(
+            &state,
+            task_id,
+            "cancelled",
+            Some("Cancelled by user".to_string()),
+        )
+        .await;
+        return;
+    }
+    if let Err(e) = process_task(state.clone(), task_id, config).await {
+        if e.is::<crate::shutdown::Interrupted>() {
+            tracing::info!("Task {} stopped for shutdown", task_id);
+            let reason = crate::shutdown::INTERRUPTED_REASON.to_string();
+            let _ = update_task_status(&state, task_id, "failed", Some(reason)).await;
+            return;
+        }
+        tracing::error!("Task {} failed: {}", task_id, e);
+        // Update status to failed
+        let log_path = std::env::current_dir()
+            .unwrap_or_default()
+            .join("logs")
+            .join("wechat_insights.log");
+        let reason = format!("Unexpected Error: {}. Log: {:?}", e, log_path);
+        let _ = update_task_status(&state, task_id, "failed", Some(reason)).await;
+    }
+}
+
+/// List the caller's tasks (all tasks for admins)
+pub async fn list_tasks(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<Vec<InsightTask>>, AppError> {
+    let tasks = sqlx::query_as::<_, InsightTask>(
+        r#"
+        SELECT t.*, q.position AS queue_position
+        FROM insight_tasks t
+        LEFT JOIN (
+            SELECT task_id, ROW_NUMBER() OVER (ORDER BY priority DESC, seq) AS position
+            FROM task_queue
+        ) q ON q.task_id = t.id
+        WHERE $1 OR t.owner_id IS NOT DISTINCT FROM $2
+        ORDER BY t.created_at DESC
+        "#,
+    )
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .fetch_all(&state.db_pool)
+    .await?;
 
     Ok(Json(tasks))
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct GetTaskQuery {
+    /// Only articles carrying all these tags, comma-separated
+    pub tag: Option<String>,
+}
+
 /// Get task details and articles
 pub async fn get_task(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Path(id): Path<Uuid>,
+    Query(query): Query<GetTaskQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
     let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
         .bind(id)
         .fetch_optional(&state.db_pool)
         .await?
         .ok_or(AppError::NotFound("Task not found".to_string()))?;
 
+    let tags = crate::api::annotations::tag_filter(query.tag.as_deref());
     let articles = sqlx::query_as::<_, InsightArticle>(
-        "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY similarity DESC NULLS LAST",
+        "SELECT * FROM insight_articles WHERE task_id = $1 AND tags @> $2 ORDER BY similarity DESC NULLS LAST",
     )
     .bind(id)
+    .bind(&tags)
     .fetch_all(&state.db_pool)
     .await?;
 
+    let usage = crate::llm::usage::task_usage(&state.db_pool, id).await?;
+    let mut annotations = crate::api::annotations::task_annotations(&state.db_pool, id).await?;
+    if !tags.is_empty() {
+        annotations.retain(|n| articles.iter().any(|a| a.id == n.article_id));
+    }
+
     Ok(Json(serde_json::json!({
         "task": task,
-        "articles": articles
+        "articles": articles,
+        "annotations": annotations,
+        "usage": usage
     })))
 }
 
-// ============ Worker Logic ============
+#[derive(Debug, Default, Deserialize)]
+pub struct CandidatesQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    /// Only candidates dropped for this reason
+    pub reason: Option<String>,
+}
 
-async fn update_task_status(
-    state: &AppState,
-    id: Uuid,
-    status: &str,
-    reason: Option<String>,
-) -> anyhow::Result<()> {
+/// A scanned article the task did not keep (`debug_candidates`)
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RejectedCandidate {
+    pub id: i64,
+    pub title: String,
+    pub url: String,
+    pub account_name: Option<String>,
+    pub similarity: Option<f64>,
+    pub reason: String,
+    pub detail: Option<String>,
+    pub created_at: i64,
+}
+
+/// Page through the articles a task scanned but did not keep, in scan order
+pub async fn list_candidates(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<CandidatesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let offset = query.offset.unwrap_or(0).max(0);
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let reason = query.reason.as_deref().filter(|r| !r.is_empty());
+
+    let total: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM insight_candidates WHERE task_id = $1 AND ($2::text IS NULL OR reason = $2)",
+    )
+    .bind(id)
+    .bind(reason)
+    .fetch_one(&state.db_pool)
+    .await?;
+    let candidates = sqlx::query_as::<_, RejectedCandidate>(
+        r#"
+        SELECT id, title, url, account_name, similarity, reason, detail, created_at
+        FROM insight_candidates
+        WHERE task_id = $1 AND ($2::text IS NULL OR reason = $2)
+        ORDER BY id
+        OFFSET $3 LIMIT $4
+        "#,
+    )
+    .bind(id)
+    .bind(reason)
+    .bind(offset)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": candidates,
+        "total": total,
+        "offset": offset,
+        "limit": limit
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VerifyLinksRequest {
+    pub limit: Option<i64>,
+}
+
+/// Start checking whether the task's article links still resolve, updating
+/// their `status`. Each check is paced, so this returns before it finishes.
+pub async fn verify_links(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    body: Option<Json<VerifyLinksRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let limit = req.limit.unwrap_or(100).clamp(1, 500);
+    if !crate::link_status::spawn_task_check(state.db_pool.clone(), id, limit) {
+        return Err(AppError::BadRequest("该任务的链接正在检查中".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "started": true,
+        "limit": limit
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateKeywordsRequest {
+    /// Replacement keywords; the generated ones are kept when omitted
+    pub keywords: Option<Vec<String>>,
+    /// Resume the task with these keywords (default true)
+    pub approve: Option<bool>,
+    // Needed only when the server restarted during the review, as API keys
+    // are not persisted; otherwise the keys given at creation are used
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+}
+
+/// Keywords of a task, trimmed and without blanks or repeats
+fn clean_keywords(keywords: Vec<String>) -> Vec<String> {
+    let mut cleaned: Vec<String> = Vec::with_capacity(keywords.len());
+    for keyword in keywords {
+        let keyword = keyword.trim();
+        if !keyword.is_empty() && !cleaned.iter().any(|k| k == keyword) {
+            cleaned.push(keyword.to_string());
+        }
+    }
+    cleaned
+}
+
+/// Keywords of a task and whether they await review
+pub async fn get_keywords(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let (status, keywords): (String, Vec<String>) =
+        sqlx::query_as("SELECT status, keywords FROM insight_tasks WHERE id = $1")
+            .bind(id)
+            .fetch_one(&state.db_pool)
+            .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "status": status,
+            "awaiting_review": status == "awaiting_review",
+            "keywords": keywords
+        }
+    })))
+}
+
+/// Edit the keywords of a task awaiting review and, unless `approve` is false,
+/// queue it again to search with them
+pub async fn update_keywords(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateKeywordsRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let status: String = sqlx::query_scalar("SELECT status FROM insight_tasks WHERE id = $1")
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await?;
+    if status != "awaiting_review" {
+        return Err(AppError::BadRequest(format!(
+            "只有等待审核关键词的任务可以修改关键词 (当前状态: {})",
+            status
+        )));
+    }
+
+    if let Some(keywords) = req.keywords {
+        let keywords = clean_keywords(keywords);
+        if keywords.is_empty() {
+            return Err(AppError::BadRequest("关键词不能为空".to_string()));
+        }
+        sqlx::query("UPDATE insight_tasks SET keywords = $1, updated_at = $2 WHERE id = $3")
+            .bind(&keywords)
+            .bind(chrono::Utc::now().timestamp())
+            .bind(id)
+            .execute(&state.db_pool)
+            .await?;
+    }
+    let keywords: Vec<String> =
+        sqlx::query_scalar("SELECT keywords FROM insight_tasks WHERE id = $1")
+            .bind(id)
+            .fetch_one(&state.db_pool)
+            .await?;
+
+    if !req.approve.unwrap_or(true) {
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "keywords": keywords,
+            "resumed": false
+        })));
+    }
+    if keywords.is_empty() {
+        return Err(AppError::BadRequest("关键词不能为空".to_string()));
+    }
+
+    let paused = AWAITING_REVIEW.lock().unwrap().get(&id).cloned();
+    let config = match paused {
+        Some(config) => config,
+        None => {
+            let mut config = stored_config(&state, id).await?;
+            config.deepseek_key = req.deepseek_api_key;
+            config.gemini_key = req.gemini_api_key;
+            config.openai_compatible_key = req.openai_compatible_api_key;
+            config
+        }
+    };
+
+    // Guard against double approval: only one request wins the status flip
+    let updated = sqlx::query(
+        "UPDATE insight_tasks SET status = 'pending', completion_reason = NULL, updated_at = $1 WHERE id = $2 AND status = 'awaiting_review'",
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .bind(id)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+    if updated == 0 {
+        return Err(AppError::BadRequest("任务已在运行".to_string()));
+    }
+    AWAITING_REVIEW.lock().unwrap().remove(&id);
+
+    tracing::info!("Task {}: Keywords approved: {:?}", id, keywords);
+    spawn_worker(&state, id, config).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "keywords": keywords,
+        "resumed": true
+    })))
+}
+
+/// Most URLs accepted by one `add_articles` call
+const MAX_ADDED_ARTICLES: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct AddArticlesRequest {
+    pub urls: Vec<String>,
+    /// Drop articles the model judges irrelevant instead of keeping them (default false)
+    pub skip_irrelevant: Option<bool>,
+    // API keys are not persisted with the task; server keys are used when omitted
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+}
+
+/// Outcome of one URL of `add_articles`
+#[derive(Debug, Serialize)]
+struct AddedArticle {
+    url: String,
+    /// "added", "irrelevant", "exists" or "error"
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    similarity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl AddedArticle {
+    fn new(url: &str, status: &'static str) -> Self {
+        AddedArticle {
+            url: url.to_string(),
+            status,
+            id: None,
+            title: None,
+            similarity: None,
+            error: None,
+        }
+    }
+
+    fn error(url: &str, error: impl ToString) -> Self {
+        AddedArticle {
+            error: Some(error.to_string()),
+            ..Self::new(url, "error")
+        }
+    }
+}
+
+/// Whether `url` is a WeChat article link
+fn is_article_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|u| {
+        matches!(u.scheme(), "http" | "https")
+            && u.host_str() == Some("mp.weixin.qq.com")
+            && u.path().starts_with("/s")
+    })
+}
+
+/// Append articles found elsewhere to a task. Each page is read from the
+/// cache or fetched, then scored and summarized with the task's providers
+/// the way the worker does.
+pub async fn add_articles(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<AddArticlesRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let mut urls: Vec<String> = Vec::new();
+    for url in req.urls {
+        let url = url.trim().to_string();
+        if !url.is_empty() && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    if urls.is_empty() {
+        return Err(AppError::BadRequest("请提供文章链接".to_string()));
+    }
+    if urls.len() > MAX_ADDED_ARTICLES {
+        return Err(AppError::BadRequest(format!(
+            "一次最多添加 {} 篇文章",
+            MAX_ADDED_ARTICLES
+        )));
+    }
+
+    let status: String = sqlx::query_scalar("SELECT status FROM insight_tasks WHERE id = $1")
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await?;
+    if matches!(status.as_str(), "pending" | "processing" | "cancelling") {
+        return Err(AppError::BadRequest(
+            "任务运行中，请在任务结束后添加文章".to_string(),
+        ));
+    }
+
+    let mut config = stored_config(&state, id).await?;
+    config.deepseek_key = req.deepseek_api_key;
+    config.gemini_key = req.gemini_api_key;
+    config.openai_compatible_key = req.openai_compatible_api_key;
+    let meter = UsageMeter::new(state.db_pool.clone(), id);
+    let embedding_config = config.provider_config(&config.embedding_provider, true);
+    let dimension = embedding_config.dimension;
+    let embedder = CachedEmbedding::new(
+        Box::new(MeteredEmbedding::new(
+            crate::api::llm::embedding_provider(
+                &state,
+                &config.embedding_provider,
+                embedding_config,
+            )
+            .await?,
+            meter.clone(),
+            "embedding",
+        )),
+        state.db_pool.clone(),
+        dimension,
+    );
+    let reasoning_llm = MeteredChat::new(
+        crate::api::llm::chat_provider(
+            &state,
+            &config.reasoning_provider,
+            config.provider_config(&config.reasoning_provider, false),
+        )
+        .await?,
+        meter,
+        "insight",
+    );
+
+    let mut prompt_embedding = embedder.embed_one(&config.prompt).await?;
+    if config.feedback_tuning {
+        prompt_embedding =
+            crate::api::feedback::tuned_embedding(&state.db_pool, id, &prompt_embedding).await?;
+    }
+
+    let existing: std::collections::HashSet<String> =
+        sqlx::query_scalar("SELECT url FROM insight_articles WHERE task_id = $1")
+            .bind(id)
+            .fetch_all(&state.db_pool)
+            .await?
+            .into_iter()
+            .collect();
+    let client = reqwest::Client::builder()
+        .user_agent(WECHAT_USER_AGENT)
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
+    let skip_irrelevant = req.skip_irrelevant.unwrap_or(false);
+    let language = crate::llm::language_name(config.output_language());
+
+    let mut results = Vec::with_capacity(urls.len());
+    for url in &urls {
+        if !is_article_url(url) {
+            results.push(AddedArticle::error(url, "不是微信文章链接"));
+            continue;
+        }
+        if existing.contains(url) {
+            results.push(AddedArticle::new(url, "exists"));
+            continue;
+        }
+
+        let html = match article_html(&state, &client, url).await {
+            Ok(html) => html,
+            Err(e) => {
+                results.push(AddedArticle::error(url, e));
+                continue;
+            }
+        };
+        let page = crate::content::extract::extract(&html);
+        if page.title.is_empty() {
+            results.push(AddedArticle::error(url, "无法解析文章内容"));
+            continue;
+        }
+        let digest = page
+            .digest
+            .clone()
+            .unwrap_or_else(|| page.text().chars().take(200).collect());
+        let fakeid = crate::comments::js_var(&html, "biz")
+            .or_else(|| crate::comments::url_param(url, "__biz"));
+
+        let text_to_embed = format!("{} {}", page.title, digest);
+        let embedding = match embedder.embed_one(&text_to_embed).await {
+            Ok(v) => v,
+            Err(e) => {
+                results.push(AddedArticle::error(url, e));
+                continue;
+            }
+        };
+        let similarity = cosine_similarity(&prompt_embedding, &embedding);
+        let (is_relevant, insight) = match generate_insight(
+            &reasoning_llm,
+            &config.prompt,
+            &page.title,
+            &digest,
+            &language,
+        )
+        .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                results.push(AddedArticle::error(url, e));
+                continue;
+            }
+        };
+        if !is_relevant && skip_irrelevant {
+            results.push(AddedArticle {
+                title: Some(page.title),
+                similarity: Some(similarity),
+                ..AddedArticle::new(url, "irrelevant")
+            });
+            continue;
+        }
+
+        let article_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO insight_articles (id, task_id, title, url, account_name, account_fakeid, publish_time, similarity, insight, relevance_score, created_at, simhash, dedup_embedding) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+        )
+        .bind(article_id)
+        .bind(id)
+        .bind(&page.title)
+        .bind(url)
+        .bind(&page.account_name)
+        .bind(&fakeid)
+        .bind(page.publish_time)
+        .bind(similarity)
+        .bind(&insight)
+        .bind(0.8)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(crate::dedup::simhash(&text_to_embed) as i64)
+        .bind(&embedding)
+        .execute(&state.db_pool)
+        .await?;
+        let meta = crate::content::extract::metadata(&html, url);
+        record_page_metadata(&state, url, Some(meta)).await;
+        results.push(AddedArticle {
+            id: Some(article_id),
+            title: Some(page.title),
+            similarity: Some(similarity),
+            ..AddedArticle::new(url, if is_relevant { "added" } else { "irrelevant" })
+        });
+    }
+
+    let added = results.iter().filter(|r| r.id.is_some()).count() as i32;
+    if added > 0 {
+        sqlx::query(
+            "UPDATE insight_tasks SET processed_count = processed_count + $1, updated_at = $2 WHERE id = $3",
+        )
+        .bind(added)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(id)
+        .execute(&state.db_pool)
+        .await?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "added": added,
+        "data": results
+    })))
+}
+
+/// NotFound unless the task exists and belongs to `principal` (or it is an admin)
+pub(crate) async fn authorize_task(
+    state: &AppState,
+    principal: &Principal,
+    task_id: Uuid,
+) -> Result<(), AppError> {
+    let owner: Option<Option<Uuid>> =
+        sqlx::query_scalar("SELECT owner_id FROM insight_tasks WHERE id = $1")
+            .bind(task_id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    match owner {
+        Some(owner) if principal.can_access(owner) => Ok(()),
+        _ => Err(AppError::NotFound("Task not found".to_string())),
+    }
+}
+
+// ============ Worker Logic ============
+
+async fn update_task_status(
+    state: &AppState,
+    id: Uuid,
+    status: &str,
+    reason: Option<String>,
+) -> anyhow::Result<()> {
     if let Some(r) = reason {
         sqlx::query("UPDATE insight_tasks SET status = $1, updated_at = $2, completion_reason = $3 WHERE id = $4")
             .bind(status)
@@ -849,22 +2826,107 @@ async fn is_task_cancelled(state: &AppState, id: Uuid) -> anyhow::Result<bool> {
     Ok(status == "cancelling" || status == "cancelled")
 }
 
-async fn process_task(
-    state: AppState,
+async fn load_checkpoint(state: &AppState, task_id: Uuid) -> anyhow::Result<TaskCheckpoint> {
+    let row: Option<(serde_json::Value,)> =
+        sqlx::query_as("SELECT checkpoint FROM insight_task_state WHERE task_id = $1")
+            .bind(task_id)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    Ok(row
+        .and_then(|(v,)| serde_json::from_value(v).ok())
+        .unwrap_or_default())
+}
+
+async fn save_checkpoint(
+    state: &AppState,
     task_id: Uuid,
-    prompt: String,
-    target_count: i32,
-    deepseek_key: Option<String>,
-    gemini_key: Option<String>,
-    specific_fakeid: Option<String>,
-    specific_name: Option<String>,
-    keyword_provider: String,
-    reasoning_provider: String,
-    embedding_provider: String,
-    ollama_base_url: Option<String>,
-    ollama_embedding_model: Option<String>,
-    search_speed: String,
+    checkpoint: &TaskCheckpoint,
 ) -> anyhow::Result<()> {
+    sqlx::query("UPDATE insight_task_state SET checkpoint = $1, updated_at = $2 WHERE task_id = $3")
+        .bind(serde_json::to_value(checkpoint)?)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(task_id)
+        .execute(&state.db_pool)
+        .await?;
+    Ok(())
+}
+
+#[allow(clippy::wildcard_in_or_patterns, clippy::manual_clamp)]
+async fn process_task(state: AppState, task_id: Uuid, config: TaskConfig) -> anyhow::Result<()> {
+    use futures::stream::{self, StreamExt};
+
+    let keyword_config = config.provider_config(&config.keyword_provider, false);
+    let reasoning_config = config.provider_config(&config.reasoning_provider, false);
+    let embedding_config = config.provider_config(&config.embedding_provider, true);
+    let TaskConfig {
+        prompt,
+        target_count,
+        specific_fakeid,
+        specific_name,
+        keyword_provider,
+        reasoning_provider,
+        embedding_provider,
+        search_speed,
+        deep_scan,
+        fetch_comments,
+        comments_in_prompt,
+        dedup,
+        dedup_threshold,
+        feedback_tuning,
+        ignore_blocklist,
+        blocked_fakeids,
+        allowlist_only,
+        allowed_fakeids,
+        owner_id,
+        review_keywords,
+        max_pages_per_account,
+        expand_albums,
+        search_mode,
+        account_parallelism,
+        prefilter,
+        rank_candidates,
+        debug_candidates,
+        ..
+    } = config.clone();
+    let window = config.publish_window();
+    let language = crate::llm::language_name(config.output_language());
+    let search_mode = search_mode
+        .as_deref()
+        .and_then(SearchMode::parse)
+        .unwrap_or(SearchMode::Accounts);
+    // Without a window only the newest page is read unless asked for more
+    let max_pages = max_pages_per_account
+        .map(|p| p.clamp(1, MAX_PAGES_PER_ACCOUNT))
+        .unwrap_or(if window.is_set() {
+            MAX_PAGES_PER_ACCOUNT
+        } else {
+            1
+        });
+    // Build providers up front so a missing key fails the task at once
+    let meter = UsageMeter::new(state.db_pool.clone(), task_id);
+    let dimension = embedding_config.dimension;
+    let embedder = CachedEmbedding::new(
+        Box::new(MeteredEmbedding::new(
+            crate::api::llm::embedding_provider(&state, &embedding_provider, embedding_config)
+                .await?,
+            meter.clone(),
+            "embedding",
+        )),
+        state.db_pool.clone(),
+        dimension,
+    );
+    let reasoning_llm = MeteredChat::new(
+        crate::api::llm::chat_provider(&state, &reasoning_provider, reasoning_config).await?,
+        meter.clone(),
+        "insight",
+    );
+    let prefilter = crate::prefilter::Prefilter::new(&prefilter.unwrap_or_default())?;
+    let dedup_mode = dedup
+        .as_deref()
+        .and_then(crate::dedup::Mode::parse)
+        .unwrap_or(crate::dedup::Mode::Skip);
+    let mut checkpoint = load_checkpoint(&state, task_id).await?;
+
     tracing::info!(
         "Starting processing for task: {} (keyword:{}, reasoning:{}, embedding:{})",
         task_id,
@@ -892,6 +2954,62 @@ async fn process_task(
     );
 
     // 1. Determine Search Space
+    let specific_mode = specific_fakeid.is_some() && specific_name.is_some();
+    // Keywords drive account discovery and in-account article search
+    let needs_keywords =
+        (!specific_mode && !checkpoint.discovery_done) || search_mode.searches_articles();
+    let keywords: Vec<String> = if needs_keywords {
+        // 1. Generate Keywords (DeepSeek)
+        if is_task_cancelled(&state, task_id).await? {
+            update_task_status(
+                &state,
+                task_id,
+                "cancelled",
+                Some("Cancelled by user".to_string()),
+            )
+            .await?;
+            return Ok(());
+        }
+
+        // A resumed task keeps the keywords generated by the interrupted run
+        let stored_keywords: Vec<String> =
+            sqlx::query_scalar("SELECT keywords FROM insight_tasks WHERE id = $1")
+                .bind(task_id)
+                .fetch_one(&state.db_pool)
+                .await?;
+
+        if !stored_keywords.is_empty() {
+            tracing::info!("Task {}: Reusing keywords: {:?}", task_id, stored_keywords);
+            stored_keywords
+        } else {
+            let keyword_llm = MeteredChat::new(
+                crate::api::llm::chat_provider(&state, &keyword_provider, keyword_config).await?,
+                meter.clone(),
+                "keywords",
+            );
+            let keywords = generate_keywords(&keyword_llm, &prompt, keyword_count).await?;
+            tracing::info!("Task {}: Generated keywords: {:?}", task_id, keywords);
+
+            sqlx::query("UPDATE insight_tasks SET keywords = $1 WHERE id = $2")
+                .bind(&keywords)
+                .bind(task_id)
+                .execute(&state.db_pool)
+                .await?;
+
+            // Wait for the keywords to be approved via `update_keywords`,
+            // which queues the task again
+            if review_keywords {
+                tracing::info!("Task {}: Keywords awaiting review", task_id);
+                AWAITING_REVIEW.lock().unwrap().insert(task_id, config);
+                update_task_status(&state, task_id, "awaiting_review", None).await?;
+                return Ok(());
+            }
+            keywords
+        }
+    } else {
+        Vec::new()
+    };
+
     let accounts_to_scan = if let (Some(fakeid), Some(nickname)) = (specific_fakeid, specific_name)
     {
         // Mode A: Specific Account Targeting
@@ -913,39 +3031,33 @@ async fn process_task(
             fakeid
         );
         vec![AccountInfo { fakeid, nickname }]
+    } else if !search_mode.discovers_accounts() {
+        Vec::new()
+    } else if checkpoint.discovery_done {
+        tracing::info!(
+            "Task {}: Resuming with {} previously discovered accounts",
+            task_id,
+            checkpoint.accounts.len()
+        );
+        checkpoint.accounts.clone()
     } else {
         // Mode B: Keyword Discovery
-        // 1. Generate Keywords (DeepSeek)
-        if is_task_cancelled(&state, task_id).await? {
-            update_task_status(
-                &state,
-                task_id,
-                "cancelled",
-                Some("Cancelled by user".to_string()),
-            )
-            .await?;
-            return Ok(());
-        }
-
-        let keywords = generate_keywords(&keyword_provider, &prompt, keyword_count, deepseek_key.as_deref(), gemini_key.as_deref()).await?;
-        tracing::info!("Task {}: Generated keywords: {:?}", task_id, keywords);
-
-        sqlx::query("UPDATE insight_tasks SET keywords = $1 WHERE id = $2")
-            .bind(&keywords)
-            .bind(task_id)
-            .execute(&state.db_pool)
-            .await?;
-
         // 2. Discover Accounts
-        let auth_key = get_valid_auth_key(&state)
+        let auth_key = get_valid_auth_key(&state, owner_id)
             .await
             .ok_or(anyhow::anyhow!("No valid WeChat login session found"))?;
 
-        let mut discovered_accounts = Vec::new();
-        // Simple deduplication
-        let mut seen_fakeids = std::collections::HashSet::new();
+        // Simple deduplication (seeded from the checkpoint when resuming)
+        let mut seen_fakeids: std::collections::HashSet<String> =
+            checkpoint.accounts.iter().map(|a| a.fakeid.clone()).collect();
 
-        for keyword in keywords {
+        for keyword in keywords.iter().cloned() {
+            if checkpoint.keywords_done.contains(&keyword) {
+                continue;
+            }
+
+            // Everything before this keyword is checkpointed
+            crate::shutdown::check()?;
             if is_task_cancelled(&state, task_id).await? {
                 update_task_status(
                     &state,
@@ -1012,71 +3124,330 @@ async fn process_task(
             for acc in accounts {
                 if !seen_fakeids.contains(&acc.fakeid) {
                     seen_fakeids.insert(acc.fakeid.clone());
-                    discovered_accounts.push(acc);
+                    checkpoint.accounts.push(acc);
                 }
             }
+            checkpoint.keywords_done.push(keyword);
+            save_checkpoint(&state, task_id, &checkpoint).await?;
+        }
+
+        checkpoint.discovery_done = true;
+        save_checkpoint(&state, task_id, &checkpoint).await?;
+        checkpoint.accounts.clone()
+    };
+
+    // Discovered accounts go through the block/allow lists, read fresh on every run.
+    // In-account article search covers the specific account, or else the
+    // allowlisted and monitored accounts the lists allow.
+    let (accounts_to_scan, search_targets) = if specific_mode {
+        let targets = if search_mode.searches_articles() {
+            accounts_to_scan.clone()
+        } else {
+            Vec::new()
+        };
+        (accounts_to_scan, targets)
+    } else {
+        let filter = crate::api::account_list::AccountFilter::for_task(
+            &state.db_pool,
+            ignore_blocklist,
+            &blocked_fakeids,
+            allowlist_only,
+            allowed_fakeids.as_deref(),
+        )
+        .await?;
+        let discovered = accounts_to_scan.len();
+        let allowed: Vec<AccountInfo> = accounts_to_scan
+            .into_iter()
+            .filter(|a| filter.allows(&a.fakeid))
+            .collect();
+        if allowed.len() < discovered {
+            tracing::info!(
+                "Task {}: Skipping {} of {} accounts (account lists)",
+                task_id,
+                discovered - allowed.len(),
+                discovered
+            );
+        }
+        let targets = if search_mode.searches_articles() {
+            crate::api::account_list::search_targets(
+                &state.db_pool,
+                &filter,
+                allowed_fakeids.as_deref().unwrap_or_default(),
+                MAX_SEARCH_ACCOUNTS,
+            )
+            .await?
+            .into_iter()
+            .map(|(fakeid, nickname)| AccountInfo { fakeid, nickname })
+            .collect()
+        } else {
+            Vec::new()
+        };
+        (allowed, targets)
+    };
+    // Search targets are scanned first
+    let search_fakeids: std::collections::HashSet<String> =
+        search_targets.iter().map(|a| a.fakeid.clone()).collect();
+    let accounts_to_scan: Vec<AccountInfo> = search_targets
+        .into_iter()
+        .chain(
+            accounts_to_scan
+                .into_iter()
+                .filter(|a| !search_fakeids.contains(&a.fakeid)),
+        )
+        .collect();
+    if !search_fakeids.is_empty() {
+        tracing::info!(
+            "Task {}: Searching {} keywords in {} accounts",
+            task_id,
+            keywords.len(),
+            search_fakeids.len()
+        );
+    }
+
+    // 2. Prepare for Scanning
+    let auth_key = get_valid_auth_key(&state, owner_id)
+        .await
+        .ok_or(anyhow::anyhow!("No valid WeChat login session found"))?;
+
+    // Generate prompt embedding using configured provider
+    let mut prompt_embedding = embedder.embed_one(&prompt).await?;
+
+    if prompt_embedding.is_empty() {
+        return Err(anyhow::anyhow!("Embedding generation failed"));
+    }
+    if feedback_tuning {
+        prompt_embedding =
+            crate::api::feedback::tuned_embedding(&state.db_pool, task_id, &prompt_embedding)
+                .await?;
+    }
+
+    // Resume: skip URLs already considered and count articles already kept
+    let existing_urls: Vec<String> =
+        sqlx::query_scalar("SELECT url FROM insight_articles WHERE task_id = $1")
+            .bind(task_id)
+            .fetch_all(&state.db_pool)
+            .await?;
+    let article_count: i32 = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected'",
+    )
+    .bind(task_id)
+    .fetch_one(&state.db_pool)
+    .await? as i32;
+    let dedup_index = crate::dedup::Index::load(
+        &state.db_pool,
+        task_id,
+        dedup_threshold.unwrap_or_else(crate::dedup::default_threshold),
+    )
+    .await?;
+    let unique_urls: std::collections::HashSet<String> = checkpoint
+        .seen_urls
+        .iter()
+        .cloned()
+        .chain(existing_urls)
+        .collect();
+
+    // Safety break to prevent infinite loops if we can't find enough relevant articles
+    // Increased limit to support large target counts (e.g. 1000)
+    let max_scan_limit = (target_count * 50).min(100000).max(1000);
+    let scanned_count = checkpoint.scanned_count;
+    let deep_scan_client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()?;
+
+    if article_count > 0 || scanned_count > 0 {
+        tracing::info!(
+            "Task {}: Resuming scan ({} articles kept, {} scanned, {} accounts done)",
+            task_id,
+            article_count,
+            scanned_count,
+            checkpoint.accounts_scanned.len()
+        );
+    }
+
+    // Accounts are scanned by a few workers at once. WeChat calls still queue
+    // on the session's crawl lane; what overlaps is the embedding, deep scan
+    // and LLM work of different accounts.
+    let pending: Vec<AccountInfo> = accounts_to_scan
+        .into_iter()
+        .filter(|a| !checkpoint.accounts_scanned.contains(&a.fakeid))
+        .collect();
+    let parallelism = account_parallelism
+        .map(|n| n.clamp(1, MAX_ACCOUNT_PARALLELISM))
+        .unwrap_or(DEFAULT_ACCOUNT_PARALLELISM) as usize;
+    tracing::info!(
+        "Task {}: Scanning {} accounts, {} at a time",
+        task_id,
+        pending.len(),
+        parallelism
+    );
+    let scanner = AccountScanner {
+        state: &state,
+        task_id,
+        auth_key: &auth_key,
+        keywords: &keywords,
+        search_fakeids: &search_fakeids,
+        window,
+        history: search_mode.discovers_accounts(),
+        max_pages,
+        article_limit: article_limit as u32,
+        target_count,
+        max_scan_limit,
+        prompt: &prompt,
+        prompt_embedding: &prompt_embedding,
+        embedder: &embedder,
+        reasoning_llm: &reasoning_llm,
+        language: &language,
+        client: &deep_scan_client,
+        deep_scan,
+        fetch_comments,
+        comments_in_prompt,
+        dedup_mode,
+        expand_albums,
+        prefilter: &prefilter,
+        rank_candidates,
+        debug_candidates,
+        progress: tokio::sync::Mutex::new(ScanProgress {
+            article_count,
+            scanned_count,
+            unique_urls,
+            dedup_index,
+            checkpoint,
+        }),
+    };
+    let mut scans = stream::iter(pending)
+        .map(|account| scanner.scan(account))
+        .buffer_unordered(parallelism);
+    while let Some(scan) = scans.next().await {
+        match scan? {
+            AccountScan::Done { fakeid, seen } => {
+                let mut progress = scanner.progress.lock().await;
+                let checkpoint = &mut progress.checkpoint;
+                checkpoint.accounts_scanned.push(fakeid);
+                checkpoint.scanned_count += seen.len() as i32;
+                checkpoint.seen_urls.extend(seen);
+                save_checkpoint(&state, task_id, checkpoint).await?;
+            }
+            AccountScan::Skipped => {}
+            AccountScan::Cancelled => {
+                tracing::info!("Task {} cancelled by user", task_id);
+                update_task_status(
+                    &state,
+                    task_id,
+                    "cancelled",
+                    Some("User Cancelled".to_string()),
+                )
+                .await?;
+                return Ok(());
+            }
         }
-        discovered_accounts
-    };
-
-    // 2. Prepare for Scanning
-    let auth_key = get_valid_auth_key(&state)
-        .await
-        .ok_or(anyhow::anyhow!("No valid WeChat login session found"))?;
+    }
+    drop(scans);
+    let ScanProgress {
+        article_count,
+        scanned_count,
+        ..
+    } = scanner.progress.into_inner();
 
-    // Generate prompt embedding using configured provider
-    let prompt_embedding = generate_embedding_configurable(
-        &embedding_provider,
-        gemini_key.as_deref(),
-        ollama_base_url.as_deref(),
-        ollama_embedding_model.as_deref(),
-        &prompt,
-    )
-    .await?;
+    // Determine final reason
+    let reason = if article_count >= target_count {
+        format!("Target Reached ({}/{})", article_count, target_count)
+    } else if scanned_count >= max_scan_limit {
+        format!("Max Scan Limit Reached ({})", scanned_count)
+    } else {
+        "All Keywords Searched".to_string()
+    };
 
-    if prompt_embedding.is_empty() {
-        return Err(anyhow::anyhow!("Embedding generation failed"));
-    }
+    update_task_status(&state, task_id, "completed", Some(reason)).await?;
+    crate::notify::task_completed(state.db_pool.clone(), task_id);
+    tracing::info!(
+        "Task {} completed. Total articles: {} (Scanned: {})",
+        task_id,
+        article_count,
+        scanned_count
+    );
+    Ok(())
+}
 
-    let mut unique_urls = std::collections::HashSet::new();
-    let mut article_count = 0;
+/// Candidates embedded per request while scanning an account
+const EMBEDDING_BATCH_SIZE: usize = 32;
+/// Accounts of a task scanned at once unless the task sets `account_parallelism`
+const DEFAULT_ACCOUNT_PARALLELISM: u32 = 3;
+/// Upper bound on `account_parallelism`
+const MAX_ACCOUNT_PARALLELISM: u32 = 8;
+
+/// Scan state shared by the account workers of a task
+struct ScanProgress {
+    article_count: i32,
+    scanned_count: i32,
+    unique_urls: std::collections::HashSet<String>,
+    dedup_index: crate::dedup::Index,
+    checkpoint: TaskCheckpoint,
+}
 
-    // Safety break to prevent infinite loops if we can't find enough relevant articles
-    // Increased limit to support large target counts (e.g. 1000)
-    let max_scan_limit = (target_count * 50).min(100000).max(1000);
-    let mut scanned_count = 0;
+/// How an account worker ended
+enum AccountScan {
+    /// Every article was scored; `seen` are the URLs it considered
+    Done {
+        fakeid: String,
+        seen: Vec<String>,
+    },
+    /// Not scanned (limits reached or history unavailable); retried on resume
+    Skipped,
+    Cancelled,
+}
 
-    for account in accounts_to_scan {
-        if article_count >= target_count {
-            break;
-        }
-        if scanned_count >= max_scan_limit {
-            break;
-        }
-        if is_task_cancelled(&state, task_id).await? {
-            tracing::info!("Task {} cancelled by user", task_id);
-            update_task_status(
-                &state,
-                task_id,
-                "cancelled",
-                Some("User Cancelled".to_string()),
-            )
-            .await?;
-            return Ok(());
-        }
+/// What the account workers of `process_task` share
+struct AccountScanner<'a> {
+    state: &'a AppState,
+    task_id: Uuid,
+    auth_key: &'a str,
+    keywords: &'a [String],
+    /// Accounts searched by keyword before their history is read
+    search_fakeids: &'a std::collections::HashSet<String>,
+    window: PublishWindow,
+    /// Whether account history is read (not in article-only search)
+    history: bool,
+    max_pages: u32,
+    article_limit: u32,
+    target_count: i32,
+    max_scan_limit: i32,
+    prompt: &'a str,
+    prompt_embedding: &'a [f32],
+    embedder: &'a CachedEmbedding,
+    reasoning_llm: &'a MeteredChat,
+    language: &'a str,
+    client: &'a reqwest::Client,
+    deep_scan: bool,
+    fetch_comments: bool,
+    comments_in_prompt: bool,
+    dedup_mode: crate::dedup::Mode,
+    expand_albums: bool,
+    prefilter: &'a crate::prefilter::Prefilter,
+    /// Judge the most similar articles of a page first
+    rank_candidates: bool,
+    /// Record articles that are not kept in `insight_candidates`
+    debug_candidates: bool,
+    progress: tokio::sync::Mutex<ScanProgress>,
+}
 
-        // Reuse inner logic
-        let account = account; // Rebind for clarity matching previous logic context if needed
+impl AccountScanner<'_> {
+    /// Enough articles kept, or the scan limit reached
+    async fn finished(&self) -> bool {
+        let progress = self.progress.lock().await;
+        progress.article_count >= self.target_count || progress.scanned_count >= self.max_scan_limit
+    }
 
-        if article_count >= target_count {
-            break;
+    /// Score the articles of one account, keeping the relevant ones
+    async fn scan(&self, account: AccountInfo) -> anyhow::Result<AccountScan> {
+        let (state, task_id) = (self.state, self.task_id);
+        if self.finished().await {
+            return Ok(AccountScan::Skipped);
         }
-        if scanned_count >= max_scan_limit {
-            break;
+        crate::shutdown::check()?;
+        if is_task_cancelled(state, task_id).await? {
+            return Ok(AccountScan::Cancelled);
         }
-        // if unique_urls.len() >= 50 { break; } // REMOVED global limit
-
-        let fakeid = account.fakeid;
+        let fakeid = &account.fakeid;
 
         // Rate Limiting: 2~5s delay before fetching articles
         let delay = rand::thread_rng().gen_range(2000..=5000);
@@ -1095,122 +3466,278 @@ async fn process_task(
             fakeid
         );
 
-        // Robustness: Retry mechanism for fetching articles
-        let mut articles = Vec::new();
-        let mut fetch_attempts = 0;
-        while fetch_attempts < 3 {
-            match fetch_account_articles(&state, &auth_key, &fakeid, article_limit as u32).await {
-                Ok(res) => {
-                    articles = res;
-                    break;
-                }
-                Err(e) => {
-                    fetch_attempts += 1;
-                    tracing::warn!(
-                        "Task {}: Fetch articles failed for {} (Attempt {}/3): {}",
+        let mut hits = Vec::new();
+        if self.search_fakeids.contains(fakeid) {
+            match search_account_articles(state, self.auth_key, fakeid, self.keywords, self.window)
+                .await
+            {
+                Ok(found) => {
+                    tracing::info!(
+                        "Task {}: Keyword search found {} articles in {}",
                         task_id,
-                        account.nickname,
-                        fetch_attempts,
-                        e
+                        found.len(),
+                        account.nickname
                     );
-                    if fetch_attempts < 3 {
-                        tokio::time::sleep(tokio::time::Duration::from_millis(
-                            2000 * fetch_attempts as u64,
-                        ))
-                        .await;
-                    }
+                    hits = found;
                 }
+                Err(e) => tracing::warn!(
+                    "Task {}: Keyword search failed for {}: {}",
+                    task_id,
+                    account.nickname,
+                    e
+                ),
             }
         }
+        // Article-only search reads no history; the search hits make up the one page
+        let max_pages = if self.history { self.max_pages } else { 1 };
+
+        let mut seen = Vec::new();
+        // Vectors of queued articles embedded ahead of their turn
+        let mut embedded: HashMap<String, Vec<f32>> = HashMap::new();
+        let mut begin = 0;
+        for page_no in 0..max_pages {
+            if page_no > 0 {
+                let delay = rand::thread_rng().gen_range(2000..=5000);
+                tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+            }
+
+            // Robustness: Retry mechanism for fetching articles
+            let mut page = (!self.history).then(AccountPage::default);
+            let mut fetch_attempts = 0;
+            while page.is_none() && fetch_attempts < 3 {
+                match fetch_account_articles(
+                    state,
+                    self.auth_key,
+                    fakeid,
+                    begin,
+                    self.article_limit,
+                    self.window,
+                )
+                .await
+                {
+                    Ok(res) => {
+                        page = Some(res);
+                        break;
+                    }
+                    Err(e) => {
+                        fetch_attempts += 1;
+                        tracing::warn!(
+                            "Task {}: Fetch articles failed for {} (Attempt {}/3): {}",
+                            task_id,
+                            account.nickname,
+                            fetch_attempts,
+                            e
+                        );
+                        if fetch_attempts < 3 {
+                            tokio::time::sleep(tokio::time::Duration::from_millis(
+                                2000 * fetch_attempts as u64,
+                            ))
+                            .await;
+                        }
+                    }
+                }
+            }
 
-        if articles.is_empty() && fetch_attempts >= 3 {
-            tracing::error!(
-                "Task {}: Failed to fetch articles for {} after 3 attempts. Skipping.",
+            let Some(page) = page else {
+                tracing::error!(
+                    "Task {}: Failed to fetch articles for {} after 3 attempts. Skipping.",
+                    task_id,
+                    account.nickname
+                );
+                // Retried on resume unless earlier pages were scanned
+                if page_no == 0 {
+                    return Ok(AccountScan::Skipped);
+                }
+                break;
+            };
+            tracing::info!(
+                "Task {}: Fetched {} articles from {} (page {})",
                 task_id,
-                account.nickname
+                page.articles.len(),
+                account.nickname,
+                page_no + 1
             );
-            continue;
-        }
-        tracing::info!(
-            "Task {}: Fetched {} articles from {}",
-            task_id,
-            articles.len(),
-            account.nickname
-        );
 
-        for article in articles {
-            if article_count >= target_count {
-                break;
-            }
-            if unique_urls.contains(&article.url) {
-                continue;
+            // Best similarity on this page; None when nothing new was scanned.
+            // Album siblings of kept articles are queued behind the page.
+            let mut page_best: Option<f64> = None;
+            let mut queue: VecDeque<SimpleArticle> = std::mem::take(&mut hits)
+                .into_iter()
+                .chain(page.articles)
+                .collect();
+            if self.rank_candidates {
+                self.rank(&mut queue, &mut embedded).await;
             }
+            while let Some(article) = queue.pop_front() {
+                // Claimed under the lock so two workers never score the same URL
+                let scanned_count = {
+                    let mut progress = self.progress.lock().await;
+                    if progress.article_count >= self.target_count {
+                        break;
+                    }
+                    if !progress.unique_urls.insert(article.url.clone()) {
+                        continue;
+                    }
+                    progress.scanned_count += 1;
+                    progress.scanned_count
+                };
+                seen.push(article.url.clone());
 
-            // Deep check cancellations per article if needed (optional, maybe overkill to check PER article)
-            // But good for responsiveness
-            if scanned_count % 5 == 0 {
-                if is_task_cancelled(&state, task_id).await? {
-                    tracing::info!("Task {} cancelled by user", task_id);
-                    update_task_status(
-                        &state,
-                        task_id,
-                        "cancelled",
-                        Some("User Cancelled".to_string()),
-                    )
-                    .await?;
-                    return Ok(());
+                // Checked every few articles for responsiveness
+                if scanned_count % 5 == 0 && is_task_cancelled(state, task_id).await? {
+                    return Ok(AccountScan::Cancelled);
                 }
-            }
 
-            unique_urls.insert(article.url.clone());
-            scanned_count += 1;
-
-            let text_to_embed = format!("{} {}", article.title, article.digest);
-            let embedding = match generate_embedding_configurable(
-                &embedding_provider,
-                gemini_key.as_deref(),
-                ollama_base_url.as_deref(),
-                ollama_embedding_model.as_deref(),
-                &text_to_embed,
-            )
-            .await
-            {
-                Ok(v) => v,
-                Err(e) => {
-                    tracing::warn!(
-                        "Task {}: Failed to embed article '{}': {}",
+                if let Err(rejection) = self.prefilter.check(&article.title, &article.digest) {
+                    tracing::info!(
+                        "Task {}: Article '{}' rejected by pre-filter: {}",
                         task_id,
                         article.title,
-                        e
+                        rejection
                     );
+                    self.record_rejection(
+                        &account,
+                        &article,
+                        None,
+                        "prefilter",
+                        Some(rejection.to_string()),
+                    )
+                    .await;
                     continue;
                 }
-            };
 
-            let similarity = cosine_similarity(&prompt_embedding, &embedding);
-            tracing::info!(
-                "Task {}: Article '{}' similarity: {:.4}",
-                task_id,
-                article.title,
-                similarity
-            );
+                let text_to_embed = article.embedding_text();
+                let embedding = match self.embed_ahead(&article, &queue, &mut embedded).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tracing::warn!(
+                            "Task {}: Failed to embed article '{}': {}",
+                            task_id,
+                            article.title,
+                            e
+                        );
+                        self.record_rejection(
+                            &account,
+                            &article,
+                            None,
+                            "embedding_failed",
+                            Some(e.to_string()),
+                        )
+                        .await;
+                        continue;
+                    }
+                };
+
+                let digest_similarity = cosine_similarity(self.prompt_embedding, &embedding);
+
+                // Deep scan: the best matching passage of the full text can lift a vague digest
+                let mut chunk_similarity = None;
+                let mut best_chunk = None;
+                let mut page_meta = None;
+                if self.deep_scan {
+                    match deep_scan_article(
+                        state,
+                        self.client,
+                        &article.url,
+                        self.prompt_embedding,
+                        self.embedder,
+                    )
+                    .await
+                    {
+                        Ok((best, meta)) => {
+                            if let Some((score, chunk)) = best {
+                                chunk_similarity = Some(score);
+                                best_chunk = Some(chunk);
+                            }
+                            page_meta = Some(meta);
+                        }
+                        Err(e) => tracing::warn!(
+                            "Task {}: Deep scan failed for '{}': {}",
+                            task_id,
+                            article.title,
+                            e
+                        ),
+                    }
+                }
+                let similarity = digest_similarity.max(chunk_similarity.unwrap_or(0.0));
+                tracing::info!(
+                    "Task {}: Article '{}' similarity: {:.4} (chunk: {:?})",
+                    task_id,
+                    article.title,
+                    similarity,
+                    chunk_similarity
+                );
+
+                page_best = Some(page_best.map_or(similarity, |b| b.max(similarity)));
+
+                if similarity <= SIMILARITY_THRESHOLD {
+                    self.record_rejection(
+                        &account,
+                        &article,
+                        Some(similarity),
+                        "below_threshold",
+                        None,
+                    )
+                    .await;
+                    continue;
+                }
+                // Reposts under other accounts: skip, or link to the kept original
+                let simhash = crate::dedup::simhash(&text_to_embed);
+                let candidate = Candidate {
+                    account: &account,
+                    article: &article,
+                    similarity,
+                    chunk_similarity,
+                };
+                if let Some(original) = self.duplicate_of(simhash, &embedding).await {
+                    self.save_duplicate(&candidate, &original, page_meta)
+                        .await?;
+                    continue;
+                }
+
+                let mut insight_context = match &best_chunk {
+                    Some(chunk) => {
+                        format!("{}\n\nMost relevant passage: {}", article.digest, chunk)
+                    }
+                    None => article.digest.clone(),
+                };
 
-            if similarity > 0.4 {
-                // ... generation & filtering logic ...
+                let mut article_id = None;
+                if self.fetch_comments {
+                    match collect_article_comments(state, self.client, &article.url).await {
+                        Ok(Some((id, comments))) => {
+                            article_id = Some(id);
+                            if self.comments_in_prompt && !comments.is_empty() {
+                                insight_context.push_str("\n\n");
+                                insight_context.push_str(&crate::comments::prompt_section(
+                                    &comments,
+                                    PROMPT_COMMENTS,
+                                ));
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => tracing::warn!(
+                            "Task {}: Comment collection failed for '{}': {}",
+                            task_id,
+                            article.title,
+                            e
+                        ),
+                    }
+                }
                 // Retry mechanism for robustness
                 let mut attempts = 0;
                 let mut success = false;
                 let mut is_relevant = false;
                 let mut insight = String::new();
+                let mut last_error = String::new();
 
                 while attempts < 3 {
                     match generate_insight(
-                        &reasoning_provider,
-                        &prompt,
+                        self.reasoning_llm,
+                        self.prompt,
                         &article.title,
-                        &article.digest,
-                        deepseek_key.as_deref(),
-                        gemini_key.as_deref(),
+                        &insight_context,
+                        self.language,
                     )
                     .await
                     {
@@ -1229,6 +3756,7 @@ async fn process_task(
                                 attempts,
                                 e
                             );
+                            last_error = e.to_string();
                             if attempts < 3 {
                                 tokio::time::sleep(tokio::time::Duration::from_millis(
                                     2000 * attempts as u64,
@@ -1241,72 +3769,498 @@ async fn process_task(
 
                 if !success {
                     tracing::error!("Task {}: Failed to generate insight for article '{}' after 3 attempts. Skipping.", task_id, article.title);
+                    self.record_rejection(
+                        &account,
+                        &article,
+                        Some(similarity),
+                        "llm_failed",
+                        Some(last_error),
+                    )
+                    .await;
                     continue; // Skip this article, do NOT fail the task
                 }
 
-                // let (is_relevant, insight) = ... (Removed)
-
                 if !is_relevant {
                     tracing::info!(
                         "Task {}: Article '{}' filtered as IRRELEVANT by AI.",
                         task_id,
                         article.title
                     );
+                    self.record_rejection(
+                        &account,
+                        &article,
+                        Some(similarity),
+                        "irrelevant",
+                        Some(insight).filter(|i| !i.is_empty()),
+                    )
+                    .await;
                     continue;
                 }
 
-                let id = Uuid::new_v4();
-                sqlx::query(
-                         "INSERT INTO insight_articles (id, task_id, title, url, account_name, account_fakeid, publish_time, similarity, insight, relevance_score, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)"
-                     )
-                     .bind(id)
-                     .bind(task_id)
-                     .bind(&article.title)
-                     .bind(&article.url)
-                     .bind(&account.nickname)
-                     .bind(&fakeid) // Save fakeid
-                     .bind(article.create_time)
-                     .bind(similarity)
-                     .bind(&insight)
-                     .bind(0.8)
-                     .bind(chrono::Utc::now().timestamp())
-                     .execute(&state.db_pool)
-                     .await?;
-
-                article_count += 1;
+                // Other workers may have reached the target or kept a repost
+                // while the insight was generated
+                let mut progress = self.progress.lock().await;
+                if progress.article_count >= self.target_count {
+                    break;
+                }
+                let original = (self.dedup_mode != crate::dedup::Mode::Off)
+                    .then(|| progress.dedup_index.find(simhash, &embedding).cloned())
+                    .flatten();
+                if let Some(original) = original {
+                    drop(progress);
+                    self.save_duplicate(&candidate, &original, page_meta)
+                        .await?;
+                    continue;
+                }
+
+                let id = Uuid::new_v4();
+                sqlx::query(
+                    "INSERT INTO insight_articles (id, task_id, title, url, account_name, account_fakeid, publish_time, similarity, insight, relevance_score, created_at, chunk_similarity, article_id, simhash, dedup_embedding) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)"
+                )
+                .bind(id)
+                .bind(task_id)
+                .bind(&article.title)
+                .bind(&article.url)
+                .bind(&account.nickname)
+                .bind(fakeid)
+                .bind(article.create_time)
+                .bind(similarity)
+                .bind(&insight)
+                .bind(0.8)
+                .bind(chrono::Utc::now().timestamp())
+                .bind(chunk_similarity)
+                .bind(&article_id)
+                .bind(simhash as i64)
+                .bind(&embedding)
+                .execute(&state.db_pool)
+                .await?;
+                progress.dedup_index.insert(crate::dedup::Entry {
+                    id,
+                    simhash,
+                    embedding,
+                    insight: Some(insight),
+                });
+                progress.article_count += 1;
+                sqlx::query("UPDATE insight_tasks SET processed_count = $1 WHERE id = $2")
+                    .bind(progress.article_count)
+                    .bind(task_id)
+                    .execute(&state.db_pool)
+                    .await?;
+                drop(progress);
+                record_page_metadata(state, &article.url, page_meta).await;
+
+                if self.expand_albums && similarity >= ALBUM_MIN_SIMILARITY {
+                    let mut expanded = self
+                        .progress
+                        .lock()
+                        .await
+                        .checkpoint
+                        .albums_expanded
+                        .clone();
+                    let known = expanded.len();
+                    match album_siblings(state, self.client, &article.url, &mut expanded).await {
+                        Ok(siblings) => {
+                            let mut progress = self.progress.lock().await;
+                            for album_id in expanded.drain(known..) {
+                                if !progress.checkpoint.albums_expanded.contains(&album_id) {
+                                    progress.checkpoint.albums_expanded.push(album_id);
+                                }
+                            }
+                            let before = queue.len();
+                            queue.extend(siblings.into_iter().filter(|a| {
+                                self.window.contains(a.create_time)
+                                    && !progress.unique_urls.contains(&a.url)
+                            }));
+                            if queue.len() > before {
+                                tracing::info!(
+                                    "Task {}: Queued {} album articles from '{}'",
+                                    task_id,
+                                    queue.len() - before,
+                                    article.title
+                                );
+                            }
+                        }
+                        Err(e) => tracing::warn!(
+                            "Task {}: Album expansion failed for '{}': {}",
+                            task_id,
+                            article.title,
+                            e
+                        ),
+                    }
+                }
+            }
+
+            if !page.more || self.finished().await {
+                break;
+            }
+            // An account whose older posts stopped matching is not paged further
+            if let Some(best) = page_best.filter(|b| *b <= SIMILARITY_THRESHOLD) {
+                tracing::info!(
+                    "Task {}: Stopping after page {} of '{}' (best similarity {:.4})",
+                    task_id,
+                    page_no + 1,
+                    account.nickname,
+                    best
+                );
+                break;
+            }
+            begin = page.next_begin;
+        }
+
+        Ok(AccountScan::Done {
+            fakeid: account.fakeid,
+            seen,
+        })
+    }
+
+    /// Embedding of `article`. Unless it was embedded ahead already, the next
+    /// unscanned articles of `queue` go in the same request and their vectors
+    /// are kept in `embedded` for their turn.
+    async fn embed_ahead(
+        &self,
+        article: &SimpleArticle,
+        queue: &VecDeque<SimpleArticle>,
+        embedded: &mut HashMap<String, Vec<f32>>,
+    ) -> anyhow::Result<Vec<f32>> {
+        if let Some(embedding) = embedded.remove(&article.url) {
+            return Ok(embedding);
+        }
+        let batch: Vec<&SimpleArticle> = {
+            let progress = self.progress.lock().await;
+            std::iter::once(article)
+                .chain(queue.iter().filter(|a| {
+                    !progress.unique_urls.contains(&a.url)
+                        && !embedded.contains_key(&a.url)
+                        && self.prefilter.check(&a.title, &a.digest).is_ok()
+                }))
+                .take(EMBEDDING_BATCH_SIZE)
+                .collect()
+        };
+        let texts: Vec<String> = batch.iter().map(|a| a.embedding_text()).collect();
+        let vectors = self.embedder.embed(&texts).await?;
+        if vectors.len() != batch.len() {
+            return Err(anyhow::anyhow!(
+                "{} embeddings returned for {} texts",
+                vectors.len(),
+                batch.len()
+            ));
+        }
+        let mut vectors = batch.into_iter().zip(vectors);
+        let (_, embedding) = vectors.next().expect("batch starts with the article");
+        embedded.extend(vectors.map(|(a, v)| (a.url.clone(), v)));
+        Ok(embedding)
+    }
+
+    /// Embed the unscanned articles of `queue` that pass the pre-filter and
+    /// order it by similarity to the prompt, best first, so the target is
+    /// reached with fewer LLM calls. Articles whose batch failed go last and
+    /// are embedded again on their turn.
+    async fn rank(
+        &self,
+        queue: &mut VecDeque<SimpleArticle>,
+        embedded: &mut HashMap<String, Vec<f32>>,
+    ) {
+        let pending: Vec<&SimpleArticle> = {
+            let progress = self.progress.lock().await;
+            queue
+                .iter()
+                .filter(|a| {
+                    !progress.unique_urls.contains(&a.url)
+                        && !embedded.contains_key(&a.url)
+                        && self.prefilter.check(&a.title, &a.digest).is_ok()
+                })
+                .collect()
+        };
+        for batch in pending.chunks(EMBEDDING_BATCH_SIZE) {
+            let texts: Vec<String> = batch.iter().map(|a| a.embedding_text()).collect();
+            match self.embedder.embed(&texts).await {
+                Ok(vectors) if vectors.len() == batch.len() => {
+                    embedded.extend(batch.iter().map(|a| a.url.clone()).zip(vectors));
+                }
+                Ok(vectors) => tracing::warn!(
+                    "Task {}: {} embeddings returned for {} texts while ranking",
+                    self.task_id,
+                    vectors.len(),
+                    batch.len()
+                ),
+                Err(e) => tracing::warn!(
+                    "Task {}: Failed to embed candidates for ranking: {}",
+                    self.task_id,
+                    e
+                ),
+            }
+        }
+
+        let mut scored: Vec<(Option<f64>, SimpleArticle)> = queue
+            .drain(..)
+            .map(|a| {
+                let score = embedded
+                    .get(&a.url)
+                    .map(|v| cosine_similarity(self.prompt_embedding, v));
+                (score, a)
+            })
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        queue.extend(scored.into_iter().map(|(_, a)| a));
+    }
+
+    /// Add a scanned article that is not kept to `insight_candidates` when the
+    /// task debugs its candidates, once per URL. Failures are only logged.
+    async fn record_rejection(
+        &self,
+        account: &AccountInfo,
+        article: &SimpleArticle,
+        similarity: Option<f64>,
+        reason: &str,
+        detail: Option<String>,
+    ) {
+        if !self.debug_candidates {
+            return;
+        }
+        let result = sqlx::query(
+            "INSERT INTO insight_candidates (task_id, title, url, account_name, similarity, reason, detail, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (task_id, url) DO NOTHING",
+        )
+        .bind(self.task_id)
+        .bind(&article.title)
+        .bind(&article.url)
+        .bind(&account.nickname)
+        .bind(similarity)
+        .bind(reason)
+        .bind(detail)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.state.db_pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                "Task {}: Failed to record rejected candidate '{}': {}",
+                self.task_id,
+                article.title,
+                e
+            );
+        }
+    }
+
+    /// Kept article this one reposts, unless dedup is off
+    async fn duplicate_of(&self, simhash: u64, embedding: &[f32]) -> Option<crate::dedup::Entry> {
+        if self.dedup_mode == crate::dedup::Mode::Off {
+            return None;
+        }
+        let progress = self.progress.lock().await;
+        progress.dedup_index.find(simhash, embedding).cloned()
+    }
+
+    /// A repost of `original`: linked to it in "link" mode, dropped in "skip" mode
+    async fn save_duplicate(
+        &self,
+        candidate: &Candidate<'_>,
+        original: &crate::dedup::Entry,
+        page_meta: Option<PageMetadata>,
+    ) -> anyhow::Result<()> {
+        let article = candidate.article;
+        tracing::info!(
+            "Task {}: Article '{}' duplicates {}",
+            self.task_id,
+            article.title,
+            original.id
+        );
+        if self.dedup_mode != crate::dedup::Mode::Link {
+            self.record_rejection(
+                candidate.account,
+                article,
+                Some(candidate.similarity),
+                "duplicate",
+                Some(original.id.to_string()),
+            )
+            .await;
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO insight_articles (id, task_id, title, url, account_name, account_fakeid, publish_time, similarity, insight, relevance_score, created_at, chunk_similarity, duplicates_of) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"
+        )
+        .bind(Uuid::new_v4())
+        .bind(self.task_id)
+        .bind(&article.title)
+        .bind(&article.url)
+        .bind(&candidate.account.nickname)
+        .bind(&candidate.account.fakeid)
+        .bind(article.create_time)
+        .bind(candidate.similarity)
+        .bind(&original.insight)
+        .bind(0.8)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(candidate.chunk_similarity)
+        .bind(original.id)
+        .execute(&self.state.db_pool)
+        .await?;
+        record_page_metadata(self.state, &article.url, page_meta).await;
+        Ok(())
+    }
+}
+
+/// An article that passed the similarity threshold
+struct Candidate<'a> {
+    account: &'a AccountInfo,
+    article: &'a SimpleArticle,
+    similarity: f64,
+    chunk_similarity: Option<f64>,
+}
+
+// ============ Helpers ============
+
+/// Characters per chunk when deep scanning an article
+const DEEP_SCAN_CHUNK_CHARS: usize = 800;
+/// Upper bound on chunks embedded per article, keeps long articles affordable
+const DEEP_SCAN_MAX_CHUNKS: usize = 12;
+/// Comments added to the insight prompt when comment collection is on
+const PROMPT_COMMENTS: usize = 5;
+/// Similarity above which an article goes on to the reasoning model; a
+/// history page with nothing above it ends paging of that account
+const SIMILARITY_THRESHOLD: f64 = 0.4;
+/// Similarity a kept article needs for its albums to be expanded
+const ALBUM_MIN_SIMILARITY: f64 = 0.5;
+/// Articles queued per expanded album
+const MAX_ALBUM_ARTICLES: usize = 30;
+
+/// Split text into chunks of about `max_chars`, breaking on line boundaries.
+/// Lines longer than `max_chars` are split on their own.
+fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let chars: Vec<char> = line.chars().collect();
+        for piece in chars.chunks(max_chars) {
+            if current_len + piece.len() > max_chars && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.extend(piece);
+            current_len += piece.len();
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Article HTML from `article_content`, fetched when not cached
+async fn article_html(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+) -> anyhow::Result<String> {
+    let cached =
+        repository::articles::find(&state.db_pool, None, Some(url), Freshness::Any).await?;
+
+    match cached {
+        Some(article) => Ok(article.content),
+        None => fetch_html_content(client, &state.downloads, url, None).await,
+    }
+}
 
-                sqlx::query("UPDATE insight_tasks SET processed_count = $1 WHERE id = $2")
-                    .bind(article_count)
-                    .bind(task_id)
-                    .execute(&state.db_pool)
-                    .await?;
-            }
+/// Articles of the albums a kept article belongs to, skipping albums in
+/// `expanded` and adding the new ones to it. Album entries carry no digest,
+/// so the album title stands in for it.
+async fn album_siblings(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+    expanded: &mut Vec<String>,
+) -> anyhow::Result<Vec<SimpleArticle>> {
+    let html = article_html(state, client, url).await?;
+    let Some(biz) = crate::album::page_biz(&html, url) else {
+        return Ok(vec![]);
+    };
+
+    let mut siblings = Vec::new();
+    for album_id in crate::album::album_ids(&html) {
+        if expanded.contains(&album_id) {
+            continue;
         }
-    } // End accounts_to_scan loop
+        expanded.push(album_id.clone());
+        let (title, articles) =
+            crate::album::articles(client, &biz, &album_id, MAX_ALBUM_ARTICLES, Priority::Task)
+                .await?;
+        let title = title.unwrap_or_default();
+        siblings.extend(articles.into_iter().map(|a| SimpleArticle {
+            title: a.title,
+            digest: title.clone(),
+            url: a.url,
+            create_time: a.create_time,
+        }));
+    }
+    Ok(siblings)
+}
 
-    // Determine final reason
-    let reason = if article_count >= target_count {
-        format!("Target Reached ({}/{})", article_count, target_count)
-    } else if scanned_count >= max_scan_limit {
-        format!("Max Scan Limit Reached ({})", scanned_count)
-    } else {
-        "All Keywords Searched".to_string()
-    };
+/// Collect and store an article's comments; `None` when comments are disabled
+async fn collect_article_comments(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+) -> anyhow::Result<Option<(String, Vec<crate::comments::Comment>)>> {
+    let html = article_html(state, client, url).await?;
+    crate::comments::collect(&state.db_pool, client, &html, url, Priority::Task).await
+}
 
-    update_task_status(&state, task_id, "completed", Some(reason)).await?;
-    tracing::info!(
-        "Task {} completed. Total articles: {} (Scanned: {})",
-        task_id,
-        article_count,
-        scanned_count
-    );
-    Ok(())
+/// Fetch an article's full text and return its best-matching chunk and similarity.
+/// Uses the cached copy in `article_content` when there is one.
+#[allow(clippy::too_many_arguments)]
+async fn deep_scan_article(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+    prompt_embedding: &[f32],
+    embedder: &dyn EmbeddingProvider,
+) -> anyhow::Result<(Option<(f64, String)>, PageMetadata)> {
+    let html = article_html(state, client, url).await?;
+    let meta = crate::content::extract::metadata(&html, url);
+    let text = crate::content::extract::extract(&html).text();
+    let mut best: Option<(f64, String)> = None;
+    for chunk in chunk_text(&text, DEEP_SCAN_CHUNK_CHARS)
+        .into_iter()
+        .take(DEEP_SCAN_MAX_CHUNKS)
+    {
+        let embedding = embedder.embed_one(&chunk).await?;
+        let score = cosine_similarity(prompt_embedding, &embedding);
+        if best.as_ref().is_none_or(|(b, _)| score > *b) {
+            best = Some((score, chunk));
+        }
+    }
+    Ok((best, meta))
 }
 
-// ============ Helpers ============
+/// Record page metadata on the insight articles of `url`. Without a page
+/// fetched for them, the stored copy is read instead, if there is one.
+async fn record_page_metadata(state: &AppState, url: &str, meta: Option<PageMetadata>) {
+    let pool = &state.db_pool;
+    let result = async {
+        let meta = match meta {
+            Some(meta) => meta,
+            None => {
+                let stored =
+                    repository::articles::find(pool, None, Some(url), Freshness::Any).await?;
+                match stored {
+                    Some(stored) => crate::content::extract::metadata(&stored.content, url),
+                    None => return Ok(()),
+                }
+            }
+        };
+        let id = repository::articles::resolve_id(pool, url).await?;
+        repository::articles::save_metadata(pool, &id, url, &meta).await
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to record page metadata of {}: {}", url, e);
+    }
+}
 
 // Simple cosine similarity
-fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+pub(crate) fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     let dot_product: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
     let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
     let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
@@ -1317,77 +4271,43 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
     }
 }
 
-async fn get_valid_auth_key(state: &AppState) -> Option<String> {
-    // Return the most recently created valid auth key (not expired, ordered by created_at DESC)
+/// Most recently created valid auth key among sessions owned by `owner`.
+/// `None` means the sessions without an owner (shared tokens, unowned tasks
+/// and schedules), never any user's.
+pub(crate) async fn get_valid_auth_key(state: &AppState, owner: Option<Uuid>) -> Option<String> {
     let now = chrono::Utc::now().timestamp();
-    let row = sqlx::query(
-        "SELECT auth_key FROM cookies WHERE expires_at > $1 ORDER BY created_at DESC LIMIT 1",
+    let sessions: Vec<(String, Option<Uuid>)> = sqlx::query_as(
+        "SELECT auth_key, owner_id FROM cookies WHERE expires_at > $1 ORDER BY created_at DESC",
     )
     .bind(now)
-    .fetch_optional(&state.db_pool)
+    .fetch_all(&state.db_pool)
     .await
-    .ok()??;
+    .ok()?;
+
+    pick_session(&sessions, owner).map(str::to_string)
+}
 
-    Some(row.get(0))
+/// First of `sessions` (newest first) owned by exactly `owner`
+fn pick_session(sessions: &[(String, Option<Uuid>)], owner: Option<Uuid>) -> Option<&str> {
+    sessions
+        .iter()
+        .find(|(_, session_owner)| *session_owner == owner)
+        .map(|(auth_key, _)| auth_key.as_str())
 }
 
 /// Validate WeChat session by making a simple API call
-async fn validate_wechat_session(state: &AppState, auth_key: &str) -> anyhow::Result<()> {
-    let token = state
-        .cookie_store
-        .get_token(auth_key)
-        .await?
-        .ok_or(anyhow::anyhow!("Token not found"))?;
-    let cookie = state
-        .cookie_store
-        .get_cookie(auth_key)
+pub(crate) async fn validate_wechat_session(
+    state: &AppState,
+    auth_key: &str,
+) -> anyhow::Result<()> {
+    MpClient::for_auth_key(state, auth_key)
         .await?
-        .ok_or(anyhow::anyhow!("Cookie not found"))?;
-    let cookie_str = cookie.to_cookie_header();
-
-    // Make a simple search request to validate session
-    let client = reqwest::Client::builder().no_proxy().build()?;
-    let resp = client
-        .get("https://mp.weixin.qq.com/cgi-bin/searchbiz")
-        .query(&[
-            ("action", "search_biz"),
-            ("begin", "0"),
-            ("count", "1"),
-            ("query", "test"),
-            ("token", &token),
-            ("lang", "zh_CN"),
-            ("f", "json"),
-            ("ajax", "1"),
-        ])
-        .header("Cookie", cookie_str)
-        .header("User-Agent", WECHAT_USER_AGENT)
-        .send()
-        .await?;
-
-    let text = resp.text().await?;
-    let json: serde_json::Value =
-        serde_json::from_str(&text).map_err(|e| anyhow::anyhow!("JSON parse error: {}", e))?;
-
-    // Check for session error
-    if let Some(ret) = json
-        .get("base_resp")
-        .and_then(|r| r.get("ret"))
-        .and_then(|v| v.as_i64())
-    {
-        if ret != 0 {
-            let msg = json
-                .get("base_resp")
-                .and_then(|r| r.get("err_msg"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown error");
-            return Err(anyhow::anyhow!("Session invalid ({}): {}", ret, msg));
-        }
-    }
-
-    Ok(())
+        .validate()
+        .await
+        .map_err(|e| anyhow::anyhow!("Session invalid: {}", e))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AccountInfo {
     fakeid: String,
     nickname: String,
@@ -1401,271 +4321,218 @@ struct SimpleArticle {
     create_time: i64,
 }
 
+impl SimpleArticle {
+    /// What the similarity to the prompt is computed on
+    fn embedding_text(&self) -> String {
+        format!("{} {}", self.title, self.digest)
+    }
+}
+
+/// Most accounts searched by keyword (`search_mode` "articles" / "both")
+const MAX_SEARCH_ACCOUNTS: usize = 20;
+/// Publish entries requested per keyword in an in-account search
+const SEARCH_PAGE_SIZE: u32 = 10;
+
+/// Where a task finds candidate articles (`search_mode`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SearchMode {
+    /// Discover accounts by keyword and read their history
+    Accounts,
+    /// Search monitored accounts for the keywords (`search_field=7`)
+    Articles,
+    Both,
+}
+
+impl SearchMode {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "accounts" => Some(SearchMode::Accounts),
+            "articles" => Some(SearchMode::Articles),
+            "both" => Some(SearchMode::Both),
+            _ => None,
+        }
+    }
+
+    fn discovers_accounts(self) -> bool {
+        self != SearchMode::Articles
+    }
+
+    fn searches_articles(self) -> bool {
+        self != SearchMode::Accounts
+    }
+}
+
+/// Most history pages read per account; also the depth used with a publish window
+const MAX_PAGES_PER_ACCOUNT: u32 = 50;
+
+/// One history page of an account, filtered to the publish window
+#[derive(Debug, Default)]
+struct AccountPage {
+    articles: Vec<SimpleArticle>,
+    /// `begin` of the next, older page
+    next_begin: u32,
+    /// Whether older pages may still hold articles in the window
+    more: bool,
+}
+
+/// Publish time range of a task's articles, unix seconds (`before` exclusive)
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PublishWindow {
+    pub after: Option<i64>,
+    pub before: Option<i64>,
+}
+
+impl PublishWindow {
+    fn is_set(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    fn contains(&self, create_time: i64) -> bool {
+        self.after.is_none_or(|t| create_time >= t) && self.before.is_none_or(|t| create_time < t)
+    }
+
+    /// Whether a history page whose newest article is `newest` lies wholly
+    /// before the window, so older pages need not be read. Pinned articles
+    /// can be old, hence the newest rather than the last one.
+    fn passed(&self, newest: i64) -> bool {
+        self.after.is_some_and(|t| newest < t)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), AppError> {
+        match (self.after, self.before) {
+            (Some(after), Some(before)) if after >= before => Err(AppError::BadRequest(
+                "published_after必须早于published_before".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
 async fn search_accounts(
     state: &AppState,
     auth_key: &str,
     keyword: &str,
     limit: u32,
 ) -> anyhow::Result<Vec<AccountInfo>> {
-    // NOTE: This duplicates logic from web.rs, ideally refactor.
-    // For now, implementing specialized client logic.
-    let token = state
-        .cookie_store
-        .get_token(auth_key)
-        .await?
-        .ok_or(anyhow::anyhow!("Token not found"))?;
-    let cookie = state
-        .cookie_store
-        .get_cookie(auth_key)
-        .await?
-        .ok_or(anyhow::anyhow!("Cookie not found"))?;
-    let cookie_str = cookie.to_cookie_header();
-    let count_str = limit.to_string();
-
-    let client = reqwest::Client::builder().no_proxy().build()?;
-    let resp = client
-        .get("https://mp.weixin.qq.com/cgi-bin/searchbiz")
-        .query(&[
-            ("action", "search_biz"),
-            ("begin", "0"),
-            ("count", &count_str),
-            ("query", keyword),
-            ("token", &token),
-            ("lang", "zh_CN"),
-            ("f", "json"),
-            ("ajax", "1"),
-        ])
-        .header("Cookie", cookie_str)
-        .header("User-Agent", WECHAT_USER_AGENT)
-        .send()
-        .await?;
-
-    let text = resp.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&text)
-        .map_err(|e| anyhow::anyhow!("WeChat Search Biz JSON Error: {} | Body: {}", e, text))?;
+    let client = MpClient::for_auth_key(state, auth_key).await?;
+    let accounts = client
+        .search_accounts(keyword, limit, Priority::Task)
+        .await
+        .inspect_err(|e| tracing::error!("WeChat Search Biz Error: {}", e))?;
+    Ok(accounts
+        .into_iter()
+        .map(|a| AccountInfo {
+            fakeid: a.fakeid,
+            nickname: a.nickname,
+        })
+        .collect())
+}
 
-    // Check for base_resp error
-    if let Some(ret) = json
-        .get("base_resp")
-        .and_then(|r| r.get("ret"))
-        .and_then(|v| v.as_i64())
-    {
-        if ret != 0 {
-            let msg = json
-                .get("base_resp")
-                .and_then(|r| r.get("err_msg"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown error");
-            tracing::error!("WeChat Search Biz Error: ret={} msg={}", ret, msg);
-            return Err(anyhow::anyhow!("WeChat Search Error ({}): {}", ret, msg));
-        }
-    }
-
-    let mut accounts = Vec::new();
-    if let Some(list) = json.get("list").and_then(|l| l.as_array()) {
-        for item in list {
-            if let (Some(fakeid), Some(nickname)) = (
-                item.get("fakeid").and_then(|s| s.as_str()),
-                item.get("nickname").and_then(|s| s.as_str()),
-            ) {
-                accounts.push(AccountInfo {
-                    fakeid: fakeid.to_string(),
-                    nickname: nickname.to_string(),
+/// Articles of an account matching any of `keywords` in the MP in-account
+/// search, inside the publish window. A keyword the MP backend rejects is skipped.
+async fn search_account_articles(
+    state: &AppState,
+    auth_key: &str,
+    fakeid: &str,
+    keywords: &[String],
+    window: PublishWindow,
+) -> anyhow::Result<Vec<SimpleArticle>> {
+    let client = MpClient::for_auth_key(state, auth_key).await?;
+    let mut found: Vec<SimpleArticle> = Vec::new();
+    for keyword in keywords {
+        crate::shutdown::check()?;
+        let page = match client
+            .search_page(fakeid, keyword, 0, SEARCH_PAGE_SIZE, Priority::Task)
+            .await
+        {
+            Ok(page) => page,
+            Err(e @ MpError::Api { .. }) => {
+                tracing::warn!(
+                    "WeChat Article Search Error for fakeid {} ('{}'): {}",
+                    fakeid,
+                    keyword,
+                    e
+                );
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        for msg in page.into_articles() {
+            if window.contains(msg.create_time) && !found.iter().any(|a| a.url == msg.link) {
+                found.push(SimpleArticle {
+                    title: msg.title,
+                    digest: msg.digest,
+                    url: msg.link,
+                    create_time: msg.create_time,
                 });
             }
         }
     }
-    Ok(accounts)
+    Ok(found)
 }
 
+/// The history page of an account starting at `begin`, `limit` pushes long
 async fn fetch_account_articles(
     state: &AppState,
     auth_key: &str,
     fakeid: &str,
+    begin: u32,
     limit: u32,
-) -> anyhow::Result<Vec<SimpleArticle>> {
-    let token = state
-        .cookie_store
-        .get_token(auth_key)
-        .await?
-        .ok_or(anyhow::anyhow!("Token not found"))?;
-    let cookie = state
-        .cookie_store
-        .get_cookie(auth_key)
-        .await?
-        .ok_or(anyhow::anyhow!("Cookie not found"))?;
-    let cookie_str = cookie.to_cookie_header();
-    let count_str = limit.to_string();
-
-    let client = reqwest::Client::builder().no_proxy().build()?;
-    let resp = client
-        .get("https://mp.weixin.qq.com/cgi-bin/appmsgpublish")
-        .query(&[
-            ("sub", "list"),
-            ("search_field", "null"),
-            ("begin", "0"),
-            ("count", &count_str),
-            ("fakeid", fakeid),
-            ("type", "101_1"),
-            ("token", &token),
-            ("lang", "zh_CN"),
-            ("f", "json"),
-            ("ajax", "1"),
-        ])
-        .header("Cookie", cookie_str)
-        .header("User-Agent", WECHAT_USER_AGENT)
-        .send()
-        .await?;
-
-    let text = resp.text().await?;
-    let json: serde_json::Value = serde_json::from_str(&text)
-        .map_err(|e| anyhow::anyhow!("WeChat Article Fetch JSON Error: {} | Body: {}", e, text))?;
-
-    // Check for base_resp error
-    if let Some(ret) = json
-        .get("base_resp")
-        .and_then(|r| r.get("ret"))
-        .and_then(|v| v.as_i64())
+    window: PublishWindow,
+) -> anyhow::Result<AccountPage> {
+    let client = MpClient::for_auth_key(state, auth_key).await?;
+    let page = match client
+        .publish_page(fakeid, begin, limit, Priority::Task)
+        .await
     {
-        if ret != 0 {
-            let msg = json
-                .get("base_resp")
-                .and_then(|r| r.get("err_msg"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("Unknown error");
-            tracing::warn!(
-                "WeChat Article Fetch Error for fakeid {}: ret={} msg={}",
-                fakeid,
-                ret,
-                msg
-            );
+        Ok(page) => page,
+        Err(e @ MpError::Api { .. }) => {
             // Don't fail the whole task for one account failure, but log it.
-            return Ok(vec![]);
+            tracing::warn!("WeChat Article Fetch Error for fakeid {}: {}", fakeid, e);
+            return Ok(AccountPage::default());
         }
-    }
-
-    let mut articles = Vec::new();
-
-    // publish_page is returned as a JSON string, we need to parse it first
-    let publish_page_json = if let Some(s) = json.get("publish_page").and_then(|s| s.as_str()) {
-        serde_json::from_str::<serde_json::Value>(s).ok()
-    } else {
-        None
+        Err(e) => return Err(e.into()),
     };
 
-    if let Some(page_obj) = publish_page_json {
-        if let Some(list) = page_obj.get("publish_list").and_then(|l| l.as_array()) {
-            for item in list {
-                if let Some(info_str) = item.get("publish_info").and_then(|s| s.as_str()) {
-                    // Safe to clean html entities?
-                    let clean_info = info_str.replace("&quot;", "\""); // Basic unescape
-                    if let Ok(info) = serde_json::from_str::<serde_json::Value>(&clean_info) {
-                        // 1. Get create_time from sent_info (shared for this push)
-                        let shared_time = info
-                            .get("sent_info")
-                            .and_then(|s| s.get("time"))
-                            .and_then(|v| v.as_f64())
-                            .map(|f| f as i64)
-                            .unwrap_or(0);
-
-                        // 2. Try appmsg_info (Primary now)
-                        if let Some(appmsg_info) =
-                            info.get("appmsg_info").and_then(|l| l.as_array())
-                        {
-                            for msg in appmsg_info {
-                                if let (Some(title), Some(url)) = (
-                                    msg.get("title").and_then(|s| s.as_str()),
-                                    msg.get("content_url").and_then(|s| s.as_str()),
-                                ) {
-                                    let digest = msg
-                                        .get("digest")
-                                        .and_then(|s| s.as_str())
-                                        .unwrap_or("")
-                                        .to_string();
-                                    articles.push(SimpleArticle {
-                                        title: title.to_string(),
-                                        digest,
-                                        url: url.replace("\\", ""), // clean escaped slashes if any
-                                        create_time: shared_time,
-                                    });
-                                }
-                            }
-                        }
-                        // 3. Fallback to appmsgex (Old format)
-                        else if let Some(appmsg_list) =
-                            info.get("appmsgex").and_then(|l| l.as_array())
-                        {
-                            for msg in appmsg_list {
-                                if let (Some(title), Some(digest), Some(link), Some(create_time)) = (
-                                    msg.get("title").and_then(|s| s.as_str()),
-                                    msg.get("digest").and_then(|s| s.as_str()),
-                                    msg.get("link").and_then(|s| s.as_str()),
-                                    msg.get("create_time")
-                                        .and_then(|v| v.as_f64())
-                                        .map(|f| f as i64),
-                                ) {
-                                    articles.push(SimpleArticle {
-                                        title: title.to_string(),
-                                        digest: digest.to_string(),
-                                        url: link.to_string(),
-                                        create_time,
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    let next_begin = begin + page.entries as u32;
+    let newest = page.articles().map(|a| a.create_time).max();
+    let more = page.entries > 0
+        && (next_begin as i64) < page.total_count as i64
+        && newest.is_some_and(|t| !window.passed(t));
+
+    let articles: Vec<SimpleArticle> = page
+        .into_articles()
+        .into_iter()
+        .filter(|msg| window.contains(msg.create_time))
+        .map(|msg| SimpleArticle {
+            title: msg.title,
+            digest: msg.digest,
+            url: msg.link,
+            create_time: msg.create_time,
+        })
+        .collect();
 
-    // Debug log only if empty (can remove later)
     if articles.is_empty() {
         tracing::debug!(
-            "Fetched 0 articles for fakeid {}. JSON Response: {:?}",
+            "Fetched 0 articles for fakeid {} at offset {}",
             fakeid,
-            json
+            begin
         );
     }
 
-    Ok(articles)
+    Ok(AccountPage {
+        articles,
+        next_begin,
+        more,
+    })
 }
 
-// ============ LLM Logic (DeepSeek & Gemini) ============
-
-/// Configurable embedding generation - dispatches to Gemini or Ollama based on provider
-async fn generate_embedding_configurable(
-    provider: &str,
-    gemini_key: Option<&str>,
-    ollama_base_url: Option<&str>,
-    ollama_model: Option<&str>,
-    text: &str,
-) -> anyhow::Result<Vec<f32>> {
-    match provider.to_lowercase().as_str() {
-        "ollama" => {
-            crate::llm::ollama::generate_embedding(
-                ollama_base_url.unwrap_or("http://127.0.0.1:11434"),
-                ollama_model.unwrap_or("qwen3-embedding:8b-q8_0"),
-                text,
-            )
-            .await
-        }
-        "gemini" | _ => {
-            let api_key = gemini_key
-                .map(|s| s.to_string())
-                .or_else(|| std::env::var("GEMINI_API_KEY").ok())
-                .ok_or_else(|| anyhow::anyhow!("Gemini API Key required for embedding"))?;
-            crate::llm::gemini::generate_embedding(&api_key, text).await
-        }
-    }
-}
+// ============ LLM Logic ============
 
 async fn generate_keywords(
-    provider: &str,
+    llm: &dyn ChatProvider,
     prompt: &str,
     count: usize,
-    deepseek_key: Option<&str>,
-    gemini_key: Option<&str>,
 ) -> anyhow::Result<Vec<String>> {
     let sys_prompt = format!("You are a keyword generator helper. The user needs to search for WeChat Official Accounts. \n\
     Generate {} search keywords based on the user's topic. \n\
@@ -1673,313 +4540,215 @@ async fn generate_keywords(
     \n\
     IMPORTANT: You must return a valid JSON object in this format: \n\
     {{ \"keywords\": [\"keyword1\", \"keyword2\"] }}", count);
+    let topic = format!("Topic: {}", prompt);
+
+    let content = crate::llm::chat_with_retry(
+        llm,
+        &ChatRequest {
+            system: Some(&sys_prompt),
+            prompt: &topic,
+            temperature: Some(0.3),
+            json: true,
+            ..Default::default()
+        },
+    )
+    .await?;
 
-    // Common JSON parsing logic
-    fn parse_keywords(text: &str) -> anyhow::Result<Vec<String>> {
-         let json: serde_json::Value = serde_json::from_str(text).map_err(|e| {
-            anyhow::anyhow!("JSON Parse Error: {} | Body: {}", e, text)
-        })?;
-
-        // Handle DeepSeek/Gemini structure differences if needed, but usually we just want the content
-        // DeepSeek: choices[0].message.content
-        // Gemini: candidates[0].content.parts[0].text
-        
-        let content = if let Some(c) = json.get("choices")
-            .and_then(|c| c.get(0))
-            .and_then(|m| m.get("message"))
-            .and_then(|m| m.get("content"))
-            .and_then(|s| s.as_str()) {
-                c.to_string()
-        } else if let Some(c) = json.get("candidates")
-            .and_then(|c| c.get(0))
-            .and_then(|c| c.get("content"))
-            .and_then(|parts| parts.get("parts"))
-            .and_then(|p| p.get(0))
-            .and_then(|t| t.get("text"))
-            .and_then(|s| s.as_str()) {
-                c.to_string()
-        } else {
-            return Err(anyhow::anyhow!("Unknown JSON structure or empty content"));
-        };
-
-        let clean_content = content
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```");
-
-        #[derive(serde::Deserialize)]
-        struct KeywordsResp {
-            keywords: Vec<String>,
-        }
+    parse_keywords(&content)
+}
 
-        let resp_obj: KeywordsResp = serde_json::from_str(clean_content).map_err(|e| {
-            anyhow::anyhow!("Content Parse Error: {} | Content: {}", e, clean_content)
-        })?;
-        Ok(resp_obj.keywords)
+/// Keywords from a model reply: `{"keywords": [...]}`, or a bare array as
+/// small local models sometimes return
+fn parse_keywords(content: &str) -> anyhow::Result<Vec<String>> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum KeywordsResp {
+        Object { keywords: Vec<String> },
+        List(Vec<String>),
     }
 
-    match provider.to_lowercase().as_str() {
-        "gemini" => {
-             let api_key = gemini_key
-                .map(|s| s.to_string())
-                .or_else(|| std::env::var("GEMINI_API_KEY").ok())
-                .ok_or_else(|| anyhow::anyhow!("Gemini API Key required for keywords"))?;
-
-            let client = reqwest::Client::new();
-            let url = format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-                api_key
-            );
-            
-            let full_prompt = format!("{}\n\nUser Topic: {}", sys_prompt, prompt);
-
-            let mut attempt = 0;
-            while attempt < 5 {
-                attempt += 1;
-                let resp = client
-                    .post(&url)
-                    .json(&serde_json::json!({
-                        "contents": [{"parts": [{"text": full_prompt}]}],
-                         "generationConfig": { "response_mime_type": "application/json" }
-                    }))
-                    .send()
-                    .await;
-
-                match resp {
-                    Ok(r) => {
-                        if r.status().is_success() {
-                            let text = r.text().await?;
-                            return parse_keywords(&text);
-                        } else {
-                             tracing::warn!("Gemini API Error (Attempt {}/5): Status {}", attempt, r.status());
-                        }
-                    }
-                    Err(e) => tracing::warn!("Gemini Network Error (Attempt {}/5): {}", attempt, e),
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-            }
-            Err(anyhow::anyhow!("Gemini API failed after 5 attempts"))
-        }
-        "deepseek" | _ => {
-            let api_key = deepseek_key
-                .map(|s| s.to_string())
-                .or_else(|| std::env::var("DEEPSEEK_API_KEY").ok())
-                .ok_or_else(|| anyhow::anyhow!("DeepSeek API Key not found"))?;
-
-            let client = reqwest::Client::new();
-            let mut attempt = 0;
-            while attempt < 5 {
-                attempt += 1;
-                let resp = client
-                    .post("https://api.deepseek.com/chat/completions")
-                    .header("Authorization", format!("Bearer {}", api_key))
-                    .json(&serde_json::json!({
-                         "model": "deepseek-chat",
-                         "messages": [
-                             {"role": "system", "content": &sys_prompt},
-                             {"role": "user", "content": format!("Topic: {}", prompt)}
-                         ],
-                         "temperature": 0.3,
-                         "response_format": { "type": "json_object" }
-                    }))
-                    .send()
-                    .await;
-                
-                  match resp {
-                    Ok(r) => {
-                        if r.status().is_success() {
-                            let text = r.text().await?;
-                            return parse_keywords(&text);
-                        } else {
-                             tracing::warn!("DeepSeek API Error (Attempt {}/5): Status {}", attempt, r.status());
-                        }
-                    }
-                    Err(e) => tracing::warn!("DeepSeek Network Error (Attempt {}/5): {}", attempt, e),
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-            }
-             Err(anyhow::anyhow!("DeepSeek API failed after 5 attempts"))
+    let clean_content = crate::llm::extract_json(content);
+    let keywords = match serde_json::from_str(clean_content) {
+        Ok(KeywordsResp::Object { keywords }) | Ok(KeywordsResp::List(keywords)) => keywords,
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "Content Parse Error: {} | Content: {}",
+                e,
+                clean_content
+            ))
         }
-    }
+    };
+    Ok(keywords
+        .into_iter()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect())
 }
 
-async fn generate_insight(
-    provider: &str,
+/// Relevance and insight of one article; `language` names the insight's
+/// language as the prompt should (see `llm::language_name`)
+pub(crate) async fn generate_insight(
+    llm: &dyn ChatProvider,
     intent: &str,
     title: &str,
     digest: &str,
-    deepseek_key: Option<&str>,
-    gemini_key: Option<&str>,
+    language: &str,
 ) -> anyhow::Result<(bool, String)> {
-     let user_prompt = format!(
+    let user_prompt = format!(
         "Intent: {}\n\nArticle Title: {}\nDigest: {}\n\nEvaluate if this article is RELEVANT to the Intent. \n\
         STRICT RULES: \n\
         1. If it is an advertisement, course promotion (training camp, free lessons), or selling anxiety, MARK AS FALSE (is_relevant: false).\n\
         2. If it is a simple notification, recruitment info, or low-value content, MARK AS FALSE.\n\
         3. Only mark as TRUE if it provides substantive knowledge, analysis, or industry insights.\n\
-        If relevant, provide a concise insight (2-3 sentences max) in Simplified Chinese. \n\
+        If relevant, provide a concise insight (2-3 sentences max) in {}. \n\
         Return JSON ONLY: {{ \"is_relevant\": boolean, \"insight\": \"string\" }}", 
-        intent, title, digest
+        intent, title, digest, language
     );
 
-    // Common Parsing Logic
-    fn parse_insight(text: &str) -> anyhow::Result<(bool, String)> {
-        let json: serde_json::Value = serde_json::from_str(text).map_err(|e| {
-            anyhow::anyhow!("JSON Error: {} | Body: {}", e, text)
-        })?;
-
-        // Extract content depending on structure
-        let content = if let Some(c) = json.get("choices")
-            .and_then(|c| c.get(0))
-            .and_then(|m| m.get("message"))
-            .and_then(|m| m.get("content"))
-            .and_then(|s| s.as_str()) {
-                c.to_string()
-        } else if let Some(c) = json.get("candidates")
-            .and_then(|c| c.get(0))
-            .and_then(|c| c.get("content"))
-            .and_then(|parts| parts.get("parts"))
-            .and_then(|p| p.get(0))
-            .and_then(|t| t.get("text"))
-            .and_then(|s| s.as_str()) {
-            c.to_string()
-        } else {
-             // Try parsing the root if it's already the object (unlikely for API response but safety)
-             return Err(anyhow::anyhow!("Unknown JSON structure"));
-        };
+    let content = crate::llm::chat_with_retry(
+        llm,
+        &ChatRequest {
+            prompt: &user_prompt,
+            temperature: Some(0.2), // Lower temp for classification
+            json: true,
+            ..Default::default()
+        },
+    )
+    .await?;
 
-        let clean_text = content
-            .trim()
-            .trim_start_matches("```json")
-            .trim_start_matches("```")
-            .trim_end_matches("```");
+    Ok(parse_insight(&content))
+}
 
-        #[derive(serde::Deserialize)]
-        struct InsightResp {
-            is_relevant: bool,
-            insight: String,
-        }
+/// `(is_relevant, insight)` from a model reply. Local models may quote the
+/// boolean or leave the insight out; anything unreadable counts as irrelevant.
+fn parse_insight(content: &str) -> (bool, String) {
+    let parsed: Option<serde_json::Value> =
+        serde_json::from_str(crate::llm::extract_json(content)).ok();
+    let Some(obj) = parsed.as_ref().and_then(|v| v.as_object()) else {
+        return (false, "Failed to parse AI response".to_string());
+    };
+    let is_relevant = match obj.get("is_relevant") {
+        Some(serde_json::Value::Bool(b)) => *b,
+        Some(serde_json::Value::String(s)) => s.trim().eq_ignore_ascii_case("true"),
+        _ => false,
+    };
+    let insight = obj
+        .get("insight")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    (is_relevant, insight)
+}
 
-        let parsed: InsightResp = serde_json::from_str(clean_text).unwrap_or(InsightResp {
-            is_relevant: false,
-            insight: "Failed to parse AI response".to_string(),
-        });
-        Ok((parsed.is_relevant, parsed.insight))
-    }
+// Export Helpers
 
-    match provider.to_lowercase().as_str() {
-        "deepseek" => {
-               let api_key = deepseek_key
-                .map(|s| s.to_string())
-                .or_else(|| std::env::var("DEEPSEEK_API_KEY").ok())
-                .ok_or_else(|| anyhow::anyhow!("DeepSeek API Key required"))?;
-            
-            let client = reqwest::Client::new();
-            let mut attempt = 0;
-             while attempt < 5 {
-                attempt += 1;
-                let resp = client
-                    .post("https://api.deepseek.com/chat/completions")
-                    .header("Authorization", format!("Bearer {}", api_key))
-                    .json(&serde_json::json!({
-                        "model": "deepseek-chat",
-                        "messages": [{"role": "user", "content": user_prompt}],
-                        "temperature": 0.2, // Lower temp for classification
-                        "response_format": { "type": "json_object" }
-                    }))
-                    .send()
-                    .await;
-                
-                  match resp {
-                    Ok(r) => {
-                        if r.status().is_success() {
-                            let text = r.text().await?;
-                            return parse_insight(&text);
-                        } else {
-                             tracing::warn!("DeepSeek Insight API Error (Attempt {}/5): Status {}", attempt, r.status());
-                        }
-                    }
-                    Err(e) => tracing::warn!("DeepSeek Insight Network Error (Attempt {}/5): {}", attempt, e),
-                }
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+/// A gateway that failed to deliver an article
+#[derive(Debug, Clone, Serialize)]
+pub struct GatewayFailure {
+    /// Gateway URL without its password
+    pub gateway: String,
+    pub error: String,
+}
+
+/// Download an article, moving to another gateway after each failed attempt,
+/// up to `gateway::max_attempts()` attempts. Each failure is appended to
+/// `failures`. Content shorter than `min_len` counts as a failure of the
+/// gateway that returned it. Without gateways this is `fetch_html_content`.
+/// Returns the content and the gateway that delivered it.
+async fn fetch_html_rotating(
+    client: &reqwest::Client,
+    downloads: &DownloadScheduler,
+    target_url: &str,
+    proxies: Option<&[ProxySpec]>,
+    authorization: Option<&str>,
+    min_len: usize,
+    failures: &mut Vec<GatewayFailure>,
+) -> anyhow::Result<(String, Option<Pick>)> {
+    let max_attempts = crate::gateway::max_attempts();
+    let mut tried: Vec<String> = Vec::new();
+    loop {
+        let Some(route) = crate::gateway::pick_untried(proxies, authorization, &tried) else {
+            let content = fetch_html_content(client, downloads, target_url, None).await?;
+            if content.trim().len() < min_len {
+                return Err(anyhow::anyhow!("Content too short"));
             }
-             Err(anyhow::anyhow!("DeepSeek API failed after 5 attempts"))
-        },
-        "gemini" | _ => {
-            // Use Gemini
-            let api_key = gemini_key
-                .map(|s| s.to_string())
-                .or_else(|| std::env::var("GEMINI_API_KEY").ok())
-                .ok_or_else(|| anyhow::anyhow!("Gemini API Key not found"))?;
-
-            let client = reqwest::Client::new();
-            let url = format!(
-                "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-                api_key
-            );
+            return Ok((content, None));
+        };
 
-            let mut attempt = 0;
-            while attempt < 5 {
-                attempt += 1;
-                let response_result = client
-                    .post(&url)
-                    .json(&serde_json::json!({
-                        "contents": [{"parts": [{"text": user_prompt}]}],
-                        "generationConfig": { "response_mime_type": "application/json" }
-                    }))
-                    .send()
-                    .await;
+        let lane = crawl::gateway_lane(&route.url);
+        let permit = crawl::acquire(&lane, Priority::Task, "insight.fetch_html").await;
+        let slot = downloads.acquire(Some(&route.url), target_url).await;
+        let started = std::time::Instant::now();
+        let result: anyhow::Result<String> = async {
+            let resp = crate::gateway::get(client, Some(&route), target_url)?
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                return Err(anyhow::anyhow!(
+                    "Request failed with status: {}",
+                    resp.status()
+                ));
+            }
+            let content = resp.text().await?;
+            if content.trim().len() < min_len {
+                return Err(anyhow::anyhow!("Content too short"));
+            }
+            Ok(content)
+        }
+        .await;
+        let error = result.as_ref().err().map(|e| e.to_string());
+        crate::gateway::record(&route.url, error.as_deref(), started.elapsed());
 
-                match response_result {
-                    Ok(response) => {
-                        if response.status().is_success() {
-                            let body_text = response.text().await?;
-                            return parse_insight(&body_text);
-                        } else {
-                            tracing::warn!("Gemini Insight API Error (Attempt {}/5): Status={}", attempt, response.status());
-                        }
-                    }
-                    Err(e) => {
-                        tracing::warn!("Gemini Network Error (Attempt {}/5): {}", attempt, e);
-                    }
+        match result {
+            Ok(content) => return Ok((content, Some(route))),
+            Err(e) => {
+                let gateway = crate::gateway::display_url(&route.url);
+                tried.push(route.url);
+                tracing::warn!(
+                    "Fetch error for {} via {} (Attempt {}/{}): {}",
+                    target_url,
+                    gateway,
+                    tried.len(),
+                    max_attempts,
+                    e
+                );
+                failures.push(GatewayFailure {
+                    gateway,
+                    error: e.to_string(),
+                });
+                if tried.len() >= max_attempts {
+                    return Err(e);
                 }
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
             }
-            Err(anyhow::anyhow!("Gemini Insight API failed after 5 attempts"))
         }
-
+        // Release the gateway before backing off
+        drop(slot);
+        drop(permit);
+        // Simple backoff
+        tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
     }
 }
 
-// Export Helpers
 async fn fetch_html_content(
     client: &reqwest::Client,
+    downloads: &DownloadScheduler,
     target_url: &str,
-    gateway: Option<&str>,
-    gateway_auth: Option<&str>,
+    route: Option<&Pick>,
 ) -> anyhow::Result<String> {
-    let final_url = if let Some(gw) = gateway {
-        // Construct Gateway URL: gw?url=encoded_target&authorization=auth
-        let mut url =
-            reqwest::Url::parse(gw).map_err(|e| anyhow::anyhow!("Invalid gateway URL: {}", e))?;
-
-        {
-            let mut pairs = url.query_pairs_mut();
-            pairs.append_pair("url", target_url);
-            if let Some(auth) = gateway_auth {
-                pairs.append_pair("authorization", auth);
-            }
-        }
-        url.to_string()
-    } else {
-        target_url.to_string()
-    };
-
+    let gateway = route.map(|r| r.url.as_str());
+    let lane = gateway.map_or(crawl::ARTICLE_LANE.to_string(), crawl::gateway_lane);
     let mut attempt = 0;
     loop {
         attempt += 1;
-        match client.get(&final_url).send().await {
+        let _permit = crawl::acquire(&lane, Priority::Task, "insight.fetch_html").await;
+        let _slot = downloads.acquire(gateway, target_url).await;
+        let started = std::time::Instant::now();
+        let resp = crate::gateway::get(client, route, target_url)?.send().await;
+        if let Some(gw) = gateway {
+            let error = crate::gateway::response_error(&resp);
+            crate::gateway::record(gw, error.as_deref(), started.elapsed());
+        }
+        match resp {
             Ok(resp) => {
                 if resp.status().is_success() {
                     return Ok(resp.text().await?);
@@ -2003,7 +4772,7 @@ async fn fetch_html_content(
                 {
                     tracing::warn!(
                         "Fetch error for {} (Attempt {}/3): {}",
-                        final_url,
+                        target_url,
                         attempt,
                         e
                     );
@@ -2017,13 +4786,14 @@ async fn fetch_html_content(
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::manual_flatten)]
 pub async fn process_html_images(
     client: &reqwest::Client,
+    downloads: &DownloadScheduler,
     html: &str,
     images_dir: &StdPath,
     _prefix: &str,
-    gateway: Option<&str>,
-    gateway_auth: Option<&str>,
+    route: Option<&Pick>,
     db_pool: &sqlx::PgPool,
     use_absolute_paths: bool, // Kept for API compatibility, but effectively ignored if using base64 logic below (I will repurpose this or add new arg)
     // Actually, I should just repurpose `use_absolute_paths` -> `embed_base64` or add a new arg.
@@ -2096,8 +4866,7 @@ pub async fn process_html_images(
 
     let download_futures = stream::iter(replacements).map(|(target_url, file_path, rel_path, _)| {
         let client = client.clone();
-        let gateway = gateway.map(|s| s.to_string());
-        let gateway_auth = gateway_auth.map(|s| s.to_string());
+        let route = route.cloned();
         let db_pool = db_pool.clone();
         let should_embed = use_absolute_paths; // Reuse flag: true = embed base64
 
@@ -2113,11 +4882,10 @@ pub async fn process_html_images(
             tracing::info!("Processing image: {}", dl_url);
 
             // A. Check Cache (Use NORMALIZED URL)
-            let cached: Option<Vec<u8>> = sqlx::query_scalar("SELECT data FROM assets WHERE url = $1")
-                .bind(&dl_url) 
-                .fetch_optional(&db_pool)
+            let cached: Option<Vec<u8>> = crate::storage::load(&db_pool, &dl_url)
                 .await
-                .unwrap_or(None);
+                .unwrap_or(None)
+                .map(|(data, _)| data);
             
             // Validate cache quality: must be > 100 bytes and look like an image
             if let Some(data) = cached {
@@ -2135,26 +4903,31 @@ pub async fn process_html_images(
             
             if image_data.is_none() {
                 // B. Download
-                 let final_url = if let Some(gw) = gateway {
-                    let mut url = reqwest::Url::parse(&gw).unwrap_or(reqwest::Url::parse("http://err").unwrap());
-                    {
-                        let mut p = url.query_pairs_mut();
-                        p.append_pair("url", &dl_url);
-                        if let Some(a) = &gateway_auth { p.append_pair("authorization", a); }
-                    }
-                    url.to_string()
-                } else { dl_url.clone() };
+                let gateway = route.as_ref().map(|r| r.url.as_str());
 
                 // Retry loop (3 attempts)
                 for i in 0..3 {
                     // Add Referer header which is often required by WeChat images
                     // Add User-Agent and Accept to look like a browser
-                    match client.get(&final_url)
+                    let _slot = downloads.acquire(gateway, &dl_url).await;
+                    let started = std::time::Instant::now();
+                    let request = match crate::gateway::get(&client, route.as_ref(), &dl_url) {
+                        Ok(request) => request,
+                        Err(e) => {
+                            tracing::warn!("Image download skipped: {} - {}", target_url, e);
+                            break;
+                        }
+                    };
+                    let resp = request
                         .header("Referer", "https://mp.weixin.qq.com/")
                         .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
                         .header("Accept", "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8")
-                        .send().await 
-                    {
+                        .send().await;
+                    if let Some(gw) = gateway {
+                        let error = crate::gateway::response_error(&resp);
+                        crate::gateway::record(gw, error.as_deref(), started.elapsed());
+                    }
+                    match resp {
                         Ok(resp) => {
                             if resp.status().is_success() {
                                 if let Ok(bytes) = resp.bytes().await {
@@ -2187,11 +4960,12 @@ pub async fn process_html_images(
                 // Prince handles mismatches reasonably well, but let's stick to no transcoding.
 
                 // Cache the fresh download using NORMALIZED URL
-                 let _ = sqlx::query("INSERT INTO assets (url, data, mime_type) VALUES ($1, $2, $3) ON CONFLICT (url) DO UPDATE SET data = $2, mime_type = $3")
+                if let Err(e) = crate::storage::save(&db_pool, &dl_url, data, mime_type).await {
+                    tracing::warn!("Failed to store image {}: {}", dl_url, e);
+                }
+                // Thumbnails of the previous bytes are stale now
+                let _ = sqlx::query("DELETE FROM asset_variants WHERE url = $1")
                     .bind(&dl_url)
-                    .bind(data)
-                    .bind(mime_type) 
-                    .bind("application/octet-stream")
                     .execute(&db_pool).await;
 
                 // Always write to file for batch export consistency (or just backup)
@@ -2209,16 +4983,9 @@ pub async fn process_html_images(
                      let b64 = base64::engine::general_purpose::STANDARD.encode(data);
                      format!("data:{};base64,{}", mime_type, b64)
                 } else {
-                    // Use absolute file:// path for Prince to find the image
+                    // Absolute file:// URL for the PDF engine to find the image
                     let abs_path = file_path.canonicalize().unwrap_or(file_path.clone());
-                    let path_str = abs_path.display().to_string().replace("\\", "/");
-                    // Ensure exactly 3 slashes: file:///path (Unix) or file:///C:/path (Windows)
-                    // If path already starts with /, use file:// + path, else file:/// + path
-                    if path_str.starts_with("/") {
-                        format!("file://{}", path_str)
-                    } else {
-                        format!("file:///{}", path_str)
-                    }
+                    crate::api::pdf_engine::file_url(&abs_path)
                 };
 
                 Some((target_url, rel_path, file_path, replacement_str))
@@ -2236,7 +5003,7 @@ pub async fn process_html_images(
     for res in results {
         if let Some((target_url, _, file_path, replacement)) = res {
             downloaded_images.push(file_path); // Track downloaded files
-            
+        
             // Log the replacement to see if it is Base64 or File URL
             if replacement.len() > 200 {
                  // Use char-safe truncation to avoid panic on multi-byte chars
@@ -2254,3 +5021,119 @@ pub async fn process_html_images(
 
     (processed_html, downloaded_images)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_mode() {
+        let mode: ImageMode = serde_json::from_str("\"base64\"").unwrap();
+        assert_eq!(mode, ImageMode::Base64);
+        assert_eq!(ImageMode::default(), ImageMode::Absolute);
+        assert_eq!(
+            relative_image_src("file:///data/export/images/a.jpg").as_deref(),
+            Some("images/a.jpg")
+        );
+        assert_eq!(relative_image_src("https://mmbiz.qpic.cn/a"), None);
+    }
+
+    #[test]
+    fn test_chunk_text() {
+        let text = "第一段\n\n第二段\n".to_string() + &"长".repeat(25);
+        let chunks = chunk_text(&text, 10);
+        assert_eq!(chunks[0], "第一段\n第二段");
+        assert_eq!(chunks[1].chars().count(), 10);
+        assert_eq!(chunks.last().unwrap().chars().count(), 5);
+        assert!(chunk_text("  \n", 10).is_empty());
+    }
+
+    #[test]
+    fn test_parse_local_model_output() {
+        let reply =
+            "<think>用户需要关键词</think>\n```json\n{\"keywords\": [\"不良资产\", \" \"]}\n```";
+        assert_eq!(parse_keywords(reply).unwrap(), vec!["不良资产"]);
+        assert_eq!(parse_keywords("[\"债权处置\"]").unwrap(), vec!["债权处置"]);
+        assert!(parse_keywords("没有关键词").is_err());
+    }
+
+    #[test]
+    fn test_is_article_url() {
+        assert!(is_article_url("https://mp.weixin.qq.com/s/AbCdEf"));
+        assert!(is_article_url(
+            "https://mp.weixin.qq.com/s?__biz=MzA=&mid=1&idx=1&sn=x"
+        ));
+        assert!(!is_article_url("https://mp.weixin.qq.com/cgi-bin/appmsg"));
+        assert!(!is_article_url("https://example.com/s/AbCdEf"));
+    }
+
+    #[test]
+    fn test_clean_keywords() {
+        let keywords = vec![
+            " 不良资产 ".to_string(),
+            "".to_string(),
+            "债权处置".to_string(),
+            "不良资产".to_string(),
+        ];
+        assert_eq!(clean_keywords(keywords), vec!["不良资产", "债权处置"]);
+
+        let (relevant, insight) =
+            parse_insight("结果如下：{\"is_relevant\": \"True\", \"insight\": \"有价值\"}");
+        assert!(relevant);
+        assert_eq!(insight, "有价值");
+        assert!(!parse_insight("not json").0);
+    }
+
+    #[test]
+    fn test_publish_window() {
+        let window = PublishWindow {
+            after: Some(1000),
+            before: Some(2000),
+        };
+        assert!(window.contains(1000));
+        assert!(!window.contains(2000));
+        assert!(!window.contains(999));
+        assert!(window.passed(999));
+        assert!(!window.passed(1500));
+        assert!(window.validate().is_ok());
+
+        let open = PublishWindow::default();
+        assert!(!open.is_set());
+        assert!(open.contains(0));
+        assert!(!open.passed(0));
+        assert!(PublishWindow {
+            after: Some(2000),
+            before: Some(1000),
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_search_mode() {
+        let both = SearchMode::parse("both").unwrap();
+        assert!(both.discovers_accounts() && both.searches_articles());
+        assert!(!SearchMode::parse("articles").unwrap().discovers_accounts());
+        assert!(!SearchMode::Accounts.searches_articles());
+        assert!(SearchMode::parse("keywords").is_none());
+    }
+
+    #[test]
+    fn test_pick_session() {
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+        let sessions = vec![
+            ("bob-new".to_string(), Some(bob)),
+            ("alice".to_string(), Some(alice)),
+            ("bob-old".to_string(), Some(bob)),
+        ];
+        assert_eq!(pick_session(&sessions, Some(alice)), Some("alice"));
+        assert_eq!(pick_session(&sessions, Some(bob)), Some("bob-new"));
+        // Shared tokens and unowned tasks never borrow a user's session
+        assert_eq!(pick_session(&sessions, None), None);
+
+        let mut sessions = sessions;
+        sessions.push(("shared".to_string(), None));
+        assert_eq!(pick_session(&sessions, None), Some("shared"));
+        assert_eq!(pick_session(&sessions, Some(Uuid::new_v4())), None);
+    }
+}
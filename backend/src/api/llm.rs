@@ -4,10 +4,21 @@
 
 #![allow(dead_code)]
 
-use axum::Json;
+use std::convert::Infallible;
+
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 
+use crate::credentials;
 use crate::error::AppError;
+use crate::llm::usage::UsageRow;
+use crate::llm::{ChatProvider, EmbeddingProvider, ProviderConfig, TextStream};
+use crate::AppState;
 
 // ============ Types ============
 
@@ -22,6 +33,8 @@ pub struct ChatRequest {
     pub profile: serde_json::Value,
     pub message: String,
     pub history: Option<Vec<ChatMessage>>,
+    /// "gemini", "deepseek" or "openai_compatible"; the first configured one when unset
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -61,24 +74,71 @@ const ROLEPLAY_PROMPT_TEMPLATE: &str = r#"你现在是 {name} 的数字分身。
 ## 回复
 {name}:"#;
 
+// ============ Provider Resolution ============
+
+/// Provider settings from the per-provider keys a request carries
+pub(crate) fn keyed_config(
+    provider: &str,
+    deepseek_key: Option<&str>,
+    gemini_key: Option<&str>,
+) -> ProviderConfig {
+    let api_key = match provider.trim().to_lowercase().as_str() {
+        "deepseek" => deepseek_key,
+        "gemini" => gemini_key,
+        _ => None,
+    };
+    ProviderConfig {
+        api_key: api_key.map(str::to_string),
+        ..Default::default()
+    }
+}
+
+/// Chat provider for a request. `config` is what the request sent for this
+/// provider; its key wins over the stored one (see `credentials`).
+pub(crate) async fn chat_provider(
+    state: &AppState,
+    provider: &str,
+    mut config: ProviderConfig,
+) -> Result<Box<dyn ChatProvider>, AppError> {
+    let provider = provider.trim().to_lowercase();
+    config.api_key = credentials::resolve(
+        &state.db_pool,
+        &provider,
+        config.api_key.as_deref(),
+        config.base_url.as_deref(),
+    )
+    .await;
+    crate::llm::chat_provider(&provider, &config).map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
+/// Embedding provider for a request, resolving its key like `chat_provider`
+pub(crate) async fn embedding_provider(
+    state: &AppState,
+    provider: &str,
+    mut config: ProviderConfig,
+) -> Result<Box<dyn EmbeddingProvider>, AppError> {
+    let provider = provider.trim().to_lowercase();
+    config.api_key = credentials::resolve(
+        &state.db_pool,
+        &provider,
+        config.api_key.as_deref(),
+        config.base_url.as_deref(),
+    )
+    .await;
+    crate::llm::embedding_provider(&provider, &config)
+        .map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
 // ============ Chat Handler ============
 
-/// Doppelganger chat with AI roleplay
-pub async fn chat(Json(req): Json<ChatRequest>) -> Result<Json<ChatResponse>, AppError> {
+/// Roleplay prompt for a chat request and the persona's name, or the message
+/// explaining why the request cannot be answered
+fn roleplay_prompt(req: &ChatRequest) -> Result<(String, String), &'static str> {
     if req.profile.is_null() {
-        return Ok(Json(ChatResponse {
-            code: -1,
-            message: Some("缺少档案数据".to_string()),
-            data: None,
-        }));
+        return Err("缺少档案数据");
     }
-
     if req.message.is_empty() {
-        return Ok(Json(ChatResponse {
-            code: -1,
-            message: Some("缺少消息内容".to_string()),
-            data: None,
-        }));
+        return Err("缺少消息内容");
     }
 
     let name = req
@@ -108,21 +168,72 @@ pub async fn chat(Json(req): Json<ChatRequest>) -> Result<Json<ChatResponse>, Ap
         .replace("{profile}", &profile_json)
         .replace("{history}", &history_text)
         .replace("{message}", &req.message);
+    Ok((name.to_string(), prompt))
+}
+
+/// The requested provider, or the first of Gemini, DeepSeek and
+/// OpenAI-compatible that is configured; `None` when nothing is
+async fn roleplay_llm(state: &AppState, provider: Option<&str>) -> Option<Box<dyn ChatProvider>> {
+    let candidates: Vec<&str> = match provider {
+        Some(provider) => vec![provider],
+        None => vec!["gemini", "deepseek", "openai_compatible"],
+    };
+    for provider in candidates {
+        let provider = provider.trim().to_lowercase();
+        if let Some(key) = credentials::resolve(&state.db_pool, &provider, None, None).await {
+            let config = ProviderConfig {
+                api_key: Some(key),
+                ..Default::default()
+            };
+            if let Ok(llm) = crate::llm::chat_provider(&provider, &config) {
+                return Some(llm);
+            }
+        }
+    }
+    None
+}
 
-    // Try Gemini first, then DeepSeek, then fallback
-    let gemini_key = std::env::var("GEMINI_API_KEY").ok();
-    let deepseek_key = std::env::var("DEEPSEEK_API_KEY").ok();
+fn roleplay_request(prompt: &str) -> crate::llm::ChatRequest<'_> {
+    crate::llm::ChatRequest {
+        prompt,
+        temperature: Some(0.8),
+        max_tokens: Some(1024),
+        ..Default::default()
+    }
+}
 
-    let reply = if let Some(key) = gemini_key {
-        call_gemini_chat(&key, &prompt).await
-    } else if let Some(key) = deepseek_key {
-        call_deepseek_chat(&key, &prompt).await
-    } else {
+/// Reply used when no provider is configured
+fn mock_reply(name: &str, message: &str) -> String {
+    format!(
+        "（这是一个模拟回复，请配置 Gemini 或 DeepSeek API Key 以启用真实 AI 对话）\n\n作为 {}，我会这样回应：根据我的档案，我倾向于理性和务实地看待问题。关于你的问题\"{}\"，我需要更多信息才能给出具体想法。",
+        name, message
+    )
+}
+
+/// Doppelganger chat with AI roleplay
+pub async fn chat(
+    State(state): State<AppState>,
+    Json(req): Json<ChatRequest>,
+) -> Result<Json<ChatResponse>, AppError> {
+    let (name, prompt) = match roleplay_prompt(&req) {
+        Ok(p) => p,
+        Err(message) => {
+            return Ok(Json(ChatResponse {
+                code: -1,
+                message: Some(message.to_string()),
+                data: None,
+            }))
+        }
+    };
+
+    let reply = match roleplay_llm(&state, req.provider.as_deref()).await {
+        Some(llm) => llm
+            .chat(&roleplay_request(&prompt))
+            .await
+            .map(|reply| reply.text)
+            .map_err(|e| AppError::BadGateway(e.to_string())),
         // Fallback response
-        Ok(format!(
-            "（这是一个模拟回复，请配置 Gemini 或 DeepSeek API Key 以启用真实 AI 对话）\n\n作为 {}，我会这样回应：根据我的档案，我倾向于理性和务实地看待问题。关于你的问题\"{}\"，我需要更多信息才能给出具体想法。",
-            name, req.message
-        ))
+        None => Ok(mock_reply(&name, &req.message)),
     };
 
     match reply {
@@ -139,6 +250,55 @@ pub async fn chat(Json(req): Json<ChatRequest>) -> Result<Json<ChatResponse>, Ap
     }
 }
 
+/// Doppelganger chat streamed as server-sent events: `delta` events carry the
+/// next piece of the reply, then one `done` event the whole reply, or an
+/// `error` event if the provider fails midway
+pub async fn chat_stream(
+    State(state): State<AppState>,
+    Json(req): Json<ChatRequest>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let (name, prompt) =
+        roleplay_prompt(&req).map_err(|message| AppError::BadRequest(message.to_string()))?;
+
+    let pieces: TextStream = match roleplay_llm(&state, req.provider.as_deref()).await {
+        Some(llm) => llm
+            .chat_stream(&roleplay_request(&prompt))
+            .await
+            .map_err(|e| AppError::BadGateway(e.to_string()))?,
+        None => {
+            let reply = mock_reply(&name, &req.message);
+            futures::stream::once(async move { Ok(reply) }).boxed()
+        }
+    };
+
+    let stream = futures::stream::unfold(
+        (pieces, String::new(), false),
+        |(mut pieces, mut reply, finished)| async move {
+            if finished {
+                return None;
+            }
+            let event = match pieces.next().await {
+                Some(Ok(text)) => {
+                    reply.push_str(&text);
+                    let event = Event::default()
+                        .event("delta")
+                        .data(serde_json::json!({ "text": text }).to_string());
+                    return Some((Ok(event), (pieces, reply, false)));
+                }
+                Some(Err(e)) => Event::default()
+                    .event("error")
+                    .data(serde_json::json!({ "message": e.to_string() }).to_string()),
+                None => Event::default()
+                    .event("done")
+                    .data(serde_json::json!({ "reply": reply }).to_string()),
+            };
+            Some((Ok(event), (pieces, reply, true)))
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TestConnectionRequest {
@@ -169,8 +329,29 @@ pub struct TestConnectionResponse {
 
 /// Test LLM connection
 pub async fn test_connection(
-    Json(req): Json<TestConnectionRequest>,
+    State(state): State<AppState>,
+    Json(mut req): Json<TestConnectionRequest>,
 ) -> Result<Json<TestConnectionResponse>, AppError> {
+    // Without a key in the request, test the stored (or env) one
+    let key = match req.provider.as_str() {
+        "gemini" => Some(&mut req.gemini_api_key),
+        "deepseek" => Some(&mut req.deepseek_api_key),
+        "openai_compatible" => Some(&mut req.openai_compatible_api_key),
+        _ => None,
+    };
+    let base_url = match req.provider.as_str() {
+        "openai_compatible" => req.openai_compatible_base_url.clone(),
+        _ => None,
+    };
+    if let Some(key) = key {
+        *key = credentials::resolve(
+            &state.db_pool,
+            &req.provider,
+            key.as_deref(),
+            base_url.as_deref(),
+        )
+        .await;
+    }
     let client = build_client(&req)?;
 
     match req.provider.as_str() {
@@ -303,6 +484,69 @@ pub async fn test_connection(
     }
 }
 
+// ============ Usage ============
+
+/// Token usage and estimated cost across all tasks: overall, per model and
+/// for the 50 most recently active tasks
+pub async fn usage(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let by_model: Vec<UsageRow> = sqlx::query_as(
+        r#"
+        SELECT NULL::TEXT AS stage, provider, model,
+            SUM(calls)::BIGINT AS calls,
+            SUM(prompt_tokens)::BIGINT AS prompt_tokens,
+            SUM(completion_tokens)::BIGINT AS completion_tokens,
+            SUM(cost_usd) AS cost_usd
+        FROM task_llm_usage
+        GROUP BY provider, model
+        ORDER BY cost_usd DESC, provider, model
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let total = UsageRow::total(&by_model);
+
+    let by_task: Vec<(uuid::Uuid, String, i64, i64, i64, f64, i64)> = sqlx::query_as(
+        r#"
+        SELECT u.task_id, t.prompt,
+            SUM(u.calls)::BIGINT, SUM(u.prompt_tokens)::BIGINT,
+            SUM(u.completion_tokens)::BIGINT, SUM(u.cost_usd), MAX(u.updated_at)
+        FROM task_llm_usage u
+        JOIN insight_tasks t ON t.id = u.task_id
+        GROUP BY u.task_id, t.prompt
+        ORDER BY MAX(u.updated_at) DESC
+        LIMIT 50
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+    let by_task: Vec<serde_json::Value> = by_task
+        .into_iter()
+        .map(
+            |(task_id, prompt, calls, prompt_tokens, completion_tokens, cost_usd, updated_at)| {
+                serde_json::json!({
+                    "task_id": task_id,
+                    "prompt": prompt,
+                    "calls": calls,
+                    "prompt_tokens": prompt_tokens,
+                    "completion_tokens": completion_tokens,
+                    "cost_usd": cost_usd,
+                    "updated_at": updated_at
+                })
+            },
+        )
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "total": total,
+            "by_model": by_model,
+            "by_task": by_task
+        }
+    })))
+}
+
 // ============ Ollama Test Connection ============
 
 #[derive(Debug, Deserialize)]
@@ -361,7 +605,7 @@ pub async fn test_ollama_connection(
                     .unwrap_or_else(|| "qwen3-embedding:8b-q8_0".to_string());
                 let has_model = models
                     .iter()
-                    .any(|m| m.starts_with(&embedding_model.split(':').next().unwrap_or("")));
+                    .any(|m| m.starts_with(embedding_model.split(':').next().unwrap_or("")));
 
                 if models.is_empty() {
                     Ok(Json(TestOllamaResponse {
@@ -433,76 +677,3 @@ fn build_client(req: &TestConnectionRequest) -> Result<reqwest::Client, AppError
         .build()
         .map_err(|e| AppError::Internal(e.to_string()))
 }
-
-// ... existing helper functions (call_gemini_chat, call_deepseek_chat) need to be updated to accept a client instead of creating new one?
-// Or I can keep them as is for now since `chat` endpoint handles creating its own client (which doesn't use the proxy config from frontend yet!).
-// Wait, the `chat` endpoint reads keys from ENV vars, but the `test` endpoint uses keys from request.
-// The `chat` endpoint implementation is currently using ENV vars, which means the frontend settings (saved in local storage) are NOT being used for actual chat?
-// That seems like a separate issue. The user is asking about "configuration page not working".
-// I will just implement the `test_connection` handler first.
-// The existing `call_gemini_chat` logic is fine for the `chat` endpoint if we assume server-side config.
-// But valid observation: the frontend configures keys, but the backend `chat` uses ENV.
-// For now, I will leave `request` helper functions below but I am replacing lines 142-202 which contain them.
-// I should preserve them.
-
-async fn call_gemini_chat(api_key: &str, prompt: &str) -> Result<String, AppError> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash:generateContent?key={}",
-        api_key
-    );
-
-    let response = client
-        .post(&url)
-        .json(&serde_json::json!({
-            "contents": [{"parts": [{"text": prompt}]}],
-            "generationConfig": {
-                "temperature": 0.8,
-                "maxOutputTokens": 1024
-            }
-        }))
-        .send()
-        .await
-        .map_err(|e| AppError::Internal(format!("Gemini Request Failed: {:#?}", e)))?;
-
-    let data: serde_json::Value = response.json().await?;
-    let text = data
-        .get("candidates")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("content"))
-        .and_then(|c| c.get("parts"))
-        .and_then(|p| p.get(0))
-        .and_then(|p| p.get("text"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("")
-        .to_string();
-
-    Ok(text)
-}
-
-async fn call_deepseek_chat(api_key: &str, prompt: &str) -> Result<String, AppError> {
-    let client = reqwest::Client::new();
-    let response = client
-        .post("https://api.deepseek.com/chat/completions")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&serde_json::json!({
-            "model": "deepseek-chat",
-            "messages": [{"role": "user", "content": prompt}],
-            "temperature": 0.8,
-            "max_tokens": 1024
-        }))
-        .send()
-        .await?;
-
-    let data: serde_json::Value = response.json().await?;
-    let text = data
-        .get("choices")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("message"))
-        .and_then(|m| m.get("content"))
-        .and_then(|t| t.as_str())
-        .unwrap_or("")
-        .to_string();
-
-    Ok(text)
-}
@@ -1,8 +1,40 @@
 //! API modules
 
+pub mod account;
+pub mod account_import;
+pub mod account_list;
+pub mod analytics;
+pub mod annotations;
+pub mod archive;
+pub mod auth;
+pub mod cache;
+pub mod compare;
+pub mod corpus;
+pub mod crawl;
+pub mod digest;
+pub mod docx;
 pub mod embedding;
+pub mod export;
+pub mod export_manifest;
+pub mod feedback;
+pub mod feeds;
+pub mod health;
 pub mod insight;
 pub mod llm;
+pub mod naming;
+pub mod obsidian;
 pub mod pdf;
+pub mod pdf_engine;
+pub mod proxy_gateway;
 pub mod public;
+pub mod reanalyze;
+pub mod schedule;
+pub mod search;
+pub mod settings;
+pub mod share;
+pub mod spreadsheet;
+pub mod summary;
+pub mod task_export;
+pub mod template;
+pub mod users;
 pub mod web;
@@ -0,0 +1,279 @@
+//! Export file naming
+//!
+//! Per-article files (markdown, PDF, DOCX) are named by a template such as
+//! `{date}_{account}_{title}`, optionally inside one folder per account.
+//! Names are assigned up front, so repeated names within a folder get a
+//! `_2`, `_3`... suffix deterministically. Files an incremental export keeps
+//! stay reserved for their articles.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::FixedOffset;
+use uuid::Uuid;
+
+use crate::api::insight::InsightArticle;
+
+/// The historical `{index}_{sanitized_title}` naming
+pub const DEFAULT_TEMPLATE: &str = "{index}_{title}";
+const PLACEHOLDERS: &[&str] = &["index", "date", "account", "title", "similarity"];
+/// Characters kept from each name or name part (before any collision suffix)
+const MAX_NAME_CHARS: usize = 120;
+/// Folder for articles without an account name
+const NO_ACCOUNT: &str = "未知公众号";
+/// Characters common filesystems reject, replaced in the template's own text
+const FORBIDDEN: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Where an article's file goes, relative to the export directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportName {
+    /// Per-account folder, when grouping by account
+    pub folder: Option<String>,
+    /// File name without extension
+    pub stem: String,
+}
+
+/// Names taken so far in one export folder, compared case-insensitively as
+/// on Windows and macOS, with the article each belongs to
+#[derive(Debug, Default)]
+pub struct Claims(HashMap<(String, String), Option<Uuid>>);
+
+impl Claims {
+    /// Reserve the files (relative paths by article) a previous export left,
+    /// so new names never overwrite a file that is kept
+    pub fn kept(files: &HashMap<Uuid, String>) -> Self {
+        let mut claims = Claims::default();
+        for (article, file) in files {
+            let path = Path::new(file);
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            let folder = path.parent().and_then(|p| p.to_str()).unwrap_or("");
+            claims.0.insert(claims_key(folder, stem), Some(*article));
+        }
+        claims
+    }
+
+    /// Reserve a name no article may take
+    pub fn reserve(&mut self, folder: &str, stem: &str) {
+        self.0.insert(claims_key(folder, stem), None);
+    }
+
+    /// Take `folder`/`stem` for `article`; `false` when another article has it
+    pub fn claim(&mut self, folder: &str, stem: &str, article: Uuid) -> bool {
+        match self.0.entry(claims_key(folder, stem)) {
+            Entry::Vacant(entry) => {
+                entry.insert(Some(article));
+                true
+            }
+            Entry::Occupied(entry) => *entry.get() == Some(article),
+        }
+    }
+}
+
+fn claims_key(folder: &str, stem: &str) -> (String, String) {
+    (folder.to_lowercase(), stem.to_lowercase())
+}
+
+/// A title or account name as a file or folder name: anything but letters,
+/// digits and spaces becomes `_`, as exports always did
+pub fn safe_component(value: &str) -> String {
+    value
+        .trim()
+        .replace(|c: char| !c.is_alphanumeric() && c != ' ', "_")
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect()
+}
+
+/// Check that every `{placeholder}` in a template is known
+pub fn validate_template(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            return Err("命名模板中的 { 没有闭合".to_string());
+        };
+        let name = &rest[start + 1..start + len];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "未知的命名占位符: {{{}}} (可选 {})",
+                name,
+                PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(())
+}
+
+/// Fill a template for the article at (0-based) `index`
+fn render(template: &str, index: usize, article: &InsightArticle) -> String {
+    let date = article
+        .publish_time
+        .filter(|ts| *ts > 0)
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        // WeChat publishing follows China time
+        .map(|dt| {
+            dt.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+                .format("%Y-%m-%d")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let similarity = article
+        .similarity
+        .map(|s| format!("{:.2}", s))
+        .unwrap_or_default();
+    let name = template
+        .replace(|c: char| FORBIDDEN.contains(&c) || c.is_control(), "_")
+        .replace("{index}", &(index + 1).to_string())
+        .replace("{date}", &date)
+        .replace(
+            "{account}",
+            &safe_component(article.account_name.as_deref().unwrap_or("")),
+        )
+        .replace("{similarity}", &similarity)
+        .replace("{title}", &safe_component(&article.title));
+    // Windows drops trailing dots and spaces
+    let name: String = name.chars().take(MAX_NAME_CHARS).collect();
+    let name = name.trim_end_matches(['.', ' ']).to_string();
+    if name.chars().all(|c| matches!(c, '_' | ' ' | '.')) {
+        (index + 1).to_string()
+    } else {
+        name
+    }
+}
+
+/// Names for every article, in order, avoiding the `kept` files of other
+/// articles. `template` defaults to `DEFAULT_TEMPLATE` and must have been
+/// validated.
+pub fn export_names(
+    template: Option<&str>,
+    by_account: bool,
+    articles: &[InsightArticle],
+    kept: &HashMap<Uuid, String>,
+) -> Vec<ExportName> {
+    let template = template
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(DEFAULT_TEMPLATE);
+    let mut claims = Claims::kept(kept);
+    articles
+        .iter()
+        .enumerate()
+        .map(|(i, article)| {
+            let folder = by_account.then(|| {
+                let account = safe_component(article.account_name.as_deref().unwrap_or(""));
+                if account.trim_matches('_').is_empty() {
+                    NO_ACCOUNT.to_string()
+                } else {
+                    account
+                }
+            });
+            let base = render(template, i, article);
+            let key = folder.as_deref().unwrap_or("");
+            let mut stem = base.clone();
+            let mut n = 2;
+            while !claims.claim(key, &stem, article.id) {
+                stem = format!("{}_{}", base, n);
+                n += 1;
+            }
+            ExportName { folder, stem }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, account: Option<&str>) -> InsightArticle {
+        InsightArticle {
+            id: Uuid::new_v4(),
+            task_id: uuid::Uuid::nil(),
+            title: title.to_string(),
+            url: String::new(),
+            account_name: account.map(str::to_string),
+            account_fakeid: None,
+            publish_time: Some(1_700_000_000),
+            similarity: Some(0.8765),
+            insight: None,
+            relevance_score: None,
+            created_at: 0,
+            chunk_similarity: None,
+            article_id: None,
+            duplicates_of: None,
+            feedback: None,
+            status: None,
+            status_checked_at: None,
+            author: None,
+            is_original: None,
+            biz: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_export_names() {
+        let articles = vec![
+            article("AI: 回顾/展望", Some("科技观察")),
+            article("周报", Some("科技观察")),
+            article("周报", Some("科技观察")),
+            article("周报", None),
+        ];
+
+        let names = export_names(None, false, &articles, &HashMap::new());
+        assert_eq!(names[0].stem, "1_AI_ 回顾_展望");
+        assert_eq!(names[0].folder, None);
+
+        let names = export_names(
+            Some("{date}_{title}_{similarity}"),
+            true,
+            &articles,
+            &HashMap::new(),
+        );
+        assert_eq!(names[0].stem, "2023-11-15_AI_ 回顾_展望_0.88");
+        assert_eq!(names[1].folder.as_deref(), Some("科技观察"));
+        assert_eq!(names[1].stem, "2023-11-15_周报_0.88");
+        assert_eq!(names[2].stem, "2023-11-15_周报_0.88_2");
+        // Another folder, so no collision
+        assert_eq!(names[3].folder.as_deref(), Some(NO_ACCOUNT));
+        assert_eq!(names[3].stem, "2023-11-15_周报_0.88");
+
+        assert_eq!(
+            export_names(Some("{account}"), false, &articles, &HashMap::new())[3].stem,
+            "4"
+        );
+        assert_eq!(
+            export_names(
+                Some("{index}: {account}"),
+                false,
+                &articles,
+                &HashMap::new()
+            )[0]
+            .stem,
+            "1_ 科技观察"
+        );
+
+        // A kept file of another article is not overwritten; an article
+        // keeps its own
+        let kept = HashMap::from([
+            (
+                articles[2].id,
+                "科技观察/2023-11-15_周报_0.88.md".to_string(),
+            ),
+            (articles[3].id, "1_AI_ 回顾_展望.pdf".to_string()),
+        ]);
+        let names = export_names(Some("{date}_{title}_{similarity}"), true, &articles, &kept);
+        assert_eq!(names[1].stem, "2023-11-15_周报_0.88_2");
+        assert_eq!(names[2].stem, "2023-11-15_周报_0.88");
+        let names = export_names(None, false, &articles, &kept);
+        assert_eq!(names[0].stem, "1_AI_ 回顾_展望_2");
+
+        assert!(validate_template("{account}/{title}").is_ok());
+        assert!(validate_template("{author}").is_err());
+        assert!(validate_template("{title").is_err());
+    }
+}
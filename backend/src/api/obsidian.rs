@@ -0,0 +1,259 @@
+//! Obsidian vault export
+//!
+//! Builds on the markdown export: one note per article with YAML front matter
+//! (tags from the task keywords) and images in the vault's `attachments/`
+//! folder, plus an index note (map of content) linking every article note with
+//! `[[wikilinks]]`.
+
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::api::insight::{InsightArticle, InsightTask};
+use crate::api::naming::Claims;
+
+/// Folder, relative to the vault root, holding downloaded images
+pub const ATTACHMENTS_DIR: &str = "attachments";
+/// Name of the map-of-content note
+pub const INDEX_NOTE: &str = "Index";
+/// Characters Obsidian rejects in note names or treats as link syntax
+const FORBIDDEN: &[char] = &[
+    '[', ']', '#', '^', '|', '\\', '/', ':', '*', '?', '"', '<', '>',
+];
+const MAX_NAME_CHARS: usize = 80;
+
+/// An article note, as referenced from the index
+pub struct NoteRef {
+    pub name: String,
+    pub title: String,
+    pub account_name: Option<String>,
+    pub publish_time: Option<i64>,
+    pub insight: Option<String>,
+}
+
+/// Note name safe for Obsidian and common filesystems
+pub fn safe_note_name(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| {
+            if FORBIDDEN.contains(&c) || c.is_control() {
+                ' '
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_start_matches('.')
+        .chars()
+        .take(MAX_NAME_CHARS)
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name
+    }
+}
+
+/// Unique note names for `(article, title)` pairs, numbering repeats
+/// ("Title 2", "Title 3", ...) and avoiding the `kept` notes of other articles
+pub fn note_names<'a>(
+    titles: impl IntoIterator<Item = (Uuid, &'a str)>,
+    kept: &HashMap<Uuid, String>,
+) -> Vec<String> {
+    let mut claims = Claims::kept(kept);
+    claims.reserve("", INDEX_NOTE);
+    titles
+        .into_iter()
+        .map(|(article, title)| {
+            let base = safe_note_name(title);
+            let mut name = base.clone();
+            let mut n = 2;
+            while !claims.claim("", &name, article) {
+                name = format!("{} {}", base, n);
+                n += 1;
+            }
+            name
+        })
+        .collect()
+}
+
+/// Obsidian tag for a keyword: no spaces or punctuation other than `_`, `-` and `/`
+pub fn tag(keyword: &str) -> Option<String> {
+    let tag: String = keyword
+        .trim()
+        .chars()
+        .map(|c| if c.is_whitespace() { '_' } else { c })
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'))
+        .collect();
+    // Purely numeric tags are not valid
+    if tag.is_empty() || tag.chars().all(|c| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(tag)
+    }
+}
+
+/// Tags shared by every note of the task
+pub fn task_tags(task: &InsightTask) -> Vec<String> {
+    let mut seen = HashSet::new();
+    std::iter::once("wechat-insight".to_string())
+        .chain(task.keywords.iter().filter_map(|k| tag(k)))
+        .filter(|t| seen.insert(t.clone()))
+        .collect()
+}
+
+/// YAML scalar; a JSON string is a valid double-quoted YAML scalar
+fn yaml(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn fmt_date(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// Local image path inside the vault; remote and inline images are kept as is
+fn attachment_path(src: &str) -> Option<String> {
+    if src.starts_with("http") || src.starts_with("//") || src.starts_with("data:") {
+        return None;
+    }
+    let file = src.rsplit('/').next().filter(|f| !f.is_empty())?;
+    Some(format!("{}/{}", ATTACHMENTS_DIR, file))
+}
+
+/// Body markdown with images pointing into `attachments/`
+fn body(html: &str) -> String {
+    crate::content::markdown::to_markdown_with(html, &|src| {
+        attachment_path(src).unwrap_or_else(|| src.to_string())
+    })
+}
+
+/// Full note of one article; `html` is its page with images pointing at the
+/// downloaded copies
+pub fn article_note(article: &InsightArticle, html: &str, tags: &[String]) -> String {
+    let mut note = String::from("---\n");
+    note.push_str(&format!("title: {}\n", yaml(&article.title)));
+    note.push_str(&format!("source: {}\n", yaml(&article.url)));
+    if let Some(account) = &article.account_name {
+        note.push_str(&format!("account: {}\n", yaml(account)));
+    }
+    if let Some(author) = &article.author {
+        note.push_str(&format!("author: {}\n", yaml(author)));
+    }
+    if let Some(original) = article.is_original {
+        note.push_str(&format!("original: {}\n", original));
+    }
+    if let Some(ts) = article.publish_time.filter(|ts| *ts > 0) {
+        note.push_str(&format!("published: {}\n", fmt_date(ts)));
+    }
+    if let Some(score) = article.similarity {
+        note.push_str(&format!("similarity: {:.4}\n", score));
+    }
+    note.push_str("tags:\n");
+    for t in tags {
+        note.push_str(&format!("  - {}\n", t));
+    }
+    note.push_str("---\n\n");
+
+    note.push_str(&format!("# {}\n\n", article.title));
+    if let Some(insight) = article.insight.as_deref().filter(|i| !i.is_empty()) {
+        note.push_str("> [!summary] Insight\n");
+        for line in insight.lines() {
+            note.push_str(&format!("> {}\n", line));
+        }
+        note.push('\n');
+    }
+    note.push_str(&format!("Back to [[{}]]\n\n", INDEX_NOTE));
+    note.push_str(&body(html));
+    note.push('\n');
+    note
+}
+
+/// Map-of-content note linking every exported article
+pub fn index_note(task: &InsightTask, notes: &[NoteRef], tags: &[String]) -> String {
+    let mut index = String::from("---\n");
+    index.push_str(&format!("title: {}\n", yaml(&task.prompt)));
+    index.push_str(&format!("created: {}\n", fmt_date(task.created_at)));
+    index.push_str(&format!("lang: {}\n", task.output_language()));
+    index.push_str("tags:\n");
+    for t in tags {
+        index.push_str(&format!("  - {}\n", t));
+    }
+    index.push_str("  - moc\n---\n\n");
+
+    index.push_str(&format!("# {}\n\n", task.prompt));
+    if !task.keywords.is_empty() {
+        index.push_str(&format!("Keywords: {}\n\n", task.keywords.join(", ")));
+    }
+    index.push_str(&format!("## Articles ({})\n\n", notes.len()));
+    for note in notes {
+        let mut meta = Vec::new();
+        if let Some(account) = &note.account_name {
+            meta.push(account.clone());
+        }
+        if let Some(ts) = note.publish_time.filter(|ts| *ts > 0) {
+            meta.push(fmt_date(ts));
+        }
+        let link = if note.name == note.title {
+            format!("[[{}]]", note.name)
+        } else {
+            format!(
+                "[[{}|{}]]",
+                note.name,
+                note.title.replace(['[', ']', '|'], " ")
+            )
+        };
+        if meta.is_empty() {
+            index.push_str(&format!("- {}\n", link));
+        } else {
+            index.push_str(&format!("- {} · {}\n", link, meta.join(" · ")));
+        }
+        if let Some(insight) = note.insight.as_deref().filter(|i| !i.is_empty()) {
+            let first_line = insight.lines().next().unwrap_or_default();
+            index.push_str(&format!("    - {}\n", first_line));
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_names_and_tags() {
+        assert_eq!(
+            safe_note_name("AI: 2024 [深度] #1 / 回顾?"),
+            "AI 2024 深度 1 回顾"
+        );
+        assert_eq!(safe_note_name("..."), "Untitled");
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let titles = ["标题", "标题", "index", "标题"];
+        assert_eq!(
+            note_names(ids.iter().copied().zip(titles), &HashMap::new()),
+            vec!["标题", "标题 2", "index 2", "标题 3"]
+        );
+        let kept = HashMap::from([(ids[3], "标题.md".to_string())]);
+        assert_eq!(
+            note_names(ids.iter().copied().zip(titles), &kept),
+            vec!["标题 2", "标题 3", "index 2", "标题"]
+        );
+
+        assert_eq!(tag("大 模型").as_deref(), Some("大_模型"));
+        assert_eq!(tag("C++"), Some("C".to_string()));
+        assert_eq!(tag("2024"), None);
+
+        assert_eq!(
+            attachment_path("images/a.png").as_deref(),
+            Some("attachments/a.png")
+        );
+        assert_eq!(attachment_path("https://mmbiz.qpic.cn/x"), None);
+    }
+}
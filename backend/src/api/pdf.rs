@@ -1,6 +1,12 @@
 //! PDF generation API
 //!
-//! Converts HTML to PDF using Prince XML.
+//! Converts HTML to PDF with the configured engine (Prince XML, or headless
+//! Chromium as a fallback), see `pdf_engine`.
+//!
+//! Page size, margins, font and header / footer come from `PdfOptions`, taken
+//! by `/api/pdf` and by PDF exports (the `pdf` field of the request). Headers and footers are
+//! Prince margin boxes; Chromium ignores them. Both engines turn headings
+//! into PDF bookmarks and take the document title from `PdfMetadata`.
 
 use axum::{
     extract::State,
@@ -8,34 +14,218 @@ use axum::{
     response::Response,
     Json,
 };
-use serde::Deserialize;
-use std::process::Command;
+use serde::{Deserialize, Serialize};
 use tokio::fs;
 
 use crate::api::insight;
 use crate::error::AppError;
 use crate::AppState;
 
-use lazy_static::lazy_static;
-
-lazy_static! {
-    /// Prince XML executable path - configurable via PRINCE_PATH env var
-    static ref PRINCE_PATH: String = std::env::var("PRINCE_PATH").unwrap_or_else(|_| {
-        if cfg!(target_os = "windows") {
-            "C:\\Program Files\\Prince\\engine\\bin\\prince.exe".to_string()
-        } else {
-            "/usr/bin/prince".to_string() // Linux/macOS default
-        }
-    });
-}
+const DEFAULT_FONT_FAMILY: &str =
+    r#""Noto Sans CJK SC", "WenQuanYi Micro Hei", "Microsoft YaHei", "SimHei", sans-serif"#;
+const MAX_MARGIN_MM: f32 = 50.0;
 
 #[derive(Debug, Deserialize)]
 pub struct PdfRequest {
     pub html: String,
     pub filename: Option<String>,
+    /// PDF author, e.g. the official account
+    pub author: Option<String>,
+    /// Unix timestamp recorded as the PDF creation date
+    pub publish_time: Option<i64>,
+    #[serde(default)]
+    pub options: PdfOptions,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+/// Styling of generated PDFs. Unset fields keep the defaults of the document
+/// kind: single articles leave page size and margins to the engine and have
+/// no header or footer, merged reports use A4 with a title header and page
+/// numbers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfOptions {
+    pub page_size: Option<PageSize>,
+    /// Uniform page margin in millimetres
+    pub margin_mm: Option<f32>,
+    /// CSS font family list, e.g. `"Source Han Serif SC", serif`
+    pub font_family: Option<String>,
+    /// Document title in the page header
+    pub header_title: Option<bool>,
+    /// `page / pages` in the page footer
+    pub page_numbers: Option<bool>,
+    /// The article's insight below its title (exports only)
+    #[serde(default = "default_true")]
+    pub include_insight: bool,
+    /// Cover page of merged reports
+    #[serde(default = "default_true")]
+    pub cover: bool,
+    /// Title on the report cover and header instead of the task prompt
+    pub cover_title: Option<String>,
 }
 
-/// Generate PDF from HTML using Prince
+fn default_true() -> bool {
+    true
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page_size: None,
+            margin_mm: None,
+            font_family: None,
+            header_title: None,
+            page_numbers: None,
+            include_insight: true,
+            cover: true,
+            cover_title: None,
+        }
+    }
+}
+
+impl PdfOptions {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if let Some(margin) = self.margin_mm {
+            if !(0.0..=MAX_MARGIN_MM).contains(&margin) {
+                return Err(AppError::BadRequest(format!(
+                    "页边距应在 0 到 {} 毫米之间",
+                    MAX_MARGIN_MM
+                )));
+            }
+        }
+        if let Some(font) = &self.font_family {
+            // Goes into the stylesheet verbatim
+            if font.trim().is_empty()
+                || font.len() > 200
+                || font.contains([';', '{', '}', '<', '>', '\\'])
+            {
+                return Err(AppError::BadRequest(format!("无效的字体: {}", font)));
+            }
+        }
+        Ok(())
+    }
+
+    fn font_family(&self) -> &str {
+        self.font_family.as_deref().unwrap_or(DEFAULT_FONT_FAMILY)
+    }
+
+    /// `@page` rule for a document kind; `header` holds the margin boxes of
+    /// the page header
+    fn page_rule(&self, header: &str, defaults: &PageDefaults) -> String {
+        let mut rule = String::new();
+        if let Some(size) = self.page_size.or(defaults.size) {
+            rule.push_str(match size {
+                PageSize::A4 => "      size: A4;\n",
+                PageSize::Letter => "      size: Letter;\n",
+            });
+        }
+        match (self.margin_mm, defaults.margin) {
+            (Some(margin), _) => rule.push_str(&format!("      margin: {}mm;\n", margin)),
+            (None, Some(margin)) => rule.push_str(&format!("      margin: {};\n", margin)),
+            (None, None) => {}
+        }
+        if self.header_title.unwrap_or(defaults.margin_boxes) {
+            rule.push_str(header);
+        }
+        if self.page_numbers.unwrap_or(defaults.margin_boxes) {
+            rule.push_str(
+                "      @bottom-center { content: counter(page) \" / \" counter(pages); font-size: 9px; color: #999; }\n",
+            );
+        }
+        if rule.is_empty() {
+            return rule;
+        }
+        format!("@page {{\n{}    }}", rule)
+    }
+}
+
+/// Page settings of a document kind where `PdfOptions` leaves them unset
+struct PageDefaults {
+    size: Option<PageSize>,
+    margin: Option<&'static str>,
+    /// Header and page numbers
+    margin_boxes: bool,
+}
+
+const ARTICLE_PAGE: PageDefaults = PageDefaults {
+    size: None,
+    margin: None,
+    margin_boxes: false,
+};
+
+const REPORT_PAGE: PageDefaults = PageDefaults {
+    size: Some(PageSize::A4),
+    margin: Some("22mm 18mm 20mm 18mm"),
+    margin_boxes: true,
+};
+
+/// Document information of a generated PDF, written as `<title>` and `<meta>`
+/// tags; Prince carries all of it into the PDF, Chromium only the title
+#[derive(Debug, Default)]
+pub struct PdfMetadata<'a> {
+    pub title: &'a str,
+    /// Official account of the article
+    pub author: Option<&'a str>,
+    /// Unix timestamp, the article's publish time
+    pub created: Option<i64>,
+    pub keywords: &'a [String],
+}
+
+impl PdfMetadata<'_> {
+    fn head(&self) -> String {
+        use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+
+        let mut head = format!("<title>{}</title>", text(self.title));
+        if let Some(author) = self.author.filter(|a| !a.is_empty()) {
+            head.push_str(&format!(
+                "\n  <meta name=\"author\" content=\"{}\">",
+                attr(author)
+            ));
+        }
+        if let Some(date) = self
+            .created
+            .filter(|ts| *ts > 0)
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        {
+            head.push_str(&format!(
+                "\n  <meta name=\"date\" content=\"{}\">",
+                date.to_rfc3339()
+            ));
+        }
+        if !self.keywords.is_empty() {
+            head.push_str(&format!(
+                "\n  <meta name=\"keywords\" content=\"{}\">",
+                attr(&self.keywords.join(", "))
+            ));
+        }
+        head
+    }
+}
+
+/// `value` as a quoted CSS string
+fn css_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' | '\r' => quoted.push(' '),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Generate PDF from HTML
 pub async fn generate_pdf(
     State(state): State<AppState>, // Inject State
     Json(req): Json<PdfRequest>,
@@ -43,6 +233,7 @@ pub async fn generate_pdf(
     if req.html.is_empty() {
         return Err(AppError::BadRequest("Missing html content".to_string()));
     }
+    req.options.validate()?;
 
     let filename = req.filename.as_deref().unwrap_or("article");
     let temp_id = uuid::Uuid::new_v4().to_string();
@@ -65,22 +256,39 @@ pub async fn generate_pdf(
         .build()
         .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
 
+    // The page comes from the client: strip scripts, iframes and trackers before rendering
+    let html = crate::content::sanitize::sanitize(&req.html);
+
     // Call process_html_images to rewrite HTML to point to local temp images (fetched from DB or net)
     // We pass None for gateway as single export doesn't currently support custom gateway selection
     let (processed_html, _downloaded_images) = insight::process_html_images(
         &client,
-        &req.html,
+        &state.downloads,
+        &html,
         &images_dir,
         &temp_id, // Prefix not really used in current impl but required
         None,
-        None,
         &state.db_pool,
         true, // Single export PDF uses absolute paths
     )
     .await;
 
     // Call helper with PROCESSED HTML
-    match convert_html_to_pdf(&processed_html, &temp_pdf, filename, Some(&temp_dir)).await {
+    let meta = PdfMetadata {
+        title: filename,
+        author: req.author.as_deref(),
+        created: req.publish_time,
+        keywords: &[],
+    };
+    match convert_html_to_pdf(
+        &processed_html,
+        &temp_pdf,
+        &meta,
+        Some(&temp_dir),
+        &req.options,
+    )
+    .await
+    {
         Ok(_) => {}
         Err(e) => {
             // cleanup on error
@@ -122,8 +330,9 @@ pub async fn generate_pdf(
 pub async fn convert_html_to_pdf(
     html: &str,
     output_path: &std::path::Path,
-    title: &str,
+    meta: &PdfMetadata<'_>,
     working_dir: Option<&std::path::Path>, // Added optional working_dir
+    options: &PdfOptions,
 ) -> Result<(), AppError> {
     let temp_id = uuid::Uuid::new_v4().to_string();
     let default_temp_dir = std::env::temp_dir().join("wechat-insights-pdf");
@@ -136,17 +345,18 @@ pub async fn convert_html_to_pdf(
         fs::create_dir_all(&temp_dir).await?;
     }
 
-    // Build full HTML with Prince-friendly styles
+    // Build full HTML with print-friendly styles
     let full_html = format!(
         r#"<!DOCTYPE html>
 <html>
 <head>
   <meta charset="utf-8">
-  <title>{}</title>
+  {}
   <style>
+    {}
     /* Force font override with !important to ignore article inline styles */
     * {{
-      font-family: "Noto Sans CJK SC", "WenQuanYi Micro Hei", "Microsoft YaHei", "SimHei", sans-serif !important;
+      font-family: {font} !important;
       overflow-wrap: break-word;
       word-wrap: break-word;
       /* Aggressive Layout Resets */
@@ -159,7 +369,7 @@ pub async fn convert_html_to_pdf(
       text-indent: 0 !important;   /* Fix weird indents */
     }}
     html, body {{
-      font-family: "Noto Sans CJK SC", "WenQuanYi Micro Hei", "Microsoft YaHei", "SimHei", sans-serif !important;
+      font-family: {font} !important;
       font-size: 14px;
       line-height: 1.6;
       color: #333;
@@ -178,6 +388,10 @@ pub async fn convert_html_to_pdf(
         box-sizing: border-box !important;
         height: auto !important;
     }}
+    /* Bookmarks: the title and the article's top-level sections */
+    h1 {{ prince-bookmark-level: 1; }}
+    h2 {{ prince-bookmark-level: 2; }}
+    h3, h4, h5, h6 {{ prince-bookmark-level: none; }}
     h1, h2, h3 {{
       font-weight: bold;
       page-break-after: avoid;
@@ -190,61 +404,314 @@ pub async fn convert_html_to_pdf(
       widows: 3;
       margin-bottom: 1em !important;
     }}
+    .insight {{
+      background: #f6f8fa;
+      border-left: 4px solid #576b95 !important;
+      padding: 8px 12px;
+      white-space: pre-wrap;
+    }}
   </style>
 </head>
 <body>
 {}
 </body>
 </html>"#,
-        title, 
-        html
+        meta.head(),
+        options.page_rule(
+            &format!(
+                "      @top-left {{ content: {}; font-size: 9px; color: #999; }}\n",
+                css_string(meta.title)
+            ),
+            &ARTICLE_PAGE
+        ),
+        html,
+        font = options.font_family()
     );
 
     // Write HTML to temp file
     fs::write(&temp_html, &full_html).await?;
 
-    // Execute Prince
-    tracing::info!("[PDF] Generating PDF with Prince: {}", temp_html.display());
+    run_engine(&temp_html, output_path).await
+}
 
-    let output = Command::new(PRINCE_PATH.as_str())
-        .arg(&temp_html)
-        .arg("--verbose") // Enable verbose logging
-        .arg("-o")
-        .arg(output_path)
-        .output();
+/// Render an HTML file and remove the file afterwards
+async fn run_engine(
+    temp_html: &std::path::Path,
+    output_path: &std::path::Path,
+) -> Result<(), AppError> {
+    let result = crate::api::pdf_engine::render(temp_html, output_path).await;
+    let _ = fs::remove_file(temp_html).await;
+    result
+}
 
-    match output {
-        Ok(result) => {
-            if !result.status.success() {
-                let stderr = String::from_utf8_lossy(&result.stderr);
-                tracing::error!("[PDF] Prince failed: {}", stderr);
+// ============ Merged Report ============
 
-                // Cleanup (only clean the file we created)
-                let _ = fs::remove_file(&temp_html).await;
+/// One article chapter of a merged report
+pub struct ReportSection {
+    pub title: String,
+    pub url: String,
+    pub account_name: Option<String>,
+    pub publish_time: Option<i64>,
+    pub similarity: Option<f64>,
+    pub insight: Option<String>,
+    /// Article body; images already point at local copies
+    pub body: crate::content::extract::ExtractedArticle,
+}
 
-                return Err(AppError::Internal(format!("Prince failed: {}", stderr)));
-            }
+/// Headings listed under their chapter in the table of contents
+const TOC_HEADING_LEVEL: u8 = 2;
+
+/// Assemble the full report document: cover page, table of contents, then one
+/// chapter per article with its insight. Page headers/footers use Prince margin boxes, which Chromium ignores.
+/// The table of contents links to every chapter and its top-level headings,
+/// which are also the PDF bookmarks.
+pub fn build_report_html(
+    task: &insight::InsightTask,
+    sections: &[ReportSection],
+    options: &PdfOptions,
+) -> String {
+    use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+
+    let fmt_date = |ts: i64| {
+        chrono::DateTime::from_timestamp(ts, 0)
+            .map(|dt| dt.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+
+    let title = options.cover_title.as_deref().unwrap_or(&task.prompt);
+    let header = format!(
+        "      @top-left {{ content: {}; font-size: 9px; color: #999; }}\n      @top-right {{ content: string(chapter-title); font-size: 9px; color: #999; }}\n",
+        css_string(title)
+    );
+
+    let mut html = String::new();
+    html.push_str(&format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+  <meta charset="utf-8">
+  {}
+  <style>
+    {}
+    @page cover {{
+      @top-left {{ content: none; }}
+      @top-right {{ content: none; }}
+      @bottom-center {{ content: none; }}
+    }}
+    html, body {{
+      font-family: {};
+      font-size: 14px;
+      line-height: 1.6;
+      color: #333;
+    }}
+    .cover {{ page: cover; padding-top: 35%; text-align: center; }}
+    .cover h1 {{ font-size: 28px; margin-bottom: 24px; }}
+    .cover .keywords span {{ display: inline-block; background: #f0f2f5; border-radius: 4px; padding: 0 6px; margin: 2px; font-size: 12px; }}
+    .cover .stats {{ color: #666; margin-top: 24px; }}
+    .toc {{ page-break-before: always; }}
+    .toc ol {{ padding-left: 20px; }}
+    .toc a {{ color: #333; text-decoration: none; }}
+    .toc a::after {{ content: leader('.') target-counter(attr(href), page); }}
+    .toc ol ol {{ font-size: 12px; color: #666; }}
+    .cover h1 {{ prince-bookmark-level: none; }}
+    .toc h2, .chapter h1 {{ prince-bookmark-level: 1; }}
+    .article-body h1, .article-body h2 {{ prince-bookmark-level: 2; }}
+    .article-body h3, .article-body h4, .article-body h5, .article-body h6 {{ prince-bookmark-level: none; }}
+    .chapter {{ page-break-before: always; }}
+    .chapter h1 {{ string-set: chapter-title content(); font-size: 22px; line-height: 1.4; page-break-after: avoid; }}
+    .chapter .meta {{ color: #888; font-size: 12px; word-break: break-all; }}
+    .chapter .insight {{ background: #f6f8fa; border-left: 4px solid #576b95; padding: 8px 12px; margin: 12px 0 20px; white-space: pre-wrap; }}
+    /* Tame WeChat inline layout, same resets as single-article PDFs */
+    .article-body * {{
+      max-width: 100% !important;
+      height: auto !important;
+      position: static !important;
+      float: none !important;
+      margin-left: 0 !important;
+      margin-right: 0 !important;
+      text-indent: 0 !important;
+      box-sizing: border-box !important;
+      overflow-wrap: break-word;
+    }}
+    .article-body img {{ display: block; margin: 10px auto !important; }}
+    .article-body p {{ orphans: 3; widows: 3; }}
+  </style>
+</head>
+<body>
+"#,
+        text(task.output_language()),
+        PdfMetadata {
+            title,
+            keywords: &task.keywords,
+            ..Default::default()
         }
-        Err(e) => {
-            // Cleanup
-            let _ = fs::remove_file(&temp_html).await;
-
-            // Check if Prince is not installed
-            if e.kind() == std::io::ErrorKind::NotFound {
-                return Err(AppError::Internal(
-                    "Prince XML not found. Please install from https://www.princexml.com/"
-                        .to_string(),
-                ));
+        .head(),
+        options.page_rule(&header, &REPORT_PAGE),
+        options.font_family()
+    ));
+
+    // Cover
+    if options.cover {
+        html.push_str("<section class=\"cover\">");
+        html.push_str(&format!("<h1>{}</h1>", text(title)));
+        if !task.keywords.is_empty() {
+            html.push_str("<div class=\"keywords\">");
+            for kw in &task.keywords {
+                html.push_str(&format!("<span>{}</span>", text(kw)));
             }
-            return Err(AppError::Internal(format!(
-                "Failed to execute Prince: {}",
-                e
-            )));
+            html.push_str("</div>");
+        }
+        html.push_str(&format!(
+            "<p class=\"stats\">{} 篇文章 · 目标 {} · 创建于 {} · 导出于 {}</p>",
+            sections.len(),
+            task.target_count,
+            fmt_date(task.created_at),
+            fmt_date(chrono::Utc::now().timestamp())
+        ));
+        html.push_str("</section>");
+    }
+
+    // Table of contents
+    html.push_str("<section class=\"toc\"><h2>目录</h2><ol>");
+    for (i, section) in sections.iter().enumerate() {
+        html.push_str(&format!(
+            "<li><a href=\"#article-{}\">{}</a>",
+            i + 1,
+            text(&section.title)
+        ));
+        let headings: Vec<String> = section
+            .body
+            .headings()
+            .enumerate()
+            .filter(|(_, (level, _))| *level <= TOC_HEADING_LEVEL)
+            .map(|(n, (_, heading))| {
+                format!(
+                    "<li><a href=\"#article-{}-{}\">{}</a></li>",
+                    i + 1,
+                    n + 1,
+                    text(heading)
+                )
+            })
+            .collect();
+        if !headings.is_empty() {
+            html.push_str(&format!("<ol>{}</ol>", headings.concat()));
+        }
+        html.push_str("</li>");
+    }
+    html.push_str("</ol></section>");
+
+    // Chapters
+    for (i, section) in sections.iter().enumerate() {
+        html.push_str(&format!("<section class=\"chapter\" id=\"article-{}\">", i + 1));
+        html.push_str(&format!("<h1>{}</h1>", text(&section.title)));
+
+        let mut meta = Vec::new();
+        if let Some(name) = &section.account_name {
+            meta.push(text(name).to_string());
+        }
+        if let Some(ts) = section.publish_time {
+            meta.push(fmt_date(ts));
+        }
+        if let Some(score) = section.similarity {
+            meta.push(format!("相关度 {:.2}", score));
         }
+        meta.push(format!(
+            "<a href=\"{}\">{}</a>",
+            attr(&section.url),
+            text(&section.url)
+        ));
+        html.push_str(&format!("<div class=\"meta\">{}</div>", meta.join(" · ")));
+
+        if let Some(insight) = section
+            .insight
+            .as_deref()
+            .filter(|s| options.include_insight && !s.is_empty())
+        {
+            html.push_str(&format!("<div class=\"insight\">{}</div>", text(insight)));
+        }
+        html.push_str(&format!(
+            "<div class=\"article-body\">{}</div></section>",
+            section.body.to_anchored_html(&format!("article-{}", i + 1))
+        ));
     }
 
-    // Cleanup HTML temp
-    let _ = fs::remove_file(&temp_html).await;
+    html.push_str("</body></html>");
+    html
+}
+
+/// Render a complete report document to PDF.
+/// `working_dir` is where relative image paths resolve.
+pub async fn render_report_pdf(
+    html: &str,
+    output_path: &std::path::Path,
+    working_dir: &std::path::Path,
+) -> Result<(), AppError> {
+    let temp_html = working_dir.join(format!("{}.html", uuid::Uuid::new_v4()));
+    fs::write(&temp_html, html).await?;
+    run_engine(&temp_html, output_path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_rule() {
+        let options = PdfOptions::default();
+        assert_eq!(options.page_rule("", &ARTICLE_PAGE), "");
+        let report = options.page_rule("", &REPORT_PAGE);
+        assert!(report.contains("size: A4;"));
+        assert!(report.contains("margin: 22mm 18mm 20mm 18mm;"));
+        assert!(report.contains("counter(pages)"));
 
-    Ok(())
+        let options: PdfOptions = serde_json::from_value(serde_json::json!({
+            "page_size": "Letter",
+            "margin_mm": 15,
+            "header_title": true,
+            "page_numbers": false
+        }))
+        .unwrap();
+        let rule = options.page_rule("      @top-left { content: \"t\"; }\n", &REPORT_PAGE);
+        assert!(rule.contains("size: Letter;"));
+        assert!(rule.contains("margin: 15mm;"));
+        assert!(rule.contains("@top-left"));
+        assert!(!rule.contains("counter(pages)"));
+        assert!(options.include_insight && options.cover);
+
+        assert_eq!(css_string("a \"b\"\\\n"), r#""a \"b\"\\ ""#);
+        let invalid = |field: &str, value: serde_json::Value| {
+            serde_json::from_value::<PdfOptions>(serde_json::json!({ field: value }))
+                .unwrap()
+                .validate()
+                .is_err()
+        };
+        assert!(invalid("margin_mm", serde_json::json!(80)));
+        assert!(invalid("font_family", serde_json::json!("a; } b { c")));
+        let font = serde_json::json!("\"Noto Serif SC\", serif");
+        assert!(!invalid("font_family", font));
+    }
+
+    #[test]
+    fn test_metadata_head() {
+        let keywords = vec!["AI".to_string(), "芯片".to_string()];
+        let head = PdfMetadata {
+            title: "标题 <1>",
+            author: Some("科技\"观察\""),
+            created: Some(1_700_000_000),
+            keywords: &keywords,
+        }
+        .head();
+        assert!(head.starts_with("<title>标题 &lt;1&gt;</title>"));
+        assert!(head.contains(r#"<meta name="author" content="科技&quot;观察&quot;">"#));
+        assert!(head.contains(r#"<meta name="date" content="2023-11-14T22:13:20+00:00">"#));
+        assert!(head.contains(r#"<meta name="keywords" content="AI, 芯片">"#));
+        assert_eq!(
+            PdfMetadata {
+                title: "t",
+                ..Default::default()
+            }
+            .head(),
+            "<title>t</title>"
+        );
+    }
 }
@@ -0,0 +1,231 @@
+//! PDF rendering engines
+//!
+//! Prince XML gives the best output (margin boxes, bookmarks), but needs a
+//! license and a separate install. Headless Chromium's `--print-to-pdf` is the
+//! fallback. `PDF_ENGINE` picks one (`prince`, `chromium`, default `auto`:
+//! Prince when installed, otherwise Chromium).
+
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use tokio::process::Command;
+
+use crate::error::AppError;
+
+lazy_static! {
+    /// Prince XML executable path - configurable via PRINCE_PATH env var
+    static ref PRINCE_PATH: String = std::env::var("PRINCE_PATH").unwrap_or_else(|_| {
+        if cfg!(target_os = "windows") {
+            "C:\\Program Files\\Prince\\engine\\bin\\prince.exe".to_string()
+        } else {
+            "/usr/bin/prince".to_string() // Linux/macOS default
+        }
+    });
+
+    static ref ENGINE: Result<Box<dyn PdfEngine>, String> = {
+        let preference = std::env::var("PDF_ENGINE").unwrap_or_else(|_| "auto".to_string());
+        let engine = choose(&preference, find_prince(), find_chromium());
+        match &engine {
+            Ok(e) => tracing::info!("[PDF] Using {} engine", e.name()),
+            Err(e) => tracing::warn!("[PDF] {}", e),
+        }
+        engine
+    };
+}
+
+/// Executables tried, in order, when `CHROMIUM_PATH` is unset
+const CHROMIUM_CANDIDATES: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe",
+    "C:\\Program Files (x86)\\Microsoft\\Edge\\Application\\msedge.exe",
+];
+
+/// An external program turning an HTML file into a PDF
+pub trait PdfEngine: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Command rendering `html` to `output`; relative URLs in `html` resolve
+    /// against its directory
+    fn command(&self, html: &Path, output: &Path) -> Command;
+}
+
+pub struct Prince {
+    path: PathBuf,
+}
+
+impl PdfEngine for Prince {
+    fn name(&self) -> &'static str {
+        "Prince"
+    }
+
+    fn command(&self, html: &Path, output: &Path) -> Command {
+        let mut cmd = Command::new(&self.path);
+        cmd.arg(html).arg("--verbose").arg("-o").arg(output);
+        cmd
+    }
+}
+
+pub struct Chromium {
+    path: PathBuf,
+}
+
+impl PdfEngine for Chromium {
+    fn name(&self) -> &'static str {
+        "Chromium"
+    }
+
+    fn command(&self, html: &Path, output: &Path) -> Command {
+        let mut cmd = Command::new(&self.path);
+        cmd.arg("--headless")
+            .arg("--disable-gpu")
+            .arg("--no-sandbox")
+            .arg("--no-pdf-header-footer")
+            // Bookmarks from the headings
+            .arg("--generate-pdf-document-outline")
+            .arg("--allow-file-access-from-files")
+            .arg(format!("--print-to-pdf={}", output.display()))
+            .arg(file_url(html));
+        cmd
+    }
+}
+
+/// `file://` URL of a local file, percent-encoded (spaces, non-ASCII names)
+/// and with the right number of slashes on every platform
+pub fn file_url(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    url::Url::from_file_path(&path)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("file://{}", path.display()))
+}
+
+/// Resolve a program name against `PATH`; paths are checked as is
+fn which(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .flat_map(|dir| {
+            let exe = dir.join(format!("{}.exe", program));
+            [dir.join(program), exe]
+        })
+        .find(|p| p.is_file())
+}
+
+pub fn find_prince() -> Option<PathBuf> {
+    which(&PRINCE_PATH).or_else(|| which("prince"))
+}
+
+fn find_chromium() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("CHROMIUM_PATH") {
+        return which(&path);
+    }
+    CHROMIUM_CANDIDATES.iter().find_map(|c| which(c))
+}
+
+/// Engine for a `PDF_ENGINE` value, given the executables found
+fn choose(
+    preference: &str,
+    prince: Option<PathBuf>,
+    chromium: Option<PathBuf>,
+) -> Result<Box<dyn PdfEngine>, String> {
+    let prince = prince.map(|path| Box::new(Prince { path }) as Box<dyn PdfEngine>);
+    let chromium = chromium.map(|path| Box::new(Chromium { path }) as Box<dyn PdfEngine>);
+    match preference.trim().to_lowercase().as_str() {
+        "prince" => prince.ok_or_else(|| {
+            "Prince XML not found. Please install from https://www.princexml.com/ or set PRINCE_PATH".to_string()
+        }),
+        "chromium" | "chrome" => chromium.ok_or_else(|| {
+            "Chromium not found. Install Chromium/Chrome or set CHROMIUM_PATH".to_string()
+        }),
+        "auto" | "" => prince.or(chromium).ok_or_else(|| {
+            "No PDF engine found. Install Prince XML (PRINCE_PATH) or Chromium (CHROMIUM_PATH)".to_string()
+        }),
+        other => Err(format!(
+            "Unknown PDF_ENGINE: {} (prince/chromium/auto)",
+            other
+        )),
+    }
+}
+
+/// The configured engine
+pub fn engine() -> Result<&'static dyn PdfEngine, AppError> {
+    ENGINE
+        .as_ref()
+        .map(|e| e.as_ref())
+        .map_err(|e| AppError::Internal(e.clone()))
+}
+
+/// Render an HTML file with the configured engine
+pub async fn render(html: &Path, output: &Path) -> Result<(), AppError> {
+    let engine = engine()?;
+    tracing::info!(
+        "[PDF] Generating PDF with {}: {}",
+        engine.name(),
+        html.display()
+    );
+
+    let result =
+        engine.command(html, output).output().await.map_err(|e| {
+            AppError::Internal(format!("Failed to execute {}: {}", engine.name(), e))
+        })?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        tracing::error!("[PDF] {} failed: {}", engine.name(), stderr);
+        return Err(AppError::Internal(format!(
+            "{} failed: {}",
+            engine.name(),
+            stderr
+        )));
+    }
+    // Chromium exits 0 even when printing fails
+    if !output.exists() {
+        return Err(AppError::Internal(format!(
+            "{} produced no PDF",
+            engine.name()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_engine() {
+        let prince = || Some(PathBuf::from("/usr/bin/prince"));
+        let chromium = || Some(PathBuf::from("/usr/bin/chromium"));
+
+        assert_eq!(
+            choose("auto", prince(), chromium()).unwrap().name(),
+            "Prince"
+        );
+        assert_eq!(choose("auto", None, chromium()).unwrap().name(), "Chromium");
+        assert_eq!(
+            choose("Chromium", prince(), chromium()).unwrap().name(),
+            "Chromium"
+        );
+        assert!(choose("prince", None, chromium()).is_err());
+        assert!(choose("auto", None, None).is_err());
+        assert!(choose("wkhtmltopdf", prince(), chromium()).is_err());
+
+        let cmd = Chromium {
+            path: PathBuf::from("chromium"),
+        }
+        .command(Path::new("/tmp/a.html"), Path::new("/tmp/a.pdf"));
+        let args: Vec<_> = cmd.as_std().get_args().collect();
+        assert!(args.contains(&std::ffi::OsStr::new("--print-to-pdf=/tmp/a.pdf")));
+        assert!(args.contains(&std::ffi::OsStr::new("file:///tmp/a.html")));
+        assert!(args.contains(&std::ffi::OsStr::new("--generate-pdf-document-outline")));
+
+        assert_eq!(
+            file_url(Path::new("/tmp/导出 1/a#b.html")),
+            "file:///tmp/%E5%AF%BC%E5%87%BA%201/a%23b.html"
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Download gateway management
+//!
+//! CRUD over `proxy_gateways` plus on-demand health checks. Entries are
+//! gateways or HTTP / SOCKS5 proxies (`type`). Listing includes each entry's
+//! running health (see `gateway`); authorization keys are never returned.
+//! `/api/proxy/gateways/stats` reports persisted request and failure counts,
+//! including proxies only ever listed in requests.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::gateway::{self, Gateway, HealthView, ProxyKind, GATEWAY_COLUMNS};
+use crate::AppState;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateGatewayRequest {
+    pub url: String,
+    /// Defaults to the kind implied by the URL scheme
+    #[serde(rename = "type")]
+    pub kind: Option<ProxyKind>,
+    pub authorization: Option<String>,
+    pub weight: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateGatewayRequest {
+    pub id: Uuid,
+    pub url: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<ProxyKind>,
+    /// Empty string removes the stored key
+    pub authorization: Option<String>,
+    pub weight: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GatewayIdRequest {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CheckGatewaysRequest {
+    /// Gateway to check; all enabled gateways when omitted
+    pub id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct GatewayView {
+    #[serde(flatten)]
+    gateway: Gateway,
+    has_authorization: bool,
+    health: HealthView,
+}
+
+// ============ Helpers ============
+
+/// URL without trailing slashes; its scheme must suit `kind`
+/// (http(s) for gateways and HTTP proxies, socks5(h) for SOCKS5)
+fn normalize_url(url: &str, kind: ProxyKind) -> Result<String, AppError> {
+    let url = url.trim().trim_end_matches('/');
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if kind.accepts_scheme(parsed.scheme()) => Ok(url.to_string()),
+        _ => Err(AppError::BadRequest(format!(
+            "无效的{}地址: {}",
+            kind.as_str(),
+            url
+        ))),
+    }
+}
+
+fn validate_weight(weight: Option<i32>) -> Result<(), AppError> {
+    match weight {
+        Some(w) if !(0..=100).contains(&w) => {
+            Err(AppError::BadRequest("weight必须在0到100之间".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn load_gateway(state: &AppState, id: Uuid) -> Result<Gateway, AppError> {
+    sqlx::query_as::<_, Gateway>(&format!(
+        "SELECT {} FROM proxy_gateways WHERE id = $1",
+        GATEWAY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(AppError::NotFound("Gateway not found".to_string()))
+}
+
+// ============ Handlers ============
+
+/// All gateways with their health
+pub async fn list_gateways(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let gateways = sqlx::query_as::<_, Gateway>(&format!(
+        "SELECT {} FROM proxy_gateways ORDER BY created_at",
+        GATEWAY_COLUMNS
+    ))
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let data: Vec<GatewayView> = gateways
+        .into_iter()
+        .map(|gateway| GatewayView {
+            has_authorization: gateway.auth_key.is_some(),
+            health: gateway::health(&gateway.url),
+            gateway,
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data
+    })))
+}
+
+/// Add a gateway to the pool
+pub async fn create_gateway(
+    State(state): State<AppState>,
+    Json(req): Json<CreateGatewayRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let kind = req.kind.unwrap_or_else(|| ProxyKind::infer(req.url.trim()));
+    let url = normalize_url(&req.url, kind)?;
+    validate_weight(req.weight)?;
+
+    let id = Uuid::new_v4();
+    let now = chrono::Utc::now().timestamp();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO proxy_gateways (id, url, kind, auth_key, weight, enabled, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $7)
+        ON CONFLICT (url) DO NOTHING
+        "#,
+    )
+    .bind(id)
+    .bind(&url)
+    .bind(kind.as_str())
+    .bind(req.authorization.filter(|a| !a.is_empty()))
+    .bind(req.weight.unwrap_or(1))
+    .bind(req.enabled.unwrap_or(true))
+    .bind(now)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Err(AppError::BadRequest(format!("网关 {} 已存在", url)));
+    }
+    gateway::reload(&state.db_pool).await?;
+
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
+
+/// Change a gateway's URL, key, weight or enabled flag
+pub async fn update_gateway(
+    State(state): State<AppState>,
+    Json(req): Json<UpdateGatewayRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let current = load_gateway(&state, req.id).await?;
+    let kind = req.kind.unwrap_or_else(|| current.proxy_kind());
+    let url = normalize_url(req.url.as_deref().unwrap_or(&current.url), kind)?;
+    validate_weight(req.weight)?;
+    let auth_key = match req.authorization {
+        Some(a) if a.is_empty() => None,
+        Some(a) => Some(a),
+        None => current.auth_key,
+    };
+
+    let updated = sqlx::query(
+        r#"
+        UPDATE proxy_gateways SET
+            url = $1,
+            kind = $2,
+            auth_key = $3,
+            weight = COALESCE($4, weight),
+            enabled = COALESCE($5, enabled),
+            updated_at = $6
+        WHERE id = $7
+          AND NOT EXISTS (SELECT 1 FROM proxy_gateways o WHERE o.url = $1 AND o.id <> $7)
+        "#,
+    )
+    .bind(&url)
+    .bind(kind.as_str())
+    .bind(auth_key)
+    .bind(req.weight)
+    .bind(req.enabled)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(AppError::BadRequest(format!("网关 {} 已存在", url)));
+    }
+    gateway::reload(&state.db_pool).await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Remove a gateway from the pool
+pub async fn delete_gateway(
+    State(state): State<AppState>,
+    Json(req): Json<GatewayIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let deleted = sqlx::query("DELETE FROM proxy_gateways WHERE id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?
+        .rows_affected();
+
+    if deleted == 0 {
+        return Err(AppError::NotFound("Gateway not found".to_string()));
+    }
+    gateway::reload(&state.db_pool).await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Probe one gateway (or every enabled one) now
+pub async fn check_gateways(
+    State(state): State<AppState>,
+    body: Option<Json<CheckGatewaysRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+    let gateways = match req.id {
+        Some(id) => vec![load_gateway(&state, id).await?],
+        None => {
+            sqlx::query_as::<_, Gateway>(&format!(
+                "SELECT {} FROM proxy_gateways WHERE enabled ORDER BY created_at",
+                GATEWAY_COLUMNS
+            ))
+            .fetch_all(&state.db_pool)
+            .await?
+        }
+    };
+
+    let client = reqwest::Client::builder()
+        .user_agent(crate::wechat::client::WECHAT_USER_AGENT)
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to build client: {}", e)))?;
+    let checks = gateways
+        .iter()
+        .map(|g| gateway::check(&state.db_pool, &client, g));
+    let results = futures::future::join_all(checks).await;
+
+    let mut data = Vec::with_capacity(gateways.len());
+    for (g, result) in gateways.iter().zip(results) {
+        let (ok, latency_ms, error) = match result? {
+            Ok(ms) => (true, Some(ms), None),
+            Err(e) => (false, None, Some(e)),
+        };
+        data.push(serde_json::json!({
+            "id": g.id,
+            "url": g.url,
+            "ok": ok,
+            "latency_ms": latency_ms,
+            "error": error,
+            "health": gateway::health(&g.url)
+        }));
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data
+    })))
+}
+
+/// Persisted request and failure counts per gateway, most failures first
+pub async fn gateway_stats(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let data = gateway::stats(&state.db_pool).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": data
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url(" https://gw.example.com/fetch/ ", ProxyKind::Gateway).unwrap(),
+            "https://gw.example.com/fetch"
+        );
+        assert!(normalize_url("ftp://gw.example.com", ProxyKind::Gateway).is_err());
+        assert!(normalize_url("not a url", ProxyKind::Http).is_err());
+        assert!(normalize_url("socks5://10.0.0.1:1080", ProxyKind::Socks5).is_ok());
+        assert!(normalize_url("socks5://10.0.0.1:1080", ProxyKind::Http).is_err());
+    }
+}
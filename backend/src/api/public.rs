@@ -5,12 +5,22 @@
 use axum::{
     extract::{Query, State},
     http::HeaderMap,
-    Json,
+    Extension, Json,
 };
 use serde::{Deserialize, Serialize};
 
+use crate::auth::Principal;
+use crate::content::sanitize::{self, ImageMode};
+use crate::crawl::{self, Priority};
+use crate::download::DownloadScheduler;
 use crate::error::AppError;
-use crate::proxy::{get_token_from_store, proxy_mp_request, ProxyRequestOptions};
+use crate::gateway::{Pick, ProxyKind, ProxySpec};
+use crate::proxy::{
+    get_auth_key_from_headers, get_token_from_store, proxy_mp_request_logged, ProxyRequestOptions,
+};
+use crate::ratelimit::{self, Endpoint};
+use crate::repository::{self, articles::Freshness};
+use crate::wechat::model::parse_publish_page;
 use crate::AppState;
 
 // ============ Common Types ============
@@ -101,16 +111,26 @@ pub async fn search_account(
 
     let cookie = crate::proxy::get_cookie_from_store(&headers, &state.cookie_store).await;
 
-    let response = proxy_mp_request(ProxyRequestOptions {
-        method: reqwest::Method::GET,
-        endpoint: "https://mp.weixin.qq.com/cgi-bin/searchbiz".to_string(),
-        query: Some(params),
-        body: None,
-        cookie,
-    })
+    let lane = crawl::session_lane(get_auth_key_from_headers(&headers).as_deref());
+    if let Err(retry_after) =
+        ratelimit::acquire(&lane, Endpoint::SearchBiz, ratelimit::INTERACTIVE_MAX_WAIT).await
+    {
+        return Ok(Json(ratelimit::over_budget_response(retry_after)));
+    }
+    let _permit = crawl::acquire(&lane, Priority::Interactive, "public.search_account").await;
+    let json = proxy_mp_request_logged(
+        ProxyRequestOptions {
+            method: reqwest::Method::GET,
+            endpoint: "https://mp.weixin.qq.com/cgi-bin/searchbiz".to_string(),
+            query: Some(params),
+            body: None,
+            cookie,
+        },
+        "public.search_account",
+        &lane,
+    )
     .await?;
-
-    let json: serde_json::Value = response.json().await?;
+    ratelimit::report(&lane, Endpoint::SearchBiz, ratelimit::base_ret(&json));
     Ok(Json(json))
 }
 
@@ -123,8 +143,10 @@ pub struct GetAccountsQuery {
 }
 
 /// Get local accounts from database with calculated article counts
+#[allow(clippy::type_complexity)]
 pub async fn get_db_accounts(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Query(query): Query<GetAccountsQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let offset = query.offset.unwrap_or(0);
@@ -154,12 +176,15 @@ pub async fn get_db_accounts(
             COALESCE((SELECT COUNT(*) FROM articles WHERE articles.fakeid = a.fakeid AND is_deleted = false AND itemidx = 1), 0) as message_count,
             COALESCE((SELECT COUNT(*) FROM articles WHERE articles.fakeid = a.fakeid AND is_deleted = false), 0) as article_count
         FROM accounts a
+        WHERE $3 OR a.owner_id IS NOT DISTINCT FROM $4
         ORDER BY a.update_time DESC NULLS LAST
         OFFSET $1 LIMIT $2
         "#
     )
     .bind(offset)
     .bind(limit)
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
     .fetch_all(&state.db_pool)
     .await?;
 
@@ -217,22 +242,155 @@ pub struct AddAccountRequest {
     pub nickname: String,
 }
 
+/// Add an account to the monitor list; it belongs to whoever adds it first
 pub async fn add_account(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     Json(req): Json<AddAccountRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     sqlx::query(
-        "INSERT INTO accounts (fakeid, nickname, create_time, update_time) VALUES ($1, $2, $3, $3) ON CONFLICT (fakeid) DO UPDATE SET nickname = $2, update_time = $3"
+        "INSERT INTO accounts (fakeid, nickname, create_time, update_time, owner_id) VALUES ($1, $2, $3, $3, $4) ON CONFLICT (fakeid) DO UPDATE SET nickname = $2, update_time = $3, owner_id = COALESCE(accounts.owner_id, EXCLUDED.owner_id)"
     )
     .bind(&req.fakeid)
     .bind(&req.nickname)
     .bind(chrono::Utc::now().timestamp())
+    .bind(principal.user_id)
     .execute(&state.db_pool)
     .await?;
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
+// ============ Account Stats ============
+
+#[derive(Debug, Deserialize)]
+pub struct AccountStatsQuery {
+    pub fakeid: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct MonthCount {
+    /// "YYYY-MM", China time
+    pub month: String,
+    pub articles: i64,
+    pub messages: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AlbumCount {
+    pub id: String,
+    pub title: String,
+    pub articles: i64,
+}
+
+/// Profile of an account's output from the synced `articles`, no WeChat calls
+pub async fn account_stats(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<AccountStatsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let account: Option<(Option<String>, Option<uuid::Uuid>)> =
+        sqlx::query_as("SELECT nickname, owner_id FROM accounts WHERE fakeid = $1")
+            .bind(&query.fakeid)
+            .fetch_optional(&state.db_pool)
+            .await?;
+    let (nickname, _) = account
+        .filter(|(_, owner)| principal.can_access(*owner))
+        .ok_or(AppError::NotFound("Account not found".to_string()))?;
+
+    // Messages are pushes: the first article of each (itemidx = 1)
+    let (articles, messages, first_seen, last_seen, avg_title_length): (
+        i64,
+        i64,
+        Option<i64>,
+        Option<i64>,
+        Option<f64>,
+    ) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), COUNT(*) FILTER (WHERE itemidx = 1), MIN(create_time), MAX(create_time),
+               AVG(char_length(title))::float8
+        FROM articles WHERE fakeid = $1 AND is_deleted = false
+        "#,
+    )
+    .bind(&query.fakeid)
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    let per_month = sqlx::query_as::<_, MonthCount>(
+        r#"
+        SELECT to_char(to_timestamp(create_time) AT TIME ZONE 'Asia/Shanghai', 'YYYY-MM') AS month,
+               COUNT(*) AS articles, COUNT(*) FILTER (WHERE itemidx = 1) AS messages
+        FROM articles WHERE fakeid = $1 AND is_deleted = false
+        GROUP BY month ORDER BY month
+        "#,
+    )
+    .bind(&query.fakeid)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let hours: Vec<(i32, i64)> = sqlx::query_as(
+        r#"
+        SELECT EXTRACT(HOUR FROM to_timestamp(create_time) AT TIME ZONE 'Asia/Shanghai')::int AS hour,
+               COUNT(*)
+        FROM articles WHERE fakeid = $1 AND is_deleted = false AND itemidx = 1
+        GROUP BY hour
+        "#,
+    )
+    .bind(&query.fakeid)
+    .fetch_all(&state.db_pool)
+    .await?;
+    let mut posting_hours = [0i64; 24];
+    for (hour, count) in hours {
+        if let Some(slot) = posting_hours.get_mut(hour as usize) {
+            *slot = count;
+        }
+    }
+
+    // Album tags as listed by appmsgpublish, kept in `raw_json` by sync
+    let top_albums = sqlx::query_as::<_, AlbumCount>(
+        r#"
+        SELECT album->>'id' AS id, MAX(album->>'title') AS title, COUNT(*) AS articles
+        FROM articles, jsonb_array_elements(
+            CASE WHEN jsonb_typeof(raw_json->'appmsg_album_infos') = 'array'
+                 THEN raw_json->'appmsg_album_infos' ELSE '[]'::jsonb END
+        ) AS album
+        WHERE fakeid = $1 AND is_deleted = false AND album->>'id' IS NOT NULL
+        GROUP BY album->>'id'
+        ORDER BY articles DESC, title
+        LIMIT 10
+        "#,
+    )
+    .bind(&query.fakeid)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let date = |ts: Option<i64>| {
+        ts.and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+            .map(|dt| {
+                dt.with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap())
+                    .format("%Y-%m-%d")
+                    .to_string()
+            })
+    };
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "fakeid": query.fakeid,
+            "nickname": nickname,
+            "articles": articles,
+            "messages": messages,
+            "first_seen": first_seen,
+            "last_seen": last_seen,
+            "first_date": date(first_seen),
+            "last_date": date(last_seen),
+            "avg_title_length": avg_title_length.map(|l| (l * 10.0).round() / 10.0),
+            "per_month": per_month,
+            "posting_hours": posting_hours,
+            "top_albums": top_albums
+        }
+    })))
+}
+
 // ============ Article List ============
 
 #[derive(Debug, Deserialize)]
@@ -299,47 +457,37 @@ pub async fn get_articles(
 
     let cookie = crate::proxy::get_cookie_from_store(&headers, &state.cookie_store).await;
 
-    let response = proxy_mp_request(ProxyRequestOptions {
-        method: reqwest::Method::GET,
-        endpoint: "https://mp.weixin.qq.com/cgi-bin/appmsgpublish".to_string(),
-        query: Some(params),
-        body: None,
-        cookie,
-    })
+    let lane = crawl::session_lane(get_auth_key_from_headers(&headers).as_deref());
+    if let Err(retry_after) =
+        ratelimit::acquire(&lane, Endpoint::AppMsgPublish, ratelimit::INTERACTIVE_MAX_WAIT).await
+    {
+        return Ok(Json(ratelimit::over_budget_response(retry_after)));
+    }
+    let _permit = crawl::acquire(&lane, Priority::Interactive, "public.get_articles").await;
+    let json = proxy_mp_request_logged(
+        ProxyRequestOptions {
+            method: reqwest::Method::GET,
+            endpoint: "https://mp.weixin.qq.com/cgi-bin/appmsgpublish".to_string(),
+            query: Some(params),
+            body: None,
+            cookie,
+        },
+        "public.get_articles",
+        &lane,
+    )
     .await?;
-
-    let json: serde_json::Value = response.json().await?;
+    ratelimit::report(&lane, Endpoint::AppMsgPublish, ratelimit::base_ret(&json));
 
     // Parse and flatten articles
-    if let Some(0) = json
-        .get("base_resp")
-        .and_then(|r| r.get("ret"))
-        .and_then(|r| r.as_i64())
-    {
-        if let Some(publish_page_str) = json.get("publish_page").and_then(|p| p.as_str()) {
-            if let Ok(publish_page) = serde_json::from_str::<serde_json::Value>(publish_page_str) {
-                if let Some(publish_list) =
-                    publish_page.get("publish_list").and_then(|l| l.as_array())
-                {
-                    let articles: Vec<serde_json::Value> = publish_list
-                        .iter()
-                        .filter_map(|item| {
-                            item.get("publish_info")
-                                .and_then(|p| p.as_str())
-                                .and_then(|s| serde_json::from_str::<serde_json::Value>(s).ok())
-                                .and_then(|info| info.get("appmsgex").cloned())
-                        })
-                        .filter_map(|v| v.as_array().cloned())
-                        .flatten()
-                        .collect();
-
-                    return Ok(Json(serde_json::json!({
-                        "base_resp": json.get("base_resp"),
-                        "articles": articles
-                    })));
-                }
-            }
-        }
+    if ratelimit::base_ret(&json) == Some(0) && json.get("publish_page").is_some() {
+        let articles: Vec<serde_json::Value> = parse_publish_page(&json)
+            .articles()
+            .map(|a| a.to_json())
+            .collect();
+        return Ok(Json(serde_json::json!({
+            "base_resp": json.get("base_resp"),
+            "articles": articles
+        })));
     }
 
     Ok(Json(json))
@@ -356,6 +504,7 @@ pub struct GetDbArticlesQuery {
 }
 
 /// Get article list from database
+#[allow(clippy::type_complexity)]
 pub async fn get_db_articles(
     State(state): State<AppState>,
     Query(query): Query<GetDbArticlesQuery>,
@@ -477,6 +626,120 @@ pub async fn get_db_articles(
     })))
 }
 
+// ============ Search Suggestions ============
+
+#[derive(Debug, Deserialize)]
+pub struct SuggestQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+/// Escape LIKE wildcards so user input is matched literally
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Type-ahead suggestions: account nicknames, past task prompts, generated keywords,
+/// and frequent title terms. Prefix matches rank first, then trigram similarity.
+pub async fn suggest(
+    State(state): State<AppState>,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let q = query.q.trim();
+    if q.is_empty() {
+        return Err(AppError::BadRequest("q不能为空".to_string()));
+    }
+    let limit = query.limit.unwrap_or(5).clamp(1, 20);
+    let pattern = escape_like(q);
+
+    let has_trgm: bool =
+        sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM pg_extension WHERE extname = 'pg_trgm')")
+            .fetch_one(&state.db_pool)
+            .await?;
+
+    // Ranking expression: prefix match > substring match > trigram similarity
+    let (match_clause, rank_clause) = if has_trgm {
+        (
+            "(v ILIKE '%' || $1 || '%' OR similarity(v, $2) > 0.2)",
+            "(v ILIKE $1 || '%') DESC, similarity(v, $2) DESC",
+        )
+    } else {
+        (
+            "strpos(lower(v), lower($2)) > 0",
+            "(v ILIKE $1 || '%') DESC, length(v) ASC",
+        )
+    };
+
+    let accounts: Vec<(String,)> = sqlx::query_as(&format!(
+        "SELECT v FROM (SELECT DISTINCT nickname AS v FROM accounts WHERE nickname IS NOT NULL) s \
+         WHERE {} ORDER BY {} LIMIT $3",
+        match_clause, rank_clause
+    ))
+    .bind(&pattern)
+    .bind(q)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let prompts: Vec<(String,)> = sqlx::query_as(&format!(
+        "SELECT v FROM (SELECT DISTINCT prompt AS v FROM insight_tasks) s \
+         WHERE {} ORDER BY {} LIMIT $3",
+        match_clause, rank_clause
+    ))
+    .bind(&pattern)
+    .bind(q)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let keywords: Vec<(String,)> = sqlx::query_as(&format!(
+        "SELECT v FROM (SELECT DISTINCT unnest(keywords) AS v FROM insight_tasks) s \
+         WHERE {} ORDER BY {} LIMIT $3",
+        match_clause, rank_clause
+    ))
+    .bind(&pattern)
+    .bind(q)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    // Title terms: split titles on whitespace/punctuation and count segments starting with q
+    let terms: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT term, COUNT(*) AS freq
+        FROM (
+            SELECT regexp_split_to_table(title, '[[:space:][:punct:]，。！？、：；“”‘’《》（）【】|]+') AS term
+            FROM articles
+            WHERE is_deleted = false AND title ILIKE '%' || $1 || '%'
+        ) t
+        WHERE term ILIKE $1 || '%' AND char_length(term) BETWEEN 2 AND 30
+        GROUP BY term
+        ORDER BY freq DESC, char_length(term) ASC
+        LIMIT $2
+        "#,
+    )
+    .bind(&pattern)
+    .bind(limit)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "accounts": accounts.into_iter().map(|(v,)| v).collect::<Vec<_>>(),
+            "prompts": prompts.into_iter().map(|(v,)| v).collect::<Vec<_>>(),
+            "keywords": keywords.into_iter().map(|(v,)| v).collect::<Vec<_>>(),
+            "terms": terms
+                .into_iter()
+                .map(|(term, count)| serde_json::json!({ "term": term, "count": count }))
+                .collect::<Vec<_>>(),
+        }
+    })))
+}
+
 // ============ Download Article ============
 
 #[derive(Debug, Deserialize)]
@@ -509,6 +772,7 @@ pub async fn download_article(
         return Err(AppError::BadRequest("不支持的format".to_string()));
     }
 
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "public.download").await;
     let client = reqwest::Client::new();
     let raw_html = client
         .get(&url)
@@ -554,6 +818,8 @@ pub async fn download_article(
 pub struct GetHtmlQuery {
     pub id: Option<String>, // fakeid:aid
     pub url: Option<String>,
+    /// Serve the page sanitized for viewing instead of raw (exports parse the raw page)
+    pub sanitize: Option<bool>,
 }
 
 /// Get article HTML from database, fallback to fetching from WeChat
@@ -563,26 +829,27 @@ pub async fn get_article_html(
 ) -> Result<axum::response::Response<String>, AppError> {
     use axum::http::header;
 
-    // Try to get from database first
-    let row: Option<(String,)> = if let Some(id) = &query.id {
-        sqlx::query_as("SELECT content FROM article_content WHERE id = $1")
-            .bind(id)
-            .fetch_optional(&state.db_pool)
-            .await?
-    } else if let Some(url) = &query.url {
-        sqlx::query_as("SELECT content FROM article_content WHERE original_url = $1")
-            .bind(url)
-            .fetch_optional(&state.db_pool)
-            .await?
-    } else {
+    if query.id.is_none() && query.url.is_none() {
         return Err(AppError::BadRequest("id或url不能为空".to_string()));
+    }
+    let render = |html: String| {
+        if query.sanitize.unwrap_or(false) {
+            viewer_html(&html)
+        } else {
+            html
+        }
     };
 
-    if let Some((content,)) = row {
+    // Try to get from database first
+    let stored =
+        repository::articles::content(&state.db_pool, query.id.as_deref(), query.url.as_deref())
+            .await?;
+
+    if let Some(content) = stored {
         let response = axum::response::Response::builder()
             .status(200)
             .header(header::CONTENT_TYPE, "text/html; charset=UTF-8")
-            .body(content)
+            .body(render(content))
             .unwrap();
         return Ok(response);
     }
@@ -595,6 +862,8 @@ pub async fn get_article_html(
             .unwrap_or_else(|_| url.clone());
 
         if decoded_url.contains("mp.weixin.qq.com") {
+            let _permit =
+                crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "public.html").await;
             let client = reqwest::Client::new();
             let raw_html = client
                 .get(&decoded_url)
@@ -612,7 +881,7 @@ pub async fn get_article_html(
             let response = axum::response::Response::builder()
                 .status(200)
                 .header(header::CONTENT_TYPE, "text/html; charset=UTF-8")
-                .body(raw_html)
+                .body(render(raw_html))
                 .unwrap();
             return Ok(response);
         }
@@ -621,12 +890,89 @@ pub async fn get_article_html(
     Err(AppError::NotFound("Article content not found".to_string()))
 }
 
+// ============ Article Versions ============
+
+#[derive(Debug, Deserialize)]
+pub struct VersionsQuery {
+    pub id: Option<String>, // fakeid:aid
+    pub url: Option<String>,
+    /// Diff these two versions instead of listing; `to` defaults to the
+    /// latest and `from` to the version before `to`
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// List the captured versions of an article, or diff two of them
+pub async fn article_versions(
+    State(state): State<AppState>,
+    Query(query): Query<VersionsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let pool = &state.db_pool;
+    let article_id = match (&query.id, &query.url) {
+        (Some(id), _) => id.clone(),
+        (None, Some(url)) => {
+            match repository::articles::find(pool, None, Some(url), Freshness::Any).await? {
+                Some(article) => article.id,
+                None => repository::articles::resolve_id(pool, url).await?,
+            }
+        }
+        (None, None) => return Err(AppError::BadRequest("id或url不能为空".to_string())),
+    };
+
+    let versions = repository::articles::versions(pool, &article_id).await?;
+    if query.from.is_none() && query.to.is_none() {
+        return Ok(Json(serde_json::json!({
+            "success": true,
+            "data": { "article_id": article_id, "versions": versions }
+        })));
+    }
+
+    let to_index = match query.to {
+        Some(to) => versions.iter().position(|v| v.id == to),
+        None => versions.len().checked_sub(1),
+    }
+    .ok_or_else(|| AppError::NotFound("Version not found".to_string()))?;
+    let from_index = match query.from {
+        Some(from) => versions.iter().position(|v| v.id == from),
+        None => to_index.checked_sub(1),
+    }
+    .ok_or_else(|| AppError::NotFound("Version not found".to_string()))?;
+
+    let (from, to) = (&versions[from_index], &versions[to_index]);
+    let (Some(old), Some(new)) = (
+        repository::articles::version_content(pool, &article_id, from.id).await?,
+        repository::articles::version_content(pool, &article_id, to.id).await?,
+    ) else {
+        return Err(AppError::NotFound("Version not found".to_string()));
+    };
+    let (similarity, diff) = crate::archive::diff_texts(
+        &crate::archive::article_text(&old),
+        &crate::archive::article_text(&new),
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": {
+            "article_id": article_id,
+            "from": from,
+            "to": to,
+            "similarity": similarity,
+            "diff": diff
+        }
+    })))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct FetchRequest {
     pub url: String,
     pub id: Option<String>, // Added optional ID
-    pub proxies: Option<Vec<String>>,
+    /// Gateways, or HTTP / SOCKS5 proxies (see `gateway::ProxySpec`)
+    pub proxies: Option<Vec<ProxySpec>>,
     pub authorization: Option<String>,
+    /// Skip (and drop) any cached copy and re-fetch from WeChat
+    pub force: Option<bool>,
+    /// TTL in seconds for the freshly stored copy
+    pub ttl_seconds: Option<i64>,
 }
 
 /// Fetch article content using optional proxies and save to DB
@@ -639,62 +985,29 @@ pub async fn fetch_article(
     if req.url.is_empty() {
         return Err(AppError::BadRequest("url不能为空".to_string()));
     }
-
-    tracing::info!("fetch_article: id={:?}, url={}", req.id, req.url);
-
-    // 1. Check DB first (Priority: ID -> Raw URL -> Decoded URL)
-    let mut row: Option<(String,)> = None;
-
-    // Check by ID if provided
-    if let Some(id) = &req.id {
-        row = sqlx::query_as("SELECT content FROM article_content WHERE id = $1")
-            .bind(id)
-            .fetch_optional(&state.db_pool)
-            .await?;
-    }
-
-    // If not found by ID, try Raw URL
-    if row.is_none() {
-        row = sqlx::query_as("SELECT content FROM article_content WHERE original_url = $1")
-            .bind(&req.url)
-            .fetch_optional(&state.db_pool)
-            .await?;
-    }
-
-    // If still not found, try Decoded URL
-    if row.is_none() {
-        let decoded_url = urlencoding::decode(&req.url)
-            .map(|s| s.to_string())
-            .unwrap_or_else(|_| req.url.clone());
-
-        if decoded_url != req.url {
-            row = sqlx::query_as("SELECT content FROM article_content WHERE original_url = $1")
-                .bind(&decoded_url)
-                .fetch_optional(&state.db_pool)
-                .await?;
-        }
+    let expires_at = crate::api::cache::expires_at(req.ttl_seconds)?;
+
+    tracing::info!(
+        "fetch_article: id={:?}, url={}, force={:?}",
+        req.id,
+        req.url,
+        req.force
+    );
+
+    let force = req.force.unwrap_or(false);
+    if force {
+        let removed =
+            repository::articles::remove(&state.db_pool, req.id.as_deref(), Some(&req.url)).await?;
+        tracing::info!("fetch_article: force re-fetch, dropped {} cached entries", removed);
     }
 
-    // Fallback: Check 'cached_articles' (Legacy Insight Cache)
-    if row.is_none() {
-        let url_hash = format!("{:x}", md5::compute(req.url.as_bytes()));
-        let cached: Option<(String,)> =
-            sqlx::query_as("SELECT content FROM cached_articles WHERE url_hash = $1")
-                .bind(&url_hash)
-                .fetch_optional(&state.db_pool)
-                .await?;
+    // 1. Check DB first (id, then raw or decoded URL)
+    let stored =
+        repository::articles::content(&state.db_pool, req.id.as_deref(), Some(&req.url)).await?;
 
-        if let Some(c) = cached {
-            tracing::info!("fetch_article: Hit legacy cache for url={}", req.url);
-            // Optional: Migrate to article_content?
-            // For now just return it.
-            row = Some(c);
-        }
-    }
-
-    if let Some((content,)) = row {
-        // Apply processing to cached content (it is raw)
-        let processed_content = process_wechat_html(&content);
+    if let Some(content) = stored {
+        // Stored content is raw, sanitize it for viewing
+        let processed_content = viewer_html(&content);
         let response = axum::response::Response::builder()
             .status(200)
             .header(header::CONTENT_TYPE, "text/html; charset=UTF-8")
@@ -731,7 +1044,13 @@ pub async fn fetch_article(
     }
 
     // Helper for direct fetch
-    async fn fetch_direct(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    async fn fetch_direct(
+        client: &reqwest::Client,
+        downloads: &DownloadScheduler,
+        url: &str,
+    ) -> Result<String, String> {
+        let _permit = crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "public.fetch").await;
+        let _slot = downloads.acquire(None, url).await;
         let resp = client
             .get(url)
             .header("Referer", "https://mp.weixin.qq.com/")
@@ -749,13 +1068,43 @@ pub async fn fetch_article(
             return Err(format!("Status code: {}", resp.status()));
         }
 
-        let html = resp.text().await.map_err(|e| e.to_string())?;
-        Ok(process_wechat_html(&html))
+        resp.text().await.map_err(|e| e.to_string())
+    }
+
+    // Helper for HTTP / SOCKS5 proxy fetch
+    async fn fetch_via_proxy(
+        client: &reqwest::Client,
+        downloads: &DownloadScheduler,
+        proxy: &ProxySpec,
+        url: &str,
+    ) -> Result<String, String> {
+        let route = Pick {
+            url: proxy.url().to_string(),
+            authorization: None,
+            kind: proxy.kind(),
+        };
+        let lane = crawl::gateway_lane(&route.url);
+        let _permit = crawl::acquire(&lane, Priority::Interactive, "public.fetch").await;
+        let _slot = downloads.acquire(Some(&route.url), url).await;
+        let resp = crate::gateway::get(client, Some(&route), url)
+            .map_err(|e| e.to_string())?
+            .header("Referer", "https://mp.weixin.qq.com/")
+            .header("Origin", "https://mp.weixin.qq.com")
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !resp.status().is_success() {
+            return Err(format!("Proxy Status code: {}", resp.status()));
+        }
+
+        resp.text().await.map_err(|e| e.to_string())
     }
 
     // Helper for web proxy fetch
     async fn fetch_via_web_proxy(
         client: &reqwest::Client,
+        downloads: &DownloadScheduler,
         proxy_base: &str,
         target_url: &str,
         auth: Option<&str>,
@@ -778,6 +1127,9 @@ pub async fn fetch_article(
             }
         }
 
+        let lane = crawl::gateway_lane(proxy_base);
+        let _permit = crawl::acquire(&lane, Priority::Interactive, "public.fetch").await;
+        let _slot = downloads.acquire(Some(proxy_base), target_url).await;
         let resp = client
             .get(&proxy_request_url)
             .timeout(std::time::Duration::from_secs(30))
@@ -789,8 +1141,7 @@ pub async fn fetch_article(
             return Err(format!("Proxy Status code: {}", resp.status()));
         }
 
-        let html = resp.text().await.map_err(|e| e.to_string())?;
-        Ok(process_wechat_html(&html))
+        resp.text().await.map_err(|e| e.to_string())
     }
 
     let client = reqwest::Client::builder()
@@ -798,11 +1149,20 @@ pub async fn fetch_article(
         .build()
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    for proxy_url_opt in attempts {
-        let result = if let Some(p_url) = proxy_url_opt {
-            fetch_via_web_proxy(&client, &p_url, &url, auth.as_deref()).await
-        } else {
-            fetch_direct(&client, &url).await
+    for proxy_opt in attempts {
+        let result = match proxy_opt {
+            Some(proxy) if proxy.kind() == ProxyKind::Gateway => {
+                fetch_via_web_proxy(
+                    &client,
+                    &state.downloads,
+                    proxy.url(),
+                    &url,
+                    auth.as_deref(),
+                )
+                .await
+            }
+            Some(proxy) => fetch_via_proxy(&client, &state.downloads, &proxy, &url).await,
+            None => fetch_direct(&client, &state.downloads, &url).await,
         };
 
         match result {
@@ -819,97 +1179,23 @@ pub async fn fetch_article(
 
     match fetched_content {
         Some(content) => {
-            // 3. Save to DB
-            // We need an ID. If article exists in `articles` table, reuse ID.
-            // But we might fetch article not in `articles` table yet (search result logic?)
-            // Wait, search result items come from `embeddings` which come from `articles`.
-            // So article MUST exist in `articles` table.
-            // Find ID from articles table by link? Or we don't know the exact link match?
-            // Actually, the `req.url` matches what we have in `articles.link`?
-            // If we just want to save content, we can generate a hash ID or try to match.
-
-            // Try to find article ID by link
-            let article_id: Option<(String,)> =
-                sqlx::query_as("SELECT id FROM articles WHERE link = $1")
-                    .bind(&req.url)
-                    .fetch_optional(&state.db_pool)
-                    .await
-                    .unwrap_or(None);
-
-            let id = if let Some((aid,)) = article_id {
-                aid
-            } else {
-                // Fallback: use md5 of url
-                format!("{:x}", md5::compute(&req.url))
-            };
-
-            let _ = sqlx::query(
-                r#"
-                 INSERT INTO article_content (id, content, original_url)
-                 VALUES ($1, $2, $3)
-                 ON CONFLICT (id) DO UPDATE SET
-                     content = EXCLUDED.content,
-                     create_time = extract(epoch from now())::bigint
-                 "#,
+            // 3. Save the raw page, serve it processed
+            if let Err(e) = repository::articles::save(
+                &state.db_pool,
+                req.id.as_deref(),
+                &req.url,
+                &content,
+                expires_at,
             )
-            .bind(&id)
-            .bind(&content) // Content is already processed here! (process_wechat_html called in helpers)
-            // Wait, do we want to store PROCESSED content or RAW content?
-            // If we store processed, then next time we fetch it, we process it AGAIN?
-            // process_wechat_html seems idempotent mostly (replace hidden with visible), but adding style tag again?
-            // It adds style tag if </head> exists.
-            // If we store PROCESSED content, then "View" works directly.
-            // Insight prefetch stores RAW content.
-            // So we have a mix.
-            // If DB allows raw, we must process on read.
-            // If DB allows processed, we can just read.
-            // Since prefetch stores RAW (via fetch_html_content), we MUST process on read.
-            // So for fetch_article saving... we should probably save RAW content if possible?
-            // But `fetch_direct` returns processed content.
-            // Let's modify fetch_direct/fetch_via_web_proxy to return RAW, then process?
-            // Or just save Processed content.
-            // If we save Processed content, `prefetch_task` saves RAW.
-            // So we have inconsistent data.
-            // Better to process on read ALWAYS.
-            // But `fetch_direct` returns processed.
-            // I should revert `fetch_direct` to return raw, or save raw.
-            // The current implementation of fetch_direct returns `process_wechat_html(&html)`.
-            // So `fetched_content` IS PROCESSED.
-            // The `prefetch_task` returns/saves RAW.
-            // If I just process on read, then if it's already processed, it might double process.
-            // Does it hurt?
-            // process_wechat_html:
-            // 1. remove scripts (ok to run again)
-            // 2. hidden -> visible (ok)
-            // 3. data-src -> src (ok)
-            // 4. Inject style (might inject twice?)
-            // It checks `processed.find("</head>")` and inserts.
-            // If style already there?
-            // It doesn't check if style is already there.
-            // So it WILL duplicate style block.
-            // Not a huge deal, but messy.
-            // DECISION:
-            // Since `prefetch_task` saves RAW, and that's the bulk of data.
-            // We should treat DB as storing RAW-dish data (or at least, Reader is responsible for presentation).
-            // So `fetch_article` READ path should process.
-            // And `fetch_article` WRITE path?
-            // If `fetch_direct` returns processed, we save processed.
-            // This is inconsistency.
-            // Ideally `prefetch_task` should also process before save?
-            // Or `fetch_article` should save raw.
-            // Let's stick to "Process on Read".
-            // So I should modify `fetch_direct` to NOT process?
-            // But currently it does.
-            // I will keep it as is for now to avoid breaking too much.
-            // The duplicate style block is acceptable for solving the "Blank Page" (Hidden) issue now.
-            .bind(&req.url)
-            .execute(&state.db_pool)
-            .await;
+            .await
+            {
+                tracing::warn!("fetch_article: failed to store {}: {}", req.url, e);
+            }
 
             let response = axum::response::Response::builder()
                 .status(200)
                 .header(header::CONTENT_TYPE, "text/html; charset=UTF-8")
-                .body(content)
+                .body(viewer_html(&content))
                 .unwrap();
             Ok(response)
         }
@@ -920,82 +1206,11 @@ pub async fn fetch_article(
     }
 }
 
-// Helper to process WeChat HTML for static viewing
-fn process_wechat_html(html: &str) -> String {
-    let mut processed = html.to_string();
-
-    // 0. Remove scripts (prevents JS from hiding content or messing with layout)
-    if let Ok(re) = regex::Regex::new(r"(?s)<script[^>]*>.*?</script>") {
-        processed = re.replace_all(&processed, "").to_string();
-    }
-
-    // 1. Force visibility: hidden -> visible on #js_content
-    processed = processed.replace("visibility: hidden;", "visibility: visible;");
-    processed = processed.replace("visibility:hidden;", "visibility:visible;");
-
-    // 2. Fix lazy loading images: data-src -> src
-    // Also add referrerpolicy="no-referrer" to bypass WeChat anti-hotlinking
-    processed = processed.replace(" data-src=\"", " referrerpolicy=\"no-referrer\" src=\"");
-
-    // 3. Ensure images have max-width and content is visible
-    let style = r#"<style>
-        #js_content { 
-            visibility: visible !important; 
-            opacity: 1 !important; 
-            display: block !important; 
-        }
-        #img-content { 
-            display: block !important; 
-        }
-        img { 
-            max-width: 100% !important; 
-            height: auto !important; 
-            display: block !important;
-            margin: 0 auto;
-        }
-        body {
-            background-color: transparent !important;
-        }
-        /* Dark Mode Adaptation */
-        :is(.dark) #js_content,
-        :is(.dark) #activity-name,
-        :is(.dark) .rich_media_title,
-        :is(.dark) .rich_media_meta_list,
-        :is(.dark) .rich_media_meta_text {
-            color: #d1d5db !important; /* gray-300 */
-            background-color: transparent !important;
-        }
-        
-        :is(.dark) #js_content *,
-        :is(.dark) #activity-name *,
-        :is(.dark) .rich_media_title *,
-        :is(.dark) .rich_media_meta_list * {
-            color: inherit !important; /* Force inheritance to override inline styles */
-            background-color: transparent !important;
-            border-color: #374151 !important;
-        }
-
-        :is(.dark) #js_content p, 
-        :is(.dark) #js_content span, 
-        :is(.dark) #js_content strong, 
-        :is(.dark) #js_content h1, 
-        :is(.dark) #js_content h2, 
-        :is(.dark) #js_content h3, 
-        :is(.dark) #js_content h4, 
-        :is(.dark) #js_content h5, 
-        :is(.dark) #js_content h6,
-        :is(.dark) #js_content li {
-             color: inherit !important;
-        }
-    </style>"#;
-
-    if let Some(pos) = processed.find("</head>") {
-        processed.insert_str(pos, style);
-    } else {
-        processed.push_str(style);
-    }
-
-    processed
+/// Page sanitized for the viewer, WeChat images served through `get_asset`
+/// (linked under `PUBLIC_BASE_URL` when the viewer is on another origin)
+fn viewer_html(html: &str) -> String {
+    let base = std::env::var("PUBLIC_BASE_URL").unwrap_or_default();
+    sanitize::sanitize_with(html, ImageMode::Proxied(&base))
 }
 
 // ============ Auth Key ============
@@ -1076,10 +1291,252 @@ pub struct GetAssetQuery {
     pub url: String,
 }
 
-/// Get asset content from database
+/// Byte range requested by a `Range` header, inclusive
+#[derive(Debug, PartialEq)]
+enum RangeRequest {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parse a single-range `bytes=` header against the asset size. Multiple
+/// ranges and other units are served in full.
+fn parse_range(header: Option<&str>, size: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let range = match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        // bytes=-500: the last 500 bytes
+        (None, Some(suffix)) if start.is_empty() && suffix > 0 => {
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        (Some(start), None) if end.is_empty() => (start, size.saturating_sub(1)),
+        (Some(start), Some(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        _ => return RangeRequest::Full,
+    };
+    if size == 0 || range.0 >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range.0, range.1)
+}
+
+fn http_date(ts: i64) -> String {
+    chrono::DateTime::from_timestamp(ts, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Whether the client's cached copy is current (`If-None-Match`, else `If-Modified-Since`)
+fn not_modified(headers: &HeaderMap, etag: &str, modified: i64) -> bool {
+    if let Some(inm) = headers
+        .get(axum::http::header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        return inm
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == etag || t == "*");
+    }
+    headers
+        .get(axum::http::header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok())
+        .is_some_and(|since| modified > 0 && modified <= since.timestamp())
+}
+
+/// Stream an asset, with ETag/Last-Modified revalidation and byte ranges
 pub async fn get_asset(
     State(state): State<AppState>,
     Query(query): Query<GetAssetQuery>,
+    headers: HeaderMap,
+) -> Result<axum::response::Response, AppError> {
+    use axum::http::{header, StatusCode};
+
+    if query.url.is_empty() {
+        return Err(AppError::BadRequest("url不能为空".to_string()));
+    }
+
+    let meta = match crate::storage::meta(&state.db_pool, &query.url).await? {
+        Some(meta) => Some(meta),
+        // Viewer pages link WeChat images here before they are cached
+        None if sanitize::is_wechat_image(&query.url) => {
+            cache_wechat_image(&state, &query.url).await?;
+            crate::storage::meta(&state.db_pool, &query.url).await?
+        }
+        None => None,
+    }
+    .ok_or(AppError::NotFound("Asset not found".to_string()))?;
+    let size = meta.size.max(0) as u64;
+    // Aliases share the holder's bytes and so its hash; unhashed legacy
+    // assets fall back to time and size
+    let etag = match meta.sha256.as_deref() {
+        Some(hash) => format!("\"{}\"", hash),
+        None => format!("\"{:x}-{:x}\"", meta.create_time, size),
+    };
+    let last_modified = http_date(meta.create_time);
+    let content_type = meta
+        .mime_type
+        .clone()
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let builder = axum::response::Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, &last_modified)
+        // Cache control for static assets
+        .header(header::CACHE_CONTROL, "public, max-age=31536000")
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    if not_modified(&headers, &etag, meta.create_time) {
+        return Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(axum::body::Body::empty())
+            .unwrap());
+    }
+
+    // A stale If-Range validator means the client wants the whole new asset
+    let range_header = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| {
+            headers
+                .get(header::IF_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .is_none_or(|v| v == etag || v == last_modified)
+        });
+    let range = match parse_range(range_header, size) {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(start, end) => Some((start, end)),
+        RangeRequest::Unsatisfiable => {
+            return Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(axum::body::Body::empty())
+                .unwrap());
+        }
+    };
+
+    let stream = crate::storage::open(&state.db_pool, &meta, range)
+        .await?
+        .ok_or(AppError::NotFound("Asset not found".to_string()))?;
+
+    let builder = builder.header(header::CONTENT_TYPE, content_type);
+    let builder = match range {
+        Some((start, end)) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, size),
+            )
+            .header(header::CONTENT_LENGTH, end - start + 1),
+        None if size > 0 => builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, size),
+        None => builder.status(StatusCode::OK),
+    };
+    Ok(builder.body(axum::body::Body::from_stream(stream)).unwrap())
+}
+
+/// Fetch a WeChat image with the Referer its CDN expects and store it as an asset
+async fn cache_wechat_image(state: &AppState, url: &str) -> Result<(), AppError> {
+    let _slot = state.downloads.acquire(None, url).await;
+    let resp = reqwest::Client::new()
+        .get(url)
+        .header("Referer", "https://mp.weixin.qq.com/")
+        .header(
+            "User-Agent",
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36",
+        )
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Failed to fetch image: {}", e)))?;
+    if !resp.status().is_success() {
+        return Err(AppError::BadGateway(format!(
+            "Failed to fetch image: {}",
+            resp.status()
+        )));
+    }
+    let data = resp
+        .bytes()
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Failed to fetch image: {}", e)))?;
+
+    // The CDN answers some errors with a placeholder page rather than a status
+    let mime_type = image::guess_format(&data)
+        .map_err(|_| AppError::BadGateway("Fetched asset is not an image".to_string()))?
+        .to_mime_type();
+    crate::storage::save(&state.db_pool, url, &data, mime_type).await?;
+    Ok(())
+}
+
+// ============ Asset Thumbnail ============
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbQuery {
+    pub url: String,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    /// "webp", "jpeg" (default) or "png"
+    pub format: Option<String>,
+}
+
+/// Thumbnail edges we generate; other sizes are rounded up to one of these
+/// so a client cannot fill `asset_variants` with one entry per pixel
+const THUMB_EDGES: &[u32] = &[64, 128, 256, 512, 768, 1024, 1536, 2048];
+
+/// Allowed edge for a requested one (0 = unconstrained)
+fn thumb_edge(requested: u32) -> u32 {
+    if requested == 0 {
+        return 0;
+    }
+    THUMB_EDGES
+        .iter()
+        .copied()
+        .find(|&edge| edge >= requested)
+        .unwrap_or(THUMB_EDGES[THUMB_EDGES.len() - 1])
+}
+
+/// Resize an image to fit within `w` x `h` (0 = unconstrained) and encode it.
+/// Never upscales.
+fn make_thumbnail(data: &[u8], w: u32, h: u32, format: &str) -> Result<Vec<u8>, String> {
+    let img = image::load_from_memory(data).map_err(|e| e.to_string())?;
+
+    let max_w = if w == 0 { u32::MAX } else { w };
+    let max_h = if h == 0 { u32::MAX } else { h };
+    let img = if img.width() > max_w || img.height() > max_h {
+        img.resize(
+            max_w.min(img.width()),
+            max_h.min(img.height()),
+            image::imageops::FilterType::Lanczos3,
+        )
+    } else {
+        img
+    };
+
+    let mut out = Vec::new();
+    let mut cursor = std::io::Cursor::new(&mut out);
+    let result = match format {
+        "webp" => image::DynamicImage::ImageRgba8(img.to_rgba8())
+            .write_to(&mut cursor, image::ImageOutputFormat::WebP),
+        "png" => img.write_to(&mut cursor, image::ImageOutputFormat::Png),
+        _ => image::DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_to(&mut cursor, image::ImageOutputFormat::Jpeg(80)),
+    };
+    result.map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+/// Resized / transcoded variant of a stored asset, cached in `asset_variants`
+pub async fn get_asset_thumb(
+    State(state): State<AppState>,
+    Query(query): Query<ThumbQuery>,
 ) -> Result<impl axum::response::IntoResponse, AppError> {
     use axum::http::header;
 
@@ -1087,26 +1544,71 @@ pub async fn get_asset(
         return Err(AppError::BadRequest("url不能为空".to_string()));
     }
 
-    let row: Option<(Vec<u8>, Option<String>)> =
-        sqlx::query_as("SELECT data, mime_type FROM assets WHERE url = $1")
-            .bind(&query.url)
-            .fetch_optional(&state.db_pool)
-            .await?;
+    let w = thumb_edge(query.w.unwrap_or(0));
+    let h = thumb_edge(query.h.unwrap_or(0));
+    if w == 0 && h == 0 {
+        return Err(AppError::BadRequest("w或h不能为空".to_string()));
+    }
 
-    if let Some((data, mime_type)) = row {
-        let content_type = mime_type.unwrap_or_else(|| "application/octet-stream".to_string());
+    let format = query.format.as_deref().unwrap_or("jpeg").to_lowercase();
+    let (format, mime_type) = match format.as_str() {
+        "webp" => ("webp", "image/webp"),
+        "png" => ("png", "image/png"),
+        "jpeg" | "jpg" => ("jpeg", "image/jpeg"),
+        _ => return Err(AppError::BadRequest("不支持的format".to_string())),
+    };
 
-        let response = axum::response::Response::builder()
+    let respond = |data: Vec<u8>| {
+        axum::response::Response::builder()
             .status(200)
-            .header(header::CONTENT_TYPE, content_type)
-            // Cache control for static assets
+            .header(header::CONTENT_TYPE, mime_type)
             .header(header::CACHE_CONTROL, "public, max-age=31536000")
             .body(axum::body::Body::from(data))
-            .unwrap();
-        Ok(response)
-    } else {
-        Err(AppError::NotFound("Asset not found".to_string()))
+            .unwrap()
+    };
+
+    let cached: Option<(Vec<u8>,)> = sqlx::query_as(
+        "SELECT data FROM asset_variants WHERE url = $1 AND width = $2 AND height = $3 AND format = $4",
+    )
+    .bind(&query.url)
+    .bind(w as i32)
+    .bind(h as i32)
+    .bind(format)
+    .fetch_optional(&state.db_pool)
+    .await?;
+
+    if let Some((data,)) = cached {
+        return Ok(respond(data));
     }
+
+    let (source, _) = crate::storage::load(&state.db_pool, &query.url)
+        .await?
+        .ok_or(AppError::NotFound("Asset not found".to_string()))?;
+
+    let thumb = tokio::task::spawn_blocking(move || make_thumbnail(&source, w, h, format))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| AppError::BadRequest(format!("无法处理图片: {}", e)))?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO asset_variants (url, width, height, format, data, mime_type, size, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (url, width, height, format) DO NOTHING
+        "#,
+    )
+    .bind(&query.url)
+    .bind(w as i32)
+    .bind(h as i32)
+    .bind(format)
+    .bind(&thumb)
+    .bind(mime_type)
+    .bind(thumb.len() as i32)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(respond(thumb))
 }
 
 // ============ Get Comments ============
@@ -1145,3 +1647,45 @@ pub async fn get_comments(
         Ok(Json(serde_json::json!({})))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(None, 100), RangeRequest::Full);
+        assert_eq!(
+            parse_range(Some("bytes=0-9"), 100),
+            RangeRequest::Partial(0, 9)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=90-"), 100),
+            RangeRequest::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-10"), 100),
+            RangeRequest::Partial(90, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=50-500"), 100),
+            RangeRequest::Partial(50, 99)
+        );
+        assert_eq!(
+            parse_range(Some("bytes=100-"), 100),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range(Some("bytes=0-1,5-9"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("items=0-9"), 100), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=9-0"), 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_thumb_edge() {
+        assert_eq!(thumb_edge(0), 0);
+        assert_eq!(thumb_edge(1), 64);
+        assert_eq!(thumb_edge(128), 128);
+        assert_eq!(thumb_edge(300), 512);
+        assert_eq!(thumb_edge(5000), 2048);
+    }
+}
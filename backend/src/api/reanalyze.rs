@@ -0,0 +1,479 @@
+//! Re-analysis of a task's collected articles
+//!
+//! Runs similarity and `generate_insight` again over the articles a task
+//! already has, with an updated prompt and/or another reasoning or embedding
+//! provider, without going back to WeChat. Each run is an analysis revision
+//! (`insight_revisions`, numbered from 1; the worker's own results count as
+//! revision 0) with its per-article results in `insight_revision_articles`,
+//! so earlier results stay available. Applying a revision copies its
+//! similarity and insight onto the articles and makes its prompt the task's.
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api::insight::{
+    authorize_task, cosine_similarity, generate_insight, stored_config, TaskConfig,
+};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::llm::cache::CachedEmbedding;
+use crate::llm::usage::{MeteredChat, MeteredEmbedding, UsageMeter};
+use crate::llm::EmbeddingProvider;
+use crate::repository::articles::{self, Freshness};
+use crate::AppState;
+
+// ============ Types ============
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReanalyzeRequest {
+    /// Defaults to the task's current prompt
+    pub prompt: Option<String>,
+    /// Default to the task's providers
+    pub reasoning_provider: Option<String>,
+    pub embedding_provider: Option<String>,
+    // API keys are not persisted with the task; server keys are used when omitted
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Revision {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub revision: i32,
+    pub prompt: String,
+    pub reasoning_provider: String,
+    pub embedding_provider: String,
+    /// "running", "completed" or "failed"
+    pub status: String,
+    /// Articles to analyze, of which `processed` are done and `relevant` kept
+    pub total: i32,
+    pub processed: i32,
+    pub relevant: i32,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub completed_at: Option<i64>,
+    pub applied_at: Option<i64>,
+}
+
+/// A revision's result for one article, next to the article's current values
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct RevisionArticle {
+    article_id: Uuid,
+    title: String,
+    url: String,
+    account_name: Option<String>,
+    current_similarity: Option<f64>,
+    current_insight: Option<String>,
+    similarity: Option<f64>,
+    is_relevant: bool,
+    insight: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SourceArticle {
+    id: Uuid,
+    title: String,
+    url: String,
+    dedup_embedding: Option<Vec<f32>>,
+}
+
+// ============ Helpers ============
+
+/// Summary the model sees next to the title: the synced digest, else the
+/// description of the stored page
+async fn article_digest(pool: &PgPool, url: &str) -> String {
+    let synced: Option<Option<String>> =
+        sqlx::query_scalar("SELECT digest FROM articles WHERE link = $1 LIMIT 1")
+            .bind(url)
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None);
+    if let Some(digest) = synced.flatten().filter(|d| !d.trim().is_empty()) {
+        return digest;
+    }
+    match articles::find(pool, None, Some(url), Freshness::Any).await {
+        Ok(Some(article)) => crate::content::extract::extract(&article.content)
+            .digest
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+async fn load_revision(
+    state: &AppState,
+    task_id: Uuid,
+    revision: i32,
+) -> Result<Revision, AppError> {
+    sqlx::query_as::<_, Revision>(
+        "SELECT * FROM insight_revisions WHERE task_id = $1 AND revision = $2",
+    )
+    .bind(task_id)
+    .bind(revision)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(AppError::NotFound("Revision not found".to_string()))
+}
+
+/// Body of a revision run. Articles keep their stored embedding only while
+/// the embedding provider is unchanged; the others are embedded again.
+async fn run(
+    state: &AppState,
+    task_id: Uuid,
+    revision_id: Uuid,
+    config: &TaskConfig,
+    embedder: &CachedEmbedding,
+    reasoning_llm: &MeteredChat,
+    articles: Vec<SourceArticle>,
+) -> anyhow::Result<()> {
+    let pool = &state.db_pool;
+    let mut prompt_embedding = embedder.embed_one(&config.prompt).await?;
+    if prompt_embedding.is_empty() {
+        return Err(anyhow::anyhow!("Embedding generation failed"));
+    }
+    if config.feedback_tuning {
+        prompt_embedding =
+            crate::api::feedback::tuned_embedding(pool, task_id, &prompt_embedding).await?;
+    }
+
+    let language = crate::llm::language_name(config.output_language());
+    for article in articles {
+        crate::shutdown::check()?;
+        let digest = article_digest(pool, &article.url).await;
+
+        let embedding = match article.dedup_embedding {
+            Some(e) if e.len() == prompt_embedding.len() => Some(e),
+            _ => match embedder
+                .embed_one(&format!("{} {}", article.title, digest))
+                .await
+            {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    tracing::warn!(
+                        "[Reanalyze] Failed to embed article '{}': {}",
+                        article.title,
+                        e
+                    );
+                    None
+                }
+            },
+        };
+        let similarity = embedding.map(|e| cosine_similarity(&prompt_embedding, &e));
+
+        match generate_insight(
+            reasoning_llm,
+            &config.prompt,
+            &article.title,
+            &digest,
+            &language,
+        )
+        .await
+        {
+            Ok((is_relevant, insight)) => {
+                sqlx::query(
+                    "INSERT INTO insight_revision_articles (revision_id, article_id, similarity, is_relevant, insight) VALUES ($1, $2, $3, $4, $5)",
+                )
+                .bind(revision_id)
+                .bind(article.id)
+                .bind(similarity)
+                .bind(is_relevant)
+                .bind(&insight)
+                .execute(pool)
+                .await?;
+                sqlx::query(
+                    "UPDATE insight_revisions SET processed = processed + 1, relevant = relevant + $2 WHERE id = $1",
+                )
+                .bind(revision_id)
+                .bind(is_relevant as i32)
+                .execute(pool)
+                .await?;
+            }
+            Err(e) => {
+                // Left out of the revision; the run goes on
+                tracing::warn!(
+                    "[Reanalyze] generate_insight failed for '{}': {}",
+                    article.title,
+                    e
+                );
+                sqlx::query("UPDATE insight_revisions SET processed = processed + 1 WHERE id = $1")
+                    .bind(revision_id)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// ============ Handlers ============
+
+/// Start a new analysis revision of a task's articles in the background
+pub async fn reanalyze(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    body: Option<Json<ReanalyzeRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+    let req = body.map(|Json(r)| r).unwrap_or_default();
+
+    let status: String = sqlx::query_scalar("SELECT status FROM insight_tasks WHERE id = $1")
+        .bind(id)
+        .fetch_one(&state.db_pool)
+        .await?;
+    if matches!(status.as_str(), "pending" | "processing" | "cancelling") {
+        return Err(AppError::BadRequest(
+            "任务运行中，请在任务结束后重新分析".to_string(),
+        ));
+    }
+    let running: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM insight_revisions WHERE task_id = $1 AND status = 'running'",
+    )
+    .bind(id)
+    .fetch_one(&state.db_pool)
+    .await?;
+    if running > 0 {
+        return Err(AppError::BadRequest("该任务正在重新分析".to_string()));
+    }
+
+    let mut config = stored_config(&state, id).await?;
+    let previous_embedding_provider = config.embedding_provider.clone();
+    if let Some(prompt) = req.prompt.map(|p| p.trim().to_string()) {
+        if prompt.is_empty() {
+            return Err(AppError::BadRequest("prompt不能为空".to_string()));
+        }
+        config.prompt = prompt;
+    }
+    if let Some(provider) = req.reasoning_provider {
+        config.reasoning_provider = provider;
+    }
+    if let Some(provider) = req.embedding_provider {
+        config.embedding_provider = provider;
+    }
+    config.deepseek_key = req.deepseek_api_key;
+    config.gemini_key = req.gemini_api_key;
+    config.openai_compatible_key = req.openai_compatible_api_key;
+
+    // Build providers up front so a missing key fails the request at once
+    let meter = UsageMeter::new(state.db_pool.clone(), id);
+    let embedding_config = config.provider_config(&config.embedding_provider, true);
+    let dimension = embedding_config.dimension;
+    let embedder = CachedEmbedding::new(
+        Box::new(MeteredEmbedding::new(
+            crate::api::llm::embedding_provider(
+                &state,
+                &config.embedding_provider,
+                embedding_config,
+            )
+            .await?,
+            meter.clone(),
+            "embedding",
+        )),
+        state.db_pool.clone(),
+        dimension,
+    );
+    let reasoning_llm = MeteredChat::new(
+        crate::api::llm::chat_provider(
+            &state,
+            &config.reasoning_provider,
+            config.provider_config(&config.reasoning_provider, false),
+        )
+        .await?,
+        meter,
+        "insight",
+    );
+
+    // Linked duplicates share their original's insight
+    let mut articles = sqlx::query_as::<_, SourceArticle>(
+        "SELECT id, title, url, dedup_embedding FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL ORDER BY similarity DESC NULLS LAST",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await?;
+    if articles.is_empty() {
+        return Err(AppError::BadRequest("任务没有可分析的文章".to_string()));
+    }
+
+    let revision_id = Uuid::new_v4();
+    let revision: i32 = sqlx::query_scalar(
+        r#"
+        INSERT INTO insight_revisions (id, task_id, revision, prompt, reasoning_provider, embedding_provider, status, total, created_at)
+        SELECT $1, $2, COALESCE(MAX(revision), 0) + 1, $3, $4, $5, 'running', $6, $7
+        FROM insight_revisions WHERE task_id = $2
+        RETURNING revision
+        "#,
+    )
+    .bind(revision_id)
+    .bind(id)
+    .bind(&config.prompt)
+    .bind(&config.reasoning_provider)
+    .bind(&config.embedding_provider)
+    .bind(articles.len() as i32)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    tracing::info!(
+        "[Reanalyze] Task {}: revision {} over {} article(s)",
+        id,
+        revision,
+        articles.len()
+    );
+    if config.embedding_provider != previous_embedding_provider {
+        articles.iter_mut().for_each(|a| a.dedup_embedding = None);
+    }
+    tokio::spawn(async move {
+        let result = run(
+            &state,
+            id,
+            revision_id,
+            &config,
+            &embedder,
+            &reasoning_llm,
+            articles,
+        )
+        .await;
+        let (status, error) = match result {
+            Ok(()) => ("completed", None),
+            Err(e) => {
+                tracing::error!("[Reanalyze] Revision {} failed: {}", revision_id, e);
+                ("failed", Some(e.to_string()))
+            }
+        };
+        let _ = sqlx::query(
+            "UPDATE insight_revisions SET status = $2, error = $3, completed_at = $4 WHERE id = $1",
+        )
+        .bind(revision_id)
+        .bind(status)
+        .bind(error)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&state.db_pool)
+        .await;
+    });
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "id": revision_id,
+        "revision": revision
+    })))
+}
+
+/// Analysis revisions of a task, newest first
+pub async fn list_revisions(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+
+    let revisions = sqlx::query_as::<_, Revision>(
+        "SELECT * FROM insight_revisions WHERE task_id = $1 ORDER BY revision DESC",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": revisions
+    })))
+}
+
+/// One revision with its per-article results, most similar first
+pub async fn get_revision(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path((id, revision)): Path<(Uuid, i32)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+    let revision = load_revision(&state, id, revision).await?;
+
+    let articles = sqlx::query_as::<_, RevisionArticle>(
+        r#"
+        SELECT r.article_id, a.title, a.url, a.account_name,
+               a.similarity AS current_similarity, a.insight AS current_insight,
+               r.similarity, r.is_relevant, r.insight
+        FROM insight_revision_articles r
+        JOIN insight_articles a ON a.id = r.article_id
+        WHERE r.revision_id = $1
+        ORDER BY r.similarity DESC NULLS LAST
+        "#,
+    )
+    .bind(revision.id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "revision": revision,
+        "articles": articles
+    })))
+}
+
+/// Copy a completed revision's similarity and insight onto the task's
+/// articles and make its prompt and providers the task's
+pub async fn apply_revision(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path((id, revision)): Path<(Uuid, i32)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, id).await?;
+    let revision = load_revision(&state, id, revision).await?;
+    if revision.status != "completed" {
+        return Err(AppError::BadRequest(format!(
+            "只有已完成的分析可以应用 (当前状态: {})",
+            revision.status
+        )));
+    }
+
+    let mut tx = state.db_pool.begin().await?;
+    let updated = sqlx::query(
+        r#"
+        UPDATE insight_articles a
+        SET similarity = COALESCE(r.similarity, a.similarity), insight = r.insight
+        FROM insight_revision_articles r
+        WHERE r.revision_id = $1 AND a.id = r.article_id
+        "#,
+    )
+    .bind(revision.id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query("UPDATE insight_tasks SET prompt = $2, updated_at = $3 WHERE id = $1")
+        .bind(id)
+        .bind(&revision.prompt)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE insight_task_state
+        SET config = config || jsonb_build_object('prompt', $2::text, 'reasoning_provider', $3::text, 'embedding_provider', $4::text)
+        WHERE task_id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(&revision.prompt)
+    .bind(&revision.reasoning_provider)
+    .bind(&revision.embedding_provider)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE insight_revisions SET applied_at = $2 WHERE id = $1")
+        .bind(revision.id)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "updated": updated
+    })))
+}
@@ -0,0 +1,597 @@
+//! Scheduled recurring insight tasks
+//!
+//! A schedule re-runs the same prompt on a fixed interval. In `append` mode each
+//! run adds new articles to one long-lived task; in `series` mode each run
+//! creates a new task linked by `schedule_id`, skipping articles already found
+//! by earlier runs.
+
+use axum::{extract::State, Extension, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::{
+    get_valid_auth_key, insert_task_record, restart_task_run, spawn_worker, CreateTaskRequest,
+    TaskConfig,
+};
+use crate::auth::Principal;
+use crate::credentials;
+use crate::error::AppError;
+use crate::AppState;
+
+/// How often the scheduler looks for due schedules
+const TICK_SECS: u64 = 60;
+/// Shortest allowed interval between runs
+const MIN_INTERVAL_SECS: i64 = 3600;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduleRequest {
+    pub name: Option<String>,
+    /// "hourly", "daily" or "weekly"; ignored when `interval_seconds` is set
+    pub frequency: Option<String>,
+    pub interval_seconds: Option<i64>,
+    /// "append" (default) or "series"
+    pub mode: Option<String>,
+    /// Unix timestamp of the first run (default: now)
+    pub start_at: Option<i64>,
+    #[serde(flatten)]
+    pub task: CreateTaskRequest,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateScheduleRequest {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub frequency: Option<String>,
+    pub interval_seconds: Option<i64>,
+    pub target_count: Option<i32>,
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    pub openai_compatible_api_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleIdRequest {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct InsightSchedule {
+    pub id: Uuid,
+    pub name: String,
+    pub prompt: String,
+    pub mode: String,
+    pub owner_id: Option<Uuid>,
+    pub interval_seconds: i64,
+    pub enabled: bool,
+    pub task_id: Option<Uuid>,
+    pub next_run_at: i64,
+    pub last_run_at: Option<i64>,
+    pub run_count: i32,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const SCHEDULE_COLUMNS: &str = "id, name, prompt, mode, owner_id, interval_seconds, enabled, task_id, next_run_at, last_run_at, run_count, last_error, created_at, updated_at";
+
+// ============ Helpers ============
+
+fn resolve_interval(
+    frequency: Option<&str>,
+    interval_seconds: Option<i64>,
+) -> Result<i64, AppError> {
+    let interval = match (interval_seconds, frequency) {
+        (Some(secs), _) => secs,
+        (None, Some("hourly")) => 3600,
+        (None, Some("daily")) | (None, None) => 86400,
+        (None, Some("weekly")) => 7 * 86400,
+        (None, Some(other)) => {
+            return Err(AppError::BadRequest(format!(
+                "不支持的frequency: {}",
+                other
+            )));
+        }
+    };
+    if interval < MIN_INTERVAL_SECS {
+        return Err(AppError::BadRequest(format!(
+            "interval_seconds不能小于{}",
+            MIN_INTERVAL_SECS
+        )));
+    }
+    Ok(interval)
+}
+
+/// Label a schedule's sealed `provider` key is bound to
+fn key_label(id: Uuid, provider: &str) -> String {
+    format!("schedule:{}:{}", id, provider)
+}
+
+/// `(nonce, ciphertext)` of a sealed key
+type SealedKey = (Vec<u8>, Vec<u8>);
+
+/// Sealed form of a key to store; `None` for a blank key
+fn seal_key(id: Uuid, provider: &str, key: Option<&str>) -> Result<Option<SealedKey>, AppError> {
+    match key.map(str::trim).filter(|k| !k.is_empty()) {
+        Some(key) => Ok(Some(credentials::seal_secret(
+            &key_label(id, provider),
+            key,
+        )?)),
+        None => Ok(None),
+    }
+}
+
+fn unseal_key(
+    id: Uuid,
+    provider: &str,
+    nonce: Option<Vec<u8>>,
+    ciphertext: Option<Vec<u8>>,
+) -> Option<String> {
+    let (nonce, ciphertext) = (nonce?, ciphertext?);
+    let key = credentials::unseal_secret(&key_label(id, provider), &nonce, &ciphertext);
+    if key.is_none() {
+        tracing::warn!(
+            "[Schedule] {} key of {} cannot be decrypted, using the server key",
+            provider,
+            id
+        );
+    }
+    key
+}
+
+#[derive(sqlx::FromRow)]
+struct ScheduleKeys {
+    config: serde_json::Value,
+    owner_id: Option<Uuid>,
+    deepseek_key_nonce: Option<Vec<u8>>,
+    deepseek_key_ciphertext: Option<Vec<u8>>,
+    gemini_key_nonce: Option<Vec<u8>>,
+    gemini_key_ciphertext: Option<Vec<u8>>,
+    openai_compatible_key_nonce: Option<Vec<u8>>,
+    openai_compatible_key_ciphertext: Option<Vec<u8>>,
+}
+
+/// Worker config of a schedule, with its owner and stored API keys. Schedules
+/// without a key of their own use the server-side credentials.
+async fn load_schedule_config(state: &AppState, id: Uuid) -> Result<TaskConfig, AppError> {
+    let row = sqlx::query_as::<_, ScheduleKeys>(
+        r#"
+        SELECT config, owner_id, deepseek_key_nonce, deepseek_key_ciphertext, gemini_key_nonce, gemini_key_ciphertext,
+               openai_compatible_key_nonce, openai_compatible_key_ciphertext
+        FROM insight_schedules WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or(AppError::NotFound("Schedule not found".to_string()))?;
+
+    let mut config: TaskConfig = serde_json::from_value(row.config)
+        .map_err(|e| AppError::Internal(format!("Invalid schedule config: {}", e)))?;
+    config.owner_id = row.owner_id;
+    config.deepseek_key = unseal_key(
+        id,
+        "deepseek",
+        row.deepseek_key_nonce,
+        row.deepseek_key_ciphertext,
+    );
+    config.gemini_key = unseal_key(
+        id,
+        "gemini",
+        row.gemini_key_nonce,
+        row.gemini_key_ciphertext,
+    );
+    config.openai_compatible_key = unseal_key(
+        id,
+        "openai_compatible",
+        row.openai_compatible_key_nonce,
+        row.openai_compatible_key_ciphertext,
+    );
+    Ok(config)
+}
+
+/// Schedule `id`, or NotFound when it is missing or not visible to `principal`
+async fn load_schedule(
+    state: &AppState,
+    principal: &Principal,
+    id: Uuid,
+) -> Result<InsightSchedule, AppError> {
+    sqlx::query_as::<_, InsightSchedule>(&format!(
+        "SELECT {} FROM insight_schedules WHERE id = $1",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .filter(|s| principal.can_access(s.owner_id))
+    .ok_or(AppError::NotFound("Schedule not found".to_string()))
+}
+
+/// Seal plaintext keys stored by earlier versions, or drop them when no
+/// SETTINGS_MASTER_KEY is configured
+pub async fn seal_legacy_keys(pool: &sqlx::PgPool) -> Result<(), AppError> {
+    let rows: Vec<(Uuid, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT id, deepseek_api_key, gemini_api_key FROM insight_schedules WHERE deepseek_api_key IS NOT NULL OR gemini_api_key IS NOT NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let encrypt = credentials::encryption_enabled();
+    for (id, deepseek, gemini) in &rows {
+        let (deepseek, gemini) = if encrypt {
+            (
+                seal_key(*id, "deepseek", deepseek.as_deref())?,
+                seal_key(*id, "gemini", gemini.as_deref())?,
+            )
+        } else {
+            (None, None)
+        };
+        sqlx::query(
+            r#"
+            UPDATE insight_schedules SET
+                deepseek_api_key = NULL,
+                gemini_api_key = NULL,
+                deepseek_key_nonce = COALESCE($1, deepseek_key_nonce),
+                deepseek_key_ciphertext = COALESCE($2, deepseek_key_ciphertext),
+                gemini_key_nonce = COALESCE($3, gemini_key_nonce),
+                gemini_key_ciphertext = COALESCE($4, gemini_key_ciphertext)
+            WHERE id = $5
+            "#,
+        )
+        .bind(deepseek.as_ref().map(|k| &k.0))
+        .bind(deepseek.as_ref().map(|k| &k.1))
+        .bind(gemini.as_ref().map(|k| &k.0))
+        .bind(gemini.as_ref().map(|k| &k.1))
+        .bind(id)
+        .execute(pool)
+        .await?;
+    }
+
+    if encrypt {
+        tracing::info!(
+            "[Schedule] Encrypted the API keys of {} schedule(s)",
+            rows.len()
+        );
+    } else {
+        tracing::warn!(
+            "[Schedule] SETTINGS_MASTER_KEY is not set; dropped the plaintext API keys of {} schedule(s), they now use the server keys",
+            rows.len()
+        );
+    }
+    Ok(())
+}
+
+/// Start one run of a schedule. Returns the task that was started.
+async fn run_schedule(state: &AppState, schedule: &InsightSchedule) -> Result<Uuid, AppError> {
+    let mut config = load_schedule_config(state, schedule.id).await?;
+    if get_valid_auth_key(state, config.owner_id).await.is_none() {
+        return Err(AppError::BadRequest("没有有效的微信登录会话".to_string()));
+    }
+
+    // Don't overlap with the previous run; a deleted task starts a fresh one
+    let mut previous_task = None;
+    if let Some(task_id) = schedule.task_id {
+        let status: Option<String> =
+            sqlx::query_scalar("SELECT status FROM insight_tasks WHERE id = $1")
+                .bind(task_id)
+                .fetch_optional(&state.db_pool)
+                .await?;
+        match status.as_deref() {
+            Some("pending" | "processing" | "cancelling") => {
+                return Err(AppError::BadRequest("上一次运行尚未结束".to_string()));
+            }
+            Some(_) => previous_task = Some(task_id),
+            None => {}
+        }
+    }
+
+    let task_id = match (schedule.mode.as_str(), previous_task) {
+        ("append", Some(task_id)) => {
+            restart_task_run(state, task_id, &mut config).await?;
+            task_id
+        }
+        _ => {
+            // Series runs skip everything earlier runs already looked at
+            let seen_urls: Vec<String> = sqlx::query_scalar(
+                "SELECT a.url FROM insight_articles a JOIN insight_tasks t ON t.id = a.task_id WHERE t.schedule_id = $1",
+            )
+            .bind(schedule.id)
+            .fetch_all(&state.db_pool)
+            .await?;
+
+            let task_id = Uuid::new_v4();
+            insert_task_record(state, task_id, &config, Some(schedule.id), Some(seen_urls)).await?;
+            task_id
+        }
+    };
+
+    spawn_worker(state, task_id, config).await?;
+    Ok(task_id)
+}
+
+/// Run a due schedule and move its `next_run_at` forward
+async fn tick_schedule(state: &AppState, schedule: &InsightSchedule) {
+    let now = chrono::Utc::now().timestamp();
+    let result = run_schedule(state, schedule).await;
+
+    let (task_id, last_error) = match &result {
+        Ok(task_id) => {
+            tracing::info!(
+                "[Schedule] '{}' started task {} (run #{})",
+                schedule.name,
+                task_id,
+                schedule.run_count + 1
+            );
+            (Some(*task_id), None)
+        }
+        Err(e) => {
+            tracing::warn!("[Schedule] '{}' skipped: {}", schedule.name, e);
+            (schedule.task_id, Some(e.to_string()))
+        }
+    };
+
+    // Skip missed slots instead of running them back to back
+    let mut next_run_at = schedule.next_run_at + schedule.interval_seconds;
+    if next_run_at <= now {
+        next_run_at = now + schedule.interval_seconds;
+    }
+
+    let run_inc = if result.is_ok() { 1 } else { 0 };
+    if let Err(e) = sqlx::query(
+        "UPDATE insight_schedules SET task_id = $1, next_run_at = $2, last_run_at = CASE WHEN $3 = 1 THEN $4 ELSE last_run_at END, run_count = run_count + $3, last_error = $5, updated_at = $4 WHERE id = $6",
+    )
+    .bind(task_id)
+    .bind(next_run_at)
+    .bind(run_inc)
+    .bind(now)
+    .bind(&last_error)
+    .bind(schedule.id)
+    .execute(&state.db_pool)
+    .await
+    {
+        tracing::error!("[Schedule] Failed to update schedule {}: {}", schedule.id, e);
+    }
+}
+
+/// Background loop that starts due schedules
+pub fn spawn_scheduler(state: AppState) {
+    tokio::spawn(async move {
+        if let Err(e) = seal_legacy_keys(&state.db_pool).await {
+            tracing::error!("[Schedule] Failed to encrypt stored API keys: {}", e);
+        }
+
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(TICK_SECS));
+        loop {
+            interval.tick().await;
+
+            let now = chrono::Utc::now().timestamp();
+            let due = sqlx::query_as::<_, InsightSchedule>(&format!(
+                "SELECT {} FROM insight_schedules WHERE enabled = true AND next_run_at <= $1 ORDER BY next_run_at",
+                SCHEDULE_COLUMNS
+            ))
+            .bind(now)
+            .fetch_all(&state.db_pool)
+            .await;
+
+            match due {
+                Ok(schedules) => {
+                    for schedule in &schedules {
+                        tick_schedule(&state, schedule).await;
+                    }
+                }
+                Err(e) => tracing::error!("[Schedule] Failed to load due schedules: {}", e),
+            }
+        }
+    });
+}
+
+// ============ Handlers ============
+
+/// Create a recurring task schedule
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<CreateScheduleRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if req.task.prompt.trim().is_empty() {
+        return Err(AppError::BadRequest("prompt不能为空".to_string()));
+    }
+
+    let interval = resolve_interval(req.frequency.as_deref(), req.interval_seconds)?;
+    let mode = req.mode.as_deref().unwrap_or("append");
+    if mode != "append" && mode != "series" {
+        return Err(AppError::BadRequest(format!("不支持的mode: {}", mode)));
+    }
+
+    let mut config = TaskConfig::from_request(&req.task);
+    config.owner_id = principal.user_id;
+    config.validate()?;
+    let now = chrono::Utc::now().timestamp();
+    let id = Uuid::new_v4();
+    let deepseek = seal_key(id, "deepseek", req.task.deepseek_api_key.as_deref())?;
+    let gemini = seal_key(id, "gemini", req.task.gemini_api_key.as_deref())?;
+    let openai_compatible = seal_key(
+        id,
+        "openai_compatible",
+        req.task.openai_compatible_api_key.as_deref(),
+    )?;
+    let name = req
+        .name
+        .clone()
+        .filter(|n| !n.trim().is_empty())
+        .unwrap_or_else(|| req.task.prompt.chars().take(40).collect());
+
+    sqlx::query(
+        r#"
+        INSERT INTO insight_schedules
+            (id, name, prompt, mode, owner_id, interval_seconds, enabled, config,
+             deepseek_key_nonce, deepseek_key_ciphertext, gemini_key_nonce, gemini_key_ciphertext,
+             openai_compatible_key_nonce, openai_compatible_key_ciphertext,
+             next_run_at, run_count, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, true, $7, $8, $9, $10, $11, $12, $13, $14, 0, $15, $15)
+        "#,
+    )
+    .bind(id)
+    .bind(&name)
+    .bind(&config.prompt)
+    .bind(mode)
+    .bind(principal.user_id)
+    .bind(interval)
+    .bind(serde_json::to_value(&config).map_err(|e| AppError::Internal(e.to_string()))?)
+    .bind(deepseek.as_ref().map(|k| &k.0))
+    .bind(deepseek.as_ref().map(|k| &k.1))
+    .bind(gemini.as_ref().map(|k| &k.0))
+    .bind(gemini.as_ref().map(|k| &k.1))
+    .bind(openai_compatible.as_ref().map(|k| &k.0))
+    .bind(openai_compatible.as_ref().map(|k| &k.1))
+    .bind(req.start_at.unwrap_or(now))
+    .bind(now)
+    .execute(&state.db_pool)
+    .await?;
+
+    tracing::info!(
+        "[Schedule] Created '{}' every {}s ({})",
+        name,
+        interval,
+        mode
+    );
+
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
+
+/// List the caller's schedules (all of them for admins)
+pub async fn list_schedules(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let schedules = sqlx::query_as::<_, InsightSchedule>(&format!(
+        "SELECT {} FROM insight_schedules WHERE $1 OR owner_id IS NOT DISTINCT FROM $2 ORDER BY created_at DESC",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": schedules
+    })))
+}
+
+/// Update interval, target, keys or enabled flag of a schedule
+pub async fn update_schedule(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<UpdateScheduleRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    load_schedule(&state, &principal, req.id).await?;
+    let mut config = load_schedule_config(&state, req.id).await?;
+    if let Some(target) = req.target_count {
+        if target <= 0 {
+            return Err(AppError::BadRequest("target_count必须大于0".to_string()));
+        }
+        config.target_count = target;
+    }
+
+    let interval = if req.frequency.is_some() || req.interval_seconds.is_some() {
+        Some(resolve_interval(
+            req.frequency.as_deref(),
+            req.interval_seconds,
+        )?)
+    } else {
+        None
+    };
+    let deepseek = seal_key(req.id, "deepseek", req.deepseek_api_key.as_deref())?;
+    let gemini = seal_key(req.id, "gemini", req.gemini_api_key.as_deref())?;
+    let openai_compatible = seal_key(
+        req.id,
+        "openai_compatible",
+        req.openai_compatible_api_key.as_deref(),
+    )?;
+
+    sqlx::query(
+        r#"
+        UPDATE insight_schedules SET
+            name = COALESCE($1, name),
+            enabled = COALESCE($2, enabled),
+            interval_seconds = COALESCE($3, interval_seconds),
+            config = $4,
+            deepseek_key_nonce = COALESCE($5, deepseek_key_nonce),
+            deepseek_key_ciphertext = COALESCE($6, deepseek_key_ciphertext),
+            gemini_key_nonce = COALESCE($7, gemini_key_nonce),
+            gemini_key_ciphertext = COALESCE($8, gemini_key_ciphertext),
+            openai_compatible_key_nonce = COALESCE($9, openai_compatible_key_nonce),
+            openai_compatible_key_ciphertext = COALESCE($10, openai_compatible_key_ciphertext),
+            updated_at = $11
+        WHERE id = $12
+        "#,
+    )
+    .bind(&req.name)
+    .bind(req.enabled)
+    .bind(interval)
+    .bind(serde_json::to_value(&config).map_err(|e| AppError::Internal(e.to_string()))?)
+    .bind(deepseek.as_ref().map(|k| &k.0))
+    .bind(deepseek.as_ref().map(|k| &k.1))
+    .bind(gemini.as_ref().map(|k| &k.0))
+    .bind(gemini.as_ref().map(|k| &k.1))
+    .bind(openai_compatible.as_ref().map(|k| &k.0))
+    .bind(openai_compatible.as_ref().map(|k| &k.1))
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Delete a schedule. Tasks it created are kept.
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<ScheduleIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    load_schedule(&state, &principal, req.id).await?;
+
+    sqlx::query("UPDATE insight_tasks SET schedule_id = NULL WHERE schedule_id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    sqlx::query("DELETE FROM insight_schedules WHERE id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Trigger a schedule immediately, outside its interval
+pub async fn run_schedule_now(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<ScheduleIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let schedule = load_schedule(&state, &principal, req.id).await?;
+
+    let task_id = run_schedule(&state, &schedule).await?;
+
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        "UPDATE insight_schedules SET task_id = $1, last_run_at = $2, run_count = run_count + 1, last_error = NULL, updated_at = $2 WHERE id = $3",
+    )
+    .bind(task_id)
+    .bind(now)
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(Json(
+        serde_json::json!({ "success": true, "task_id": task_id }),
+    ))
+}
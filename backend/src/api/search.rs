@@ -0,0 +1,160 @@
+//! Full-text search API
+//!
+//! Keyword search over article titles/digests and cached article bodies, ranked
+//! with `ts_rank_cd` and returned with highlighted snippets.
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::fulltext;
+use crate::AppState;
+
+/// Characters shown on each side of the first match
+const SNIPPET_RADIUS: usize = 60;
+
+#[derive(Debug, Deserialize)]
+pub struct FulltextQuery {
+    pub q: String,
+    /// "all" (default), "title" (title + digest) or "content" (article body)
+    pub scope: Option<String>,
+    pub fakeid: Option<String>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct FulltextRow {
+    id: String,
+    rank: f64,
+    content_text: Option<String>,
+    title: Option<String>,
+    fakeid: Option<String>,
+    link: Option<String>,
+    create_time: Option<i64>,
+    digest: Option<String>,
+    original_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FulltextHit {
+    pub id: String,
+    pub title: String,
+    /// Title with matches wrapped in `<mark>`
+    pub title_highlight: String,
+    pub fakeid: Option<String>,
+    pub url: Option<String>,
+    pub create_time: Option<i64>,
+    pub rank: f64,
+    /// True when the article body matched (not only title/digest)
+    pub content_match: bool,
+    pub snippet: String,
+}
+
+/// Ranked keyword search
+pub async fn fulltext_search(
+    State(state): State<AppState>,
+    Query(query): Query<FulltextQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let scope = query.scope.unwrap_or_else(|| "all".to_string());
+    if !matches!(scope.as_str(), "all" | "title" | "content") {
+        return Err(AppError::BadRequest(format!("不支持的scope: {}", scope)));
+    }
+    let terms = fulltext::query_terms(&query.q);
+    if terms.is_empty() {
+        return Err(AppError::BadRequest("搜索关键词不能为空".to_string()));
+    }
+
+    // plainto_tsquery ANDs the segmented tokens and handles escaping
+    let rows = sqlx::query_as::<_, FulltextRow>(
+        r#"
+        WITH q AS (SELECT plainto_tsquery('simple', $1) AS q),
+        hits AS (
+            SELECT a.id, ts_rank_cd(a.search_tsv, q.q) * 2 AS rank, NULL::TEXT AS body
+            FROM articles a, q
+            WHERE $2 <> 'content' AND a.search_tsv @@ q.q
+            UNION ALL
+            SELECT c.id, ts_rank_cd(c.search_tsv, q.q) AS rank, c.search_text AS body
+            FROM article_content c, q
+            WHERE $2 <> 'title' AND c.search_tsv @@ q.q
+        )
+        SELECT h.id, SUM(h.rank)::FLOAT8 AS rank, MAX(h.body) AS content_text,
+               a.title, a.fakeid, a.link, a.create_time, a.digest, c.original_url
+        FROM hits h
+        LEFT JOIN articles a ON a.id = h.id
+        LEFT JOIN article_content c ON c.id = h.id
+        WHERE ($3::TEXT IS NULL OR a.fakeid = $3)
+        GROUP BY h.id, a.title, a.fakeid, a.link, a.create_time, a.digest, c.original_url
+        ORDER BY rank DESC, a.create_time DESC NULLS LAST
+        LIMIT $4 OFFSET $5
+        "#,
+    )
+    .bind(terms.join(" "))
+    .bind(&scope)
+    .bind(&query.fakeid)
+    .bind(query.limit.unwrap_or(20).clamp(1, 100))
+    .bind(query.offset.unwrap_or(0).max(0))
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let hits: Vec<FulltextHit> = rows
+        .into_iter()
+        .map(|row| {
+            let title = row.title.unwrap_or_default();
+            let content_match = row.content_text.is_some();
+            let source = row
+                .content_text
+                .as_deref()
+                .or(row.digest.as_deref())
+                .unwrap_or("");
+            FulltextHit {
+                title_highlight: fulltext::snippet(&title, &terms, title.chars().count()),
+                snippet: fulltext::snippet(source, &terms, SNIPPET_RADIUS),
+                id: row.id,
+                title,
+                fakeid: row.fakeid,
+                url: row.link.or(row.original_url),
+                create_time: row.create_time,
+                rank: row.rank,
+                content_match,
+            }
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "terms": terms,
+        "data": hits
+    })))
+}
+
+/// Drop all search vectors so the background indexer rebuilds them
+pub async fn fulltext_reindex(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let articles =
+        sqlx::query("UPDATE articles SET search_tsv = NULL WHERE search_tsv IS NOT NULL")
+            .execute(&state.db_pool)
+            .await?
+            .rows_affected();
+    let contents =
+        sqlx::query("UPDATE article_content SET search_tsv = NULL WHERE search_tsv IS NOT NULL")
+            .execute(&state.db_pool)
+            .await?
+            .rows_affected();
+
+    tracing::info!(
+        "[Fulltext] Reindex requested: {} articles, {} contents",
+        articles,
+        contents
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "articles": articles,
+        "contents": contents
+    })))
+}
@@ -0,0 +1,105 @@
+//! Settings API handlers
+//!
+//! Server-side LLM provider keys (see `credentials`) and task completion
+//! notifiers (see `notify`).
+
+use axum::{extract::State, Json};
+use serde::Deserialize;
+
+use crate::credentials;
+use crate::error::AppError;
+use crate::notify::{self, SaveNotifier};
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct SaveLlmKeyRequest {
+    pub provider: String,
+    pub api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteLlmKeyRequest {
+    pub provider: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotifierKindRequest {
+    pub kind: String,
+}
+
+/// Where each provider's key comes from, masked
+pub async fn get_llm(State(state): State<AppState>) -> Result<Json<serde_json::Value>, AppError> {
+    let providers = credentials::list(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "encryption_enabled": credentials::encryption_enabled(),
+        "data": providers
+    })))
+}
+
+/// Store a provider key encrypted
+pub async fn save_llm(
+    State(state): State<AppState>,
+    Json(req): Json<SaveLlmKeyRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    credentials::save(&state.db_pool, &req.provider, &req.api_key).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "hint": credentials::mask(req.api_key.trim())
+    })))
+}
+
+/// Forget a stored provider key
+pub async fn delete_llm(
+    State(state): State<AppState>,
+    Json(req): Json<DeleteLlmKeyRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !credentials::remove(&state.db_pool, &req.provider).await? {
+        return Err(AppError::NotFound("API Key not found".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Configured notifiers, secrets masked
+pub async fn get_notifiers(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let notifiers = notify::list(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "encryption_enabled": credentials::encryption_enabled(),
+        "data": notifiers
+    })))
+}
+
+/// Create or update a notifier
+pub async fn save_notifier(
+    State(state): State<AppState>,
+    Json(req): Json<SaveNotifier>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let notifier = notify::save(&state.db_pool, &req).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": notifier
+    })))
+}
+
+/// Remove a notifier
+pub async fn delete_notifier(
+    State(state): State<AppState>,
+    Json(req): Json<NotifierKindRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !notify::remove(&state.db_pool, &req.kind).await? {
+        return Err(AppError::NotFound("通知方式未配置".to_string()));
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Send a sample digest through a notifier
+pub async fn test_notifier(
+    State(state): State<AppState>,
+    Json(req): Json<NotifierKindRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    notify::send_test(&state.db_pool, &req.kind).await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
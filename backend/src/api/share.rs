@@ -0,0 +1,370 @@
+//! Read-only public sharing
+//!
+//! Publishes a task's results at a tokenized URL (JSON or server-rendered HTML)
+//! with per-share expiry and revocation. Only the task summary and article list
+//! are exposed — never cookies, API keys or cached HTML. Tokens are random and
+//! looked up in `task_shares`, so they cannot be forged and a revocation takes
+//! effect at once. Links are served under `/api/shared/:token`; `/api/share/`
+//! keeps working for links handed out before.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::{authorize_task, InsightArticle, InsightTask};
+use crate::api::summary::ExecutiveSummary;
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareRequest {
+    pub task_id: Uuid,
+    /// Seconds until the link expires. `None` keeps it valid until revoked.
+    pub ttl_seconds: Option<i64>,
+}
+
+/// Body of `POST /api/insight/:id/share`, which may be empty
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskShareRequest {
+    /// Seconds until the link expires (default 7 days)
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeShareRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListSharesQuery {
+    pub task_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ViewShareQuery {
+    /// "json" (default) or "html"
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TaskShare {
+    pub token: String,
+    pub task_id: Uuid,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub view_count: i32,
+}
+
+/// Public view of a task, stripped of internal fields
+#[derive(Debug, Serialize)]
+struct SharedTask {
+    prompt: String,
+    status: String,
+    keywords: Vec<String>,
+    completion_reason: Option<String>,
+    output_language: String,
+    created_at: i64,
+    updated_at: i64,
+    /// Executive summary, when one was generated
+    summary: Option<serde_json::Value>,
+    articles: Vec<SharedArticle>,
+}
+
+#[derive(Debug, Serialize)]
+struct SharedArticle {
+    title: String,
+    url: String,
+    account_name: Option<String>,
+    publish_time: Option<i64>,
+    similarity: Option<f64>,
+    relevance_score: Option<f64>,
+    insight: Option<String>,
+}
+
+/// Expiry of links minted by `POST /api/insight/:id/share` without `ttl_seconds`
+const DEFAULT_TTL_SECS: i64 = 7 * 24 * 3600;
+
+// ============ Helpers ============
+
+/// Random URL-safe share token
+fn generate_token() -> String {
+    use base64::Engine;
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn share_path(token: &str) -> String {
+    format!("/api/shared/{}", token)
+}
+
+fn render_html(task: &SharedTask) -> String {
+    use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+
+    let fmt_time = |ts: i64| {
+        chrono::DateTime::from_timestamp(ts, 0)
+            .map(|dt| dt.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html><html lang=\"zh-CN\"><head><meta charset=\"UTF-8\">");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">");
+    html.push_str("<meta name=\"robots\" content=\"noindex\">");
+    html.push_str(&format!("<title>{}</title>", text(&task.prompt)));
+    html.push_str(
+        "<style>body{font-family:-apple-system,'PingFang SC','Microsoft YaHei',sans-serif;max-width:860px;margin:0 auto;padding:24px;color:#222;line-height:1.6}\
+         .meta{color:#888;font-size:13px}.kw{display:inline-block;background:#f0f2f5;border-radius:4px;padding:0 6px;margin:2px;font-size:13px}\
+         article{border-bottom:1px solid #eee;padding:16px 0}article h3{margin:0 0 4px}a{color:#576b95;text-decoration:none}\
+         .insight{white-space:pre-wrap;margin-top:8px}.summary{background:#f7f8fa;border-radius:8px;padding:4px 16px}</style></head><body>",
+    );
+
+    html.push_str(&format!("<h1>{}</h1>", text(&task.prompt)));
+    html.push_str(&format!(
+        "<p class=\"meta\">{} · {} 篇文章 · 更新于 {}</p>",
+        text(&task.status),
+        task.articles.len(),
+        fmt_time(task.updated_at)
+    ));
+    if !task.keywords.is_empty() {
+        html.push_str("<p>");
+        for kw in &task.keywords {
+            html.push_str(&format!("<span class=\"kw\">{}</span>", text(kw)));
+        }
+        html.push_str("</p>");
+    }
+    let summary = task
+        .summary
+        .clone()
+        .and_then(|s| serde_json::from_value::<ExecutiveSummary>(s).ok());
+    if let Some(summary) = summary {
+        html.push_str(&format!(
+            "<section class=\"summary\"><h2>概要</h2><p>{}</p>",
+            text(&summary.overview)
+        ));
+        if !summary.key_trends.is_empty() {
+            html.push_str("<ul>");
+            for trend in &summary.key_trends {
+                html.push_str(&format!(
+                    "<li><strong>{}</strong>：{}</li>",
+                    text(&trend.title),
+                    text(&trend.detail)
+                ));
+            }
+            html.push_str("</ul>");
+        }
+        html.push_str("</section>");
+    }
+
+    for article in &task.articles {
+        html.push_str("<article>");
+        html.push_str(&format!(
+            "<h3><a href=\"{}\" target=\"_blank\" rel=\"noopener noreferrer\">{}</a></h3>",
+            attr(&article.url),
+            text(&article.title)
+        ));
+        let mut meta = Vec::new();
+        if let Some(name) = &article.account_name {
+            meta.push(text(name).to_string());
+        }
+        if let Some(ts) = article.publish_time {
+            meta.push(fmt_time(ts));
+        }
+        if let Some(score) = article.relevance_score.or(article.similarity) {
+            meta.push(format!("相关度 {:.2}", score));
+        }
+        html.push_str(&format!("<div class=\"meta\">{}</div>", meta.join(" · ")));
+        if let Some(insight) = &article.insight {
+            html.push_str(&format!("<div class=\"insight\">{}</div>", text(insight)));
+        }
+        html.push_str("</article>");
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+// ============ Handlers ============
+
+/// Store a new share of `task_id`, valid for `ttl_seconds` or until revoked
+async fn mint_share(
+    state: &AppState,
+    principal: &Principal,
+    task_id: Uuid,
+    ttl_seconds: Option<i64>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if matches!(ttl_seconds, Some(ttl) if ttl <= 0) {
+        return Err(AppError::BadRequest("ttl_seconds必须大于0".to_string()));
+    }
+
+    authorize_task(state, principal, task_id).await?;
+
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = ttl_seconds.map(|ttl| now + ttl);
+    let token = generate_token();
+
+    sqlx::query(
+        "INSERT INTO task_shares (token, task_id, created_at, expires_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(&token)
+    .bind(task_id)
+    .bind(now)
+    .bind(expires_at)
+    .execute(&state.db_pool)
+    .await?;
+
+    tracing::info!("[Share] Created share for task {}", task_id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "token": token,
+        "url": share_path(&token),
+        "html_url": format!("{}?format=html", share_path(&token)),
+        "expires_at": expires_at
+    })))
+}
+
+/// Mint a read-only share link for a task
+pub async fn create_share(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<CreateShareRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    mint_share(&state, &principal, req.task_id, req.ttl_seconds).await
+}
+
+/// Mint a share link for the task in the path, expiring after 7 days by default
+pub async fn create_task_share(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    body: Option<Json<TaskShareRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let req = body.map(|Json(req)| req).unwrap_or_default();
+    let ttl = req.ttl_seconds.unwrap_or(DEFAULT_TTL_SECS);
+    mint_share(&state, &principal, id, Some(ttl)).await
+}
+
+/// List share links of a task
+pub async fn list_shares(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Query(query): Query<ListSharesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, query.task_id).await?;
+
+    let shares = sqlx::query_as::<_, TaskShare>(
+        "SELECT token, task_id, created_at, expires_at, revoked_at, view_count FROM task_shares WHERE task_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(query.task_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": shares
+    })))
+}
+
+/// Revoke a share link
+pub async fn revoke_share(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<RevokeShareRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let task_id: Uuid = sqlx::query_scalar("SELECT task_id FROM task_shares WHERE token = $1")
+        .bind(&req.token)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Share not found".to_string()))?;
+    authorize_task(&state, &principal, task_id).await?;
+
+    let updated = sqlx::query(
+        "UPDATE task_shares SET revoked_at = $1 WHERE token = $2 AND revoked_at IS NULL",
+    )
+    .bind(chrono::Utc::now().timestamp())
+    .bind(&req.token)
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+
+    if updated == 0 {
+        return Err(AppError::NotFound("Share not found".to_string()));
+    }
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Public read-only view of a shared task
+pub async fn view_share(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    Query(query): Query<ViewShareQuery>,
+) -> Result<Response, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let task_id: Option<Uuid> = sqlx::query_scalar(
+        "UPDATE task_shares SET view_count = view_count + 1 WHERE token = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2) RETURNING task_id",
+    )
+    .bind(&token)
+    .bind(now)
+    .fetch_optional(&state.db_pool)
+    .await?;
+
+    let task_id = task_id.ok_or(AppError::NotFound("分享链接不存在或已失效".to_string()))?;
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected' ORDER BY similarity DESC NULLS LAST",
+    )
+    .bind(task_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let shared = SharedTask {
+        output_language: task.output_language().to_string(),
+        prompt: task.prompt,
+        status: task.status,
+        keywords: task.keywords,
+        completion_reason: task.completion_reason,
+        created_at: task.created_at,
+        updated_at: task.updated_at,
+        summary: task.summary,
+        articles: articles
+            .into_iter()
+            .map(|a| SharedArticle {
+                title: a.title,
+                url: a.url,
+                account_name: a.account_name,
+                publish_time: a.publish_time,
+                similarity: a.similarity,
+                relevance_score: a.relevance_score,
+                insight: a.insight,
+            })
+            .collect(),
+    };
+
+    if query.format.as_deref() == Some("html") {
+        return Ok(Html(render_html(&shared)).into_response());
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": shared
+    }))
+    .into_response())
+}
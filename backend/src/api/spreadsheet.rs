@@ -0,0 +1,301 @@
+//! Article table export (CSV / XLSX)
+//!
+//! A task's article metadata, one row per article, without downloading any
+//! content. The XLSX is a minimal SpreadsheetML package (inline strings, a
+//! bold frozen header row), built like `docx`.
+
+use std::io::{Cursor, Write};
+
+use chrono::FixedOffset;
+use html_escape::encode_text;
+use zip::write::SimpleFileOptions;
+
+use crate::api::insight::InsightArticle;
+
+const HEADERS: [&str; 8] = [
+    "标题",
+    "公众号",
+    "作者",
+    "原创",
+    "发布日期",
+    "链接",
+    "相似度",
+    "洞察",
+];
+/// Column widths in characters, per `HEADERS`
+const WIDTHS: [u32; 8] = [48, 18, 12, 6, 12, 40, 8, 80];
+
+/// Table export formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TableFormat {
+    Csv,
+    Xlsx,
+}
+
+impl TableFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "csv" => Some(Self::Csv),
+            "xlsx" => Some(Self::Xlsx),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+        }
+    }
+
+    pub fn build(self, articles: &[InsightArticle]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Csv => Ok(to_csv(articles).into_bytes()),
+            Self::Xlsx => build_xlsx(articles),
+        }
+    }
+}
+
+enum Cell {
+    Text(String),
+    Number(f64),
+    Empty,
+}
+
+fn rows(articles: &[InsightArticle]) -> Vec<[Cell; 8]> {
+    articles
+        .iter()
+        .map(|a| {
+            let date = a
+                .publish_time
+                .filter(|ts| *ts > 0)
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                // WeChat publishing follows China time
+                .map(|dt| {
+                    dt.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+                        .format("%Y-%m-%d")
+                        .to_string()
+                });
+            let text = |value: Option<&str>| match value {
+                Some(v) if !v.is_empty() => Cell::Text(v.to_string()),
+                _ => Cell::Empty,
+            };
+            [
+                text(Some(&a.title)),
+                text(a.account_name.as_deref()),
+                text(a.author.as_deref()),
+                text(a.is_original.map(|o| if o { "是" } else { "否" })),
+                text(date.as_deref()),
+                text(Some(&a.url)),
+                a.similarity.map_or(Cell::Empty, |s| {
+                    Cell::Number((s * 10_000.0).round() / 10_000.0)
+                }),
+                text(a.insight.as_deref()),
+            ]
+        })
+        .collect()
+}
+
+/// Text a spreadsheet would not read as a formula
+fn neutralize(text: &str) -> String {
+    if text.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// CSV with a UTF-8 BOM so Excel detects the encoding
+pub fn to_csv(articles: &[InsightArticle]) -> String {
+    let mut out = String::from("\u{feff}");
+    out.push_str(&HEADERS.join(","));
+    out.push_str("\r\n");
+    for row in rows(articles) {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|cell| match cell {
+                Cell::Text(t) => csv_field(&neutralize(t)),
+                Cell::Number(n) => n.to_string(),
+                Cell::Empty => String::new(),
+            })
+            .collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// Spreadsheet column letters for a 0-based index (A..Z is enough here)
+fn column(i: usize) -> char {
+    (b'A' + i as u8) as char
+}
+
+/// Text as XML character data; control characters XML cannot hold are dropped
+fn xml_text(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    encode_text(&text).to_string()
+}
+
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const WORKBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="文章" sheetId="1" r:id="rId1"/></sheets></workbook>"#;
+
+const WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+/// Style 0: default; 1: bold header; 2: wrapped text (insights)
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border/></borders><cellStyleXfs count="1"><xf/></cellStyleXfs><cellXfs count="3"><xf/><xf fontId="1" applyFont="1"/><xf applyAlignment="1"><alignment wrapText="1" vertical="top"/></xf></cellXfs></styleSheet>"#;
+
+/// A whole `.xlsx` file with one sheet of articles
+pub fn build_xlsx(articles: &[InsightArticle]) -> anyhow::Result<Vec<u8>> {
+    let mut sheet = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><cols>"#,
+    );
+    for (i, width) in WIDTHS.iter().enumerate() {
+        sheet.push_str(&format!(
+            r#"<col min="{0}" max="{0}" width="{1}" customWidth="1"/>"#,
+            i + 1,
+            width
+        ));
+    }
+    sheet.push_str("</cols><sheetData><row r=\"1\">");
+    for (i, header) in HEADERS.iter().enumerate() {
+        sheet.push_str(&format!(
+            r#"<c r="{}1" t="inlineStr" s="1"><is><t>{}</t></is></c>"#,
+            column(i),
+            header
+        ));
+    }
+    sheet.push_str("</row>");
+    for (r, row) in rows(articles).iter().enumerate() {
+        let r = r + 2;
+        sheet.push_str(&format!("<row r=\"{}\">", r));
+        for (i, cell) in row.iter().enumerate() {
+            let style = if HEADERS[i] == "洞察" {
+                r#" s="2""#
+            } else {
+                ""
+            };
+            match cell {
+                Cell::Text(t) => sheet.push_str(&format!(
+                    r#"<c r="{}{}" t="inlineStr"{}><is><t xml:space="preserve">{}</t></is></c>"#,
+                    column(i),
+                    r,
+                    style,
+                    xml_text(t)
+                )),
+                Cell::Number(n) => {
+                    sheet.push_str(&format!(r#"<c r="{}{}"><v>{}</v></c>"#, column(i), r, n))
+                }
+                Cell::Empty => {}
+            }
+        }
+        sheet.push_str("</row>");
+    }
+    sheet.push_str(&format!(
+        r#"</sheetData><autoFilter ref="A1:{}{}"/></worksheet>"#,
+        column(HEADERS.len() - 1),
+        articles.len() + 1
+    ));
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let parts: [(&str, &[u8]); 6] = [
+        ("[Content_Types].xml", CONTENT_TYPES.as_bytes()),
+        ("_rels/.rels", ROOT_RELS.as_bytes()),
+        ("xl/workbook.xml", WORKBOOK.as_bytes()),
+        ("xl/_rels/workbook.xml.rels", WORKBOOK_RELS.as_bytes()),
+        ("xl/styles.xml", STYLES.as_bytes()),
+        ("xl/worksheets/sheet1.xml", sheet.as_bytes()),
+    ];
+    for (name, data) in parts {
+        zip.start_file(name, options)?;
+        zip.write_all(data)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn article(title: &str, insight: Option<&str>) -> InsightArticle {
+        InsightArticle {
+            id: uuid::Uuid::nil(),
+            task_id: uuid::Uuid::nil(),
+            title: title.to_string(),
+            url: "https://mp.weixin.qq.com/s/abc".to_string(),
+            account_name: Some("科技观察".to_string()),
+            account_fakeid: None,
+            publish_time: Some(1_700_000_000),
+            similarity: Some(0.876543),
+            insight: insight.map(str::to_string),
+            relevance_score: None,
+            created_at: 0,
+            chunk_similarity: None,
+            article_id: None,
+            duplicates_of: None,
+            feedback: None,
+            status: None,
+            status_checked_at: None,
+            author: Some("李雷".to_string()),
+            is_original: Some(true),
+            biz: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_csv_and_xlsx() {
+        let articles = vec![
+            article("AI, \"大模型\" 回顾", Some("要点一\n要点二")),
+            article("=HYPERLINK(\"x\")", None),
+        ];
+
+        let csv = to_csv(&articles);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(
+            lines[0],
+            "\u{feff}标题,公众号,作者,原创,发布日期,链接,相似度,洞察"
+        );
+        assert_eq!(
+            lines[1],
+            "\"AI, \"\"大模型\"\" 回顾\",科技观察,李雷,是,2023-11-15,https://mp.weixin.qq.com/s/abc,0.8765,\"要点一\n要点二\""
+        );
+        assert!(lines[2].starts_with("\"'=HYPERLINK(\"\"x\"\")\",科技观察"));
+        assert!(lines[2].ends_with(",0.8765,"));
+
+        let bytes = build_xlsx(&articles).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut sheet = String::new();
+        zip.by_name("xl/worksheets/sheet1.xml")
+            .unwrap()
+            .read_to_string(&mut sheet)
+            .unwrap();
+        assert!(sheet.contains(
+            r#"<c r="A2" t="inlineStr"><is><t xml:space="preserve">AI, "大模型" 回顾</t></is></c>"#
+        ));
+        assert!(sheet.contains(r#"<c r="G2"><v>0.8765</v></c>"#));
+        assert!(sheet.contains(r#"<autoFilter ref="A1:H3"/>"#));
+        assert!(zip.by_name("xl/styles.xml").is_ok());
+    }
+}
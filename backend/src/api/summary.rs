@@ -0,0 +1,445 @@
+//! Executive summary of an insight task
+//!
+//! Groups a task's collected articles into themes by shared terms, then asks the
+//! reasoning LLM for a structured report: overview, key trends, notable accounts
+//! and representative articles. Article references in the reply are resolved
+//! against the task's own articles, so links always point at collected URLs.
+//! The result is stored in `insight_tasks.summary` and returned by `get_task`.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{extract::State, Extension, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::{authorize_task, InsightArticle, InsightTask};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::llm::ChatRequest;
+use crate::AppState;
+
+/// Most articles fed into one summary
+const MAX_ARTICLES: i64 = 200;
+/// Share of an article's terms a theme must contain for the article to join it
+const CLUSTER_THRESHOLD: f64 = 0.3;
+/// Terms shared by more than this share of all articles are topic-wide, not thematic
+const COMMON_TERM_RATIO: f64 = 0.5;
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct SummarizeRequest {
+    pub task_id: Uuid,
+    /// "gemini" or "deepseek"; defaults to the task's reasoning provider
+    pub provider: Option<String>,
+    pub deepseek_api_key: Option<String>,
+    pub gemini_api_key: Option<String>,
+    /// Output language, defaults to the task's (`output_language`)
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArticleLink {
+    pub title: String,
+    pub url: String,
+    pub account_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trend {
+    pub title: String,
+    pub detail: String,
+    pub articles: Vec<ArticleLink>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotableAccount {
+    pub name: String,
+    pub fakeid: Option<String>,
+    pub article_count: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepresentativeArticle {
+    #[serde(flatten)]
+    pub article: ArticleLink,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Theme {
+    pub label: String,
+    pub articles: Vec<ArticleLink>,
+}
+
+/// Stored in `insight_tasks.summary`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExecutiveSummary {
+    pub overview: String,
+    pub key_trends: Vec<Trend>,
+    pub notable_accounts: Vec<NotableAccount>,
+    pub representative_articles: Vec<RepresentativeArticle>,
+    pub themes: Vec<Theme>,
+    pub provider: String,
+    pub article_count: usize,
+    pub created_at: i64,
+}
+
+/// Shape requested from the LLM; article references are 1-based numbers
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LlmSummary {
+    overview: String,
+    key_trends: Vec<LlmTrend>,
+    notable_accounts: Vec<LlmAccount>,
+    representative_articles: Vec<LlmArticle>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LlmTrend {
+    title: String,
+    detail: String,
+    articles: Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LlmAccount {
+    name: String,
+    reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LlmArticle {
+    index: usize,
+    reason: String,
+}
+
+// ============ Clustering ============
+
+/// Thematic terms of each article (title + insight), without topic-wide terms
+fn article_terms(articles: &[InsightArticle]) -> Vec<HashSet<String>> {
+    let mut terms: Vec<HashSet<String>> = articles
+        .iter()
+        .map(|a| {
+            let text = format!("{} {}", a.title, a.insight.as_deref().unwrap_or(""));
+            crate::fulltext::segment(&text)
+                .split(' ')
+                .filter(|t| t.chars().count() >= 2)
+                .map(String::from)
+                .collect()
+        })
+        .collect();
+
+    if terms.len() >= 4 {
+        let mut df: HashMap<&str, usize> = HashMap::new();
+        for set in &terms {
+            for t in set {
+                *df.entry(t.as_str()).or_default() += 1;
+            }
+        }
+        let limit = (terms.len() as f64 * COMMON_TERM_RATIO) as usize;
+        let common: HashSet<String> = df
+            .into_iter()
+            .filter(|(_, n)| *n > limit)
+            .map(|(t, _)| t.to_string())
+            .collect();
+        for set in &mut terms {
+            set.retain(|t| !common.contains(t));
+        }
+    }
+    terms
+}
+
+/// Greedy single-pass clustering: each article joins the theme covering the
+/// largest share of its terms (at least `CLUSTER_THRESHOLD`), else starts a new one.
+/// Returns member indices and a label of the theme's most frequent terms.
+fn cluster(terms: &[HashSet<String>]) -> Vec<(String, Vec<usize>)> {
+    let mut themes: Vec<(HashMap<&str, usize>, Vec<usize>)> = Vec::new();
+    for (i, set) in terms.iter().enumerate() {
+        let best = themes
+            .iter()
+            .enumerate()
+            .filter(|_| !set.is_empty())
+            .map(|(ti, (counts, _))| {
+                let shared = set
+                    .iter()
+                    .filter(|t| counts.contains_key(t.as_str()))
+                    .count();
+                (ti, shared as f64 / set.len() as f64)
+            })
+            .filter(|(_, score)| *score >= CLUSTER_THRESHOLD)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        let ti = match best {
+            Some((ti, _)) => ti,
+            None => {
+                themes.push((HashMap::new(), Vec::new()));
+                themes.len() - 1
+            }
+        };
+        for t in set {
+            *themes[ti].0.entry(t.as_str()).or_default() += 1;
+        }
+        themes[ti].1.push(i);
+    }
+
+    let mut result: Vec<(String, Vec<usize>)> = themes
+        .into_iter()
+        .map(|(counts, members)| {
+            let mut top: Vec<(&str, usize)> = counts.into_iter().collect();
+            top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            let label = top
+                .iter()
+                .take(3)
+                .map(|(t, _)| *t)
+                .collect::<Vec<_>>()
+                .join(" / ");
+            (label, members)
+        })
+        .collect();
+    result.sort_by_key(|(_, members)| std::cmp::Reverse(members.len()));
+    result
+}
+
+// ============ Prompt ============
+
+fn build_prompt(
+    task: &InsightTask,
+    articles: &[InsightArticle],
+    themes: &[(String, Vec<usize>)],
+    language: &str,
+) -> String {
+    let mut list = String::new();
+    for (i, a) in articles.iter().enumerate() {
+        list.push_str(&format!(
+            "[{}] {} ({})\n    Insight: {}\n",
+            i + 1,
+            a.title,
+            a.account_name.as_deref().unwrap_or("unknown"),
+            a.insight.as_deref().unwrap_or("-")
+        ));
+    }
+    let mut groups = String::new();
+    for (label, members) in themes {
+        let refs: Vec<String> = members.iter().map(|i| format!("[{}]", i + 1)).collect();
+        groups.push_str(&format!("- {}: {}\n", label, refs.join(" ")));
+    }
+
+    format!(
+        "You are writing an executive report on the research topic: {}\n\n\
+        Collected WeChat articles:\n{}\n\
+        Pre-computed theme groups (by shared terms, may be imperfect):\n{}\n\
+        Write the report in {}. Identify 3-6 key trends across the themes, the most \
+        notable accounts and 3-5 representative articles. Refer to articles ONLY by \
+        their numbers.\n\
+        Return JSON ONLY: {{ \"overview\": \"one paragraph\", \
+        \"key_trends\": [{{ \"title\": \"...\", \"detail\": \"2-3 sentences\", \"articles\": [1, 2] }}], \
+        \"notable_accounts\": [{{ \"name\": \"account name\", \"reason\": \"...\" }}], \
+        \"representative_articles\": [{{ \"index\": 1, \"reason\": \"...\" }}] }}",
+        task.prompt, list, groups, language
+    )
+}
+
+fn parse_reply(text: &str) -> Option<LlmSummary> {
+    let clean = text
+        .trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim();
+    serde_json::from_str(clean)
+        .ok()
+        .filter(|s: &LlmSummary| !s.overview.trim().is_empty())
+}
+
+fn link(a: &InsightArticle) -> ArticleLink {
+    ArticleLink {
+        title: a.title.clone(),
+        url: a.url.clone(),
+        account_name: a.account_name.clone(),
+    }
+}
+
+/// Resolve the LLM's article numbers and account names against the task's articles
+fn assemble(
+    reply: LlmSummary,
+    articles: &[InsightArticle],
+    themes: &[(String, Vec<usize>)],
+    provider: &str,
+) -> ExecutiveSummary {
+    let by_number = |n: usize| n.checked_sub(1).and_then(|i| articles.get(i));
+
+    let key_trends = reply
+        .key_trends
+        .into_iter()
+        .map(|t| Trend {
+            title: t.title,
+            detail: t.detail,
+            articles: t
+                .articles
+                .into_iter()
+                .filter_map(by_number)
+                .map(link)
+                .collect(),
+        })
+        .collect();
+
+    let notable_accounts = reply
+        .notable_accounts
+        .into_iter()
+        .map(|acc| {
+            let own: Vec<&InsightArticle> = articles
+                .iter()
+                .filter(|a| a.account_name.as_deref() == Some(acc.name.as_str()))
+                .collect();
+            NotableAccount {
+                fakeid: own.iter().find_map(|a| a.account_fakeid.clone()),
+                article_count: own.len(),
+                name: acc.name,
+                reason: acc.reason,
+            }
+        })
+        .collect();
+
+    let representative_articles = reply
+        .representative_articles
+        .into_iter()
+        .filter_map(|r| {
+            Some(RepresentativeArticle {
+                article: link(by_number(r.index)?),
+                reason: r.reason,
+            })
+        })
+        .collect();
+
+    ExecutiveSummary {
+        overview: reply.overview,
+        key_trends,
+        notable_accounts,
+        representative_articles,
+        themes: themes
+            .iter()
+            .map(|(label, members)| Theme {
+                label: label.clone(),
+                articles: members.iter().map(|&i| link(&articles[i])).collect(),
+            })
+            .collect(),
+        provider: provider.to_string(),
+        article_count: articles.len(),
+        created_at: chrono::Utc::now().timestamp(),
+    }
+}
+
+// ============ Handlers ============
+
+/// Generate and store the executive summary of a task
+pub async fn summarize_task(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<SummarizeRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    authorize_task(&state, &principal, req.task_id).await?;
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(req.task_id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY relevance_score DESC NULLS LAST, similarity DESC NULLS LAST LIMIT $2",
+    )
+    .bind(req.task_id)
+    .bind(MAX_ARTICLES)
+    .fetch_all(&state.db_pool)
+    .await?;
+    if articles.is_empty() {
+        return Err(AppError::BadRequest("任务没有可用于总结的文章".to_string()));
+    }
+
+    let configured: Option<String> = sqlx::query_scalar(
+        "SELECT config->>'reasoning_provider' FROM insight_task_state WHERE task_id = $1",
+    )
+    .bind(req.task_id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .flatten();
+    let provider = req
+        .provider
+        .or(configured)
+        .unwrap_or_else(|| "gemini".to_string());
+
+    let themes = cluster(&article_terms(&articles));
+    let language = req
+        .language
+        .unwrap_or_else(|| crate::llm::language_name(task.output_language()));
+    let prompt = build_prompt(&task, &articles, &themes, &language);
+
+    let config = crate::api::llm::keyed_config(
+        &provider,
+        req.deepseek_api_key.as_deref(),
+        req.gemini_api_key.as_deref(),
+    );
+    let llm = crate::api::llm::chat_provider(&state, &provider, config).await?;
+    let reply = llm
+        .chat(&ChatRequest {
+            prompt: &prompt,
+            temperature: Some(0.3),
+            max_tokens: Some(4000),
+            ..Default::default()
+        })
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Summary generation failed: {}", e)))?
+        .text;
+
+    let parsed = parse_reply(&reply).ok_or(AppError::BadGateway(
+        "Failed to parse summary from LLM response".to_string(),
+    ))?;
+    let summary = assemble(parsed, &articles, &themes, &provider);
+
+    sqlx::query("UPDATE insight_tasks SET summary = $1 WHERE id = $2")
+        .bind(serde_json::to_value(&summary).map_err(anyhow::Error::from)?)
+        .bind(req.task_id)
+        .execute(&state.db_pool)
+        .await?;
+    tracing::info!(
+        "[Summary] Task {}: {} articles, {} themes, {} trends",
+        req.task_id,
+        summary.article_count,
+        summary.themes.len(),
+        summary.key_trends.len()
+    );
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": summary
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster() {
+        let sets: Vec<HashSet<String>> = [
+            vec!["芯片", "出口", "管制"],
+            vec!["新能源", "电池", "储能"],
+            vec!["芯片", "管制", "光刻"],
+            vec![],
+            vec!["电池", "储能", "钠离子"],
+        ]
+        .into_iter()
+        .map(|v| v.into_iter().map(String::from).collect())
+        .collect();
+
+        let themes = cluster(&sets);
+        let members: Vec<&Vec<usize>> = themes.iter().map(|(_, m)| m).collect();
+        assert_eq!(members, vec![&vec![0, 2], &vec![1, 4], &vec![3]]);
+        assert!(themes[0].0.starts_with("管制 / 芯片"));
+    }
+}
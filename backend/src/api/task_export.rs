@@ -0,0 +1,266 @@
+//! Structured task export
+//!
+//! `GET /api/insight/:id/export.json` returns a task and its articles as one
+//! JSON document for notebooks and BI tools. The schema is versioned by
+//! `schema_version` and documented in `docs/LLM_CONFIG.md`; fields are only
+//! ever added within a version. With `?content=text` (or `markdown`) each
+//! article carries its cleaned body from the article cache; articles that are
+//! not cached get `content: null` rather than being fetched.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api::insight::{authorize_task, InsightArticle, InsightTask};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskExportQuery {
+    /// Inline cleaned content: "text" or "markdown"
+    pub content: Option<String>,
+    /// Serve as a file download (`<task id>.json`)
+    #[serde(default)]
+    pub download: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ContentFormat {
+    Text,
+    Markdown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskDocument {
+    pub schema_version: u32,
+    pub exported_at: i64,
+    pub task: TaskInfo,
+    pub articles: Vec<ArticleRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskInfo {
+    pub id: Uuid,
+    pub prompt: String,
+    pub status: String,
+    pub keywords: Vec<String>,
+    pub target_count: i32,
+    pub processed_count: i32,
+    pub output_language: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub completion_reason: Option<String>,
+    pub schedule_id: Option<Uuid>,
+    pub summary: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArticleRecord {
+    pub id: Uuid,
+    pub title: String,
+    pub url: String,
+    pub account_name: Option<String>,
+    pub account_fakeid: Option<String>,
+    pub publish_time: Option<i64>,
+    /// Byline parsed from the fetched page
+    pub author: Option<String>,
+    /// Declared 原创 by the account; `null` until the page has been fetched
+    pub is_original: Option<bool>,
+    pub biz: Option<String>,
+    pub similarity: Option<f64>,
+    pub relevance_score: Option<f64>,
+    pub chunk_similarity: Option<f64>,
+    pub insight: Option<String>,
+    pub feedback: Option<String>,
+    /// Online availability at the last check, e.g. "ok" or "deleted"
+    pub link_status: Option<String>,
+    pub duplicates_of: Option<Uuid>,
+    pub created_at: i64,
+    pub tags: Vec<String>,
+    /// User notes, oldest first
+    pub notes: Vec<String>,
+    /// Only with `?content=`; `null` when the article is not cached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<Option<ArticleContent>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArticleContent {
+    /// "text" or "markdown"
+    pub format: &'static str,
+    pub body: String,
+    pub author: Option<String>,
+    pub digest: Option<String>,
+    /// Characters of plain text, whatever the format
+    pub char_count: usize,
+}
+
+impl From<InsightTask> for TaskInfo {
+    fn from(task: InsightTask) -> Self {
+        TaskInfo {
+            output_language: task.output_language().to_string(),
+            id: task.id,
+            prompt: task.prompt,
+            status: task.status,
+            keywords: task.keywords,
+            target_count: task.target_count,
+            processed_count: task.processed_count,
+            created_at: task.created_at,
+            updated_at: task.updated_at,
+            completion_reason: task.completion_reason,
+            schedule_id: task.schedule_id,
+            summary: task.summary,
+        }
+    }
+}
+
+impl From<InsightArticle> for ArticleRecord {
+    fn from(a: InsightArticle) -> Self {
+        ArticleRecord {
+            id: a.id,
+            title: a.title,
+            url: a.url,
+            account_name: a.account_name,
+            account_fakeid: a.account_fakeid,
+            publish_time: a.publish_time,
+            author: a.author,
+            is_original: a.is_original,
+            biz: a.biz,
+            similarity: a.similarity,
+            relevance_score: a.relevance_score,
+            chunk_similarity: a.chunk_similarity,
+            insight: a.insight,
+            feedback: a.feedback,
+            link_status: a.status,
+            duplicates_of: a.duplicates_of,
+            created_at: a.created_at,
+            tags: a.tags,
+            notes: Vec::new(),
+            content: None,
+        }
+    }
+}
+
+fn parse_content(value: Option<&str>) -> Result<Option<ContentFormat>, AppError> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some("text") => Ok(Some(ContentFormat::Text)),
+        Some("markdown") => Ok(Some(ContentFormat::Markdown)),
+        Some(other) => Err(AppError::BadRequest(format!(
+            "未知的content格式: {} (可选 text, markdown)",
+            other
+        ))),
+    }
+}
+
+fn article_content(html: &str, format: ContentFormat) -> ArticleContent {
+    let extracted = crate::content::extract::extract(html);
+    let text = extracted.text();
+    let (format, body) = match format {
+        ContentFormat::Text => ("text", text.clone()),
+        ContentFormat::Markdown => ("markdown", crate::content::markdown::to_markdown(html)),
+    };
+    ArticleContent {
+        format,
+        body,
+        author: extracted.author,
+        digest: extracted.digest,
+        char_count: text.chars().count(),
+    }
+}
+
+// ============ Handlers ============
+
+/// A task with its articles as a versioned JSON document
+pub async fn export_json(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<TaskExportQuery>,
+) -> Result<Response, AppError> {
+    authorize_task(&state, &principal, id).await?;
+    let content = parse_content(query.content.as_deref())?;
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or(AppError::NotFound("Task not found".to_string()))?;
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 ORDER BY similarity DESC NULLS LAST",
+    )
+    .bind(id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let annotations = crate::api::annotations::task_annotations(&state.db_pool, id).await?;
+
+    let mut records = Vec::with_capacity(articles.len());
+    for article in articles {
+        let mut record = ArticleRecord::from(article);
+        record.notes = annotations
+            .iter()
+            .filter(|n| n.article_id == record.id)
+            .map(|n| n.note.clone())
+            .collect();
+        if let Some(format) = content {
+            let html =
+                crate::repository::articles::content(&state.db_pool, None, Some(&record.url))
+                    .await?;
+            record.content = Some(html.map(|html| article_content(&html, format)));
+        }
+        records.push(record);
+    }
+
+    let document = TaskDocument {
+        schema_version: SCHEMA_VERSION,
+        exported_at: chrono::Utc::now().timestamp(),
+        task: task.into(),
+        articles: records,
+    };
+    let mut response = Json(document).into_response();
+    if query.download {
+        let disposition = format!("attachment; filename=\"{}.json\"", id);
+        if let Ok(value) = HeaderValue::from_str(&disposition) {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_DISPOSITION, value);
+        }
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_article_content() {
+        assert!(parse_content(None).unwrap().is_none());
+        assert_eq!(
+            parse_content(Some("markdown")).unwrap(),
+            Some(ContentFormat::Markdown)
+        );
+        assert!(parse_content(Some("html")).is_err());
+
+        let html = r#"<html><head><meta name="author" content="张三"></head><body>
+            <h1 class="rich_media_title">标题</h1>
+            <div id="js_content"><h2>小节</h2><p>第一段</p></div>
+        </body></html>"#;
+        let text = article_content(html, ContentFormat::Text);
+        assert_eq!(text.format, "text");
+        assert!(text.body.contains("第一段"));
+        assert_eq!(text.author.as_deref(), Some("张三"));
+        let markdown = article_content(html, ContentFormat::Markdown);
+        assert!(markdown.body.contains("## 小节"));
+        assert_eq!(markdown.char_count, text.char_count);
+    }
+}
@@ -0,0 +1,313 @@
+//! Task templates
+//!
+//! A template is a named, reusable set of `CreateTaskRequest` fields (providers,
+//! speed, dedup threshold, target count, specific account, ...). Starting a task
+//! from a template merges the call's fields over the saved ones, so a new task
+//! only needs a prompt. API keys are never stored in a template.
+
+use axum::{
+    extract::{Path, State},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::api::insight::{create_task, CreateTaskRequest};
+use crate::auth::Principal;
+use crate::error::AppError;
+use crate::AppState;
+
+/// Request fields kept out of stored settings
+const SECRET_FIELDS: &[&str] = &[
+    "deepseek_api_key",
+    "gemini_api_key",
+    "openai_compatible_api_key",
+];
+
+// ============ Types ============
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Any `CreateTaskRequest` fields; `prompt` is optional here
+    #[serde(flatten)]
+    pub settings: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Fields to set; `null` removes a saved field
+    #[serde(flatten)]
+    pub settings: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplateIdRequest {
+    pub id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LaunchTemplateRequest {
+    pub id: Uuid,
+    /// `CreateTaskRequest` fields overriding the template (usually `prompt` and API keys)
+    #[serde(flatten)]
+    pub overrides: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct InsightTemplate {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub owner_id: Option<Uuid>,
+    pub settings: Value,
+    pub use_count: i32,
+    pub last_used_at: Option<i64>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+const TEMPLATE_COLUMNS: &str =
+    "id, name, description, owner_id, settings, use_count, last_used_at, created_at, updated_at";
+
+// ============ Helpers ============
+
+/// `settings` with `overrides` applied; `null` overrides remove a field
+fn merge_settings(
+    settings: &Map<String, Value>,
+    overrides: Map<String, Value>,
+) -> Map<String, Value> {
+    let mut merged = settings.clone();
+    for (key, value) in overrides {
+        if value.is_null() {
+            merged.remove(&key);
+        } else {
+            merged.insert(key, value);
+        }
+    }
+    merged
+}
+
+/// Settings as a task request; errors on fields of the wrong type
+fn to_task_request(settings: Map<String, Value>) -> Result<CreateTaskRequest, AppError> {
+    let mut fields = settings;
+    fields
+        .entry("prompt")
+        .or_insert_with(|| Value::String(String::new()));
+    serde_json::from_value(Value::Object(fields))
+        .map_err(|e| AppError::BadRequest(format!("无效的模板配置: {}", e)))
+}
+
+/// Validated settings without secrets
+fn clean_settings(mut settings: Map<String, Value>) -> Result<Map<String, Value>, AppError> {
+    for field in SECRET_FIELDS {
+        settings.remove(*field);
+    }
+    settings.retain(|_, v| !v.is_null());
+    to_task_request(settings.clone())?;
+    Ok(settings)
+}
+
+/// Template `id`, or NotFound when it is missing or not visible to `principal`
+async fn load_template(
+    state: &AppState,
+    principal: &Principal,
+    id: Uuid,
+) -> Result<InsightTemplate, AppError> {
+    sqlx::query_as::<_, InsightTemplate>(&format!(
+        "SELECT {} FROM insight_templates WHERE id = $1",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .filter(|t| principal.can_access(t.owner_id))
+    .ok_or(AppError::NotFound("Template not found".to_string()))
+}
+
+fn settings_map(template: &InsightTemplate) -> Map<String, Value> {
+    template.settings.as_object().cloned().unwrap_or_default()
+}
+
+// ============ Handlers ============
+
+/// Save a named task configuration
+pub async fn create_template(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<CreateTemplateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("name不能为空".to_string()));
+    }
+    let settings = clean_settings(req.settings)?;
+
+    let id = Uuid::new_v4();
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        r#"
+        INSERT INTO insight_templates (id, name, description, owner_id, settings, use_count, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, 0, $6, $6)
+        "#,
+    )
+    .bind(id)
+    .bind(name)
+    .bind(&req.description)
+    .bind(principal.user_id)
+    .bind(Value::Object(settings))
+    .bind(now)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "success": true, "id": id })))
+}
+
+/// List the caller's templates (all of them for admins), most recently used first
+pub async fn list_templates(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let templates = sqlx::query_as::<_, InsightTemplate>(&format!(
+        "SELECT {} FROM insight_templates WHERE $1 OR owner_id IS NOT DISTINCT FROM $2 ORDER BY last_used_at DESC NULLS LAST, created_at DESC",
+        TEMPLATE_COLUMNS
+    ))
+    .bind(principal.is_admin())
+    .bind(principal.user_id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": templates
+    })))
+}
+
+/// Get one template
+pub async fn get_template(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let template = load_template(&state, &principal, id).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": template
+    })))
+}
+
+/// Rename a template or change its settings
+pub async fn update_template(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<UpdateTemplateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let template = load_template(&state, &principal, req.id).await?;
+    let name = req.name.as_deref().map(str::trim);
+    if name == Some("") {
+        return Err(AppError::BadRequest("name不能为空".to_string()));
+    }
+    let settings = clean_settings(merge_settings(&settings_map(&template), req.settings))?;
+
+    sqlx::query(
+        r#"
+        UPDATE insight_templates SET
+            name = COALESCE($1, name),
+            description = COALESCE($2, description),
+            settings = $3,
+            updated_at = $4
+        WHERE id = $5
+        "#,
+    )
+    .bind(name)
+    .bind(&req.description)
+    .bind(Value::Object(settings))
+    .bind(chrono::Utc::now().timestamp())
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Delete a template. Tasks created from it are kept.
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<TemplateIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    load_template(&state, &principal, req.id).await?;
+
+    sqlx::query("DELETE FROM insight_templates WHERE id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+/// Create and start a task from a template
+pub async fn launch_template(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    Json(req): Json<LaunchTemplateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let template = load_template(&state, &principal, req.id).await?;
+    let task = to_task_request(merge_settings(&settings_map(&template), req.overrides))?;
+    if task.prompt.trim().is_empty() {
+        return Err(AppError::BadRequest("prompt不能为空".to_string()));
+    }
+
+    let Json(created) = create_task(State(state.clone()), Extension(principal), Json(task)).await?;
+
+    let now = chrono::Utc::now().timestamp();
+    sqlx::query(
+        "UPDATE insight_templates SET use_count = use_count + 1, last_used_at = $1 WHERE id = $2",
+    )
+    .bind(now)
+    .bind(req.id)
+    .execute(&state.db_pool)
+    .await?;
+
+    tracing::info!("[Template] '{}' started task {}", template.name, created.id);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "task_id": created.id,
+        "warning": created.warning
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_merge_and_clean() {
+        let saved = serde_json::json!({
+            "keyword_provider": "deepseek",
+            "target_count": 50,
+            "gemini_api_key": "secret"
+        });
+        let settings = clean_settings(saved.as_object().cloned().unwrap()).unwrap();
+        assert!(!settings.contains_key("gemini_api_key"));
+
+        let overrides = serde_json::json!({ "prompt": "AI芯片", "target_count": null });
+        let task = to_task_request(merge_settings(
+            &settings,
+            overrides.as_object().cloned().unwrap(),
+        ))
+        .unwrap();
+        assert_eq!(task.prompt, "AI芯片");
+        assert_eq!(task.keyword_provider.as_deref(), Some("deepseek"));
+        assert_eq!(task.target_count, None);
+
+        let bad = serde_json::json!({ "target_count": "many" });
+        assert!(clean_settings(bad.as_object().cloned().unwrap()).is_err());
+    }
+}
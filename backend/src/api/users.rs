@@ -0,0 +1,97 @@
+//! User management (admin only)
+//!
+//! Each user gets a random API token, returned once on creation and stored
+//! only as a hash. Deleting a user leaves their tasks, sessions and accounts
+//! in place without an owner, visible to admins and the shared env tokens.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::{self, Role};
+use crate::error::AppError;
+use crate::AppState;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct User {
+    pub id: Uuid,
+    pub name: String,
+    pub role: String,
+    pub created_at: i64,
+    pub last_seen_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub name: String,
+    /// Defaults to `write`
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteUserRequest {
+    pub id: Uuid,
+}
+
+pub async fn list_users(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let users = sqlx::query_as::<_, User>(
+        "SELECT id, name, role, created_at, last_seen_at FROM users ORDER BY created_at",
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+    Ok(Json(serde_json::json!({ "success": true, "data": users })))
+}
+
+/// Create a user and return their token
+pub async fn create_user(
+    State(state): State<AppState>,
+    Json(req): Json<CreateUserRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("用户名不能为空".to_string()));
+    }
+    let role = req.role.unwrap_or(Role::Write);
+    let token = auth::generate_token();
+    let id = Uuid::new_v4();
+
+    let inserted = sqlx::query(
+        "INSERT INTO users (id, name, token_hash, role, created_at) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (name) DO NOTHING",
+    )
+    .bind(id)
+    .bind(name)
+    .bind(auth::hash_token(&token))
+    .bind(role.as_str())
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db_pool)
+    .await?
+    .rows_affected();
+    if inserted == 0 {
+        return Err(AppError::BadRequest(format!("用户 {} 已存在", name)));
+    }
+    auth::refresh(&state.db_pool).await?;
+    tracing::info!("Created {} user {}", role.as_str(), name);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "data": { "id": id, "name": name, "role": role, "token": token }
+    })))
+}
+
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Json(req): Json<DeleteUserRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let deleted = sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(req.id)
+        .execute(&state.db_pool)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    auth::refresh(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
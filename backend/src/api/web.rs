@@ -6,17 +6,20 @@ use axum::{
     body::Body,
     extract::State,
     http::{header, HeaderMap, Response, StatusCode},
-    Json,
+    Extension, Json,
 };
 use reqwest::header::{COOKIE, SET_COOKIE};
 use serde::{Deserialize, Serialize};
 
+use crate::auth::Principal;
 use crate::cookie::AccountCookie;
+use crate::crawl::{self, Priority};
 use crate::error::AppError;
+use crate::ratelimit;
+use crate::request_log;
+use crate::wechat::client::{with_mp_headers, MpClient, MpError};
 use crate::AppState;
 
-const WECHAT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
-
 // ============ Login: Session ============
 
 #[allow(dead_code)]
@@ -40,9 +43,8 @@ pub async fn start_login_session(
     let cookie = get_cookies_from_request(&headers);
 
     let client = reqwest::Client::new();
-    let mut request = client
-        .post("https://mp.weixin.qq.com/cgi-bin/bizlogin")
-        .query(&[("action", "startlogin")])
+    let mut request =
+        with_mp_headers(client.post("https://mp.weixin.qq.com/cgi-bin/bizlogin")).query(&[("action", "startlogin")])
         .form(&[
             ("userlang", "zh_CN"),
             ("redirect_url", ""),
@@ -52,10 +54,7 @@ pub async fn start_login_session(
             ("lang", "zh_CN"),
             ("f", "json"),
             ("ajax", "1"),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("Origin", "https://mp.weixin.qq.com")
-        .header("User-Agent", WECHAT_USER_AGENT);
+        ]);
 
     if let Some(c) = cookie {
         request = request.header(COOKIE, c);
@@ -90,15 +89,11 @@ pub async fn get_qrcode(headers: HeaderMap) -> Result<Response<Body>, AppError>
     let cookie = get_cookies_from_request(&headers);
 
     let client = reqwest::Client::new();
-    let mut request = client
-        .get("https://mp.weixin.qq.com/cgi-bin/scanloginqrcode")
-        .query(&[
+    let mut request =
+        with_mp_headers(client.get("https://mp.weixin.qq.com/cgi-bin/scanloginqrcode")).query(&[
             ("action", "getqrcode"),
             ("random", &chrono::Utc::now().timestamp_millis().to_string()),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("Origin", "https://mp.weixin.qq.com")
-        .header("User-Agent", WECHAT_USER_AGENT);
+        ]);
 
     if let Some(c) = cookie {
         request = request.header(COOKIE, c);
@@ -143,18 +138,14 @@ pub async fn check_scan(headers: HeaderMap) -> Result<Json<serde_json::Value>, A
     let cookie = get_cookies_from_request(&headers);
 
     let client = reqwest::Client::new();
-    let mut request = client
-        .get("https://mp.weixin.qq.com/cgi-bin/scanloginqrcode")
-        .query(&[
+    let mut request =
+        with_mp_headers(client.get("https://mp.weixin.qq.com/cgi-bin/scanloginqrcode")).query(&[
             ("action", "ask"),
             ("token", ""),
             ("lang", "zh_CN"),
             ("f", "json"),
             ("ajax", "1"),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("Origin", "https://mp.weixin.qq.com")
-        .header("User-Agent", WECHAT_USER_AGENT);
+        ]);
 
     if let Some(c) = cookie {
         request = request.header(COOKIE, c);
@@ -183,14 +174,14 @@ pub struct BizLoginResponse {
 /// Complete login and get auth key
 pub async fn biz_login(
     State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
     headers: HeaderMap,
 ) -> Result<Response<Body>, AppError> {
     let cookie = get_cookies_from_request(&headers);
 
     let client = reqwest::Client::new();
-    let mut request = client
-        .post("https://mp.weixin.qq.com/cgi-bin/bizlogin")
-        .query(&[("action", "login")])
+    let mut request =
+        with_mp_headers(client.post("https://mp.weixin.qq.com/cgi-bin/bizlogin")).query(&[("action", "login")])
         .form(&[
             ("userlang", "zh_CN"),
             ("redirect_url", ""),
@@ -202,10 +193,7 @@ pub async fn biz_login(
             ("lang", "zh_CN"),
             ("f", "json"),
             ("ajax", "1"),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("Origin", "https://mp.weixin.qq.com")
-        .header("User-Agent", WECHAT_USER_AGENT);
+        ]);
 
     if let Some(c) = cookie {
         request = request.header(COOKIE, c);
@@ -240,7 +228,7 @@ pub async fn biz_login(
 
         state
             .cookie_store
-            .set_cookie(&auth_key, &account_cookie)
+            .set_cookie(&auth_key, &account_cookie, principal.user_id)
             .await?;
 
         // Get account info
@@ -291,22 +279,8 @@ pub struct MpInfo {
 }
 
 async fn get_mp_info_internal(state: &AppState, auth_key: &str) -> Option<MpInfo> {
-    let account_cookie = state.cookie_store.get_cookie(auth_key).await.ok()??;
-    let cookie_str = account_cookie.to_cookie_header();
-    let token = account_cookie.token;
-
-    let client = reqwest::Client::new();
-    let response = client
-        .get("https://mp.weixin.qq.com/cgi-bin/home")
-        .query(&[("t", "home/index"), ("token", &token), ("lang", "zh_CN")])
-        .header(COOKIE, &cookie_str)
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("User-Agent", WECHAT_USER_AGENT)
-        .send()
-        .await
-        .ok()?;
-
-    let html = response.text().await.ok()?;
+    let client = MpClient::for_auth_key(state, auth_key).await.ok()?;
+    let html = client.home_html().await.ok()?;
 
     // Parse nick_name and head_img from HTML
     let nick_name = regex::Regex::new(r#"nick_name\s*:\s*["']([^"']+)["']"#)
@@ -356,6 +330,55 @@ pub async fn get_mp_info(
     })))
 }
 
+/// Keepalive state of the caller's stored sessions (all for admins);
+/// `current` marks the one in use
+pub async fn session_health(
+    State(state): State<AppState>,
+    Extension(principal): Extension<Principal>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let current = crate::proxy::get_auth_key_from_headers(&headers);
+    let now = chrono::Utc::now().timestamp();
+    let interval = crate::keepalive::interval_secs() as i64;
+
+    let sessions: Vec<serde_json::Value> = state
+        .cookie_store
+        .health(principal.is_admin(), principal.user_id)
+        .await?
+        .into_iter()
+        .map(|s| {
+            let status = if s.expires_at <= now {
+                "expired"
+            } else if s.check_failures > 0 {
+                "unverified"
+            } else if s.expires_at - now < 2 * interval.max(3600) {
+                "expiring"
+            } else {
+                "healthy"
+            };
+            serde_json::json!({
+                "current": current.as_deref() == Some(s.auth_key.as_str()),
+                // Auth keys are credentials; only a prefix to tell sessions apart
+                "auth_key": s.auth_key.chars().take(8).collect::<String>(),
+                "status": status,
+                "created_at": s.created_at,
+                "expires_at": s.expires_at,
+                "remaining_secs": (s.expires_at - now).max(0),
+                "last_checked_at": s.last_checked_at,
+                "last_ok_at": s.last_ok_at,
+                "last_error": s.last_error,
+                "check_failures": s.check_failures,
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "keepalive_interval_secs": interval,
+        "sessions": sessions,
+    })))
+}
+
 /// Logout
 pub async fn logout(_headers: HeaderMap) -> Json<serde_json::Value> {
     // Just return success - client should clear auth-key cookie
@@ -373,6 +396,22 @@ fn get_cookies_from_request(headers: &HeaderMap) -> Option<String> {
         .map(|s| s.to_string())
 }
 
+/// Pass the MP body through; login and budget failures become MP-style bodies
+fn mp_json_response(
+    result: Result<serde_json::Value, MpError>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match result {
+        Ok(json) => Ok(Json(json)),
+        Err(MpError::NotLoggedIn) => Ok(Json(serde_json::json!({
+            "base_resp": {"ret": -1, "err_msg": "认证信息无效"}
+        }))),
+        Err(MpError::OverBudget(retry_after)) => {
+            Ok(Json(ratelimit::over_budget_response(retry_after)))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
 // ============ Misc: Status ============
 
 /// Get proxy status from external service
@@ -401,12 +440,9 @@ pub async fn misc_accountname(
         .map(|s| s.to_string())
         .unwrap_or(query.url);
 
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "web.accountname").await;
     let client = reqwest::Client::new();
-    let html = client
-        .get(&url)
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("Origin", "https://mp.weixin.qq.com")
-        .header("User-Agent", WECHAT_USER_AGENT)
+    let html = with_mp_headers(client.get(&url))
         .send()
         .await?
         .text()
@@ -423,6 +459,47 @@ pub async fn misc_accountname(
     Ok(name)
 }
 
+// ============ Misc: Rate Limit ============
+
+/// Current request budget per session and endpoint
+pub async fn misc_ratelimit(headers: HeaderMap) -> Json<serde_json::Value> {
+    let current = crate::proxy::get_auth_key_from_headers(&headers)
+        .map(|key| crawl::session_lane(Some(&key)));
+    Json(serde_json::json!({
+        "success": true,
+        "current_session": current,
+        "data": ratelimit::status()
+    }))
+}
+
+// ============ Misc: Request Log ============
+
+/// Outbound MP requests, newest first
+pub async fn misc_requestlog(
+    State(state): State<AppState>,
+    axum::extract::Query(filter): axum::extract::Query<request_log::LogFilter>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (rows, total) = request_log::list(&state.db_pool, &filter).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "total": total,
+        "data": rows
+    })))
+}
+
+/// Outbound MP request counts per time bucket, session and endpoint
+pub async fn misc_requestlog_stats(
+    State(state): State<AppState>,
+    axum::extract::Query(filter): axum::extract::Query<request_log::LogFilter>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let (bucket_secs, rows) = request_log::stats(&state.db_pool, &filter).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "bucket_secs": bucket_secs,
+        "data": rows
+    })))
+}
+
 // ============ Misc: Comment ============
 
 #[derive(Debug, Deserialize)]
@@ -438,9 +515,9 @@ pub struct CommentQuery {
 pub async fn misc_comment(
     axum::extract::Query(query): axum::extract::Query<CommentQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "web.comment").await;
     let client = reqwest::Client::new();
-    let response = client
-        .get("https://mp.weixin.qq.com/mp/appmsg_comment")
+    let response = with_mp_headers(client.get("https://mp.weixin.qq.com/mp/appmsg_comment"))
         .query(&[
             ("action", "getcomment"),
             ("__biz", &query.__biz),
@@ -451,8 +528,6 @@ pub async fn misc_comment(
             ("limit", "1000"),
             ("f", "json"),
         ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("User-Agent", WECHAT_USER_AGENT)
         .send()
         .await?;
 
@@ -475,56 +550,20 @@ pub async fn mp_searchbiz(
     headers: HeaderMap,
     axum::extract::Query(query): axum::extract::Query<SearchBizQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let auth_key = crate::proxy::get_auth_key_from_headers(&headers);
-
-    let token = if let Some(key) = &auth_key {
-        state.cookie_store.get_token(key).await.ok().flatten()
-    } else {
-        None
-    };
-
-    let token = match token {
-        Some(t) => t,
-        None => {
-            return Ok(Json(serde_json::json!({
-                "base_resp": {"ret": -1, "err_msg": "认证信息无效"}
-            })));
+    let result = match MpClient::from_headers(&state, &headers).await {
+        Ok(client) => {
+            client
+                .search_biz(
+                    &query.keyword,
+                    query.begin.unwrap_or(0),
+                    query.size.unwrap_or(5),
+                    Priority::Interactive,
+                )
+                .await
         }
+        Err(e) => Err(e),
     };
-
-    let begin = query.begin.unwrap_or(0);
-    let size = query.size.unwrap_or(5);
-
-    let account_cookie = if let Some(key) = &auth_key {
-        state.cookie_store.get_cookie(key).await.ok().flatten()
-    } else {
-        None
-    };
-    let cookie_str = account_cookie.map(|c| c.to_cookie_header());
-
-    let client = reqwest::Client::new();
-    let mut request = client
-        .get("https://mp.weixin.qq.com/cgi-bin/searchbiz")
-        .query(&[
-            ("action", "search_biz"),
-            ("begin", &begin.to_string()),
-            ("count", &size.to_string()),
-            ("query", &query.keyword),
-            ("token", &token),
-            ("lang", "zh_CN"),
-            ("f", "json"),
-            ("ajax", "1"),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("User-Agent", WECHAT_USER_AGENT);
-
-    if let Some(cookie) = cookie_str {
-        request = request.header(COOKIE, cookie);
-    }
-
-    let response = request.send().await?;
-    let json: serde_json::Value = response.json().await?;
-    Ok(Json(json))
+    mp_json_response(result)
 }
 
 // ============ MP: App Msg Publish ============
@@ -543,61 +582,21 @@ pub async fn mp_appmsgpublish(
     headers: HeaderMap,
     axum::extract::Query(query): axum::extract::Query<AppMsgPublishQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let auth_key = crate::proxy::get_auth_key_from_headers(&headers);
-
-    let token = if let Some(key) = &auth_key {
-        state.cookie_store.get_token(key).await.ok().flatten()
-    } else {
-        None
-    };
-
-    let token = match token {
-        Some(t) => t,
-        None => {
-            return Ok(Json(serde_json::json!({
-                "base_resp": {"ret": -1, "err_msg": "认证信息无效"}
-            })));
+    let result = match MpClient::from_headers(&state, &headers).await {
+        Ok(client) => {
+            client
+                .appmsgpublish(
+                    &query.fakeid,
+                    query.begin.unwrap_or(0),
+                    query.size.unwrap_or(5),
+                    query.keyword.as_deref().unwrap_or(""),
+                    Priority::Interactive,
+                )
+                .await
         }
+        Err(e) => Err(e),
     };
-
-    let begin = query.begin.unwrap_or(0);
-    let size = query.size.unwrap_or(5);
-
-    let account_cookie = if let Some(key) = &auth_key {
-        state.cookie_store.get_cookie(key).await.ok().flatten()
-    } else {
-        None
-    };
-    let cookie_str = account_cookie.map(|c| c.to_cookie_header());
-
-    let client = reqwest::Client::new();
-    let mut request = client
-        .get("https://mp.weixin.qq.com/cgi-bin/appmsgpublish")
-        .query(&[
-            ("sub", "list"),
-            ("search_field", "null"),
-            ("begin", &begin.to_string()),
-            ("count", &size.to_string()),
-            ("query", query.keyword.as_deref().unwrap_or("")),
-            ("fakeid", &query.fakeid),
-            ("type", "101_1"),
-            ("free_publish_type", "1"),
-            ("sub_action", "list_ex"),
-            ("token", &token),
-            ("lang", "zh_CN"),
-            ("f", "json"),
-            ("ajax", "1"),
-        ])
-        .header("Referer", "https://mp.weixin.qq.com/")
-        .header("User-Agent", WECHAT_USER_AGENT);
-
-    if let Some(cookie) = cookie_str {
-        request = request.header(COOKIE, cookie);
-    }
-
-    let response = request.send().await?;
-    let json: serde_json::Value = response.json().await?;
-    Ok(Json(json))
+    mp_json_response(result)
 }
 
 // ============ MP: App Msg Album ============
@@ -636,10 +635,9 @@ pub async fn mp_appmsgalbum_proxy(
     }
 
     // Usually this endpoint is public, we just proxy it
-    let response = client
-        .get("https://mp.weixin.qq.com/mp/appmsgalbum")
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, Priority::Interactive, "web.appmsgalbum").await;
+    let response = with_mp_headers(client.get("https://mp.weixin.qq.com/mp/appmsgalbum"))
         .query(&req_query)
-        .header("User-Agent", WECHAT_USER_AGENT)
         .send()
         .await?;
 
@@ -0,0 +1,570 @@
+//! Archive availability verification
+//!
+//! Periodically samples archived article links, checks whether the online copy
+//! still resolves, and records the outcome in `article_link_checks`.
+//! Takedowns and significant edits are compared against the local snapshot and
+//! raised as alerts (`archive_alerts`, plus an optional webhook). Live pages
+//! whose text changed are also kept as article versions.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use similar::TextDiff;
+use sqlx::PgPool;
+
+use crate::repository::articles::{self, Freshness};
+use crate::wechat::client::WECHAT_USER_AGENT;
+
+/// Default interval between verification rounds (6 hours)
+const DEFAULT_INTERVAL_SECS: u64 = 6 * 60 * 60;
+/// Default number of links checked per round
+const DEFAULT_SAMPLE_SIZE: i64 = 20;
+/// Text similarity below which a live article counts as significantly changed
+const CHANGE_ALERT_RATIO: f32 = 0.9;
+/// Upper bound on diff text stored with an alert
+const MAX_DIFF_CHARS: usize = 20_000;
+
+lazy_static! {
+    static ref SCRIPT_STYLE_RE: Regex = Regex::new(r"(?is)<(script|style)[^>]*>.*?</(script|style)>").unwrap();
+    static ref BLOCK_END_RE: Regex = Regex::new(r"(?i)<br\s*/?>|</(p|div|section|h[1-6]|li|tr|blockquote)>").unwrap();
+    static ref TAG_RE: Regex = Regex::new(r"(?s)<[^>]+>").unwrap();
+}
+
+/// Availability status of an online article
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// Article page renders normally
+    Ok,
+    /// "该内容已被发布者删除"
+    Deleted,
+    /// Removed by the platform ("此内容因违规无法查看", complaints, etc.)
+    Violation,
+    /// "参数错误" or malformed link
+    Invalid,
+    /// HTTP 404
+    NotFound,
+    /// Network error or unexpected page
+    Error,
+}
+
+impl LinkStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Deleted => "deleted",
+            LinkStatus::Violation => "violation",
+            LinkStatus::Invalid => "invalid",
+            LinkStatus::NotFound => "not_found",
+            LinkStatus::Error => "error",
+        }
+    }
+
+    /// Whether the online copy is gone for good (as opposed to a transient error)
+    pub fn is_vanished(&self) -> bool {
+        matches!(
+            self,
+            LinkStatus::Deleted | LinkStatus::Violation | LinkStatus::Invalid | LinkStatus::NotFound
+        )
+    }
+}
+
+/// Status strings that count as "vanished" in SQL filters
+pub const VANISHED_STATUSES: [&str; 4] = ["deleted", "violation", "invalid", "not_found"];
+
+/// Result of checking a single link
+#[derive(Debug)]
+pub struct LinkCheck {
+    pub status: LinkStatus,
+    pub http_status: Option<i32>,
+    pub detail: Option<String>,
+    /// Page body, empty on network errors
+    pub body: String,
+}
+
+/// Classify a WeChat article page by HTTP status and body markers
+pub fn classify_article_page(http_status: u16, body: &str) -> (LinkStatus, Option<String>) {
+    if http_status == 404 {
+        return (LinkStatus::NotFound, None);
+    }
+
+    const DELETED_MARKERS: [&str; 3] = ["该内容已被发布者删除", "内容已被删除", "该公众号已迁移"];
+    const VIOLATION_MARKERS: [&str; 4] = [
+        "此内容因违规无法查看",
+        "此内容被投诉且经审核涉嫌侵权",
+        "该内容已被封禁",
+        "涉嫌违反相关法律法规和政策",
+    ];
+
+    for marker in DELETED_MARKERS {
+        if body.contains(marker) {
+            return (LinkStatus::Deleted, Some(marker.to_string()));
+        }
+    }
+    for marker in VIOLATION_MARKERS {
+        if body.contains(marker) {
+            return (LinkStatus::Violation, Some(marker.to_string()));
+        }
+    }
+    if body.contains("参数错误") && !body.contains("id=\"js_content\"") {
+        return (LinkStatus::Invalid, Some("参数错误".to_string()));
+    }
+
+    if (200..300).contains(&http_status)
+        && (body.contains("id=\"js_content\"") || body.contains("rich_media_content"))
+    {
+        return (LinkStatus::Ok, None);
+    }
+
+    (
+        LinkStatus::Error,
+        Some(format!("Unrecognized page (HTTP {})", http_status)),
+    )
+}
+
+/// Fetch and classify a single article URL
+pub async fn check_url(client: &reqwest::Client, url: &str) -> LinkCheck {
+    let _permit = crate::crawl::acquire(
+        crate::crawl::ARTICLE_LANE,
+        crate::crawl::Priority::Background,
+        "archive.verify",
+    )
+    .await;
+    let resp = client
+        .get(url)
+        .header("Referer", "https://mp.weixin.qq.com/")
+        .header("User-Agent", WECHAT_USER_AGENT)
+        .timeout(std::time::Duration::from_secs(20))
+        .send()
+        .await;
+
+    match resp {
+        Ok(r) => {
+            let http_status = r.status().as_u16();
+            let body = r.text().await.unwrap_or_default();
+            let (status, detail) = classify_article_page(http_status, &body);
+            LinkCheck {
+                status,
+                http_status: Some(http_status as i32),
+                detail,
+                body,
+            }
+        }
+        Err(e) => LinkCheck {
+            status: LinkStatus::Error,
+            http_status: None,
+            detail: Some(e.to_string()),
+            body: String::new(),
+        },
+    }
+}
+
+/// Readable text of an article page, one block per line.
+/// Uses the `#js_content` region when present, otherwise the whole page.
+pub fn article_text(html: &str) -> String {
+    let region = match html.find("id=\"js_content\"") {
+        Some(start) => {
+            let rest = &html[start..];
+            let rest = &rest[rest.find('>').map_or(0, |i| i + 1)..];
+            let end = rest.find("<script").unwrap_or(rest.len());
+            &rest[..end]
+        }
+        None => html,
+    };
+
+    let text = SCRIPT_STYLE_RE.replace_all(region, "");
+    let text = BLOCK_END_RE.replace_all(&text, "\n");
+    let text = TAG_RE.replace_all(&text, "");
+    let text = html_escape::decode_html_entities(&text);
+
+    text.lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Line diff between snapshot and live text. Returns (similarity ratio, unified diff).
+pub fn diff_texts(snapshot: &str, live: &str) -> (f32, String) {
+    let diff = TextDiff::from_lines(snapshot, live);
+    let ratio = diff.ratio();
+    let mut unified = diff
+        .unified_diff()
+        .context_radius(2)
+        .header("snapshot", "live")
+        .to_string();
+    if unified.len() > MAX_DIFF_CHARS {
+        let mut cut = MAX_DIFF_CHARS;
+        while !unified.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        unified.truncate(cut);
+        unified.push_str("\n... (diff truncated)\n");
+    }
+    (ratio, unified)
+}
+
+/// Persist a check result to the status history
+pub async fn record_check(
+    pool: &PgPool,
+    url: &str,
+    article_id: Option<&str>,
+    check: &LinkCheck,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO article_link_checks (url, article_id, http_status, status, detail, checked_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(url)
+    .bind(article_id)
+    .bind(check.http_status)
+    .bind(check.status.as_str())
+    .bind(&check.detail)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Pick links to verify: never-checked links first, then least recently checked.
+/// Links already known to be vanished are not re-checked.
+async fn sample_links(pool: &PgPool, limit: i64) -> Result<Vec<(String, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT c.url, c.article_id
+        FROM (
+            SELECT DISTINCT ON (url) url, article_id
+            FROM (
+                SELECT link AS url, id AS article_id FROM articles WHERE is_deleted = false
+                UNION ALL
+                SELECT url, NULL AS article_id FROM insight_articles
+            ) src
+            ORDER BY url, article_id NULLS LAST
+        ) c
+        LEFT JOIN LATERAL (
+            SELECT status, checked_at FROM article_link_checks k
+            WHERE k.url = c.url ORDER BY checked_at DESC LIMIT 1
+        ) last ON true
+        WHERE last.status IS NULL OR NOT (last.status = ANY($2))
+        ORDER BY last.checked_at ASC NULLS FIRST, random()
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .bind(&VANISHED_STATUSES[..])
+    .fetch_all(pool)
+    .await
+}
+
+/// Locally preserved copy of an article
+struct Snapshot {
+    content: String,
+    /// Link to the snapshot served by this backend
+    link: String,
+}
+
+/// Look up the stored snapshot for a link, expired or not
+async fn load_snapshot(
+    pool: &PgPool,
+    url: &str,
+    article_id: Option<&str>,
+) -> Result<Option<Snapshot>, sqlx::Error> {
+    let stored = articles::find(pool, article_id, Some(url), Freshness::Any).await?;
+    let base = std::env::var("PUBLIC_BASE_URL").unwrap_or_default();
+    Ok(stored.map(|article| Snapshot {
+        content: article.content,
+        link: format!(
+            "{}/api/public/v1/html?id={}",
+            base.trim_end_matches('/'),
+            urlencoding::encode(&article.id)
+        ),
+    }))
+}
+
+/// A takedown or content change detected against the local snapshot
+#[derive(Debug, serde::Serialize)]
+pub struct ArchiveAlert {
+    /// `takedown` or `changed`
+    pub kind: &'static str,
+    pub url: String,
+    pub article_id: Option<String>,
+    pub status: String,
+    pub detail: Option<String>,
+    pub similarity: Option<f32>,
+    pub snapshot_url: Option<String>,
+    pub diff: Option<String>,
+    pub created_at: i64,
+}
+
+/// Keep the live page as a new article version when its text changed
+async fn capture_live_version(
+    pool: &PgPool,
+    url: &str,
+    article_id: Option<&str>,
+    body: &str,
+) -> Result<(), sqlx::Error> {
+    let id = match articles::find(pool, article_id, Some(url), Freshness::Any).await? {
+        Some(stored) => stored.id,
+        None => match article_id {
+            Some(id) => id.to_string(),
+            None => articles::resolve_id(pool, url).await?,
+        },
+    };
+    if articles::capture_version(pool, &id, url, body, "verify").await? {
+        tracing::info!("[Archive] Captured new version of {}", url);
+    }
+    Ok(())
+}
+
+/// Build an alert for a check result, if it warrants one.
+/// Takedowns always alert; live pages only when the text drifted past the threshold.
+async fn detect_alert(
+    pool: &PgPool,
+    url: &str,
+    article_id: Option<&str>,
+    check: &LinkCheck,
+) -> Result<Option<ArchiveAlert>, sqlx::Error> {
+    let vanished = check.status.is_vanished();
+    if !vanished && check.status != LinkStatus::Ok {
+        return Ok(None);
+    }
+
+    let snapshot = load_snapshot(pool, url, article_id).await?;
+    let compared = snapshot
+        .as_ref()
+        .map(|snap| diff_texts(&article_text(&snap.content), &article_text(&check.body)));
+
+    let kind = if vanished {
+        "takedown"
+    } else {
+        match compared {
+            Some((ratio, _)) if ratio < CHANGE_ALERT_RATIO => "changed",
+            _ => return Ok(None),
+        }
+    };
+
+    // A changed article stays changed; only alert again when the diff moves
+    if kind == "changed" {
+        let diff = compared.as_ref().map(|(_, d)| d.as_str());
+        let seen: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM archive_alerts WHERE url = $1 AND kind = 'changed' AND diff = $2)",
+        )
+        .bind(url)
+        .bind(diff)
+        .fetch_one(pool)
+        .await?;
+        if seen {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(ArchiveAlert {
+        kind,
+        url: url.to_string(),
+        article_id: article_id.map(|s| s.to_string()),
+        status: check.status.as_str().to_string(),
+        detail: check.detail.clone(),
+        similarity: compared.as_ref().map(|(ratio, _)| *ratio),
+        snapshot_url: snapshot.map(|s| s.link),
+        diff: compared.map(|(_, diff)| diff),
+        created_at: chrono::Utc::now().timestamp(),
+    }))
+}
+
+/// Store an alert and forward it to `ARCHIVE_ALERT_WEBHOOK` when configured
+async fn raise_alert(pool: &PgPool, client: &reqwest::Client, alert: &ArchiveAlert) {
+    tracing::warn!(
+        "[Archive] Alert ({}): {} similarity={:?} snapshot={:?}",
+        alert.kind,
+        alert.url,
+        alert.similarity,
+        alert.snapshot_url
+    );
+
+    let mut notified = false;
+    if let Ok(webhook) = std::env::var("ARCHIVE_ALERT_WEBHOOK") {
+        if !webhook.is_empty() {
+            match client
+                .post(&webhook)
+                .json(alert)
+                .timeout(std::time::Duration::from_secs(15))
+                .send()
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => notified = true,
+                Ok(resp) => tracing::error!("[Archive] Alert webhook returned {}", resp.status()),
+                Err(e) => tracing::error!("[Archive] Alert webhook failed: {}", e),
+            }
+        }
+    }
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO archive_alerts (url, article_id, kind, status, detail, similarity, snapshot_url, diff, notified, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&alert.url)
+    .bind(&alert.article_id)
+    .bind(alert.kind)
+    .bind(&alert.status)
+    .bind(&alert.detail)
+    .bind(alert.similarity)
+    .bind(&alert.snapshot_url)
+    .bind(&alert.diff)
+    .bind(notified)
+    .bind(alert.created_at)
+    .execute(pool)
+    .await
+    {
+        tracing::error!("[Archive] Failed to store alert for {}: {}", alert.url, e);
+    }
+}
+
+/// Summary of one verification round
+#[derive(Debug, Default, serde::Serialize)]
+pub struct VerifyRoundSummary {
+    pub checked: usize,
+    pub ok: usize,
+    pub vanished: usize,
+    pub errors: usize,
+    pub alerts: usize,
+}
+
+/// Run a single verification round over a sample of archived links
+pub async fn run_verification_round(pool: &PgPool, sample_size: i64) -> anyhow::Result<VerifyRoundSummary> {
+    use rand::Rng;
+
+    let links = sample_links(pool, sample_size).await?;
+    let client = reqwest::Client::builder().build()?;
+    let mut summary = VerifyRoundSummary::default();
+
+    for (i, (url, article_id)) in links.iter().enumerate() {
+        if i > 0 {
+            // Spread requests out to avoid WeChat frequency control
+            let delay = rand::thread_rng().gen_range(1000..=3000);
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+        }
+
+        let check = check_url(&client, url).await;
+        if let Err(e) = record_check(pool, url, article_id.as_deref(), &check).await {
+            tracing::error!("[Archive] Failed to record check for {}: {}", url, e);
+        }
+
+        if check.status == LinkStatus::Ok {
+            if let Err(e) =
+                capture_live_version(pool, url, article_id.as_deref(), &check.body).await
+            {
+                tracing::error!("[Archive] Failed to capture version of {}: {}", url, e);
+            }
+        }
+
+        match detect_alert(pool, url, article_id.as_deref(), &check).await {
+            Ok(Some(alert)) => {
+                raise_alert(pool, &client, &alert).await;
+                summary.alerts += 1;
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("[Archive] Snapshot comparison failed for {}: {}", url, e),
+        }
+
+        summary.checked += 1;
+        if check.status == LinkStatus::Ok {
+            summary.ok += 1;
+        } else if check.status.is_vanished() {
+            summary.vanished += 1;
+            tracing::warn!(
+                "[Archive] Online copy vanished ({}): {}",
+                check.status.as_str(),
+                url
+            );
+        } else {
+            summary.errors += 1;
+        }
+    }
+
+    tracing::info!(
+        "[Archive] Verification round done: checked={}, ok={}, vanished={}, errors={}, alerts={}",
+        summary.checked,
+        summary.ok,
+        summary.vanished,
+        summary.errors,
+        summary.alerts
+    );
+    Ok(summary)
+}
+
+/// Spawn the periodic verification job.
+/// Configured via `ARCHIVE_VERIFY_INTERVAL_SECS` (0 disables) and `ARCHIVE_VERIFY_SAMPLE`.
+pub fn spawn_verifier(pool: PgPool) {
+    let interval_secs = std::env::var("ARCHIVE_VERIFY_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    let sample_size = std::env::var("ARCHIVE_VERIFY_SAMPLE")
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_SAMPLE_SIZE);
+
+    if interval_secs == 0 {
+        tracing::info!("[Archive] Verification job disabled");
+        return;
+    }
+
+    tracing::info!(
+        "[Archive] Verification job every {}s, {} links per round",
+        interval_secs,
+        sample_size
+    );
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        // Skip the immediate first tick so startup isn't slowed by network checks
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_verification_round(&pool, sample_size).await {
+                tracing::error!("[Archive] Verification round failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_deleted_sample() {
+        let html = include_str!("../../samples/作者已删除/01.html");
+        let (status, _) = classify_article_page(200, html);
+        assert_eq!(status, LinkStatus::Deleted);
+    }
+
+    #[test]
+    fn test_classify_violation_sample() {
+        let html = include_str!("../../samples/内容违规/01.html");
+        let (status, _) = classify_article_page(200, html);
+        assert_eq!(status, LinkStatus::Violation);
+    }
+
+    #[test]
+    fn test_classify_normal_sample() {
+        let html = include_str!("../../samples/普通图文/01.html");
+        let (status, _) = classify_article_page(200, html);
+        assert_eq!(status, LinkStatus::Ok);
+    }
+
+    #[test]
+    fn test_classify_not_found() {
+        assert_eq!(classify_article_page(404, "").0, LinkStatus::NotFound);
+    }
+
+    #[test]
+    fn test_article_text_and_diff() {
+        let html = r#"<div id="js_content"><p>第一段</p><p>第二&amp;段</p></div><script>var x = 1;</script>"#;
+        let text = article_text(html);
+        assert_eq!(text, "第一段\n第二&段");
+
+        let (same, _) = diff_texts(&text, &text);
+        assert_eq!(same, 1.0);
+        let (ratio, diff) = diff_texts(&text, "第一段\n改写后的段落");
+        assert!(ratio < CHANGE_ALERT_RATIO);
+        assert!(diff.contains("-第二&段"));
+        assert!(diff.contains("+改写后的段落"));
+    }
+}
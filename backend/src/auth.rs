@@ -0,0 +1,395 @@
+//! API authentication
+//!
+//! Requests carry an API token as `Authorization: Bearer <token>`,
+//! `X-API-Key: <token>`, or the `insight_token` cookie set by
+//! `/api/auth/login` for the browser. RSS feeds under `/api/feeds/` also take
+//! it as `?token=`, since feed readers rarely send headers. Each token grants
+//! a role:
+//!
+//! - `read`: GET requests only
+//! - `write`: everything except admin operations
+//! - `admin`: everything, including clearing embeddings, stored keys, MP
+//!   session cookies and managing users
+//!
+//! Shared tokens come from `API_READ_TOKEN`, `API_TOKEN` and
+//! `API_ADMIN_TOKEN`; per-user tokens are created through `/api/users` and
+//! stored hashed in `users`. Users only see the tasks, MP sessions and
+//! accounts they own, admins see everything, and the shared tokens see the
+//! rows without an owner.
+//!
+//! With no token configured and no user created authentication is off and
+//! every request is treated as admin, as before. `/health`, public share
+//! links and the login and logout endpoints are always open.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    extract::{Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use lazy_static::lazy_static;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::AppState;
+
+lazy_static! {
+    static ref TOKENS: Vec<(Role, String)> = tokens_from_env();
+}
+
+/// Whether `users` has rows; kept current by `refresh`
+static USERS_EXIST: AtomicBool = AtomicBool::new(false);
+
+/// Cookie holding the token for browser sessions
+pub const COOKIE_NAME: &str = "insight_token";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Read => "read",
+            Role::Write => "write",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Role> {
+        match s {
+            "read" => Some(Role::Read),
+            "write" => Some(Role::Write),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Who made the request; added to request extensions by `authenticate`
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    pub role: Role,
+    /// `None` for the shared env tokens and when authentication is off
+    pub user_id: Option<Uuid>,
+    pub name: String,
+}
+
+impl Principal {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// Whether a row owned by `owner` is visible to this principal.
+    /// Queries use the same rule as `($1 OR owner_id IS NOT DISTINCT FROM $2)`
+    /// bound to `is_admin()` and `user_id`.
+    pub fn can_access(&self, owner: Option<Uuid>) -> bool {
+        self.is_admin() || owner == self.user_id
+    }
+}
+
+fn tokens_from_env() -> Vec<(Role, String)> {
+    [
+        (Role::Read, "API_READ_TOKEN"),
+        (Role::Write, "API_TOKEN"),
+        (Role::Admin, "API_ADMIN_TOKEN"),
+    ]
+    .into_iter()
+    .filter_map(|(role, var)| {
+        let token = std::env::var(var).ok()?.trim().to_string();
+        (!token.is_empty()).then_some((role, token))
+    })
+    .collect()
+}
+
+pub fn enabled() -> bool {
+    !TOKENS.is_empty() || USERS_EXIST.load(Ordering::Relaxed)
+}
+
+/// Re-read whether any user exists; called at startup and after user changes
+pub async fn refresh(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let exist: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM users)")
+        .fetch_one(pool)
+        .await?;
+    USERS_EXIST.store(exist, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Compare without leaking the position of the first difference
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Role granted by a shared env token, the highest if several variables share it
+fn env_role(token: &str) -> Option<Role> {
+    TOKENS
+        .iter()
+        .filter(|(_, t)| constant_time_eq(t.as_bytes(), token.as_bytes()))
+        .map(|(role, _)| *role)
+        .max()
+}
+
+/// Stored form of a user token
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// New random user token
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Principal for `token`, from the env tokens or `users`
+pub async fn resolve(pool: &PgPool, token: &str) -> Result<Option<Principal>, sqlx::Error> {
+    if let Some(role) = env_role(token) {
+        return Ok(Some(Principal {
+            role,
+            user_id: None,
+            name: role.as_str().to_string(),
+        }));
+    }
+    if !USERS_EXIST.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+    // Looked up by hash, so the comparison never sees the token itself
+    let row: Option<(Uuid, String, String)> = sqlx::query_as(
+        "UPDATE users SET last_seen_at = $2 WHERE token_hash = $1 RETURNING id, name, role",
+    )
+    .bind(hash_token(token))
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(id, name, role)| Principal {
+        role: Role::parse(&role).unwrap_or(Role::Read),
+        user_id: Some(id),
+        name,
+    }))
+}
+
+/// Paths that need an admin token whatever the method
+const ADMIN_PATHS: &[&str] = &[
+    "/api/embedding/clear",
+    "/api/embedding/clean",
+    "/api/embedding/export",
+    "/api/embedding/import",
+    "/api/embedding/index/drop",
+    "/api/settings/llm",
+    "/api/settings/llm/delete",
+    "/api/settings/notifiers",
+    "/api/settings/notifiers/delete",
+    "/api/settings/notifiers/test",
+    "/api/cache/invalidate",
+    "/api/cache/ttl",
+    "/api/cache/gc",
+    "/api/cache/clear",
+    "/api/cache/evict",
+    "/api/proxy/gateways",
+    "/api/proxy/gateways/update",
+    "/api/proxy/gateways/delete",
+    "/api/proxy/gateways/check",
+    "/api/proxy/gateways/stats",
+    "/api/public/v1/authkey",
+    "/api/web/mp/logout",
+    "/api/search/fulltext/reindex",
+    "/api/users",
+    "/api/users/create",
+    "/api/users/delete",
+];
+
+/// Paths whose GET is a status read but whose writes need an admin token
+const ADMIN_WRITE_PATHS: &[&str] = &[
+    "/api/embedding/migrate",
+    "/api/embedding/index",
+    "/api/embedding/auto_index/daemon",
+];
+
+/// GET endpoints with side effects (they drive the MP login flow)
+const WRITE_GET_PREFIXES: &[&str] = &["/api/web/login/"];
+
+/// Role needed for a request, `None` when it is open to everyone
+pub fn required_role(method: &Method, path: &str) -> Option<Role> {
+    if method == Method::OPTIONS
+        || path == "/health"
+        || path.starts_with("/health/")
+        || path.starts_with("/api/share/")
+        || path.starts_with("/api/shared/")
+        || path == "/api/auth/login"
+        || path == "/api/auth/logout"
+    {
+        return None;
+    }
+    let read_only = method == Method::GET || method == Method::HEAD;
+    if ADMIN_PATHS.contains(&path) || (!read_only && ADMIN_WRITE_PATHS.contains(&path)) {
+        Some(Role::Admin)
+    } else if !read_only || WRITE_GET_PREFIXES.iter().any(|p| path.starts_with(p)) {
+        Some(Role::Write)
+    } else {
+        Some(Role::Read)
+    }
+}
+
+/// Token sent with the request, from the header or the session cookie
+fn request_token(req: &Request) -> Option<String> {
+    let headers = req.headers();
+    if let Some(value) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    {
+        if let Some(token) = value.strip_prefix("Bearer ") {
+            return Some(token.trim().to_string());
+        }
+    }
+    if let Some(value) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(value.trim().to_string());
+    }
+    if req.uri().path().starts_with("/api/feeds/") {
+        let token = url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+            .find(|(name, _)| name == "token")
+            .map(|(_, value)| value.trim().to_string());
+        if token.is_some() {
+            return token;
+        }
+    }
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            (name == COOKIE_NAME).then(|| value.to_string())
+        })
+}
+
+/// Middleware: reject requests without a token of the role the route needs
+pub async fn authenticate(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(required) = required_role(req.method(), req.uri().path()) else {
+        return Ok(next.run(req).await);
+    };
+    let principal = if enabled() {
+        let token = request_token(&req)
+            .ok_or_else(|| AppError::Unauthorized("需要 API Token".to_string()))?;
+        resolve(&state.db_pool, &token)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("API Token 无效".to_string()))?
+    } else {
+        Principal {
+            role: Role::Admin,
+            user_id: None,
+            name: "anonymous".to_string(),
+        }
+    };
+    if principal.role < required {
+        return Err(AppError::Forbidden(format!(
+            "当前 Token 权限不足 (需要 {})",
+            required.as_str()
+        )));
+    }
+    req.extensions_mut().insert(principal);
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_role() {
+        assert_eq!(required_role(&Method::GET, "/health"), None);
+        assert_eq!(required_role(&Method::GET, "/api/share/abc"), None);
+        assert_eq!(required_role(&Method::GET, "/api/shared/abc"), None);
+        assert_eq!(
+            required_role(&Method::OPTIONS, "/api/embedding/clear"),
+            None
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/insight/list"),
+            Some(Role::Read)
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/insight/create"),
+            Some(Role::Write)
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/web/login/getqrcode"),
+            Some(Role::Write)
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/embedding/clear"),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/api/embedding/migrate"),
+            Some(Role::Read)
+        );
+        assert_eq!(
+            required_role(&Method::POST, "/api/embedding/migrate"),
+            Some(Role::Admin)
+        );
+        assert_eq!(required_role(&Method::GET, "/api/users"), Some(Role::Admin));
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+    }
+
+    #[test]
+    fn test_principal_access() {
+        let owner = Uuid::new_v4();
+        let user = Principal {
+            role: Role::Write,
+            user_id: Some(owner),
+            name: "alice".to_string(),
+        };
+        assert!(user.can_access(Some(owner)));
+        assert!(!user.can_access(Some(Uuid::new_v4())));
+        assert!(!user.can_access(None));
+
+        let shared = Principal {
+            role: Role::Write,
+            user_id: None,
+            name: "write".to_string(),
+        };
+        assert!(shared.can_access(None));
+        assert!(!shared.can_access(Some(owner)));
+
+        let admin = Principal {
+            role: Role::Admin,
+            ..shared
+        };
+        assert!(admin.can_access(Some(owner)));
+        assert_eq!(hash_token("abc").len(), 64);
+        assert_eq!(Role::parse(Role::Read.as_str()), Some(Role::Read));
+    }
+
+    #[test]
+    fn test_query_token() {
+        let request = |uri: &str| {
+            Request::builder()
+                .uri(uri)
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        assert_eq!(
+            request_token(&request("/api/feeds/task/x.xml?limit=5&token=a%2Bb")).as_deref(),
+            Some("a+b")
+        );
+        // Only feeds take the token from the URL
+        assert_eq!(request_token(&request("/api/insight/list?token=ab")), None);
+    }
+}
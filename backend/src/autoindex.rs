@@ -0,0 +1,276 @@
+//! Auto-indexing daemon
+//!
+//! Keeps article embeddings up to date without manual `/api/embedding/auto_index`
+//! calls. One batch runs at a time and the next starts only after it finishes;
+//! provider concurrency is capped by `llm::embedding_permit`. Failures back off
+//! exponentially, and the daemon pauses while an embedding migration runs.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::Notify;
+
+use crate::error::AppError;
+
+lazy_static! {
+    static ref STATUS: Mutex<DaemonStatus> = Mutex::new(DaemonStatus::from_env());
+    /// Wakes the daemon early after start/stop/config changes
+    static ref WAKE: Notify = Notify::new();
+}
+
+/// Pause between batches while a backlog remains
+const BATCH_PAUSE: Duration = Duration::from_secs(2);
+/// First retry delay after a failed batch; doubles per consecutive failure
+const FAILURE_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DaemonStatus {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub batch_size: i32,
+    /// stopped, idle, indexing, backoff, paused
+    pub state: String,
+    /// Articles without title embeddings
+    pub unindexed: Option<i64>,
+    /// Cached bodies without current chunk embeddings (indexed once titles are done)
+    pub content_unindexed: Option<i64>,
+    pub total_indexed: u64,
+    pub total_failed: u64,
+    pub consecutive_failures: u32,
+    pub last_run_at: Option<i64>,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<i64>,
+}
+
+impl DaemonStatus {
+    fn from_env() -> Self {
+        let enabled = std::env::var("AUTO_INDEX_ENABLED")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        DaemonStatus {
+            enabled,
+            interval_secs: std::env::var("AUTO_INDEX_INTERVAL_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(300),
+            batch_size: std::env::var("AUTO_INDEX_BATCH_SIZE")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20),
+            state: if enabled { "idle" } else { "stopped" }.to_string(),
+            unindexed: None,
+            content_unindexed: None,
+            total_indexed: 0,
+            total_failed: 0,
+            consecutive_failures: 0,
+            last_run_at: None,
+            last_error: None,
+            next_run_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DaemonControl {
+    /// "start" or "stop"; omit to only change settings
+    pub action: Option<String>,
+    #[serde(rename = "intervalSecs")]
+    pub interval_secs: Option<u64>,
+    #[serde(rename = "batchSize")]
+    pub batch_size: Option<i32>,
+}
+
+pub fn status() -> DaemonStatus {
+    STATUS.lock().unwrap().clone()
+}
+
+/// Apply a control request and wake the daemon
+pub fn control(req: DaemonControl) -> Result<DaemonStatus, AppError> {
+    let enabled = match req.action.as_deref() {
+        None => None,
+        Some("start") => Some(true),
+        Some("stop") => Some(false),
+        Some(other) => return Err(AppError::BadRequest(format!("不支持的操作: {}", other))),
+    };
+
+    let status = {
+        let mut s = STATUS.lock().unwrap();
+        if let Some(enabled) = enabled {
+            s.enabled = enabled;
+            if enabled {
+                s.consecutive_failures = 0;
+            } else {
+                s.state = "stopped".to_string();
+                s.next_run_at = None;
+            }
+        }
+        if let Some(interval) = req.interval_secs {
+            s.interval_secs = interval.max(10);
+        }
+        if let Some(batch_size) = req.batch_size {
+            s.batch_size = batch_size.clamp(1, 500);
+        }
+        s.clone()
+    };
+    WAKE.notify_one();
+    tracing::info!(
+        "[AutoIndex] enabled={} interval={}s batch={}",
+        status.enabled,
+        status.interval_secs,
+        status.batch_size
+    );
+    Ok(status)
+}
+
+fn set_state(state: &str, delay: Option<Duration>) {
+    let mut s = STATUS.lock().unwrap();
+    if !s.enabled {
+        // Stopped while this pass was running
+        s.state = "stopped".to_string();
+        s.next_run_at = None;
+        return;
+    }
+    s.state = state.to_string();
+    s.next_run_at = delay.map(|d| chrono::Utc::now().timestamp() + d.as_secs() as i64);
+}
+
+/// Sleep for `delay`, returning early on a control change
+async fn wait(delay: Duration) {
+    tokio::select! {
+        _ = tokio::time::sleep(delay) => {}
+        _ = WAKE.notified() => {}
+    }
+}
+
+/// What the next batch embeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Title,
+    Content,
+}
+
+/// Titles first, then full-text chunks; `None` when both are caught up
+async fn next_phase(pool: &PgPool) -> Result<Option<Phase>, AppError> {
+    let titles = crate::api::embedding::count_unindexed(pool).await?;
+    STATUS.lock().unwrap().unindexed = Some(titles);
+    if titles > 0 {
+        return Ok(Some(Phase::Title));
+    }
+    let contents = crate::api::embedding::count_unindexed_content(pool).await?;
+    STATUS.lock().unwrap().content_unindexed = Some(contents);
+    Ok((contents > 0).then_some(Phase::Content))
+}
+
+/// Run one check/index pass and return how long to wait before the next one
+async fn run_once(pool: &PgPool, previous_remaining: &mut Option<(Phase, i64)>) -> Duration {
+    let (interval, batch_size, failures) = {
+        let s = STATUS.lock().unwrap();
+        (
+            Duration::from_secs(s.interval_secs),
+            s.batch_size,
+            s.consecutive_failures,
+        )
+    };
+
+    if crate::embedding_registry::migration_status().is_some_and(|m| m.status == "running") {
+        set_state("paused", Some(interval));
+        return interval;
+    }
+
+    let phase = match next_phase(pool).await {
+        Ok(Some(phase)) => phase,
+        Ok(None) => {
+            *previous_remaining = None;
+            set_state("idle", Some(interval));
+            return interval;
+        }
+        Err(e) => {
+            tracing::error!("[AutoIndex] Failed to count unindexed articles: {}", e);
+            STATUS.lock().unwrap().last_error = Some(e.to_string());
+            set_state("idle", Some(interval));
+            return interval;
+        }
+    };
+
+    set_state("indexing", None);
+    let result = match phase {
+        Phase::Title => crate::api::embedding::index_batch(pool, batch_size).await,
+        // Each body is up to EMBEDDING_MAX_CHUNKS windows, so take fewer articles
+        Phase::Content => {
+            crate::api::embedding::index_content_batch(pool, (batch_size / 4).max(1)).await
+        }
+    };
+    let now = chrono::Utc::now().timestamp();
+
+    let error = match result {
+        Ok(batch) if batch.success => {
+            let remaining = batch.remaining as i64;
+            // Rows that never get an embedding would otherwise be retried in a tight loop
+            let stalled = previous_remaining.is_some_and(|(p, r)| p == phase && remaining >= r);
+            *previous_remaining = Some((phase, remaining));
+            {
+                let mut s = STATUS.lock().unwrap();
+                s.total_indexed += batch.indexed as u64;
+                s.total_failed += batch.failed as u64;
+                s.consecutive_failures = 0;
+                s.last_run_at = Some(now);
+                s.last_error = None;
+                match phase {
+                    Phase::Title => s.unindexed = Some(remaining),
+                    Phase::Content => s.content_unindexed = Some(remaining),
+                }
+            }
+            tracing::info!(
+                "[AutoIndex] Indexed {} articles ({:?}), {} remaining",
+                batch.indexed,
+                phase,
+                remaining
+            );
+            let delay = if remaining > 0 && !stalled {
+                BATCH_PAUSE
+            } else {
+                interval
+            };
+            set_state("idle", Some(delay));
+            return delay;
+        }
+        Ok(batch) => batch.error.unwrap_or_else(|| "索引失败".to_string()),
+        Err(e) => e.to_string(),
+    };
+
+    let backoff = FAILURE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.min(16)))
+        .min(MAX_BACKOFF);
+    tracing::warn!(
+        "[AutoIndex] Batch failed ({}), retrying in {}s",
+        error,
+        backoff.as_secs()
+    );
+    {
+        let mut s = STATUS.lock().unwrap();
+        s.consecutive_failures += 1;
+        s.last_run_at = Some(now);
+        s.last_error = Some(error);
+    }
+    set_state("backoff", Some(backoff));
+    backoff
+}
+
+/// Start the daemon loop; it idles until enabled
+pub fn spawn_daemon(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut previous_remaining = None;
+        loop {
+            if !STATUS.lock().unwrap().enabled {
+                WAKE.notified().await;
+                continue;
+            }
+            let delay = run_once(&pool, &mut previous_remaining).await;
+            wait(delay).await;
+        }
+    });
+}
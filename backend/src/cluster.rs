@@ -0,0 +1,232 @@
+//! Article clustering for the topic map
+//!
+//! Spherical k-means over article vectors (cosine similarity, k-means++
+//! seeding with a fixed seed so the same articles give the same map) and a
+//! 2-D projection onto the two principal components for plotting.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const MAX_ITERATIONS: usize = 50;
+const SEED: u64 = 42;
+/// Most clusters a request may ask for; each one is labelled by the LLM
+pub const MAX_K: usize = 50;
+
+/// Cosine similarity for unit-length vectors
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(v: &mut [f32]) {
+    let norm = dot(v, v).sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Unit-length mean direction of unit-length vectors
+pub fn centroid<'a>(vectors: impl IntoIterator<Item = &'a Vec<f32>>) -> Vec<f32> {
+    let mut sum: Vec<f32> = Vec::new();
+    for v in vectors {
+        if sum.is_empty() {
+            sum = vec![0.0; v.len()];
+        }
+        sum.iter_mut().zip(v).for_each(|(s, x)| *s += x);
+    }
+    normalize(&mut sum);
+    sum
+}
+
+/// Cluster count used when the caller does not pick one: about √(n/2), 2..=12
+pub fn default_k(n: usize) -> usize {
+    ((n as f64 / 2.0).sqrt().round() as usize).clamp(2, 12)
+}
+
+/// Result of `kmeans`
+pub struct Clustering {
+    /// Cluster of each input vector
+    pub assignments: Vec<usize>,
+    /// Unit-length centroid of each cluster
+    pub centroids: Vec<Vec<f32>>,
+}
+
+impl Clustering {
+    /// Members of `cluster`, most central first
+    pub fn members(&self, vectors: &[Vec<f32>], cluster: usize) -> Vec<usize> {
+        let centroid = &self.centroids[cluster];
+        let mut members: Vec<(usize, f32)> = self
+            .assignments
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == cluster)
+            .map(|(i, _)| (i, dot(&vectors[i], centroid)))
+            .collect();
+        members.sort_by(|a, b| b.1.total_cmp(&a.1));
+        members.into_iter().map(|(i, _)| i).collect()
+    }
+}
+
+/// Spherical k-means; `vectors` are normalized in place. `k` is capped at the
+/// number of vectors, and empty clusters are dropped from the result.
+pub fn kmeans(vectors: &mut [Vec<f32>], k: usize) -> Clustering {
+    vectors.iter_mut().for_each(|v| normalize(v));
+    let n = vectors.len();
+    let k = k.clamp(1, n.max(1));
+    if n == 0 {
+        return Clustering {
+            assignments: Vec::new(),
+            centroids: Vec::new(),
+        };
+    }
+
+    // k-means++: each next seed is drawn weighted by its distance to the nearest seed
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut centroids = vec![vectors[rng.gen_range(0..n)].clone()];
+    while centroids.len() < k {
+        let distances: Vec<f32> = vectors
+            .iter()
+            .map(|v| {
+                centroids
+                    .iter()
+                    .map(|c| (1.0 - dot(v, c)).max(0.0))
+                    .fold(f32::MAX, f32::min)
+            })
+            .collect();
+        let total: f32 = distances.iter().sum();
+        if total <= 0.0 {
+            break; // fewer distinct vectors than clusters
+        }
+        let mut target = rng.gen_range(0.0..total);
+        let mut next = n - 1;
+        for (i, d) in distances.iter().enumerate() {
+            if target < *d {
+                next = i;
+                break;
+            }
+            target -= d;
+        }
+        centroids.push(vectors[next].clone());
+    }
+
+    let mut assignments = vec![usize::MAX; n];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, v) in vectors.iter().enumerate() {
+            let best = centroids
+                .iter()
+                .enumerate()
+                .max_by(|a, b| dot(v, a.1).total_cmp(&dot(v, b.1)))
+                .map(|(c, _)| c)
+                .unwrap_or(0);
+            if assignments[i] != best {
+                assignments[i] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        let dim = vectors[0].len();
+        let mut sums = vec![vec![0.0f32; dim]; centroids.len()];
+        for (v, c) in vectors.iter().zip(&assignments) {
+            sums[*c].iter_mut().zip(v).for_each(|(s, x)| *s += x);
+        }
+        for (centroid, mut sum) in centroids.iter_mut().zip(sums) {
+            if sum.iter().any(|x| *x != 0.0) {
+                normalize(&mut sum);
+                *centroid = sum;
+            }
+        }
+    }
+
+    // Renumber so cluster ids are dense after dropping empty ones
+    let mut remap = vec![None; centroids.len()];
+    let mut kept = Vec::new();
+    for c in assignments.iter_mut() {
+        let id = *remap[*c].get_or_insert_with(|| {
+            kept.push(centroids[*c].clone());
+            kept.len() - 1
+        });
+        *c = id;
+    }
+    Clustering {
+        assignments,
+        centroids: kept,
+    }
+}
+
+/// Coordinates of each vector on the first two principal components,
+/// found by power iteration
+pub fn project_2d(vectors: &[Vec<f32>]) -> Vec<(f32, f32)> {
+    let n = vectors.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let dim = vectors[0].len();
+    let mut mean = vec![0.0f32; dim];
+    for v in vectors {
+        mean.iter_mut().zip(v).for_each(|(m, x)| *m += x / n as f32);
+    }
+    let centered: Vec<Vec<f32>> = vectors
+        .iter()
+        .map(|v| v.iter().zip(&mean).map(|(x, m)| x - m).collect())
+        .collect();
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let mut components: Vec<Vec<f32>> = Vec::new();
+    for _ in 0..2 {
+        let mut axis: Vec<f32> = (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        normalize(&mut axis);
+        for _ in 0..30 {
+            // axis <- Xᵀ X axis, kept orthogonal to the components already found
+            let mut next = vec![0.0f32; dim];
+            for row in &centered {
+                let weight = dot(row, &axis);
+                next.iter_mut().zip(row).for_each(|(a, x)| *a += weight * x);
+            }
+            for c in &components {
+                let overlap = dot(&next, c);
+                next.iter_mut().zip(c).for_each(|(a, x)| *a -= overlap * x);
+            }
+            normalize(&mut next);
+            axis = next;
+        }
+        components.push(axis);
+    }
+
+    centered
+        .iter()
+        .map(|row| (dot(row, &components[0]), dot(row, &components[1])))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kmeans_and_projection() {
+        // Two tight groups around the x and y axes
+        let mut vectors = vec![
+            vec![1.0, 0.05, 0.0],
+            vec![0.95, 0.0, 0.1],
+            vec![1.0, -0.05, 0.0],
+            vec![0.0, 1.0, 0.05],
+            vec![0.1, 0.9, 0.0],
+        ];
+        let clustering = kmeans(&mut vectors, 2);
+        let a = clustering.assignments[0];
+        assert_eq!(&clustering.assignments[..3], &[a, a, a]);
+        assert_ne!(clustering.assignments[3], a);
+        assert_eq!(clustering.assignments[3], clustering.assignments[4]);
+        assert_eq!(clustering.members(&vectors, a).len(), 3);
+
+        let points = project_2d(&vectors);
+        assert_eq!(points.len(), 5);
+        // The groups land on opposite sides of the first component
+        assert!(points[0].0 * points[3].0 < 0.0);
+
+        assert_eq!(kmeans(&mut [vec![1.0, 0.0]], 3).centroids.len(), 1);
+        assert_eq!(default_k(200), 10);
+    }
+}
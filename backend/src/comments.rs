@@ -0,0 +1,228 @@
+//! Article comments
+//!
+//! Collects the elected (featured) comments of an article through
+//! `mp/appmsg_comment?action=getcomment` and stores the raw response in
+//! `comments`, keyed by the same `fakeid:aid` id as `articles`. The ids the
+//! call needs are read from the article page. WeChat only answers for articles
+//! with comments enabled; anything else simply yields no comments.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::crawl::{self, Priority};
+use crate::wechat::client::with_mp_headers;
+
+lazy_static! {
+    static ref JS_STRING: Regex = Regex::new(r#""([^"]*)"|'([^']*)'"#).unwrap();
+}
+
+/// Ids identifying an article's comment thread
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentRef {
+    /// `__biz`, the account's fakeid
+    pub biz: String,
+    pub appmsgid: String,
+    pub idx: String,
+    pub comment_id: String,
+}
+
+impl CommentRef {
+    /// Key shared with `articles.id`
+    pub fn article_id(&self) -> String {
+        format!("{}:{}_{}", self.biz, self.appmsgid, self.idx)
+    }
+}
+
+/// First non-empty string literal assigned to `var <name> = ...;`
+pub(crate) fn js_var(html: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r"var\s+{}\s*=\s*([^;\n]+)", regex::escape(name))).ok()?;
+    let rhs = re.captures(html)?.get(1)?.as_str();
+    JS_STRING
+        .captures_iter(rhs)
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| m.as_str().trim().to_string())
+        .find(|s| !s.is_empty())
+}
+
+pub(crate) fn url_param(url: &str, name: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    url.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .filter(|v| !v.is_empty())
+}
+
+/// Comment thread ids from an article page; `None` when comments are disabled
+pub fn comment_ref(html: &str, url: &str) -> Option<CommentRef> {
+    let comment_id = js_var(html, "comment_id").filter(|id| id != "0")?;
+    Some(CommentRef {
+        biz: js_var(html, "biz").or_else(|| url_param(url, "__biz"))?,
+        appmsgid: js_var(html, "mid")
+            .or_else(|| js_var(html, "appmsgid"))
+            .or_else(|| url_param(url, "mid"))?,
+        idx: js_var(html, "idx")
+            .or_else(|| url_param(url, "idx"))
+            .unwrap_or_else(|| "1".to_string()),
+        comment_id,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comment {
+    pub nick_name: String,
+    pub content: String,
+    pub like_num: i64,
+    pub create_time: i64,
+    /// The author's reply, if any
+    pub reply: Option<String>,
+}
+
+/// Elected comments of a `getcomment` response, most liked first
+pub fn parse_comments(json: &serde_json::Value) -> Vec<Comment> {
+    let mut comments: Vec<Comment> = json
+        .get("elected_comment")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|c| {
+            let content = c.get("content").and_then(|v| v.as_str())?.trim();
+            if content.is_empty() {
+                return None;
+            }
+            let reply = ["reply_new", "reply"]
+                .iter()
+                .filter_map(|key| c.get(*key)?.get("reply_list")?.get(0)?.get("content"))
+                .find_map(|v| v.as_str())
+                .map(str::to_string);
+            Some(Comment {
+                nick_name: c
+                    .get("nick_name")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                content: content.to_string(),
+                like_num: c.get("like_num").and_then(|v| v.as_i64()).unwrap_or(0),
+                create_time: c.get("create_time").and_then(|v| v.as_i64()).unwrap_or(0),
+                reply,
+            })
+        })
+        .collect();
+    comments.sort_by_key(|c| std::cmp::Reverse(c.like_num));
+    comments
+}
+
+/// Fetch the comment thread; errors on a non-zero `base_resp`
+pub async fn fetch(
+    client: &reqwest::Client,
+    r: &CommentRef,
+    priority: Priority,
+) -> anyhow::Result<serde_json::Value> {
+    let _permit = crawl::acquire(crawl::ARTICLE_LANE, priority, "comments.fetch").await;
+    let json: serde_json::Value =
+        with_mp_headers(client.get("https://mp.weixin.qq.com/mp/appmsg_comment"))
+            .query(&[
+                ("action", "getcomment"),
+                ("__biz", r.biz.as_str()),
+                ("appmsgid", r.appmsgid.as_str()),
+                ("idx", r.idx.as_str()),
+                ("comment_id", r.comment_id.as_str()),
+                ("offset", "0"),
+                ("limit", "100"),
+                ("f", "json"),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+    crate::wechat::client::check_base_resp(&json)?;
+    Ok(json)
+}
+
+/// Store the raw response as the article's comment entry
+pub async fn store(
+    pool: &PgPool,
+    article_id: &str,
+    json: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO comments (id, article_id, content_json, create_time)
+        VALUES ($1, $1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET
+            content_json = EXCLUDED.content_json,
+            create_time = EXCLUDED.create_time
+        "#,
+    )
+    .bind(article_id)
+    .bind(json)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fetch, store and parse the comments of an article page.
+/// Returns the article id and its comments, or `None` when comments are disabled.
+pub async fn collect(
+    pool: &PgPool,
+    client: &reqwest::Client,
+    html: &str,
+    url: &str,
+    priority: Priority,
+) -> anyhow::Result<Option<(String, Vec<Comment>)>> {
+    let Some(r) = comment_ref(html, url) else {
+        return Ok(None);
+    };
+    let json = fetch(client, &r, priority).await?;
+    let article_id = r.article_id();
+    store(pool, &article_id, &json).await?;
+    Ok(Some((article_id, parse_comments(&json))))
+}
+
+/// Top comments formatted for an LLM prompt
+pub fn prompt_section(comments: &[Comment], max: usize) -> String {
+    let mut section = String::from("Top reader comments:");
+    for c in comments.iter().take(max) {
+        let content: String = c.content.chars().take(200).collect();
+        section.push_str(&format!("\n- ({} likes) {}", c.like_num, content));
+        if let Some(reply) = &c.reply {
+            let reply: String = reply.chars().take(200).collect();
+            section.push_str(&format!("\n  Author reply: {}", reply));
+        }
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comment_ref_and_parse() {
+        let html = r#"
+            var biz = "" || "MzA5";
+            var mid = "" || "" || "2650001";
+            var idx = "" || "" || "2";
+            var comment_id = "1234567" * 1;
+        "#;
+        let r = comment_ref(html, "https://mp.weixin.qq.com/s/abc").unwrap();
+        assert_eq!(r.article_id(), "MzA5:2650001_2");
+        assert_eq!(r.comment_id, "1234567");
+        assert!(comment_ref(r#"var comment_id = "0";"#, "").is_none());
+
+        let json = serde_json::json!({
+            "elected_comment": [
+                {"nick_name": "a", "content": "一般", "like_num": 1},
+                {"nick_name": "b", "content": "很好", "like_num": 9,
+                 "reply_new": {"reply_list": [{"content": "谢谢"}]}},
+                {"nick_name": "c", "content": " "}
+            ]
+        });
+        let comments = parse_comments(&json);
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].nick_name, "b");
+        assert_eq!(comments[0].reply.as_deref(), Some("谢谢"));
+    }
+}
@@ -0,0 +1,95 @@
+//! Token-window chunking
+//!
+//! Splits article text into overlapping windows for chunk embeddings. Tokens are
+//! approximated the way embedding tokenizers treat mixed Chinese/English text:
+//! every CJK character (or other symbol) counts as one token, a run of ASCII
+//! letters/digits counts as one.
+
+/// Window size in tokens (`EMBEDDING_CHUNK_TOKENS`, default 512)
+pub fn window_tokens() -> usize {
+    env_usize("EMBEDDING_CHUNK_TOKENS", 512).max(16)
+}
+
+/// Tokens shared by consecutive windows (`EMBEDDING_CHUNK_OVERLAP`, default 64)
+pub fn overlap_tokens() -> usize {
+    env_usize("EMBEDDING_CHUNK_OVERLAP", 64).min(window_tokens() / 2)
+}
+
+/// Most windows embedded per article (`EMBEDDING_MAX_CHUNKS`, default 32)
+pub fn max_chunks() -> usize {
+    env_usize("EMBEDDING_MAX_CHUNKS", 32).max(1)
+}
+
+fn env_usize(key: &str, default: usize) -> usize {
+    std::env::var(key)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Byte offsets where each token starts; whitespace belongs to the preceding token
+fn token_starts(text: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            in_word = false;
+        } else if c.is_ascii_alphanumeric() {
+            if !in_word {
+                starts.push(i);
+            }
+            in_word = true;
+        } else {
+            starts.push(i);
+            in_word = false;
+        }
+    }
+    starts
+}
+
+/// Approximate token count of `text`
+pub fn count_tokens(text: &str) -> usize {
+    token_starts(text).len()
+}
+
+/// Overlapping windows of about `window` tokens, advancing `window - overlap` each step
+pub fn token_windows(text: &str, window: usize, overlap: usize) -> Vec<String> {
+    let starts = token_starts(text);
+    let window = window.max(1);
+    let step = window.saturating_sub(overlap).max(1);
+
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < starts.len() {
+        let last = (first + window).min(starts.len());
+        let end = starts.get(last).copied().unwrap_or(text.len());
+        chunks.push(text[starts[first]..end].trim().to_string());
+        if last == starts.len() {
+            break;
+        }
+        first += step;
+    }
+    chunks
+}
+
+/// Article text split with the configured window, overlap and chunk cap
+pub fn article_chunks(text: &str) -> Vec<String> {
+    let mut chunks = token_windows(text, window_tokens(), overlap_tokens());
+    chunks.truncate(max_chunks());
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_windows() {
+        // 大 模 型 hello world 应 用 -> 7 tokens
+        let text = "大模型 hello world 应用";
+        let chunks = token_windows(text, 4, 1);
+        assert_eq!(chunks, vec!["大模型 hello", "hello world 应用"]);
+        assert_eq!(token_windows(text, 10, 2), vec![text]);
+        assert!(token_windows("   ", 4, 1).is_empty());
+    }
+}
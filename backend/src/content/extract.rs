@@ -0,0 +1,518 @@
+//! Article extraction
+//!
+//! Parses a WeChat article page, isolates `#js_content`, drops scripts, ads and
+//! hidden tracking markup, resolves lazy-loaded images and returns the article as
+//! metadata plus a flat list of body blocks. The blocks can be rendered back to
+//! clean HTML (PDF) or plain text (embeddings); Markdown comes from
+//! `content::markdown`, which keeps inline formatting.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use scraper::{ElementRef, Html, Node, Selector};
+use serde::Serialize;
+
+lazy_static! {
+    static ref CONTENT_SEL: Selector = Selector::parse("#js_content, .rich_media_content").unwrap();
+    static ref BODY_SEL: Selector = Selector::parse("body").unwrap();
+    static ref TITLE_SEL: Selector =
+        Selector::parse("#activity-name, .rich_media_title, meta[property=\"og:title\"], title")
+            .unwrap();
+    static ref AUTHOR_SEL: Selector = Selector::parse("meta[name=\"author\"]").unwrap();
+    static ref BYLINE_SEL: Selector = Selector::parse("#js_author_name").unwrap();
+    static ref COPYRIGHT_SEL: Selector = Selector::parse("#copyright_logo").unwrap();
+    static ref DESCRIPTION_SEL: Selector =
+        Selector::parse("meta[name=\"description\"], meta[property=\"og:description\"]").unwrap();
+    static ref ACCOUNT_SEL: Selector = Selector::parse("#js_name, .wx_follow_nickname").unwrap();
+    static ref CT_RE: Regex = Regex::new(r#"\bct\s*=\s*"(\d{9,11})""#).unwrap();
+    static ref CREATE_TIME_RE: Regex =
+        Regex::new(r#"create_time\s*[:=]\s*(?:JsDecode\()?['"](\d{9,11})['"]"#).unwrap();
+    static ref HIDDEN_STYLE_RE: Regex = Regex::new(r"(?i)display\s*:\s*none").unwrap();
+}
+
+/// Elements that never carry article content
+pub(crate) const SKIPPED_TAGS: &[&str] = &[
+    "script",
+    "style",
+    "noscript",
+    "iframe",
+    "svg",
+    "mpvoice",
+    "mpvoicecard",
+    "mp-miniprogram",
+    "mp-common-profile",
+    "qqmusic",
+    "form",
+    "button",
+    "input",
+    "textarea",
+];
+
+/// Hosts of tracking pixels / report beacons embedded in articles
+const TRACKING_HOSTS: &[&str] = &["mp.weixin.qq.com/mp/", "badjs", "report.url.cn"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Heading { level: u8, text: String },
+    Paragraph { text: String },
+    Quote { text: String },
+    ListItem { ordered: bool, text: String },
+    Code { text: String },
+    Image { src: String, alt: Option<String> },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExtractedArticle {
+    pub title: String,
+    /// Article author (`meta[name=author]` or the byline), often empty on WeChat
+    pub author: Option<String>,
+    /// Name of the publishing official account
+    pub account_name: Option<String>,
+    /// Summary from the page's description meta tags
+    pub digest: Option<String>,
+    /// Unix timestamp parsed from the page scripts
+    pub publish_time: Option<i64>,
+    pub blocks: Vec<Block>,
+}
+
+impl ExtractedArticle {
+    /// Plain text, one block per line (images omitted)
+    pub fn text(&self) -> String {
+        self.blocks
+            .iter()
+            .filter_map(|b| match b {
+                Block::Heading { text, .. }
+                | Block::Paragraph { text }
+                | Block::Quote { text }
+                | Block::ListItem { text, .. }
+                | Block::Code { text } => Some(text.as_str()),
+                Block::Image { .. } => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Clean body HTML without WeChat's inline layout
+    pub fn to_html(&self) -> String {
+        self.render_html(None)
+    }
+
+    /// `to_html` with headings anchored as `<prefix>-<n>`, `n` counting
+    /// headings from 1 in the order of `headings`
+    pub fn to_anchored_html(&self, prefix: &str) -> String {
+        self.render_html(Some(prefix))
+    }
+
+    /// Level and text of each heading
+    pub fn headings(&self) -> impl Iterator<Item = (u8, &str)> {
+        self.blocks.iter().filter_map(|b| match b {
+            Block::Heading { level, text } => Some((*level, text.as_str())),
+            _ => None,
+        })
+    }
+
+    fn render_html(&self, anchor_prefix: Option<&str>) -> String {
+        use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+
+        let mut html = String::new();
+        let mut open_list: Option<bool> = None;
+        let mut heading = 0;
+        for block in &self.blocks {
+            let ordered = match block {
+                Block::ListItem { ordered, .. } => Some(*ordered),
+                _ => None,
+            };
+            if open_list.is_some() && open_list != ordered {
+                html.push_str(if open_list == Some(true) {
+                    "</ol>"
+                } else {
+                    "</ul>"
+                });
+                open_list = None;
+            }
+            match block {
+                Block::Heading { level, text: t } => {
+                    heading += 1;
+                    let id = anchor_prefix
+                        .map(|prefix| format!(" id=\"{}-{}\"", attr(prefix), heading))
+                        .unwrap_or_default();
+                    html.push_str(&format!("<h{0}{1}>{2}</h{0}>", level, id, text(t)))
+                }
+                Block::Paragraph { text: t } => html.push_str(&format!("<p>{}</p>", text(t))),
+                Block::Quote { text: t } => {
+                    html.push_str(&format!("<blockquote>{}</blockquote>", text(t)))
+                }
+                Block::Code { text: t } => {
+                    html.push_str(&format!("<pre><code>{}</code></pre>", text(t)))
+                }
+                Block::Image { src, alt } => html.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\">",
+                    attr(src),
+                    attr(alt.as_deref().unwrap_or(""))
+                )),
+                Block::ListItem { ordered, text: t } => {
+                    if open_list.is_none() {
+                        html.push_str(if *ordered { "<ol>" } else { "<ul>" });
+                        open_list = Some(*ordered);
+                    }
+                    html.push_str(&format!("<li>{}</li>", text(t)));
+                }
+            }
+        }
+        if let Some(ordered) = open_list {
+            html.push_str(if ordered { "</ol>" } else { "</ul>" });
+        }
+        html
+    }
+}
+
+/// Extract a WeChat article page. Falls back to the whole `<body>` when the
+/// page has no `#js_content` (non-standard or already cleaned HTML).
+pub fn extract(html: &str) -> ExtractedArticle {
+    let doc = Html::parse_document(html);
+
+    let title = doc
+        .select(&TITLE_SEL)
+        .map(|el| match el.value().attr("content") {
+            Some(content) => normalize_text(content),
+            None => element_text(el),
+        })
+        .find(|t| !t.is_empty())
+        .unwrap_or_default();
+    let author = author(&doc, html);
+    let account_name = doc
+        .select(&ACCOUNT_SEL)
+        .map(element_text)
+        .find(|t| !t.is_empty());
+    let digest = doc
+        .select(&DESCRIPTION_SEL)
+        .filter_map(|el| el.value().attr("content"))
+        .map(normalize_text)
+        .find(|t| !t.is_empty());
+    let publish_time = publish_time(html);
+
+    let mut walker = BlockWalker::default();
+    if let Some(root) = content_root(&doc) {
+        walker.walk(root);
+    }
+    walker.flush();
+
+    ExtractedArticle {
+        title,
+        author,
+        account_name,
+        digest,
+        publish_time,
+        blocks: walker.blocks,
+    }
+}
+
+/// Byline and provenance of an article page, stored with the article each
+/// time its HTML is fetched (see `repository::articles::save`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PageMetadata {
+    pub author: Option<String>,
+    /// Marked 原创 by the account; `None` when the page does not say
+    pub original: Option<bool>,
+    pub publish_time: Option<i64>,
+    /// `__biz` of the publishing account
+    pub biz: Option<String>,
+}
+
+impl PageMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Metadata of an article page, read from its meta tags, byline and embedded
+/// JS variables without extracting the body
+pub fn metadata(html: &str, url: &str) -> PageMetadata {
+    let doc = Html::parse_document(html);
+    PageMetadata {
+        author: author(&doc, html),
+        original: original(&doc, html),
+        publish_time: publish_time(html),
+        biz: crate::album::page_biz(html, url),
+    }
+}
+
+/// `meta[name=author]`, then the `#js_author_name` byline, then `var author`
+fn author(doc: &Html, html: &str) -> Option<String> {
+    doc.select(&AUTHOR_SEL)
+        .filter_map(|el| el.value().attr("content"))
+        .map(normalize_text)
+        .chain(doc.select(&BYLINE_SEL).map(element_text))
+        .find(|t| !t.is_empty())
+        .or_else(|| crate::comments::js_var(html, "author"))
+}
+
+/// `copyright_stat` is "11" for articles declared original; the 原创 badge
+/// (`#copyright_logo`) covers pages that render it without the variable
+fn original(doc: &Html, html: &str) -> Option<bool> {
+    if doc.select(&COPYRIGHT_SEL).next().is_some() {
+        return Some(true);
+    }
+    crate::comments::js_var(html, "copyright_stat")
+        .or_else(|| crate::comments::js_var(html, "_copyright_stat"))
+        .map(|stat| stat == "11")
+}
+
+fn publish_time(html: &str) -> Option<i64> {
+    CT_RE
+        .captures(html)
+        .or_else(|| CREATE_TIME_RE.captures(html))
+        .and_then(|c| c[1].parse().ok())
+}
+
+/// `#js_content`, or the whole `<body>` of other pages
+pub(crate) fn content_root(doc: &Html) -> Option<ElementRef<'_>> {
+    doc.select(&CONTENT_SEL)
+        .next()
+        .or_else(|| doc.select(&BODY_SEL).next())
+}
+
+/// Collapse whitespace and decode nothing further (scraper already decoded entities)
+fn normalize_text(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn element_text(el: ElementRef) -> String {
+    normalize_text(&el.text().collect::<String>())
+}
+
+/// Real image URL, preferring lazy-load attributes over placeholders
+pub(crate) fn image_src(el: &ElementRef) -> Option<String> {
+    let value = el.value();
+    let src = ["data-src", "src", "data-original"]
+        .iter()
+        .filter_map(|a| value.attr(a))
+        .map(str::trim)
+        .find(|s| !s.is_empty() && !s.starts_with("data:"))?;
+
+    if TRACKING_HOSTS.iter().any(|h| src.contains(h)) {
+        return None;
+    }
+    Some(if src.starts_with("//") {
+        format!("https:{}", src)
+    } else {
+        src.to_string()
+    })
+}
+
+pub(crate) fn is_hidden(el: &ElementRef) -> bool {
+    let value = el.value();
+    value.attr("hidden").is_some()
+        || value
+            .attr("style")
+            .is_some_and(|s| HIDDEN_STYLE_RE.is_match(s))
+        || value
+            .attr("class")
+            .is_some_and(|c| c.contains("js_ad") || c.contains("qr_code"))
+}
+
+#[derive(Default)]
+struct BlockWalker {
+    blocks: Vec<Block>,
+    buffer: String,
+}
+
+impl BlockWalker {
+    fn flush(&mut self) {
+        let text = normalize_text(&self.buffer);
+        self.buffer.clear();
+        if !text.is_empty() {
+            self.blocks.push(Block::Paragraph { text });
+        }
+    }
+
+    fn walk(&mut self, el: ElementRef) {
+        for child in el.children() {
+            match child.value() {
+                Node::Text(t) => self.buffer.push_str(t),
+                Node::Element(_) => {
+                    if let Some(child_el) = ElementRef::wrap(child) {
+                        self.visit(child_el);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn visit(&mut self, el: ElementRef) {
+        let name = el.value().name();
+        if SKIPPED_TAGS.contains(&name) || is_hidden(&el) {
+            return;
+        }
+
+        match name {
+            "img" => {
+                if let Some(src) = image_src(&el) {
+                    self.flush();
+                    let alt = el
+                        .value()
+                        .attr("alt")
+                        .map(normalize_text)
+                        .filter(|a| !a.is_empty());
+                    self.blocks.push(Block::Image { src, alt });
+                }
+            }
+            "br" => self.flush(),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                let text = element_text(el);
+                if !text.is_empty() {
+                    let level = name[1..].parse().unwrap_or(2);
+                    self.blocks.push(Block::Heading { level, text });
+                }
+                self.walk_images(el);
+            }
+            "blockquote" => {
+                self.flush();
+                let text = element_text(el);
+                if !text.is_empty() {
+                    self.blocks.push(Block::Quote { text });
+                }
+            }
+            "pre" => {
+                self.flush();
+                let text = el.text().collect::<String>().trim_end().to_string();
+                if !text.trim().is_empty() {
+                    self.blocks.push(Block::Code { text });
+                }
+            }
+            "li" => {
+                self.flush();
+                let ordered = el
+                    .parent()
+                    .and_then(ElementRef::wrap)
+                    .is_some_and(|p| p.value().name() == "ol");
+                let text = element_text(el);
+                if !text.is_empty() {
+                    self.blocks.push(Block::ListItem { ordered, text });
+                }
+                self.walk_images(el);
+            }
+            "p" | "section" | "div" | "ul" | "ol" | "table" | "tr" | "figure" | "figcaption"
+            | "hr" => {
+                self.flush();
+                self.walk(el);
+                self.flush();
+            }
+            // Inline elements (span, strong, a, em ...) contribute to the current paragraph
+            _ => self.walk(el),
+        }
+    }
+
+    /// Images nested inside a block that was collected as text
+    fn walk_images(&mut self, el: ElementRef) {
+        for img in el.descendants().filter_map(ElementRef::wrap) {
+            if img.value().name() == "img" {
+                if let Some(src) = image_src(&img) {
+                    self.blocks.push(Block::Image { src, alt: None });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head>
+        <meta property="og:title" content="测试 标题">
+        <meta name="author" content="张三">
+        <meta name="description" content=" 一段 摘要 ">
+        </head><body>
+        <h1 class="rich_media_title" id="activity-name"> 测试 标题 </h1>
+        <a id="js_name">某公众号</a>
+        <div class="rich_media_content" id="js_content" style="visibility: hidden;">
+          <section><span>第一段</span><strong>加粗</strong></section>
+          <h2>小标题</h2>
+          <p><img data-src="//mmbiz.qpic.cn/a.jpg" src="data:image/gif;base64,R0l"></p>
+          <ul><li>甲</li><li>乙</li></ul>
+          <p style="display:none">隐藏</p>
+          <script>var x = 1;</script>
+          <img src="https://mp.weixin.qq.com/mp/report?x=1">
+        </div>
+        <script>var ct = "1700000000";</script>
+        </body></html>"#;
+
+    #[test]
+    fn test_extract_metadata() {
+        let article = extract(PAGE);
+        assert_eq!(article.title, "测试 标题");
+        assert_eq!(article.author.as_deref(), Some("张三"));
+        assert_eq!(article.account_name.as_deref(), Some("某公众号"));
+        assert_eq!(article.digest.as_deref(), Some("一段 摘要"));
+        assert_eq!(article.publish_time, Some(1700000000));
+    }
+
+    #[test]
+    fn test_page_metadata() {
+        let page = r#"<html><body>
+            <span id="copyright_logo">原创</span>
+            <a id="js_author_name">李四</a>
+            <script>var biz = "" || "MzA5NjQ=";
+            var copyright_stat = "11";
+            var ct = "1700000000";</script>
+            </body></html>"#;
+        let meta = metadata(page, "https://mp.weixin.qq.com/s/abc");
+        assert_eq!(meta.author.as_deref(), Some("李四"));
+        assert_eq!(meta.original, Some(true));
+        assert_eq!(meta.publish_time, Some(1700000000));
+        assert_eq!(meta.biz.as_deref(), Some("MzA5NjQ="));
+
+        let meta = metadata(
+            r#"<script>var copyright_stat = "0";</script>"#,
+            "https://mp.weixin.qq.com/s?__biz=MzI1&mid=1",
+        );
+        assert_eq!(meta.author, None);
+        assert_eq!(meta.original, Some(false));
+        assert_eq!(meta.biz.as_deref(), Some("MzI1"));
+        assert!(metadata("<p>x</p>", "").is_empty());
+    }
+
+    #[test]
+    fn test_extract_blocks() {
+        let article = extract(PAGE);
+        assert_eq!(
+            article.blocks,
+            vec![
+                Block::Paragraph {
+                    text: "第一段加粗".to_string()
+                },
+                Block::Heading {
+                    level: 2,
+                    text: "小标题".to_string()
+                },
+                Block::Image {
+                    src: "https://mmbiz.qpic.cn/a.jpg".to_string(),
+                    alt: None
+                },
+                Block::ListItem {
+                    ordered: false,
+                    text: "甲".to_string()
+                },
+                Block::ListItem {
+                    ordered: false,
+                    text: "乙".to_string()
+                },
+            ]
+        );
+        assert_eq!(article.text(), "第一段加粗\n小标题\n甲\n乙");
+        assert_eq!(
+            crate::content::markdown::to_markdown(PAGE),
+            "第一段**加粗**\n\n## 小标题\n\n![](https://mmbiz.qpic.cn/a.jpg)\n\n- 甲\n- 乙"
+        );
+        assert!(article
+            .to_html()
+            .ends_with("<ul><li>甲</li><li>乙</li></ul>"));
+        assert!(article
+            .to_anchored_html("article-1")
+            .contains("<h2 id=\"article-1-1\">小标题</h2>"));
+        assert_eq!(article.headings().collect::<Vec<_>>(), vec![(2, "小标题")]);
+    }
+}
@@ -0,0 +1,543 @@
+//! WeChat-aware HTML to Markdown
+//!
+//! Article bodies nest every line in layers of `<section>` and styled
+//! `<span>`s: bold and italic are often inline styles rather than tags, code
+//! snippets put each line in its own `<code>`, single-cell tables are used as
+//! boxes and captions are short centred paragraphs under the image. This
+//! converter flattens the sections into Markdown blocks while keeping inline
+//! emphasis, links, inline code, quotes, nested lists, code blocks, tables and
+//! image captions. It works on `#js_content` like `extract`, which remains the
+//! source of plain text and of the PDF / DOCX blocks.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use scraper::{ElementRef, Html, Node};
+
+use crate::content::extract::{content_root, image_src, is_hidden, SKIPPED_TAGS};
+
+lazy_static! {
+    static ref BOLD_RE: Regex =
+        Regex::new(r"(?i)font-weight\s*:\s*(bold|bolder|[6-9]00)\b").unwrap();
+    static ref ITALIC_RE: Regex = Regex::new(r"(?i)font-style\s*:\s*italic").unwrap();
+    static ref STRIKE_RE: Regex = Regex::new(r"(?i)text-decoration[^;]*line-through").unwrap();
+    static ref CENTER_RE: Regex = Regex::new(r"(?i)text-align\s*:\s*center").unwrap();
+    static ref CODE_LANG_RE: Regex = Regex::new(r"(?:code-snippet__|language-)(\w+)").unwrap();
+    static ref ORDERED_RE: Regex = Regex::new(r"^\d+[.)]").unwrap();
+}
+
+/// Longest centred paragraph under an image still taken as its caption
+const MAX_CAPTION_CHARS: usize = 60;
+
+/// Markdown of an article page or fragment, images kept as they are
+pub fn to_markdown(html: &str) -> String {
+    to_markdown_with(html, &|src| src.to_string())
+}
+
+/// Markdown of an article page or fragment; `map_src` rewrites each image
+/// source (e.g. to a path relative to the file)
+pub fn to_markdown_with(html: &str, map_src: &dyn Fn(&str) -> String) -> String {
+    let doc = Html::parse_document(html);
+    let Some(root) = content_root(&doc) else {
+        return String::new();
+    };
+    let mut converter = Converter::new(map_src);
+    converter.children(root);
+    converter.flush();
+    join(&converter.blocks)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    Image,
+}
+
+/// Open inline markup, written around its text once the element ends
+struct Span {
+    start: usize,
+    open: String,
+    close: String,
+}
+
+struct Converter<'a> {
+    map_src: &'a dyn Fn(&str) -> String,
+    blocks: Vec<(Kind, String)>,
+    /// Inline Markdown of the paragraph being built
+    buffer: String,
+    spans: Vec<Span>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(map_src: &'a dyn Fn(&str) -> String) -> Self {
+        Self {
+            map_src,
+            blocks: Vec::new(),
+            buffer: String::new(),
+            spans: Vec::new(),
+        }
+    }
+
+    /// Blocks of an element's content, converted on their own
+    fn sub(&self, el: ElementRef) -> Vec<(Kind, String)> {
+        let mut converter = Converter::new(self.map_src);
+        converter.children(el);
+        converter.flush();
+        converter.blocks
+    }
+
+    /// End the current paragraph. Open spans are closed here and continue
+    /// into the next paragraph.
+    fn flush(&mut self) {
+        for i in (0..self.spans.len()).rev() {
+            wrap(&mut self.buffer, &self.spans[i]);
+        }
+        for span in &mut self.spans {
+            span.start = 0;
+        }
+        let text = std::mem::take(&mut self.buffer);
+        let text = text.trim();
+        if !text.is_empty() {
+            self.blocks.push((Kind::Text, escape_line_start(text)));
+        }
+    }
+
+    fn push_block(&mut self, kind: Kind, block: String) {
+        self.flush();
+        if !block.trim().is_empty() {
+            self.blocks.push((kind, block));
+        }
+    }
+
+    fn children(&mut self, el: ElementRef) {
+        for child in el.children() {
+            match child.value() {
+                Node::Text(t) => self.text(t),
+                Node::Element(_) => {
+                    if let Some(child_el) = ElementRef::wrap(child) {
+                        self.element(child_el);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Append text with whitespace collapsed and Markdown syntax escaped
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !self.buffer.is_empty() && !self.buffer.ends_with(' ') {
+                    self.buffer.push(' ');
+                }
+                continue;
+            }
+            if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '~') {
+                self.buffer.push('\\');
+            }
+            self.buffer.push(c);
+        }
+    }
+
+    fn element(&mut self, el: ElementRef) {
+        let name = el.value().name();
+        if SKIPPED_TAGS.contains(&name) || is_hidden(&el) {
+            return;
+        }
+
+        match name {
+            "img" => self.image(el),
+            "br" => self.flush(),
+            "hr" => self.push_block(Kind::Text, "---".to_string()),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.heading(el, name[1..].parse().unwrap_or(2))
+            }
+            "blockquote" => self.quote(el),
+            "pre" => self.code_block(el),
+            "ul" | "ol" => {
+                // Line numbers next to WeChat code snippets
+                if !has_class(&el, "code-snippet__line-index") {
+                    self.list(el, name == "ol");
+                }
+            }
+            "table" => self.table(el),
+            "code" => {
+                let code: String = el.text().collect();
+                if !code.trim().is_empty() {
+                    let fence = if code.contains('`') { "``" } else { "`" };
+                    self.buffer
+                        .push_str(&format!("{0}{1}{0}", fence, code.trim()));
+                }
+            }
+            "p" | "section" | "div" | "figure" | "figcaption" | "header" | "footer" | "article"
+            | "center" => self.paragraph(el),
+            _ => {
+                let pushed = self.open_spans(&el);
+                self.children(el);
+                self.close_spans(pushed);
+            }
+        }
+    }
+
+    /// Emphasis, strike-through or link markup of an inline element; returns
+    /// how many spans were opened
+    fn open_spans(&mut self, el: &ElementRef) -> usize {
+        let value = el.value();
+        let style = value.attr("style").unwrap_or("");
+        let mut markers: Vec<(String, String)> = Vec::new();
+        let mut marker = |open: &str, close: &str| markers.push((open.into(), close.into()));
+        match value.name() {
+            "strong" | "b" => marker("**", "**"),
+            "em" | "i" => marker("*", "*"),
+            "del" | "s" | "strike" => marker("~~", "~~"),
+            "a" => {
+                if let Some(href) = value
+                    .attr("href")
+                    .map(str::trim)
+                    .filter(|h| h.starts_with("http"))
+                {
+                    marker("[", &format!("]({})", href.replace(' ', "%20")));
+                }
+            }
+            _ => {}
+        }
+        if BOLD_RE.is_match(style) {
+            marker("**", "**");
+        }
+        if ITALIC_RE.is_match(style) {
+            marker("*", "*");
+        }
+        if STRIKE_RE.is_match(style) {
+            marker("~~", "~~");
+        }
+
+        let mut pushed = 0;
+        for (open, close) in markers {
+            // Nested bold in bold would read `****`
+            if self.spans.iter().any(|s| s.open == open) {
+                continue;
+            }
+            self.spans.push(Span {
+                start: self.buffer.len(),
+                open,
+                close,
+            });
+            pushed += 1;
+        }
+        pushed
+    }
+
+    fn close_spans(&mut self, count: usize) {
+        for _ in 0..count {
+            if let Some(span) = self.spans.pop() {
+                wrap(&mut self.buffer, &span);
+            }
+        }
+    }
+
+    /// A block container. A short centred one right under an image is the
+    /// image's caption.
+    fn paragraph(&mut self, el: ElementRef) {
+        self.flush();
+        let before = self.blocks.len();
+        let pushed = self.open_spans(&el);
+        self.children(el);
+        self.flush();
+        self.close_spans(pushed);
+
+        let centred = el.value().name() == "figcaption"
+            || el
+                .value()
+                .attr("style")
+                .is_some_and(|s| CENTER_RE.is_match(s));
+        if !centred || before == 0 || self.blocks.len() != before + 1 {
+            return;
+        }
+        let (kind, text) = &self.blocks[before];
+        let caption = text.replace("**", "").replace(['*', '_'], "");
+        let caption = caption.trim().trim_start_matches('\\');
+        if *kind == Kind::Text
+            && self.blocks[before - 1].0 == Kind::Image
+            && !text.contains('\n')
+            && caption.chars().count() <= MAX_CAPTION_CHARS
+        {
+            let caption = format!("\n*{}*", caption);
+            self.blocks.pop();
+            self.blocks[before - 1].1.push_str(&caption);
+        }
+    }
+
+    fn image(&mut self, el: ElementRef) {
+        let Some(src) = image_src(&el) else {
+            return;
+        };
+        let src = (self.map_src)(&src);
+        let src = if src.contains([' ', '(', ')']) {
+            format!("<{}>", src)
+        } else {
+            src
+        };
+        let alt = el
+            .value()
+            .attr("alt")
+            .map(|a| a.split_whitespace().collect::<Vec<_>>().join(" "))
+            .unwrap_or_default()
+            .replace(['[', ']'], "");
+        self.push_block(Kind::Image, format!("![{}]({})", alt, src));
+    }
+
+    fn heading(&mut self, el: ElementRef, level: usize) {
+        self.flush();
+        let blocks = self.sub(el);
+        let text = blocks
+            .iter()
+            .filter(|(kind, _)| *kind == Kind::Text)
+            .map(|(_, text)| text.replace("**", ""))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.trim().is_empty() {
+            self.blocks
+                .push((Kind::Text, format!("{} {}", "#".repeat(level), text.trim())));
+        }
+        self.blocks
+            .extend(blocks.into_iter().filter(|(kind, _)| *kind == Kind::Image));
+    }
+
+    fn quote(&mut self, el: ElementRef) {
+        self.flush();
+        let quoted = join(&self.sub(el));
+        let quoted = quoted
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    ">".to_string()
+                } else {
+                    format!("> {}", line)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.push_block(Kind::Text, quoted);
+    }
+
+    fn code_block(&mut self, el: ElementRef) {
+        let code = code_text(el);
+        if code.trim().is_empty() {
+            return;
+        }
+        let lang = std::iter::once(el)
+            .chain(el.children().filter_map(ElementRef::wrap))
+            .filter_map(|e| e.value().attr("class"))
+            .find_map(|class| CODE_LANG_RE.captures(class).map(|c| c[1].to_string()))
+            .unwrap_or_default();
+        let fence = if code.contains("```") { "~~~~" } else { "```" };
+        self.push_block(Kind::Text, format!("{0}{1}\n{2}\n{0}", fence, lang, code));
+    }
+
+    fn list(&mut self, el: ElementRef, ordered: bool) {
+        self.flush();
+        let mut number: usize = el
+            .value()
+            .attr("start")
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(1);
+        let mut items = Vec::new();
+        for li in el.children().filter_map(ElementRef::wrap) {
+            if li.value().name() != "li" || is_hidden(&li) {
+                continue;
+            }
+            let content = join(&self.sub(li));
+            if content.trim().is_empty() {
+                continue;
+            }
+            let marker = if ordered {
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+            number += 1;
+            let indent = " ".repeat(marker.len());
+            let item = content
+                .lines()
+                .enumerate()
+                .map(|(i, line)| match i {
+                    0 => format!("{}{}", marker, line),
+                    _ if line.is_empty() => String::new(),
+                    _ => format!("{}{}", indent, line),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            items.push(item);
+        }
+        self.push_block(Kind::Text, items.join("\n"));
+    }
+
+    /// GFM table, first row as header. Single-column tables are layout boxes
+    /// and keep their content as ordinary blocks.
+    fn table(&mut self, el: ElementRef) {
+        self.flush();
+        let rows: Vec<Vec<ElementRef>> = el
+            .descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|e| e.value().name() == "tr")
+            .map(|tr| {
+                tr.children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|c| matches!(c.value().name(), "td" | "th"))
+                    .collect()
+            })
+            .filter(|cells: &Vec<ElementRef>| !cells.is_empty())
+            .collect();
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+        if columns <= 1 {
+            for cell in rows.into_iter().flatten() {
+                let blocks = self.sub(cell);
+                self.blocks.extend(blocks);
+            }
+            return;
+        }
+
+        let mut lines = Vec::with_capacity(rows.len() + 1);
+        for (i, row) in rows.iter().enumerate() {
+            let mut cells: Vec<String> = row
+                .iter()
+                .map(|cell| {
+                    self.sub(*cell)
+                        .into_iter()
+                        .map(|(_, text)| text)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .replace('\n', " ")
+                        .replace('|', "\\|")
+                })
+                .collect();
+            cells.resize(columns, String::new());
+            lines.push(format!("| {} |", cells.join(" | ")));
+            if i == 0 {
+                lines.push(format!("|{}", " --- |".repeat(columns)));
+            }
+        }
+        self.blocks.push((Kind::Text, lines.join("\n")));
+    }
+}
+
+/// Write `span` around the buffer's text since it opened; whitespace stays
+/// outside the markers and spans without text leave no markers
+fn wrap(buffer: &mut String, span: &Span) {
+    let segment = buffer.split_off(span.start.min(buffer.len()));
+    let trimmed = segment.trim();
+    if trimmed.is_empty() {
+        buffer.push_str(&segment);
+        return;
+    }
+    let leading = &segment[..segment.len() - segment.trim_start().len()];
+    let trailing = &segment[segment.trim_end().len()..];
+    buffer.push_str(leading);
+    buffer.push_str(&span.open);
+    buffer.push_str(trimmed);
+    buffer.push_str(&span.close);
+    buffer.push_str(trailing);
+}
+
+/// Escape a paragraph start that would read as a heading, quote or list
+fn escape_line_start(text: &str) -> String {
+    if text.starts_with(['#', '>', '-', '+']) || ORDERED_RE.is_match(text) {
+        format!("\\{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn join(blocks: &[(Kind, String)]) -> String {
+    blocks
+        .iter()
+        .map(|(_, block)| block.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn has_class(el: &ElementRef, class: &str) -> bool {
+    el.value()
+        .attr("class")
+        .is_some_and(|c| c.split_whitespace().any(|c| c == class))
+}
+
+/// Text of a code block with its line breaks; WeChat snippets hold one
+/// `<code>` per line
+fn code_text(el: ElementRef) -> String {
+    let lines: Vec<ElementRef> = el
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|c| c.value().name() == "code")
+        .collect();
+    let text = if lines.len() > 1 {
+        lines
+            .into_iter()
+            .map(raw_text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        raw_text(el)
+    };
+    text.trim_matches('\n').trim_end().to_string()
+}
+
+fn raw_text(el: ElementRef) -> String {
+    let mut text = String::new();
+    for node in el.descendants() {
+        match node.value() {
+            Node::Text(t) => text.push_str(t),
+            Node::Element(e) if e.name() == "br" => text.push('\n'),
+            _ => {}
+        }
+    }
+    text.replace('\u{a0}', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let html = r#"<div id="js_content">
+          <section><section><p><span style="font-weight: bold;">加粗</span><span>正文 a_b </span><em>斜体</em></p></section></section>
+          <h2><span><strong>小标题</strong></span></h2>
+          <section style="text-align: center"><img data-src="https://mmbiz.qpic.cn/a.jpg"></section>
+          <section style="text-align: center"><span style="color: #888">图源：网络</span></section>
+          <p>第二段<a href="https://example.com/x">链接</a><code>x = 1</code></p>
+          <ul><li><p>甲</p></li><li>乙<ol><li>子项</li></ol></li></ul>
+          <blockquote><p>引用一</p><p>引用二</p></blockquote>
+          <section class="code-snippet__fix"><ul class="code-snippet__line-index"><li></li></ul>
+            <pre class="code-snippet__js"><code><span>let a = 1;</span></code><code><span>  a += 1;</span></code></pre></section>
+          <table><tr><td>名称</td><td>数值</td></tr><tr><td>A|B</td><td>1</td></tr></table>
+          <table><tr><td><p>一格表格</p></td></tr></table>
+          <p>1. 不是列表</p>
+          <p><strong> </strong></p>
+        </div>"#;
+        let md = to_markdown_with(html, &|src| {
+            src.replace("https://mmbiz.qpic.cn/", "images/")
+        });
+        assert_eq!(
+            md,
+            [
+                "**加粗**正文 a\\_b *斜体*",
+                "## 小标题",
+                "![](images/a.jpg)\n*图源：网络*",
+                "第二段[链接](https://example.com/x)`x = 1`",
+                "- 甲\n- 乙\n\n  1. 子项",
+                "> 引用一\n>\n> 引用二",
+                "```js\nlet a = 1;\n  a += 1;\n```",
+                "| 名称 | 数值 |\n| --- | --- |\n| A\\|B | 1 |",
+                "一格表格",
+                "\\1. 不是列表",
+            ]
+            .join("\n\n")
+        );
+    }
+
+    #[test]
+    fn test_spans_across_paragraphs() {
+        let md = to_markdown(r#"<body><strong>第一行<br>第二行</strong></body>"#);
+        assert_eq!(md, "**第一行**\n\n**第二行**");
+    }
+}
@@ -0,0 +1,8 @@
+//! Article content processing
+//!
+//! Turns raw WeChat article pages into structured, clean content.
+
+pub mod chunk;
+pub mod extract;
+pub mod markdown;
+pub mod sanitize;
@@ -0,0 +1,471 @@
+//! HTML sanitizer
+//!
+//! Rebuilds an article page from its parsed DOM for the viewer and for exports
+//! that render user-supplied HTML. Only allowlisted tags and attributes
+//! survive: scripts, iframes, forms, players and tracking pixels are dropped,
+//! event handlers and `javascript:` URLs cannot get through, lazy-loaded
+//! images get their real `src`, and the viewer stylesheet is injected into the
+//! head exactly once. Style sheets are written out as raw text, so `<` is
+//! escaped in them and `<style>` inside SVG (where the parser decodes entities
+//! in its text) is dropped; linked stylesheets must come from WeChat hosts. Unknown tags (WeChat's `mp-*` custom elements) are
+//! unwrapped so their text stays. Sanitizing sanitized output changes nothing.
+//!
+//! WeChat image hosts refuse hotlinked requests, so the viewer can have image
+//! URLs rewritten to this backend's asset endpoint (`ImageMode::Proxied`),
+//! which fetches and caches misses.
+
+use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+use scraper::{ElementRef, Html, Node};
+
+use super::extract::image_src;
+
+/// `id` of the injected viewer stylesheet, so a second pass can replace it
+const VIEWER_STYLE_ID: &str = "wechat-viewer-style";
+
+/// Removed together with their content
+const DROPPED_TAGS: &[&str] = &[
+    "script",
+    "noscript",
+    "iframe",
+    "frame",
+    "frameset",
+    "object",
+    "embed",
+    "applet",
+    "template",
+    "base",
+    "form",
+    "input",
+    "button",
+    "select",
+    "textarea",
+    "audio",
+    "video",
+    "source",
+    "track",
+    "canvas",
+    "mpvoice",
+    "mpvoicecard",
+    "mp-miniprogram",
+    "mp-common-profile",
+    "qqmusic",
+];
+
+const VOID_TAGS: &[&str] = &["br", "col", "hr", "img", "link", "meta", "wbr"];
+
+const VIEWER_STYLE: &str = r#"<style id="wechat-viewer-style">
+        #js_content {
+            visibility: visible !important;
+            opacity: 1 !important;
+            display: block !important;
+        }
+        #img-content {
+            display: block !important;
+        }
+        img {
+            max-width: 100% !important;
+            height: auto !important;
+            display: block !important;
+            margin: 0 auto;
+        }
+        body {
+            background-color: transparent !important;
+        }
+        /* Dark Mode Adaptation */
+        :is(.dark) #js_content,
+        :is(.dark) #activity-name,
+        :is(.dark) .rich_media_title,
+        :is(.dark) .rich_media_meta_list,
+        :is(.dark) .rich_media_meta_text {
+            color: #d1d5db !important; /* gray-300 */
+            background-color: transparent !important;
+        }
+
+        :is(.dark) #js_content *,
+        :is(.dark) #activity-name *,
+        :is(.dark) .rich_media_title *,
+        :is(.dark) .rich_media_meta_list * {
+            color: inherit !important; /* Force inheritance to override inline styles */
+            background-color: transparent !important;
+            border-color: #374151 !important;
+        }
+
+        :is(.dark) #js_content p,
+        :is(.dark) #js_content span,
+        :is(.dark) #js_content strong,
+        :is(.dark) #js_content h1,
+        :is(.dark) #js_content h2,
+        :is(.dark) #js_content h3,
+        :is(.dark) #js_content h4,
+        :is(.dark) #js_content h5,
+        :is(.dark) #js_content h6,
+        :is(.dark) #js_content li {
+             color: inherit !important;
+        }
+    </style>"#;
+
+/// Hosts `<link rel="stylesheet">` may load from
+fn is_allowed_stylesheet(href: &str) -> bool {
+    let href = href.trim();
+    let absolute = if href.starts_with("//") {
+        format!("https:{}", href)
+    } else {
+        href.to_string()
+    };
+    url::Url::parse(&absolute).is_ok_and(|u| {
+        matches!(u.scheme(), "http" | "https")
+            && u.host_str()
+                .is_some_and(|h| h == "res.wx.qq.com" || h.ends_with(".res.wx.qq.com"))
+    })
+}
+
+/// Style sheet text that cannot close its element or open another one
+fn style_text(css: &str) -> String {
+    css.replace('<', "\\3c ")
+}
+
+/// Kept as elements; anything not listed here or in `DROPPED_TAGS` is unwrapped
+fn is_allowed_tag(name: &str) -> bool {
+    matches!(
+        name,
+        // Document
+        "html" | "head" | "body" | "title" | "meta" | "link" | "style"
+        // Text and layout
+        | "a" | "abbr" | "article" | "aside" | "b" | "bdi" | "bdo" | "big" | "blockquote" | "br"
+        | "caption" | "center" | "cite" | "code" | "col" | "colgroup" | "dd" | "del" | "details"
+        | "dfn" | "div" | "dl" | "dt" | "em" | "figcaption" | "figure" | "font" | "footer" | "h1"
+        | "h2" | "h3" | "h4" | "h5" | "h6" | "header" | "hr" | "i" | "img" | "ins" | "kbd" | "li"
+        | "main" | "mark" | "nav" | "ol" | "p" | "pre" | "q" | "rp" | "rt" | "ruby" | "s" | "samp"
+        | "section" | "small" | "span" | "strike" | "strong" | "sub" | "summary" | "sup" | "table"
+        | "tbody" | "td" | "tfoot" | "th" | "thead" | "time" | "tr" | "tt" | "u" | "ul" | "var"
+        | "wbr"
+        // Static SVG used by WeChat layouts (no animation elements)
+        | "svg" | "g" | "defs" | "rect" | "circle" | "ellipse" | "line" | "path" | "polygon"
+        | "polyline" | "text" | "tspan" | "image" | "foreignObject"
+    )
+}
+
+fn is_allowed_attr(key: &str) -> bool {
+    matches!(
+        key,
+        "id" | "class" | "style" | "title" | "lang" | "dir" | "align" | "valign" | "width"
+        | "height" | "border" | "cellpadding" | "cellspacing" | "colspan" | "rowspan" | "span"
+        | "start" | "type" | "reversed" | "alt" | "color" | "face" | "size" | "bgcolor" | "datetime"
+        | "open" | "target" | "charset" | "name" | "content" | "property"
+        // SVG geometry and paint
+        | "viewBox" | "preserveAspectRatio" | "xmlns" | "x" | "y" | "cx" | "cy" | "r" | "rx" | "ry"
+        | "x1" | "y1" | "x2" | "y2" | "d" | "points" | "fill" | "fill-opacity" | "stroke"
+        | "stroke-width" | "opacity" | "transform"
+    ) || (key.starts_with("data-") && !key.contains("src") && !key.contains("url"))
+}
+
+/// Where the `src` of sanitized images points
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ImageMode<'a> {
+    /// The image's own URL
+    #[default]
+    Original,
+    /// WeChat images through `/api/public/v1/asset` on this backend, whose
+    /// public URL is given (empty for same-origin links)
+    Proxied(&'a str),
+}
+
+/// Sanitized, complete HTML document for `html` (a page or a fragment)
+pub fn sanitize(html: &str) -> String {
+    sanitize_with(html, ImageMode::Original)
+}
+
+/// `sanitize` with image URLs rewritten per `images`
+pub fn sanitize_with(html: &str, images: ImageMode) -> String {
+    let doc = Html::parse_document(html);
+    let mut out = String::with_capacity(html.len() / 2);
+    out.push_str("<!DOCTYPE html>");
+    write_element(&mut out, doc.root_element(), images);
+    out
+}
+
+/// Images served by WeChat's CDNs (`mmbiz.qpic.cn`, `wx.qlogo.cn`, ...)
+pub fn is_wechat_image(url: &str) -> bool {
+    url::Url::parse(url).is_ok_and(|u| {
+        matches!(u.scheme(), "http" | "https")
+            && u.host_str()
+                .is_some_and(|h| h.ends_with(".qpic.cn") || h.ends_with(".qlogo.cn"))
+    })
+}
+
+/// Link to an image through the asset endpoint
+pub fn asset_url(base: &str, url: &str) -> String {
+    format!(
+        "{}/api/public/v1/asset?url={}",
+        base.trim_end_matches('/'),
+        urlencoding::encode(url)
+    )
+}
+
+fn write_element(out: &mut String, el: ElementRef, images: ImageMode) {
+    let name = el.value().name();
+    if DROPPED_TAGS.contains(&name) || (name == "style" && el.value().id() == Some(VIEWER_STYLE_ID))
+    {
+        return;
+    }
+    // Foreign content decodes entities in style text, e.g. `&lt;/style&gt;`
+    if name == "style"
+        && el
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .any(|a| a.value().name() == "svg")
+    {
+        return;
+    }
+
+    let keep = is_allowed_tag(name);
+    if keep {
+        let Some(attrs) = attributes(&el, images) else {
+            return;
+        };
+        out.push('<');
+        out.push_str(name);
+        for (key, value) in attrs {
+            out.push_str(&format!(" {}=\"{}\"", key, attr(&value)));
+        }
+        out.push('>');
+        if VOID_TAGS.contains(&name) {
+            return;
+        }
+    }
+
+    for (i, child) in el.children().enumerate() {
+        match child.value() {
+            // Style text is written raw, so it must not contain markup
+            Node::Text(t) if name == "style" => out.push_str(&style_text(t)),
+            Node::Text(t) => {
+                // The parser drops one newline right after <pre>, keep the text's own
+                if i == 0 && name == "pre" && t.starts_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&text(&**t));
+            }
+            Node::Element(_) => {
+                if let Some(child_el) = ElementRef::wrap(child) {
+                    write_element(out, child_el, images);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if name == "head" {
+        out.push_str(VIEWER_STYLE);
+    }
+    if keep {
+        out.push_str("</");
+        out.push_str(name);
+        out.push('>');
+    }
+}
+
+/// Allowed attributes sorted by name, or `None` when the element must go
+/// (images without a usable source, links other than stylesheets)
+fn attributes(el: &ElementRef, images: ImageMode) -> Option<Vec<(String, String)>> {
+    let element = el.value();
+    let name = element.name();
+    let mut attrs: Vec<(String, String)> = element
+        .attrs()
+        .filter(|(key, value)| match *key {
+            "style" => safe_style(value),
+            "href" => matches!(name, "a" | "image") && safe_url(value),
+            "src" | "data-src" => false,
+            _ => is_allowed_attr(key),
+        })
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    match name {
+        "img" => {
+            let src = image_src(el).or_else(|| {
+                element
+                    .attr("src")
+                    .filter(|s| s.trim_start().starts_with("data:image/"))
+                    .map(str::to_string)
+            })?;
+            let src = match images {
+                ImageMode::Proxied(base) if is_wechat_image(&src) => asset_url(base, &src),
+                _ => src,
+            };
+            attrs.push(("src".to_string(), src));
+            attrs.push(("referrerpolicy".to_string(), "no-referrer".to_string()));
+        }
+        "a" if attrs.iter().any(|(k, _)| k == "href") => {
+            attrs.retain(|(k, _)| k != "rel");
+            attrs.push(("rel".to_string(), "noopener noreferrer".to_string()));
+        }
+        "link" => {
+            let href = element.attr("href").filter(|h| is_allowed_stylesheet(h))?;
+            if !element
+                .attr("rel")
+                .is_some_and(|r| r.eq_ignore_ascii_case("stylesheet"))
+            {
+                return None;
+            }
+            attrs = vec![
+                ("href".to_string(), href.to_string()),
+                ("rel".to_string(), "stylesheet".to_string()),
+            ];
+        }
+        // http-equiv is not allowed, so no refresh redirects
+        _ => {}
+    }
+
+    attrs.sort();
+    Some(attrs)
+}
+
+/// Relative URLs and http(s) / mailto links. Browsers ignore whitespace and
+/// control characters inside a scheme, so they are ignored here as well.
+fn safe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match url.find([':', '/', '?', '#']) {
+        Some(i) if url[i..].starts_with(':') => matches!(&url[..i], "http" | "https" | "mailto"),
+        _ => true,
+    }
+}
+
+/// Inline styles without script-capable CSS
+fn safe_style(style: &str) -> bool {
+    let style: String = style
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    !["expression(", "javascript:", "behavior:", "-moz-binding"]
+        .iter()
+        .any(|bad| style.contains(bad))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_markup() {
+        let html = r#"<html><head><meta http-equiv="refresh" content="0;url=https://evil.example">
+            <link rel="stylesheet" href="//res.wx.qq.com/a.css"><link rel="preload" href="/x.js">
+            <style id="wechat-viewer-style">old</style></head>
+            <body onload="steal()"><div id="js_content" style="visibility: hidden;">
+            <p onclick="x()">正文&lt;b&gt;<a href="java&#10;script:alert(1)">坏链接</a>
+            <a href="https://mp.weixin.qq.com/s/abc" rel="opener">好链接</a></p>
+            <img data-src="https://mmbiz.qpic.cn/a/640?wx_fmt=png&amp;from=appmsg" src="data:image/gif;base64,R0l">
+            <img src="https://mp.weixin.qq.com/mp/report?x=1">
+            <iframe src="https://v.qq.com/x"></iframe><script>alert(1)</script>
+            <mp-style-type data-value="3"><span>保留</span></mp-style-type>
+            <pre>
+code</pre></div></body></html>"#;
+        let out = sanitize(html);
+
+        for gone in [
+            "<script",
+            "<iframe",
+            "onload",
+            "onclick",
+            "javascript",
+            "refresh",
+            "preload",
+            "report",
+            "mp-style-type",
+            "old</style>",
+        ] {
+            assert!(!out.contains(gone), "{} survived", gone);
+        }
+        assert_eq!(out.matches(VIEWER_STYLE_ID).count(), 1);
+        assert!(out.contains(r#"<link href="//res.wx.qq.com/a.css" rel="stylesheet">"#));
+        assert!(out.contains("正文&lt;b&gt;<a>坏链接</a>"));
+        assert!(out.contains(
+            r#"<a href="https://mp.weixin.qq.com/s/abc" rel="noopener noreferrer">好链接</a>"#
+        ));
+        assert!(out.contains(
+            r#"<img referrerpolicy="no-referrer" src="https://mmbiz.qpic.cn/a/640?wx_fmt=png&amp;from=appmsg">"#
+        ));
+        assert!(out.contains("<span>保留</span>"));
+        assert_eq!(sanitize(&out), out);
+    }
+
+    #[test]
+    fn test_sanitize_style_breakout() {
+        let out = sanitize(
+            r#"<div><svg><style>&lt;/style&gt;&lt;img src=x onerror=alert(1)&gt;</style></svg></div>"#,
+        );
+        assert!(!out.contains("onerror"), "{}", out);
+        assert!(!out.contains("<img"));
+
+        let out =
+            sanitize("<style>p::after { content: '</p><img src=x onerror=alert(1)>' }</style>");
+        assert!(!out.contains("<img"), "{}", out);
+        assert!(!out.contains("<p>"));
+        assert_eq!(sanitize(&out), out);
+
+        let out = sanitize(
+            r#"<head><link rel="stylesheet" href="https://evil.example/x.css">
+            <link rel="stylesheet" href="/api/x.css"></head>"#,
+        );
+        assert!(!out.contains("<link"), "{}", out);
+        assert!(is_allowed_stylesheet("https://res.wx.qq.com/a.css"));
+        assert!(!is_allowed_stylesheet(
+            "https://res.wx.qq.com.evil.example/a.css"
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_wechat_pages() {
+        for html in [
+            include_str!("../../../samples/普通图文/01.html"),
+            include_str!("../../../samples/文章分享/03.html"),
+        ] {
+            let out = sanitize(html);
+            assert!(!out.contains("<script"));
+            assert!(!out.contains("<iframe"));
+            assert!(!out.contains(" data-src="));
+            assert_eq!(out.matches(VIEWER_STYLE_ID).count(), 1);
+            assert_eq!(sanitize(&out), out);
+
+            // Nothing of the article itself is lost
+            let before = super::super::extract::extract(html);
+            let after = super::super::extract::extract(&out);
+            assert_eq!(after.title, before.title);
+            assert_eq!(after.blocks, before.blocks);
+        }
+    }
+
+    #[test]
+    fn test_proxied_images() {
+        let html = r#"<p><img data-src="//mmbiz.qpic.cn/a/640?wx_fmt=png&amp;from=appmsg">
+            <img src="https://example.com/b.png"></p>"#;
+        let out = sanitize_with(html, ImageMode::Proxied("http://localhost:3001/"));
+        assert!(out.contains(
+            "src=\"http://localhost:3001/api/public/v1/asset?url=https%3A%2F%2Fmmbiz.qpic.cn%2Fa%2F640%3Fwx_fmt%3Dpng%26from%3Dappmsg\""
+        ));
+        assert!(out.contains("src=\"https://example.com/b.png\""));
+        assert_eq!(
+            sanitize_with(&out, ImageMode::Proxied("http://localhost:3001/")),
+            out
+        );
+
+        assert!(is_wechat_image("https://wx.qlogo.cn/mmhead/x/0"));
+        assert!(!is_wechat_image("https://qpic.cn.example.com/x"));
+        assert!(!is_wechat_image("file:///etc/passwd"));
+    }
+
+    #[test]
+    fn test_safe_url() {
+        assert!(safe_url("https://mp.weixin.qq.com/s/abc"));
+        assert!(safe_url("images/a.png"));
+        assert!(safe_url("#article-1"));
+        assert!(safe_url("/path?next=javascript:x"));
+        assert!(!safe_url(" JavaScript:alert(1)"));
+        assert!(!safe_url("jav\tascript:alert(1)"));
+        assert!(!safe_url("data:text/html,<script>"));
+    }
+}
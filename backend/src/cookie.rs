@@ -6,6 +6,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
+/// Lifetime of a fresh MP login
+pub const SESSION_TTL_SECS: i64 = 4 * 24 * 60 * 60;
+
 /// A single parsed cookie entity
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CookieEntity {
@@ -113,6 +116,18 @@ impl AccountCookie {
     }
 }
 
+/// Keepalive state of a stored session
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct SessionHealth {
+    pub auth_key: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub last_checked_at: Option<i64>,
+    pub last_ok_at: Option<i64>,
+    pub last_error: Option<String>,
+    pub check_failures: i32,
+}
+
 /// Cookie store with PostgreSQL persistence
 pub struct CookieStore {
     pool: PgPool,
@@ -123,44 +138,29 @@ impl CookieStore {
         Self { pool }
     }
 
-    /// Initialize the cookies table
-    pub async fn init(&self) -> Result<(), sqlx::Error> {
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS cookies (
-                auth_key TEXT PRIMARY KEY,
-                token TEXT NOT NULL,
-                cookies_json TEXT NOT NULL,
-                created_at INTEGER NOT NULL,
-                expires_at INTEGER NOT NULL
-            );
-            "#,
-        )
-        .execute(&self.pool)
-        .await?;
-        Ok(())
-    }
-
-    /// Store cookies for an auth key
+    /// Store cookies for an auth key, owned by `owner_id` when logged in as a user
     pub async fn set_cookie(
         &self,
         auth_key: &str,
         account_cookie: &AccountCookie,
+        owner_id: Option<uuid::Uuid>,
     ) -> Result<bool, sqlx::Error> {
         tracing::info!("Setting cookie for auth_key: {}", auth_key);
         let now = chrono::Utc::now().timestamp();
-        let expires_at = now + (4 * 24 * 60 * 60); // 4 days
+        let expires_at = now + SESSION_TTL_SECS;
         let cookies_json = serde_json::to_string(&account_cookie.cookies).unwrap_or_default();
 
         sqlx::query(
             r#"
-            INSERT INTO cookies (auth_key, token, cookies_json, created_at, expires_at)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO cookies (auth_key, token, cookies_json, created_at, expires_at, owner_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
             ON CONFLICT (auth_key) DO UPDATE SET
                 token = EXCLUDED.token,
                 cookies_json = EXCLUDED.cookies_json,
                 created_at = EXCLUDED.created_at,
-                expires_at = EXCLUDED.expires_at
+                expires_at = EXCLUDED.expires_at,
+                last_error = NULL,
+                check_failures = 0
             "#,
         )
         .bind(auth_key)
@@ -168,6 +168,7 @@ impl CookieStore {
         .bind(&cookies_json)
         .bind(now)
         .bind(expires_at)
+        .bind(owner_id)
         .execute(&self.pool)
         .await?;
 
@@ -247,6 +248,107 @@ impl CookieStore {
         }
     }
 
+    /// Auth keys of sessions not known to be expired
+    pub async fn live_auth_keys(&self) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT auth_key FROM cookies WHERE expires_at > $1 ORDER BY created_at DESC",
+        )
+        .bind(chrono::Utc::now().timestamp())
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Keepalive ping succeeded: merge re-issued cookies and move `expires_at`
+    /// (see `keepalive::next_expiry`)
+    pub async fn record_alive(
+        &self,
+        auth_key: &str,
+        refreshed: &[CookieEntity],
+        interval: i64,
+    ) -> Result<(), sqlx::Error> {
+        let row: Option<(String, i64)> =
+            sqlx::query_as("SELECT cookies_json, expires_at FROM cookies WHERE auth_key = $1")
+                .bind(auth_key)
+                .fetch_optional(&self.pool)
+                .await?;
+        let Some((cookies_json, expires_at)) = row else {
+            return Ok(());
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        let mut cookies: Vec<CookieEntity> =
+            serde_json::from_str(&cookies_json).unwrap_or_default();
+        for cookie in refreshed {
+            cookies.retain(|c| c.name != cookie.name);
+            cookies.push(cookie.clone());
+        }
+        let expires_at = crate::keepalive::next_expiry(expires_at, refreshed, now, interval);
+
+        sqlx::query(
+            r#"
+            UPDATE cookies SET cookies_json = $2, expires_at = $3, last_checked_at = $4,
+                last_ok_at = $4, last_error = NULL, check_failures = 0
+            WHERE auth_key = $1
+            "#,
+        )
+        .bind(auth_key)
+        .bind(serde_json::to_string(&cookies).unwrap_or_default())
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The MP backend rejected the session: expire it now
+    pub async fn record_dead(&self, auth_key: &str, error: &str) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now().timestamp();
+        sqlx::query(
+            "UPDATE cookies SET expires_at = LEAST(expires_at, $2), last_checked_at = $2, last_error = $3 WHERE auth_key = $1",
+        )
+        .bind(auth_key)
+        .bind(now)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The ping failed for another reason (network, frequency control)
+    pub async fn record_check_failed(
+        &self,
+        auth_key: &str,
+        error: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE cookies SET last_checked_at = $2, last_error = $3, check_failures = check_failures + 1 WHERE auth_key = $1",
+        )
+        .bind(auth_key)
+        .bind(chrono::Utc::now().timestamp())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Keepalive view of every stored session, newest login first
+    pub async fn health(
+        &self,
+        all: bool,
+        owner_id: Option<uuid::Uuid>,
+    ) -> Result<Vec<SessionHealth>, sqlx::Error> {
+        sqlx::query_as::<_, SessionHealth>(
+            r#"
+            SELECT auth_key, created_at, expires_at, last_checked_at, last_ok_at, last_error, check_failures
+            FROM cookies WHERE $1 OR owner_id IS NOT DISTINCT FROM $2 ORDER BY created_at DESC
+            "#,
+        )
+        .bind(all)
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
     /// Delete expired cookies
     pub async fn cleanup_expired(&self) -> Result<u64, sqlx::Error> {
         let result = sqlx::query("DELETE FROM cookies WHERE expires_at <= $1")
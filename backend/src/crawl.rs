@@ -0,0 +1,359 @@
+//! Global crawl coordinator
+//!
+//! Every feature that talks to WeChat (insight tasks, account search, prefetch,
+//! export, archive verification) goes through one rate-limited queue per lane:
+//! one lane per logged-in MP session, plus lanes for public article pages and
+//! each download gateway. Within a lane, interactive requests jump ahead of
+//! task traffic, which jumps ahead of background jobs.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tokio::sync::{oneshot, Notify};
+
+lazy_static! {
+    static ref COORDINATOR: CrawlCoordinator = CrawlCoordinator::default();
+}
+
+/// Lane for direct article page fetches (no session required)
+pub const ARTICLE_LANE: &str = "article";
+
+/// Who is waiting; higher priority is served first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Scheduled / periodic jobs (archive verification, monitoring)
+    Background = 0,
+    /// Long-running user tasks (insight tasks, prefetch, export)
+    Task = 1,
+    /// A user is waiting on the HTTP response
+    Interactive = 2,
+}
+
+/// Pacing rules for a lane
+#[derive(Debug, Clone, Copy)]
+struct LaneConfig {
+    max_in_flight: usize,
+    min_interval: Duration,
+}
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
+impl LaneConfig {
+    /// MP backend calls are the most frequency-controlled: one at a time per session
+    fn for_lane(name: &str) -> Self {
+        if name.starts_with("session:") {
+            LaneConfig {
+                max_in_flight: 1,
+                min_interval: Duration::from_millis(env_u64("CRAWL_SESSION_INTERVAL_MS", 500)),
+            }
+        } else {
+            LaneConfig {
+                max_in_flight: env_u64("CRAWL_ARTICLE_CONCURRENCY", 4).max(1) as usize,
+                min_interval: Duration::from_millis(env_u64("CRAWL_ARTICLE_INTERVAL_MS", 200)),
+            }
+        }
+    }
+}
+
+struct Ticket {
+    priority: Priority,
+    seq: u64,
+    source: &'static str,
+    tx: oneshot::Sender<CrawlPermit>,
+}
+
+impl PartialEq for Ticket {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for Ticket {}
+
+impl PartialOrd for Ticket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ticket {
+    // Max-heap: highest priority first, then FIFO by sequence
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct LaneState {
+    waiting: BinaryHeap<Ticket>,
+    in_flight: usize,
+    last_start: Option<Instant>,
+    next_seq: u64,
+    served: u64,
+    /// Moving average of how long a permit is held
+    avg_service_ms: f64,
+}
+
+struct Lane {
+    name: String,
+    config: LaneConfig,
+    state: Mutex<LaneState>,
+    notify: Notify,
+}
+
+impl Lane {
+    /// Dispatcher loop: hands out permits respecting concurrency and spacing
+    async fn run(self: Arc<Self>) {
+        loop {
+            let next = {
+                let mut state = self.state.lock().unwrap();
+                if state.waiting.is_empty() || state.in_flight >= self.config.max_in_flight {
+                    Err(None)
+                } else {
+                    let elapsed = state
+                        .last_start
+                        .map(|t| t.elapsed())
+                        .unwrap_or(self.config.min_interval);
+                    if elapsed < self.config.min_interval {
+                        Err(Some(self.config.min_interval - elapsed))
+                    } else {
+                        state.in_flight += 1;
+                        state.last_start = Some(Instant::now());
+                        Ok(state.waiting.pop().unwrap())
+                    }
+                }
+            };
+
+            match next {
+                Ok(ticket) => {
+                    let permit = CrawlPermit {
+                        lane: self.clone(),
+                        granted: Instant::now(),
+                        completed: true,
+                    };
+                    // Send fails when the caller stopped waiting; the returned
+                    // permit is dropped here (outside the lock) and frees the slot
+                    match ticket.tx.send(permit) {
+                        Ok(()) => {
+                            self.state.lock().unwrap().served += 1;
+                            tracing::debug!(
+                                "[Crawl] {} -> {} ({:?})",
+                                self.name,
+                                ticket.source,
+                                ticket.priority
+                            );
+                        }
+                        Err(mut permit) => permit.completed = false,
+                    }
+                }
+                Err(Some(delay)) => tokio::time::sleep(delay).await,
+                Err(None) => self.notify.notified().await,
+            }
+        }
+    }
+
+    fn release(&self, held: Option<Duration>) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        if let Some(held) = held {
+            let ms = held.as_secs_f64() * 1000.0;
+            state.avg_service_ms = if state.avg_service_ms == 0.0 {
+                ms
+            } else {
+                state.avg_service_ms * 0.8 + ms * 0.2
+            };
+        }
+        drop(state);
+        self.notify.notify_one();
+    }
+}
+
+/// Held while a WeChat request is in flight; releases the lane slot on drop
+pub struct CrawlPermit {
+    lane: Arc<Lane>,
+    granted: Instant,
+    /// False for permits that were never handed to a caller
+    completed: bool,
+}
+
+impl Drop for CrawlPermit {
+    fn drop(&mut self) {
+        let held = self.completed.then(|| self.granted.elapsed());
+        self.lane.release(held);
+    }
+}
+
+/// Queue depth and pacing of a single lane
+#[derive(Debug, Serialize)]
+pub struct LaneStatus {
+    pub lane: String,
+    pub waiting: usize,
+    pub waiting_interactive: usize,
+    pub waiting_task: usize,
+    pub waiting_background: usize,
+    pub in_flight: usize,
+    pub max_in_flight: usize,
+    pub min_interval_ms: u64,
+    pub served: u64,
+    pub avg_service_ms: u64,
+    /// Rough wait for a newly queued background request
+    pub estimated_wait_ms: u64,
+}
+
+#[derive(Default)]
+pub struct CrawlCoordinator {
+    lanes: Mutex<HashMap<String, Arc<Lane>>>,
+}
+
+impl CrawlCoordinator {
+    fn lane(&self, name: &str) -> Arc<Lane> {
+        let mut lanes = self.lanes.lock().unwrap();
+        if let Some(lane) = lanes.get(name) {
+            return lane.clone();
+        }
+
+        let lane = Arc::new(Lane {
+            name: name.to_string(),
+            config: LaneConfig::for_lane(name),
+            state: Mutex::new(LaneState::default()),
+            notify: Notify::new(),
+        });
+        tokio::spawn(lane.clone().run());
+        lanes.insert(name.to_string(), lane.clone());
+        lane
+    }
+
+    /// Wait for a slot in `lane`
+    pub async fn acquire(
+        &self,
+        lane: &str,
+        priority: Priority,
+        source: &'static str,
+    ) -> CrawlPermit {
+        let lane = self.lane(lane);
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut state = lane.state.lock().unwrap();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Ticket {
+                priority,
+                seq,
+                source,
+                tx,
+            });
+        }
+        lane.notify.notify_one();
+
+        // The dispatcher only drops a queued sender after sending a permit
+        rx.await.expect("crawl dispatcher stopped")
+    }
+
+    pub fn status(&self) -> Vec<LaneStatus> {
+        let lanes: Vec<Arc<Lane>> = self.lanes.lock().unwrap().values().cloned().collect();
+        let mut statuses: Vec<LaneStatus> = lanes
+            .iter()
+            .map(|lane| {
+                let state = lane.state.lock().unwrap();
+                let count = |p: Priority| state.waiting.iter().filter(|t| t.priority == p).count();
+                let min_interval_ms = lane.config.min_interval.as_millis() as u64;
+                let per_request_ms = (min_interval_ms as f64)
+                    .max(state.avg_service_ms / lane.config.max_in_flight as f64);
+                let ahead = state.waiting.len() + state.in_flight;
+
+                LaneStatus {
+                    lane: lane.name.clone(),
+                    waiting: state.waiting.len(),
+                    waiting_interactive: count(Priority::Interactive),
+                    waiting_task: count(Priority::Task),
+                    waiting_background: count(Priority::Background),
+                    in_flight: state.in_flight,
+                    max_in_flight: lane.config.max_in_flight,
+                    min_interval_ms,
+                    served: state.served,
+                    avg_service_ms: state.avg_service_ms as u64,
+                    estimated_wait_ms: (ahead as f64 * per_request_ms) as u64,
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.lane.cmp(&b.lane));
+        statuses
+    }
+}
+
+/// Global coordinator instance
+pub fn coordinator() -> &'static CrawlCoordinator {
+    &COORDINATOR
+}
+
+/// Shorthand for `coordinator().acquire(..)`
+pub async fn acquire(lane: &str, priority: Priority, source: &'static str) -> CrawlPermit {
+    COORDINATOR.acquire(lane, priority, source).await
+}
+
+/// Lane for MP backend calls made with a session.
+/// The auth key is hashed so it never shows up in the status endpoint.
+pub fn session_lane(auth_key: Option<&str>) -> String {
+    match auth_key {
+        Some(key) => format!("session:{}", &format!("{:x}", md5::compute(key))[..8]),
+        None => "session:anonymous".to_string(),
+    }
+}
+
+/// Lane for fetches routed through a download gateway (keyed by host)
+pub fn gateway_lane(gateway: &str) -> String {
+    let host = url::Url::parse(gateway)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+        .unwrap_or_else(|| gateway.to_string());
+    format!("gateway:{}", host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticket_order() {
+        let mut heap = BinaryHeap::new();
+        for (seq, priority) in [
+            (0, Priority::Background),
+            (1, Priority::Task),
+            (2, Priority::Interactive),
+            (3, Priority::Task),
+        ] {
+            let (tx, _rx) = oneshot::channel();
+            heap.push(Ticket {
+                priority,
+                seq,
+                source: "test",
+                tx,
+            });
+        }
+
+        let order: Vec<u64> = std::iter::from_fn(|| heap.pop().map(|t| t.seq)).collect();
+        assert_eq!(order, vec![2, 1, 3, 0]);
+    }
+
+    #[test]
+    fn test_lanes() {
+        assert_eq!(session_lane(None), "session:anonymous");
+        assert!(!session_lane(Some("secret-key")).contains("secret"));
+        assert_eq!(
+            gateway_lane("https://gw.example.com/fetch?x=1"),
+            "gateway:gw.example.com"
+        );
+    }
+}
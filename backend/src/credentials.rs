@@ -0,0 +1,303 @@
+//! LLM provider credentials
+//!
+//! API keys used to travel with every task request. They can now be stored
+//! server-side, encrypted with AES-256-GCM under a key derived from
+//! `SETTINGS_MASTER_KEY`. Lookups go request override → stored key → the
+//! provider's environment variable, so existing setups keep working. Without a
+//! master key nothing can be stored and stored keys are ignored. Stored and
+//! environment keys are only sent to the server-configured endpoint: a request
+//! naming its own base URL must bring its own key.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+/// Providers whose keys can be stored
+pub const PROVIDERS: &[&str] = &["gemini", "deepseek", "openai_compatible"];
+
+/// Where the key a provider would use comes from
+#[derive(Debug, Serialize)]
+pub struct CredentialStatus {
+    pub provider: &'static str,
+    /// `stored`, `env` or `none`
+    pub source: &'static str,
+    /// Masked key, e.g. `sk-1…cdef`
+    pub hint: Option<String>,
+    pub updated_at: Option<i64>,
+}
+
+fn env_var(provider: &str) -> Option<&'static str> {
+    match provider {
+        "gemini" => Some("GEMINI_API_KEY"),
+        "deepseek" => Some("DEEPSEEK_API_KEY"),
+        "openai_compatible" => Some("OPENAI_COMPATIBLE_API_KEY"),
+        _ => None,
+    }
+}
+
+/// Endpoint the server configures for a provider, if it takes a base URL
+fn server_base_url(provider: &str) -> Option<String> {
+    match provider {
+        "openai_compatible" => std::env::var("OPENAI_COMPATIBLE_BASE_URL").ok(),
+        _ => None,
+    }
+}
+
+/// Whether a request's `base_url` is the server's own endpoint (or unset)
+fn same_endpoint(base_url: Option<&str>, server: Option<&str>) -> bool {
+    let normalize = |url: &str| url.trim().trim_end_matches('/').to_lowercase();
+    match base_url.map(normalize).filter(|url| !url.is_empty()) {
+        None => true,
+        Some(url) => server.is_some_and(|server| normalize(server) == url),
+    }
+}
+
+fn env_key(provider: &str) -> Option<String> {
+    env_var(provider)
+        .and_then(|var| std::env::var(var).ok())
+        .filter(|k| !k.trim().is_empty())
+}
+
+fn cipher_for(master_key: &str) -> Aes256Gcm {
+    let key = Sha256::digest(master_key.as_bytes());
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+}
+
+fn cipher() -> Option<Aes256Gcm> {
+    std::env::var("SETTINGS_MASTER_KEY")
+        .ok()
+        .filter(|k| !k.is_empty())
+        .map(|k| cipher_for(&k))
+}
+
+/// Encrypt `api_key`, bound to its provider so rows cannot be swapped.
+/// Returns `(nonce, ciphertext)`.
+fn seal(cipher: &Aes256Gcm, provider: &str, api_key: &str) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let payload = Payload {
+        msg: api_key.as_bytes(),
+        aad: provider.as_bytes(),
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| anyhow::anyhow!("encryption failed"))?;
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+fn unseal(cipher: &Aes256Gcm, provider: &str, nonce: &[u8], ciphertext: &[u8]) -> Option<String> {
+    if nonce.len() != 12 {
+        return None;
+    }
+    let payload = Payload {
+        msg: ciphertext,
+        aad: provider.as_bytes(),
+    };
+    let plain = cipher.decrypt(Nonce::from_slice(nonce), payload).ok()?;
+    String::from_utf8(plain).ok()
+}
+
+/// Masked form of a key safe to show in the UI
+pub fn mask(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    if chars.len() <= 8 {
+        return "*".repeat(chars.len());
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}…{}", head, tail)
+}
+
+fn check_provider(provider: &str) -> Result<(), AppError> {
+    if PROVIDERS.contains(&provider) {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "不支持的provider: {}",
+            provider
+        )))
+    }
+}
+
+/// Encrypt and store a provider key, replacing any previous one
+pub async fn save(pool: &PgPool, provider: &str, api_key: &str) -> Result<(), AppError> {
+    check_provider(provider)?;
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return Err(AppError::BadRequest("API Key不能为空".to_string()));
+    }
+    let cipher = cipher().ok_or_else(|| {
+        AppError::BadRequest("服务端未配置 SETTINGS_MASTER_KEY，无法保存 API Key".to_string())
+    })?;
+    let (nonce, ciphertext) = seal(&cipher, provider, api_key)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO llm_credentials (provider, nonce, ciphertext, hint, updated_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (provider) DO UPDATE SET
+            nonce = EXCLUDED.nonce,
+            ciphertext = EXCLUDED.ciphertext,
+            hint = EXCLUDED.hint,
+            updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(provider)
+    .bind(nonce)
+    .bind(ciphertext)
+    .bind(mask(api_key))
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Delete a stored key; false when there was none
+pub async fn remove(pool: &PgPool, provider: &str) -> Result<bool, AppError> {
+    check_provider(provider)?;
+    let deleted = sqlx::query("DELETE FROM llm_credentials WHERE provider = $1")
+        .bind(provider)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Decrypted stored key of a provider
+pub async fn stored(pool: &PgPool, provider: &str) -> Option<String> {
+    let cipher = cipher()?;
+    let row: Option<(Vec<u8>, Vec<u8>)> =
+        sqlx::query_as("SELECT nonce, ciphertext FROM llm_credentials WHERE provider = $1")
+            .bind(provider)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| tracing::error!("[Credentials] Failed to load {} key: {}", provider, e))
+            .ok()?;
+    let (nonce, ciphertext) = row?;
+    let key = unseal(&cipher, provider, &nonce, &ciphertext);
+    if key.is_none() {
+        tracing::warn!(
+            "[Credentials] Stored {} key cannot be decrypted; was SETTINGS_MASTER_KEY changed?",
+            provider
+        );
+    }
+    key
+}
+
+/// Key to use for a provider: the request's own, then the stored one, then
+/// the environment. The server's keys are withheld when the request sends its
+/// own `base_url` that differs from the configured endpoint.
+pub async fn resolve(
+    pool: &PgPool,
+    provider: &str,
+    request_key: Option<&str>,
+    base_url: Option<&str>,
+) -> Option<String> {
+    if let Some(key) = request_key.map(str::trim).filter(|k| !k.is_empty()) {
+        return Some(key.to_string());
+    }
+    if !same_endpoint(base_url, server_base_url(provider).as_deref()) {
+        tracing::warn!(
+            "[Credentials] Not sending the server's {} key to a request-supplied base URL",
+            provider
+        );
+        return None;
+    }
+    match stored(pool, provider).await {
+        Some(key) => Some(key),
+        None => env_key(provider),
+    }
+}
+
+/// Key source of every provider, without revealing the keys
+pub async fn list(pool: &PgPool) -> Result<Vec<CredentialStatus>, AppError> {
+    let rows: Vec<(String, String, i64)> =
+        sqlx::query_as("SELECT provider, hint, updated_at FROM llm_credentials")
+            .fetch_all(pool)
+            .await?;
+    let usable = cipher().is_some();
+
+    Ok(PROVIDERS
+        .iter()
+        .map(|&provider| {
+            let row = rows
+                .iter()
+                .find(|(p, _, _)| p == provider)
+                .filter(|_| usable);
+            match (row, env_key(provider)) {
+                (Some((_, hint, updated_at)), _) => CredentialStatus {
+                    provider,
+                    source: "stored",
+                    hint: Some(hint.clone()),
+                    updated_at: Some(*updated_at),
+                },
+                (None, Some(key)) => CredentialStatus {
+                    provider,
+                    source: "env",
+                    hint: Some(mask(&key)),
+                    updated_at: None,
+                },
+                (None, None) => CredentialStatus {
+                    provider,
+                    source: "none",
+                    hint: None,
+                    updated_at: None,
+                },
+            }
+        })
+        .collect())
+}
+
+/// Whether keys can be stored at all
+pub fn encryption_enabled() -> bool {
+    cipher().is_some()
+}
+
+/// Encrypt another server-side secret (e.g. a notifier password), bound to
+/// `label`, which must not be a provider name. Returns `(nonce, ciphertext)`.
+pub fn seal_secret(label: &str, secret: &str) -> Result<(Vec<u8>, Vec<u8>), AppError> {
+    let cipher = cipher().ok_or_else(|| {
+        AppError::BadRequest("服务端未配置 SETTINGS_MASTER_KEY，无法保存密钥".to_string())
+    })?;
+    Ok(seal(&cipher, label, secret)?)
+}
+
+/// Decrypt a secret sealed by `seal_secret`
+pub fn unseal_secret(label: &str, nonce: &[u8], ciphertext: &[u8]) -> Option<String> {
+    unseal(&cipher()?, label, nonce, ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let cipher = cipher_for("master");
+        let (nonce, ciphertext) = seal(&cipher, "gemini", "AIzaSy-secret").unwrap();
+        assert!(!ciphertext.windows(6).any(|w| w == b"secret"));
+        assert_eq!(
+            unseal(&cipher, "gemini", &nonce, &ciphertext).as_deref(),
+            Some("AIzaSy-secret")
+        );
+        // Wrong master key or a row moved to another provider fails to open
+        assert!(unseal(&cipher_for("other"), "gemini", &nonce, &ciphertext).is_none());
+        assert!(unseal(&cipher, "deepseek", &nonce, &ciphertext).is_none());
+
+        assert_eq!(mask("sk-1234567890abcdef"), "sk-1…cdef");
+        assert_eq!(mask("short"), "*****");
+    }
+
+    #[test]
+    fn test_same_endpoint() {
+        let server = Some("https://llm.internal/v1");
+        assert!(same_endpoint(None, server));
+        assert!(same_endpoint(Some(" "), None));
+        assert!(same_endpoint(Some("https://LLM.internal/v1/"), server));
+        assert!(!same_endpoint(Some("https://attacker.example/v1"), server));
+        assert!(!same_endpoint(Some("https://llm.internal/v1"), None));
+    }
+}
@@ -0,0 +1,195 @@
+//! Duplicate article detection
+//!
+//! Reposts of one article under different accounts have different URLs, so
+//! URL dedup misses them. Each accepted article of a task is indexed by a
+//! 64-bit simhash of its text (character shingles, robust to small edits) and
+//! its embedding; a candidate matching either is a duplicate. The index is
+//! rebuilt from `insight_articles` when a task resumes.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Shingle length in characters
+const SHINGLE_CHARS: usize = 3;
+/// Max differing simhash bits for a near-duplicate
+const SIMHASH_MAX_DISTANCE: u32 = 3;
+
+/// What to do with a detected duplicate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    /// Drop it
+    Skip,
+    /// Keep it, pointing `duplicates_of` at the original and reusing its insight
+    Link,
+}
+
+impl Mode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Mode::Off),
+            "skip" => Some(Mode::Skip),
+            "link" => Some(Mode::Link),
+            _ => None,
+        }
+    }
+}
+
+/// Cosine similarity above which two embeddings are the same article
+/// (`DEDUP_SIMILARITY_THRESHOLD`, default 0.95)
+pub fn default_threshold() -> f64 {
+    std::env::var("DEDUP_SIMILARITY_THRESHOLD")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0.95)
+}
+
+/// FNV-1a; stable across builds, unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// 64-bit simhash over lowercase alphanumeric character shingles
+pub fn simhash(text: &str) -> u64 {
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if chars.is_empty() {
+        return 0;
+    }
+
+    let mut weights = [0i32; 64];
+    let mut add = |shingle: &[char]| {
+        let h = fnv1a(shingle.iter().collect::<String>().as_bytes());
+        for (bit, w) in weights.iter_mut().enumerate() {
+            *w += if h >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    };
+    if chars.len() < SHINGLE_CHARS {
+        add(&chars);
+    } else {
+        chars.windows(SHINGLE_CHARS).for_each(&mut add);
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0u64, |h, (bit, _)| h | 1 << bit)
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        (dot / (na * nb)) as f64
+    }
+}
+
+/// An accepted article
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: Uuid,
+    pub simhash: u64,
+    pub embedding: Vec<f32>,
+    pub insight: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EntryRow {
+    id: Uuid,
+    simhash: i64,
+    dedup_embedding: Option<Vec<f32>>,
+    insight: Option<String>,
+}
+
+/// Accepted articles of one task
+#[derive(Debug, Default)]
+pub struct Index {
+    entries: Vec<Entry>,
+    threshold: f64,
+}
+
+impl Index {
+    pub fn new(threshold: f64) -> Self {
+        Index {
+            entries: Vec::new(),
+            threshold,
+        }
+    }
+
+    /// Index of the task's stored originals (linked duplicates excluded)
+    pub async fn load(pool: &PgPool, task_id: Uuid, threshold: f64) -> Result<Self, sqlx::Error> {
+        let rows = sqlx::query_as::<_, EntryRow>(
+            r#"
+            SELECT id, simhash, dedup_embedding, insight FROM insight_articles
+            WHERE task_id = $1 AND duplicates_of IS NULL AND simhash IS NOT NULL
+            "#,
+        )
+        .bind(task_id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut index = Index::new(threshold);
+        index.entries = rows
+            .into_iter()
+            .map(|row| Entry {
+                id: row.id,
+                simhash: row.simhash as u64,
+                embedding: row.dedup_embedding.unwrap_or_default(),
+                insight: row.insight,
+            })
+            .collect();
+        Ok(index)
+    }
+
+    /// Original this candidate duplicates, if any
+    pub fn find(&self, simhash: u64, embedding: &[f32]) -> Option<&Entry> {
+        self.entries.iter().find(|e| {
+            (simhash != 0 && (e.simhash ^ simhash).count_ones() <= SIMHASH_MAX_DISTANCE)
+                || cosine(&e.embedding, embedding) >= self.threshold
+        })
+    }
+
+    pub fn insert(&mut self, entry: Entry) {
+        self.entries.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simhash_near_duplicates() {
+        let original = "央行宣布降准0.5个百分点，释放长期资金约1万亿元，以支持实体经济发展";
+        let repost = "【转载】央行宣布降准0.5个百分点，释放长期资金约1万亿元，以支持实体经济发展！";
+        let other = "新能源汽车销量连续三个月增长，电池原材料价格回落带动整车降价";
+
+        let (a, b, c) = (simhash(original), simhash(repost), simhash(other));
+        assert!((a ^ b).count_ones() <= 6, "{}", (a ^ b).count_ones());
+        assert!((a ^ c).count_ones() > SIMHASH_MAX_DISTANCE);
+
+        let mut index = Index::new(0.95);
+        let id = Uuid::new_v4();
+        index.insert(Entry {
+            id,
+            simhash: a,
+            embedding: vec![1.0, 0.0],
+            insight: None,
+        });
+        assert_eq!(index.find(a, &[0.0, 1.0]).map(|e| e.id), Some(id));
+        assert_eq!(index.find(c, &[0.99, 0.05]).map(|e| e.id), Some(id));
+        assert!(index.find(c, &[0.0, 1.0]).is_none());
+    }
+}
@@ -0,0 +1,158 @@
+//! Shared download scheduler
+//!
+//! Exports and prefetches size their own concurrency, so two jobs running at
+//! once used to double the load on the same gateways. Every article and image
+//! download now takes a permit from the scheduler in `AppState`: at most
+//! `DOWNLOAD_MAX_CONCURRENCY` (default 16) downloads in total, and at most
+//! `DOWNLOAD_MAX_PER_HOST` (default 4) per gateway, or per target host when
+//! fetching directly.
+//!
+//! This caps concurrency only; request pacing per lane stays with `crawl`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const DEFAULT_MAX_CONCURRENCY: usize = 16;
+const DEFAULT_MAX_PER_HOST: usize = 4;
+
+/// Held for the duration of one download
+pub struct DownloadPermit {
+    _host: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+/// Downloads in flight for one key
+#[derive(Debug, Serialize)]
+pub struct HostStatus {
+    pub key: String,
+    pub in_flight: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SchedulerStatus {
+    pub max_concurrency: usize,
+    pub max_per_host: usize,
+    pub in_flight: usize,
+    pub hosts: Vec<HostStatus>,
+}
+
+pub struct DownloadScheduler {
+    max_concurrency: usize,
+    max_per_host: usize,
+    global: Arc<Semaphore>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl DownloadScheduler {
+    pub fn new(max_concurrency: usize, max_per_host: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        DownloadScheduler {
+            max_concurrency,
+            max_per_host: max_per_host.clamp(1, max_concurrency),
+            global: Arc::new(Semaphore::new(max_concurrency)),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Limits from `DOWNLOAD_MAX_CONCURRENCY` and `DOWNLOAD_MAX_PER_HOST`
+    pub fn from_env() -> Self {
+        let env = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            env("DOWNLOAD_MAX_CONCURRENCY", DEFAULT_MAX_CONCURRENCY),
+            env("DOWNLOAD_MAX_PER_HOST", DEFAULT_MAX_PER_HOST),
+        )
+    }
+
+    /// Wait for a slot for a download of `url`, through `gateway` if set.
+    /// The per-host slot is taken first so a busy host does not hold global slots.
+    pub async fn acquire(&self, gateway: Option<&str>, url: &str) -> DownloadPermit {
+        let host = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts
+                .entry(host_key(gateway, url))
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+                .clone()
+        };
+        // The semaphores are never closed
+        let host = host.acquire_owned().await.unwrap();
+        let global = self.global.clone().acquire_owned().await.unwrap();
+        DownloadPermit {
+            _host: host,
+            _global: global,
+        }
+    }
+
+    pub fn status(&self) -> SchedulerStatus {
+        let hosts = self.hosts.lock().unwrap();
+        let mut busy: Vec<HostStatus> = hosts
+            .iter()
+            .map(|(key, sem)| HostStatus {
+                key: key.clone(),
+                in_flight: self.max_per_host - sem.available_permits(),
+            })
+            .filter(|h| h.in_flight > 0)
+            .collect();
+        busy.sort_by(|a, b| a.key.cmp(&b.key));
+        SchedulerStatus {
+            max_concurrency: self.max_concurrency,
+            max_per_host: self.max_per_host,
+            in_flight: self.max_concurrency - self.global.available_permits(),
+            hosts: busy,
+        }
+    }
+}
+
+/// Scheduling key: the gateway host when routed through one, otherwise the target host
+fn host_key(gateway: Option<&str>, url: &str) -> String {
+    let target = gateway.unwrap_or(url);
+    url::Url::parse(target)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| target.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_key() {
+        assert_eq!(
+            host_key(None, "https://mmbiz.qpic.cn/a/640?wx_fmt=png"),
+            "mmbiz.qpic.cn"
+        );
+        assert_eq!(
+            host_key(
+                Some("https://gw.example.com/fetch"),
+                "https://mmbiz.qpic.cn/a"
+            ),
+            "gw.example.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_per_host_limit() {
+        let scheduler = DownloadScheduler::new(3, 2);
+        let _a = scheduler.acquire(None, "https://a.com/1").await;
+        let _b = scheduler.acquire(None, "https://a.com/2").await;
+        let blocked = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            scheduler.acquire(None, "https://a.com/3"),
+        )
+        .await;
+        assert!(blocked.is_err());
+        let _c = scheduler.acquire(None, "https://b.com/1").await;
+
+        let status = scheduler.status();
+        assert_eq!(status.in_flight, 3);
+        assert_eq!(status.hosts.len(), 2);
+    }
+}
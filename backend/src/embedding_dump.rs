@@ -0,0 +1,379 @@
+//! Binary embedding dumps
+//!
+//! `/api/embedding/export` streams the embeddings table in a compact binary
+//! format and `/api/embedding/import` reads it back from the request body, so
+//! large vector sets can be backed up or moved without JSON bodies. Layout,
+//! all integers little-endian:
+//!
+//! ```text
+//! "WEMB" u32 version  u32 header_len  header JSON (`DumpHeader`)
+//! repeated: u32 meta_len (> 0)  meta JSON (`DumpMeta`)  dimension × f32
+//! u32 0   end marker, so a truncated dump is rejected
+//! ```
+
+use axum::body::Bytes;
+use futures::stream::{self, BoxStream, StreamExt};
+use pgvector::Vector;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::AppError;
+
+const MAGIC: &[u8; 4] = b"WEMB";
+const VERSION: u32 = 1;
+/// Rows read per export page
+const BATCH_SIZE: usize = 1000;
+/// Upper bound on header/metadata JSON, to reject garbage before allocating
+const MAX_JSON_LEN: u32 = 1 << 20;
+/// Upper bound on the header's vector dimension, for the same reason
+const MAX_DIMENSION: u32 = 16_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DumpHeader {
+    pub dimension: u32,
+    /// Registry provider/model the vectors were built with
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    /// Rows at export time (rows written meanwhile may be included too)
+    pub count: i64,
+    pub exported_at: i64,
+}
+
+/// Everything of an `embeddings` row except the vector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DumpMeta {
+    pub id: String,
+    pub fakeid: String,
+    pub aid: Option<String>,
+    pub title: String,
+    pub source: String,
+    pub text_hash: String,
+    pub indexed_at: i64,
+    pub provider: Option<String>,
+    pub chunk_index: Option<i32>,
+}
+
+#[derive(sqlx::FromRow)]
+struct DumpRow {
+    id: String,
+    fakeid: String,
+    aid: Option<String>,
+    title: String,
+    source: String,
+    text_hash: String,
+    vector: Vector,
+    indexed_at: i64,
+    provider: Option<String>,
+    chunk_index: Option<i32>,
+}
+
+fn push_json<T: Serialize>(buf: &mut Vec<u8>, value: &T) {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    buf.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&json);
+}
+
+pub fn encode_header(header: &DumpHeader) -> Vec<u8> {
+    let mut buf = MAGIC.to_vec();
+    buf.extend_from_slice(&VERSION.to_le_bytes());
+    push_json(&mut buf, header);
+    buf
+}
+
+pub fn encode_record(buf: &mut Vec<u8>, meta: &DumpMeta, vector: &[f32]) {
+    push_json(buf, meta);
+    for x in vector {
+        buf.extend_from_slice(&x.to_le_bytes());
+    }
+}
+
+pub fn encode_end() -> Vec<u8> {
+    0u32.to_le_bytes().to_vec()
+}
+
+/// Export filter
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportFilter {
+    pub fakeid: Option<String>,
+    pub source: Option<String>,
+}
+
+/// Header followed by every matching row, paged by id so the stream holds no
+/// connection between pages
+pub async fn export_stream(
+    pool: &PgPool,
+    filter: ExportFilter,
+) -> Result<BoxStream<'static, Result<Bytes, sqlx::Error>>, AppError> {
+    let registry = crate::embedding_registry::current()
+        .ok_or_else(|| AppError::BadRequest("向量库尚未建立, 没有可导出的向量".to_string()))?;
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM embeddings WHERE ($1::text IS NULL OR fakeid = $1) AND ($2::text IS NULL OR source = $2)",
+    )
+    .bind(&filter.fakeid)
+    .bind(&filter.source)
+    .fetch_one(pool)
+    .await?;
+    let header = encode_header(&DumpHeader {
+        dimension: registry.dimension as u32,
+        provider: registry.provider,
+        model: registry.model,
+        count,
+        exported_at: chrono::Utc::now().timestamp(),
+    });
+
+    let pool = pool.clone();
+    // None once the last page has been sent
+    let pages = stream::unfold(Some(String::new()), move |after| {
+        let pool = pool.clone();
+        let filter = filter.clone();
+        async move {
+            let after = after?;
+            let rows: Vec<DumpRow> = match sqlx::query_as(
+                r#"
+                SELECT id, fakeid, aid, title, source, text_hash, vector, indexed_at, provider, chunk_index
+                FROM embeddings
+                WHERE id > $1
+                  AND ($2::text IS NULL OR fakeid = $2)
+                  AND ($3::text IS NULL OR source = $3)
+                ORDER BY id
+                LIMIT $4
+                "#,
+            )
+            .bind(&after)
+            .bind(&filter.fakeid)
+            .bind(&filter.source)
+            .bind(BATCH_SIZE as i64)
+            .fetch_all(&pool)
+            .await
+            {
+                Ok(rows) => rows,
+                Err(e) => return Some((Err(e), None)),
+            };
+            if rows.is_empty() {
+                return Some((Ok(Bytes::from(encode_end())), None));
+            }
+
+            let next = rows.last().map(|r| r.id.clone());
+            let mut buf = Vec::new();
+            for row in rows {
+                let vector = row.vector.to_vec();
+                let meta = DumpMeta {
+                    id: row.id,
+                    fakeid: row.fakeid,
+                    aid: row.aid,
+                    title: row.title,
+                    source: row.source,
+                    text_hash: row.text_hash,
+                    indexed_at: row.indexed_at,
+                    provider: row.provider,
+                    chunk_index: row.chunk_index,
+                };
+                encode_record(&mut buf, &meta, &vector);
+            }
+            Some((Ok(Bytes::from(buf)), next))
+        }
+    });
+
+    Ok(stream::once(async move { Ok(Bytes::from(header)) })
+        .chain(pages)
+        .boxed())
+}
+
+/// Fill `buf`; a dump that ends early is the client's fault
+async fn read_exact<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> Result<(), AppError> {
+    match reader.read_exact(buf).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            Err(AppError::BadRequest("向量文件不完整".to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn read_u32<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u32, AppError> {
+    let mut buf = [0u8; 4];
+    read_exact(reader, &mut buf).await?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+async fn read_json<R, T>(reader: &mut R, len: u32) -> Result<T, AppError>
+where
+    R: AsyncRead + Unpin,
+    T: serde::de::DeserializeOwned,
+{
+    if len > MAX_JSON_LEN {
+        return Err(AppError::BadRequest("向量文件格式错误".to_string()));
+    }
+    let mut buf = vec![0u8; len as usize];
+    read_exact(reader, &mut buf).await?;
+    serde_json::from_slice(&buf)
+        .map_err(|e| AppError::BadRequest(format!("向量文件格式错误: {}", e)))
+}
+
+pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<DumpHeader, AppError> {
+    let mut magic = [0u8; 4];
+    read_exact(reader, &mut magic).await?;
+    if &magic != MAGIC {
+        return Err(AppError::BadRequest("不是向量导出文件".to_string()));
+    }
+    let version = read_u32(reader).await?;
+    if version != VERSION {
+        return Err(AppError::BadRequest(format!(
+            "不支持的向量文件版本: {}",
+            version
+        )));
+    }
+    let len = read_u32(reader).await?;
+    let header: DumpHeader = read_json(reader, len).await?;
+    if header.dimension == 0 || header.dimension > MAX_DIMENSION {
+        return Err(AppError::BadRequest(format!(
+            "向量文件维度无效: {}",
+            header.dimension
+        )));
+    }
+    Ok(header)
+}
+
+/// Next record, or `None` at the end marker
+pub async fn read_record<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    dimension: u32,
+) -> Result<Option<(DumpMeta, Vec<f32>)>, AppError> {
+    let len = read_u32(reader).await?;
+    if len == 0 {
+        return Ok(None);
+    }
+    let meta: DumpMeta = read_json(reader, len).await?;
+    let mut bytes = vec![0u8; dimension as usize * 4];
+    read_exact(reader, &mut bytes).await?;
+    let vector = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok(Some((meta, vector)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportSummary {
+    pub header: DumpHeader,
+    pub imported: usize,
+}
+
+/// Upsert every record of a dump in one transaction, so a dump that turns
+/// out truncated or malformed imports nothing. `force` accepts vectors from another
+/// provider/model of the same dimension, which only makes sense if the
+/// registry is wrong.
+pub async fn import<R: AsyncRead + Unpin>(
+    pool: &PgPool,
+    reader: &mut R,
+    force: bool,
+) -> Result<ImportSummary, AppError> {
+    let header = read_header(reader).await?;
+    crate::embedding_registry::check_dimension(header.dimension as usize)?;
+    if let Some(registry) = crate::embedding_registry::current() {
+        let differs = |ours: &Option<String>, theirs: &Option<String>| matches!((ours, theirs), (Some(a), Some(b)) if !a.eq_ignore_ascii_case(b));
+        if !force
+            && (differs(&registry.provider, &header.provider)
+                || differs(&registry.model, &header.model))
+        {
+            return Err(AppError::BadRequest(format!(
+                "向量文件由 {} / {} 生成, 与当前向量库不一致",
+                header.provider.as_deref().unwrap_or("-"),
+                header.model.as_deref().unwrap_or("-")
+            )));
+        }
+    }
+
+    let mut imported = 0;
+    let mut tx = pool.begin().await?;
+    while let Some((meta, vector)) = read_record(reader, header.dimension).await? {
+        sqlx::query(
+            r#"
+            INSERT INTO embeddings (id, fakeid, aid, title, source, text_hash, vector, indexed_at, provider, dimension, chunk_index)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (id) DO UPDATE SET
+                fakeid = EXCLUDED.fakeid,
+                aid = EXCLUDED.aid,
+                title = EXCLUDED.title,
+                source = EXCLUDED.source,
+                text_hash = EXCLUDED.text_hash,
+                vector = EXCLUDED.vector,
+                indexed_at = EXCLUDED.indexed_at,
+                provider = EXCLUDED.provider,
+                dimension = EXCLUDED.dimension,
+                chunk_index = EXCLUDED.chunk_index
+            "#,
+        )
+        .bind(&meta.id)
+        .bind(&meta.fakeid)
+        .bind(&meta.aid)
+        .bind(&meta.title)
+        .bind(&meta.source)
+        .bind(&meta.text_hash)
+        .bind(Vector::from(vector))
+        .bind(meta.indexed_at)
+        .bind(meta.provider.as_ref().or(header.provider.as_ref()))
+        .bind(header.dimension as i32)
+        .bind(meta.chunk_index)
+        .execute(&mut *tx)
+        .await?;
+        imported += 1;
+    }
+    tx.commit().await?;
+
+    tracing::info!("[Embedding] Imported {} embeddings", imported);
+    Ok(ImportSummary { header, imported })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dump_roundtrip() {
+        let header = DumpHeader {
+            dimension: 3,
+            provider: Some("gemini".to_string()),
+            model: None,
+            count: 1,
+            exported_at: 1_700_000_000,
+        };
+        let meta = DumpMeta {
+            id: "e1".to_string(),
+            fakeid: "MzA".to_string(),
+            aid: Some("2247_1".to_string()),
+            title: "标题".to_string(),
+            source: "content".to_string(),
+            text_hash: "abc".to_string(),
+            indexed_at: 1_700_000_001,
+            provider: None,
+            chunk_index: Some(2),
+        };
+        let mut bytes = encode_header(&header);
+        encode_record(&mut bytes, &meta, &[0.5, -1.0, 2.25]);
+        bytes.extend(encode_end());
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(read_header(&mut reader).await.unwrap(), header);
+        let (read_meta, vector) = read_record(&mut reader, 3).await.unwrap().unwrap();
+        assert_eq!(read_meta, meta);
+        assert_eq!(vector, vec![0.5, -1.0, 2.25]);
+        assert!(read_record(&mut reader, 3).await.unwrap().is_none());
+
+        // Truncated dumps fail instead of importing a partial set
+        let mut truncated = &bytes[..bytes.len() - 6];
+        read_header(&mut truncated).await.unwrap();
+        assert!(read_record(&mut truncated, 3).await.is_err());
+        assert!(read_header(&mut &b"JSON"[..]).await.is_err());
+
+        // Dimensions that would allocate absurd vectors are refused up front
+        for dimension in [0, u32::MAX] {
+            let bytes = encode_header(&DumpHeader {
+                dimension,
+                ..header.clone()
+            });
+            assert!(read_header(&mut bytes.as_slice()).await.is_err());
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! Embedding dimension registry
+//!
+//! The `embeddings.vector` column has a fixed dimension, so vectors from a
+//! different provider (Gemini 768 vs Ollama 4096) cannot be stored or compared.
+//! The registry records which provider/model/dimension the table holds
+//! (`embedding_registry`, plus `provider`/`dimension` per row), lets handlers
+//! reject mismatched vectors with a clear error, and migrates the table to a
+//! new provider by re-embedding every row in the background.
+
+use std::sync::{Mutex, RwLock};
+
+use lazy_static::lazy_static;
+use pgvector::Vector;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+lazy_static! {
+    static ref CURRENT: RwLock<Option<Registry>> = RwLock::new(None);
+    static ref MIGRATION: Mutex<Option<MigrationStatus>> = Mutex::new(None);
+}
+
+/// Registry key of the main embeddings table
+const NAMESPACE: &str = "embeddings";
+/// Rows re-embedded per batch
+const BATCH_SIZE: i64 = 50;
+/// Longest text sent to the provider when re-embedding article bodies
+const MAX_TEXT_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Registry {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub dimension: i32,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MigrateRequest {
+    /// "gemini" or "ollama"
+    pub provider: String,
+    pub model: Option<String>,
+    /// Target dimension; detected from a probe embedding when omitted
+    pub dimension: Option<i32>,
+    #[serde(rename = "apiKey")]
+    pub api_key: Option<String>,
+    #[serde(rename = "ollamaBaseUrl")]
+    pub ollama_base_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub provider: String,
+    pub model: Option<String>,
+    pub dimension: i32,
+    pub status: String, // running, completed, failed
+    pub total: i64,
+    pub done: i64,
+    pub message: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+}
+
+/// Embedding row still waiting to be re-embedded
+#[derive(sqlx::FromRow)]
+struct PendingRow {
+    id: String,
+    source: String,
+    title: String,
+    digest: Option<String>,
+    /// Raw cached article HTML
+    content: Option<String>,
+    chunk_index: Option<i32>,
+}
+
+/// Declared dimension of `embeddings.vector` (pgvector stores it as the typmod)
+async fn table_dimension(pool: &PgPool) -> anyhow::Result<i32> {
+    let dim: i32 = sqlx::query_scalar(
+        "SELECT atttypmod FROM pg_attribute WHERE attrelid = 'embeddings'::regclass AND attname = 'vector'",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(dim)
+}
+
+/// Sync the registry with the actual table and cache it. Called at startup.
+pub async fn load(pool: &PgPool) -> anyhow::Result<Registry> {
+    let dimension = table_dimension(pool).await?;
+    let registry = sqlx::query_as::<_, Registry>(
+        r#"
+        INSERT INTO embedding_registry (name, dimension, updated_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO UPDATE SET
+            dimension = EXCLUDED.dimension,
+            updated_at = CASE WHEN embedding_registry.dimension = EXCLUDED.dimension
+                              THEN embedding_registry.updated_at ELSE EXCLUDED.updated_at END
+        RETURNING provider, model, dimension, updated_at
+        "#,
+    )
+    .bind(NAMESPACE)
+    .bind(dimension)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool)
+    .await?;
+
+    let configured = std::env::var("EMBEDDING_DIMENSION")
+        .ok()
+        .and_then(|s| s.parse::<i32>().ok());
+    if let Some(configured) = configured.filter(|d| *d != dimension) {
+        tracing::warn!(
+            "[Embedding] EMBEDDING_DIMENSION={} but the embeddings table stores {}-dim vectors; use /api/embedding/migrate to switch",
+            configured,
+            dimension
+        );
+    }
+
+    *CURRENT.write().unwrap() = Some(registry.clone());
+    Ok(registry)
+}
+
+/// Cached registry entry
+pub fn current() -> Option<Registry> {
+    CURRENT.read().unwrap().clone()
+}
+
+/// Reject vectors that do not fit the embeddings table
+pub fn check_dimension(len: usize) -> Result<(), AppError> {
+    let Some(registry) = current() else {
+        return Ok(());
+    };
+    if len as i32 == registry.dimension {
+        return Ok(());
+    }
+    Err(AppError::BadRequest(format!(
+        "向量维度不匹配: 输入为 {} 维, 向量库为 {} 维{}。请切换到相同的 embedding 模型, 或通过 /api/embedding/migrate 迁移向量库",
+        len,
+        registry.dimension,
+        registry
+            .provider
+            .as_deref()
+            .map(|p| format!(" ({})", p))
+            .unwrap_or_default()
+    )))
+}
+
+/// Status of the last migration in this process
+pub fn migration_status() -> Option<MigrationStatus> {
+    MIGRATION.lock().unwrap().clone()
+}
+
+fn update_status(f: impl FnOnce(&mut MigrationStatus)) {
+    if let Some(status) = MIGRATION.lock().unwrap().as_mut() {
+        f(status);
+    }
+}
+
+/// Embed text with the migration target provider
+async fn embed(
+    req: &MigrateRequest,
+    dimension: Option<i32>,
+    text: &str,
+) -> anyhow::Result<Vec<f32>> {
+    let config = crate::llm::ProviderConfig {
+        api_key: req.api_key.clone(),
+        base_url: req.ollama_base_url.clone(),
+        model: req.model.clone(),
+        dimension,
+        ..Default::default()
+    };
+    crate::llm::embedding_provider(&req.provider, &config)?
+        .embed_one(text)
+        .await
+}
+
+/// Validate the request, resolve the target dimension and start the migration
+pub async fn start_migration(
+    pool: &PgPool,
+    mut req: MigrateRequest,
+) -> Result<MigrationStatus, AppError> {
+    if !matches!(req.provider.to_lowercase().as_str(), "gemini" | "ollama") {
+        return Err(AppError::BadRequest(format!(
+            "不支持的 embedding provider: {}",
+            req.provider
+        )));
+    }
+    if migration_status().is_some_and(|s| s.status == "running") {
+        return Err(AppError::BadRequest("已有向量迁移正在进行".to_string()));
+    }
+    if req.provider.eq_ignore_ascii_case("gemini") {
+        req.api_key =
+            crate::credentials::resolve(pool, "gemini", req.api_key.as_deref(), None).await;
+    }
+
+    // Probe once: checks the provider works and detects the dimension
+    let probe = embed(&req, req.dimension, "dimension probe")
+        .await
+        .map_err(|e| AppError::BadGateway(format!("Embedding provider error: {}", e)))?;
+    if req.dimension.is_some_and(|d| d as usize != probe.len()) {
+        return Err(AppError::BadRequest(format!(
+            "模型返回 {} 维向量, 与指定的 {} 维不一致",
+            probe.len(),
+            req.dimension.unwrap_or_default()
+        )));
+    }
+    let dimension = probe.len() as i32;
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM embeddings")
+        .fetch_one(pool)
+        .await?;
+
+    // Fresh staging table for the re-embedded vectors
+    sqlx::query("DROP TABLE IF EXISTS embeddings_migration")
+        .execute(pool)
+        .await?;
+    sqlx::query(&format!(
+        "CREATE TABLE embeddings_migration (id TEXT PRIMARY KEY, vector vector({}) NOT NULL)",
+        dimension
+    ))
+    .execute(pool)
+    .await?;
+
+    let status = MigrationStatus {
+        provider: req.provider.to_lowercase(),
+        model: req.model.clone(),
+        dimension,
+        status: "running".to_string(),
+        total,
+        done: 0,
+        message: None,
+        started_at: chrono::Utc::now().timestamp(),
+        finished_at: None,
+    };
+    *MIGRATION.lock().unwrap() = Some(status.clone());
+
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let (status, message) = match run_migration(&pool, &req, dimension).await {
+            Ok(migrated) => (
+                "completed",
+                format!(
+                    "Migrated {} embeddings to {} dimensions",
+                    migrated, dimension
+                ),
+            ),
+            Err(e) => ("failed", e.to_string()),
+        };
+        tracing::info!("[Embedding] Migration {}: {}", status, message);
+        update_status(|s| {
+            s.status = status.to_string();
+            s.message = Some(message);
+            s.finished_at = Some(chrono::Utc::now().timestamp());
+        });
+    });
+
+    Ok(status)
+}
+
+/// Text an embedding row was built from. Embeddings keep no text, so it is
+/// rebuilt from the article (`content` is the body text or the row's chunk);
+/// sources without stored text fall back to the title.
+pub(crate) fn source_text(source: &str, title: &str, digest: Option<&str>, content: Option<&str>) -> String {
+    let text = match source {
+        "digest" => digest,
+        "content" => content,
+        _ => None,
+    };
+    text.filter(|t| !t.trim().is_empty())
+        .unwrap_or(title)
+        .chars()
+        .take(MAX_TEXT_CHARS)
+        .collect()
+}
+
+async fn run_migration(pool: &PgPool, req: &MigrateRequest, dimension: i32) -> anyhow::Result<u64> {
+    // Re-embed until no row is left; rows stored meanwhile are picked up too
+    loop {
+        let rows = sqlx::query_as::<_, PendingRow>(
+            r#"
+            SELECT e.id, e.source, COALESCE(a.title, e.title) AS title, a.digest, c.content, e.chunk_index
+            FROM embeddings e
+            LEFT JOIN articles a ON a.fakeid = e.fakeid AND a.aid = e.aid
+            LEFT JOIN article_content c ON c.id = a.id
+            WHERE NOT EXISTS (SELECT 1 FROM embeddings_migration m WHERE m.id = e.id)
+            ORDER BY e.id
+            LIMIT $1
+            "#,
+        )
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        // Chunks of one article are adjacent (ids share the fakeid:aid prefix): extract once
+        let mut body: Option<(String, String)> = None;
+        for row in rows {
+            let content = match (row.source.as_str(), row.content) {
+                ("content", Some(html)) => {
+                    if body.as_ref().is_none_or(|(h, _)| *h != html) {
+                        let text = crate::content::extract::extract(&html).text();
+                        body = Some((html, text));
+                    }
+                    let text = body.as_ref().map(|(_, t)| t.as_str()).unwrap_or("");
+                    match row.chunk_index {
+                        // Re-chunk with the same settings the indexer used
+                        Some(i) => crate::content::chunk::article_chunks(text)
+                            .into_iter()
+                            .nth(i as usize),
+                        None => Some(text.to_string()),
+                    }
+                }
+                _ => None,
+            };
+            let text = source_text(
+                &row.source,
+                &row.title,
+                row.digest.as_deref(),
+                content.as_deref(),
+            );
+            let vector = embed(req, Some(dimension), &text).await?;
+            if vector.len() as i32 != dimension {
+                anyhow::bail!(
+                    "Provider returned {} dimensions instead of {}",
+                    vector.len(),
+                    dimension
+                );
+            }
+            sqlx::query(
+                "INSERT INTO embeddings_migration (id, vector) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET vector = EXCLUDED.vector",
+            )
+            .bind(&row.id)
+            .bind(Vector::from(vector))
+            .execute(pool)
+            .await?;
+            update_status(|s| s.done += 1);
+        }
+    }
+
+    // Swap the column. Rows stored between the last batch and the lock have
+    // no migrated vector and are dropped; auto-index re-creates them.
+    let mut tx = pool.begin().await?;
+    sqlx::query("LOCK TABLE embeddings IN EXCLUSIVE MODE")
+        .execute(&mut *tx)
+        .await?;
+    let dropped = sqlx::query(
+        "DELETE FROM embeddings e WHERE NOT EXISTS (SELECT 1 FROM embeddings_migration m WHERE m.id = e.id)",
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query("ALTER TABLE embeddings DROP COLUMN vector")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!(
+        "ALTER TABLE embeddings ADD COLUMN vector vector({})",
+        dimension
+    ))
+    .execute(&mut *tx)
+    .await?;
+    let migrated = sqlx::query(
+        "UPDATE embeddings e SET vector = m.vector, provider = $1, dimension = $2 FROM embeddings_migration m WHERE m.id = e.id",
+    )
+    .bind(req.provider.to_lowercase())
+    .bind(dimension)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query("ALTER TABLE embeddings ALTER COLUMN vector SET NOT NULL")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "UPDATE embedding_registry SET provider = $1, model = $2, dimension = $3, updated_at = $4 WHERE name = $5",
+    )
+    .bind(req.provider.to_lowercase())
+    .bind(&req.model)
+    .bind(dimension)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(NAMESPACE)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    if dropped > 0 {
+        tracing::warn!(
+            "[Embedding] Dropped {} rows stored during migration",
+            dropped
+        );
+    }
+    sqlx::query("DROP TABLE IF EXISTS embeddings_migration")
+        .execute(pool)
+        .await?;
+
+    load(pool).await?;
+    // Dropping the column dropped its index
+    if let Err(e) = crate::vector_index::ensure(pool).await {
+        tracing::warn!("[Embedding] Failed to recreate vector index: {}", e);
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_text() {
+        assert_eq!(source_text("title", "标题", Some("摘要"), None), "标题");
+        assert_eq!(source_text("digest", "标题", Some("摘要"), None), "摘要");
+        assert_eq!(source_text("digest", "标题", Some("  "), None), "标题");
+        assert_eq!(source_text("content", "标题", None, Some("正文")), "正文");
+        assert_eq!(source_text("comment", "标题", None, Some("正文")), "标题");
+    }
+}
@@ -0,0 +1,212 @@
+//! Full-text index
+//!
+//! Postgres has no built-in Chinese parser, so text is segmented with jieba on
+//! our side and stored as a `simple`-config `tsvector` (`articles.search_tsv`,
+//! `article_content.search_tsv`). Rows whose text changes get their vector reset
+//! by a trigger and are picked up again by the background indexer.
+
+use std::time::Duration;
+
+use jieba_rs::Jieba;
+use lazy_static::lazy_static;
+use sqlx::PgPool;
+
+lazy_static! {
+    static ref JIEBA: Jieba = Jieba::new();
+}
+
+/// Rows read per query while indexing
+const BATCH_SIZE: i64 = 200;
+/// Pause between indexing passes
+const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Segment text into lowercase search tokens separated by spaces.
+/// Punctuation and whitespace tokens are dropped.
+pub fn segment(text: &str) -> String {
+    JIEBA
+        .cut_for_search(text, true)
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty() && t.chars().all(char::is_alphanumeric))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Distinct search terms of a query, longest first (for highlighting)
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms: Vec<String> = segment(query).split(' ').map(String::from).collect();
+    terms.retain(|t| !t.is_empty());
+    terms.sort_by(|a, b| b.chars().count().cmp(&a.chars().count()).then(a.cmp(b)));
+    terms.dedup();
+    terms
+}
+
+/// HTML snippet of `text` around the first match, with every term wrapped in `<mark>`
+pub fn snippet(text: &str, terms: &[String], radius: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let term_chars: Vec<Vec<char>> = terms.iter().map(|t| t.chars().collect()).collect();
+
+    let match_at = |i: usize| {
+        term_chars
+            .iter()
+            .find(|t| !t.is_empty() && lower[i..].starts_with(t))
+            .map(|t| t.len())
+    };
+
+    let first = (0..lower.len()).find(|&i| match_at(i).is_some());
+    let (start, end) = match first {
+        Some(pos) => (
+            pos.saturating_sub(radius),
+            (pos + radius * 2).min(chars.len()),
+        ),
+        None => (0, (radius * 3).min(chars.len())),
+    };
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut i = start;
+    while i < end {
+        match match_at(i) {
+            Some(len) => {
+                let len = len.min(end - i);
+                let word: String = chars[i..i + len].iter().collect();
+                out.push_str(&format!("<mark>{}</mark>", html_escape::encode_text(&word)));
+                i += len;
+            }
+            None => {
+                let mut buf = [0u8; 4];
+                out.push_str(&html_escape::encode_text(chars[i].encode_utf8(&mut buf)));
+                i += 1;
+            }
+        }
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+/// Index rows without a search vector, walking each table once in id order.
+/// Returns the number of rows indexed.
+///
+/// Rows whose text changes while they are segmented are not written; the
+/// change resets their vector anyway, and the next pass picks them up.
+pub async fn index_pending(pool: &PgPool) -> anyhow::Result<u64> {
+    let mut indexed = 0;
+
+    let mut cursor = String::new();
+    loop {
+        let articles: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, title, digest FROM articles WHERE search_tsv IS NULL AND id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(&cursor)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let full = articles.len() as i64 >= BATCH_SIZE;
+
+        for (id, title, digest) in articles {
+            // Title matches outrank digest matches
+            indexed += sqlx::query(
+                "UPDATE articles SET search_tsv = setweight(to_tsvector('simple', $2), 'A') || setweight(to_tsvector('simple', $3), 'B') \
+                 WHERE id = $1 AND title = $4 AND digest IS NOT DISTINCT FROM $5",
+            )
+            .bind(&id)
+            .bind(segment(&title))
+            .bind(segment(digest.as_deref().unwrap_or("")))
+            .bind(&title)
+            .bind(&digest)
+            .execute(pool)
+            .await?
+            .rows_affected();
+            cursor = id;
+        }
+        if !full {
+            break;
+        }
+    }
+
+    let mut cursor = String::new();
+    loop {
+        let contents: Vec<(String, String)> = sqlx::query_as(
+            "SELECT id, content FROM article_content WHERE search_tsv IS NULL AND id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(&cursor)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let full = contents.len() as i64 >= BATCH_SIZE;
+
+        for (id, content) in contents {
+            let hash = format!("{:x}", md5::compute(content.as_bytes()));
+            let text = tokio::task::spawn_blocking(move || {
+                crate::content::extract::extract(&content).text()
+            })
+            .await?;
+            let segmented = segment(&text);
+
+            // Skip the write if the content changed while we were extracting
+            indexed += sqlx::query(
+                "UPDATE article_content SET search_text = $2, search_tsv = to_tsvector('simple', $3) \
+                 WHERE id = $1 AND md5(content) = $4",
+            )
+            .bind(&id)
+            .bind(&text)
+            .bind(&segmented)
+            .bind(&hash)
+            .execute(pool)
+            .await?
+            .rows_affected();
+            cursor = id;
+        }
+        if !full {
+            break;
+        }
+    }
+
+    Ok(indexed)
+}
+
+/// Keep the index up to date in the background
+pub fn spawn_indexer(pool: PgPool) {
+    tokio::spawn(async move {
+        loop {
+            match index_pending(&pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("[Fulltext] Indexed {} rows", n),
+                Err(e) => tracing::error!("[Fulltext] Indexing failed: {}", e),
+            }
+            tokio::time::sleep(IDLE_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment() {
+        let tokens = segment("人工智能, AI 大模型!");
+        assert!(tokens.split(' ').any(|t| t == "人工智能"));
+        assert!(tokens.split(' ').any(|t| t == "ai"));
+        assert!(!tokens.contains(','));
+    }
+
+    #[test]
+    fn test_snippet() {
+        let terms = vec!["模型".to_string()];
+        let text = "今天我们来聊一聊大模型<应用>";
+        assert_eq!(
+            snippet(text, &terms, 3),
+            "…一聊大<mark>模型</mark>&lt;应用&gt;"
+        );
+        assert_eq!(snippet("没有命中", &terms, 1), "没有命…");
+    }
+}
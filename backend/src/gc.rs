@@ -0,0 +1,351 @@
+//! Cached content and asset garbage collection
+//!
+//! Stored article HTML (`article_content`) references images by their mmbiz URL, and `assets` stores one blob per URL. Nothing
+//! ties the two together, so deleting content leaves its images behind.
+//!
+//! - `purge_task` removes the cached HTML of a task's articles that no other
+//!   task references, then the assets only that HTML used.
+//! - `collect` removes every asset no cached HTML references any more. It runs
+//!   every `ASSET_GC_INTERVAL_HOURS` (default 24, 0 disables) and skips assets
+//!   younger than `ASSET_GC_MIN_AGE_HOURS` (default 24) so a fetch that has
+//!   stored images but not yet its HTML is never collected.
+//!
+//! URLs deduplicated into `asset_aliases` are collected like assets, and first:
+//! removing an asset that still has aliases hands its blob to one of them.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::repository::articles::url_variants;
+
+const DEFAULT_INTERVAL_HOURS: u64 = 24;
+const DEFAULT_MIN_AGE_HOURS: i64 = 24;
+/// Rows read per query when scanning content or assets
+const SCAN_BATCH: i64 = 200;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    static ref IMAGE_URL_RE: Regex =
+        Regex::new(r#"(?:https?:)?//mmbiz\.qpic\.cn/[^"'\s]+"#).unwrap();
+}
+
+/// What a GC run or a purge removed (or, for a dry run, would remove)
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub dry_run: bool,
+    /// Cached HTML rows removed (purge only)
+    pub cached_pages: u64,
+    pub assets: u64,
+    /// Blob and variant bytes freed
+    pub bytes: i64,
+    /// Unreferenced assets left alone because their backend is not configured
+    pub skipped: u64,
+    /// Variants whose asset (or alias) row no longer exists
+    pub orphan_variants: u64,
+}
+
+/// How an asset URL is matched against HTML: host and path, without the
+/// scheme or query, so raw, entity-encoded and normalized forms all match
+fn asset_key(url: &str) -> &str {
+    let url = url
+        .strip_prefix("https:")
+        .or_else(|| url.strip_prefix("http:"))
+        .unwrap_or(url);
+    let url = url.strip_prefix("//").unwrap_or(url);
+    url.split(['?', '&']).next().unwrap_or(url)
+}
+
+/// Asset URLs in an HTML page, in the forms they are stored under (raw and
+/// entity-decoded, with an `https:` scheme)
+fn asset_urls(html: &str) -> HashSet<String> {
+    let mut urls = HashSet::new();
+    for m in IMAGE_URL_RE.find_iter(html) {
+        let raw = m.as_str();
+        let decoded = html_escape::decode_html_entities(raw).to_string();
+        for url in [raw.to_string(), decoded] {
+            let url = if url.starts_with("//") {
+                format!("https:{}", url)
+            } else {
+                url
+            };
+            urls.insert(url);
+        }
+    }
+    urls
+}
+
+/// Keys of every asset referenced by stored HTML
+async fn referenced_keys(pool: &PgPool) -> Result<HashSet<String>, sqlx::Error> {
+    let mut keys = HashSet::new();
+    let mut after = String::new();
+    loop {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT id, content FROM article_content WHERE id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(&after)
+        .bind(SCAN_BATCH)
+        .fetch_all(pool)
+        .await?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        after = last.clone();
+        for (_, content) in &rows {
+            keys.extend(
+                IMAGE_URL_RE
+                    .find_iter(content)
+                    .map(|m| asset_key(m.as_str()).to_string()),
+            );
+        }
+    }
+    Ok(keys)
+}
+
+/// Remove assets unless dry-running, adding them to the report
+async fn remove_assets(
+    pool: &PgPool,
+    assets: Vec<(String, i64)>,
+    report: &mut Report,
+) -> anyhow::Result<()> {
+    for (url, size) in assets {
+        if report.dry_run {
+            report.assets += 1;
+            report.bytes += size;
+            continue;
+        }
+        match crate::storage::remove(pool, &url).await? {
+            Some(freed) => {
+                report.assets += 1;
+                report.bytes += freed;
+            }
+            None => report.skipped += 1,
+        }
+    }
+    Ok(())
+}
+
+/// Delete the cached HTML of a task's articles that no other task shares,
+/// and the assets nothing else references. Call before the task's
+/// `insight_articles` rows are deleted.
+pub async fn purge_task(pool: &PgPool, task_id: Uuid) -> anyhow::Result<Report> {
+    let urls: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT url FROM insight_articles a
+        WHERE task_id = $1
+          AND NOT EXISTS (SELECT 1 FROM insight_articles o WHERE o.url = a.url AND o.task_id <> $1)
+        "#,
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let mut report = Report::default();
+    if urls.is_empty() {
+        return Ok(report);
+    }
+
+    let variants: Vec<String> = urls.iter().flat_map(|u| url_variants(u)).collect();
+    let pages: Vec<String> =
+        sqlx::query_scalar("SELECT content FROM article_content WHERE original_url = ANY($1)")
+            .bind(&variants)
+            .fetch_all(pool)
+            .await?;
+    let candidates: HashSet<String> = pages.iter().flat_map(|html| asset_urls(html)).collect();
+
+    report.cached_pages = sqlx::query("DELETE FROM article_content WHERE original_url = ANY($1)")
+        .bind(&variants)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    if !candidates.is_empty() {
+        let referenced = referenced_keys(pool).await?;
+        let unreferenced: Vec<String> = candidates
+            .into_iter()
+            .filter(|url| !referenced.contains(asset_key(url)))
+            .collect();
+        let assets: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT url, 0::bigint FROM asset_aliases WHERE url = ANY($1)
+            UNION ALL
+            SELECT url, COALESCE(octet_length(data)::bigint, size::bigint, 0) FROM assets WHERE url = ANY($1)
+            "#,
+        )
+        .bind(&unreferenced)
+        .fetch_all(pool)
+        .await?;
+        remove_assets(pool, assets, &mut report).await?;
+    }
+
+    tracing::info!(
+        "[GC] Purged task {}: {} cached page(s), {} asset(s), {} bytes",
+        task_id,
+        report.cached_pages,
+        report.assets,
+        report.bytes
+    );
+    Ok(report)
+}
+
+/// Remove (or with `dry_run`, only count) assets no cached HTML references
+pub async fn collect(pool: &PgPool, dry_run: bool) -> anyhow::Result<Report> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("Asset GC is already running");
+    }
+    let result = collect_inner(pool, dry_run).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+async fn collect_inner(pool: &PgPool, dry_run: bool) -> anyhow::Result<Report> {
+    let min_age_hours = std::env::var("ASSET_GC_MIN_AGE_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_MIN_AGE_HOURS);
+    let cutoff = chrono::Utc::now().timestamp() - min_age_hours * 3600;
+    let referenced = referenced_keys(pool).await?;
+    let mut report = Report {
+        dry_run,
+        ..Default::default()
+    };
+
+    let mut after = String::new();
+    loop {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT x.url,
+                   COALESCE((SELECT SUM(COALESCE(v.size::bigint, octet_length(v.data)::bigint))
+                             FROM asset_variants v WHERE v.url = x.url), 0)::bigint
+            FROM asset_aliases x
+            WHERE x.url > $1 AND x.created_at < $2
+            ORDER BY x.url LIMIT $3
+            "#,
+        )
+        .bind(&after)
+        .bind(cutoff)
+        .bind(SCAN_BATCH)
+        .fetch_all(pool)
+        .await?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        after = last.clone();
+        let unreferenced = rows
+            .into_iter()
+            .filter(|(url, _)| !referenced.contains(asset_key(url)))
+            .collect();
+        remove_assets(pool, unreferenced, &mut report).await?;
+    }
+
+    let mut after = String::new();
+    loop {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            r#"
+            SELECT a.url,
+                   COALESCE(octet_length(a.data)::bigint, a.size::bigint, 0)
+                   + COALESCE((SELECT SUM(COALESCE(v.size::bigint, octet_length(v.data)::bigint))
+                               FROM asset_variants v WHERE v.url = a.url), 0)::bigint
+            FROM assets a
+            WHERE a.url > $1 AND COALESCE(a.create_time, 0) < $2
+            ORDER BY a.url LIMIT $3
+            "#,
+        )
+        .bind(&after)
+        .bind(cutoff)
+        .bind(SCAN_BATCH)
+        .fetch_all(pool)
+        .await?;
+        let Some((last, _)) = rows.last() else {
+            break;
+        };
+        after = last.clone();
+        let unreferenced = rows
+            .into_iter()
+            .filter(|(url, _)| !referenced.contains(asset_key(url)))
+            .collect();
+        remove_assets(pool, unreferenced, &mut report).await?;
+    }
+
+    let orphans = r#"
+        FROM asset_variants v
+        WHERE NOT EXISTS (SELECT 1 FROM assets a WHERE a.url = v.url)
+          AND NOT EXISTS (SELECT 1 FROM asset_aliases x WHERE x.url = v.url)
+    "#;
+    report.orphan_variants = if dry_run {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) {}", orphans))
+            .fetch_one(pool)
+            .await?;
+        count as u64
+    } else {
+        sqlx::query(&format!("DELETE {}", orphans))
+            .execute(pool)
+            .await?
+            .rows_affected()
+    };
+
+    Ok(report)
+}
+
+/// Run `collect` every `ASSET_GC_INTERVAL_HOURS`
+pub fn spawn(pool: PgPool) {
+    let hours = std::env::var("ASSET_GC_INTERVAL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_INTERVAL_HOURS);
+    if hours == 0 {
+        tracing::info!("[GC] Scheduled asset GC disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let period = Duration::from_secs(hours * 3600);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            match collect(&pool, false).await {
+                Ok(report) if report.assets == 0 && report.orphan_variants == 0 => {}
+                Ok(report) => tracing::info!(
+                    "[GC] Removed {} asset(s), {} bytes ({} skipped, {} orphan variant(s))",
+                    report.assets,
+                    report.bytes,
+                    report.skipped,
+                    report.orphan_variants
+                ),
+                Err(e) => tracing::warn!("[GC] Asset GC failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_asset_urls_and_keys() {
+        let html = r#"<img data-src="https://mmbiz.qpic.cn/mmbiz_png/abc/640?wx_fmt=png&amp;from=appmsg"><img src='//mmbiz.qpic.cn/mmbiz_jpg/def/0'>"#;
+        let urls = asset_urls(html);
+        assert!(urls.contains("https://mmbiz.qpic.cn/mmbiz_png/abc/640?wx_fmt=png&amp;from=appmsg"));
+        assert!(urls.contains("https://mmbiz.qpic.cn/mmbiz_png/abc/640?wx_fmt=png&from=appmsg"));
+        assert!(urls.contains("https://mmbiz.qpic.cn/mmbiz_jpg/def/0"));
+        assert_eq!(urls.len(), 3);
+
+        let keys: HashSet<&str> = urls.iter().map(|u| asset_key(u)).collect();
+        assert_eq!(
+            keys,
+            HashSet::from([
+                "mmbiz.qpic.cn/mmbiz_png/abc/640",
+                "mmbiz.qpic.cn/mmbiz_jpg/def/0"
+            ])
+        );
+        assert_eq!(
+            asset_key("//mmbiz.qpic.cn/mmbiz_jpg/def/0"),
+            "mmbiz.qpic.cn/mmbiz_jpg/def/0"
+        );
+    }
+}
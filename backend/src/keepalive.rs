@@ -0,0 +1,190 @@
+//! MP session keepalive
+//!
+//! MP logins die about four days after the QR scan, and a task outliving its
+//! session fails midway with "请先登录微信公众平台". Every
+//! `SESSION_KEEPALIVE_INTERVAL_SECS` (default 30 minutes, 0 disables) each
+//! stored session is pinged with a cheap authenticated call. Cookies the MP
+//! backend re-issues are saved and `expires_at` follows their expiry; sessions
+//! the backend rejects are marked expired at once rather than on the next task.
+
+use crate::cookie::{CookieEntity, SESSION_TTL_SECS};
+use crate::crawl::Priority;
+use crate::wechat::client::{MpClient, MpError};
+use crate::AppState;
+
+/// Default interval between keepalive rounds (30 minutes)
+const DEFAULT_INTERVAL_SECS: u64 = 30 * 60;
+/// `base_resp.ret` values meaning the login itself is gone
+const SESSION_INVALID_RETS: &[i64] = &[200003, 200040];
+/// Task duration per matched article when there is no history to go by
+const DEFAULT_SECS_PER_ARTICLE: f64 = 60.0;
+/// Completed tasks the duration estimate is based on
+const ESTIMATE_SAMPLE: i64 = 20;
+
+pub fn interval_secs() -> u64 {
+    std::env::var("SESSION_KEEPALIVE_INTERVAL_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS)
+}
+
+/// New `expires_at` of a session that just answered a ping.
+///
+/// Re-issued cookies carrying an expiry decide it (the earliest one, capped at
+/// a fresh login's lifetime). Otherwise the stored value stands, except that a
+/// session already past it evidently still works until the next round.
+pub fn next_expiry(current: i64, refreshed: &[CookieEntity], now: i64, interval: i64) -> i64 {
+    let reissued = refreshed
+        .iter()
+        .filter(|c| c.value != "EXPIRED" && !c.value.is_empty())
+        .filter_map(|c| c.expires_timestamp)
+        .map(|ms| ms / 1000)
+        .filter(|ts| *ts > now)
+        .min();
+    match reissued {
+        Some(ts) => ts.min(now + SESSION_TTL_SECS),
+        None => current.max(now + 2 * interval),
+    }
+}
+
+/// Ping one session and record the outcome
+async fn check(state: &AppState, auth_key: &str, interval: i64) {
+    let store = &state.cookie_store;
+    let result = match MpClient::for_auth_key(state, auth_key).await {
+        Ok(client) => client.ping(Priority::Background).await,
+        Err(e) => Err(e),
+    };
+    let recorded = match result {
+        Ok(refreshed) => store.record_alive(auth_key, &refreshed, interval).await,
+        Err(MpError::NotLoggedIn) => store.record_dead(auth_key, "未登录或登录已过期").await,
+        Err(MpError::Api { ret, msg }) if SESSION_INVALID_RETS.contains(&ret) => {
+            tracing::warn!(
+                "[Keepalive] Session {} rejected: {} ({})",
+                auth_key,
+                msg,
+                ret
+            );
+            store
+                .record_dead(auth_key, &format!("{} ({})", msg, ret))
+                .await
+        }
+        // Network trouble or frequency control: the session may be fine
+        Err(e) => store.record_check_failed(auth_key, &e.to_string()).await,
+    };
+    if let Err(e) = recorded {
+        tracing::error!("[Keepalive] Failed to record session {}: {}", auth_key, e);
+    }
+}
+
+/// One keepalive round over every live session
+pub async fn run_round(state: &AppState) {
+    let interval = interval_secs().max(60) as i64;
+    match state.cookie_store.live_auth_keys().await {
+        Ok(keys) => {
+            for auth_key in keys {
+                check(state, &auth_key, interval).await;
+            }
+        }
+        Err(e) => tracing::error!("[Keepalive] Failed to list sessions: {}", e),
+    }
+}
+
+/// Spawn the periodic keepalive job
+pub fn spawn(state: AppState) {
+    let interval_secs = interval_secs();
+    if interval_secs == 0 {
+        tracing::info!("[Keepalive] Session keepalive disabled");
+        return;
+    }
+    tracing::info!("[Keepalive] Pinging sessions every {}s", interval_secs);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            run_round(&state).await;
+        }
+    });
+}
+
+fn fmt_duration(secs: i64) -> String {
+    let minutes = (secs.max(0) + 59) / 60;
+    if minutes >= 60 {
+        format!("{}小时{}分钟", minutes / 60, minutes % 60)
+    } else {
+        format!("{}分钟", minutes)
+    }
+}
+
+/// Warning for task creation when the session is likely to expire before a
+/// task collecting `target_count` articles finishes. The estimate comes from
+/// recently completed tasks.
+pub async fn expiry_warning(state: &AppState, auth_key: &str, target_count: i32) -> Option<String> {
+    let (_, _, expires_at, _) = state.cookie_store.get_session_status(auth_key).await.ok()?;
+    let secs_per_article: Option<f64> = sqlx::query_scalar(
+        r#"
+        SELECT AVG((updated_at - created_at)::float8 / processed_count) FROM (
+            SELECT created_at, updated_at, processed_count FROM insight_tasks
+            WHERE status = 'completed' AND processed_count > 0 AND updated_at > created_at
+            ORDER BY created_at DESC LIMIT $1
+        ) recent
+        "#,
+    )
+    .bind(ESTIMATE_SAMPLE)
+    .fetch_one(&state.db_pool)
+    .await
+    .ok()?;
+
+    let estimate =
+        (secs_per_article.unwrap_or(DEFAULT_SECS_PER_ARTICLE) * target_count.max(1) as f64) as i64;
+    let remaining = expires_at - chrono::Utc::now().timestamp();
+    (remaining < estimate).then(|| {
+        format!(
+            "微信登录约{}后过期，而任务预计需要{}，可能中途失败，建议重新扫码登录",
+            fmt_duration(remaining),
+            fmt_duration(estimate)
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie(name: &str, value: &str, expires: Option<i64>) -> CookieEntity {
+        CookieEntity {
+            name: name.to_string(),
+            value: value.to_string(),
+            domain: None,
+            path: None,
+            expires: None,
+            expires_timestamp: expires.map(|ts| ts * 1000),
+        }
+    }
+
+    #[test]
+    fn test_next_expiry() {
+        let now = 1_000_000;
+        let interval = 1800;
+        // No re-issued cookies: keep the stored expiry, or bridge to the next round
+        assert_eq!(next_expiry(now + 7200, &[], now, interval), now + 7200);
+        assert_eq!(next_expiry(now - 10, &[], now, interval), now + 3600);
+
+        let refreshed = [
+            cookie("slave_sid", "a", Some(now + 86_400)),
+            cookie("slave_user", "b", Some(now + 172_800)),
+            cookie("old", "EXPIRED", Some(now + 10)),
+            cookie("gone", "c", Some(now - 10)),
+        ];
+        assert_eq!(next_expiry(now, &refreshed, now, interval), now + 86_400);
+
+        let far = [cookie("slave_sid", "a", Some(now + 30 * 86_400))];
+        assert_eq!(
+            next_expiry(now, &far, now, interval),
+            now + SESSION_TTL_SECS
+        );
+
+        assert_eq!(fmt_duration(59), "1分钟");
+        assert_eq!(fmt_duration(3 * 3600 + 600), "3小时10分钟");
+    }
+}
@@ -0,0 +1,239 @@
+//! Online availability of insight articles
+//!
+//! Checks the URL of each matched article (see `archive::check_url`) and keeps
+//! the outcome on `insight_articles.status`, so deleted, removed and
+//! "参数错误" links are visible in task results. Every check is also recorded
+//! in `article_link_checks`. A live page with no stored copy is saved on the
+//! spot, since the body is already in hand.
+//!
+//! Runs every `INSIGHT_LINK_CHECK_INTERVAL_SECS` (default 12 hours, 0 disables)
+//! over `INSIGHT_LINK_CHECK_BATCH` links (default 30), least recently checked
+//! first; `/api/insight/:id/verify_links` checks one task on demand, in the
+//! background as well.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use rand::Rng;
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::archive::{self, LinkStatus, VANISHED_STATUSES};
+use crate::repository::articles;
+
+const DEFAULT_INTERVAL_SECS: u64 = 12 * 60 * 60;
+const DEFAULT_BATCH: i64 = 30;
+/// Links checked more recently than this are skipped by the background job
+const RECHECK_AFTER_SECS: i64 = 24 * 60 * 60;
+
+lazy_static! {
+    /// Tasks with an on-demand check in progress
+    static ref CHECKING: Mutex<HashSet<Uuid>> = Mutex::new(HashSet::new());
+}
+
+/// Outcome of checking a set of links
+#[derive(Debug, Default, Serialize)]
+pub struct CheckSummary {
+    pub checked: usize,
+    pub ok: usize,
+    pub vanished: usize,
+    pub errors: usize,
+    /// Live pages stored because no copy existed yet
+    pub archived: usize,
+}
+
+/// Prefetch rank of an article, lower first. Articles likely to disappear
+/// soon come first: pages that no longer render normally and articles of
+/// accounts that already lost others. Vanished articles come last, as
+/// fetching them can only fail.
+pub fn prefetch_rank(status: Option<&str>, account_has_vanished: bool) -> u8 {
+    match status {
+        Some(s) if VANISHED_STATUSES.contains(&s) => 3,
+        Some("error") => 0,
+        _ if account_has_vanished => 0,
+        None => 1,
+        Some(_) => 2,
+    }
+}
+
+/// Accounts among `fakeids` with at least one vanished insight article
+pub async fn accounts_with_vanished(
+    pool: &PgPool,
+    fakeids: &[String],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let accounts: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT account_fakeid FROM insight_articles WHERE account_fakeid = ANY($1) AND status = ANY($2)",
+    )
+    .bind(fakeids)
+    .bind(&VANISHED_STATUSES[..])
+    .fetch_all(pool)
+    .await?;
+    Ok(accounts.into_iter().collect())
+}
+
+/// Check links one by one, updating every insight article with that URL
+async fn check_links(
+    pool: &PgPool,
+    links: Vec<(String, Option<String>)>,
+) -> anyhow::Result<CheckSummary> {
+    let client = reqwest::Client::builder().build()?;
+    let mut summary = CheckSummary::default();
+
+    for (i, (url, article_id)) in links.iter().enumerate() {
+        if i > 0 {
+            // Spread requests out to avoid WeChat frequency control
+            let delay = rand::thread_rng().gen_range(1000..=3000);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+
+        let check = archive::check_url(&client, url).await;
+        if let Err(e) = archive::record_check(pool, url, article_id.as_deref(), &check).await {
+            tracing::error!("[LinkStatus] Failed to record check for {}: {}", url, e);
+        }
+        sqlx::query(
+            "UPDATE insight_articles SET status = $2, status_checked_at = $3 WHERE url = $1",
+        )
+        .bind(url)
+        .bind(check.status.as_str())
+        .bind(articles::now_ts())
+        .execute(pool)
+        .await?;
+
+        summary.checked += 1;
+        if check.status == LinkStatus::Ok {
+            summary.ok += 1;
+            let stored = articles::find(
+                pool,
+                article_id.as_deref(),
+                Some(url),
+                articles::Freshness::Any,
+            )
+            .await?;
+            if stored.is_none() {
+                articles::save(pool, article_id.as_deref(), url, &check.body, None).await?;
+                summary.archived += 1;
+            }
+        } else if check.status.is_vanished() {
+            summary.vanished += 1;
+            tracing::warn!(
+                "[LinkStatus] {} ({}): {}",
+                check.status.as_str(),
+                url,
+                article_id.as_deref().unwrap_or("-")
+            );
+        } else {
+            summary.errors += 1;
+        }
+    }
+    Ok(summary)
+}
+
+/// Check up to `limit` links of a task, unchecked and least recently checked first
+pub async fn check_task(pool: &PgPool, task_id: Uuid, limit: i64) -> anyhow::Result<CheckSummary> {
+    let links: Vec<(String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT url, MAX(article_id)
+        FROM insight_articles WHERE task_id = $1
+        GROUP BY url
+        ORDER BY MIN(COALESCE(status_checked_at, 0))
+        LIMIT $2
+        "#,
+    )
+    .bind(task_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    check_links(pool, links).await
+}
+
+/// Run `check_task` in the background; `false` when the task is already being
+/// checked
+pub fn spawn_task_check(pool: PgPool, task_id: Uuid, limit: i64) -> bool {
+    if !CHECKING.lock().unwrap().insert(task_id) {
+        return false;
+    }
+    tokio::spawn(async move {
+        match check_task(&pool, task_id, limit).await {
+            Ok(summary) => tracing::info!("[LinkStatus] Task {}: {:?}", task_id, summary),
+            Err(e) => tracing::error!("[LinkStatus] Task {} check failed: {}", task_id, e),
+        }
+        CHECKING.lock().unwrap().remove(&task_id);
+    });
+    true
+}
+
+/// One background round: links not checked in the last day and not already
+/// known to be gone
+async fn check_stale(pool: &PgPool, limit: i64) -> anyhow::Result<CheckSummary> {
+    let links: Vec<(String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT url, MAX(article_id)
+        FROM insight_articles
+        WHERE (status IS NULL OR NOT (status = ANY($1)))
+          AND (status_checked_at IS NULL OR status_checked_at < $2)
+        GROUP BY url
+        ORDER BY MIN(COALESCE(status_checked_at, 0))
+        LIMIT $3
+        "#,
+    )
+    .bind(&VANISHED_STATUSES[..])
+    .bind(articles::now_ts() - RECHECK_AFTER_SECS)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    check_links(pool, links).await
+}
+
+/// Run `check_stale` every `INSIGHT_LINK_CHECK_INTERVAL_SECS`
+pub fn spawn(pool: PgPool) {
+    let interval_secs = std::env::var("INSIGHT_LINK_CHECK_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    let batch = std::env::var("INSIGHT_LINK_CHECK_BATCH")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_BATCH);
+    if interval_secs == 0 {
+        tracing::info!("[LinkStatus] Insight link checks disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let period = Duration::from_secs(interval_secs);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            match check_stale(&pool, batch).await {
+                Ok(summary) if summary.checked == 0 => {}
+                Ok(summary) => tracing::info!(
+                    "[LinkStatus] Checked {} link(s): ok={}, vanished={}, errors={}, archived={}",
+                    summary.checked,
+                    summary.ok,
+                    summary.vanished,
+                    summary.errors,
+                    summary.archived
+                ),
+                Err(e) => tracing::warn!("[LinkStatus] Link check round failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefetch_rank() {
+        assert_eq!(prefetch_rank(Some("error"), false), 0);
+        assert_eq!(prefetch_rank(Some("ok"), true), 0);
+        assert_eq!(prefetch_rank(None, false), 1);
+        assert_eq!(prefetch_rank(Some("ok"), false), 2);
+        assert_eq!(prefetch_rank(Some("deleted"), true), 3);
+        assert_eq!(prefetch_rank(Some("not_found"), false), 3);
+    }
+}
@@ -0,0 +1,225 @@
+//! Embedding cache
+//!
+//! `CachedEmbedding` looks every text up in `embedding_cache` by provider,
+//! model, output dimension and the SHA-256 of the text, and only sends the
+//! misses to the wrapped provider; their vectors are stored for next time.
+//! Cache errors are logged and fall through to the provider, so a broken
+//! cache costs API calls but never fails a task. Hits and misses since
+//! startup are counted for `/api/cache/stats`. Vectors older than
+//! `EMBEDDING_CACHE_RETENTION_DAYS` (default 90, 0 keeps everything) are
+//! pruned hourly.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+use super::EmbeddingProvider;
+
+const DEFAULT_RETENTION_DAYS: i64 = 90;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Texts looked up since startup
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+pub fn counters() -> CacheCounters {
+    CacheCounters {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
+
+/// Key of `text` in `embedding_cache`
+pub fn text_hash(text: &str) -> String {
+    hex::encode(Sha256::digest(text.as_bytes()))
+}
+
+/// Delete every cached vector; returns the rows removed
+pub async fn clear(pool: &PgPool) -> Result<u64> {
+    Ok(sqlx::query("DELETE FROM embedding_cache")
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+fn retention_days() -> i64 {
+    std::env::var("EMBEDDING_CACHE_RETENTION_DAYS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Delete vectors past the retention period
+pub async fn prune(pool: &PgPool) -> Result<u64> {
+    let days = retention_days();
+    if days <= 0 {
+        return Ok(0);
+    }
+    let cutoff = chrono::Utc::now().timestamp() - days * 24 * 3600;
+    Ok(
+        sqlx::query("DELETE FROM embedding_cache WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Start the hourly pruning
+pub fn spawn_pruner(pool: PgPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match prune(&pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("[EmbeddingCache] Pruned {} vectors", n),
+                Err(e) => tracing::warn!("[EmbeddingCache] Pruning failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Embedding provider that serves repeated texts from `embedding_cache`
+pub struct CachedEmbedding {
+    inner: Box<dyn EmbeddingProvider>,
+    pool: PgPool,
+    /// Requested output dimension, 0 for the model's default
+    dimension: i32,
+}
+
+impl CachedEmbedding {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, pool: PgPool, dimension: Option<i32>) -> Self {
+        CachedEmbedding {
+            inner,
+            pool,
+            dimension: dimension.unwrap_or(0),
+        }
+    }
+
+    async fn lookup(&self, hashes: &[String]) -> Result<HashMap<String, Vec<f32>>> {
+        let rows: Vec<(String, Vec<f32>)> = sqlx::query_as(
+            r#"
+            SELECT text_hash, embedding FROM embedding_cache
+            WHERE provider = $1 AND model = $2 AND dimension = $3 AND text_hash = ANY($4)
+            "#,
+        )
+        .bind(self.inner.name())
+        .bind(self.inner.model())
+        .bind(self.dimension)
+        .bind(hashes)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().collect())
+    }
+
+    /// Store vectors of equal length in one statement. Postgres cannot unnest
+    /// a 2-D array into rows, so they are sent concatenated and sliced back.
+    async fn store(&self, hashes: &[&str], embeddings: &[&[f32]]) -> Result<()> {
+        let Some(len) = embeddings.first().map(|e| e.len()) else {
+            return Ok(());
+        };
+        if embeddings.iter().any(|e| e.len() != len) {
+            anyhow::bail!("vectors of different lengths");
+        }
+        let flat: Vec<f32> = embeddings.iter().flat_map(|e| e.iter().copied()).collect();
+        sqlx::query(
+            r#"
+            INSERT INTO embedding_cache (provider, model, dimension, text_hash, embedding, created_at)
+            SELECT $1, $2, $3, t.hash, ($5::real[])[(t.i - 1) * $6 + 1 : t.i * $6], $7
+            FROM UNNEST($4::text[]) WITH ORDINALITY AS t(hash, i)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(self.inner.name())
+        .bind(self.inner.model())
+        .bind(self.dimension)
+        .bind(hashes)
+        .bind(&flat)
+        .bind(len as i32)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+impl EmbeddingProvider for CachedEmbedding {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let hashes: Vec<String> = texts.iter().map(|t| text_hash(t)).collect();
+            let mut cached = self.lookup(&hashes).await.unwrap_or_else(|e| {
+                tracing::warn!("[EmbeddingCache] Lookup failed: {}", e);
+                HashMap::new()
+            });
+
+            // Each distinct missing text is embedded once
+            let mut missing: Vec<usize> = Vec::new();
+            for (i, hash) in hashes.iter().enumerate() {
+                if !cached.contains_key(hash) && !missing.iter().any(|&j| hashes[j] == *hash) {
+                    missing.push(i);
+                }
+            }
+            HITS.fetch_add((texts.len() - missing.len()) as u64, Ordering::Relaxed);
+            MISSES.fetch_add(missing.len() as u64, Ordering::Relaxed);
+
+            if !missing.is_empty() {
+                let batch: Vec<String> = missing.iter().map(|&i| texts[i].clone()).collect();
+                let vectors = self.inner.embed(&batch).await?;
+                let (stored, embeddings): (Vec<&str>, Vec<&[f32]>) = missing
+                    .iter()
+                    .zip(&vectors)
+                    .map(|(&i, vector)| (hashes[i].as_str(), vector.as_slice()))
+                    .unzip();
+                if let Err(e) = self.store(&stored, &embeddings).await {
+                    tracing::warn!("[EmbeddingCache] Failed to store vectors: {}", e);
+                }
+                for (&i, vector) in missing.iter().zip(vectors) {
+                    cached.insert(hashes[i].clone(), vector);
+                }
+            }
+
+            hashes
+                .iter()
+                .map(|hash| {
+                    cached.get(hash).cloned().ok_or_else(|| {
+                        anyhow::anyhow!("No embedding returned from {}", self.name())
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_hash() {
+        let hash = text_hash("标题 摘要");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, text_hash("标题 摘要"));
+        assert_ne!(hash, text_hash("标题  摘要"));
+    }
+}
@@ -1,4 +1,22 @@
 //! DeepSeek LLM provider implementation
 //!
-//! Note: Text generation is handled inline in insight.rs for full control.
-//! This module is reserved for future shared utilities.
+//! DeepSeek speaks the OpenAI Chat Completions format, so this is the
+//! OpenAI-compatible provider pointed at its endpoint.
+
+use anyhow::Result;
+
+use super::openai_compatible::OpenAiCompatible;
+use super::{ChatProvider, ProviderConfig};
+
+const DEEPSEEK_API_BASE: &str = "https://api.deepseek.com";
+const DEFAULT_MODEL: &str = "deepseek-chat";
+
+pub fn chat(config: &ProviderConfig) -> Result<Box<dyn ChatProvider>> {
+    Ok(Box::new(OpenAiCompatible::new(
+        "deepseek",
+        DEEPSEEK_API_BASE,
+        &config.require_key("DeepSeek")?,
+        config.model.as_deref().unwrap_or(DEFAULT_MODEL),
+        config.proxy_url.as_deref(),
+    )?))
+}
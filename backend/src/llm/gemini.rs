@@ -1,64 +1,270 @@
 //! Gemini LLM provider implementation
 
 use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::{StreamExt, TryStreamExt};
+
+use super::{
+    ChatProvider, ChatReply, ChatRequest, EmbeddingProvider, ProviderConfig, TextStream, TokenUsage,
+};
 
 const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+const DEFAULT_CHAT_MODEL: &str = "gemini-2.0-flash";
+const EMBEDDING_MODEL: &str = "gemini-embedding-001";
+/// Most texts `batchEmbedContents` accepts per request
+const EMBEDDING_BATCH_LIMIT: usize = 100;
 
-/// Generate embedding using Gemini gemini-embedding-001
-/// Supports flexible output dimensions: 128-3072 (recommended: 768, 1536, 3072)
-pub async fn generate_embedding(api_key: &str, text: &str) -> Result<Vec<f32>> {
-    generate_embedding_with_dim(api_key, text, None).await
+pub struct Gemini {
+    api_key: String,
+    model: String,
 }
 
-/// Generate embedding with custom output dimension
-pub async fn generate_embedding_with_dim(
-    api_key: &str,
-    text: &str,
-    output_dim: Option<i32>,
-) -> Result<Vec<f32>> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "{}/models/gemini-embedding-001:embedContent?key={}",
-        GEMINI_API_BASE, api_key
-    );
-
-    let mut request_body = serde_json::json!({
-        "content": {
-            "parts": [{"text": text}]
-        }
-    });
+pub fn chat(config: &ProviderConfig) -> Result<Box<dyn ChatProvider>> {
+    Ok(Box::new(Gemini {
+        api_key: config.require_key("Gemini")?,
+        model: config
+            .model
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CHAT_MODEL.to_string()),
+    }))
+}
+
+impl ChatProvider for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
 
-    // Add output dimension if specified (MRL technique allows truncation)
-    if let Some(dim) = output_dim {
-        request_body["outputDimensionality"] = serde_json::json!(dim);
+    fn model(&self) -> &str {
+        &self.model
     }
 
-    let response = client.post(&url).json(&request_body).send().await?;
+    fn chat<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<ChatReply>> {
+        Box::pin(async move {
+            let text = prompt_text(req);
+            let response = self
+                .generate(req, "generateContent?", std::time::Duration::from_secs(180))
+                .await?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(anyhow::anyhow!(
-            "Gemini Embedding API error: {}",
-            error_text
-        ));
+            let json: serde_json::Value = response.json().await?;
+            let reply = candidate_text(&json)
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Invalid Gemini response"))?;
+
+            let count = |key: &str| {
+                json.get("usageMetadata")
+                    .and_then(|u| u.get(key))
+                    .and_then(|n| n.as_u64())
+            };
+            let usage = match (count("promptTokenCount"), count("candidatesTokenCount")) {
+                (Some(prompt_tokens), Some(completion_tokens)) => TokenUsage {
+                    prompt_tokens,
+                    completion_tokens,
+                },
+                _ => TokenUsage::estimate(&text, &reply),
+            };
+            Ok(ChatReply { text: reply, usage })
+        })
     }
 
-    let json: serde_json::Value = response.json().await?;
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<TextStream>> {
+        Box::pin(async move {
+            let response = self
+                .generate(
+                    req,
+                    "streamGenerateContent?alt=sse&",
+                    std::time::Duration::from_secs(600),
+                )
+                .await?;
+            // Every event is a partial response holding the next piece of text
+            let pieces = super::sse_data(response).try_filter_map(|data| async move {
+                let json: serde_json::Value = match serde_json::from_str(&data) {
+                    Ok(json) => json,
+                    Err(_) => return Ok(None),
+                };
+                Ok(candidate_text(&json)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string))
+            });
+            Ok(pieces.boxed())
+        })
+    }
+}
 
-    let values = json
-        .get("embedding")
-        .and_then(|e| e.get("values"))
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| anyhow::anyhow!("Invalid Gemini embedding response"))?;
+/// System prompt and prompt as the single user turn Gemini receives
+fn prompt_text(req: &ChatRequest<'_>) -> String {
+    match req.system {
+        Some(system) => format!("{}\n\n{}", system, req.prompt),
+        None => req.prompt.to_string(),
+    }
+}
 
-    let embedding: Vec<f32> = values
-        .iter()
-        .filter_map(|v| v.as_f64().map(|f| f as f32))
-        .collect();
+/// Text of the first candidate in a (partial) response
+fn candidate_text(json: &serde_json::Value) -> Option<&str> {
+    json.get("candidates")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("content"))
+        .and_then(|c| c.get("parts"))
+        .and_then(|p| p.get(0))
+        .and_then(|p| p.get("text"))
+        .and_then(|t| t.as_str())
+}
+
+impl Gemini {
+    /// POST `req` to `method`, which ends in `?` or `&` so the key can follow;
+    /// non-2xx responses become errors
+    async fn generate(
+        &self,
+        req: &ChatRequest<'_>,
+        method: &str,
+        timeout: std::time::Duration,
+    ) -> Result<reqwest::Response> {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/models/{}:{}key={}",
+            GEMINI_API_BASE, self.model, method, self.api_key
+        );
+
+        let mut generation_config = serde_json::json!({});
+        if let Some(temperature) = req.temperature {
+            generation_config["temperature"] = serde_json::json!(temperature);
+        }
+        if let Some(max_tokens) = req.max_tokens {
+            generation_config["maxOutputTokens"] = serde_json::json!(max_tokens);
+        }
+        if req.json {
+            generation_config["response_mime_type"] = serde_json::json!("application/json");
+        }
+
+        let response = client
+            .post(&url)
+            .json(&serde_json::json!({
+                "contents": [{"parts": [{"text": prompt_text(req)}]}],
+                "generationConfig": generation_config
+            }))
+            .timeout(timeout)
+            .send()
+            .await?;
 
-    if embedding.is_empty() {
-        return Err(anyhow::anyhow!("Empty embedding returned from Gemini"));
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "Gemini API error {}: {}",
+                status,
+                error_text
+            ));
+        }
+        Ok(response)
     }
+}
 
-    Ok(embedding)
+/// Embeddings from gemini-embedding-001.
+/// Supports flexible output dimensions: 128-3072 (recommended: 768, 1536, 3072)
+pub struct GeminiEmbedding {
+    api_key: String,
+    dimension: Option<i32>,
+}
+
+pub fn embedding(config: &ProviderConfig) -> Result<Box<dyn EmbeddingProvider>> {
+    Ok(Box::new(GeminiEmbedding {
+        api_key: config.require_key("Gemini")?,
+        dimension: config.dimension,
+    }))
+}
+
+impl GeminiEmbedding {
+    /// Embed up to `EMBEDDING_BATCH_LIMIT` texts in one `batchEmbedContents` call
+    async fn batch_embed_contents(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let _permit = super::embedding_permit().await;
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/models/{}:batchEmbedContents?key={}",
+            GEMINI_API_BASE, EMBEDDING_MODEL, self.api_key
+        );
+
+        let requests: Vec<serde_json::Value> = texts
+            .iter()
+            .map(|text| {
+                let mut request = serde_json::json!({
+                    "model": format!("models/{}", EMBEDDING_MODEL),
+                    "content": {
+                        "parts": [{"text": text}]
+                    }
+                });
+                // Add output dimension if specified (MRL technique allows truncation)
+                if let Some(dim) = self.dimension {
+                    request["outputDimensionality"] = serde_json::json!(dim);
+                }
+                request
+            })
+            .collect();
+
+        let response = client
+            .post(&url)
+            .json(&serde_json::json!({ "requests": requests }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "Gemini Embedding API error: {}",
+                error_text
+            ));
+        }
+
+        let json: serde_json::Value = response.json().await?;
+
+        let embeddings = json
+            .get("embeddings")
+            .and_then(|e| e.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Invalid Gemini embedding response"))?;
+        if embeddings.len() != texts.len() {
+            return Err(anyhow::anyhow!(
+                "Gemini returned {} embeddings for {} texts",
+                embeddings.len(),
+                texts.len()
+            ));
+        }
+
+        embeddings
+            .iter()
+            .map(|e| {
+                let embedding: Vec<f32> = e
+                    .get("values")
+                    .and_then(|v| v.as_array())
+                    .map(|values| {
+                        values
+                            .iter()
+                            .filter_map(|v| v.as_f64().map(|f| f as f32))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if embedding.is_empty() {
+                    return Err(anyhow::anyhow!("Empty embedding returned from Gemini"));
+                }
+                Ok(embedding)
+            })
+            .collect()
+    }
+}
+
+impl EmbeddingProvider for GeminiEmbedding {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        EMBEDDING_MODEL
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for batch in texts.chunks(EMBEDDING_BATCH_LIMIT) {
+                vectors.extend(self.batch_embed_contents(batch).await?);
+            }
+            Ok(vectors)
+        })
+    }
 }
@@ -1,7 +1,344 @@
 //! LLM abstraction layer for unified API calls
 //! Supports Gemini, DeepSeek, Ollama, and OpenAI-compatible APIs
+//!
+//! Providers implement `ChatProvider` and/or `EmbeddingProvider` and are
+//! registered by name below. Callers look one up with `chat_provider` or
+//! `embedding_provider` instead of talking to the HTTP APIs themselves; adding
+//! a provider means one new impl plus its registry entry.
 
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, StreamExt};
+use lazy_static::lazy_static;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+pub mod cache;
 pub mod deepseek;
 pub mod gemini;
 pub mod ollama;
 pub mod openai_compatible;
+pub mod usage;
+
+lazy_static! {
+    /// Caps concurrent embedding requests across tasks, search and auto-indexing
+    static ref EMBEDDING_SLOTS: Semaphore = Semaphore::new(
+        std::env::var("EMBEDDING_CONCURRENCY")
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(2)
+            .max(1)
+    );
+}
+
+/// Wait for a free embedding slot; hold the permit for the duration of the request
+pub async fn embedding_permit() -> SemaphorePermit<'static> {
+    EMBEDDING_SLOTS
+        .acquire()
+        .await
+        .expect("embedding semaphore is never closed")
+}
+
+/// Attempts made by `chat_with_retry`
+const CHAT_ATTEMPTS: u32 = 5;
+/// Pause between `chat_with_retry` attempts
+const CHAT_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Settings a provider is built from; each provider falls back to its
+/// environment variables and defaults for anything left unset
+#[derive(Debug, Clone, Default)]
+pub struct ProviderConfig {
+    pub api_key: Option<String>,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub proxy_url: Option<String>,
+    /// Embedding output dimension, for models that support truncation
+    pub dimension: Option<i32>,
+}
+
+impl ProviderConfig {
+    /// Configured API key, or an error naming the provider
+    fn require_key(&self, provider: &str) -> Result<String> {
+        self.api_key
+            .clone()
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{} API Key required", provider))
+    }
+}
+
+/// A single-turn chat completion
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequest<'a> {
+    pub system: Option<&'a str>,
+    pub prompt: &'a str,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Ask the model for a JSON object
+    pub json: bool,
+}
+
+/// Tokens consumed by one call
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Estimate from the texts, for APIs that do not report usage
+    pub fn estimate(prompt: &str, completion: &str) -> Self {
+        TokenUsage {
+            prompt_tokens: crate::content::chunk::count_tokens(prompt) as u64,
+            completion_tokens: crate::content::chunk::count_tokens(completion) as u64,
+        }
+    }
+}
+
+/// The model's reply and the tokens it took
+#[derive(Debug, Clone)]
+pub struct ChatReply {
+    pub text: String,
+    pub usage: TokenUsage,
+}
+
+/// Pieces of a reply as the model produces them
+pub type TextStream = BoxStream<'static, Result<String>>;
+
+pub trait ChatProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Model the requests go to, used for pricing
+    fn model(&self) -> &str;
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<ChatReply>>;
+
+    /// Stream the reply. Providers without a streaming API send it as one piece.
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<TextStream>> {
+        Box::pin(async move {
+            let reply = self.chat(req).await?;
+            Ok(futures::stream::once(async move { Ok(reply.text) }).boxed())
+        })
+    }
+}
+
+pub trait EmbeddingProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn model(&self) -> &str;
+
+    /// One vector per input text, in order
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>>;
+
+    fn embed_one<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>>> {
+        Box::pin(async move {
+            let texts = [text.to_string()];
+            self.embed(&texts)
+                .await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("No embedding returned from {}", self.name()))
+        })
+    }
+}
+
+type ChatFactory = fn(&ProviderConfig) -> Result<Box<dyn ChatProvider>>;
+type EmbeddingFactory = fn(&ProviderConfig) -> Result<Box<dyn EmbeddingProvider>>;
+
+const CHAT_PROVIDERS: &[(&str, ChatFactory)] = &[
+    ("gemini", gemini::chat),
+    ("deepseek", deepseek::chat),
+    ("ollama", ollama::chat),
+    ("openai_compatible", openai_compatible::chat),
+];
+
+const EMBEDDING_PROVIDERS: &[(&str, EmbeddingFactory)] = &[
+    ("gemini", gemini::embedding),
+    ("ollama", ollama::embedding),
+    ("openai_compatible", openai_compatible::embedding),
+];
+
+fn lookup<T: Copy>(registry: &[(&str, T)], kind: &str, name: &str) -> Result<T> {
+    let key = name.trim().to_lowercase();
+    registry
+        .iter()
+        .find(|(n, _)| *n == key)
+        .map(|(_, factory)| *factory)
+        .ok_or_else(|| {
+            let known: Vec<&str> = registry.iter().map(|(n, _)| *n).collect();
+            anyhow::anyhow!("Unknown {} provider: {} ({})", kind, name, known.join("/"))
+        })
+}
+
+/// Build the named chat provider
+pub fn chat_provider(name: &str, config: &ProviderConfig) -> Result<Box<dyn ChatProvider>> {
+    lookup(CHAT_PROVIDERS, "chat", name)?(config)
+}
+
+/// Build the named embedding provider
+pub fn embedding_provider(
+    name: &str,
+    config: &ProviderConfig,
+) -> Result<Box<dyn EmbeddingProvider>> {
+    lookup(EMBEDDING_PROVIDERS, "embedding", name)?(config)
+}
+
+/// Chat, retrying failed requests a few times before giving up
+pub async fn chat_with_retry(provider: &dyn ChatProvider, req: &ChatRequest<'_>) -> Result<String> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match provider.chat(req).await {
+            Ok(reply) => return Ok(reply.text),
+            Err(e) if attempt >= CHAT_ATTEMPTS => {
+                return Err(e.context(format!(
+                    "{} failed after {} attempts",
+                    provider.name(),
+                    CHAT_ATTEMPTS
+                )))
+            }
+            Err(e) => tracing::warn!(
+                "[LLM] {} error (attempt {}/{}): {}",
+                provider.name(),
+                attempt,
+                CHAT_ATTEMPTS,
+                e
+            ),
+        }
+        tokio::time::sleep(CHAT_RETRY_DELAY).await;
+    }
+}
+
+/// Payloads of the `data:` lines of a server-sent event response
+pub(crate) fn sse_data(response: reqwest::Response) -> TextStream {
+    let bytes = response.bytes_stream().fuse().boxed();
+    futures::stream::unfold((bytes, Vec::new()), |(mut bytes, mut buf)| async move {
+        loop {
+            if let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buf.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                if let Some(data) = line.trim_end().strip_prefix("data:") {
+                    return Some((Ok(data.trim_start().to_string()), (bytes, buf)));
+                }
+                continue;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => buf.extend_from_slice(&chunk),
+                Some(Err(e)) => return Some((Err(e.into()), (bytes, buf))),
+                // Flush a last line that has no newline
+                None if !buf.is_empty() => buf.push(b'\n'),
+                None => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Output language of tasks that do not set one
+pub const DEFAULT_OUTPUT_LANGUAGE: &str = "zh-CN";
+
+/// How a prompt names the language of a BCP 47 tag ("zh-CN", "en", ...)
+pub fn language_name(tag: &str) -> String {
+    let lower = tag.to_ascii_lowercase();
+    let mut parts = lower.split('-');
+    match parts.next().unwrap_or_default() {
+        "zh" if parts.any(|p| matches!(p, "hant" | "tw" | "hk" | "mo")) => {
+            "Traditional Chinese".to_string()
+        }
+        "zh" => "Simplified Chinese".to_string(),
+        "en" => "English".to_string(),
+        "ja" => "Japanese".to_string(),
+        "ko" => "Korean".to_string(),
+        _ => format!("the language tagged \"{}\"", tag),
+    }
+}
+
+/// Model output with any Markdown code fence around it removed
+pub fn strip_code_fence(text: &str) -> &str {
+    text.trim()
+        .trim_start_matches("```json")
+        .trim_start_matches("```")
+        .trim_end_matches("```")
+        .trim()
+}
+
+/// The JSON object in a model reply. Local models often wrap it in a
+/// `<think>` block, a code fence or a sentence of prose, so everything outside
+/// the outermost braces is dropped; replies without an object are returned
+/// with just the fence removed.
+pub fn extract_json(text: &str) -> &str {
+    let text = match text.rfind("</think>") {
+        Some(end) => &text[end + "</think>".len()..],
+        None => text,
+    };
+    let text = strip_code_fence(text);
+    match (text.find('{'), text.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_lookup() {
+        let config = ProviderConfig {
+            api_key: Some("key".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(chat_provider("Gemini", &config).unwrap().name(), "gemini");
+        assert_eq!(
+            chat_provider("deepseek", &config).unwrap().name(),
+            "deepseek"
+        );
+        assert!(chat_provider("deepseek", &ProviderConfig::default()).is_err());
+        assert!(chat_provider("claude", &config).is_err());
+        assert_eq!(
+            embedding_provider("ollama", &ProviderConfig::default())
+                .unwrap()
+                .name(),
+            "ollama"
+        );
+        let endpoint = ProviderConfig {
+            api_key: Some("key".to_string()),
+            base_url: Some("https://api.example.com/v1/".to_string()),
+            model: Some("text-embedding-3-small".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            embedding_provider("openai_compatible", &endpoint)
+                .unwrap()
+                .name(),
+            "openai_compatible"
+        );
+
+        assert_eq!(strip_code_fence("```json\n{\"a\":1}\n```"), "{\"a\":1}");
+        assert_eq!(
+            extract_json("<think>{maybe}</think>\nSure:\n```json\n{\"a\":{\"b\":1}}\n```"),
+            "{\"a\":{\"b\":1}}"
+        );
+        assert_eq!(extract_json("[\"x\"]"), "[\"x\"]");
+        assert_eq!(
+            chat_provider("ollama", &ProviderConfig::default())
+                .unwrap()
+                .name(),
+            "ollama"
+        );
+    }
+
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("zh-CN"), "Simplified Chinese");
+        assert_eq!(language_name("zh-Hant-TW"), "Traditional Chinese");
+        assert_eq!(language_name("en-US"), "English");
+        assert_eq!(language_name("fr"), "the language tagged \"fr\"");
+    }
+
+    #[tokio::test]
+    async fn test_sse_data() {
+        let body = "data: {\"a\":1}\r\n\r\n: keep-alive\nevent: x\ndata:[DONE]";
+        let response = reqwest::Response::from(axum::http::Response::new(body));
+        let data: Vec<String> = sse_data(response).map(|d| d.unwrap()).collect().await;
+        assert_eq!(data, vec!["{\"a\":1}", "[DONE]"]);
+    }
+}
@@ -1,41 +1,216 @@
 //! Ollama local LLM provider implementation
 
 use anyhow::Result;
+use futures::future::BoxFuture;
 use serde::Deserialize;
 
+use super::{ChatProvider, ChatReply, ChatRequest, EmbeddingProvider, ProviderConfig, TokenUsage};
+
+const DEFAULT_BASE_URL: &str = "http://127.0.0.1:11434";
+const DEFAULT_EMBEDDING_MODEL: &str = "qwen3-embedding:8b-q8_0";
+const DEFAULT_CHAT_MODEL: &str = "qwen3:8b";
+
 #[derive(Debug, Deserialize)]
 struct OllamaEmbedResponse {
     embeddings: Vec<Vec<f32>>,
 }
 
-/// Generate embedding using Ollama
-pub async fn generate_embedding(base_url: &str, model: &str, text: &str) -> Result<Vec<f32>> {
-    let client = reqwest::Client::builder()
+#[derive(Debug, Deserialize)]
+struct OllamaChatResponse {
+    message: OllamaMessage,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaMessage {
+    content: String,
+}
+
+fn base_url(config: &ProviderConfig) -> String {
+    config
+        .base_url
+        .clone()
+        .or_else(|| std::env::var("OLLAMA_BASE_URL").ok())
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string())
+}
+
+/// Local models are slow, and the server is on this machine: no proxy
+fn client(timeout_secs: u64) -> Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
         .no_proxy()
-        .timeout(std::time::Duration::from_secs(120))
-        .build()?;
+        .timeout(std::time::Duration::from_secs(timeout_secs))
+        .build()?)
+}
+
+/// Turn a non-2xx Ollama response into an error
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let error_text = response.text().await.unwrap_or_default();
+    tracing::error!("[Ollama] Error Body: '{}'", error_text);
+    Err(anyhow::anyhow!(
+        "Ollama error (Status: {}): {}",
+        status,
+        if error_text.is_empty() {
+            "(Empty response body)"
+        } else {
+            &error_text
+        }
+    ))
+}
+
+/// Whether the server at `OLLAMA_BASE_URL` answers, if that is set
+pub async fn ping() -> Option<Result<()>> {
+    let base_url = std::env::var("OLLAMA_BASE_URL").ok()?;
+    let result = async {
+        client(5)?
+            .get(format!("{}/api/tags", base_url.trim_end_matches('/')))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+    .await;
+    Some(result)
+}
+
+pub struct Ollama {
+    base_url: String,
+    model: String,
+}
 
-    let url = format!("{}/api/embed", base_url);
+/// Embeddings from `/api/embed`; `OLLAMA_BASE_URL` and `OLLAMA_EMBEDDING_MODEL`
+/// fill in what the config leaves unset
+pub fn embedding(config: &ProviderConfig) -> Result<Box<dyn EmbeddingProvider>> {
+    Ok(Box::new(Ollama {
+        base_url: base_url(config),
+        model: config
+            .model
+            .clone()
+            .or_else(|| std::env::var("OLLAMA_EMBEDDING_MODEL").ok())
+            .unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string()),
+    }))
+}
+
+impl EmbeddingProvider for Ollama {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
 
-    let response = client
-        .post(&url)
-        .json(&serde_json::json!({
-            "model": model,
-            "input": text
-        }))
-        .send()
-        .await?;
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let _permit = super::embedding_permit().await;
+            let client = client(600)?; // large batches are slow
 
-    if !response.status().is_success() {
-        let error_text = response.text().await?;
-        return Err(anyhow::anyhow!("Ollama Embedding error: {}", error_text));
+            let url = format!("{}/api/embed", self.base_url.trim_end_matches('/'));
+            tracing::debug!(
+                "[Ollama] Embedding {} texts with '{}' at {}",
+                texts.len(),
+                self.model,
+                url
+            );
+
+            let response = client
+                .post(&url)
+                .json(&serde_json::json!({
+                    "model": self.model,
+                    "input": texts
+                }))
+                .send()
+                .await
+                .inspect_err(|e| tracing::error!("[Ollama] Failed to connect to {}: {}", url, e))?;
+
+            let result: OllamaEmbedResponse = check_status(response).await?.json().await?;
+            if result.embeddings.len() != texts.len() {
+                return Err(anyhow::anyhow!(
+                    "Ollama returned {} embeddings for {} texts",
+                    result.embeddings.len(),
+                    texts.len()
+                ));
+            }
+            Ok(result.embeddings)
+        })
     }
+}
 
-    let result: OllamaEmbedResponse = response.json().await?;
+/// Chat through `/api/chat`; `OLLAMA_BASE_URL` and `OLLAMA_CHAT_MODEL` fill in
+/// what the config leaves unset
+pub fn chat(config: &ProviderConfig) -> Result<Box<dyn ChatProvider>> {
+    Ok(Box::new(Ollama {
+        base_url: base_url(config),
+        model: config
+            .model
+            .clone()
+            .or_else(|| std::env::var("OLLAMA_CHAT_MODEL").ok())
+            .unwrap_or_else(|| DEFAULT_CHAT_MODEL.to_string()),
+    }))
+}
 
-    result
-        .embeddings
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("No embedding returned from Ollama"))
+impl ChatProvider for Ollama {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<ChatReply>> {
+        Box::pin(async move {
+            let client = client(600)?;
+            let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+
+            let mut messages = Vec::new();
+            if let Some(system) = req.system {
+                messages.push(serde_json::json!({"role": "system", "content": system}));
+            }
+            messages.push(serde_json::json!({"role": "user", "content": req.prompt}));
+
+            let mut options = serde_json::json!({});
+            if let Some(temperature) = req.temperature {
+                options["temperature"] = serde_json::json!(temperature);
+            }
+            if let Some(max_tokens) = req.max_tokens {
+                options["num_predict"] = serde_json::json!(max_tokens);
+            }
+
+            let mut body = serde_json::json!({
+                "model": self.model,
+                "messages": messages,
+                "stream": false,
+                "options": options
+            });
+            if req.json {
+                // Constrains sampling to valid JSON
+                body["format"] = serde_json::json!("json");
+            }
+
+            let response = client
+                .post(&url)
+                .json(&body)
+                .send()
+                .await
+                .inspect_err(|e| tracing::error!("[Ollama] Failed to connect to {}: {}", url, e))?;
+
+            let result: OllamaChatResponse = check_status(response).await?.json().await?;
+            let usage = match (result.prompt_eval_count, result.eval_count) {
+                (Some(prompt_tokens), Some(completion_tokens)) => TokenUsage {
+                    prompt_tokens,
+                    completion_tokens,
+                },
+                _ => TokenUsage::estimate(req.prompt, &result.message.content),
+            };
+            Ok(ChatReply {
+                text: result.message.content,
+                usage,
+            })
+        })
+    }
 }
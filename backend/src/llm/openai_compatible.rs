@@ -3,8 +3,14 @@
 //! (e.g., POE, OpenRouter, Azure OpenAI, local deployments)
 
 use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::{StreamExt, TryStreamExt};
 use serde::{Deserialize, Serialize};
 
+use super::{
+    ChatProvider, ChatReply, ChatRequest, EmbeddingProvider, ProviderConfig, TextStream, TokenUsage,
+};
+
 #[derive(Debug, Serialize)]
 struct ChatMessage {
     role: String,
@@ -17,11 +23,25 @@ struct ChatCompletionRequest {
     messages: Vec<ChatMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ChatCompletionResponse {
     choices: Vec<Choice>,
+    usage: Option<CompletionUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionUsage {
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -46,48 +66,256 @@ fn build_client(proxy_url: Option<&str>) -> Result<reqwest::Client> {
     Ok(builder.build()?)
 }
 
-/// Generate text using an OpenAI-compatible API
-#[allow(dead_code)]
-pub async fn generate_text(
-    base_url: &str,
-    api_key: &str,
-    model: &str,
-    prompt: &str,
-    proxy_url: Option<&str>,
-) -> Result<String> {
-    let client = build_client(proxy_url)?;
-    
-    let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
-    
-    let request = ChatCompletionRequest {
-        model: model.to_string(),
-        messages: vec![ChatMessage {
+pub struct OpenAiCompatible {
+    name: &'static str,
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+}
+
+impl OpenAiCompatible {
+    pub fn new(
+        name: &'static str,
+        base_url: &str,
+        api_key: &str,
+        model: &str,
+        proxy_url: Option<&str>,
+    ) -> Result<Self> {
+        Ok(OpenAiCompatible {
+            name,
+            client: build_client(proxy_url)?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+        })
+    }
+}
+
+/// Any OpenAI-compatible endpoint; `OPENAI_COMPATIBLE_BASE_URL` and
+/// `OPENAI_COMPATIBLE_MODEL` fill in what the config leaves unset
+pub fn chat(config: &ProviderConfig) -> Result<Box<dyn ChatProvider>> {
+    let base_url = config
+        .base_url
+        .clone()
+        .or_else(|| std::env::var("OPENAI_COMPATIBLE_BASE_URL").ok())
+        .ok_or_else(|| anyhow!("OpenAI-compatible base URL required"))?;
+    let model = config
+        .model
+        .clone()
+        .or_else(|| std::env::var("OPENAI_COMPATIBLE_MODEL").ok())
+        .ok_or_else(|| anyhow!("OpenAI-compatible model required"))?;
+    Ok(Box::new(OpenAiCompatible::new(
+        "openai_compatible",
+        &base_url,
+        &config.require_key("OpenAI-compatible")?,
+        &model,
+        config.proxy_url.as_deref(),
+    )?))
+}
+
+impl OpenAiCompatible {
+    /// POST a completion request; non-2xx responses become errors
+    async fn complete(
+        &self,
+        req: &ChatRequest<'_>,
+        stream: bool,
+        timeout: std::time::Duration,
+    ) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.base_url);
+
+        let mut messages = Vec::with_capacity(2);
+        if let Some(system) = req.system {
+            messages.push(ChatMessage {
+                role: "system".to_string(),
+                content: system.to_string(),
+            });
+        }
+        messages.push(ChatMessage {
             role: "user".to_string(),
-            content: prompt.to_string(),
-        }],
-        max_tokens: None,
-    };
+            content: req.prompt.to_string(),
+        });
+        let request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages,
+            max_tokens: req.max_tokens,
+            temperature: req.temperature,
+            response_format: req
+                .json
+                .then(|| serde_json::json!({ "type": "json_object" })),
+            stream: stream.then_some(true),
+        };
 
-    let response = client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await?;
+        let response = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .json(&request)
+            .timeout(timeout)
+            .send()
+            .await?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(anyhow!("API error {}: {}", status, error_text));
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!("API error {}: {}", status, error_text));
+        }
+        Ok(response)
     }
+}
 
-    let data: ChatCompletionResponse = response.json().await?;
-    
-    data.choices
-        .first()
-        .and_then(|c| c.message.content.clone())
-        .ok_or_else(|| anyhow!("No response content from OpenAI-compatible API"))
+impl ChatProvider for OpenAiCompatible {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<ChatReply>> {
+        Box::pin(async move {
+            let response = self
+                .complete(req, false, std::time::Duration::from_secs(180))
+                .await?;
+            let data: ChatCompletionResponse = response.json().await?;
+
+            let text = data
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|c| c.message.content)
+                .ok_or_else(|| anyhow!("No response content from {}", self.name))?;
+            let usage = match data.usage {
+                Some(u) => TokenUsage {
+                    prompt_tokens: u.prompt_tokens,
+                    completion_tokens: u.completion_tokens,
+                },
+                None => TokenUsage::estimate(req.prompt, &text),
+            };
+            Ok(ChatReply { text, usage })
+        })
+    }
+
+    fn chat_stream<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<TextStream>> {
+        Box::pin(async move {
+            let response = self
+                .complete(req, true, std::time::Duration::from_secs(600))
+                .await?;
+            // Each event is a chunk whose delta carries the next piece of text
+            let pieces = super::sse_data(response).try_filter_map(|data| async move {
+                if data == "[DONE]" {
+                    return Ok(None);
+                }
+                let chunk: serde_json::Value = match serde_json::from_str(&data) {
+                    Ok(chunk) => chunk,
+                    Err(_) => return Ok(None),
+                };
+                Ok(chunk
+                    .pointer("/choices/0/delta/content")
+                    .and_then(|c| c.as_str())
+                    .filter(|c| !c.is_empty())
+                    .map(str::to_string))
+            });
+            Ok(pieces.boxed())
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: usize,
+}
+
+pub struct OpenAiCompatibleEmbedding {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    dimension: Option<i32>,
+}
+
+/// Embeddings from `{base_url}/embeddings`; `OPENAI_COMPATIBLE_BASE_URL` and
+/// `OPENAI_COMPATIBLE_EMBEDDING_MODEL` fill in what the config leaves unset
+pub fn embedding(config: &ProviderConfig) -> Result<Box<dyn EmbeddingProvider>> {
+    let base_url = config
+        .base_url
+        .clone()
+        .or_else(|| std::env::var("OPENAI_COMPATIBLE_BASE_URL").ok())
+        .ok_or_else(|| anyhow!("OpenAI-compatible base URL required"))?;
+    let model = config
+        .model
+        .clone()
+        .or_else(|| std::env::var("OPENAI_COMPATIBLE_EMBEDDING_MODEL").ok())
+        .ok_or_else(|| anyhow!("OpenAI-compatible embedding model required"))?;
+    Ok(Box::new(OpenAiCompatibleEmbedding {
+        client: build_client(config.proxy_url.as_deref())?,
+        base_url: base_url.trim_end_matches('/').to_string(),
+        api_key: config.require_key("OpenAI-compatible")?,
+        model,
+        dimension: config.dimension,
+    }))
+}
+
+impl EmbeddingProvider for OpenAiCompatibleEmbedding {
+    fn name(&self) -> &'static str {
+        "openai_compatible"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let _permit = super::embedding_permit().await;
+            let response = self
+                .client
+                .post(format!("{}/embeddings", self.base_url))
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .json(&EmbeddingRequest {
+                    model: &self.model,
+                    input: texts,
+                    dimensions: self.dimension,
+                })
+                .timeout(std::time::Duration::from_secs(120))
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(anyhow!("Embedding API error {}: {}", status, error_text));
+            }
+
+            let mut data: EmbeddingResponse = response.json().await?;
+            if data.data.len() != texts.len() {
+                return Err(anyhow!(
+                    "Embedding API returned {} vectors for {} texts",
+                    data.data.len(),
+                    texts.len()
+                ));
+            }
+            // `index` ties each vector to its input; order is not guaranteed
+            data.data.sort_by_key(|d| d.index);
+            Ok(data.data.into_iter().map(|d| d.embedding).collect())
+        })
+    }
 }
 
 /// Test connection to an OpenAI-compatible API (with proxy support)
@@ -98,9 +326,9 @@ pub async fn test_connection_with_proxy(
     proxy_url: Option<&str>,
 ) -> Result<String> {
     let client = build_client(proxy_url)?;
-    
+
     let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
-    
+
     let request = ChatCompletionRequest {
         model: model.to_string(),
         messages: vec![ChatMessage {
@@ -108,6 +336,9 @@ pub async fn test_connection_with_proxy(
             content: "Say 'OK' if you can hear me.".to_string(),
         }],
         max_tokens: Some(50),
+        temperature: None,
+        response_format: None,
+        stream: None,
     };
 
     let response = client
@@ -126,13 +357,21 @@ pub async fn test_connection_with_proxy(
     }
 
     let data: ChatCompletionResponse = response.json().await?;
-    
+
     let content = data
         .choices
         .first()
         .and_then(|c| c.message.content.clone())
         .unwrap_or_default();
 
-    let proxy_note = if proxy_url.is_some() { " (通过代理)" } else { "" };
-    Ok(format!("✓ 连接成功{}！模型响应: {}", proxy_note, content.trim()))
+    let proxy_note = if proxy_url.is_some() {
+        " (通过代理)"
+    } else {
+        ""
+    };
+    Ok(format!(
+        "✓ 连接成功{}！模型响应: {}",
+        proxy_note,
+        content.trim()
+    ))
 }
@@ -0,0 +1,246 @@
+//! Token usage and cost tracking
+//!
+//! A task wraps its providers in `MeteredChat` / `MeteredEmbedding`; every
+//! successful call adds its tokens and estimated cost to the task's row for
+//! that stage and model in `task_llm_usage`. Chat usage comes from the API
+//! response where the provider reports it. Embedding APIs are not consistent
+//! about it, so embedding tokens are always estimated from the input text.
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{ChatProvider, ChatReply, ChatRequest, EmbeddingProvider, TokenUsage};
+
+/// USD per million (prompt, completion) tokens, matched by model prefix; the
+/// longest matching prefix wins. Unknown and local models cost nothing.
+const PRICES: &[(&str, f64, f64)] = &[
+    ("gemini-2.0-flash-lite", 0.075, 0.30),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-2.5-flash-lite", 0.10, 0.40),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("gemini-embedding-001", 0.15, 0.0),
+    ("deepseek-chat", 0.27, 1.10),
+    ("deepseek-reasoner", 0.55, 2.19),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("text-embedding-3-small", 0.02, 0.0),
+    ("text-embedding-3-large", 0.13, 0.0),
+];
+
+/// Estimated USD cost of `usage` on `model`
+pub fn cost_usd(provider: &str, model: &str, usage: TokenUsage) -> f64 {
+    if provider == "ollama" {
+        return 0.0;
+    }
+    let model = model.to_lowercase();
+    let price = PRICES
+        .iter()
+        .filter(|(prefix, _, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _, _)| prefix.len());
+    match price {
+        Some((_, prompt, completion)) => {
+            (usage.prompt_tokens as f64 * prompt + usage.completion_tokens as f64 * completion)
+                / 1_000_000.0
+        }
+        None => 0.0,
+    }
+}
+
+/// Records the usage of one task's calls
+#[derive(Clone)]
+pub struct UsageMeter {
+    pool: PgPool,
+    task_id: Uuid,
+}
+
+impl UsageMeter {
+    pub fn new(pool: PgPool, task_id: Uuid) -> Self {
+        UsageMeter { pool, task_id }
+    }
+
+    /// Add one call to the task's totals. Failures are logged, never returned:
+    /// losing a usage row must not fail the task.
+    pub async fn record(&self, stage: &str, provider: &str, model: &str, usage: TokenUsage) {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO task_llm_usage
+                (task_id, stage, provider, model, calls, prompt_tokens, completion_tokens, cost_usd, updated_at)
+            VALUES ($1, $2, $3, $4, 1, $5, $6, $7, $8)
+            ON CONFLICT (task_id, stage, provider, model) DO UPDATE SET
+                calls = task_llm_usage.calls + 1,
+                prompt_tokens = task_llm_usage.prompt_tokens + EXCLUDED.prompt_tokens,
+                completion_tokens = task_llm_usage.completion_tokens + EXCLUDED.completion_tokens,
+                cost_usd = task_llm_usage.cost_usd + EXCLUDED.cost_usd,
+                updated_at = EXCLUDED.updated_at
+            "#,
+        )
+        .bind(self.task_id)
+        .bind(stage)
+        .bind(provider)
+        .bind(model)
+        .bind(usage.prompt_tokens as i64)
+        .bind(usage.completion_tokens as i64)
+        .bind(cost_usd(provider, model, usage))
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                "[Usage] Failed to record {} usage for task {}: {}",
+                stage,
+                self.task_id,
+                e
+            );
+        }
+    }
+}
+
+/// Chat provider that records each reply's usage under `stage`
+pub struct MeteredChat {
+    inner: Box<dyn ChatProvider>,
+    meter: UsageMeter,
+    stage: &'static str,
+}
+
+impl MeteredChat {
+    pub fn new(inner: Box<dyn ChatProvider>, meter: UsageMeter, stage: &'static str) -> Self {
+        MeteredChat {
+            inner,
+            meter,
+            stage,
+        }
+    }
+}
+
+impl ChatProvider for MeteredChat {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn chat<'a>(&'a self, req: &'a ChatRequest<'a>) -> BoxFuture<'a, Result<ChatReply>> {
+        Box::pin(async move {
+            let reply = self.inner.chat(req).await?;
+            self.meter
+                .record(self.stage, self.name(), self.model(), reply.usage)
+                .await;
+            Ok(reply)
+        })
+    }
+}
+
+/// Embedding provider that records estimated input tokens under `stage`
+pub struct MeteredEmbedding {
+    inner: Box<dyn EmbeddingProvider>,
+    meter: UsageMeter,
+    stage: &'static str,
+}
+
+impl MeteredEmbedding {
+    pub fn new(inner: Box<dyn EmbeddingProvider>, meter: UsageMeter, stage: &'static str) -> Self {
+        MeteredEmbedding {
+            inner,
+            meter,
+            stage,
+        }
+    }
+}
+
+impl EmbeddingProvider for MeteredEmbedding {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let vectors = self.inner.embed(texts).await?;
+            let usage = TokenUsage {
+                prompt_tokens: texts
+                    .iter()
+                    .map(|t| crate::content::chunk::count_tokens(t) as u64)
+                    .sum(),
+                completion_tokens: 0,
+            };
+            self.meter
+                .record(self.stage, self.name(), self.model(), usage)
+                .await;
+            Ok(vectors)
+        })
+    }
+}
+
+/// One `task_llm_usage` row, or a sum of rows
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct UsageRow {
+    pub stage: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost_usd: f64,
+}
+
+impl UsageRow {
+    /// Sum of `rows`
+    pub fn total(rows: &[UsageRow]) -> UsageRow {
+        UsageRow {
+            stage: None,
+            provider: None,
+            model: None,
+            calls: rows.iter().map(|r| r.calls).sum(),
+            prompt_tokens: rows.iter().map(|r| r.prompt_tokens).sum(),
+            completion_tokens: rows.iter().map(|r| r.completion_tokens).sum(),
+            cost_usd: rows.iter().map(|r| r.cost_usd).sum(),
+        }
+    }
+}
+
+/// Per-stage rows of a task and their total
+pub async fn task_usage(pool: &PgPool, task_id: Uuid) -> Result<serde_json::Value, sqlx::Error> {
+    let rows: Vec<UsageRow> = sqlx::query_as(
+        r#"
+        SELECT stage, provider, model, calls, prompt_tokens, completion_tokens, cost_usd
+        FROM task_llm_usage WHERE task_id = $1
+        ORDER BY stage, provider, model
+        "#,
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+
+    let total = UsageRow::total(&rows);
+    Ok(serde_json::json!({ "stages": rows, "total": total }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_usd() {
+        let usage = TokenUsage {
+            prompt_tokens: 1_000_000,
+            completion_tokens: 500_000,
+        };
+        // Longest prefix wins: "gemini-2.0-flash-lite" over "gemini-2.0-flash"
+        assert!((cost_usd("gemini", "gemini-2.0-flash-lite-001", usage) - 0.225).abs() < 1e-9);
+        assert!((cost_usd("gemini", "gemini-2.0-flash", usage) - 0.30).abs() < 1e-9);
+        assert!((cost_usd("openai_compatible", "GPT-4o-mini", usage) - 0.45).abs() < 1e-9);
+        assert_eq!(cost_usd("ollama", "gpt-4o", usage), 0.0);
+        assert_eq!(cost_usd("openai_compatible", "my-local-model", usage), 0.0);
+    }
+}
@@ -2,7 +2,8 @@
 //!
 //! A high-performance backend for semantic search and WeChat API proxy.
 
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use axum::{
@@ -16,12 +17,42 @@ use sqlx::PgPool;
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
+mod album;
 mod api;
+mod archive;
+mod auth;
+mod autoindex;
+mod cluster;
+mod comments;
+mod content;
 mod cookie;
+mod crawl;
+mod credentials;
 mod db;
+mod dedup;
+mod download;
+mod embedding_dump;
+mod embedding_registry;
 mod error;
+mod fulltext;
+mod gateway;
+mod gc;
+mod keepalive;
+mod link_status;
 mod llm;
+mod notify;
+mod prefilter;
 mod proxy;
+mod rag;
+mod ratelimit;
+mod repository;
+mod request_log;
+mod shutdown;
+mod storage;
+mod sync;
+mod task_queue;
+mod vector_index;
+mod wechat;
 
 use cookie::CookieStore;
 
@@ -32,6 +63,34 @@ struct Args {
     /// Enable debug level logging
     #[arg(long, default_value_t = false)]
     debug: bool,
+
+    /// Move image blobs from the database to the configured ASSET_STORAGE, then exit
+    #[arg(long, default_value_t = false)]
+    migrate_assets: bool,
+
+    /// Hash stored images and merge identical ones, then exit
+    #[arg(long, default_value_t = false)]
+    dedup_assets: bool,
+
+    /// Apply pending database migrations, then exit
+    #[arg(long, default_value_t = false)]
+    migrate_only: bool,
+
+    /// Address to listen on
+    #[arg(long, env = "BIND_HOST", default_value = "0.0.0.0")]
+    host: IpAddr,
+
+    /// Port to listen on
+    #[arg(long, env = "PORT", default_value_t = 3001)]
+    port: u16,
+
+    /// PEM certificate chain; serves HTTPS together with --tls-key
+    #[arg(long, env = "TLS_CERT_PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "TLS_KEY_PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 }
 
 /// Application state shared across handlers
@@ -39,10 +98,16 @@ struct Args {
 pub struct AppState {
     pub db_pool: PgPool,
     pub cookie_store: Arc<CookieStore>,
+    pub task_queue: Arc<task_queue::TaskQueue>,
+    /// Concurrency limits shared by every article / image download
+    pub downloads: Arc<download::DownloadScheduler>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Load environment variables (before arguments, which fall back to them)
+    dotenvy::dotenv().ok();
+
     // Parse command line arguments
     let args = Args::parse();
 
@@ -69,23 +134,69 @@ async fn main() -> anyhow::Result<()> {
 
     tracing::info!("Log level: {}", log_level);
 
-    // Load environment variables
-    dotenvy::dotenv().ok();
-
     // Initialize database
-    let db_pool = db::init_db().await?;
+    let db_pool = db::init_db(args.migrate_only).await?;
+    if args.migrate_only {
+        tracing::info!("Database schema is up to date");
+        return Ok(());
+    }
+
+    // Asset blob storage (Postgres, local disk or S3)
+    storage::init(&db_pool)?;
+    if args.migrate_assets {
+        let moved = storage::migrate_to_store(&db_pool).await?;
+        tracing::info!("Moved {} asset(s) out of the database", moved);
+        return Ok(());
+    }
+    if args.dedup_assets {
+        let (hashed, merged, freed) = storage::dedup_existing(&db_pool).await?;
+        tracing::info!(
+            "Hashed {} asset(s), merged {} duplicate(s), freed {} bytes",
+            hashed,
+            merged,
+            freed
+        );
+        return Ok(());
+    }
 
-    // Startup Cleanup: Reset any tasks stuck in processing/cancelling state
+    // Startup Cleanup: Reset any tasks stuck in processing/cancelling state.
+    // Interrupted tasks keep their checkpoint and can be continued via /api/insight/resume;
+    // tasks still waiting in the queue are restored below.
     tracing::info!("Cleaning up stuck tasks...");
     sqlx::query(
-        "UPDATE insight_tasks SET status = 'failed' WHERE status IN ('processing', 'cancelling')",
+        "UPDATE insight_tasks SET status = 'failed', completion_reason = 'Interrupted by server restart (resumable)' WHERE status = 'processing' OR (status = 'pending' AND id NOT IN (SELECT task_id FROM task_queue))",
     )
     .execute(&db_pool)
     .await?;
+    sqlx::query(
+        "DELETE FROM task_queue WHERE task_id NOT IN (SELECT id FROM insight_tasks WHERE status = 'pending')",
+    )
+    .execute(&db_pool)
+    .await?;
+    sqlx::query("UPDATE insight_tasks SET status = 'cancelled' WHERE status = 'cancelling'")
+        .execute(&db_pool)
+        .await?;
+    sqlx::query(
+        "UPDATE export_jobs SET status = 'failed', message = 'Interrupted by server restart' WHERE status = 'running'",
+    )
+    .execute(&db_pool)
+    .await?;
+    sqlx::query(
+        "UPDATE insight_revisions SET status = 'failed', error = 'Interrupted by server restart' WHERE status = 'running'",
+    )
+    .execute(&db_pool)
+    .await?;
+
+    // Load embedding dimension registry
+    let registry = embedding_registry::load(&db_pool).await?;
+    tracing::info!(
+        "Embedding registry: {} dimensions ({})",
+        registry.dimension,
+        registry.provider.as_deref().unwrap_or("unknown provider")
+    );
 
     // Initialize cookie store
     let cookie_store = CookieStore::new(db_pool.clone());
-    cookie_store.init().await?;
 
     // Cleanup expired sessions on startup
     let cleaned = cookie_store.cleanup_expired().await?;
@@ -93,12 +204,58 @@ async fn main() -> anyhow::Result<()> {
         tracing::info!("Cleaned up {} expired session(s)", cleaned);
     }
 
+    // Start archive availability verification job
+    archive::spawn_verifier(db_pool.clone());
+
+    // Start full-text indexer
+    fulltext::spawn_indexer(db_pool.clone());
+
+    // Start embedding auto-index daemon (idle unless AUTO_INDEX_ENABLED or started via API)
+    autoindex::spawn_daemon(db_pool.clone());
+
+    // Build the vector index once the embeddings table is large enough
+    vector_index::spawn_watcher(db_pool.clone());
+
     // Create app state
     let app_state = AppState {
         db_pool: db_pool.clone(),
         cookie_store: Arc::new(cookie_store),
+        task_queue: Arc::new(task_queue::TaskQueue::new()),
+        downloads: Arc::new(download::DownloadScheduler::from_env()),
     };
 
+    // Start the task queue, continuing tasks that were waiting at shutdown
+    let restored = app_state.task_queue.restore(&db_pool).await?;
+    if restored > 0 {
+        tracing::info!("Restored {} queued task(s)", restored);
+    }
+    task_queue::TaskQueue::spawn_dispatcher(app_state.clone());
+
+    // Record outbound MP requests
+    request_log::spawn(app_state.db_pool.clone());
+    gc::spawn(app_state.db_pool.clone());
+    llm::cache::spawn_pruner(app_state.db_pool.clone());
+    api::export::spawn_archive_purge(app_state.db_pool.clone());
+    link_status::spawn(app_state.db_pool.clone());
+
+    // Load download gateways and probe them periodically
+    gateway::spawn(app_state.db_pool.clone());
+
+    // Keep MP sessions alive and their expiry accurate
+    keepalive::spawn(app_state.clone());
+
+    // Start recurring task scheduler
+    api::schedule::spawn_scheduler(app_state.clone());
+
+    // Continue account syncs interrupted by the last shutdown
+    sync::resume_interrupted(&app_state).await;
+
+    // API tokens (env) and per-user tokens
+    auth::refresh(&app_state.db_pool).await?;
+    if !auth::enabled() {
+        tracing::warn!("No API token or user configured; the API is open to anyone who can reach it");
+    }
+
     // Setup CORS - Allow credentials by mirroring request origin
     let cors = CorsLayer::new()
         .allow_origin(tower_http::cors::AllowOrigin::mirror_request())
@@ -111,7 +268,17 @@ async fn main() -> anyhow::Result<()> {
             Method::PATCH,
             Method::OPTIONS,
         ])
-        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION, header::COOKIE]);
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::COOKIE,
+            header::HeaderName::from_static("x-api-key"),
+        ])
+        // Read by the frontend when saving a downloaded export
+        .expose_headers([
+            header::CONTENT_DISPOSITION,
+            header::HeaderName::from_static(api::insight::EXPORT_JOB_HEADER),
+        ]);
 
     // Build router
     let app = Router::new()
@@ -123,7 +290,39 @@ async fn main() -> anyhow::Result<()> {
             "/api/embedding/search",
             post(api::embedding::search_handler),
         )
+        .route(
+            "/api/embedding/hybrid_search",
+            post(api::embedding::hybrid_search_handler),
+        )
+        .route(
+            "/api/embedding/migrate",
+            get(api::embedding::migrate_status_handler).post(api::embedding::migrate_handler),
+        )
         .route("/api/embedding/stats", get(api::embedding::stats_handler))
+        .route(
+            "/api/embedding/similar",
+            get(api::embedding::similar_handler),
+        )
+        .route(
+            "/api/embedding/cluster",
+            post(api::embedding::cluster_handler),
+        )
+        .route(
+            "/api/embedding/index",
+            get(api::embedding::index_status_handler).post(api::embedding::index_build_handler),
+        )
+        .route(
+            "/api/embedding/index/drop",
+            post(api::embedding::index_drop_handler),
+        )
+        .route(
+            "/api/embedding/export",
+            get(api::embedding::export_handler),
+        )
+        .route(
+            "/api/embedding/import",
+            post(api::embedding::import_handler),
+        )
         .route("/api/embedding/clear", post(api::embedding::clear_handler))
         .route("/api/embedding/clean", post(api::embedding::clean_handler))
         .route(
@@ -134,13 +333,23 @@ async fn main() -> anyhow::Result<()> {
             "/api/embedding/auto_index",
             post(api::embedding::auto_index_handler),
         )
+        .route(
+            "/api/embedding/auto_index/daemon",
+            get(api::embedding::auto_index_daemon_status)
+                .post(api::embedding::auto_index_daemon_control),
+        )
         // ============ Public API v1 ============
         .route("/api/public/v1/account", get(api::public::search_account))
+        .route(
+            "/api/public/v1/account/stats",
+            get(api::public::account_stats),
+        )
         .route("/api/account/add", post(api::public::add_account)) // New endpoint for Insight "Add to Monitor"
         .route(
             "/api/public/v1/accounts/db",
             get(api::public::get_db_accounts),
         ) // New DB-backed endpoint
+        .route("/api/public/v1/suggest", get(api::public::suggest))
         .route("/api/public/v1/article", get(api::public::get_articles))
         .route(
             "/api/public/v1/article/fetch",
@@ -155,7 +364,12 @@ async fn main() -> anyhow::Result<()> {
             get(api::public::download_article),
         )
         .route("/api/public/v1/html", get(api::public::get_article_html))
+        .route(
+            "/api/public/v1/article/versions",
+            get(api::public::article_versions),
+        )
         .route("/api/public/v1/asset", get(api::public::get_asset))
+        .route("/api/public/v1/asset/thumb", get(api::public::get_asset_thumb))
         .route("/api/public/v1/comments", get(api::public::get_comments))
         .route("/api/public/v1/authkey", get(api::public::get_auth_key))
         // ============ Web Login API ============
@@ -168,6 +382,7 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/web/login/bizlogin", post(api::web::biz_login))
         .route("/api/web/mp/info", get(api::web::get_mp_info))
         .route("/api/web/mp/logout", get(api::web::logout))
+        .route("/api/web/session/health", get(api::web::session_health))
         .route("/api/web/mp/searchbiz", get(api::web::mp_searchbiz))
         .route("/api/web/mp/appmsgpublish", get(api::web::mp_appmsgpublish))
         .route(
@@ -178,36 +393,269 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/web/misc/status", get(api::web::misc_status))
         .route("/api/web/misc/accountname", get(api::web::misc_accountname))
         .route("/api/web/misc/comment", get(api::web::misc_comment))
+        .route("/api/web/misc/ratelimit", get(api::web::misc_ratelimit))
+        .route("/api/web/misc/requestlog", get(api::web::misc_requestlog))
+        .route(
+            "/api/web/misc/requestlog/stats",
+            get(api::web::misc_requestlog_stats),
+        )
         // ============ LLM API ============
+        .route("/api/llm/chat", post(api::llm::chat))
+        .route("/api/llm/chat/stream", post(api::llm::chat_stream))
         .route("/api/llm/test", post(api::llm::test_connection))
         .route(
             "/api/llm/test-ollama",
             post(api::llm::test_ollama_connection),
         )
+        .route("/api/llm/usage", get(api::llm::usage))
+        // ============ Corpus Chat API ============
+        .route("/api/chat/corpus", post(api::corpus::chat))
+        // ============ Settings API ============
+        .route(
+            "/api/settings/llm",
+            get(api::settings::get_llm).post(api::settings::save_llm),
+        )
+        .route("/api/settings/llm/delete", post(api::settings::delete_llm))
+        .route(
+            "/api/settings/notifiers",
+            get(api::settings::get_notifiers).post(api::settings::save_notifier),
+        )
+        .route(
+            "/api/settings/notifiers/delete",
+            post(api::settings::delete_notifier),
+        )
+        .route(
+            "/api/settings/notifiers/test",
+            post(api::settings::test_notifier),
+        )
         // ============ Insight API ============
         .route("/api/insight/create", post(api::insight::create_task))
+        .route("/api/insight/create_batch", post(api::insight::create_batch))
+        .route("/api/insight/queue", get(api::insight::queue_status))
+        .route(
+            "/api/insight/compare_accounts",
+            post(api::compare::compare_accounts),
+        )
+        .route(
+            "/api/insight/article/accept",
+            post(api::feedback::accept_article),
+        )
+        .route(
+            "/api/insight/article/reject",
+            post(api::feedback::reject_article),
+        )
+        .route(
+            "/api/insight/article/tags",
+            post(api::annotations::update_tags),
+        )
+        .route("/api/insight/article/notes", post(api::annotations::add_note))
+        .route(
+            "/api/insight/article/notes/update",
+            post(api::annotations::update_note),
+        )
+        .route(
+            "/api/insight/article/notes/delete",
+            post(api::annotations::delete_note),
+        )
         .route("/api/insight/list", get(api::insight::list_tasks))
         .route("/api/insight/cancel", post(api::insight::cancel_task))
+        .route("/api/insight/resume", post(api::insight::resume_task))
+        .route("/api/insight/schedule", post(api::schedule::create_schedule))
+        .route("/api/insight/schedules", get(api::schedule::list_schedules))
+        .route(
+            "/api/insight/schedule/update",
+            post(api::schedule::update_schedule),
+        )
+        .route(
+            "/api/insight/schedule/delete",
+            post(api::schedule::delete_schedule),
+        )
+        .route("/api/insight/schedule/run", post(api::schedule::run_schedule_now))
+        .route(
+            "/api/insight/templates",
+            get(api::template::list_templates).post(api::template::create_template),
+        )
+        .route(
+            "/api/insight/templates/update",
+            post(api::template::update_template),
+        )
+        .route(
+            "/api/insight/templates/delete",
+            post(api::template::delete_template),
+        )
+        .route(
+            "/api/insight/templates/launch",
+            post(api::template::launch_template),
+        )
+        .route("/api/insight/templates/:id", get(api::template::get_template))
         .route("/api/insight/delete", post(api::insight::delete_task))
         .route("/api/insight/export", post(api::insight::export_task))
+        .route(
+            "/api/insight/export/download",
+            post(api::insight::export_download),
+        )
+        .route("/api/insight/exports", get(api::export::list_exports))
+        .route("/api/insight/export/:job_id", get(api::export::get_export_job))
+        .route(
+            "/api/insight/export/:job_id/events",
+            get(api::export::export_events),
+        )
+        .route(
+            "/api/insight/export/:job_id/cancel",
+            post(api::export::cancel_export),
+        )
+        .route(
+            "/api/insight/export/:job_id/download",
+            get(api::export::download_export),
+        )
         .route("/api/insight/prefetch", post(api::insight::prefetch_task))
+        .route("/api/insight/digest", post(api::digest::generate_digest))
+        .route("/api/insight/digests", get(api::digest::list_digests))
+        .route("/api/insight/summarize", post(api::summary::summarize_task))
+        .route("/api/insight/share", post(api::share::create_share))
+        .route("/api/insight/shares", get(api::share::list_shares))
+        .route("/api/insight/share/revoke", post(api::share::revoke_share))
         .route("/api/insight/:id", get(api::insight::get_task))
+        .route("/api/insight/:id/share", post(api::share::create_task_share))
+        .route(
+            "/api/insight/:id/export.json",
+            get(api::task_export::export_json),
+        )
+        .route(
+            "/api/insight/:id/analytics",
+            get(api::analytics::task_analytics),
+        )
+        .route("/api/insight/:id/verify_links", post(api::insight::verify_links))
+        .route("/api/insight/:id/candidates", get(api::insight::list_candidates))
+        .route("/api/insight/:id/tags", get(api::annotations::task_tags))
+        .route(
+            "/api/insight/:id/keywords",
+            get(api::insight::get_keywords).put(api::insight::update_keywords),
+        )
+        .route("/api/insight/:id/articles/add", post(api::insight::add_articles))
+        .route("/api/insight/:id/reanalyze", post(api::reanalyze::reanalyze))
+        .route("/api/insight/:id/revisions", get(api::reanalyze::list_revisions))
+        .route(
+            "/api/insight/:id/revisions/:revision",
+            get(api::reanalyze::get_revision),
+        )
+        .route(
+            "/api/insight/:id/revisions/:revision/apply",
+            post(api::reanalyze::apply_revision),
+        )
+        // ============ RSS Feeds ============
+        .route("/api/feeds/task/:file", get(api::feeds::task_feed))
+        .route("/api/feeds/account/:file", get(api::feeds::account_feed))
+        // ============ Public Share (read-only) ============
+        .route("/api/shared/:token", get(api::share::view_share))
+        .route("/api/share/:token", get(api::share::view_share))
+        // ============ Download Gateways ============
+        .route(
+            "/api/proxy/gateways",
+            get(api::proxy_gateway::list_gateways).post(api::proxy_gateway::create_gateway),
+        )
+        .route(
+            "/api/proxy/gateways/update",
+            post(api::proxy_gateway::update_gateway),
+        )
+        .route(
+            "/api/proxy/gateways/delete",
+            post(api::proxy_gateway::delete_gateway),
+        )
+        .route(
+            "/api/proxy/gateways/check",
+            post(api::proxy_gateway::check_gateways),
+        )
+        .route(
+            "/api/proxy/gateways/stats",
+            get(api::proxy_gateway::gateway_stats),
+        )
+        // ============ Cache API ============
+        .route("/api/cache/invalidate", post(api::cache::invalidate))
+        .route("/api/cache/ttl", post(api::cache::set_ttl))
+        .route("/api/cache/stats", get(api::cache::stats))
+        .route("/api/cache/clear", post(api::cache::clear))
+        .route("/api/cache/evict", post(api::cache::evict))
+        .route("/api/cache/gc", get(api::cache::gc_report).post(api::cache::gc_run))
+        // ============ Archive API ============
+        .route("/api/archive/vanished", get(api::archive::list_vanished))
+        .route("/api/archive/history", get(api::archive::link_history))
+        .route("/api/archive/verify", post(api::archive::verify_now))
+        .route("/api/archive/alerts", get(api::archive::list_alerts))
+        // ============ Search API ============
+        .route("/api/search/fulltext", get(api::search::fulltext_search))
+        .route(
+            "/api/search/fulltext/reindex",
+            post(api::search::fulltext_reindex),
+        )
+        // ============ Account Sync API ============
+        .route("/api/account/sync", post(api::account::start_sync))
+        .route("/api/account/sync/:fakeid", get(api::account::get_sync_status))
+        .route(
+            "/api/account/sync/:fakeid/cancel",
+            post(api::account::cancel_sync),
+        )
+        .route("/api/account/refresh", post(api::account::refresh_accounts))
+        .route("/api/account/monitor", post(api::account::set_monitored))
+        .route(
+            "/api/account/import",
+            post(api::account_import::import_accounts),
+        )
+        .route("/api/account/lists", get(api::account_list::get_lists))
+        .route("/api/account/lists/add", post(api::account_list::add_entry))
+        .route(
+            "/api/account/lists/remove",
+            post(api::account_list::remove_entry),
+        )
+        // ============ Crawl Coordinator ============
+        .route("/api/crawl/status", get(api::crawl::status))
         // ============ PDF API ============
         .route("/api/pdf", post(api::pdf::generate_pdf))
+        .route("/api/docx", post(api::docx::generate_docx))
+        // ============ Auth API ============
+        .route("/api/auth/login", post(api::auth::login))
+        .route("/api/auth/logout", post(api::auth::logout))
+        .route("/api/auth/me", get(api::auth::me))
+        .route("/api/users", get(api::users::list_users))
+        .route("/api/users/create", post(api::users::create_user))
+        .route("/api/users/delete", post(api::users::delete_user))
         // ============ Health Check ============
         .route("/health", get(|| async { "OK" }))
+        .route("/health/ready", get(api::health::ready))
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::authenticate,
+        ))
         .layer(cors)
-        .with_state(app_state)
+        .with_state(app_state.clone())
         // Increase body limit to 300MB for large batch embedding uploads
         // 10,000 items * 4096 dimensions * 4 bytes = ~160MB raw data
         .layer(DefaultBodyLimit::max(300 * 1024 * 1024));
 
-    // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
-    tracing::info!("Starting server on {}", addr);
+    // Start server; on SIGTERM/Ctrl-C stop accepting, drain requests, then let jobs checkpoint
+    let addr = SocketAddr::new(args.host, args.port);
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        shutdown_handle.graceful_shutdown(Some(shutdown::timeout()));
+    });
+
+    let service = app.into_make_service();
+    if let (Some(cert), Some(key)) = (&args.tls_cert, &args.tls_key) {
+        let tls = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert, key).await?;
+        tracing::info!("Starting server on https://{}", addr);
+        axum_server::bind_rustls(addr, tls)
+            .handle(handle)
+            .serve(service)
+            .await?;
+    } else {
+        tracing::info!("Starting server on {}", addr);
+        axum_server::bind(addr).handle(handle).serve(service).await?;
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    shutdown::wait_for_jobs(&app_state, shutdown::timeout()).await;
+    tracing::info!("Server stopped");
 
     Ok(())
 }
@@ -0,0 +1,726 @@
+//! Task completion notifiers
+//!
+//! Finished insight tasks can be reported by email (SMTP) or through a
+//! Telegram bot, configured with `/api/settings/notifiers` and kept in
+//! `notifiers`. The SMTP password or bot token is encrypted like LLM keys (see
+//! `credentials`). The digest carries the prompt, counts and the top articles
+//! with their insights; a notifier marked `scheduled_only` only reports runs
+//! started by a schedule.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api::insight::{InsightArticle, InsightTask};
+use crate::credentials;
+use crate::error::AppError;
+
+/// Notifier kinds
+pub const KINDS: &[&str] = &["email", "telegram"];
+/// Articles listed in a digest
+const TOP_ARTICLES: usize = 10;
+/// Characters kept of each insight
+const INSIGHT_CHARS: usize = 300;
+/// Telegram rejects longer messages
+const TELEGRAM_MAX_CHARS: usize = 4096;
+const TELEGRAM_API: &str = "https://api.telegram.org";
+const SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// Defaults to the port of `security`: 465, 587 or 25
+    pub smtp_port: Option<u16>,
+    /// "tls" (implicit TLS), "starttls" (the default) or "none"
+    pub security: Option<String>,
+    /// SMTP login; the password is the notifier's secret
+    pub username: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    pub chat_id: String,
+    /// Bot API server, e.g. a self-hosted one or a reverse proxy
+    pub api_base: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Config {
+    Email(EmailConfig),
+    Telegram(TelegramConfig),
+}
+
+/// A notifier as shown in settings, without its secret
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NotifierStatus {
+    pub kind: String,
+    pub enabled: bool,
+    pub scheduled_only: bool,
+    pub config: serde_json::Value,
+    /// Masked SMTP password or bot token
+    pub hint: Option<String>,
+    pub last_sent_at: Option<i64>,
+    pub last_error: Option<String>,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveNotifier {
+    pub kind: String,
+    /// Defaults to true
+    pub enabled: Option<bool>,
+    #[serde(default)]
+    pub scheduled_only: bool,
+    pub config: serde_json::Value,
+    /// SMTP password or bot token; omitted keeps the stored one, "" clears it
+    pub secret: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct NotifierRow {
+    kind: String,
+    scheduled_only: bool,
+    config: serde_json::Value,
+    nonce: Option<Vec<u8>>,
+    ciphertext: Option<Vec<u8>>,
+}
+
+/// A notifier ready to send
+struct Notifier {
+    config: Config,
+    secret: Option<String>,
+}
+
+/// What a digest reports
+#[derive(Debug)]
+pub struct Digest {
+    pub heading: String,
+    pub prompt: String,
+    pub reason: Option<String>,
+    pub target_count: i32,
+    pub article_count: usize,
+    pub account_count: usize,
+    pub articles: Vec<DigestArticle>,
+}
+
+#[derive(Debug)]
+pub struct DigestArticle {
+    pub title: String,
+    pub url: String,
+    pub account_name: Option<String>,
+    pub similarity: Option<f64>,
+    pub insight: Option<String>,
+}
+
+/// Secrets are sealed under their own label, apart from LLM keys
+fn label(kind: &str) -> String {
+    format!("notifier:{}", kind)
+}
+
+fn check_kind(kind: &str) -> Result<(), AppError> {
+    if KINDS.contains(&kind) {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!("不支持的通知方式: {}", kind)))
+    }
+}
+
+fn parse_config(kind: &str, config: &serde_json::Value) -> Result<Config, String> {
+    match kind {
+        "email" => {
+            let config: EmailConfig = serde_json::from_value(config.clone())
+                .map_err(|e| format!("邮件配置无效: {}", e))?;
+            if config.smtp_host.trim().is_empty() {
+                return Err("SMTP 服务器不能为空".to_string());
+            }
+            if !matches!(
+                config.security.as_deref(),
+                None | Some("tls") | Some("starttls") | Some("none")
+            ) {
+                return Err("security 只能是 tls, starttls 或 none".to_string());
+            }
+            if config.to.is_empty() {
+                return Err("收件人不能为空".to_string());
+            }
+            for address in std::iter::once(&config.from).chain(&config.to) {
+                address
+                    .parse::<Mailbox>()
+                    .map_err(|_| format!("邮件地址无效: {}", address))?;
+            }
+            Ok(Config::Email(config))
+        }
+        "telegram" => {
+            let config: TelegramConfig = serde_json::from_value(config.clone())
+                .map_err(|e| format!("Telegram 配置无效: {}", e))?;
+            if config.chat_id.trim().is_empty() {
+                return Err("chat_id 不能为空".to_string());
+            }
+            Ok(Config::Telegram(config))
+        }
+        _ => Err(format!("不支持的通知方式: {}", kind)),
+    }
+}
+
+impl Config {
+    fn to_value(&self) -> serde_json::Value {
+        match self {
+            Config::Email(c) => serde_json::to_value(c),
+            Config::Telegram(c) => serde_json::to_value(c),
+        }
+        .unwrap_or_default()
+    }
+
+    /// Why a notifier cannot work without a secret, if so
+    fn missing_secret(&self) -> Option<&'static str> {
+        match self {
+            Config::Email(c) if c.username.as_deref().is_some_and(|u| !u.is_empty()) => {
+                Some("设置了 SMTP 用户名时需要提供密码")
+            }
+            Config::Email(_) => None,
+            Config::Telegram(_) => Some("需要提供 Bot Token"),
+        }
+    }
+}
+
+impl NotifierRow {
+    fn open(self) -> Result<Notifier, String> {
+        let config = parse_config(&self.kind, &self.config)?;
+        let secret = match (self.nonce, self.ciphertext) {
+            (Some(nonce), Some(ciphertext)) => Some(
+                credentials::unseal_secret(&label(&self.kind), &nonce, &ciphertext)
+                    .ok_or("密钥无法解密，SETTINGS_MASTER_KEY 是否已更改？".to_string())?,
+            ),
+            _ => None,
+        };
+        Ok(Notifier { config, secret })
+    }
+}
+
+// ============ Settings ============
+
+/// Every configured notifier, secrets masked
+pub async fn list(pool: &PgPool) -> Result<Vec<NotifierStatus>, AppError> {
+    Ok(sqlx::query_as::<_, NotifierStatus>(
+        "SELECT kind, enabled, scheduled_only, config, hint, last_sent_at, last_error, updated_at FROM notifiers ORDER BY kind",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Create or replace a notifier's settings
+pub async fn save(pool: &PgPool, req: &SaveNotifier) -> Result<NotifierStatus, AppError> {
+    check_kind(&req.kind)?;
+    let config = parse_config(&req.kind, &req.config).map_err(AppError::BadRequest)?;
+
+    // `None` keeps the stored secret
+    let secret = match req.secret.as_deref().map(str::trim) {
+        None => None,
+        Some("") => Some(None),
+        Some(secret) => {
+            let (nonce, ciphertext) = credentials::seal_secret(&label(&req.kind), secret)?;
+            Some(Some((nonce, ciphertext, credentials::mask(secret))))
+        }
+    };
+    let has_secret = match &secret {
+        Some(secret) => secret.is_some(),
+        None => sqlx::query_scalar::<_, bool>(
+            "SELECT ciphertext IS NOT NULL FROM notifiers WHERE kind = $1",
+        )
+        .bind(&req.kind)
+        .fetch_optional(pool)
+        .await?
+        .unwrap_or(false),
+    };
+    if let Some(message) = config.missing_secret().filter(|_| !has_secret) {
+        return Err(AppError::BadRequest(message.to_string()));
+    }
+
+    let replace_secret = secret.is_some();
+    let (nonce, ciphertext, hint) = match secret.flatten() {
+        Some((nonce, ciphertext, hint)) => (Some(nonce), Some(ciphertext), Some(hint)),
+        None => (None, None, None),
+    };
+    let status = sqlx::query_as::<_, NotifierStatus>(
+        r#"
+        INSERT INTO notifiers (kind, enabled, scheduled_only, config, nonce, ciphertext, hint, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (kind) DO UPDATE SET
+            enabled = EXCLUDED.enabled,
+            scheduled_only = EXCLUDED.scheduled_only,
+            config = EXCLUDED.config,
+            nonce = CASE WHEN $9 THEN EXCLUDED.nonce ELSE notifiers.nonce END,
+            ciphertext = CASE WHEN $9 THEN EXCLUDED.ciphertext ELSE notifiers.ciphertext END,
+            hint = CASE WHEN $9 THEN EXCLUDED.hint ELSE notifiers.hint END,
+            last_error = NULL,
+            updated_at = EXCLUDED.updated_at
+        RETURNING kind, enabled, scheduled_only, config, hint, last_sent_at, last_error, updated_at
+        "#,
+    )
+    .bind(&req.kind)
+    .bind(req.enabled.unwrap_or(true))
+    .bind(req.scheduled_only)
+    .bind(config.to_value())
+    .bind(nonce)
+    .bind(ciphertext)
+    .bind(hint)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(replace_secret)
+    .fetch_one(pool)
+    .await?;
+    Ok(status)
+}
+
+/// Delete a notifier; false when there was none
+pub async fn remove(pool: &PgPool, kind: &str) -> Result<bool, AppError> {
+    check_kind(kind)?;
+    let deleted = sqlx::query("DELETE FROM notifiers WHERE kind = $1")
+        .bind(kind)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    Ok(deleted > 0)
+}
+
+/// Send a sample digest through one notifier, enabled or not
+pub async fn send_test(pool: &PgPool, kind: &str) -> Result<(), AppError> {
+    check_kind(kind)?;
+    let row = sqlx::query_as::<_, NotifierRow>(
+        "SELECT kind, scheduled_only, config, nonce, ciphertext FROM notifiers WHERE kind = $1",
+    )
+    .bind(kind)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound("通知方式未配置".to_string()))?;
+    let notifier = row.open().map_err(AppError::BadRequest)?;
+
+    let result = send(&notifier, &sample_digest()).await;
+    record(pool, kind, &result).await;
+    result.map_err(|e| AppError::BadGateway(format!("发送失败: {}", e)))
+}
+
+// ============ Delivery ============
+
+/// Report a completed task to every enabled notifier, in the background
+pub fn task_completed(pool: PgPool, task_id: Uuid) {
+    tokio::spawn(async move {
+        if let Err(e) = notify_task_completed(&pool, task_id).await {
+            tracing::error!("[Notify] Digest of task {} failed: {}", task_id, e);
+        }
+    });
+}
+
+async fn notify_task_completed(pool: &PgPool, task_id: Uuid) -> anyhow::Result<()> {
+    let rows = sqlx::query_as::<_, NotifierRow>(
+        "SELECT kind, scheduled_only, config, nonce, ciphertext FROM notifiers WHERE enabled",
+    )
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let task = sqlx::query_as::<_, InsightTask>("SELECT * FROM insight_tasks WHERE id = $1")
+        .bind(task_id)
+        .fetch_one(pool)
+        .await?;
+    let rows: Vec<NotifierRow> = rows
+        .into_iter()
+        .filter(|row| !row.scheduled_only || task.schedule_id.is_some())
+        .collect();
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let articles = sqlx::query_as::<_, InsightArticle>(
+        "SELECT * FROM insight_articles WHERE task_id = $1 AND duplicates_of IS NULL AND feedback IS DISTINCT FROM 'rejected' ORDER BY similarity DESC NULLS LAST",
+    )
+    .bind(task_id)
+    .fetch_all(pool)
+    .await?;
+    let digest = digest(&task, &articles);
+
+    for row in rows {
+        let kind = row.kind.clone();
+        let result = match row.open() {
+            Ok(notifier) => send(&notifier, &digest).await,
+            Err(e) => Err(anyhow::anyhow!(e)),
+        };
+        match &result {
+            Ok(()) => tracing::info!("[Notify] Sent digest of task {} via {}", task_id, kind),
+            Err(e) => tracing::warn!("[Notify] {} digest of task {} failed: {}", kind, task_id, e),
+        }
+        record(pool, &kind, &result).await;
+    }
+    Ok(())
+}
+
+/// Keep the outcome of the last delivery for the settings page
+async fn record(pool: &PgPool, kind: &str, result: &anyhow::Result<()>) {
+    let query = match result {
+        Ok(()) => {
+            sqlx::query("UPDATE notifiers SET last_sent_at = $2, last_error = NULL WHERE kind = $1")
+                .bind(kind)
+                .bind(chrono::Utc::now().timestamp())
+        }
+        Err(e) => sqlx::query("UPDATE notifiers SET last_error = $2 WHERE kind = $1")
+            .bind(kind)
+            .bind(e.to_string()),
+    };
+    if let Err(e) = query.execute(pool).await {
+        tracing::error!("[Notify] Failed to record {} delivery: {}", kind, e);
+    }
+}
+
+async fn send(notifier: &Notifier, digest: &Digest) -> anyhow::Result<()> {
+    match &notifier.config {
+        Config::Email(config) => send_email(config, notifier.secret.as_deref(), digest).await,
+        Config::Telegram(config) => {
+            let token = notifier
+                .secret
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Bot Token 未设置"))?;
+            send_telegram(config, token, digest).await
+        }
+    }
+}
+
+async fn send_email(
+    config: &EmailConfig,
+    password: Option<&str>,
+    digest: &Digest,
+) -> anyhow::Result<()> {
+    let mut message = Message::builder()
+        .from(config.from.parse::<Mailbox>()?)
+        .subject(&digest.heading);
+    for to in &config.to {
+        message = message.to(to.parse::<Mailbox>()?);
+    }
+    let message = message.multipart(MultiPart::alternative_plain_html(
+        plain_text(digest),
+        html(digest),
+    ))?;
+
+    let host = config.smtp_host.trim();
+    let mut transport = match config.security.as_deref() {
+        Some("tls") => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        Some("none") => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+    };
+    if let Some(port) = config.smtp_port {
+        transport = transport.port(port);
+    }
+    if let Some(username) = config.username.as_deref().filter(|u| !u.is_empty()) {
+        transport = transport.credentials(Credentials::new(
+            username.to_string(),
+            password.unwrap_or_default().to_string(),
+        ));
+    }
+    transport
+        .timeout(Some(SEND_TIMEOUT))
+        .build()
+        .send(message)
+        .await?;
+    Ok(())
+}
+
+async fn send_telegram(
+    config: &TelegramConfig,
+    token: &str,
+    digest: &Digest,
+) -> anyhow::Result<()> {
+    let base = config
+        .api_base
+        .as_deref()
+        .map(|b| b.trim().trim_end_matches('/'))
+        .filter(|b| !b.is_empty())
+        .unwrap_or(TELEGRAM_API);
+    let response = reqwest::Client::new()
+        .post(format!("{}/bot{}/sendMessage", base, token))
+        .json(&serde_json::json!({
+            "chat_id": config.chat_id.trim(),
+            "text": telegram_text(digest),
+            "parse_mode": "HTML",
+            "disable_web_page_preview": true
+        }))
+        .timeout(SEND_TIMEOUT)
+        .send()
+        .await
+        // The URL holds the bot token
+        .map_err(|e| e.without_url())?;
+    let status = response.status();
+    if !status.is_success() {
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        anyhow::bail!(
+            "Telegram returned {}: {}",
+            status,
+            body["description"].as_str().unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+// ============ Digest ============
+
+/// First `max` characters of `text` on one line
+fn clip(text: &str, max: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > max {
+        format!("{}…", text.chars().take(max).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Digest of a completed task; `articles` are ranked best first
+pub fn digest(task: &InsightTask, articles: &[InsightArticle]) -> Digest {
+    let accounts: HashSet<&str> = articles
+        .iter()
+        .filter_map(|a| a.account_name.as_deref())
+        .collect();
+    let kind = if task.schedule_id.is_some() {
+        "定时任务"
+    } else {
+        "洞察任务"
+    };
+    Digest {
+        heading: format!("{}完成：{}", kind, clip(&task.prompt, 40)),
+        prompt: task.prompt.clone(),
+        reason: task.completion_reason.clone(),
+        target_count: task.target_count,
+        article_count: articles.len(),
+        account_count: accounts.len(),
+        articles: articles
+            .iter()
+            .take(TOP_ARTICLES)
+            .map(|a| DigestArticle {
+                title: a.title.clone(),
+                url: a.url.clone(),
+                account_name: a.account_name.clone(),
+                similarity: a.similarity,
+                insight: a
+                    .insight
+                    .as_deref()
+                    .filter(|i| !i.trim().is_empty())
+                    .map(|i| clip(i, INSIGHT_CHARS)),
+            })
+            .collect(),
+    }
+}
+
+fn sample_digest() -> Digest {
+    Digest {
+        heading: "通知测试".to_string(),
+        prompt: "这是一条测试通知，任务完成时会收到类似的摘要。".to_string(),
+        reason: None,
+        target_count: 1,
+        article_count: 1,
+        account_count: 1,
+        articles: vec![DigestArticle {
+            title: "示例文章".to_string(),
+            url: "https://mp.weixin.qq.com/".to_string(),
+            account_name: Some("示例公众号".to_string()),
+            similarity: Some(0.9),
+            insight: Some("文章的洞察会显示在这里。".to_string()),
+        }],
+    }
+}
+
+fn counts(digest: &Digest) -> String {
+    format!(
+        "共 {} 篇文章（目标 {}），来自 {} 个公众号",
+        digest.article_count, digest.target_count, digest.account_count
+    )
+}
+
+/// "公众号 · 0.87"
+fn byline(article: &DigestArticle) -> String {
+    let mut parts = Vec::new();
+    if let Some(account) = article.account_name.as_deref() {
+        parts.push(account.to_string());
+    }
+    if let Some(similarity) = article.similarity {
+        parts.push(format!("{:.2}", similarity));
+    }
+    parts.join(" · ")
+}
+
+fn plain_text(digest: &Digest) -> String {
+    let mut out = format!(
+        "{}\n\n关注点：{}\n{}\n",
+        digest.heading,
+        digest.prompt,
+        counts(digest)
+    );
+    if let Some(reason) = &digest.reason {
+        out.push_str(&format!("结束原因：{}\n", reason));
+    }
+    for (i, article) in digest.articles.iter().enumerate() {
+        out.push_str(&format!("\n{}. {}", i + 1, article.title));
+        let byline = byline(article);
+        if !byline.is_empty() {
+            out.push_str(&format!(" [{}]", byline));
+        }
+        out.push_str(&format!("\n   {}\n", article.url));
+        if let Some(insight) = &article.insight {
+            out.push_str(&format!("   {}\n", insight));
+        }
+    }
+    out
+}
+
+fn html(digest: &Digest) -> String {
+    let mut out = format!(
+        "<h2>{}</h2><p>关注点：{}<br>{}",
+        encode_text(&digest.heading),
+        encode_text(&digest.prompt),
+        counts(digest)
+    );
+    if let Some(reason) = &digest.reason {
+        out.push_str(&format!("<br>结束原因：{}", encode_text(reason)));
+    }
+    out.push_str("</p><ol>");
+    for article in &digest.articles {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> <small>{}</small>",
+            encode_double_quoted_attribute(&article.url),
+            encode_text(&article.title),
+            encode_text(&byline(article))
+        ));
+        if let Some(insight) = &article.insight {
+            out.push_str(&format!("<p>{}</p>", encode_text(insight)));
+        }
+        out.push_str("</li>");
+    }
+    out.push_str("</ol>");
+    out
+}
+
+/// Telegram's HTML subset, articles dropped from the end to fit one message
+pub fn telegram_text(digest: &Digest) -> String {
+    let mut out = format!(
+        "<b>{}</b>\n关注点：{}\n{}",
+        encode_text(&digest.heading),
+        encode_text(&clip(&digest.prompt, 500)),
+        counts(digest)
+    );
+    if let Some(reason) = &digest.reason {
+        out.push_str(&format!("\n结束原因：{}", encode_text(reason)));
+    }
+    for (i, article) in digest.articles.iter().enumerate() {
+        let mut item = format!(
+            "\n\n{}. <a href=\"{}\">{}</a>",
+            i + 1,
+            encode_double_quoted_attribute(&article.url),
+            encode_text(&article.title)
+        );
+        let byline = byline(article);
+        if !byline.is_empty() {
+            item.push_str(&format!("\n<i>{}</i>", encode_text(&byline)));
+        }
+        if let Some(insight) = &article.insight {
+            item.push_str(&format!("\n{}", encode_text(insight)));
+        }
+        let more = format!("\n\n…另有 {} 篇", digest.articles.len() - i);
+        if out.chars().count() + item.chars().count() + more.chars().count() > TELEGRAM_MAX_CHARS {
+            out.push_str(&more);
+            break;
+        }
+        out.push_str(&item);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, similarity: f64) -> InsightArticle {
+        InsightArticle {
+            id: Uuid::nil(),
+            task_id: Uuid::nil(),
+            title: title.to_string(),
+            url: "https://mp.weixin.qq.com/s/abc?a=1&b=2".to_string(),
+            account_name: Some("科技观察".to_string()),
+            account_fakeid: None,
+            publish_time: None,
+            similarity: Some(similarity),
+            insight: Some("x".repeat(1000)),
+            relevance_score: None,
+            created_at: 0,
+            chunk_similarity: None,
+            article_id: None,
+            duplicates_of: None,
+            feedback: None,
+            status: None,
+            status_checked_at: None,
+            author: None,
+            is_original: None,
+            biz: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let task = InsightTask {
+            id: Uuid::nil(),
+            prompt: "AI <芯片> 进展".to_string(),
+            status: "completed".to_string(),
+            keywords: vec![],
+            target_count: 20,
+            processed_count: 12,
+            created_at: 0,
+            updated_at: 0,
+            completion_reason: Some("All Keywords Searched".to_string()),
+            schedule_id: Some(Uuid::nil()),
+            summary: None,
+            queue_position: None,
+            output_language: None,
+        };
+        let articles: Vec<InsightArticle> = (0..12)
+            .map(|i| article(&format!("文章{}", i), 0.9))
+            .collect();
+
+        let mut digest = digest(&task, &articles);
+        assert_eq!(digest.heading, "定时任务完成：AI <芯片> 进展");
+        assert_eq!(digest.article_count, 12);
+        assert_eq!(digest.account_count, 1);
+        assert_eq!(digest.articles.len(), TOP_ARTICLES);
+        assert_eq!(
+            digest.articles[0].insight.as_ref().unwrap().chars().count(),
+            INSIGHT_CHARS + 1
+        );
+
+        assert!(plain_text(&digest).contains("1. 文章0 [科技观察 · 0.90]"));
+        assert!(html(&digest).contains("<h2>定时任务完成：AI &lt;芯片&gt; 进展</h2>"));
+
+        for article in &mut digest.articles {
+            article.title = "长".repeat(200);
+        }
+        let telegram = telegram_text(&digest);
+        assert!(telegram.chars().count() <= TELEGRAM_MAX_CHARS);
+        assert!(telegram.contains("<a href=\"https://mp.weixin.qq.com/s/abc?a=1&amp;b=2\">"));
+        assert!(telegram.ends_with("篇"));
+
+        assert!(parse_config("telegram", &serde_json::json!({"chat_id": ""})).is_err());
+        assert!(parse_config(
+            "email",
+            &serde_json::json!({"smtp_host": "smtp.qq.com", "from": "a@qq.com", "to": ["not an address"]})
+        )
+        .is_err());
+        let config = parse_config(
+            "email",
+            &serde_json::json!({"smtp_host": "smtp.qq.com", "username": "a@qq.com", "from": "监控 <a@qq.com>", "to": ["b@qq.com"]}),
+        )
+        .unwrap();
+        assert!(config.missing_secret().is_some());
+    }
+}
@@ -0,0 +1,135 @@
+//! Lexical candidate pre-filter
+//!
+//! Cheap checks a task can apply to each candidate's title and digest before
+//! it is embedded or sent to the reasoning model: terms that must all appear,
+//! terms that must not appear, and a regex the title must match. Terms match
+//! case-insensitively anywhere in the title or digest.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Pre-filter settings as stored with a task
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rules {
+    #[serde(default)]
+    pub must_include: Vec<String>,
+    #[serde(default)]
+    pub must_exclude: Vec<String>,
+    #[serde(default)]
+    pub title_regex: Option<String>,
+}
+
+/// Why a candidate was dropped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    MissingTerm(String),
+    ExcludedTerm(String),
+    TitleMismatch,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::MissingTerm(term) => write!(f, "missing term '{}'", term),
+            Rejection::ExcludedTerm(term) => write!(f, "excluded term '{}'", term),
+            Rejection::TitleMismatch => write!(f, "title does not match title_regex"),
+        }
+    }
+}
+
+/// Compiled `Rules`
+#[derive(Debug, Default)]
+pub struct Prefilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    title: Option<Regex>,
+}
+
+/// Lowercased terms, blanks dropped
+fn normalize(terms: &[String]) -> Vec<String> {
+    terms
+        .iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+impl Prefilter {
+    pub fn new(rules: &Rules) -> Result<Self, regex::Error> {
+        let title = match rules.title_regex.as_deref().map(str::trim) {
+            Some(pattern) if !pattern.is_empty() => Some(Regex::new(pattern)?),
+            _ => None,
+        };
+        Ok(Prefilter {
+            include: normalize(&rules.must_include),
+            exclude: normalize(&rules.must_exclude),
+            title,
+        })
+    }
+
+    /// Whether any check is configured
+    pub fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty() || self.title.is_some()
+    }
+
+    /// `Ok` when the article passes every check
+    pub fn check(&self, title: &str, digest: &str) -> Result<(), Rejection> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        if let Some(re) = &self.title {
+            if !re.is_match(title) {
+                return Err(Rejection::TitleMismatch);
+            }
+        }
+        let text = format!("{} {}", title, digest).to_lowercase();
+        if let Some(term) = self.exclude.iter().find(|t| text.contains(t.as_str())) {
+            return Err(Rejection::ExcludedTerm(term.clone()));
+        }
+        if let Some(term) = self.include.iter().find(|t| !text.contains(t.as_str())) {
+            return Err(Rejection::MissingTerm(term.clone()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefilter_check() {
+        let filter = Prefilter::new(&Rules {
+            must_include: vec!["AI".to_string(), " ".to_string()],
+            must_exclude: vec!["广告".to_string()],
+            title_regex: Some("^(?!.*招聘)".to_string()),
+        });
+        // Look-around is not supported by the regex crate
+        assert!(filter.is_err());
+
+        let filter = Prefilter::new(&Rules {
+            must_include: vec!["AI".to_string(), " ".to_string()],
+            must_exclude: vec!["广告".to_string()],
+            title_regex: Some("芯片|算力".to_string()),
+        })
+        .unwrap();
+        assert!(filter.is_active());
+        assert_eq!(filter.check("算力竞赛", "ai 基础设施"), Ok(()));
+        assert_eq!(
+            filter.check("算力竞赛", "云服务"),
+            Err(Rejection::MissingTerm("ai".to_string()))
+        );
+        assert_eq!(
+            filter.check("算力竞赛", "AI 广告投放"),
+            Err(Rejection::ExcludedTerm("广告".to_string()))
+        );
+        assert_eq!(
+            filter.check("大模型周报", "AI"),
+            Err(Rejection::TitleMismatch)
+        );
+
+        let empty = Prefilter::new(&Rules::default()).unwrap();
+        assert!(!empty.is_active());
+        assert_eq!(empty.check("任意标题", ""), Ok(()));
+    }
+}
@@ -3,13 +3,13 @@
 //! Handles forwarding requests to WeChat API with proper authentication.
 
 use axum::http::HeaderMap;
-use reqwest::header::{COOKIE, ORIGIN, REFERER, USER_AGENT};
+use reqwest::header::COOKIE;
 use serde::{Deserialize, Serialize};
 
 use crate::cookie::CookieStore;
 use crate::error::AppError;
-
-const WECHAT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+use crate::request_log;
+use crate::wechat::client::with_mp_headers;
 
 /// Options for proxying a request to WeChat
 #[derive(Debug)]
@@ -47,13 +47,7 @@ pub async fn proxy_mp_request(options: ProxyRequestOptions) -> Result<reqwest::R
         }
     }
 
-    let mut request = client.request(options.method.clone(), &url);
-
-    // Set headers
-    request = request
-        .header(REFERER, "https://mp.weixin.qq.com/")
-        .header(ORIGIN, "https://mp.weixin.qq.com")
-        .header(USER_AGENT, WECHAT_USER_AGENT);
+    let mut request = with_mp_headers(client.request(options.method.clone(), &url));
 
     // Add cookie if provided
     if let Some(cookie) = &options.cookie {
@@ -71,6 +65,37 @@ pub async fn proxy_mp_request(options: ProxyRequestOptions) -> Result<reqwest::R
     Ok(response)
 }
 
+/// Proxy a request, parse the JSON body and record the call in `request_log`
+pub async fn proxy_mp_request_logged(
+    options: ProxyRequestOptions,
+    source: &'static str,
+    session: &str,
+) -> Result<serde_json::Value, AppError> {
+    let entry = request_log::Entry::start(
+        &options.endpoint,
+        source,
+        session,
+        options.query.as_deref().unwrap_or_default(),
+    );
+    let mut status = None;
+    let result = async {
+        let response = proxy_mp_request(options).await?;
+        status = Some(response.status().as_u16());
+        Ok::<serde_json::Value, AppError>(response.json().await?)
+    }
+    .await;
+    match result {
+        Ok(json) => {
+            entry.finish(status, crate::ratelimit::base_ret(&json), None);
+            Ok(json)
+        }
+        Err(e) => {
+            entry.finish(status, None, Some(e.to_string()));
+            Err(e)
+        }
+    }
+}
+
 /// Proxy a request and return JSON
 #[allow(dead_code)]
 pub async fn proxy_mp_request_json<T: for<'de> Deserialize<'de>>(
@@ -0,0 +1,257 @@
+//! Retrieval-augmented chat over the article corpus
+//!
+//! A question is embedded with the provider the embeddings table was built
+//! with, the nearest title/digest/content embeddings are looked up, and their
+//! text is rebuilt from the stored articles (embeddings keep no text). The
+//! passages are numbered per article so the model can cite them as `[n]` and
+//! the reply can be matched back to titles and links.
+
+use pgvector::Vector;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+/// Candidates fetched per requested passage; duplicates of one article's
+/// title and digest are folded away afterwards
+const CANDIDATE_FACTOR: i64 = 3;
+/// History messages kept in the prompt
+const MAX_HISTORY: usize = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryMessage {
+    pub role: String, // "user" or "assistant"
+    pub content: String,
+}
+
+/// A retrieved piece of an article
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub article_id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub text: String,
+    pub score: f32,
+}
+
+/// An article the answer may cite as `[index]`
+#[derive(Debug, Clone, Serialize)]
+pub struct Citation {
+    pub index: usize,
+    pub article_id: String,
+    pub title: String,
+    pub link: Option<String>,
+    /// Best passage similarity
+    pub score: f32,
+    /// Whether the answer actually refers to it
+    pub cited: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct CandidateRow {
+    fakeid: String,
+    aid: Option<String>,
+    title: String,
+    source: String,
+    chunk_index: Option<i32>,
+    link: Option<String>,
+    digest: Option<String>,
+    /// Raw cached article HTML, only loaded for content chunks
+    content: Option<String>,
+    score: f64,
+}
+
+/// The `top_k` passages most similar to `vector` scoring at least `min_score`
+pub async fn retrieve(
+    pool: &PgPool,
+    vector: Vec<f32>,
+    top_k: usize,
+    min_score: f32,
+) -> Result<Vec<Passage>, AppError> {
+    let rows: Vec<CandidateRow> = sqlx::query_as(
+        r#"
+        WITH nearest AS (
+            SELECT e.fakeid, e.aid, e.title, e.source, e.chunk_index,
+                   1 - (e.vector <=> $1::vector) AS score
+            FROM embeddings e
+            ORDER BY e.vector <=> $1::vector
+            LIMIT $2
+        )
+        SELECT n.fakeid, n.aid, COALESCE(a.title, n.title) AS title, n.source, n.chunk_index,
+               a.link, a.digest,
+               CASE WHEN n.source = 'content' THEN c.content END AS content,
+               n.score
+        FROM nearest n
+        LEFT JOIN articles a ON a.fakeid = n.fakeid AND a.aid = n.aid
+        LEFT JOIN article_content c ON c.id = a.id
+        WHERE n.score >= $3
+        ORDER BY n.score DESC
+        "#,
+    )
+    .bind(Vector::from(vector))
+    .bind(top_k as i64 * CANDIDATE_FACTOR)
+    .bind(min_score as f64)
+    .fetch_all(pool)
+    .await?;
+
+    let passages = tokio::task::spawn_blocking(move || {
+        let mut passages: Vec<Passage> = Vec::new();
+        for row in rows {
+            let article_id = format!("{}:{}", row.fakeid, row.aid.as_deref().unwrap_or(""));
+            let chunk = match (row.content.as_deref(), row.chunk_index) {
+                (Some(html), Some(i)) => {
+                    let text = crate::content::extract::extract(html).text();
+                    crate::content::chunk::article_chunks(&text)
+                        .into_iter()
+                        .nth(i as usize)
+                }
+                _ => None,
+            };
+            let text = crate::embedding_registry::source_text(
+                &row.source,
+                &row.title,
+                row.digest.as_deref(),
+                chunk.as_deref(),
+            );
+            // A title and a digest that say the same thing add nothing
+            if passages
+                .iter()
+                .any(|p| p.article_id == article_id && p.text == text)
+            {
+                continue;
+            }
+            passages.push(Passage {
+                article_id,
+                title: row.title,
+                link: row.link,
+                text,
+                score: row.score as f32,
+            });
+            if passages.len() >= top_k {
+                break;
+            }
+        }
+        passages
+    })
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    Ok(passages)
+}
+
+/// One citation per article, numbered in order of first appearance
+pub fn citations(passages: &[Passage]) -> Vec<Citation> {
+    let mut citations: Vec<Citation> = Vec::new();
+    for passage in passages {
+        match citations
+            .iter_mut()
+            .find(|c| c.article_id == passage.article_id)
+        {
+            Some(c) => c.score = c.score.max(passage.score),
+            None => citations.push(Citation {
+                index: citations.len() + 1,
+                article_id: passage.article_id.clone(),
+                title: passage.title.clone(),
+                link: passage.link.clone(),
+                score: passage.score,
+                cited: false,
+            }),
+        }
+    }
+    citations
+}
+
+/// Mark the citations the answer refers to as `[n]` (also `[1, 2]`, `[1][2]`)
+pub fn mark_cited(answer: &str, citations: &mut [Citation]) {
+    let mut rest = answer;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+        for part in rest[..close].split([',', '，', ' ']) {
+            if let Ok(n) = part.trim().parse::<usize>() {
+                if let Some(c) = citations.iter_mut().find(|c| c.index == n) {
+                    c.cited = true;
+                }
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+}
+
+/// Instructions and sources for the model
+pub fn system_prompt(passages: &[Passage], citations: &[Citation]) -> String {
+    let mut sources = String::new();
+    for citation in citations {
+        sources.push_str(&format!("[{}] 《{}》\n", citation.index, citation.title));
+        for passage in passages
+            .iter()
+            .filter(|p| p.article_id == citation.article_id)
+        {
+            sources.push_str(&passage.text);
+            sources.push_str("\n\n");
+        }
+    }
+    format!(
+        "你是一个基于公众号文章库回答问题的助手。只根据下面的资料回答，用 [编号] 标注每个论点来自哪篇文章，可以同时引用多篇。\
+         资料中没有答案时直接说明，不要编造。使用提问的语言回答。\n\n## 资料\n\n{}",
+        sources.trim_end()
+    )
+}
+
+/// Recent conversation followed by the new question
+pub fn user_prompt(history: &[HistoryMessage], question: &str) -> String {
+    let recent = &history[history.len().saturating_sub(MAX_HISTORY)..];
+    if recent.is_empty() {
+        return question.to_string();
+    }
+    let turns: Vec<String> = recent
+        .iter()
+        .map(|m| {
+            let role = if m.role == "user" { "用户" } else { "助手" };
+            format!("{}: {}", role, m.content)
+        })
+        .collect();
+    format!("## 对话历史\n{}\n\n## 问题\n{}", turns.join("\n"), question)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn passage(article_id: &str, text: &str, score: f32) -> Passage {
+        Passage {
+            article_id: article_id.to_string(),
+            title: format!("标题{}", article_id),
+            link: None,
+            text: text.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn test_citations() {
+        let passages = vec![
+            passage("a:1", "甲", 0.9),
+            passage("b:2", "乙", 0.8),
+            passage("a:1", "丙", 0.7),
+        ];
+        let mut cites = citations(&passages);
+        assert_eq!(cites.len(), 2);
+        assert_eq!((cites[1].index, cites[1].article_id.as_str()), (2, "b:2"));
+
+        let prompt = system_prompt(&passages, &cites);
+        assert!(prompt.contains("[1] 《标题a:1》\n甲\n\n丙"));
+
+        mark_cited("结论如此[2]。另见 [1，3]", &mut cites);
+        assert!(cites.iter().all(|c| c.cited));
+
+        let history = vec![HistoryMessage {
+            role: "assistant".to_string(),
+            content: "你好".to_string(),
+        }];
+        assert_eq!(user_prompt(&[], "问题"), "问题");
+        assert!(user_prompt(&history, "问题").starts_with("## 对话历史\n助手: 你好"));
+    }
+}
@@ -0,0 +1,257 @@
+//! Per-session endpoint budgets
+//!
+//! WeChat freezes accounts that hit `searchbiz` / `appmsgpublish` too fast.
+//! Each (session, endpoint) pair gets a token bucket; when a response reports
+//! frequency control (`base_resp.ret` 200013) the bucket stops handing out
+//! tokens for an exponentially growing backoff window.
+//!
+//! This complements the crawl coordinator: the coordinator spaces individual
+//! requests, the budget caps how many a session spends per minute.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+lazy_static! {
+    static ref BUCKETS: Mutex<HashMap<(String, Endpoint), Bucket>> = Mutex::new(HashMap::new());
+}
+
+/// `base_resp.ret` values that mean "too many requests"
+pub const FREQ_CONTROL_RETS: [i64; 1] = [200013];
+/// Longest an interactive handler waits for budget before answering "too frequent"
+pub const INTERACTIVE_MAX_WAIT: Duration = Duration::from_secs(15);
+/// Longest backoff after repeated frequency control
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+
+/// Rate-controlled MP endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endpoint {
+    SearchBiz,
+    AppMsgPublish,
+}
+
+impl Endpoint {
+    /// Requests per minute (also the burst size)
+    fn per_minute(self) -> f64 {
+        let (key, default) = match self {
+            Endpoint::SearchBiz => ("RATELIMIT_SEARCHBIZ_PER_MIN", 6),
+            Endpoint::AppMsgPublish => ("RATELIMIT_APPMSGPUBLISH_PER_MIN", 12),
+        };
+        std::env::var(key)
+            .ok()
+            .and_then(|s| s.parse::<u32>().ok())
+            .unwrap_or(default)
+            .max(1) as f64
+    }
+}
+
+fn backoff_base() -> Duration {
+    Duration::from_secs(
+        std::env::var("RATELIMIT_BACKOFF_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(60),
+    )
+}
+
+/// Backoff for the n-th consecutive frequency control (n >= 1)
+fn backoff_for(level: u32, base: Duration) -> Duration {
+    base.saturating_mul(1u32 << (level.clamp(1, 16) - 1))
+        .min(MAX_BACKOFF)
+}
+
+pub fn is_freq_control(ret: i64) -> bool {
+    FREQ_CONTROL_RETS.contains(&ret)
+}
+
+struct Bucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+    backoff_level: u32,
+    backoff_until: Option<Instant>,
+    freq_control_hits: u64,
+}
+
+impl Bucket {
+    fn new(endpoint: Endpoint) -> Self {
+        let capacity = endpoint.per_minute();
+        Bucket {
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+            backoff_level: 0,
+            backoff_until: None,
+            freq_control_hits: 0,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Take a token, or report how long until one is available
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.backoff_until {
+            if until > now {
+                return Err(until - now);
+            }
+            self.backoff_until = None;
+        }
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) * 60.0 / self.capacity,
+            ))
+        }
+    }
+}
+
+/// Wait for budget on `endpoint` for the session `lane` (see `crawl::session_lane`).
+/// Returns `Err(retry_after)` instead of waiting when the wait would exceed `max_wait`.
+pub async fn acquire(lane: &str, endpoint: Endpoint, max_wait: Duration) -> Result<(), Duration> {
+    let mut waited = Duration::ZERO;
+    loop {
+        let wait = {
+            let mut buckets = BUCKETS.lock().unwrap();
+            let bucket = buckets
+                .entry((lane.to_string(), endpoint))
+                .or_insert_with(|| Bucket::new(endpoint));
+            match bucket.try_take(Instant::now()) {
+                Ok(()) => return Ok(()),
+                Err(wait) => wait,
+            }
+        };
+        if waited.saturating_add(wait) > max_wait {
+            return Err(wait);
+        }
+        tracing::debug!("[RateLimit] {} {:?}: waiting {:?}", lane, endpoint, wait);
+        tokio::time::sleep(wait).await;
+        waited += wait;
+    }
+}
+
+/// Feed back a response's `base_resp.ret`; frequency control triggers backoff
+pub fn report(lane: &str, endpoint: Endpoint, ret: Option<i64>) {
+    let Some(ret) = ret else { return };
+    let mut buckets = BUCKETS.lock().unwrap();
+    let bucket = buckets
+        .entry((lane.to_string(), endpoint))
+        .or_insert_with(|| Bucket::new(endpoint));
+
+    if is_freq_control(ret) {
+        bucket.backoff_level += 1;
+        bucket.freq_control_hits += 1;
+        bucket.tokens = 0.0;
+        let backoff = backoff_for(bucket.backoff_level, backoff_base());
+        bucket.backoff_until = Some(Instant::now() + backoff);
+        tracing::warn!(
+            "[RateLimit] {} {:?}: frequency control (ret={}), backing off {:?}",
+            lane,
+            endpoint,
+            ret,
+            backoff
+        );
+    } else if ret == 0 {
+        bucket.backoff_level = 0;
+    }
+}
+
+/// Extract `base_resp.ret` from an MP JSON response
+pub fn base_ret(json: &serde_json::Value) -> Option<i64> {
+    json.get("base_resp")
+        .and_then(|r| r.get("ret"))
+        .and_then(|v| v.as_i64())
+}
+
+/// MP-style error body for handlers that refuse to wait
+pub fn over_budget_response(retry_after: Duration) -> serde_json::Value {
+    serde_json::json!({
+        "base_resp": {
+            "ret": FREQ_CONTROL_RETS[0],
+            "err_msg": format!("请求过于频繁，请{}秒后重试", retry_after.as_secs().max(1))
+        },
+        "retry_after": retry_after.as_secs().max(1)
+    })
+}
+
+/// Current budget of one (session, endpoint) pair
+#[derive(Debug, Serialize)]
+pub struct BudgetStatus {
+    pub lane: String,
+    pub endpoint: Endpoint,
+    pub tokens: f64,
+    /// Requests per minute, also the burst size
+    pub capacity: f64,
+    pub backoff_level: u32,
+    /// Seconds until requests are allowed again (0 when not backing off)
+    pub backoff_remaining_secs: u64,
+    pub freq_control_hits: u64,
+}
+
+pub fn status() -> Vec<BudgetStatus> {
+    let now = Instant::now();
+    let mut buckets = BUCKETS.lock().unwrap();
+    let mut statuses: Vec<BudgetStatus> = buckets
+        .iter_mut()
+        .map(|((lane, endpoint), bucket)| {
+            bucket.refill(now);
+            BudgetStatus {
+                lane: lane.clone(),
+                endpoint: *endpoint,
+                tokens: (bucket.tokens * 100.0).floor() / 100.0,
+                capacity: bucket.capacity,
+                backoff_level: bucket.backoff_level,
+                backoff_remaining_secs: bucket
+                    .backoff_until
+                    .map(|until| until.saturating_duration_since(now).as_secs())
+                    .unwrap_or(0),
+                freq_control_hits: bucket.freq_control_hits,
+            }
+        })
+        .collect();
+    statuses.sort_by(|a, b| {
+        a.lane
+            .cmp(&b.lane)
+            .then_with(|| a.endpoint.cmp(&b.endpoint))
+    });
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_growth() {
+        let base = Duration::from_secs(60);
+        assert_eq!(backoff_for(1, base), Duration::from_secs(60));
+        assert_eq!(backoff_for(3, base), Duration::from_secs(240));
+        assert_eq!(backoff_for(40, base), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_bucket_take_and_backoff() {
+        let mut bucket = Bucket::new(Endpoint::SearchBiz);
+        let now = Instant::now();
+        for _ in 0..bucket.capacity as usize {
+            assert!(bucket.try_take(now).is_ok());
+        }
+        assert!(bucket.try_take(now).is_err());
+
+        // Tokens have refilled after a minute, but backoff still blocks
+        bucket.backoff_until = Some(now + Duration::from_secs(90));
+        let wait = bucket.try_take(now + Duration::from_secs(60)).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(30));
+        assert!(bucket.try_take(now + Duration::from_secs(90)).is_ok());
+    }
+}
@@ -0,0 +1,397 @@
+//! Stored article HTML
+//!
+//! `article_content` is the one content store. Rows are keyed by the
+//! `articles` id (`fakeid:aid`) when the article is known, otherwise by the
+//! md5 of its URL, and always record the URL they were fetched from, so a
+//! lookup by either finds the same copy. Content is stored raw, as fetched;
+//! readers that display it run their own cleanup.
+//!
+//! Every fetched copy whose readable text differs from the previous one is
+//! also kept in `article_versions`, so edits made after publication can be
+//! listed and diffed. Copies stored before versioning become the first
+//! version the next time the article is captured.
+//!
+//! Saving a copy also records the page metadata (author, 原创 flag, account
+//! biz) on the matching `articles` and `insight_articles` rows.
+
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::content::extract::{self, PageMetadata};
+
+/// A stored copy of an article page
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredArticle {
+    pub id: String,
+    pub content: String,
+}
+
+/// A captured version of an article, without its content
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Version {
+    pub id: i64,
+    pub url: Option<String>,
+    pub content_hash: String,
+    pub captured_at: i64,
+    pub source: String,
+    /// Bytes of stored HTML
+    pub size: i32,
+}
+
+/// Which copies `find` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// Only copies whose TTL has not passed
+    Live,
+    /// Expired copies too (snapshots, comparisons)
+    Any,
+}
+
+/// Current unix timestamp, used for `expires_at` comparisons
+pub fn now_ts() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Raw and URL-decoded variants of a URL (rows may hold either)
+pub fn url_variants(url: &str) -> Vec<String> {
+    let mut variants = vec![url.to_string()];
+    if let Ok(decoded) = urlencoding::decode(url) {
+        if decoded != url {
+            variants.push(decoded.to_string());
+        }
+    }
+    variants
+}
+
+/// md5 of a URL, the key of articles not in `articles`
+pub fn url_hash(url: &str) -> String {
+    format!("{:x}", md5::compute(url.as_bytes()))
+}
+
+/// md5 of the readable text of a page, so markup and script churn between
+/// fetches does not count as a new version
+pub fn content_hash(html: &str) -> String {
+    format!(
+        "{:x}",
+        md5::compute(crate::archive::article_text(html).as_bytes())
+    )
+}
+
+/// Find the stored copy of an article by id and/or URL, preferring an id match
+pub async fn find(
+    pool: &PgPool,
+    id: Option<&str>,
+    url: Option<&str>,
+    freshness: Freshness,
+) -> Result<Option<StoredArticle>, sqlx::Error> {
+    if id.is_none() && url.is_none() {
+        return Ok(None);
+    }
+    let variants = url.map(url_variants).unwrap_or_default();
+    sqlx::query_as::<_, StoredArticle>(
+        r#"
+        SELECT id, content
+        FROM article_content
+        WHERE (id = $1 OR original_url = ANY($2))
+          AND ($3 OR expires_at IS NULL OR expires_at > $4)
+        ORDER BY (id = $1) DESC, create_time DESC NULLS LAST
+        LIMIT 1
+        "#,
+    )
+    .bind(id.unwrap_or_default())
+    .bind(&variants)
+    .bind(freshness == Freshness::Any)
+    .bind(now_ts())
+    .fetch_optional(pool)
+    .await
+}
+
+/// Content of the live stored copy, if any
+pub async fn content(
+    pool: &PgPool,
+    id: Option<&str>,
+    url: Option<&str>,
+) -> Result<Option<String>, sqlx::Error> {
+    Ok(find(pool, id, url, Freshness::Live)
+        .await?
+        .map(|article| article.content))
+}
+
+/// Key under which an article URL is stored
+pub async fn resolve_id(pool: &PgPool, url: &str) -> Result<String, sqlx::Error> {
+    let variants = url_variants(url);
+    let id: Option<String> =
+        sqlx::query_scalar("SELECT id FROM articles WHERE link = ANY($1) LIMIT 1")
+            .bind(&variants)
+            .fetch_optional(pool)
+            .await?;
+    Ok(id.unwrap_or_else(|| url_hash(url)))
+}
+
+/// Store (or replace) the raw HTML of an article. Returns the id it is stored under.
+pub async fn save(
+    pool: &PgPool,
+    id: Option<&str>,
+    url: &str,
+    content: &str,
+    expires_at: Option<i64>,
+) -> Result<String, sqlx::Error> {
+    let id = match id {
+        Some(id) => id.to_string(),
+        None => resolve_id(pool, url).await?,
+    };
+    // Before the upsert, so the copy it replaces is kept as a version
+    capture_version(pool, &id, url, content, "fetch").await?;
+    sqlx::query(
+        r#"
+        INSERT INTO article_content (id, content, original_url, create_time, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (id) DO UPDATE SET
+            content = EXCLUDED.content,
+            original_url = EXCLUDED.original_url,
+            create_time = EXCLUDED.create_time,
+            expires_at = EXCLUDED.expires_at
+        "#,
+    )
+    .bind(&id)
+    .bind(content)
+    .bind(url)
+    .bind(now_ts())
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+    save_metadata(pool, &id, url, &extract::metadata(content, url)).await?;
+    Ok(id)
+}
+
+/// Record the metadata of a fetched page on the `articles` row stored under
+/// `id` and on every insight article with its URL. Values the page does not
+/// carry keep what was recorded before.
+pub async fn save_metadata(
+    pool: &PgPool,
+    id: &str,
+    url: &str,
+    meta: &PageMetadata,
+) -> Result<(), sqlx::Error> {
+    if meta.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(
+        r#"
+        UPDATE articles SET
+            author = COALESCE($2, author),
+            is_original = COALESCE($3, is_original),
+            biz = COALESCE($4, biz)
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(&meta.author)
+    .bind(meta.original)
+    .bind(&meta.biz)
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE insight_articles SET
+            author = COALESCE($2, author),
+            is_original = COALESCE($3, is_original),
+            biz = COALESCE($4, biz),
+            publish_time = COALESCE(NULLIF(publish_time, 0), $5)
+        WHERE url = ANY($1)
+        "#,
+    )
+    .bind(url_variants(url))
+    .bind(&meta.author)
+    .bind(meta.original)
+    .bind(&meta.biz)
+    .bind(meta.publish_time)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Remove the stored copies of an article id and/or URL. Returns removed row count.
+pub async fn remove(
+    pool: &PgPool,
+    id: Option<&str>,
+    url: Option<&str>,
+) -> Result<u64, sqlx::Error> {
+    let variants = url.map(url_variants).unwrap_or_default();
+    Ok(
+        sqlx::query("DELETE FROM article_content WHERE id = $1 OR original_url = ANY($2)")
+            .bind(id.unwrap_or_default())
+            .bind(&variants)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Set (or with `None`, clear) the expiry of the stored copies of an article.
+/// Returns updated row count.
+pub async fn set_expiry(
+    pool: &PgPool,
+    id: Option<&str>,
+    url: Option<&str>,
+    expires_at: Option<i64>,
+) -> Result<u64, sqlx::Error> {
+    let variants = url.map(url_variants).unwrap_or_default();
+    Ok(sqlx::query(
+        "UPDATE article_content SET expires_at = $3 WHERE id = $1 OR original_url = ANY($2)",
+    )
+    .bind(id.unwrap_or_default())
+    .bind(&variants)
+    .bind(expires_at)
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+/// Remove copies past their TTL and, with `cutoff`, copies stored before it.
+/// Returns removed row count.
+pub async fn evict(pool: &PgPool, cutoff: Option<i64>) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query(
+        "DELETE FROM article_content WHERE (expires_at IS NOT NULL AND expires_at <= $1) OR create_time < $2",
+    )
+    .bind(now_ts())
+    .bind(cutoff)
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+/// Remove every stored copy. Returns removed row count.
+pub async fn clear(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query("DELETE FROM article_content")
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+async fn insert_version(
+    pool: &PgPool,
+    id: &str,
+    url: Option<&str>,
+    content: &str,
+    hash: &str,
+    captured_at: i64,
+    source: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO article_versions (article_id, url, content_hash, content, captured_at, source) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(id)
+    .bind(url)
+    .bind(hash)
+    .bind(content)
+    .bind(captured_at)
+    .bind(source)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Record `content` as a new version of article `id` unless its text matches
+/// the latest version. Returns whether a version was added.
+pub async fn capture_version(
+    pool: &PgPool,
+    id: &str,
+    url: &str,
+    content: &str,
+    source: &str,
+) -> Result<bool, sqlx::Error> {
+    let hash = content_hash(content);
+    let mut latest: Option<String> = sqlx::query_scalar(
+        "SELECT content_hash FROM article_versions WHERE article_id = $1 ORDER BY captured_at DESC, id DESC LIMIT 1",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    // First capture: the copy stored before versioning is the baseline
+    if latest.is_none() {
+        let stored: Option<(String, Option<String>, Option<i64>)> = sqlx::query_as(
+            "SELECT content, original_url, create_time FROM article_content WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+        if let Some((stored, stored_url, create_time)) = stored {
+            let stored_hash = content_hash(&stored);
+            insert_version(
+                pool,
+                id,
+                stored_url.as_deref(),
+                &stored,
+                &stored_hash,
+                create_time.unwrap_or_else(now_ts),
+                "stored",
+            )
+            .await?;
+            latest = Some(stored_hash);
+        }
+    }
+
+    if latest.as_deref() == Some(hash.as_str()) {
+        return Ok(false);
+    }
+    insert_version(pool, id, Some(url), content, &hash, now_ts(), source).await?;
+    Ok(true)
+}
+
+/// Versions of an article, oldest first
+pub async fn versions(pool: &PgPool, id: &str) -> Result<Vec<Version>, sqlx::Error> {
+    sqlx::query_as::<_, Version>(
+        r#"
+        SELECT id, url, content_hash, captured_at, source, octet_length(content) AS size
+        FROM article_versions WHERE article_id = $1
+        ORDER BY captured_at, id
+        "#,
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await
+}
+
+/// HTML of one version of an article
+pub async fn version_content(
+    pool: &PgPool,
+    id: &str,
+    version: i64,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT content FROM article_versions WHERE article_id = $1 AND id = $2")
+        .bind(id)
+        .bind(version)
+        .fetch_optional(pool)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_variants() {
+        let url = "https://mp.weixin.qq.com/s?__biz=MzA%3D&mid=1";
+        assert_eq!(
+            url_variants(url),
+            vec![
+                url.to_string(),
+                "https://mp.weixin.qq.com/s?__biz=MzA=&mid=1".to_string()
+            ]
+        );
+        assert_eq!(url_variants("https://a/b"), vec!["https://a/b".to_string()]);
+        assert_eq!(url_hash("abc"), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn test_content_hash_ignores_markup() {
+        let a =
+            r#"<div id="js_content"><p>第一段</p><p>第二段</p></div><script>var t = 1;</script>"#;
+        let b = r#"<div id="js_content" class="x"><p style="color:red">第一段</p><p>第二段</p></div><script>var t = 2;</script>"#;
+        let edited = r#"<div id="js_content"><p>第一段</p><p>改过的第二段</p></div>"#;
+        assert_eq!(content_hash(a), content_hash(b));
+        assert_ne!(content_hash(a), content_hash(edited));
+    }
+}
@@ -0,0 +1,6 @@
+//! Storage access shared by handlers and background jobs
+//!
+//! Keeps the SQL for a table family in one place so read and write paths
+//! agree on keys, expiry and what is stored.
+
+pub mod articles;
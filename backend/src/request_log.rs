@@ -0,0 +1,346 @@
+//! Audit log of outbound MP backend requests
+//!
+//! Every `cgi-bin` call made with a session (through `MpClient` or the
+//! public proxy handlers) is recorded in `request_log` with its endpoint,
+//! target fakeid or keyword, HTTP status, `base_resp.ret`, latency and the
+//! session's crawl lane, so an account freeze can be matched against exactly
+//! what was sent and when. Entries are queued and written in batches off the
+//! request path; rows older than `REQUEST_LOG_RETENTION_DAYS` (default 14,
+//! 0 keeps everything) are pruned hourly.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+
+const DEFAULT_RETENTION_DAYS: i64 = 14;
+const BATCH_SIZE: usize = 200;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+static SENDER: OnceLock<mpsc::UnboundedSender<Entry>> = OnceLock::new();
+
+/// One outbound request
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub requested_at: i64,
+    pub endpoint: String,
+    pub source: &'static str,
+    pub session: String,
+    pub fakeid: Option<String>,
+    pub keyword: Option<String>,
+    pub http_status: Option<u16>,
+    pub ret: Option<i64>,
+    pub latency_ms: u64,
+    pub error: Option<String>,
+    started: Instant,
+}
+
+impl Entry {
+    /// Entry for a request to `endpoint` about to be sent; `query` supplies
+    /// the fakeid and search keyword when present
+    pub fn start<K: AsRef<str>, V: AsRef<str>>(
+        endpoint: &str,
+        source: &'static str,
+        session: &str,
+        query: &[(K, V)],
+    ) -> Self {
+        let param = |name: &str| {
+            query
+                .iter()
+                .find(|(k, v)| k.as_ref() == name && !v.as_ref().is_empty())
+                .map(|(_, v)| v.as_ref().to_string())
+        };
+        Entry {
+            requested_at: chrono::Utc::now().timestamp_millis(),
+            endpoint: endpoint
+                .trim_start_matches("https://mp.weixin.qq.com")
+                .to_string(),
+            source,
+            session: session.to_string(),
+            fakeid: param("fakeid"),
+            keyword: param("query"),
+            http_status: None,
+            ret: None,
+            latency_ms: 0,
+            error: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Fill in the outcome and queue the entry
+    pub fn finish(mut self, http_status: Option<u16>, ret: Option<i64>, error: Option<String>) {
+        self.latency_ms = self.started.elapsed().as_millis() as u64;
+        self.http_status = http_status;
+        self.ret = ret;
+        self.error = error;
+        record(self);
+    }
+}
+
+/// Queue an entry; dropped when the writer is not running
+pub fn record(entry: Entry) {
+    if let Some(sender) = SENDER.get() {
+        let _ = sender.send(entry);
+    }
+}
+
+fn retention_days() -> i64 {
+    std::env::var("REQUEST_LOG_RETENTION_DAYS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS)
+}
+
+/// Start the batch writer and the hourly pruning
+pub fn spawn(pool: PgPool) {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Entry>();
+    if SENDER.set(sender).is_err() {
+        return;
+    }
+
+    let writer_pool = pool.clone();
+    tokio::spawn(async move {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        loop {
+            let flush = tokio::time::sleep(FLUSH_INTERVAL);
+            tokio::pin!(flush);
+            while batch.len() < BATCH_SIZE {
+                tokio::select! {
+                    entry = receiver.recv() => match entry {
+                        Some(entry) => batch.push(entry),
+                        None => break,
+                    },
+                    _ = &mut flush => break,
+                }
+            }
+            if !batch.is_empty() {
+                if let Err(e) = insert_batch(&writer_pool, &batch).await {
+                    tracing::warn!(
+                        "[RequestLog] Failed to write {} entries: {}",
+                        batch.len(),
+                        e
+                    );
+                }
+                batch.clear();
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match prune(&pool).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("[RequestLog] Pruned {} entries", n),
+                Err(e) => tracing::warn!("[RequestLog] Pruning failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn insert_batch(pool: &PgPool, batch: &[Entry]) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO request_log
+            (requested_at, endpoint, source, session, fakeid, keyword, http_status, ret, latency_ms, error)
+        SELECT * FROM UNNEST(
+            $1::bigint[], $2::text[], $3::text[], $4::text[], $5::text[],
+            $6::text[], $7::int[], $8::bigint[], $9::int[], $10::text[]
+        )
+        "#,
+    )
+    .bind(batch.iter().map(|e| e.requested_at).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.endpoint.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.source).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.session.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.fakeid.clone()).collect::<Vec<_>>())
+    .bind(batch.iter().map(|e| e.keyword.clone()).collect::<Vec<_>>())
+    .bind(
+        batch
+            .iter()
+            .map(|e| e.http_status.map(i32::from))
+            .collect::<Vec<_>>(),
+    )
+    .bind(batch.iter().map(|e| e.ret).collect::<Vec<_>>())
+    .bind(
+        batch
+            .iter()
+            .map(|e| e.latency_ms.min(i32::MAX as u64) as i32)
+            .collect::<Vec<_>>(),
+    )
+    .bind(batch.iter().map(|e| e.error.clone()).collect::<Vec<_>>())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Delete entries past the retention period
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let days = retention_days();
+    if days <= 0 {
+        return Ok(0);
+    }
+    let cutoff = chrono::Utc::now().timestamp_millis() - days * 24 * 3600 * 1000;
+    let result = sqlx::query("DELETE FROM request_log WHERE requested_at < $1")
+        .bind(cutoff)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// Filters shared by the list and stats endpoints; times are Unix milliseconds
+#[derive(Debug, Default, Deserialize)]
+pub struct LogFilter {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub endpoint: Option<String>,
+    pub session: Option<String>,
+    pub fakeid: Option<String>,
+    pub keyword: Option<String>,
+    /// Only entries with this `base_resp.ret`
+    pub ret: Option<i64>,
+    /// Only entries that failed (non-zero ret, HTTP error or transport error)
+    #[serde(default)]
+    pub errors_only: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    /// Stats bucket width in seconds (default 3600, at least 60)
+    pub bucket_secs: Option<i64>,
+}
+
+const FILTER_SQL: &str = r#"
+    ($1::bigint IS NULL OR requested_at >= $1)
+    AND ($2::bigint IS NULL OR requested_at < $2)
+    AND ($3::text IS NULL OR endpoint = $3)
+    AND ($4::text IS NULL OR session = $4)
+    AND ($5::text IS NULL OR fakeid = $5)
+    AND ($6::text IS NULL OR keyword = $6)
+    AND ($7::bigint IS NULL OR ret = $7)
+    AND (NOT $8 OR error IS NOT NULL OR ret <> 0 OR http_status >= 400)
+"#;
+
+macro_rules! bind_filter {
+    ($query:expr, $filter:expr) => {
+        $query
+            .bind($filter.since)
+            .bind($filter.until)
+            .bind(&$filter.endpoint)
+            .bind(&$filter.session)
+            .bind(&$filter.fakeid)
+            .bind(&$filter.keyword)
+            .bind($filter.ret)
+            .bind($filter.errors_only)
+    };
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LogRow {
+    pub id: i64,
+    pub requested_at: i64,
+    pub endpoint: String,
+    pub source: String,
+    pub session: String,
+    pub fakeid: Option<String>,
+    pub keyword: Option<String>,
+    pub http_status: Option<i32>,
+    pub ret: Option<i64>,
+    pub latency_ms: i32,
+    pub error: Option<String>,
+}
+
+/// Matching entries, newest first, and their total count
+pub async fn list(pool: &PgPool, filter: &LogFilter) -> Result<(Vec<LogRow>, i64), sqlx::Error> {
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = filter.offset.unwrap_or(0).max(0);
+    let rows = bind_filter!(
+        sqlx::query_as::<_, LogRow>(&format!(
+            "SELECT * FROM request_log WHERE {} ORDER BY requested_at DESC, id DESC LIMIT $9 OFFSET $10",
+            FILTER_SQL
+        )),
+        filter
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    let total: i64 = bind_filter!(
+        sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM request_log WHERE {}",
+            FILTER_SQL
+        )),
+        filter
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok((rows, total))
+}
+
+/// Request counts per time bucket, session and endpoint
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct StatsRow {
+    /// Bucket start, Unix milliseconds
+    pub bucket: i64,
+    pub session: String,
+    pub endpoint: String,
+    pub requests: i64,
+    pub errors: i64,
+    /// Frequency-control answers (see `ratelimit::is_freq_control`)
+    pub freq_control: i64,
+    pub avg_latency_ms: f64,
+}
+
+/// Aggregate matching entries into `filter.bucket_secs` buckets; returns the
+/// bucket width used
+pub async fn stats(pool: &PgPool, filter: &LogFilter) -> Result<(i64, Vec<StatsRow>), sqlx::Error> {
+    let bucket_secs = filter.bucket_secs.unwrap_or(3600).max(60);
+    let rows = bind_filter!(
+        sqlx::query_as::<_, StatsRow>(&format!(
+            r#"
+            SELECT requested_at / $9 * $9 AS bucket, session, endpoint,
+                   COUNT(*) AS requests,
+                   COUNT(*) FILTER (WHERE error IS NOT NULL OR ret <> 0 OR http_status >= 400) AS errors,
+                   COUNT(*) FILTER (WHERE ret = ANY($10)) AS freq_control,
+                   AVG(latency_ms)::float8 AS avg_latency_ms
+            FROM request_log WHERE {}
+            GROUP BY 1, 2, 3
+            ORDER BY 1 DESC, 2, 3
+            LIMIT 5000
+            "#,
+            FILTER_SQL
+        )),
+        filter
+    )
+    .bind(bucket_secs * 1000)
+    .bind(crate::ratelimit::FREQ_CONTROL_RETS.to_vec())
+    .fetch_all(pool)
+    .await?;
+    Ok((bucket_secs, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_start() {
+        let entry = Entry::start(
+            "https://mp.weixin.qq.com/cgi-bin/appmsgpublish",
+            "test",
+            "session:abcd1234",
+            &[("fakeid", "MzA5"), ("query", ""), ("begin", "0")],
+        );
+        assert_eq!(entry.endpoint, "/cgi-bin/appmsgpublish");
+        assert_eq!(entry.fakeid.as_deref(), Some("MzA5"));
+        assert_eq!(entry.keyword, None);
+
+        let entry = Entry::start("/cgi-bin/searchbiz", "test", "s", &[("query", "AI")]);
+        assert_eq!(entry.endpoint, "/cgi-bin/searchbiz");
+        assert_eq!(entry.keyword.as_deref(), Some("AI"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Graceful shutdown
+//!
+//! On SIGTERM or Ctrl-C the server stops accepting connections and lets
+//! in-flight requests finish. Insight workers stop at their next checkpoint
+//! (after a keyword or an account) and are left `failed` with a resumable
+//! reason; export jobs stop between articles. Open connections and then
+//! running jobs are each given up to `SHUTDOWN_TIMEOUT_SECS` (default 30).
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::AppState;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Reason recorded on insight tasks stopped by a shutdown
+pub const INTERRUPTED_REASON: &str = "Interrupted by server shutdown (resumable)";
+
+/// Returned by `check` once a shutdown has been requested
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(INTERRUPTED_REASON)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Err at a checkpoint where a worker should stop for shutdown
+pub fn check() -> Result<(), Interrupted> {
+    if requested() {
+        Err(Interrupted)
+    } else {
+        Ok(())
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C and marks the shutdown as requested
+pub async fn signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    REQUESTED.store(true, Ordering::Relaxed);
+    crate::api::export::cancel_all();
+    tracing::info!("Shutdown requested, waiting for in-flight work");
+}
+
+/// How long to wait for connections and jobs after the signal
+pub fn timeout() -> Duration {
+    Duration::from_secs(
+        std::env::var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30),
+    )
+}
+
+/// Wait until no insight task or export job is running, at most `timeout`
+pub async fn wait_for_jobs(state: &AppState, timeout: Duration) {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let tasks = state.task_queue.snapshot().running.len();
+        let exports = crate::api::export::running_count();
+        if tasks == 0 && exports == 0 {
+            return;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            tracing::warn!(
+                "Shutting down with {} task(s) and {} export(s) still running",
+                tasks,
+                exports
+            );
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
@@ -0,0 +1,892 @@
+//! Asset storage
+//!
+//! Image bytes used to live only in `assets.data`, which bloats the database.
+//! `ASSET_STORAGE` selects where new blobs go:
+//!
+//! - `postgres` (default): `assets.data`
+//! - `fs`: files under `ASSET_DIR` (default `data/assets`)
+//! - `s3`: an S3-compatible bucket (`S3_ENDPOINT`, `S3_BUCKET`, `S3_REGION`,
+//!   `S3_ACCESS_KEY_ID`, `S3_SECRET_ACCESS_KEY`, optional `S3_PREFIX`)
+//!
+//! The `assets` row always keeps the metadata and records which backend holds
+//! the blob, so rows written before a switch stay readable as long as their
+//! backend is still configured or the data is inline. `--migrate-assets` moves
+//! inline blobs to the configured backend.
+//!
+//! Blobs are deduplicated by content: `assets.sha256` is unique, and a URL
+//! whose bytes are already stored under another URL only gets a row in
+//! `asset_aliases`. Reads resolve aliases, so callers keep addressing assets
+//! by their own URL. `--dedup-assets` hashes assets saved before this.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+
+static STORE: OnceLock<Box<dyn AssetStore>> = OnceLock::new();
+
+/// Asset bytes streamed from a backend
+pub type ByteStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+/// Blobs migrated (or hashed) per batch by `migrate_to_store` and `dedup_existing`
+const MIGRATE_BATCH: i64 = 100;
+
+/// Where asset bytes live, keyed by the asset URL
+pub trait AssetStore: Send + Sync {
+    /// Value of `assets.storage` for blobs in this store
+    fn name(&self) -> &'static str;
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>>;
+    fn put<'a>(&'a self, url: &'a str, data: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>>;
+    /// Stream the blob, or the inclusive byte `range` of it
+    fn open<'a>(
+        &'a self,
+        url: &'a str,
+        range: Option<(u64, u64)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<ByteStream>>>;
+    /// Remove the blob; a missing blob is not an error
+    fn delete<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+fn once(data: Vec<u8>) -> ByteStream {
+    futures::stream::once(async move { Ok(Bytes::from(data)) }).boxed()
+}
+
+/// Object key of an asset: md5 of the URL, sharded by its first two hex digits
+fn object_key(url: &str) -> String {
+    let hash = format!("{:x}", md5::compute(url.as_bytes()));
+    format!("{}/{}", &hash[..2], hash)
+}
+
+// ============ Postgres ============
+
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl AssetStore for PostgresStore {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let data: Option<Option<Vec<u8>>> =
+                sqlx::query_scalar("SELECT data FROM assets WHERE url = $1")
+                    .bind(url)
+                    .fetch_optional(&self.pool)
+                    .await?;
+            Ok(data.flatten())
+        })
+    }
+
+    fn put<'a>(&'a self, url: &'a str, data: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO assets (url, data) VALUES ($1, $2) ON CONFLICT (url) DO UPDATE SET data = EXCLUDED.data",
+            )
+            .bind(url)
+            .bind(data)
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn open<'a>(
+        &'a self,
+        url: &'a str,
+        range: Option<(u64, u64)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<ByteStream>>> {
+        Box::pin(async move {
+            // Only the requested slice leaves the database
+            let (from, count) = match range {
+                Some((start, end)) => (start as i64 + 1, (end - start + 1) as i64),
+                None => (1, i32::MAX as i64),
+            };
+            let data: Option<Option<Vec<u8>>> = sqlx::query_scalar(
+                "SELECT substring(data FROM $2::int FOR $3::int) FROM assets WHERE url = $1",
+            )
+            .bind(url)
+            .bind(from)
+            .bind(count)
+            .fetch_optional(&self.pool)
+            .await?;
+            Ok(data.flatten().map(once))
+        })
+    }
+
+    fn delete<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            sqlx::query("UPDATE assets SET data = NULL WHERE url = $1")
+                .bind(url)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+// ============ Local disk ============
+
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    fn path(&self, url: &str) -> PathBuf {
+        self.root.join(object_key(url))
+    }
+}
+
+impl AssetStore for FsStore {
+    fn name(&self) -> &'static str {
+        "fs"
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(url)).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn put<'a>(&'a self, url: &'a str, data: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let path = self.path(url);
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            // Write then rename so readers never see a partial file
+            let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+            tokio::fs::write(&tmp, data).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            Ok(())
+        })
+    }
+
+    fn open<'a>(
+        &'a self,
+        url: &'a str,
+        range: Option<(u64, u64)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<ByteStream>>> {
+        Box::pin(async move {
+            use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+            let mut file = match tokio::fs::File::open(self.path(url)).await {
+                Ok(f) => f,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let stream = match range {
+                Some((start, end)) => {
+                    file.seek(std::io::SeekFrom::Start(start)).await?;
+                    tokio_util::io::ReaderStream::new(file.take(end - start + 1)).boxed()
+                }
+                None => tokio_util::io::ReaderStream::new(file).boxed(),
+            };
+            Ok(Some(stream))
+        })
+    }
+
+    fn delete<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(url)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+// ============ S3-compatible ============
+
+pub struct S3Store {
+    client: reqwest::Client,
+    /// e.g. `https://s3.us-east-1.amazonaws.com` or a MinIO URL; path-style addressing
+    endpoint: reqwest::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    prefix: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// SigV4 signing key for a day (`YYYYMMDD`)
+fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac(format!("AWS4{}", secret).as_bytes(), date);
+    let k_region = hmac(&k_date, region);
+    let k_service = hmac(&k_region, service);
+    hmac(&k_service, "aws4_request")
+}
+
+/// RFC 3986 encoding of a path segment, as SigV4 expects
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl S3Store {
+    fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| anyhow::anyhow!("ASSET_STORAGE=s3 requires {}", name))
+        };
+        Ok(S3Store {
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(60))
+                .build()?,
+            endpoint: reqwest::Url::parse(&var("S3_ENDPOINT")?)?,
+            bucket: var("S3_BUCKET")?,
+            region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+            access_key: var("S3_ACCESS_KEY_ID")?,
+            secret_key: var("S3_SECRET_ACCESS_KEY")?,
+            prefix: std::env::var("S3_PREFIX")
+                .unwrap_or_default()
+                .trim_matches('/')
+                .to_string(),
+        })
+    }
+
+    /// Canonical (encoded) path of an asset's object
+    fn object_path(&self, url: &str) -> String {
+        let key = if self.prefix.is_empty() {
+            object_key(url)
+        } else {
+            format!("{}/{}", self.prefix, object_key(url))
+        };
+        let base = self.endpoint.path().trim_end_matches('/');
+        std::iter::once(self.bucket.as_str())
+            .chain(key.split('/'))
+            .map(uri_encode)
+            .fold(base.to_string(), |path, seg| format!("{}/{}", path, seg))
+    }
+
+    /// Signed request for an object; `body` is empty for GET/DELETE
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: &[u8],
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let path = self.object_path(url);
+        let host = match (self.endpoint.host_str(), self.endpoint.port()) {
+            (Some(h), Some(p)) => format!("{}:{}", h, p),
+            (Some(h), None) => h.to_string(),
+            _ => anyhow::bail!("S3_ENDPOINT has no host"),
+        };
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(body));
+
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, host, payload_hash, amz_date, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signature = hex::encode(hmac(
+            &signing_key(&self.secret_key, &date, &self.region, "s3"),
+            &string_to_sign,
+        ));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key, scope, signature
+        );
+
+        let mut target = self.endpoint.clone();
+        target.set_path(&path);
+        Ok(self
+            .client
+            .request(method, target)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(reqwest::header::AUTHORIZATION, authorization))
+    }
+}
+
+impl AssetStore for S3Store {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let resp = self
+                .request(reqwest::Method::GET, url, &[], chrono::Utc::now())?
+                .send()
+                .await?;
+            if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !resp.status().is_success() {
+                anyhow::bail!("S3 GET failed: {}", resp.status());
+            }
+            Ok(Some(resp.bytes().await?.to_vec()))
+        })
+    }
+
+    fn put<'a>(&'a self, url: &'a str, data: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let resp = self
+                .request(reqwest::Method::PUT, url, data, chrono::Utc::now())?
+                .body(data.to_vec())
+                .send()
+                .await?;
+            if !resp.status().is_success() {
+                let status = resp.status();
+                let body = resp.text().await.unwrap_or_default();
+                anyhow::bail!("S3 PUT failed: {} {}", status, body);
+            }
+            Ok(())
+        })
+    }
+
+    fn open<'a>(
+        &'a self,
+        url: &'a str,
+        range: Option<(u64, u64)>,
+    ) -> BoxFuture<'a, anyhow::Result<Option<ByteStream>>> {
+        Box::pin(async move {
+            let mut req = self.request(reqwest::Method::GET, url, &[], chrono::Utc::now())?;
+            if let Some((start, end)) = range {
+                req = req.header(reqwest::header::RANGE, format!("bytes={}-{}", start, end));
+            }
+            let resp = req.send().await?;
+            if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            if !resp.status().is_success() {
+                anyhow::bail!("S3 GET failed: {}", resp.status());
+            }
+            Ok(Some(
+                resp.bytes_stream().map_err(std::io::Error::other).boxed(),
+            ))
+        })
+    }
+
+    fn delete<'a>(&'a self, url: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let resp = self
+                .request(reqwest::Method::DELETE, url, &[], chrono::Utc::now())?
+                .send()
+                .await?;
+            if !resp.status().is_success() && resp.status() != reqwest::StatusCode::NOT_FOUND {
+                anyhow::bail!("S3 DELETE failed: {}", resp.status());
+            }
+            Ok(())
+        })
+    }
+}
+
+// ============ Facade ============
+
+/// Set up the backend chosen by `ASSET_STORAGE`. Call once at startup.
+pub fn init(pool: &PgPool) -> anyhow::Result<()> {
+    let kind = std::env::var("ASSET_STORAGE").unwrap_or_else(|_| "postgres".to_string());
+    let store: Box<dyn AssetStore> = match kind.trim().to_lowercase().as_str() {
+        "postgres" | "db" | "" => Box::new(PostgresStore { pool: pool.clone() }),
+        "fs" | "disk" => Box::new(FsStore {
+            root: PathBuf::from(
+                std::env::var("ASSET_DIR").unwrap_or_else(|_| "data/assets".to_string()),
+            ),
+        }),
+        "s3" => Box::new(S3Store::from_env()?),
+        other => anyhow::bail!("Unknown ASSET_STORAGE: {} (postgres/fs/s3)", other),
+    };
+    tracing::info!("Asset storage: {}", store.name());
+    let _ = STORE.set(store);
+    Ok(())
+}
+
+/// The configured backend
+pub fn store() -> &'static dyn AssetStore {
+    STORE.get().expect("storage::init not called").as_ref()
+}
+
+/// `assets` rows of a URL: its own, or that of the asset it is an alias of
+const ASSET_OF_URL: &str = r#"
+    SELECT a.* FROM assets a WHERE a.url = $1
+    UNION ALL
+    SELECT a.* FROM asset_aliases x JOIN assets a ON a.sha256 = x.sha256 WHERE x.url = $1
+    LIMIT 1
+"#;
+
+#[derive(sqlx::FromRow)]
+struct AssetRow {
+    /// URL the blob is stored under
+    url: String,
+    data: Option<Vec<u8>>,
+    mime_type: Option<String>,
+    storage: Option<String>,
+}
+
+/// Bytes and MIME type of a stored asset
+pub async fn load(pool: &PgPool, url: &str) -> anyhow::Result<Option<(Vec<u8>, Option<String>)>> {
+    let row = sqlx::query_as::<_, AssetRow>(&format!(
+        "SELECT url, data, mime_type, storage FROM ({}) a",
+        ASSET_OF_URL
+    ))
+    .bind(url)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    if let Some(data) = row.data {
+        return Ok(Some((data, row.mime_type)));
+    }
+
+    let store = store();
+    match row.storage.as_deref() {
+        Some(name) if name == store.name() => {
+            Ok(store.get(&row.url).await?.map(|data| (data, row.mime_type)))
+        }
+        other => {
+            tracing::warn!(
+                "[Storage] Asset {} is in {:?}, but {} is configured",
+                url,
+                other,
+                store.name()
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// What `get_asset` needs before touching the blob
+#[derive(sqlx::FromRow)]
+pub struct AssetMeta {
+    pub mime_type: Option<String>,
+    pub size: i64,
+    pub create_time: i64,
+    storage: String,
+    /// URL the blob is stored under (differs from the requested one for aliases)
+    url: String,
+    /// SHA-256 of the content, `None` for assets saved before hashing
+    pub sha256: Option<String>,
+}
+
+pub async fn meta(pool: &PgPool, url: &str) -> Result<Option<AssetMeta>, sqlx::Error> {
+    sqlx::query_as::<_, AssetMeta>(&format!(
+        r#"
+        SELECT mime_type,
+               COALESCE(octet_length(data)::bigint, size::bigint, 0) AS size,
+               COALESCE(create_time, 0) AS create_time,
+               storage, url, sha256
+        FROM ({}) a
+        "#,
+        ASSET_OF_URL
+    ))
+    .bind(url)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Stream an asset, or the inclusive byte `range` of it
+pub async fn open(
+    pool: &PgPool,
+    meta: &AssetMeta,
+    range: Option<(u64, u64)>,
+) -> anyhow::Result<Option<ByteStream>> {
+    let store = store();
+    if meta.storage == "postgres" {
+        // Inline blobs stay readable whichever backend is configured now
+        let inline = PostgresStore { pool: pool.clone() };
+        return inline.open(&meta.url, range).await;
+    }
+    if meta.storage != store.name() {
+        tracing::warn!(
+            "[Storage] Asset {} is in {}, but {} is configured",
+            meta.url,
+            meta.storage,
+            store.name()
+        );
+        return Ok(None);
+    }
+    store.open(&meta.url, range).await
+}
+
+/// Whether an asset is stored
+pub async fn exists(pool: &PgPool, url: &str) -> bool {
+    sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM assets WHERE url = $1) OR EXISTS(SELECT 1 FROM asset_aliases WHERE url = $1)",
+    )
+    .bind(url)
+    .fetch_one(pool)
+    .await
+    .unwrap_or(false)
+}
+
+fn content_hash(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// The asset already holding content `sha256`
+async fn holder_of(pool: &PgPool, sha256: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT url FROM assets WHERE sha256 = $1")
+        .bind(sha256)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Point `url` at the stored content `sha256`, dropping any blob of its own
+async fn make_alias(pool: &PgPool, url: &str, sha256: &str) -> anyhow::Result<()> {
+    if remove(pool, url).await?.is_none() {
+        remove_variants(pool, url).await?;
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO asset_aliases (url, sha256, created_at) VALUES ($1, $2, $3)
+        ON CONFLICT (url) DO UPDATE SET sha256 = EXCLUDED.sha256, created_at = EXCLUDED.created_at
+        "#,
+    )
+    .bind(url)
+    .bind(sha256)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Hand the blob of asset `meta` over to one of its aliases, which becomes
+/// the asset. Returns false when there is no alias to take it, or its backend
+/// is not configured.
+async fn promote_alias(pool: &PgPool, meta: &AssetMeta) -> anyhow::Result<bool> {
+    let Some(sha256) = meta.sha256.as_deref() else {
+        return Ok(false);
+    };
+    let alias: Option<String> = sqlx::query_scalar(
+        "SELECT url FROM asset_aliases WHERE sha256 = $1 ORDER BY created_at, url LIMIT 1",
+    )
+    .bind(sha256)
+    .fetch_optional(pool)
+    .await?;
+    let Some(alias) = alias else {
+        return Ok(false);
+    };
+
+    let store = store();
+    let moved_blob = meta.storage != "postgres";
+    if moved_blob {
+        if meta.storage != store.name() {
+            return Ok(false);
+        }
+        let Some(data) = store.get(&meta.url).await? else {
+            return Ok(false);
+        };
+        store.put(&alias, &data).await?;
+    }
+    // Inline data moves with the row
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM asset_aliases WHERE url = $1")
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE assets SET url = $2 WHERE url = $1")
+        .bind(&meta.url)
+        .bind(&alias)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    if moved_blob {
+        store.delete(&meta.url).await?;
+    }
+    Ok(true)
+}
+
+/// Store (or replace) an asset's bytes in the configured backend. Content
+/// already stored under another URL is not stored again; `url` becomes an
+/// alias of it.
+pub async fn save(pool: &PgPool, url: &str, data: &[u8], mime_type: &str) -> anyhow::Result<()> {
+    let sha256 = content_hash(data);
+    if let Some(holder) = holder_of(pool, &sha256).await? {
+        if holder != url {
+            return make_alias(pool, url, &sha256).await;
+        }
+    }
+
+    // `url` keeps a blob of its own; aliases of its previous content take that over
+    let own: Option<AssetMeta> = sqlx::query_as(
+        r#"
+        SELECT mime_type, COALESCE(size::bigint, 0) AS size, COALESCE(create_time, 0) AS create_time,
+               storage, url, sha256
+        FROM assets WHERE url = $1
+        "#,
+    )
+    .bind(url)
+    .fetch_optional(pool)
+    .await?;
+    let unchanged = own
+        .as_ref()
+        .is_some_and(|m| m.sha256.as_deref() == Some(sha256.as_str()));
+    if let Some(own) = own.filter(|m| m.sha256.as_deref().is_some_and(|h| h != sha256)) {
+        promote_alias(pool, &own).await?;
+    }
+    // Thumbnails of the previous content are stale
+    if !unchanged {
+        remove_variants(pool, url).await?;
+    }
+    sqlx::query("DELETE FROM asset_aliases WHERE url = $1")
+        .bind(url)
+        .execute(pool)
+        .await?;
+
+    let store = store();
+    store.put(url, data).await?;
+    let inline = store.name() == "postgres";
+    let saved = sqlx::query(
+        r#"
+        INSERT INTO assets (url, mime_type, size, storage, create_time, sha256) VALUES ($1, $2, $3, $4, $5, $7)
+        ON CONFLICT (url) DO UPDATE SET
+            mime_type = EXCLUDED.mime_type,
+            size = EXCLUDED.size,
+            storage = EXCLUDED.storage,
+            sha256 = EXCLUDED.sha256,
+            data = CASE WHEN $6 THEN assets.data ELSE NULL END
+        "#,
+    )
+    .bind(url)
+    .bind(mime_type)
+    .bind(data.len() as i32)
+    .bind(store.name())
+    .bind(chrono::Utc::now().timestamp())
+    .bind(inline)
+    .bind(&sha256)
+    .execute(pool)
+    .await;
+    match saved {
+        Ok(_) => Ok(()),
+        // Another URL stored the same bytes meanwhile
+        Err(sqlx::Error::Database(e)) if e.constraint() == Some("assets_sha256_key") => {
+            make_alias(pool, url, &sha256).await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete an asset: its blob, its row and its cached variants. Returns the
+/// bytes freed, or `None` when the blob is in a backend that is not
+/// configured any more (the row is kept so it is not orphaned silently).
+/// Removing an alias only drops the alias; removing an asset that has
+/// aliases hands its blob to one of them.
+pub async fn remove(pool: &PgPool, url: &str) -> anyhow::Result<Option<i64>> {
+    let aliased = sqlx::query("DELETE FROM asset_aliases WHERE url = $1")
+        .bind(url)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    let meta = if aliased > 0 {
+        None
+    } else {
+        meta(pool, url).await?
+    };
+    let Some(meta) = meta else {
+        return Ok(Some(remove_variants(pool, url).await?));
+    };
+    if promote_alias(pool, &meta).await? {
+        return Ok(Some(remove_variants(pool, url).await?));
+    }
+    let store = store();
+    if meta.storage != "postgres" {
+        if meta.storage != store.name() {
+            tracing::warn!(
+                "[Storage] Not removing {}: it is in {}, but {} is configured",
+                url,
+                meta.storage,
+                store.name()
+            );
+            return Ok(None);
+        }
+        store.delete(url).await?;
+    }
+
+    let variants = remove_variants(pool, url).await?;
+    sqlx::query("DELETE FROM assets WHERE url = $1")
+        .bind(url)
+        .execute(pool)
+        .await?;
+    Ok(Some(meta.size + variants))
+}
+
+/// Delete the cached variants of a URL; returns their bytes
+async fn remove_variants(pool: &PgPool, url: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        WITH d AS (
+            DELETE FROM asset_variants WHERE url = $1
+            RETURNING COALESCE(size::bigint, octet_length(data)::bigint) AS size
+        )
+        SELECT COALESCE(SUM(size), 0)::bigint FROM d
+        "#,
+    )
+    .bind(url)
+    .fetch_one(pool)
+    .await
+}
+
+/// Hash assets stored before deduplication, turning repeated content into
+/// aliases. Returns (assets hashed, aliases created, bytes freed).
+pub async fn dedup_existing(pool: &PgPool) -> anyhow::Result<(usize, usize, i64)> {
+    let (mut hashed, mut aliased, mut freed) = (0, 0, 0);
+    let mut failed: Vec<String> = Vec::new();
+    loop {
+        let batch: Vec<String> = sqlx::query_scalar(
+            "SELECT url FROM assets WHERE sha256 IS NULL AND NOT (url = ANY($1)) ORDER BY url LIMIT $2",
+        )
+        .bind(&failed)
+        .bind(MIGRATE_BATCH)
+        .fetch_all(pool)
+        .await?;
+        if batch.is_empty() {
+            break;
+        }
+        for url in batch {
+            let data = match load(pool, &url).await {
+                Ok(Some((data, _))) => data,
+                Ok(None) => {
+                    failed.push(url);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!("[Storage] Failed to read {}: {}", url, e);
+                    failed.push(url);
+                    continue;
+                }
+            };
+            let sha256 = content_hash(&data);
+            if holder_of(pool, &sha256).await?.is_some() {
+                make_alias(pool, &url, &sha256).await?;
+                aliased += 1;
+                freed += data.len() as i64;
+            } else {
+                sqlx::query("UPDATE assets SET sha256 = $2 WHERE url = $1")
+                    .bind(&url)
+                    .bind(&sha256)
+                    .execute(pool)
+                    .await?;
+            }
+            hashed += 1;
+        }
+        tracing::info!(
+            "[Storage] Hashed {} asset(s), {} duplicate(s) so far",
+            hashed,
+            aliased
+        );
+    }
+    if !failed.is_empty() {
+        tracing::warn!("[Storage] {} asset(s) could not be read", failed.len());
+    }
+    Ok((hashed, aliased, freed))
+}
+
+/// Move blobs still stored inline in `assets.data` to the configured backend.
+/// Returns how many were moved.
+pub async fn migrate_to_store(pool: &PgPool) -> anyhow::Result<usize> {
+    let store = store();
+    if store.name() == "postgres" {
+        anyhow::bail!(
+            "ASSET_STORAGE is postgres; set it to fs or s3 to migrate assets out of the database"
+        );
+    }
+
+    let mut moved = 0;
+    let mut failed: Vec<String> = Vec::new();
+    loop {
+        let batch: Vec<(String, Vec<u8>)> = sqlx::query_as(
+            "SELECT url, data FROM assets WHERE data IS NOT NULL AND NOT (url = ANY($1)) ORDER BY url LIMIT $2",
+        )
+        .bind(&failed)
+        .bind(MIGRATE_BATCH)
+        .fetch_all(pool)
+        .await?;
+        if batch.is_empty() {
+            break;
+        }
+        for (url, data) in batch {
+            if let Err(e) = store.put(&url, &data).await {
+                tracing::warn!("[Storage] Failed to migrate {}: {}", url, e);
+                failed.push(url);
+                continue;
+            }
+            sqlx::query("UPDATE assets SET data = NULL, storage = $2, size = $3 WHERE url = $1")
+                .bind(&url)
+                .bind(store.name())
+                .bind(data.len() as i32)
+                .execute(pool)
+                .await?;
+            moved += 1;
+        }
+        tracing::info!("[Storage] Migrated {} asset(s) to {}", moved, store.name());
+    }
+    if !failed.is_empty() {
+        tracing::warn!("[Storage] {} asset(s) could not be migrated", failed.len());
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sigv4_and_keys() {
+        // Example from the AWS Signature Version 4 documentation
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+
+        assert_eq!(uri_encode("a b/c~"), "a%20b%2Fc~");
+        let key = object_key("https://mmbiz.qpic.cn/x.jpg");
+        assert_eq!(key.len(), 35);
+        assert_eq!(&key[..2], &key[3..5]);
+
+        let store = S3Store {
+            client: reqwest::Client::new(),
+            endpoint: reqwest::Url::parse("http://localhost:9000").unwrap(),
+            bucket: "wechat".to_string(),
+            region: "us-east-1".to_string(),
+            access_key: "a".to_string(),
+            secret_key: "s".to_string(),
+            prefix: "assets".to_string(),
+        };
+        assert_eq!(
+            store.object_path("https://mmbiz.qpic.cn/x.jpg"),
+            format!("/wechat/assets/{}", key)
+        );
+    }
+}
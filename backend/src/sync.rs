@@ -0,0 +1,449 @@
+//! Account history sync
+//!
+//! Crawls an official account's complete publish history by paging
+//! `appmsgpublish` over `begin` offsets and upserting every article into
+//! `articles`. Progress (next offset, counts, status) lives on the `accounts`
+//! row, so a sync interrupted by a restart or failure continues where it
+//! stopped. Requests go through the session's crawl lane and endpoint budget.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::crawl::Priority;
+use crate::wechat::client::{MpClient, MpError};
+use crate::wechat::model::AppMsg;
+use crate::AppState;
+
+/// Publish entries requested per page (appmsgpublish caps this at 20)
+const PAGE_SIZE: u32 = 20;
+/// Pages checked per account on refresh before giving up on reaching known history
+const MAX_REFRESH_PAGES: usize = 5;
+
+lazy_static! {
+    /// Running syncs by fakeid, with their cancel flags
+    static ref RUNNING: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+/// Upsert articles; returns for each whether it was new
+pub async fn upsert_articles(
+    pool: &PgPool,
+    fakeid: &str,
+    articles: &[&AppMsg],
+) -> Result<Vec<bool>, sqlx::Error> {
+    let mut inserted = Vec::with_capacity(articles.len());
+    for article in articles {
+        let is_new: bool = sqlx::query_scalar(
+            r#"
+            INSERT INTO articles (id, fakeid, aid, title, link, create_time, update_time, digest, cover, is_deleted, itemidx, raw_json)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            ON CONFLICT (id) DO UPDATE SET
+                title = EXCLUDED.title,
+                link = EXCLUDED.link,
+                update_time = EXCLUDED.update_time,
+                digest = EXCLUDED.digest,
+                cover = EXCLUDED.cover,
+                is_deleted = EXCLUDED.is_deleted,
+                itemidx = EXCLUDED.itemidx,
+                raw_json = EXCLUDED.raw_json
+            RETURNING (xmax = 0)
+            "#,
+        )
+        .bind(format!("{}:{}", fakeid, article.aid))
+        .bind(fakeid)
+        .bind(&article.aid)
+        .bind(&article.title)
+        .bind(&article.link)
+        .bind(article.create_time)
+        .bind(article.update_time)
+        .bind(&article.digest)
+        .bind(&article.cover)
+        .bind(article.is_deleted)
+        .bind(article.itemidx)
+        .bind(&article.raw)
+        .fetch_one(pool)
+        .await?;
+        inserted.push(is_new);
+    }
+    Ok(inserted)
+}
+
+pub fn is_running(fakeid: &str) -> bool {
+    RUNNING.lock().unwrap().contains_key(fakeid)
+}
+
+/// Ask a running sync to stop after the current page
+pub fn cancel(fakeid: &str) -> bool {
+    match RUNNING.lock().unwrap().get(fakeid) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Start (or resume) a sync in the background. `full` crawls the whole history;
+/// otherwise the sync stops at the first page with no new articles.
+/// Returns false when a sync for this account is already running.
+pub async fn start(
+    state: &AppState,
+    fakeid: &str,
+    full: bool,
+    restart: bool,
+) -> Result<bool, sqlx::Error> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = RUNNING.lock().unwrap();
+        if running.contains_key(fakeid) {
+            return Ok(false);
+        }
+        running.insert(fakeid.to_string(), cancelled.clone());
+    }
+
+    // Resume from the saved offset unless asked to start over or the last sync finished
+    let now = chrono::Utc::now().timestamp();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO accounts (fakeid, create_time, update_time, sync_status, sync_full, sync_begin, sync_synced, sync_started_at)
+        VALUES ($1, $2, $2, 'running', $3, 0, 0, $2)
+        ON CONFLICT (fakeid) DO UPDATE SET
+            sync_status = 'running',
+            sync_full = $3,
+            sync_error = NULL,
+            sync_finished_at = NULL,
+            sync_begin = CASE WHEN $4 OR accounts.sync_status = 'completed' OR accounts.sync_full IS DISTINCT FROM $3
+                              THEN 0 ELSE COALESCE(accounts.sync_begin, 0) END,
+            sync_synced = CASE WHEN $4 OR accounts.sync_status = 'completed' OR accounts.sync_full IS DISTINCT FROM $3
+                               THEN 0 ELSE COALESCE(accounts.sync_synced, 0) END,
+            sync_started_at = $2
+        "#,
+    )
+    .bind(fakeid)
+    .bind(now)
+    .bind(full)
+    .bind(restart)
+    .execute(&state.db_pool)
+    .await;
+    if let Err(e) = result {
+        RUNNING.lock().unwrap().remove(fakeid);
+        return Err(e);
+    }
+
+    let state = state.clone();
+    let fakeid = fakeid.to_string();
+    tokio::spawn(async move {
+        let outcome = run(&state, &fakeid, full, &cancelled).await;
+        let (status, error) = match outcome {
+            Ok(true) => ("completed", None),
+            Ok(false) => ("cancelled", None),
+            Err(e) => ("failed", Some(e.to_string())),
+        };
+        if let Err(e) = sqlx::query(
+            r#"
+            UPDATE accounts SET
+                sync_status = $2,
+                sync_error = $3,
+                sync_finished_at = $4,
+                sync_all = sync_all OR ($2 = 'completed' AND sync_full),
+                update_time = $4
+            WHERE fakeid = $1
+            "#,
+        )
+        .bind(&fakeid)
+        .bind(status)
+        .bind(&error)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&state.db_pool)
+        .await
+        {
+            tracing::error!("[Sync] Failed to save status for {}: {}", fakeid, e);
+        }
+        RUNNING.lock().unwrap().remove(&fakeid);
+        tracing::info!(
+            "[Sync] {} {}{}",
+            fakeid,
+            status,
+            error.map(|e| format!(": {}", e)).unwrap_or_default()
+        );
+    });
+
+    Ok(true)
+}
+
+/// Page through the history. Returns false when cancelled.
+async fn run(
+    state: &AppState,
+    fakeid: &str,
+    full: bool,
+    cancelled: &AtomicBool,
+) -> anyhow::Result<bool> {
+    let (mut begin, owner): (i32, Option<uuid::Uuid>) =
+        sqlx::query_as("SELECT COALESCE(sync_begin, 0), owner_id FROM accounts WHERE fakeid = $1")
+            .bind(fakeid)
+            .fetch_one(&state.db_pool)
+            .await?;
+    let client = MpClient::for_owner(state, owner).await?;
+
+    loop {
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let page = client
+            .publish_page(fakeid, begin as u32, PAGE_SIZE, Priority::Background)
+            .await
+            .map_err(|e| anyhow::anyhow!("Offset {}: {}", begin, e))?;
+        let articles: Vec<&AppMsg> = page.articles().collect();
+        let inserted = upsert_articles(&state.db_pool, fakeid, &articles)
+            .await?
+            .into_iter()
+            .filter(|new| *new)
+            .count();
+        begin += page.entries as i32;
+        let last_update_time = articles.iter().map(|a| a.create_time).max();
+
+        sqlx::query(
+            r#"
+            UPDATE accounts SET
+                sync_begin = $2,
+                sync_synced = COALESCE(sync_synced, 0) + $3,
+                total_count = CASE WHEN $4 > 0 THEN $4 ELSE total_count END,
+                count = (SELECT COUNT(*) FROM articles WHERE fakeid = $1 AND itemidx = 1),
+                articles = (SELECT COUNT(*) FROM articles WHERE fakeid = $1),
+                last_update_time = GREATEST(last_update_time, $5),
+                update_time = $6
+            WHERE fakeid = $1
+            "#,
+        )
+        .bind(fakeid)
+        .bind(begin)
+        .bind(articles.len() as i32)
+        .bind(page.total_count)
+        .bind(last_update_time)
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&state.db_pool)
+        .await?;
+
+        tracing::info!(
+            "[Sync] {}: offset {}/{} (+{} articles, {} new)",
+            fakeid,
+            begin,
+            page.total_count,
+            articles.len(),
+            inserted
+        );
+
+        let exhausted = page.entries == 0 || begin >= page.total_count;
+        // Incremental sync: a page with nothing new means we reached known history
+        let caught_up = !full && inserted == 0;
+        if exhausted || caught_up {
+            return Ok(true);
+        }
+    }
+}
+
+// ============ Incremental refresh ============
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ArticleRef {
+    pub aid: String,
+    pub title: String,
+    pub link: String,
+    pub create_time: i64,
+}
+
+impl From<&AppMsg> for ArticleRef {
+    fn from(a: &AppMsg) -> Self {
+        ArticleRef {
+            aid: a.aid.clone(),
+            title: a.title.clone(),
+            link: a.link.clone(),
+            create_time: a.create_time,
+        }
+    }
+}
+
+/// What changed for one account since its last refresh
+#[derive(Debug, Default, Serialize)]
+pub struct RefreshSummary {
+    pub fakeid: String,
+    pub nickname: Option<String>,
+    pub new_articles: Vec<ArticleRef>,
+    /// Articles that disappeared from the refreshed range, now marked `is_deleted`
+    pub deleted: Vec<ArticleRef>,
+    /// Already-known articles seen again (metadata refreshed)
+    pub seen: usize,
+    pub pages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Fetch pages newer than `last_update_time`, insert new articles and mark
+/// articles missing from the fetched range as deleted
+async fn refresh_account(
+    state: &AppState,
+    client: &MpClient,
+    summary: &mut RefreshSummary,
+    last_update_time: Option<i64>,
+) -> anyhow::Result<()> {
+    let fakeid = summary.fakeid.clone();
+    let mut begin = 0;
+    let mut total_count = 0;
+    let mut fetched_ids = Vec::new();
+    let mut oldest_seen: Option<i64> = None;
+
+    // Accounts never synced only get their newest page
+    let cutoff = last_update_time.unwrap_or(i64::MAX);
+    while summary.pages < MAX_REFRESH_PAGES {
+        let page = client
+            .publish_page(&fakeid, begin as u32, PAGE_SIZE, Priority::Task)
+            .await?;
+        summary.pages += 1;
+        total_count = page.total_count;
+        begin += page.entries as i32;
+
+        let articles: Vec<&AppMsg> = page.articles().collect();
+        let inserted = upsert_articles(&state.db_pool, &fakeid, &articles).await?;
+        for (article, is_new) in articles.iter().zip(inserted) {
+            fetched_ids.push(format!("{}:{}", fakeid, article.aid));
+            if is_new {
+                summary.new_articles.push((*article).into());
+            } else {
+                summary.seen += 1;
+            }
+        }
+
+        let page_oldest = articles.iter().map(|a| a.create_time).min();
+        if page_oldest.is_some() {
+            oldest_seen = page_oldest;
+        }
+        let reached_known = page_oldest.is_none_or(|t| t <= cutoff);
+        if reached_known || page.entries == 0 || begin >= page.total_count {
+            break;
+        }
+    }
+
+    // Pages are contiguous from the newest, so every live article since
+    // `oldest_seen` was returned; anything else in that range was deleted
+    if let Some(oldest) = oldest_seen {
+        summary.deleted = sqlx::query_as::<_, ArticleRef>(
+            r#"
+            UPDATE articles SET is_deleted = TRUE
+            WHERE fakeid = $1 AND create_time >= $2 AND is_deleted = FALSE AND NOT (id = ANY($3))
+            RETURNING aid, title, link, create_time
+            "#,
+        )
+        .bind(&fakeid)
+        .bind(oldest)
+        .bind(&fetched_ids)
+        .fetch_all(&state.db_pool)
+        .await?;
+    }
+
+    let newest = summary.new_articles.iter().map(|a| a.create_time).max();
+    sqlx::query(
+        r#"
+        UPDATE accounts SET
+            total_count = CASE WHEN $2 > 0 THEN $2 ELSE total_count END,
+            count = (SELECT COUNT(*) FROM articles WHERE fakeid = $1 AND itemidx = 1),
+            articles = (SELECT COUNT(*) FROM articles WHERE fakeid = $1),
+            last_update_time = GREATEST(last_update_time, $3),
+            update_time = $4
+        WHERE fakeid = $1
+        "#,
+    )
+    .bind(&fakeid)
+    .bind(total_count)
+    .bind(newest)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Refresh the given accounts, or every monitored account. Accounts with a
+/// running full sync are skipped; per-account failures are reported in the summary.
+pub async fn refresh_accounts(
+    state: &AppState,
+    fakeids: Option<&[String]>,
+) -> anyhow::Result<Vec<RefreshSummary>> {
+    #[allow(clippy::type_complexity)]
+    let accounts: Vec<(String, Option<String>, Option<i64>, Option<uuid::Uuid>)> = match fakeids {
+        Some(ids) => sqlx::query_as(
+            "SELECT fakeid, nickname, last_update_time, owner_id FROM accounts WHERE fakeid = ANY($1) ORDER BY fakeid",
+        )
+        .bind(ids)
+        .fetch_all(&state.db_pool)
+        .await?,
+        None => sqlx::query_as(
+            "SELECT fakeid, nickname, last_update_time, owner_id FROM accounts WHERE monitored ORDER BY fakeid",
+        )
+        .fetch_all(&state.db_pool)
+        .await?,
+    };
+
+    // Each account is refreshed with a session of its owner
+    let mut clients: HashMap<Option<uuid::Uuid>, MpClient> = HashMap::new();
+    let mut summaries = Vec::with_capacity(accounts.len());
+    for (fakeid, nickname, last_update_time, owner) in accounts {
+        let mut summary = RefreshSummary {
+            fakeid,
+            nickname,
+            ..Default::default()
+        };
+        let client = match clients.entry(owner) {
+            std::collections::hash_map::Entry::Occupied(e) => Some(e.into_mut()),
+            std::collections::hash_map::Entry::Vacant(e) => MpClient::for_owner(state, owner)
+                .await
+                .ok()
+                .map(|c| e.insert(c)),
+        };
+        if is_running(&summary.fakeid) {
+            summary.error = Some("Full sync in progress".to_string());
+        } else if let Some(client) = client {
+            if let Err(e) = refresh_account(state, client, &mut summary, last_update_time).await {
+                tracing::warn!("[Sync] Refresh of {} failed: {}", summary.fakeid, e);
+                summary.error = Some(e.to_string());
+            }
+        } else {
+            summary.error = Some(MpError::NotLoggedIn.to_string());
+        }
+        tracing::info!(
+            "[Sync] Refreshed {}: {} new, {} deleted",
+            summary.fakeid,
+            summary.new_articles.len(),
+            summary.deleted.len()
+        );
+        summaries.push(summary);
+    }
+    Ok(summaries)
+}
+
+/// Continue syncs that were running when the server stopped
+pub async fn resume_interrupted(state: &AppState) {
+    let rows: Vec<(String, bool)> = match sqlx::query_as(
+        "SELECT fakeid, COALESCE(sync_full, TRUE) FROM accounts WHERE sync_status = 'running'",
+    )
+    .fetch_all(&state.db_pool)
+    .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            tracing::error!("[Sync] Failed to load interrupted syncs: {}", e);
+            return;
+        }
+    };
+
+    for (fakeid, full) in rows {
+        tracing::info!("[Sync] Resuming interrupted sync for {}", fakeid);
+        if let Err(e) = start(state, &fakeid, full, false).await {
+            tracing::error!("[Sync] Failed to resume {}: {}", fakeid, e);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+//! Insight task queue
+//!
+//! Task workers are long-running and each one drives WeChat searches, embeddings
+//! and LLM calls through the same MP session, so at most `TASK_CONCURRENCY`
+//! (default 2) run at once; a semaphore bounds the pool. Further tasks stay
+//! `pending` until a slot frees up, highest priority first and FIFO within a
+//! priority.
+//!
+//! Waiting tasks are mirrored in the `task_queue` table, which gives
+//! `list_tasks` their position and lets them survive a restart. API keys are
+//! never persisted, so restored tasks fall back to the server's keys.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use sqlx::PgPool;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+use crate::api::insight::{run_worker, TaskConfig};
+use crate::AppState;
+
+/// Priority of tasks created without one
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+struct QueuedTask {
+    task_id: Uuid,
+    priority: i32,
+    seq: i64,
+    queued_at: i64,
+    config: TaskConfig,
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for QueuedTask {}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedTask {
+    // Max-heap: highest priority first, then FIFO by sequence
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    waiting: BinaryHeap<QueuedTask>,
+    running: HashSet<Uuid>,
+}
+
+pub struct TaskQueue {
+    max_running: usize,
+    slots: Arc<Semaphore>,
+    state: Mutex<QueueState>,
+    notify: Notify,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedEntry {
+    pub task_id: Uuid,
+    pub priority: i32,
+    /// 1 = next to start
+    pub position: usize,
+    pub queued_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueSnapshot {
+    pub max_running: usize,
+    pub running: Vec<Uuid>,
+    pub queued: Vec<QueuedEntry>,
+}
+
+#[derive(sqlx::FromRow)]
+struct QueueRow {
+    task_id: Uuid,
+    priority: i32,
+    seq: i64,
+    enqueued_at: i64,
+    config: serde_json::Value,
+}
+
+/// Frees a running task's slot when its worker ends, panics included
+struct RunningGuard {
+    state: AppState,
+    task_id: Uuid,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.state
+            .task_queue
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .running
+            .remove(&self.task_id);
+    }
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TaskQueue {
+    /// Empty queue sized by `TASK_CONCURRENCY`
+    pub fn new() -> Self {
+        let max_running = std::env::var("TASK_CONCURRENCY")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2usize)
+            .max(1);
+        TaskQueue {
+            max_running,
+            slots: Arc::new(Semaphore::new(max_running)),
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Reload tasks still queued at the last shutdown; returns how many
+    pub async fn restore(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let rows = sqlx::query_as::<_, QueueRow>(
+            r#"
+            SELECT q.task_id, q.priority, q.seq, q.enqueued_at, s.config
+            FROM task_queue q
+            JOIN insight_tasks t ON t.id = q.task_id AND t.status = 'pending'
+            JOIN insight_task_state s ON s.task_id = q.task_id
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut restored = 0;
+        for row in rows {
+            match serde_json::from_value::<TaskConfig>(row.config) {
+                Ok(config) => {
+                    self.push(QueuedTask {
+                        task_id: row.task_id,
+                        priority: row.priority,
+                        seq: row.seq,
+                        queued_at: row.enqueued_at,
+                        config,
+                    });
+                    restored += 1;
+                }
+                Err(e) => tracing::warn!("[Queue] Dropping task {}: {}", row.task_id, e),
+            }
+        }
+        Ok(restored)
+    }
+
+    /// Queue a task whose record is already inserted with status `pending`
+    pub async fn enqueue(
+        &self,
+        pool: &PgPool,
+        task_id: Uuid,
+        config: TaskConfig,
+        priority: i32,
+    ) -> Result<(), sqlx::Error> {
+        let queued_at = chrono::Utc::now().timestamp();
+        let seq: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO task_queue (task_id, priority, enqueued_at) VALUES ($1, $2, $3)
+            ON CONFLICT (task_id) DO UPDATE SET priority = EXCLUDED.priority
+            RETURNING seq
+            "#,
+        )
+        .bind(task_id)
+        .bind(priority)
+        .bind(queued_at)
+        .fetch_one(pool)
+        .await?;
+
+        self.push(QueuedTask {
+            task_id,
+            priority,
+            seq,
+            queued_at,
+            config,
+        });
+        Ok(())
+    }
+
+    fn push(&self, task: QueuedTask) {
+        self.state.lock().unwrap().waiting.push(task);
+        self.notify.notify_one();
+    }
+
+    /// Take a waiting task out of the queue. Returns false if it wasn't queued.
+    pub async fn remove(&self, pool: &PgPool, task_id: Uuid) -> Result<bool, sqlx::Error> {
+        let removed = {
+            let mut state = self.state.lock().unwrap();
+            let before = state.waiting.len();
+            state.waiting.retain(|t| t.task_id != task_id);
+            state.waiting.len() != before
+        };
+        sqlx::query("DELETE FROM task_queue WHERE task_id = $1")
+            .bind(task_id)
+            .execute(pool)
+            .await?;
+        Ok(removed)
+    }
+
+    /// Dispatcher: starts the next waiting task whenever a slot is free
+    pub fn spawn_dispatcher(state: AppState) {
+        tokio::spawn(async move {
+            let queue = state.task_queue.clone();
+            loop {
+                let permit = queue
+                    .slots
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("task queue semaphore closed");
+                let task = loop {
+                    // Waiting tasks stay queued in the database for the next start
+                    if crate::shutdown::requested() {
+                        return;
+                    }
+                    let next = {
+                        let mut s = queue.state.lock().unwrap();
+                        let next = s.waiting.pop();
+                        if let Some(task) = &next {
+                            s.running.insert(task.task_id);
+                        }
+                        next
+                    };
+                    match next {
+                        Some(task) => break task,
+                        None => queue.notify.notified().await,
+                    }
+                };
+
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let task_id = task.task_id;
+                    let _running = RunningGuard {
+                        state: state.clone(),
+                        task_id,
+                        _permit: permit,
+                    };
+                    if let Err(e) = sqlx::query("DELETE FROM task_queue WHERE task_id = $1")
+                        .bind(task_id)
+                        .execute(&state.db_pool)
+                        .await
+                    {
+                        tracing::warn!("[Queue] Failed to dequeue task {}: {}", task_id, e);
+                    }
+                    run_worker(state.clone(), task_id, task.config).await;
+                });
+            }
+        });
+    }
+
+    /// Running tasks and the waiting ones in start order
+    pub fn snapshot(&self) -> QueueSnapshot {
+        let state = self.state.lock().unwrap();
+        let mut waiting: Vec<&QueuedTask> = state.waiting.iter().collect();
+        waiting.sort_by(|a, b| b.cmp(a));
+        QueueSnapshot {
+            max_running: self.max_running,
+            running: state.running.iter().copied().collect(),
+            queued: waiting
+                .into_iter()
+                .enumerate()
+                .map(|(i, t)| QueuedEntry {
+                    task_id: t.task_id,
+                    priority: t.priority,
+                    position: i + 1,
+                    queued_at: t.queued_at,
+                })
+                .collect(),
+        }
+    }
+}
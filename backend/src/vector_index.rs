@@ -0,0 +1,335 @@
+//! Approximate nearest-neighbour index on `embeddings.vector`
+//!
+//! IVFFlat learns its lists from the rows present at build time, so building
+//! it on an empty table gives a useless index and it has to be rebuilt as the
+//! table grows; HNSW can be built at any time but is slower to build. Either
+//! can be created, rebuilt or dropped through `/api/embedding/index`. A
+//! background check builds the default index once the table has enough rows,
+//! and after an embedding migration drops it with the old column.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::error::AppError;
+
+lazy_static! {
+    static ref STATE: Mutex<BuildState> = Mutex::new(BuildState::from_env());
+}
+
+const INDEX_NAME: &str = "idx_embeddings_vector";
+/// pgvector indexes `vector` columns of at most this many dimensions
+const MAX_DIM: i32 = 2000;
+const DEFAULT_MIN_ROWS: i64 = 1000;
+const DEFAULT_CHECK_SECS: u64 = 600;
+
+/// Index method and build parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexSpec {
+    /// "ivfflat" or "hnsw"
+    pub method: String,
+    /// IVFFlat list count; derived from the row count when omitted
+    pub lists: Option<u32>,
+    /// HNSW connections per node (pgvector default 16)
+    pub m: Option<u32>,
+    /// HNSW candidate list size while building (pgvector default 64)
+    pub ef_construction: Option<u32>,
+}
+
+impl IndexSpec {
+    /// Spec used by the automatic build (`VECTOR_INDEX_METHOD`, default ivfflat)
+    fn from_env() -> Self {
+        IndexSpec {
+            method: std::env::var("VECTOR_INDEX_METHOD")
+                .map(|m| m.to_lowercase())
+                .unwrap_or_else(|_| "ivfflat".to_string()),
+            lists: None,
+            m: None,
+            ef_construction: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        match self.method.as_str() {
+            "ivfflat" | "hnsw" => {}
+            other => return Err(AppError::BadRequest(format!("不支持的索引类型: {}", other))),
+        }
+        if self.lists.is_some_and(|l| !(1..=32768).contains(&l)) {
+            return Err(AppError::BadRequest("lists 需在 1-32768 之间".to_string()));
+        }
+        if self.m.is_some_and(|m| !(2..=100).contains(&m)) {
+            return Err(AppError::BadRequest("m 需在 2-100 之间".to_string()));
+        }
+        let m = self.m.unwrap_or(16);
+        if self
+            .ef_construction
+            .is_some_and(|ef| ef < 2 * m || ef > 1000)
+        {
+            return Err(AppError::BadRequest(format!(
+                "ef_construction 需在 {}-1000 之间",
+                2 * m
+            )));
+        }
+        Ok(())
+    }
+
+    /// `CREATE INDEX` statement for a table of `rows` rows
+    fn ddl(&self, rows: i64) -> String {
+        let with = if self.method == "hnsw" {
+            format!(
+                "m = {}, ef_construction = {}",
+                self.m.unwrap_or(16),
+                self.ef_construction.unwrap_or(64)
+            )
+        } else {
+            format!(
+                "lists = {}",
+                self.lists.unwrap_or_else(|| default_lists(rows))
+            )
+        };
+        format!(
+            "CREATE INDEX {} ON embeddings USING {} (vector vector_cosine_ops) WITH ({})",
+            INDEX_NAME, self.method, with
+        )
+    }
+}
+
+/// pgvector's suggestion: rows / 1000 up to a million rows, √rows beyond
+pub fn default_lists(rows: i64) -> u32 {
+    let lists = if rows <= 1_000_000 {
+        rows / 1000
+    } else {
+        (rows as f64).sqrt() as i64
+    };
+    lists.clamp(10, 32768) as u32
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BuildState {
+    /// Build automatically once the table reaches `min_rows`; turned off by a
+    /// manual drop until the next manual build
+    auto_build: bool,
+    min_rows: i64,
+    building: Option<IndexSpec>,
+    last_built_at: Option<i64>,
+    last_error: Option<String>,
+}
+
+impl BuildState {
+    fn from_env() -> Self {
+        BuildState {
+            auto_build: std::env::var("VECTOR_INDEX_AUTO")
+                .map(|v| v != "0" && !v.eq_ignore_ascii_case("false"))
+                .unwrap_or(true),
+            min_rows: std::env::var("VECTOR_INDEX_MIN_ROWS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_MIN_ROWS),
+            building: None,
+            last_built_at: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Current index and build state
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexStatus {
+    /// "ivfflat" or "hnsw" when the index exists
+    pub method: Option<String>,
+    pub definition: Option<String>,
+    pub size_bytes: Option<i64>,
+    /// Rows in `embeddings`
+    pub rows: i64,
+    pub dimension: Option<i32>,
+    pub auto_build: bool,
+    pub min_rows: i64,
+    /// Spec of the build in progress
+    pub building: Option<IndexSpec>,
+    pub last_built_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+/// Definition of the index, if it exists
+async fn definition(pool: &PgPool) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT indexdef FROM pg_indexes WHERE indexname = $1")
+        .bind(INDEX_NAME)
+        .fetch_optional(pool)
+        .await
+}
+
+async fn row_count(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM embeddings")
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn status(pool: &PgPool) -> Result<IndexStatus, AppError> {
+    let definition = definition(pool).await?;
+    let size_bytes: Option<i64> = match definition {
+        Some(_) => {
+            sqlx::query_scalar("SELECT pg_relation_size($1::regclass)")
+                .bind(INDEX_NAME)
+                .fetch_one(pool)
+                .await?
+        }
+        None => None,
+    };
+    let method = definition.as_deref().and_then(|d| {
+        ["hnsw", "ivfflat"]
+            .into_iter()
+            .find(|m| d.contains(&format!("USING {} ", m)))
+            .map(str::to_string)
+    });
+    let state = STATE.lock().unwrap().clone();
+    Ok(IndexStatus {
+        method,
+        definition,
+        size_bytes,
+        rows: row_count(pool).await?,
+        dimension: crate::embedding_registry::current().map(|r| r.dimension),
+        auto_build: state.auto_build,
+        min_rows: state.min_rows,
+        building: state.building,
+        last_built_at: state.last_built_at,
+        last_error: state.last_error,
+    })
+}
+
+/// Drop and recreate the index with `spec` in the background
+pub fn start_build(pool: &PgPool, spec: IndexSpec) -> Result<(), AppError> {
+    spec.validate()?;
+    if let Some(dimension) = crate::embedding_registry::current().map(|r| r.dimension) {
+        if dimension > MAX_DIM {
+            return Err(AppError::BadRequest(format!(
+                "{} 维向量超过 pgvector 索引上限 ({} 维)",
+                dimension, MAX_DIM
+            )));
+        }
+    }
+    {
+        let mut state = STATE.lock().unwrap();
+        if state.building.is_some() {
+            return Err(AppError::BadRequest("向量索引正在构建中".to_string()));
+        }
+        state.building = Some(spec.clone());
+        state.auto_build = true;
+    }
+
+    let pool = pool.clone();
+    tokio::spawn(async move {
+        let result = build(&pool, &spec).await;
+        let mut state = STATE.lock().unwrap();
+        state.building = None;
+        match result {
+            Ok(()) => {
+                state.last_built_at = Some(chrono::Utc::now().timestamp());
+                state.last_error = None;
+            }
+            Err(e) => {
+                tracing::error!("[VectorIndex] Build failed: {}", e);
+                state.last_error = Some(e.to_string());
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn build(pool: &PgPool, spec: &IndexSpec) -> Result<(), sqlx::Error> {
+    let rows = row_count(pool).await?;
+    let ddl = spec.ddl(rows);
+    tracing::info!("[VectorIndex] Building over {} rows: {}", rows, ddl);
+    let started = std::time::Instant::now();
+    sqlx::query(&format!("DROP INDEX IF EXISTS {}", INDEX_NAME))
+        .execute(pool)
+        .await?;
+    sqlx::query(&ddl).execute(pool).await?;
+    tracing::info!(
+        "[VectorIndex] Built {} index in {:.1}s",
+        spec.method,
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Drop the index and stop building it automatically until the next manual build
+pub async fn drop_index(pool: &PgPool) -> Result<(), AppError> {
+    if STATE.lock().unwrap().building.is_some() {
+        return Err(AppError::BadRequest("向量索引正在构建中".to_string()));
+    }
+    sqlx::query(&format!("DROP INDEX IF EXISTS {}", INDEX_NAME))
+        .execute(pool)
+        .await?;
+    STATE.lock().unwrap().auto_build = false;
+    tracing::info!("[VectorIndex] Dropped index");
+    Ok(())
+}
+
+/// Build the default index if there is none and the table has reached the threshold
+pub async fn ensure(pool: &PgPool) -> Result<(), AppError> {
+    let min_rows = {
+        let state = STATE.lock().unwrap();
+        if !state.auto_build || state.building.is_some() {
+            return Ok(());
+        }
+        state.min_rows
+    };
+    if crate::embedding_registry::current().is_some_and(|r| r.dimension > MAX_DIM)
+        || crate::embedding_registry::migration_status().is_some_and(|m| m.status == "running")
+        || definition(pool).await?.is_some()
+        || row_count(pool).await? < min_rows
+    {
+        return Ok(());
+    }
+    start_build(pool, IndexSpec::from_env())
+}
+
+/// Check for a missing index at startup and every `VECTOR_INDEX_CHECK_SECS`
+pub fn spawn_watcher(pool: PgPool) {
+    let interval_secs = std::env::var("VECTOR_INDEX_CHECK_SECS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_CHECK_SECS)
+        .max(10);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            if let Err(e) = ensure(&pool).await {
+                tracing::warn!("[VectorIndex] Automatic build skipped: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_ddl() {
+        let mut spec = IndexSpec {
+            method: "ivfflat".to_string(),
+            lists: None,
+            m: None,
+            ef_construction: None,
+        };
+        assert!(spec
+            .ddl(50_000)
+            .ends_with("USING ivfflat (vector vector_cosine_ops) WITH (lists = 50)"));
+        assert_eq!(default_lists(0), 10);
+        assert_eq!(default_lists(4_000_000), 2000);
+
+        spec.method = "hnsw".to_string();
+        spec.m = Some(24);
+        assert!(spec.ddl(0).ends_with("WITH (m = 24, ef_construction = 64)"));
+        assert!(spec.validate().is_ok());
+        spec.ef_construction = Some(40);
+        assert!(spec.validate().is_err());
+        spec.method = "flat".to_string();
+        assert!(spec.validate().is_err());
+    }
+}
@@ -0,0 +1,357 @@
+//! Authenticated MP backend client
+//!
+//! One place for what every `mp.weixin.qq.com/cgi-bin` call needs: the
+//! session's token and cookie, the standard browser headers, the session's
+//! endpoint budget and crawl lane, frequency-control retries and `base_resp`
+//! error mapping. Interactive callers fail fast when the budget is spent;
+//! task and background callers wait it out.
+
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use reqwest::header::{COOKIE, ORIGIN, REFERER, SET_COOKIE, USER_AGENT};
+use serde_json::Value;
+
+use crate::cookie::{AccountCookie, CookieEntity};
+use crate::crawl::{self, Priority};
+use crate::error::AppError;
+use crate::ratelimit::{self, Endpoint};
+use crate::request_log;
+use crate::wechat::model::{parse_publish_page, parse_search_biz, BizAccount, PublishPage};
+use crate::AppState;
+
+pub const WECHAT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+const MP_BASE: &str = "https://mp.weixin.qq.com";
+/// Consecutive frequency-control answers before a waiting caller gives up
+const MAX_FREQ_CONTROL_RETRIES: u32 = 5;
+
+lazy_static! {
+    static ref HTTP: reqwest::Client = reqwest::Client::builder()
+        .no_proxy()
+        .build()
+        .expect("failed to build MP http client");
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MpError {
+    #[error("未登录或登录已过期")]
+    NotLoggedIn,
+
+    #[error("请求过于频繁，请 {}s 后重试", .0.as_secs())]
+    OverBudget(Duration),
+
+    #[error("WeChat error ({ret}): {msg}")]
+    Api { ret: i64, msg: String },
+
+    #[error("Invalid WeChat response: {0}")]
+    InvalidResponse(String),
+
+    #[error("Request error: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<MpError> for AppError {
+    fn from(e: MpError) -> Self {
+        match e {
+            MpError::Request(e) => AppError::Request(e),
+            MpError::Database(e) => AppError::Database(e),
+            MpError::NotLoggedIn => AppError::BadRequest(e.to_string()),
+            e => AppError::BadGateway(e.to_string()),
+        }
+    }
+}
+
+/// Referer, Origin and User-Agent the MP backend expects from a browser
+pub fn with_mp_headers(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    request
+        .header(REFERER, "https://mp.weixin.qq.com/")
+        .header(ORIGIN, MP_BASE)
+        .header(USER_AGENT, WECHAT_USER_AGENT)
+}
+
+/// `Err` for a non-zero `base_resp.ret`
+pub fn check_base_resp(json: &Value) -> Result<(), MpError> {
+    match ratelimit::base_ret(json) {
+        Some(ret) if ret != 0 => Err(MpError::Api {
+            ret,
+            msg: json
+                .get("base_resp")
+                .and_then(|r| r.get("err_msg"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("Unknown error")
+                .to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// A logged-in MP session
+pub struct MpClient {
+    token: String,
+    cookie: String,
+    lane: String,
+}
+
+impl MpClient {
+    /// Session stored for `auth_key`
+    pub async fn for_auth_key(state: &AppState, auth_key: &str) -> Result<Self, MpError> {
+        let cookie = state
+            .cookie_store
+            .get_cookie(auth_key)
+            .await?
+            .ok_or(MpError::NotLoggedIn)?;
+        Ok(MpClient {
+            token: cookie.token.clone(),
+            cookie: cookie.to_cookie_header(),
+            lane: crawl::session_lane(Some(auth_key)),
+        })
+    }
+
+    /// Session of the request's auth key
+    pub async fn from_headers(
+        state: &AppState,
+        headers: &axum::http::HeaderMap,
+    ) -> Result<Self, MpError> {
+        let auth_key =
+            crate::proxy::get_auth_key_from_headers(headers).ok_or(MpError::NotLoggedIn)?;
+        Self::for_auth_key(state, &auth_key).await
+    }
+
+    /// The most recent valid login of `owner` (`None`: the unowned sessions),
+    /// for server-side jobs acting on that owner's rows
+    pub async fn for_owner(state: &AppState, owner: Option<uuid::Uuid>) -> Result<Self, MpError> {
+        let auth_key = crate::api::insight::get_valid_auth_key(state, owner)
+            .await
+            .ok_or(MpError::NotLoggedIn)?;
+        Self::for_auth_key(state, &auth_key).await
+    }
+
+    /// GET a `cgi-bin` JSON endpoint under the session's budget and lane.
+    ///
+    /// Returns the raw body, whatever its `base_resp`; frequency control is
+    /// retried for non-interactive callers.
+    pub async fn get_json(
+        &self,
+        endpoint: Endpoint,
+        path: &str,
+        query: &[(&str, &str)],
+        priority: Priority,
+        source: &'static str,
+    ) -> Result<Value, MpError> {
+        let mut freq_controlled = 0;
+        loop {
+            if priority == Priority::Interactive {
+                ratelimit::acquire(&self.lane, endpoint, ratelimit::INTERACTIVE_MAX_WAIT)
+                    .await
+                    .map_err(MpError::OverBudget)?;
+            } else {
+                // Waits out the budget (and any frequency-control backoff) instead of failing
+                let _ = ratelimit::acquire(&self.lane, endpoint, Duration::MAX).await;
+            }
+
+            let (entry, sent) = {
+                let _permit = crawl::acquire(&self.lane, priority, source).await;
+                let entry = request_log::Entry::start(path, source, &self.lane, query);
+                let sent = match self.request(path, query).send().await {
+                    Ok(response) => {
+                        let status = response.status().as_u16();
+                        response.text().await.map(|text| (status, text))
+                    }
+                    Err(e) => Err(e),
+                };
+                (entry, sent)
+            };
+            let (status, text) = match sent {
+                Ok(sent) => sent,
+                Err(e) => {
+                    entry.finish(None, None, Some(e.to_string()));
+                    return Err(e.into());
+                }
+            };
+            let json: Value = match serde_json::from_str(&text) {
+                Ok(json) => json,
+                Err(e) => {
+                    entry.finish(Some(status), None, Some(format!("Invalid JSON: {}", e)));
+                    return Err(MpError::InvalidResponse(format!("{} | Body: {}", e, text)));
+                }
+            };
+            entry.finish(Some(status), ratelimit::base_ret(&json), None);
+
+            let ret = ratelimit::base_ret(&json);
+            ratelimit::report(&self.lane, endpoint, ret);
+            match ret {
+                Some(ret)
+                    if ratelimit::is_freq_control(ret) && priority != Priority::Interactive =>
+                {
+                    freq_controlled += 1;
+                    if freq_controlled >= MAX_FREQ_CONTROL_RETRIES {
+                        return Ok(json);
+                    }
+                }
+                _ => return Ok(json),
+            }
+        }
+    }
+
+    /// Raw `searchbiz` response
+    pub async fn search_biz(
+        &self,
+        keyword: &str,
+        begin: u32,
+        count: u32,
+        priority: Priority,
+    ) -> Result<Value, MpError> {
+        self.get_json(
+            Endpoint::SearchBiz,
+            "/cgi-bin/searchbiz",
+            &[
+                ("action", "search_biz"),
+                ("begin", &begin.to_string()),
+                ("count", &count.to_string()),
+                ("query", keyword),
+                ("lang", "zh_CN"),
+                ("f", "json"),
+                ("ajax", "1"),
+            ],
+            priority,
+            "wechat.searchbiz",
+        )
+        .await
+    }
+
+    /// Accounts matching `keyword`
+    pub async fn search_accounts(
+        &self,
+        keyword: &str,
+        count: u32,
+        priority: Priority,
+    ) -> Result<Vec<BizAccount>, MpError> {
+        let json = self.search_biz(keyword, 0, count, priority).await?;
+        check_base_resp(&json)?;
+        Ok(parse_search_biz(&json))
+    }
+
+    /// Raw `appmsgpublish` response (list_ex format)
+    pub async fn appmsgpublish(
+        &self,
+        fakeid: &str,
+        begin: u32,
+        count: u32,
+        keyword: &str,
+        priority: Priority,
+    ) -> Result<Value, MpError> {
+        let searching = !keyword.is_empty();
+        self.get_json(
+            Endpoint::AppMsgPublish,
+            "/cgi-bin/appmsgpublish",
+            &[
+                ("sub", if searching { "search" } else { "list" }),
+                ("search_field", if searching { "7" } else { "null" }),
+                ("begin", &begin.to_string()),
+                ("count", &count.to_string()),
+                ("query", keyword),
+                ("fakeid", fakeid),
+                ("type", "101_1"),
+                ("free_publish_type", "1"),
+                ("sub_action", "list_ex"),
+                ("lang", "zh_CN"),
+                ("f", "json"),
+                ("ajax", "1"),
+            ],
+            priority,
+            "wechat.appmsgpublish",
+        )
+        .await
+    }
+
+    /// One page of an account's publish history starting at `begin`
+    pub async fn publish_page(
+        &self,
+        fakeid: &str,
+        begin: u32,
+        count: u32,
+        priority: Priority,
+    ) -> Result<PublishPage, MpError> {
+        let json = self
+            .appmsgpublish(fakeid, begin, count, "", priority)
+            .await?;
+        check_base_resp(&json)?;
+        Ok(parse_publish_page(&json))
+    }
+
+    /// One page of an account's articles matching `keyword` (in-account search)
+    pub async fn search_page(
+        &self,
+        fakeid: &str,
+        keyword: &str,
+        begin: u32,
+        count: u32,
+        priority: Priority,
+    ) -> Result<PublishPage, MpError> {
+        let json = self
+            .appmsgpublish(fakeid, begin, count, keyword, priority)
+            .await?;
+        check_base_resp(&json)?;
+        Ok(parse_publish_page(&json))
+    }
+
+    /// Cheap authenticated call that fails when the login has expired
+    pub async fn validate(&self) -> Result<(), MpError> {
+        self.ping(Priority::Interactive).await.map(|_| ())
+    }
+
+    /// `validate`, also returning the cookies the MP backend re-issued
+    /// (`Set-Cookie`), whose expiry tells how long the session lives on
+    pub async fn ping(&self, priority: Priority) -> Result<Vec<CookieEntity>, MpError> {
+        // Not budgeted: one request per task start or keepalive round
+        let (json, refreshed): (Value, Vec<CookieEntity>) = {
+            let _permit = crawl::acquire(&self.lane, priority, "wechat.validate").await;
+            let resp = self
+                .request(
+                    "/cgi-bin/searchbiz",
+                    &[
+                        ("action", "search_biz"),
+                        ("begin", "0"),
+                        ("count", "1"),
+                        ("query", "test"),
+                        ("lang", "zh_CN"),
+                        ("f", "json"),
+                        ("ajax", "1"),
+                    ],
+                )
+                .send()
+                .await?;
+            let raw: Vec<String> = resp
+                .headers()
+                .get_all(SET_COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok().map(|s| s.to_string()))
+                .collect();
+            (resp.json().await?, AccountCookie::parse_cookies(&raw))
+        };
+        check_base_resp(&json)?;
+        Ok(refreshed)
+    }
+
+    /// HTML of the MP home page (carries the account's nickname and avatar)
+    pub async fn home_html(&self) -> Result<String, MpError> {
+        let _permit = crawl::acquire(&self.lane, Priority::Interactive, "wechat.home").await;
+        Ok(self
+            .request("/cgi-bin/home", &[("t", "home/index"), ("lang", "zh_CN")])
+            .send()
+            .await?
+            .text()
+            .await?)
+    }
+
+    fn request(&self, path: &str, query: &[(&str, &str)]) -> reqwest::RequestBuilder {
+        with_mp_headers(HTTP.get(format!("{}{}", MP_BASE, path)))
+            .query(query)
+            .query(&[("token", &self.token)])
+            .header(COOKIE, &self.cookie)
+    }
+}
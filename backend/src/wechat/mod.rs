@@ -0,0 +1,6 @@
+//! WeChat MP protocol
+//!
+//! Typed views of the mp.weixin.qq.com backend responses.
+
+pub mod client;
+pub mod model;
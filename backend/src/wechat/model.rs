@@ -0,0 +1,307 @@
+//! `appmsgpublish` response models
+//!
+//! `publish_page` is a JSON string whose `publish_list` entries each carry
+//! another JSON string, `publish_info`, describing one push. Newer responses
+//! list the push's articles under `appmsg_info` (time in `sent_info`), older
+//! ones under `appmsgex`. Both are normalized into [`AppMsg`] here, with HTML
+//! entities in text fields and links decoded.
+
+use serde_json::Value;
+
+/// One page of `appmsgpublish`
+#[derive(Debug, Default, Clone)]
+pub struct PublishPage {
+    pub total_count: i32,
+    /// Raw `publish_list` length; paging `begin` advances by this
+    pub entries: usize,
+    pub publish_list: Vec<PublishInfo>,
+}
+
+/// One push (group message), possibly with several articles
+#[derive(Debug, Default, Clone)]
+pub struct PublishInfo {
+    pub appmsgs: Vec<AppMsg>,
+}
+
+/// One article of a push
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppMsg {
+    pub aid: String,
+    pub title: String,
+    pub link: String,
+    pub digest: String,
+    pub cover: String,
+    pub author: String,
+    pub create_time: i64,
+    pub update_time: Option<i64>,
+    pub itemidx: i32,
+    pub is_deleted: bool,
+    /// Original item as returned by WeChat
+    pub raw: Value,
+}
+
+impl PublishPage {
+    /// Every article on the page, in push order
+    pub fn articles(&self) -> impl Iterator<Item = &AppMsg> {
+        self.publish_list.iter().flat_map(|p| p.appmsgs.iter())
+    }
+
+    pub fn into_articles(self) -> Vec<AppMsg> {
+        self.publish_list
+            .into_iter()
+            .flat_map(|p| p.appmsgs)
+            .collect()
+    }
+}
+
+impl AppMsg {
+    /// `raw` with the normalized fields written over it, in `appmsgex` naming
+    pub fn to_json(&self) -> Value {
+        let mut value = match &self.raw {
+            Value::Object(map) => map.clone(),
+            _ => serde_json::Map::new(),
+        };
+        for (key, field) in [
+            ("aid", Value::from(self.aid.clone())),
+            ("title", Value::from(self.title.clone())),
+            ("link", Value::from(self.link.clone())),
+            ("digest", Value::from(self.digest.clone())),
+            ("cover", Value::from(self.cover.clone())),
+            ("author", Value::from(self.author.clone())),
+            ("create_time", Value::from(self.create_time)),
+            ("update_time", Value::from(self.update_time)),
+            ("itemidx", Value::from(self.itemidx)),
+            ("is_deleted", Value::from(self.is_deleted)),
+        ] {
+            value.insert(key.to_string(), field);
+        }
+        Value::Object(value)
+    }
+}
+
+/// Parse the `publish_page` of an `appmsgpublish` response; empty when missing or malformed
+pub fn parse_publish_page(json: &Value) -> PublishPage {
+    let Some(page) = json.get("publish_page").and_then(embedded_json) else {
+        return PublishPage::default();
+    };
+
+    let list = page
+        .get("publish_list")
+        .and_then(|l| l.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    PublishPage {
+        total_count: page.get("total_count").and_then(lenient_i64).unwrap_or(0) as i32,
+        entries: list.len(),
+        publish_list: list
+            .iter()
+            .filter_map(|item| item.get("publish_info").and_then(embedded_json))
+            .map(|info| parse_publish_info(&info))
+            .collect(),
+    }
+}
+
+fn parse_publish_info(info: &Value) -> PublishInfo {
+    // Shared by every article of the push; `appmsg_info` items carry no time
+    let sent_time = info
+        .get("sent_info")
+        .and_then(|s| s.get("time"))
+        .and_then(lenient_i64)
+        .unwrap_or(0);
+
+    let items = |key: &str| {
+        info.get(key)
+            .and_then(|l| l.as_array())
+            .filter(|l| !l.is_empty())
+            .cloned()
+    };
+    let appmsgs = items("appmsg_info")
+        .or_else(|| items("appmsgex"))
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|msg| parse_appmsg(msg, sent_time))
+        .collect();
+
+    PublishInfo { appmsgs }
+}
+
+/// Item of either `appmsg_info` or `appmsgex`; `None` without a usable link
+fn parse_appmsg(msg: &Value, sent_time: i64) -> Option<AppMsg> {
+    let text = |key: &str| {
+        msg.get(key)
+            .and_then(|v| v.as_str())
+            .map(unescape)
+            .unwrap_or_default()
+    };
+    let first_text = |keys: &[&str]| {
+        keys.iter()
+            .map(|k| text(k))
+            .find(|s| !s.is_empty())
+            .unwrap_or_default()
+    };
+
+    let link = unescape_link(&first_text(&["link", "content_url"]));
+    if link.is_empty() {
+        return None;
+    }
+    let itemidx = msg.get("itemidx").and_then(lenient_i64).unwrap_or(1) as i32;
+    let aid = match text("aid") {
+        aid if !aid.is_empty() => aid,
+        _ => {
+            let appmsgid = msg.get("appmsgid").and_then(lenient_i64)?;
+            format!("{}_{}", appmsgid, itemidx)
+        }
+    };
+
+    Some(AppMsg {
+        aid,
+        title: text("title"),
+        link,
+        digest: text("digest"),
+        cover: unescape_link(&first_text(&["cover", "cover_img", "pic_cdn_url_1_1"])),
+        author: first_text(&["author", "author_name"]),
+        create_time: msg
+            .get("create_time")
+            .and_then(lenient_i64)
+            .filter(|t| *t > 0)
+            .unwrap_or(sent_time),
+        update_time: msg.get("update_time").and_then(lenient_i64),
+        itemidx,
+        is_deleted: msg.get("is_deleted").is_some_and(lenient_bool),
+        raw: msg.clone(),
+    })
+}
+
+/// Account entry of a `searchbiz` response
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BizAccount {
+    pub fakeid: String,
+    pub nickname: String,
+    pub alias: String,
+    pub round_head_img: String,
+}
+
+/// Accounts of a `searchbiz` response; entries without fakeid or nickname are skipped
+pub fn parse_search_biz(json: &Value) -> Vec<BizAccount> {
+    json.get("list")
+        .and_then(|l| l.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let text = |key: &str| item.get(key).and_then(|v| v.as_str()).map(unescape);
+            Some(BizAccount {
+                fakeid: text("fakeid")?,
+                nickname: text("nickname")?,
+                alias: text("alias").unwrap_or_default(),
+                round_head_img: text("round_head_img").unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// A value that is either a JSON object or a string containing one
+fn embedded_json(value: &Value) -> Option<Value> {
+    match value {
+        Value::Object(_) => Some(value.clone()),
+        Value::String(s) if !s.is_empty() => serde_json::from_str(s)
+            .ok()
+            // Some responses entity-encode the quotes of the embedded JSON
+            .or_else(|| serde_json::from_str(&html_escape::decode_html_entities(s)).ok()),
+        _ => None,
+    }
+}
+
+fn unescape(s: &str) -> String {
+    html_escape::decode_html_entities(s).into_owned()
+}
+
+/// Links are sometimes double-encoded (`&amp;amp;`) and carry escaped slashes
+fn unescape_link(s: &str) -> String {
+    let once = unescape(s);
+    let twice = if once.contains('&') {
+        unescape(&once)
+    } else {
+        once
+    };
+    twice.replace("\\/", "/").replace('\\', "")
+}
+
+pub(crate) fn lenient_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f as i64)),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn lenient_bool(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_i64().is_some_and(|n| n != 0),
+        Value::String(s) => s == "1" || s.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_publish_page() {
+        let appmsgex = serde_json::json!({
+            "appmsgex": [
+                {"aid": "100_1", "title": "头条 &amp; 资讯", "link": "https://mp.weixin.qq.com/s?__biz=x&amp;mid=1", "create_time": 1700000000, "itemidx": 1, "cover": "c1"},
+                {"aid": "100_2", "title": "次条", "link": "https://mp.weixin.qq.com/s/b", "create_time": 1700000000, "itemidx": 2, "pic_cdn_url_1_1": "c2"},
+                {"title": "no link"}
+            ]
+        });
+        let appmsg_info = serde_json::json!({
+            "sent_info": {"time": 1710000000},
+            "appmsg_info": [
+                {"appmsgid": 200, "itemidx": 1, "title": "新格式", "content_url": "http:\\/\\/mp.weixin.qq.com\\/s?a=1&amp;amp;b=2", "is_deleted": 1}
+            ]
+        });
+        let page = serde_json::json!({
+            "total_count": "42",
+            "publish_list": [
+                {"publish_info": appmsgex.to_string()},
+                {"publish_info": appmsg_info.to_string().replace('"', "&quot;")},
+                {"publish_info": ""}
+            ]
+        });
+        let json = serde_json::json!({"publish_page": page.to_string()});
+
+        let parsed = parse_publish_page(&json);
+        assert_eq!(parsed.total_count, 42);
+        assert_eq!(parsed.entries, 3);
+        let articles: Vec<&AppMsg> = parsed.articles().collect();
+        assert_eq!(articles.len(), 3);
+        assert_eq!(articles[0].title, "头条 & 资讯");
+        assert_eq!(articles[0].link, "https://mp.weixin.qq.com/s?__biz=x&mid=1");
+        assert_eq!(articles[1].itemidx, 2);
+        assert_eq!(articles[1].cover, "c2");
+        assert_eq!(articles[2].aid, "200_1");
+        assert_eq!(articles[2].link, "http://mp.weixin.qq.com/s?a=1&b=2");
+        assert_eq!(articles[2].create_time, 1710000000);
+        assert!(articles[2].is_deleted);
+        assert_eq!(articles[2].to_json()["link"], articles[2].link.as_str());
+
+        assert_eq!(parse_publish_page(&serde_json::json!({})).entries, 0);
+    }
+
+    #[test]
+    fn test_parse_search_biz() {
+        let json = serde_json::json!({
+            "list": [
+                {"fakeid": "MzA1", "nickname": "A &amp; B", "alias": "ab"},
+                {"nickname": "no fakeid"}
+            ]
+        });
+        let accounts = parse_search_biz(&json);
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].nickname, "A & B");
+        assert_eq!(accounts[0].round_head_img, "");
+    }
+}
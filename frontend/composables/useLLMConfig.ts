@@ -17,6 +17,7 @@ export interface LLMConfig {
   openaiCompatibleBaseUrl: string;
   openaiCompatibleApiKey: string;
   openaiCompatibleModel: string;
+  openaiCompatibleEmbeddingModel: string;
   openaiCompatibleProxyEnabled: boolean;
   // Ollama (Local)
   ollamaEnabled: boolean;
@@ -41,6 +42,7 @@ export const DEFAULT_LLM_CONFIG: LLMConfig = {
   openaiCompatibleBaseUrl: '',
   openaiCompatibleApiKey: '',
   openaiCompatibleModel: '',
+  openaiCompatibleEmbeddingModel: '',
   openaiCompatibleProxyEnabled: true, // 默认使用代理
   ollamaEnabled: false,
   ollamaBaseUrl: 'http://127.0.0.1:11434',